@@ -23,7 +23,8 @@ use adw::subclass::prelude::*;
 use gettextrs::gettext;
 use gtk::{gio, glib};
 
-use crate::config::VERSION;
+use crate::config::{APP_ID, VERSION};
+use crate::preferences::create_preferences_dialog;
 use crate::NovaWindow;
 
 mod imp {
@@ -45,6 +46,11 @@ mod imp {
             let obj = self.obj();
             obj.setup_gactions();
             obj.set_accels_for_action("app.quit", &["<primary>q"]);
+            obj.set_accels_for_action("win.volume-up", &["<primary>Up"]);
+            obj.set_accels_for_action("win.volume-down", &["<primary>Down"]);
+            obj.set_accels_for_action("win.toggle-mute", &["<primary>m"]);
+            obj.set_accels_for_action("win.focus-search", &["<primary>k"]);
+            obj.set_accels_for_action("win.refresh-library::incremental", &["<primary>r"]);
         }
     }
 
@@ -86,22 +92,33 @@ impl NovaApplication {
 
     fn setup_gactions(&self) {
         let quit_action = gio::ActionEntry::builder("quit")
-            .activate(move |app: &Self, _, _| app.quit())
+            // Close the windows rather than quitting outright so they can
+            // offer to keep an unfinished queue
+            .activate(move |app: &Self, _, _| {
+                for window in app.windows() {
+                    window.close();
+                }
+            })
             .build();
         let about_action = gio::ActionEntry::builder("about")
             .activate(move |app: &Self, _, _| app.show_about())
             .build();
         // Add preferences action
         let preferences_action = gio::ActionEntry::builder("preferences")
-            .activate(|app: &Self, _, _| {
-                println!("Preferences action activated");
-                // Add actual preferences implementation later
-            })
+            .activate(|app: &Self, _, _| app.show_preferences())
             .build();
         self.add_action_entries([quit_action, about_action, preferences_action]);
         self.set_accels_for_action("app.preferences", &["<primary>comma"]);
     }
 
+    fn show_preferences(&self) {
+        let window = self.active_window().unwrap();
+        let settings = gio::Settings::new(APP_ID);
+        let preferences = create_preferences_dialog(&settings);
+
+        preferences.present(Some(&window));
+    }
+
     fn show_about(&self) {
         let window = self.active_window().unwrap();
         let about = adw::AboutDialog::builder()
@@ -1,3 +1,4 @@
+pub static APP_ID: &str = "com.lucamignatti.nova";
 pub static VERSION: &str = "0.1.0";
 pub static GETTEXT_PACKAGE: &str = "nova";
 pub static LOCALEDIR: &str = "/app/share/locale";
@@ -0,0 +1,175 @@
+//! The folders the local library is built from. Folders are picked with
+//! gtk::FileDialog, which goes through the FileChooser portal inside the
+//! Flatpak sandbox. The portal shares the folder through the Documents
+//! portal with a permanent grant, so the path it hands back keeps working
+//! across restarts until the user revokes it.
+
+use crate::platform;
+use crate::services::models::{PlayableItem, PlaybackSource, Playlist, PlaylistPlayback, Track};
+use chrono::Utc;
+use gtk::gio;
+use gtk::prelude::*;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const SETTINGS_KEY: &str = "library-folders";
+
+/// Folders to scan, falling back to the Music folder when none are set
+pub fn library_folders(settings: &gio::Settings) -> Vec<PathBuf> {
+    let folders: Vec<PathBuf> = settings
+        .strv(SETTINGS_KEY)
+        .iter()
+        .map(|folder| PathBuf::from(folder.as_str()))
+        .collect();
+
+    if folders.is_empty() {
+        vec![platform::music_dir()]
+    } else {
+        folders
+    }
+}
+
+fn save_library_folders(settings: &gio::Settings, folders: &[PathBuf]) {
+    let values: Vec<String> = folders
+        .iter()
+        .map(|folder| folder.to_string_lossy().into_owned())
+        .collect();
+    let refs: Vec<&str> = values.iter().map(String::as_str).collect();
+
+    if let Err(e) = settings.set_strv(SETTINGS_KEY, refs.as_slice()) {
+        eprintln!("Failed to save library folders: {}", e);
+    }
+}
+
+pub fn add_library_folder(settings: &gio::Settings, folder: PathBuf) {
+    let mut folders = library_folders(settings);
+    if !folders.contains(&folder) {
+        folders.push(folder);
+        save_library_folders(settings, &folders);
+    }
+}
+
+pub fn remove_library_folder(settings: &gio::Settings, folder: &Path) {
+    let mut folders = library_folders(settings);
+    folders.retain(|f| f != folder);
+    save_library_folders(settings, &folders);
+}
+
+/// Swap `old` for `new` in place, e.g. after access to `old` was granted
+/// again through the portal under a new path
+pub fn replace_library_folder(settings: &gio::Settings, old: &Path, new: PathBuf) {
+    let mut folders = library_folders(settings);
+    match folders.iter().position(|f| f == old) {
+        Some(index) if !folders.contains(&new) => folders[index] = new,
+        Some(index) => {
+            folders.remove(index);
+        }
+        None if !folders.contains(&new) => folders.push(new),
+        None => return,
+    }
+    save_library_folders(settings, &folders);
+}
+
+/// Display name for a folder. Portal paths are long and meaningless, but
+/// they keep the original folder name as their last component.
+pub fn folder_name(folder: &Path) -> String {
+    folder
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| folder.display().to_string())
+}
+
+/// Ask the user for a folder and hand it to `on_chosen`. `initial` opens
+/// the chooser at a folder, such as one whose access is being restored.
+pub fn choose_folder<F>(
+    parent: Option<&gtk::Window>,
+    title: &str,
+    initial: Option<&Path>,
+    on_chosen: F,
+) where
+    F: FnOnce(PathBuf) + 'static,
+{
+    let dialog = gtk::FileDialog::builder().title(title).modal(true).build();
+    if let Some(initial) = initial {
+        dialog.set_initial_folder(Some(&gio::File::for_path(initial)));
+    }
+
+    dialog.select_folder(parent, gio::Cancellable::NONE, move |result| match result {
+        Ok(file) => match file.path() {
+            Some(path) => on_chosen(path),
+            None => eprintln!("Chosen folder has no local path: {}", file.uri()),
+        },
+        Err(e) => {
+            if !e.matches(gtk::DialogError::Dismissed) {
+                eprintln!("Failed to choose a folder: {}", e);
+            }
+        }
+    });
+}
+
+fn local_path(track: &Track) -> Option<&Path> {
+    match &track.source {
+        PlaybackSource::Local { path, .. } => Some(path),
+        _ => None,
+    }
+}
+
+/// Playlists for the folders `depth` levels inside the library folders
+/// `roots`, each with its tracks in file order. They are built from the
+/// library index, which the file watcher keeps in step with the disk.
+pub fn folder_playlists(
+    roots: &[PathBuf],
+    tracks: &[PlayableItem],
+    depth: usize,
+) -> Vec<(Playlist, Vec<Track>)> {
+    let mut folders: BTreeMap<PathBuf, (String, Vec<Track>)> = BTreeMap::new();
+    for item in tracks {
+        let Some(path) = local_path(&item.track) else {
+            continue;
+        };
+        let Some((root, relative)) = roots
+            .iter()
+            .find_map(|root| Some((root, path.strip_prefix(root).ok()?)))
+        else {
+            continue;
+        };
+
+        // Tracks right inside a library folder aren't in any folder below it
+        let components: Vec<_> = match relative.parent() {
+            Some(parent) => parent.components().take(depth).collect(),
+            None => Vec::new(),
+        };
+        if components.is_empty() {
+            continue;
+        }
+
+        let folder = components.iter().fold(root.clone(), |folder, c| folder.join(c));
+        let name = components
+            .iter()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" / ");
+        folders
+            .entry(folder)
+            .or_insert_with(|| (name, Vec::new()))
+            .1
+            .push(item.track.clone());
+    }
+
+    folders
+        .into_iter()
+        .map(|(folder, (name, mut tracks))| {
+            tracks.sort_by(|a, b| local_path(a).cmp(&local_path(b)));
+            let playlist = Playlist {
+                id: format!("folder:{}", folder.display()),
+                name,
+                created_at: Utc::now(),
+                track_ids: tracks.iter().map(|track| track.id.clone()).collect(),
+                folder: Some(folder),
+                group: None,
+                playback: PlaylistPlayback::default(),
+            };
+            (playlist, tracks)
+        })
+        .collect()
+}
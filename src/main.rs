@@ -20,13 +20,17 @@
 
 mod application;
 mod config;
+mod library_folders;
+mod migrations;
+mod platform;
+mod preferences;
 mod services;
 mod window;
 
 use self::application::NovaApplication;
 use self::window::NovaWindow;
 
-use config::{GETTEXT_PACKAGE, LOCALEDIR, PKGDATADIR};
+use config::{APP_ID, GETTEXT_PACKAGE, LOCALEDIR, PKGDATADIR};
 use gettextrs::{bind_textdomain_codeset, bindtextdomain, textdomain};
 use gtk::prelude::*;
 use gtk::{gio, glib};
@@ -44,10 +48,12 @@ async fn main() -> glib::ExitCode {
         .expect("Could not load resources");
     gio::resources_register(&resources);
 
+    migrations::migrate_settings();
+
     // Create a new GtkApplication. The application manages our main loop,
     // application windows, integration with the window manager/compositor, and
     // desktop features such as file opening and single-instance applications.
-    let app = NovaApplication::new("com.lucamignatti.nova", &gio::ApplicationFlags::empty());
+    let app = NovaApplication::new(APP_ID, &gio::ApplicationFlags::empty());
 
     // Load CSS
     app.connect_startup(|_| {
@@ -0,0 +1,156 @@
+//! Versioned upgrades of what Nova keeps between runs. Each store records
+//! the last migration it went through: the user store in its SQLite
+//! user_version, settings and files on disk in the migration-version key.
+//! Migrations run in order at startup, and each one is recorded as soon as
+//! it succeeds, so a failure leaves the store at the last good version to
+//! be retried next time rather than half upgraded.
+//!
+//! With NOVA_MIGRATIONS_DRY_RUN set, pending migrations are only logged.
+
+use crate::config::APP_ID;
+use crate::services::secrets;
+use gtk::gio;
+use gtk::prelude::*;
+use std::error::Error;
+
+const SETTINGS_KEY: &str = "migration-version";
+const DRY_RUN_VAR: &str = "NOVA_MIGRATIONS_DRY_RUN";
+
+type Apply<T> = fn(&T) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+/// One step up from the version before it
+pub struct Migration<T> {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: Apply<T>,
+}
+
+/// Something that keeps the version it has been migrated to
+pub trait Versioned: Sized {
+    fn version(&self) -> Result<u32, Box<dyn Error + Send + Sync>>;
+
+    /// Run `apply` and record `version`, together where the store allows
+    fn advance(&self, version: u32, apply: Apply<Self>)
+        -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+impl Versioned for rusqlite::Connection {
+    fn version(&self) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        Ok(self.pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    fn advance(
+        &self,
+        version: u32,
+        apply: Apply<Self>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tx = self.unchecked_transaction()?;
+        apply(&tx)?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl Versioned for gio::Settings {
+    fn version(&self) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        Ok(self.int(SETTINGS_KEY).try_into()?)
+    }
+
+    fn advance(
+        &self,
+        version: u32,
+        apply: Apply<Self>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        apply(self)?;
+        self.set_int(SETTINGS_KEY, version.try_into()?)?;
+        Ok(())
+    }
+}
+
+// Renamed keys, moved caches and other changes outside the databases go
+// here, numbered on from the last
+const SETTINGS_MIGRATIONS: &[Migration<gio::Settings>] = &[
+    Migration {
+        version: 1,
+        description: "move the WebDAV password to the secret store",
+        apply: move_webdav_password,
+    },
+    Migration {
+        version: 2,
+        description: "move the ListenBrainz token to the secret store",
+        apply: move_listenbrainz_token,
+    },
+];
+
+fn move_webdav_password(settings: &gio::Settings) -> Result<(), Box<dyn Error + Send + Sync>> {
+    move_to_secrets(settings, secrets::WEBDAV_PASSWORD)
+}
+
+fn move_listenbrainz_token(settings: &gio::Settings) -> Result<(), Box<dyn Error + Send + Sync>> {
+    move_to_secrets(settings, secrets::LISTENBRAINZ_TOKEN)
+}
+
+// The settings key and the secret share a name
+fn move_to_secrets(
+    settings: &gio::Settings,
+    key: &'static str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let value = settings.string(key);
+    if !value.is_empty() {
+        secrets::store_blocking(key, value.to_string())?;
+        settings.reset(key);
+    }
+    Ok(())
+}
+
+/// Bring `target` up to the last of `migrations`, which are in version order.
+/// `name` is for the log.
+pub fn migrate<T: Versioned>(
+    name: &str,
+    target: &T,
+    migrations: &[Migration<T>],
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let current = target.version()?;
+    let latest = migrations.last().map_or(0, |migration| migration.version);
+    if current > latest {
+        // Left by a newer Nova; its data is kept as it is
+        eprintln!(
+            "{} is at version {}, newer than this Nova knows ({})",
+            name, current, latest
+        );
+        return Ok(());
+    }
+
+    let dry_run = std::env::var_os(DRY_RUN_VAR).is_some();
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        if dry_run {
+            println!(
+                "Would migrate {} to version {}: {}",
+                name, migration.version, migration.description
+            );
+            continue;
+        }
+        println!(
+            "Migrating {} to version {}: {}",
+            name, migration.version, migration.description
+        );
+        target
+            .advance(migration.version, migration.apply)
+            .map_err(|e| {
+                format!(
+                    "Migrating {} to version {} failed: {}",
+                    name, migration.version, e
+                )
+            })?;
+    }
+    Ok(())
+}
+
+/// Migrate settings and files on disk, before anything reads them
+pub fn migrate_settings() {
+    let settings = gio::Settings::new(APP_ID);
+    if let Err(e) = migrate("settings", &settings, SETTINGS_MIGRATIONS) {
+        eprintln!("{}", e);
+    }
+}
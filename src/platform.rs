@@ -0,0 +1,132 @@
+//! Differences between Linux, macOS and Windows: default locations and the
+//! audio outputs to try. File watching goes through notify, which already
+//! uses inotify, FSEvents or ReadDirectoryChangesW as appropriate.
+
+use std::path::{Path, PathBuf};
+
+fn home_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| {
+        // $HOME on Unix, %USERPROFILE% on Windows
+        std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+    })
+}
+
+/// Folder scanned for local music: XDG_MUSIC_DIR on Linux, ~/Music on macOS
+/// and the Music known folder on Windows
+pub fn music_dir() -> PathBuf {
+    dirs::audio_dir().unwrap_or_else(|| home_dir().join("Music"))
+}
+
+/// Where browsers save downloads: XDG_DOWNLOAD_DIR on Linux and ~/Downloads
+/// elsewhere
+pub fn download_dir() -> PathBuf {
+    dirs::download_dir().unwrap_or_else(|| home_dir().join("Downloads"))
+}
+
+/// Whether Nova runs inside the Flatpak sandbox, where folders outside the
+/// granted ones can only be reached through the portals
+pub fn is_sandboxed() -> bool {
+    Path::new("/.flatpak-info").exists()
+}
+
+/// Whether `dir` can be listed. Fails for folders that were never granted
+/// to the sandbox, or whose portal grant has since been revoked.
+pub fn can_read_dir(dir: &Path) -> bool {
+    std::fs::read_dir(dir).is_ok()
+}
+
+// Filesystems whose files live on another machine
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "9p", "afs", "ceph", "glusterfs", "davfs",
+    "fuse.sshfs", "fuse.rclone", "fuse.gvfsd-fuse", "fuse.davfs2",
+];
+
+/// What is known about the filesystem a path is on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MountInfo {
+    pub read_only: bool,
+    pub network: bool,
+}
+
+/// The mount `path` lives on. Only Linux reports mounts; elsewhere every
+/// path is taken to be on a local, writable disk.
+pub fn mount_info(path: &Path) -> MountInfo {
+    let Ok(mounts) = std::fs::read_to_string("/proc/self/mounts") else {
+        return MountInfo::default();
+    };
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    // The deepest mount point containing the path is the one it's on
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            // Spaces in mount points are escaped as \040
+            let mount_point = PathBuf::from(fields.next()?.replace("\\040", " "));
+            let fs_type = fields.next()?;
+            let options = fields.next()?;
+            path.starts_with(&mount_point).then(|| {
+                let info = MountInfo {
+                    read_only: options.split(',').any(|option| option == "ro"),
+                    network: NETWORK_FILESYSTEMS.contains(&fs_type),
+                };
+                (mount_point.components().count(), info)
+            })
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, info)| info)
+        .unwrap_or_default()
+}
+
+/// Per-user storage for Nova's own state
+pub fn data_dir() -> PathBuf {
+    let base = dirs::data_dir().unwrap_or_else(|| {
+        if cfg!(target_os = "macos") {
+            home_dir().join("Library").join("Application Support")
+        } else if cfg!(windows) {
+            home_dir().join("AppData").join("Roaming")
+        } else {
+            home_dir().join(".local").join("share")
+        }
+    });
+    base.join("nova")
+}
+
+/// Per-user storage for state worth keeping across a crash but not worth
+/// backing up. Only Linux has a separate place for it.
+pub fn state_dir() -> PathBuf {
+    dirs::state_dir()
+        .map(|base| base.join("nova"))
+        .unwrap_or_else(data_dir)
+}
+
+/// Per-user storage for files Nova can regenerate
+pub fn cache_dir() -> PathBuf {
+    let base = dirs::cache_dir().unwrap_or_else(|| {
+        if cfg!(target_os = "macos") {
+            home_dir().join("Library").join("Caches")
+        } else if cfg!(windows) {
+            home_dir().join("AppData").join("Local")
+        } else {
+            home_dir().join(".cache")
+        }
+    });
+    base.join("nova")
+}
+
+/// GStreamer sinks to try for playback, best first. autoaudiosink picks the
+/// PipeWire/PulseAudio sink on Linux; elsewhere the native sink is named
+/// first so a missing plugin fails over instead of ending up silent.
+pub fn audio_sinks() -> &'static [&'static str] {
+    if cfg!(target_os = "macos") {
+        &["osxaudiosink", "autoaudiosink"]
+    } else if cfg!(windows) {
+        &["wasapi2sink", "wasapisink", "directsoundsink", "autoaudiosink"]
+    } else {
+        &["autoaudiosink"]
+    }
+}
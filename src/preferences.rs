@@ -0,0 +1,1077 @@
+/* preferences.rs
+ *
+ * Copyright 2025 Luca Mignatti
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+use crate::library_folders::{
+    add_library_folder, choose_folder, folder_name, library_folders, remove_library_folder,
+    replace_library_folder,
+};
+use crate::platform;
+use crate::services::http;
+use crate::services::plugins::{discover_plugins, plugins_dir, PluginManifest};
+use crate::services::ranking::parse_weights;
+use crate::services::secrets;
+use adw::prelude::*;
+use gettextrs::gettext;
+use gtk::gio;
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+// Player bar layouts in the order they appear in the combo row
+const PLAYER_BAR_LAYOUTS: &[(&str, &str)] = &[("compact", "Compact"), ("expanded", "Expanded")];
+
+const EXPLICIT_CONTENT_FILTERS: &[(&str, &str)] = &[
+    ("allow", "Allow"),
+    ("hide", "Hide Explicit Tracks"),
+    ("skip", "Skip During Playback"),
+];
+
+const CLIPPING_PREVENTION_CHOICES: &[(&str, &str)] = &[
+    ("limiter", "Limit Peaks"),
+    ("reduce-gain", "Reduce Gain"),
+];
+
+const SCAN_PRIORITIES: &[(&str, &str)] = &[
+    ("idle", "When Idle"),
+    ("low", "Low"),
+    ("full", "Full Speed"),
+];
+
+const RESAMPLER_QUALITIES: &[(&str, &str)] = &[
+    ("fast", "Fast"),
+    ("medium", "Medium"),
+    ("high", "High"),
+];
+
+const UNFINISHED_QUEUE_ACTIONS: &[(&str, &str)] = &[
+    ("ask", "Ask"),
+    ("save-playlist", "Save as Playlist"),
+    ("restore", "Restore Next Time"),
+    ("discard", "Discard"),
+];
+
+const SEARCH_RANKINGS: &[(&str, &str)] = &[
+    ("balanced", "Balanced"),
+    ("exact-titles", "Exact Titles First"),
+    ("artists", "Artists First"),
+    ("recently-played", "Recently Played First"),
+    ("custom", "Custom Weights"),
+];
+
+const STARTUP_PAGES: &[(&str, &str)] = &[
+    ("home", "Home"),
+    ("albums", "Albums"),
+    ("playlists", "Playlists"),
+    ("resume", "Resume Playback"),
+];
+
+const METERED_POLICIES: &[(&str, &str)] = &[
+    ("unrestricted", "Unrestricted"),
+    ("reduce-quality", "Reduce Streaming Quality"),
+    ("no-downloads", "Reduce Quality and Pause Downloads"),
+];
+
+/// Combo row for a string settings key restricted to `choices`, given as
+/// (setting value, untranslated label) pairs
+fn create_choice_row(
+    settings: &gio::Settings,
+    key: &'static str,
+    title: &str,
+    subtitle: &str,
+    choices: &'static [(&'static str, &'static str)],
+) -> adw::ComboRow {
+    let labels: Vec<String> = choices.iter().map(|(_, label)| gettext(*label)).collect();
+    let label_refs: Vec<&str> = labels.iter().map(String::as_str).collect();
+    let model = gtk::StringList::new(&label_refs);
+
+    let row = adw::ComboRow::builder()
+        .title(title)
+        .subtitle(subtitle)
+        .model(&model)
+        .build();
+
+    let current = settings.string(key);
+    let selected = choices
+        .iter()
+        .position(|(value, _)| *value == current.as_str())
+        .unwrap_or(0);
+    row.set_selected(selected as u32);
+
+    let settings = settings.clone();
+    row.connect_selected_notify(move |row| {
+        if let Some((value, _)) = choices.get(row.selected() as usize) {
+            if let Err(e) = settings.set_string(key, value) {
+                eprintln!("Failed to save {}: {}", key, e);
+            }
+        }
+    });
+
+    row
+}
+
+fn create_folder_row(
+    settings: &gio::Settings,
+    folder: &Path,
+    removable: bool,
+) -> adw::ActionRow {
+    let row = adw::ActionRow::builder()
+        .title(folder_name(folder))
+        .subtitle(folder.display().to_string())
+        .build();
+    row.add_prefix(&gtk::Image::from_icon_name("folder-music-symbolic"));
+
+    // A folder whose grant was revoked has to go through the chooser again
+    if !platform::can_read_dir(folder) {
+        row.set_subtitle(&gettext("Nova can no longer access this folder"));
+        row.add_css_class("error");
+
+        let reauthorize_button = gtk::Button::builder()
+            .label(gettext("Re-authorize"))
+            .valign(gtk::Align::Center)
+            .build();
+        let settings = settings.clone();
+        let folder_clone = folder.to_path_buf();
+        reauthorize_button.connect_clicked(move |button| {
+            let parent = button.root().and_downcast::<gtk::Window>();
+            let settings = settings.clone();
+            let old = folder_clone.clone();
+            choose_folder(
+                parent.as_ref(),
+                &gettext("Choose the Folder Again"),
+                Some(&folder_clone),
+                move |folder| replace_library_folder(&settings, &old, folder),
+            );
+        });
+        row.add_suffix(&reauthorize_button);
+    }
+
+    let remove_button = gtk::Button::builder()
+        .icon_name("user-trash-symbolic")
+        .tooltip_text(gettext("Remove Folder"))
+        .valign(gtk::Align::Center)
+        .sensitive(removable)
+        .build();
+    remove_button.add_css_class("flat");
+    let settings = settings.clone();
+    let folder = folder.to_path_buf();
+    remove_button.connect_clicked(move |_| remove_library_folder(&settings, &folder));
+    row.add_suffix(&remove_button);
+
+    row
+}
+
+/// Which changes go into the files' tags as well as Nova's library
+fn create_write_back_group(settings: &gio::Settings) -> adw::PreferencesGroup {
+    let group = adw::PreferencesGroup::builder()
+        .title(gettext("File Tags"))
+        .description(gettext(
+            "Changes are always kept in Nova. Files that can't be written yet keep theirs \
+             waiting until they can.",
+        ))
+        .build();
+
+    let rows = [
+        (
+            "write-edits-to-files",
+            gettext("Write Edits"),
+            gettext("Titles, artists, albums and genres"),
+        ),
+        ("write-ratings-to-files", gettext("Write Ratings"), String::new()),
+        ("write-play-counts-to-files", gettext("Write Play Counts"), String::new()),
+        (
+            "write-replay-gain",
+            gettext("Write ReplayGain Tags"),
+            gettext("Analyze loudness so other players can level the volume too"),
+        ),
+        (
+            "embed-artwork",
+            gettext("Embed Album Art"),
+            gettext("Add the album's art to files without any, for other devices"),
+        ),
+        (
+            "write-to-network-files",
+            gettext("Write to Network Shares"),
+            gettext("Network shares can be slow, or shared with other people"),
+        ),
+    ];
+    for (key, title, subtitle) in rows {
+        let row = adw::SwitchRow::builder()
+            .title(title)
+            .subtitle(subtitle)
+            .build();
+        settings.bind(key, &row, "active").build();
+        group.add(&row);
+    }
+
+    let art_size_row = adw::SpinRow::builder()
+        .title(gettext("Largest Embedded Art"))
+        .subtitle(gettext("Pixels a side; bigger art is scaled down"))
+        .adjustment(&gtk::Adjustment::new(
+            1200.0, 300.0, 3000.0, 100.0, 500.0, 0.0,
+        ))
+        .build();
+    settings
+        .bind("embedded-artwork-max-size", &art_size_row, "value")
+        .build();
+    settings
+        .bind("embed-artwork", &art_size_row, "sensitive")
+        .get_only()
+        .build();
+    group.add(&art_size_row);
+
+    group
+}
+
+fn genre_mappings(settings: &gio::Settings) -> Vec<String> {
+    settings
+        .strv("genre-mappings")
+        .iter()
+        .map(|mapping| mapping.to_string())
+        .collect()
+}
+
+fn save_genre_mappings(settings: &gio::Settings, mappings: &[String]) {
+    let refs: Vec<&str> = mappings.iter().map(String::as_str).collect();
+    if let Err(e) = settings.set_strv("genre-mappings", refs.as_slice()) {
+        eprintln!("Failed to save genre mappings: {}", e);
+    }
+}
+
+/// Genre normalization with the user's own mappings, rebuilt whenever they
+/// change
+fn create_genres_group(settings: &gio::Settings) -> adw::PreferencesGroup {
+    let group = adw::PreferencesGroup::builder()
+        .title(gettext("Genres"))
+        .description(gettext(
+            "Group genre tags written in different ways. Add mappings like \
+             \"dream pop = Pop ▸ Dream Pop\" for tags Nova doesn't know.",
+        ))
+        .build();
+
+    let normalize_row = adw::SwitchRow::builder()
+        .title(gettext("Normalize Genres"))
+        .subtitle(gettext("The files' tags are left as they are"))
+        .build();
+    settings
+        .bind("normalize-genres", &normalize_row, "active")
+        .build();
+    group.add(&normalize_row);
+
+    let add_row = adw::EntryRow::builder()
+        .title(gettext("Add Mapping"))
+        .show_apply_button(true)
+        .build();
+    normalize_row
+        .bind_property("active", &add_row, "sensitive")
+        .sync_create()
+        .build();
+    let settings_clone = settings.clone();
+    add_row.connect_apply(move |row| {
+        let mapping = row.text().trim().to_string();
+        if !mapping.contains('=') {
+            row.add_css_class("error");
+            return;
+        }
+        row.remove_css_class("error");
+        let mut mappings = genre_mappings(&settings_clone);
+        mappings.push(mapping);
+        save_genre_mappings(&settings_clone, &mappings);
+        row.set_text("");
+    });
+    group.add(&add_row);
+
+    let rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::new(RefCell::new(Vec::new()));
+    let fill = {
+        let group = group.downgrade();
+        let rows = rows.clone();
+        let normalize_row = normalize_row.downgrade();
+        move |settings: &gio::Settings| {
+            let (Some(group), Some(normalize_row)) = (group.upgrade(), normalize_row.upgrade())
+            else {
+                return;
+            };
+            for row in rows.take() {
+                group.remove(&row);
+            }
+
+            let mappings = settings.strv("genre-mappings");
+            for (index, mapping) in mappings.iter().enumerate() {
+                let row = adw::ActionRow::builder()
+                    .title(gtk::glib::markup_escape_text(mapping))
+                    .build();
+                normalize_row
+                    .bind_property("active", &row, "sensitive")
+                    .sync_create()
+                    .build();
+
+                let remove_button = gtk::Button::builder()
+                    .icon_name("user-trash-symbolic")
+                    .tooltip_text(gettext("Remove Mapping"))
+                    .valign(gtk::Align::Center)
+                    .build();
+                remove_button.add_css_class("flat");
+                let settings = settings.clone();
+                remove_button.connect_clicked(move |_| {
+                    let mut mappings = genre_mappings(&settings);
+                    if index < mappings.len() {
+                        mappings.remove(index);
+                    }
+                    save_genre_mappings(&settings, &mappings);
+                });
+                row.add_suffix(&remove_button);
+
+                group.add(&row);
+                rows.borrow_mut().push(row);
+            }
+        }
+    };
+
+    refill_on_change(settings, "genre-mappings", &group, fill);
+
+    group
+}
+
+/// Rows for the library folders, rebuilt whenever the list changes
+fn create_library_group(settings: &gio::Settings) -> adw::PreferencesGroup {
+    let group = adw::PreferencesGroup::builder()
+        .title(gettext("Library"))
+        .description(gettext("Folders scanned for music on this device"))
+        .build();
+
+    let add_button = gtk::Button::builder()
+        .icon_name("list-add-symbolic")
+        .tooltip_text(gettext("Add Folder"))
+        .valign(gtk::Align::Center)
+        .build();
+    add_button.add_css_class("flat");
+    let settings_clone = settings.clone();
+    add_button.connect_clicked(move |button| {
+        let parent = button.root().and_downcast::<gtk::Window>();
+        let settings = settings_clone.clone();
+        choose_folder(
+            parent.as_ref(),
+            &gettext("Add Library Folder"),
+            None,
+            move |folder| add_library_folder(&settings, folder),
+        );
+    });
+    group.set_header_suffix(Some(&add_button));
+
+    let rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::new(RefCell::new(Vec::new()));
+    let fill = {
+        let group = group.downgrade();
+        let rows = rows.clone();
+        move |settings: &gio::Settings| {
+            let Some(group) = group.upgrade() else {
+                return;
+            };
+            for row in rows.take() {
+                group.remove(&row);
+            }
+
+            // Keep at least one folder, otherwise the default comes back
+            let folders = library_folders(settings);
+            let removable = folders.len() > 1;
+            for folder in &folders {
+                let row = create_folder_row(settings, folder, removable);
+                group.add(&row);
+                rows.borrow_mut().push(row);
+            }
+        }
+    };
+
+    refill_on_change(settings, "library-folders", &group, fill);
+
+    group
+}
+
+/// Call `fill` now and whenever `key` changes, for as long as `widget` is
+/// around. The handler goes when it does, so each time the preferences
+/// are opened doesn't leave another one behind.
+fn refill_on_change(
+    settings: &gio::Settings,
+    key: &str,
+    widget: &impl IsA<gtk::Widget>,
+    fill: impl Fn(&gio::Settings) + 'static,
+) {
+    fill(settings);
+    let handler = settings.connect_changed(Some(key), move |settings, _| fill(settings));
+    let handler = RefCell::new(Some(handler));
+    let settings = settings.clone();
+    widget.connect_destroy(move |_| {
+        if let Some(handler) = handler.take() {
+            settings.disconnect(handler);
+        }
+    });
+}
+
+/// Installed provider plugins, each with a switch to run it
+fn create_plugins_page(settings: &gio::Settings) -> adw::PreferencesPage {
+    let page = adw::PreferencesPage::builder()
+        .title(gettext("Plugins"))
+        .icon_name("application-x-addon-symbolic")
+        .build();
+
+    let group = adw::PreferencesGroup::builder()
+        .title(gettext("Provider Plugins"))
+        .description(gettext(
+            "Plugins add music services to Nova. They run as programs on this computer, \
+             so only turn on plugins you trust.",
+        ))
+        .build();
+
+    let open_button = gtk::Button::builder()
+        .icon_name("folder-open-symbolic")
+        .tooltip_text(gettext("Open Plugins Folder"))
+        .valign(gtk::Align::Center)
+        .build();
+    open_button.add_css_class("flat");
+    open_button.connect_clicked(|_| {
+        let dir = plugins_dir();
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            eprintln!("Failed to create plugins folder {:?}: {}", dir, e);
+            return;
+        }
+        let uri = gio::File::for_path(&dir).uri();
+        if let Err(e) = gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>) {
+            eprintln!("Failed to open plugins folder: {}", e);
+        }
+    });
+    group.set_header_suffix(Some(&open_button));
+
+    let plugins = discover_plugins();
+    if plugins.is_empty() {
+        let row = adw::ActionRow::builder()
+            .title(gettext("No Plugins Installed"))
+            .subtitle(gtk::glib::markup_escape_text(
+                &gettext("Plugins go in {}").replace("{}", &plugins_dir().display().to_string()),
+            ))
+            .build();
+        group.add(&row);
+    }
+    for plugin in plugins {
+        group.add(&create_plugin_row(settings, plugin));
+    }
+
+    page.add(&group);
+    page
+}
+
+fn create_plugin_row(settings: &gio::Settings, plugin: PluginManifest) -> adw::SwitchRow {
+    let subtitle = if !plugin.is_compatible() {
+        gettext("Made for a different version of Nova")
+    } else if plugin.version.is_empty() {
+        plugin.description.clone()
+    } else {
+        format!("{} · {}", plugin.version, plugin.description)
+    };
+    let row = adw::SwitchRow::builder()
+        .title(gtk::glib::markup_escape_text(&plugin.name))
+        .subtitle(gtk::glib::markup_escape_text(
+            subtitle.trim_end_matches(" · "),
+        ))
+        .active(enabled_plugins(settings).contains(&plugin.id))
+        .sensitive(plugin.is_compatible())
+        .build();
+
+    let settings = settings.clone();
+    row.connect_active_notify(move |row| {
+        let mut enabled = enabled_plugins(&settings);
+        enabled.retain(|id| *id != plugin.id);
+        if row.is_active() {
+            enabled.push(plugin.id.clone());
+        }
+        let refs: Vec<&str> = enabled.iter().map(String::as_str).collect();
+        if let Err(e) = settings.set_strv("enabled-plugins", refs.as_slice()) {
+            eprintln!("Failed to save enabled plugins: {}", e);
+        }
+    });
+    row
+}
+
+pub fn enabled_plugins(settings: &gio::Settings) -> Vec<String> {
+    settings
+        .strv("enabled-plugins")
+        .iter()
+        .map(|id| id.to_string())
+        .collect()
+}
+
+/// Group for signing in to a WebDAV server, such as a Nextcloud music
+/// folder, to play music from
+fn create_webdav_group(settings: &gio::Settings) -> adw::PreferencesGroup {
+    let group = adw::PreferencesGroup::builder()
+        .title(gettext("Cloud Drive"))
+        .description(gettext(
+            "Play music from a WebDAV folder, such as a Nextcloud music folder",
+        ))
+        .build();
+
+    let enabled_row = adw::SwitchRow::builder()
+        .title(gettext("Use Cloud Drive"))
+        .build();
+    settings
+        .bind("webdav-enabled", &enabled_row, "active")
+        .build();
+    group.add(&enabled_row);
+
+    // Saved on apply, so the server isn't contacted on every keystroke
+    let url_row = adw::EntryRow::builder()
+        .title(gettext("Folder Address"))
+        .input_purpose(gtk::InputPurpose::Url)
+        .build();
+    let username_row = adw::EntryRow::builder()
+        .title(gettext("Username"))
+        .build();
+    let password_row = adw::PasswordEntryRow::builder()
+        .title(gettext("Password"))
+        .build();
+    for (key, row) in [("webdav-url", &url_row), ("webdav-username", &username_row)] {
+        row.set_text(&settings.string(key));
+        row.set_show_apply_button(true);
+        let settings = settings.clone();
+        row.connect_apply(move |row| {
+            if let Err(e) = settings.set_string(key, row.text().trim()) {
+                eprintln!("Failed to save {}: {}", key, e);
+            }
+        });
+        enabled_row
+            .bind_property("active", row, "sensitive")
+            .sync_create()
+            .build();
+        group.add(row);
+    }
+
+    // The password is kept in the secret store rather than the settings
+    let row_weak = password_row.downgrade();
+    gtk::glib::MainContext::default().spawn_local(async move {
+        match secrets::load(secrets::WEBDAV_PASSWORD).await {
+            Ok(password) => {
+                // Unless something was typed in the meantime
+                if let Some(row) = row_weak.upgrade().filter(|row| row.text().is_empty()) {
+                    row.set_text(&password.unwrap_or_default());
+                }
+            }
+            Err(e) => eprintln!("Failed to read the WebDAV password: {}", e),
+        }
+    });
+    password_row.set_show_apply_button(true);
+    password_row.connect_apply(|row| {
+        let password = row.text().trim().to_string();
+        gtk::glib::MainContext::default().spawn_local(async move {
+            if let Err(e) = secrets::store(secrets::WEBDAV_PASSWORD, password).await {
+                eprintln!("Failed to save the WebDAV password: {}", e);
+            }
+        });
+    });
+    enabled_row
+        .bind_property("active", &password_row, "sensitive")
+        .sync_create()
+        .build();
+    group.add(&password_row);
+    group
+}
+
+pub fn create_preferences_dialog(settings: &gio::Settings) -> adw::PreferencesDialog {
+    let dialog = adw::PreferencesDialog::new();
+
+    let page = adw::PreferencesPage::builder()
+        .title(gettext("General"))
+        .icon_name("preferences-system-symbolic")
+        .build();
+
+    let library_group = create_library_group(settings);
+
+    // Providers
+    let providers_group = adw::PreferencesGroup::builder()
+        .title(gettext("Providers"))
+        .build();
+
+    let local_library_row = adw::SwitchRow::builder()
+        .title(gettext("Use Local Library"))
+        .subtitle(gettext("Play and search music from the library folders"))
+        .build();
+    settings
+        .bind("local-library-enabled", &local_library_row, "active")
+        .build();
+    providers_group.add(&local_library_row);
+
+    let split_chapters_row = adw::SwitchRow::builder()
+        .title(gettext("Split Chapters into Tracks"))
+        .subtitle(gettext("Show each chapter of a long file, such as a live set, as a track"))
+        .build();
+    settings
+        .bind("split-chapters", &split_chapters_row, "active")
+        .build();
+    providers_group.add(&split_chapters_row);
+
+    // Folder playlists
+    let folder_playlists_group = adw::PreferencesGroup::builder()
+        .title(gettext("Folder Playlists"))
+        .description(gettext("Show folders in your library as playlists that follow the files"))
+        .build();
+
+    let folder_playlists_row = adw::SwitchRow::builder()
+        .title(gettext("Show Folder Playlists"))
+        .build();
+    settings
+        .bind("folder-playlists", &folder_playlists_row, "active")
+        .build();
+    folder_playlists_group.add(&folder_playlists_row);
+
+    let folder_depth_row = adw::SpinRow::builder()
+        .title(gettext("Folder Depth"))
+        .subtitle(gettext("How many levels inside a library folder the playlists start"))
+        .adjustment(&gtk::Adjustment::new(1.0, 1.0, 4.0, 1.0, 1.0, 0.0))
+        .build();
+    settings
+        .bind("folder-playlist-depth", &folder_depth_row, "value")
+        .build();
+    folder_playlists_row
+        .bind_property("active", &folder_depth_row, "sensitive")
+        .sync_create()
+        .build();
+    folder_playlists_group.add(&folder_depth_row);
+
+    // Downloads
+    let downloads_group = adw::PreferencesGroup::builder()
+        .title(gettext("Downloads"))
+        .description(gettext(
+            "Fields for file names are {artist}, {album}, {title}, {track}, {disc}, {year} \
+             and {genre}. {track:02} pads the number to two digits and / starts a folder.",
+        ))
+        .build();
+
+    let watch_downloads_row = adw::SwitchRow::builder()
+        .title(gettext("Watch Downloads"))
+        .subtitle(gettext("Offer to move music saved to Downloads into the library"))
+        .build();
+    settings
+        .bind("watch-downloads", &watch_downloads_row, "active")
+        .build();
+    downloads_group.add(&watch_downloads_row);
+
+    let naming_row = adw::EntryRow::builder()
+        .title(gettext("File Names"))
+        .build();
+    settings
+        .bind("file-naming-template", &naming_row, "text")
+        .build();
+    downloads_group.add(&naming_row);
+
+    // Playback
+    let playback_group = adw::PreferencesGroup::builder()
+        .title(gettext("Playback"))
+        .build();
+
+    let volume_curve_row = adw::SwitchRow::builder()
+        .title(gettext("Logarithmic Volume"))
+        .subtitle(gettext("Make volume changes sound even across the whole slider"))
+        .build();
+    settings
+        .bind("logarithmic-volume", &volume_curve_row, "active")
+        .build();
+    playback_group.add(&volume_curve_row);
+
+    let limiter_row = adw::SwitchRow::builder()
+        .title(gettext("Volume Limiter"))
+        .subtitle(gettext("Cap the volume and hold down loud peaks to protect your ears"))
+        .build();
+    settings
+        .bind("volume-limiter", &limiter_row, "active")
+        .build();
+    playback_group.add(&limiter_row);
+
+    let max_volume_row = adw::SpinRow::builder()
+        .title(gettext("Maximum Volume"))
+        .subtitle(gettext("Percent of full volume the limiter allows"))
+        .adjustment(&gtk::Adjustment::new(60.0, 5.0, 100.0, 5.0, 10.0, 0.0))
+        .build();
+    settings
+        .bind("max-volume-percent", &max_volume_row, "value")
+        .build();
+    limiter_row
+        .bind_property("active", &max_volume_row, "sensitive")
+        .sync_create()
+        .build();
+    playback_group.add(&max_volume_row);
+
+    let normalize_volume_row = adw::SwitchRow::builder()
+        .title(gettext("Level Volume"))
+        .subtitle(gettext("Play tracks at an even loudness using their ReplayGain tags"))
+        .build();
+    settings
+        .bind("normalize-volume", &normalize_volume_row, "active")
+        .build();
+    playback_group.add(&normalize_volume_row);
+
+    let clipping_row = create_choice_row(
+        settings,
+        "clipping-prevention",
+        &gettext("Clipping Prevention"),
+        &gettext("When leveling would push a track's peaks past full scale"),
+        CLIPPING_PREVENTION_CHOICES,
+    );
+    normalize_volume_row
+        .bind_property("active", &clipping_row, "sensitive")
+        .sync_create()
+        .build();
+    playback_group.add(&clipping_row);
+
+    let explicit_row = create_choice_row(
+        settings,
+        "explicit-content-filter",
+        &gettext("Explicit Content"),
+        &gettext("Tracks with a parental advisory tag or provider flag"),
+        EXPLICIT_CONTENT_FILTERS,
+    );
+    playback_group.add(&explicit_row);
+
+    let smart_shuffle_row = adw::SwitchRow::builder()
+        .title(gettext("Smart Shuffle"))
+        .subtitle(gettext("Avoid playing the same artist or album back to back"))
+        .build();
+    settings
+        .bind("smart-shuffle", &smart_shuffle_row, "active")
+        .build();
+    playback_group.add(&smart_shuffle_row);
+
+    let dj_mode_row = adw::SwitchRow::builder()
+        .title(gettext("DJ Mode"))
+        .subtitle(gettext(
+            "Crossfade on the beat and keep playing tracks that fit when the queue ends",
+        ))
+        .build();
+    settings.bind("dj-mode", &dj_mode_row, "active").build();
+    playback_group.add(&dj_mode_row);
+
+    let fade_row = adw::SpinRow::builder()
+        .title(gettext("Fade Duration"))
+        .subtitle(gettext("Milliseconds to fade in and out on play, pause and seek"))
+        .adjustment(&gtk::Adjustment::new(200.0, 0.0, 1000.0, 50.0, 100.0, 0.0))
+        .build();
+    settings.bind("fade-duration-ms", &fade_row, "value").build();
+    playback_group.add(&fade_row);
+
+    let buffer_row = adw::SpinRow::builder()
+        .title(gettext("Output Buffer"))
+        .subtitle(gettext("Milliseconds held ahead; raise it if playback drops out"))
+        .adjustment(&gtk::Adjustment::new(200.0, 20.0, 2000.0, 20.0, 100.0, 0.0))
+        .build();
+    settings.bind("output-buffer-ms", &buffer_row, "value").build();
+    playback_group.add(&buffer_row);
+
+    let latency_row = adw::SpinRow::builder()
+        .title(gettext("Output Latency"))
+        .subtitle(gettext("Milliseconds sent to the device at a time"))
+        .adjustment(&gtk::Adjustment::new(10.0, 1.0, 100.0, 1.0, 10.0, 0.0))
+        .build();
+    settings.bind("output-latency-ms", &latency_row, "value").build();
+    playback_group.add(&latency_row);
+
+    let resampler_row = create_choice_row(
+        settings,
+        "resampler-quality",
+        &gettext("Resampler Quality"),
+        &gettext("Converting to the output's sample rate; High matches SoX's best"),
+        RESAMPLER_QUALITIES,
+    );
+    playback_group.add(&resampler_row);
+
+    let restart_row = adw::SpinRow::builder()
+        .title(gettext("Previous Restarts Track After"))
+        .subtitle(gettext("Seconds in; 0 always goes to the track before"))
+        .adjustment(&gtk::Adjustment::new(3.0, 0.0, 30.0, 1.0, 5.0, 0.0))
+        .build();
+    settings
+        .bind("previous-restart-seconds", &restart_row, "value")
+        .build();
+    playback_group.add(&restart_row);
+
+    let audible_seeking_row = adw::SwitchRow::builder()
+        .title(gettext("Hear Audio While Seeking"))
+        .subtitle(gettext("Holding next or previous plays the track sped up"))
+        .build();
+    settings
+        .bind("audible-seeking", &audible_seeking_row, "active")
+        .build();
+    playback_group.add(&audible_seeking_row);
+
+    let output_return_row = adw::SwitchRow::builder()
+        .title(gettext("Resume When Headphones Return"))
+        .subtitle(gettext("Playback pauses when its output disconnects"))
+        .build();
+    settings
+        .bind("resume-on-output-return", &output_return_row, "active")
+        .build();
+    playback_group.add(&output_return_row);
+
+    let unfinished_queue_row = create_choice_row(
+        settings,
+        "unfinished-queue",
+        &gettext("Unfinished Queue"),
+        &gettext("What happens to the rest of the queue when Nova is closed"),
+        UNFINISHED_QUEUE_ACTIONS,
+    );
+    playback_group.add(&unfinished_queue_row);
+
+    // Search
+    let search_group = adw::PreferencesGroup::builder()
+        .title(gettext("Search"))
+        .build();
+
+    let prefer_local_row = adw::SwitchRow::builder()
+        .title(gettext("Prefer Local Results"))
+        .subtitle(gettext("Rank tracks from your library above streamed matches"))
+        .build();
+    settings
+        .bind("prefer-local-results", &prefer_local_row, "active")
+        .build();
+    search_group.add(&prefer_local_row);
+
+    let ranking_row = create_choice_row(
+        settings,
+        "search-ranking",
+        &gettext("Ranking"),
+        &gettext("Which results come first when they match equally well"),
+        SEARCH_RANKINGS,
+    );
+    search_group.add(&ranking_row);
+
+    let custom_weights_row = adw::EntryRow::builder()
+        .title(gettext("Custom Weights"))
+        .tooltip_text(gettext(
+            "Such as “artist=2 exact=1.5 provider.local=2”. Also track, album, \
+             artist-match and recent.",
+        ))
+        .build();
+    settings
+        .bind("custom-search-weights", &custom_weights_row, "text")
+        .build();
+    // Weights that don't parse are ignored, so point them out while typing
+    let update_custom_weights = |row: &adw::EntryRow| {
+        if parse_weights(&row.text()).is_ok() {
+            row.remove_css_class("error");
+        } else {
+            row.add_css_class("error");
+        }
+    };
+    update_custom_weights(&custom_weights_row);
+    custom_weights_row.connect_changed(update_custom_weights);
+    let is_custom = |row: &adw::ComboRow| {
+        SEARCH_RANKINGS
+            .get(row.selected() as usize)
+            .is_some_and(|(value, _)| *value == "custom")
+    };
+    custom_weights_row.set_sensitive(is_custom(&ranking_row));
+    let weights_row = custom_weights_row.clone();
+    ranking_row.connect_selected_notify(move |row| weights_row.set_sensitive(is_custom(row)));
+    search_group.add(&custom_weights_row);
+
+    let type_to_filter_row = adw::SwitchRow::builder()
+        .title(gettext("Type to Filter Pages"))
+        .subtitle(gettext(
+            "Typing on Artists or Albums filters the page instead of searching everything",
+        ))
+        .build();
+    settings
+        .bind("type-to-filter", &type_to_filter_row, "active")
+        .build();
+    search_group.add(&type_to_filter_row);
+
+    // Appearance
+    let appearance_group = adw::PreferencesGroup::builder()
+        .title(gettext("Appearance"))
+        .build();
+
+    let layout_row = create_choice_row(
+        settings,
+        "player-bar-layout",
+        &gettext("Player Bar Layout"),
+        &gettext("Expanded shows larger artwork and the next track"),
+        PLAYER_BAR_LAYOUTS,
+    );
+    appearance_group.add(&layout_row);
+
+    let startup_page_row = create_choice_row(
+        settings,
+        "startup-page",
+        &gettext("Startup Page"),
+        &gettext("The page shown when Nova opens"),
+        STARTUP_PAGES,
+    );
+    appearance_group.add(&startup_page_row);
+
+    let context_home_row = adw::SwitchRow::builder()
+        .title(gettext("Adapt Home to the Time of Day"))
+        .subtitle(gettext("Show what you usually play at this time first"))
+        .build();
+    settings
+        .bind("context-aware-home", &context_home_row, "active")
+        .build();
+    appearance_group.add(&context_home_row);
+
+    // Network
+    let network_group = adw::PreferencesGroup::builder()
+        .title(gettext("Network"))
+        .build();
+
+    let resume_row = adw::SwitchRow::builder()
+        .title(gettext("Resume on Reconnect"))
+        .subtitle(gettext("Continue streaming that paused when the connection dropped"))
+        .build();
+    settings
+        .bind("resume-on-reconnect", &resume_row, "active")
+        .build();
+    network_group.add(&resume_row);
+
+    let metered_row = create_choice_row(
+        settings,
+        "metered-connection-policy",
+        &gettext("Metered Connections"),
+        &gettext("How streaming and downloads behave on limited data plans"),
+        METERED_POLICIES,
+    );
+    network_group.add(&metered_row);
+
+    let metadata_row = adw::SwitchRow::builder()
+        .title(gettext("Fetch Artist Info"))
+        .subtitle(gettext("Biographies and similar artists from Last.fm and MusicBrainz"))
+        .build();
+    settings
+        .bind("network-metadata", &metadata_row, "active")
+        .build();
+    network_group.add(&metadata_row);
+
+    let updates_row = adw::SwitchRow::builder()
+        .title(gettext("Check for Updates"))
+        .subtitle(gettext("Ask GitHub once a day whether a newer Nova is out"))
+        .build();
+    settings
+        .bind("check-for-updates", &updates_row, "active")
+        .build();
+    network_group.add(&updates_row);
+
+    let lastfm_key_row = adw::PasswordEntryRow::builder()
+        .title(gettext("Last.fm API Key"))
+        .build();
+    settings
+        .bind("lastfm-api-key", &lastfm_key_row, "text")
+        .build();
+    metadata_row
+        .bind_property("active", &lastfm_key_row, "sensitive")
+        .sync_create()
+        .build();
+    network_group.add(&lastfm_key_row);
+
+    let listenbrainz_row = adw::PasswordEntryRow::builder()
+        .title(gettext("ListenBrainz Token"))
+        .build();
+    let row_weak = listenbrainz_row.downgrade();
+    gtk::glib::MainContext::default().spawn_local(async move {
+        match secrets::load(secrets::LISTENBRAINZ_TOKEN).await {
+            Ok(token) => {
+                if let Some(row) = row_weak.upgrade().filter(|row| row.text().is_empty()) {
+                    row.set_text(&token.unwrap_or_default());
+                }
+            }
+            Err(e) => eprintln!("Failed to read the ListenBrainz token: {}", e),
+        }
+    });
+    listenbrainz_row.set_show_apply_button(true);
+    listenbrainz_row.connect_apply(|row| {
+        let token = row.text().trim().to_string();
+        gtk::glib::MainContext::default().spawn_local(async move {
+            if let Err(e) = secrets::store(secrets::LISTENBRAINZ_TOKEN, token).await {
+                eprintln!("Failed to save the ListenBrainz token: {}", e);
+            }
+        });
+    });
+    network_group.add(&listenbrainz_row);
+
+    let response_cache_row = adw::ActionRow::builder()
+        .title(gettext("Cached Lookups"))
+        .subtitle(gettext("Artist info kept on disk for quick and offline use"))
+        .build();
+    let clear_cache_button = gtk::Button::builder()
+        .label(gettext("Clear"))
+        .valign(gtk::Align::Center)
+        .build();
+    let dialog_weak = dialog.downgrade();
+    clear_cache_button.connect_clicked(move |_| {
+        let dialog_weak = dialog_weak.clone();
+        gtk::glib::MainContext::default().spawn_local(async move {
+            let message = match http::clear_cache().await {
+                Ok(freed) => gettext("Cleared {}").replace("{}", &gtk::glib::format_size(freed)),
+                Err(e) => {
+                    eprintln!("Failed to clear cached lookups: {}", e);
+                    gettext("Couldn't clear cached lookups")
+                }
+            };
+            if let Some(dialog) = dialog_weak.upgrade() {
+                dialog.add_toast(adw::Toast::new(&message));
+            }
+        });
+    });
+    response_cache_row.add_suffix(&clear_cache_button);
+    network_group.add(&response_cache_row);
+
+    // Performance
+    let performance_group = adw::PreferencesGroup::builder()
+        .title(gettext("Performance"))
+        .build();
+
+    let low_memory_row = adw::SwitchRow::builder()
+        .title(gettext("Low-Memory Mode"))
+        .subtitle(gettext(
+            "Use less memory on older computers, with smaller artwork and slower scans",
+        ))
+        .build();
+    settings
+        .bind("low-memory-mode", &low_memory_row, "active")
+        .build();
+    performance_group.add(&low_memory_row);
+
+    let scan_priority_row = create_choice_row(
+        settings,
+        "scan-priority",
+        &gettext("Scan Priority"),
+        &gettext("How hard library scans work the computer; on battery they wait for idle time"),
+        SCAN_PRIORITIES,
+    );
+    performance_group.add(&scan_priority_row);
+
+    page.add(&library_group);
+    page.add(&providers_group);
+    page.add(&create_webdav_group(settings));
+    page.add(&folder_playlists_group);
+    page.add(&downloads_group);
+    page.add(&create_write_back_group(settings));
+    page.add(&playback_group);
+    page.add(&search_group);
+    page.add(&create_genres_group(settings));
+    page.add(&appearance_group);
+    page.add(&network_group);
+    page.add(&performance_group);
+    dialog.add(&page);
+    dialog.add(&create_plugins_page(settings));
+
+    dialog
+}
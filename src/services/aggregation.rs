@@ -0,0 +1,213 @@
+use crate::services::models::{
+    Album, Artist, Artwork, ArtworkSource, Availability, ItemSource, PlayableItem, PlaybackSource,
+    Track,
+};
+use std::collections::HashMap;
+
+/// Matching key for names coming from different providers: case and
+/// whitespace differences are ignored
+fn normalize(value: &str) -> String {
+    value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn has_artwork(artwork: &Option<Artwork>) -> bool {
+    match artwork {
+        Some(artwork) => {
+            artwork.thumbnails.is_some() || !matches!(artwork.full_art, ArtworkSource::None)
+        }
+        None => false,
+    }
+}
+
+fn merge_sources(into: &mut Vec<ItemSource>, from: Vec<ItemSource>) {
+    for source in from {
+        if !into.contains(&source) {
+            into.push(source);
+        }
+    }
+}
+
+fn merge_ids(into: &mut Vec<String>, from: Vec<String>) {
+    for id in from {
+        if !into.contains(&id) {
+            into.push(id);
+        }
+    }
+}
+
+// Copies of a track further apart in length than this are different
+// recordings, such as a live take or a radio edit
+const SAME_TRACK_SECONDS: u32 = 5;
+
+// File formats that keep all of the recording
+const LOSSLESS_FORMATS: [&str; 7] = ["flac", "wav", "aiff", "aif", "ape", "wv", "alac"];
+
+/// Whether `track` is a lossless file
+pub fn is_lossless(track: &Track) -> bool {
+    match &track.source {
+        PlaybackSource::Local { file_format, .. } => {
+            LOSSLESS_FORMATS.contains(&file_format.to_lowercase().as_str())
+        }
+        _ => false,
+    }
+}
+
+/// How good a copy of a track is, higher being better: lossless files, then
+/// other files, then a provider's own streams, then the big services
+fn quality(track: &Track) -> u8 {
+    match &track.source {
+        PlaybackSource::Local { .. } if is_lossless(track) => 3,
+        PlaybackSource::Local { .. } => 2,
+        PlaybackSource::Stream { .. } => 1,
+        PlaybackSource::Spotify { .. } | PlaybackSource::YouTube { .. } => 0,
+    }
+}
+
+/// Which copy of a track to open: one that plays, then the best quality
+fn preference(item: &PlayableItem) -> (bool, u8) {
+    (
+        item.track.availability != Availability::Unavailable,
+        quality(&item.track),
+    )
+}
+
+fn same_length(a: &Track, b: &Track) -> bool {
+    // Some providers don't know how long their tracks are
+    a.duration == 0 || b.duration == 0 || a.duration.abs_diff(b.duration) <= SAME_TRACK_SECONDS
+}
+
+/// Whether copies of one track differ in quality, so the best is worth
+/// pointing out
+pub fn differs_in_quality(item: &PlayableItem, others: &[PlayableItem]) -> bool {
+    others
+        .iter()
+        .any(|other| quality(&other.track) != quality(&item.track))
+}
+
+/// Record `provider` as the source of every album that doesn't name one yet
+pub fn tag_albums(albums: &mut [Album], provider: &str) {
+    for album in albums.iter_mut().filter(|album| album.sources.is_empty()) {
+        album.sources.push(ItemSource {
+            provider: provider.to_string(),
+            id: album.id.clone(),
+        });
+    }
+}
+
+/// Record `provider` as the source of every artist that doesn't name one yet
+pub fn tag_artists(artists: &mut [Artist], provider: &str) {
+    for artist in artists.iter_mut().filter(|artist| artist.sources.is_empty()) {
+        artist.sources.push(ItemSource {
+            provider: provider.to_string(),
+            id: artist.id.clone(),
+        });
+    }
+}
+
+/// Collapse copies of the same album from different providers into one entry
+/// that lists every source. The first copy seen keeps its position and ID;
+/// missing metadata is filled in from the later copies.
+pub fn merge_albums(albums: Vec<Album>) -> Vec<Album> {
+    let mut merged: Vec<Album> = Vec::new();
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+
+    for album in albums {
+        let key = (normalize(&album.artist), normalize(&album.title));
+        match index.get(&key) {
+            Some(&i) => {
+                let existing = &mut merged[i];
+                if existing.year.is_none() {
+                    existing.year = album.year;
+                }
+                if existing.art_url.is_none() {
+                    existing.art_url = album.art_url;
+                }
+                if !has_artwork(&existing.artwork) && has_artwork(&album.artwork) {
+                    existing.artwork = album.artwork;
+                }
+                merge_ids(&mut existing.tracks, album.tracks);
+                merge_sources(&mut existing.sources, album.sources);
+            }
+            None => {
+                index.insert(key, merged.len());
+                merged.push(album);
+            }
+        }
+    }
+
+    merged
+}
+
+/// Collapse copies of the same artist from different providers into one
+/// entry that lists every source
+pub fn merge_artists(artists: Vec<Artist>) -> Vec<Artist> {
+    let mut merged: Vec<Artist> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for artist in artists {
+        let key = normalize(&artist.name);
+        match index.get(&key) {
+            Some(&i) => {
+                let existing = &mut merged[i];
+                if !has_artwork(&existing.artwork) && has_artwork(&artist.artwork) {
+                    existing.artwork = artist.artwork;
+                }
+                merge_ids(&mut existing.albums, artist.albums);
+                merge_sources(&mut existing.sources, artist.sources);
+            }
+            None => {
+                index.insert(key, merged.len());
+                merged.push(artist);
+            }
+        }
+    }
+
+    merged
+}
+
+/// Collapse copies of the same track, from different providers or in
+/// different formats, into the best of them. It takes the place of the
+/// first copy seen. The copies left out are returned by the kept copy's ID.
+pub fn merge_tracks(
+    tracks: Vec<PlayableItem>,
+) -> (Vec<PlayableItem>, HashMap<String, Vec<PlayableItem>>) {
+    let mut merged: Vec<PlayableItem> = Vec::new();
+    let mut others: Vec<Vec<PlayableItem>> = Vec::new();
+    let mut index: HashMap<(String, String), Vec<usize>> = HashMap::new();
+
+    for item in tracks {
+        let key = (normalize(&item.track.artist), normalize(&item.track.title));
+        let candidates = index.entry(key).or_default();
+        let found = candidates
+            .iter()
+            .copied()
+            .find(|&i| same_length(&merged[i].track, &item.track));
+        match found {
+            Some(i) => {
+                if preference(&item) > preference(&merged[i]) {
+                    let kept = std::mem::replace(&mut merged[i], item);
+                    others[i].push(kept);
+                } else {
+                    others[i].push(item);
+                }
+            }
+            None => {
+                candidates.push(merged.len());
+                merged.push(item);
+                others.push(Vec::new());
+            }
+        }
+    }
+
+    let versions = merged
+        .iter()
+        .zip(others)
+        .filter(|(_, others)| !others.is_empty())
+        .map(|(item, others)| (item.track.id.clone(), others))
+        .collect();
+    (merged, versions)
+}
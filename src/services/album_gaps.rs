@@ -0,0 +1,107 @@
+//! Tracks an album is missing, going by the track numbers and track totals
+//! in the tags of the tracks that are there
+
+use crate::services::models::{PlayableItem, Track};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A track number with no track in the library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MissingTrack {
+    pub disc: Option<u32>,
+    pub number: u32,
+}
+
+/// Tracks missing from an album made of `tracks`, in disc and track order.
+/// A disc without a track total is taken to end at its highest number.
+pub fn missing_tracks(tracks: &[Track]) -> Vec<MissingTrack> {
+    let mut discs: BTreeMap<Option<u32>, (BTreeSet<u32>, Option<u32>)> = BTreeMap::new();
+    for track in tracks {
+        let Some(number) = track.track_number.filter(|number| *number > 0) else {
+            continue;
+        };
+        let (numbers, total) = discs.entry(track.disc_number).or_default();
+        numbers.insert(number);
+        *total = (*total).max(track.track_total);
+    }
+
+    let mut missing = Vec::new();
+    for (disc, (numbers, total)) in discs {
+        let last = total.unwrap_or_default().max(*numbers.last().unwrap_or(&0));
+        missing.extend(
+            (1..=last)
+                .filter(|number| !numbers.contains(number))
+                .map(|number| MissingTrack { disc, number }),
+        );
+    }
+    missing
+}
+
+/// "3, 5–7" or, with several discs, "Disc 1: 3 • Disc 2: 5–7"
+pub fn describe_missing(missing: &[MissingTrack]) -> String {
+    let mut discs: BTreeMap<Option<u32>, Vec<u32>> = BTreeMap::new();
+    for track in missing {
+        discs.entry(track.disc).or_default().push(track.number);
+    }
+    let several = discs.len() > 1;
+    let parts: Vec<String> = discs
+        .into_iter()
+        .map(|(disc, numbers)| {
+            let ranges = number_ranges(&numbers);
+            match disc {
+                Some(disc) if several => format!("Disc {}: {}", disc, ranges),
+                _ => ranges,
+            }
+        })
+        .collect();
+    parts.join(" • ")
+}
+
+/// "3, 5–7" from sorted `numbers`
+fn number_ranges(numbers: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &number in numbers {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == number => *end = number,
+            _ => ranges.push((number, number)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}–{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Tracks among `found` that fill `missing` on the album `album` by
+/// `artist`, in album order with one per gap
+pub fn fill_gaps(
+    missing: &[MissingTrack],
+    album: &str,
+    artist: &str,
+    found: Vec<PlayableItem>,
+) -> Vec<PlayableItem> {
+    let mut fills: BTreeMap<MissingTrack, PlayableItem> = BTreeMap::new();
+    for item in found {
+        let track = &item.track;
+        if !track.album.eq_ignore_ascii_case(album) || !track.artist.eq_ignore_ascii_case(artist) {
+            continue;
+        }
+        let Some(number) = track.track_number else {
+            continue;
+        };
+        // A provider that leaves out disc numbers still fills a single disc
+        let gap = missing.iter().find(|gap| {
+            gap.number == number && (gap.disc == track.disc_number || track.disc_number.is_none())
+        });
+        if let Some(gap) = gap {
+            fills.entry(*gap).or_insert(item);
+        }
+    }
+    fills.into_values().collect()
+}
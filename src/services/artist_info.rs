@@ -0,0 +1,266 @@
+//! Artist biographies and similar artists for the artist page. Last.fm has
+//! both but needs an API key; without one, or when it doesn't know the
+//! artist, a short description is put together from MusicBrainz instead.
+//! Results are cached in the user store so they show up offline too.
+
+use crate::services::http::HttpClient;
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use std::error::Error;
+use std::sync::OnceLock;
+
+// Cached info older than this is fetched again when the network allows
+const REFRESH_AFTER_DAYS: i64 = 30;
+
+// MusicBrainz search scores go up to 100; below this it's likely someone else
+const MIN_MUSICBRAINZ_SCORE: u32 = 90;
+
+const MAX_SIMILAR_ARTISTS: usize = 12;
+
+const LASTFM_API: &str = "https://ws.audioscrobbler.com/2.0/";
+const MUSICBRAINZ_API: &str = "https://musicbrainz.org/ws/2/artist/";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtistInfo {
+    /// Artist name as it was looked up
+    pub name: String,
+    pub bio: Option<String>,
+    /// Names of similar artists, most similar first
+    pub similar: Vec<String>,
+    /// Page the info came from, for reading more
+    pub url: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl ArtistInfo {
+    /// Whether the info is old enough to be fetched again
+    pub fn is_stale(&self) -> bool {
+        Utc::now() - self.fetched_at > Duration::days(REFRESH_AFTER_DAYS)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bio.is_none() && self.similar.is_empty()
+    }
+}
+
+/// Look `name` up online, on Last.fm when there's an API key and falling
+/// back to MusicBrainz
+pub async fn fetch_artist_info(
+    name: &str,
+    lastfm_api_key: Option<&str>,
+) -> Result<ArtistInfo, Box<dyn Error + Send + Sync>> {
+    if let Some(key) = lastfm_api_key.filter(|key| !key.is_empty()) {
+        match fetch_from_lastfm(name, key).await {
+            Ok(info) if info.bio.is_some() => return Ok(info),
+            Ok(info) => {
+                // Keep Last.fm's similar artists with MusicBrainz's description
+                let bio = fetch_from_musicbrainz(name).await.ok().and_then(|mb| mb.bio);
+                return Ok(ArtistInfo { bio, ..info });
+            }
+            Err(e) => eprintln!("Last.fm lookup for {} failed: {}", name, e),
+        }
+    }
+    fetch_from_musicbrainz(name).await
+}
+
+fn client() -> &'static HttpClient {
+    static CLIENT: OnceLock<HttpClient> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        // MusicBrainz turns away requests without a descriptive user agent
+        let client = reqwest::Client::builder()
+            .user_agent(concat!(
+                "Nova/",
+                env!("CARGO_PKG_VERSION"),
+                " (https://github.com/jumpers775/Nova)"
+            ))
+            .timeout(std::time::Duration::from_secs(15))
+            .build()
+            .unwrap_or_default();
+        HttpClient::new(client)
+    })
+}
+
+#[derive(Deserialize)]
+struct LastfmResponse {
+    artist: Option<LastfmArtist>,
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LastfmArtist {
+    url: Option<String>,
+    bio: Option<LastfmBio>,
+    similar: Option<LastfmSimilar>,
+}
+
+#[derive(Deserialize)]
+struct LastfmBio {
+    summary: String,
+}
+
+#[derive(Deserialize)]
+struct LastfmSimilar {
+    #[serde(default)]
+    artist: Vec<LastfmSimilarArtist>,
+}
+
+#[derive(Deserialize)]
+struct LastfmSimilarArtist {
+    name: String,
+}
+
+async fn fetch_from_lastfm(
+    name: &str,
+    api_key: &str,
+) -> Result<ArtistInfo, Box<dyn Error + Send + Sync>> {
+    let request = client().get(LASTFM_API).query(&[
+        ("method", "artist.getinfo"),
+        ("artist", name),
+        ("api_key", api_key),
+        ("autocorrect", "1"),
+        ("format", "json"),
+    ]);
+    // Errors come back as JSON with a message, whatever the status
+    let response: LastfmResponse = client().fetch(request).await?.json()?;
+
+    let artist = match (response.artist, response.message) {
+        (Some(artist), _) => artist,
+        (None, Some(message)) => return Err(message.into()),
+        (None, None) => return Err("Last.fm sent no artist".into()),
+    };
+
+    let bio = artist
+        .bio
+        .map(|bio| strip_html(&bio.summary))
+        .filter(|bio| !bio.is_empty());
+    let similar = artist
+        .similar
+        .map(|similar| similar.artist)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|artist| artist.name)
+        .take(MAX_SIMILAR_ARTISTS)
+        .collect();
+
+    Ok(ArtistInfo {
+        name: name.to_string(),
+        bio,
+        similar,
+        url: artist.url,
+        fetched_at: Utc::now(),
+    })
+}
+
+/// Drop markup from a Last.fm summary, along with the "Read more on
+/// Last.fm" link it always ends with
+fn strip_html(summary: &str) -> String {
+    let summary = match summary.find("<a href") {
+        Some(link) => &summary[..link],
+        None => summary,
+    };
+
+    let mut text = String::with_capacity(summary.len());
+    let mut in_tag = false;
+    for c in summary.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&amp;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .trim()
+        .to_string()
+}
+
+#[derive(Deserialize)]
+struct MusicBrainzSearch {
+    #[serde(default)]
+    artists: Vec<MusicBrainzArtist>,
+}
+
+#[derive(Deserialize)]
+struct MusicBrainzArtist {
+    id: String,
+    name: String,
+    #[serde(default)]
+    score: u32,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    area: Option<MusicBrainzArea>,
+    #[serde(rename = "life-span")]
+    life_span: Option<MusicBrainzLifeSpan>,
+    disambiguation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MusicBrainzArea {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct MusicBrainzLifeSpan {
+    begin: Option<String>,
+    end: Option<String>,
+}
+
+async fn fetch_from_musicbrainz(name: &str) -> Result<ArtistInfo, Box<dyn Error + Send + Sync>> {
+    let query = format!("artist:\"{}\"", name.replace('"', ""));
+    let request = client().get(MUSICBRAINZ_API).query(&[
+        ("query", query.as_str()),
+        ("fmt", "json"),
+        ("limit", "1"),
+    ]);
+    let search: MusicBrainzSearch = client().fetch(request).await?.json()?;
+
+    let artist = search
+        .artists
+        .into_iter()
+        .find(|artist| artist.score >= MIN_MUSICBRAINZ_SCORE)
+        .ok_or("MusicBrainz doesn't know the artist")?;
+
+    Ok(ArtistInfo {
+        name: name.to_string(),
+        bio: describe(&artist),
+        similar: Vec::new(),
+        url: Some(format!("https://musicbrainz.org/artist/{}", artist.id)),
+        fetched_at: Utc::now(),
+    })
+}
+
+/// One line about an artist, such as "Group from Iceland, 1994–present.
+/// Post-rock band."
+fn describe(artist: &MusicBrainzArtist) -> Option<String> {
+    let base = artist.kind.clone().unwrap_or_else(|| artist.name.clone());
+    let mut first = base.clone();
+    if let Some(area) = &artist.area {
+        first.push_str(&format!(" from {}", area.name));
+    }
+    if let Some(span) = &artist.life_span {
+        let year = |date: &Option<String>| date.as_deref().map(|d| d[..d.len().min(4)].to_string());
+        match (year(&span.begin), year(&span.end)) {
+            (Some(begin), Some(end)) => first.push_str(&format!(", {}–{}", begin, end)),
+            (Some(begin), None) => first.push_str(&format!(", {}–present", begin)),
+            _ => {}
+        }
+    }
+
+    let mut sentences = Vec::new();
+    // A bare "Person." says nothing worth showing
+    if first != base {
+        sentences.push(format!("{}.", first));
+    }
+    if let Some(disambiguation) = artist.disambiguation.as_deref().filter(|d| !d.is_empty()) {
+        let mut chars = disambiguation.chars();
+        let capitalized: String = chars
+            .next()
+            .map(|c| c.to_uppercase().chain(chars).collect())
+            .unwrap_or_default();
+        sentences.push(format!("{}.", capitalized));
+    }
+
+    (!sentences.is_empty()).then(|| sentences.join(" "))
+}
@@ -0,0 +1,103 @@
+//! Audio outputs coming and going, such as Bluetooth headphones connecting
+//! and disconnecting, as GStreamer's device monitor reports them
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::rc::Rc;
+
+/// An output that appeared or went away
+#[derive(Debug, Clone)]
+pub enum OutputChange {
+    Added(OutputDevice),
+    /// The device, and whether sound was going to it as far as can be told
+    Removed(OutputDevice, bool),
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputDevice {
+    pub name: String,
+    // Whether the sound server sends to it when not told otherwise. Not
+    // every platform says.
+    pub is_default: Option<bool>,
+}
+
+impl OutputDevice {
+    fn from_device(device: &gst::Device) -> Self {
+        let is_default = device
+            .properties()
+            .and_then(|props| props.get::<bool>("is-default").ok());
+        Self {
+            name: device.display_name().to_string(),
+            is_default,
+        }
+    }
+}
+
+/// Watches outputs for as long as it's kept, reporting changes on the main
+/// context
+#[derive(Debug)]
+pub struct OutputWatch {
+    monitor: gst::DeviceMonitor,
+    _bus_watch: gst::bus::BusWatchGuard,
+}
+
+impl OutputWatch {
+    pub fn start(
+        on_change: impl Fn(OutputChange) + 'static,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        gst::init()?;
+        let monitor = gst::DeviceMonitor::new();
+        monitor
+            .add_filter(Some("Audio/Sink"), None)
+            .ok_or("Audio outputs can't be watched here")?;
+
+        // Outputs present, kept so a removal can be told apart from the rest
+        let known: Rc<RefCell<HashMap<String, OutputDevice>>> = Rc::default();
+        let known_clone = known.clone();
+        let bus_watch = monitor.bus().add_watch_local(move |_, msg| {
+            match msg.view() {
+                gst::MessageView::DeviceAdded(added) => {
+                    let device = OutputDevice::from_device(&added.device());
+                    known_clone
+                        .borrow_mut()
+                        .insert(device.name.clone(), device.clone());
+                    on_change(OutputChange::Added(device));
+                }
+                gst::MessageView::DeviceRemoved(removed) => {
+                    let device = OutputDevice::from_device(&removed.device());
+                    let mut known = known_clone.borrow_mut();
+                    known.remove(&device.name);
+                    // Without defaults to go by, any output going may have
+                    // been the one in use
+                    let was_active = match device.is_default {
+                        Some(is_default) => is_default,
+                        None => known.values().all(|other| other.is_default.is_none()),
+                    };
+                    drop(known);
+                    on_change(OutputChange::Removed(device, was_active));
+                }
+                _ => {}
+            }
+            gst::glib::ControlFlow::Continue
+        })?;
+
+        monitor.start()?;
+        for device in monitor.devices() {
+            let device = OutputDevice::from_device(&device);
+            known.borrow_mut().insert(device.name.clone(), device);
+        }
+        Ok(Self {
+            monitor,
+            _bus_watch: bus_watch,
+        })
+    }
+}
+
+impl Drop for OutputWatch {
+    fn drop(&mut self) {
+        self.monitor.stop();
+    }
+}
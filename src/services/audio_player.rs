@@ -1,21 +1,48 @@
-use crate::services::local::LocalAudioBackend;
-use crate::services::models::{PlayableItem, Track};
+use crate::services::local::{LocalAudioBackend, PipelineKind};
+use crate::services::models::{
+    PlayableItem, PlaybackSource, QueueEntry, ReleaseType, ShuffleExclusions, Track, Trim,
+};
+use crate::services::StreamingQuality;
 use async_trait::async_trait;
 use parking_lot::RwLock;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::Rng;
 use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::broadcast;
 
 #[derive(Debug)]
 pub struct AudioPlayer {
-    backend: Arc<dyn AudioBackend>,
+    // Backends to try in order when one can't decode a track
+    backends: Vec<Arc<dyn AudioBackend>>,
+    active_backend: Arc<RwLock<usize>>,
+    // Backend that last played each file format, tried first from then on
+    format_backends: Arc<RwLock<HashMap<String, usize>>>,
     queue: Arc<RwLock<Queue>>,
     current_track: Arc<RwLock<Option<Track>>>,
+    restart_threshold: Arc<RwLock<Duration>>,
+    // Start and stop points set on tracks, by track ID
+    trims: Arc<RwLock<HashMap<String, Trim>>>,
+    // Speed one track starts playing at, by its ID
+    start_rate: Arc<RwLock<Option<(String, f64)>>>,
+    // The next few tracks, sent whenever the queue or the place in it changes
+    upcoming: broadcast::Sender<Vec<Track>>,
 }
 
 #[async_trait::async_trait]
 pub trait AudioBackend: Send + Sync + std::fmt::Debug + Any {
-    fn play(&self, track: &Track) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    /// Short name for logs
+    fn name(&self) -> &'static str;
+    /// Play `track` from `start` into it, at `rate` times normal speed
+    fn play(
+        &self,
+        track: &Track,
+        start: Duration,
+        rate: f64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
     fn stop(&self);
     fn pause(&self);
     fn resume(&self);
@@ -24,83 +51,475 @@ pub trait AudioBackend: Send + Sync + std::fmt::Debug + Any {
     fn set_position(&self, position: Duration);
     fn get_duration(&self) -> Option<Duration>;
     fn set_volume(&self, volume: f64);
+    fn set_volume_curve(&self, curve: VolumeCurve);
+    /// Length of the volume ramps on play, pause, stop and seek. Zero
+    /// switches them off.
+    fn set_fade_duration(&self, duration: Duration);
+    /// Length of the fade when a track starts while another is playing, or
+    /// the fade duration with None
+    fn set_crossfade(&self, crossfade: Option<Duration>);
+    /// Cap the 0.0-1.0 volume at `limit` and hold down loud peaks, or
+    /// neither with None
+    fn set_volume_limit(&self, limit: Option<f64>);
+    /// Level tracks by their ReplayGain tags, keeping the gain from clipping
+    /// the way `normalization` says, or play them as they are with None
+    fn set_normalization(&self, normalization: Option<ClippingPrevention>);
+    /// Level by album gain instead of track gain, from the next track
+    fn set_album_gain(&self, album_gain: bool);
+    /// What each stage of the current pipeline does to the sound, or None
+    /// when nothing is loaded
+    fn gain_stages(&self) -> Option<GainStages>;
+    /// Have the level meter report, which costs a message on the main loop
+    /// every few dozen milliseconds, so it's only on while someone looks
+    fn set_metering(&self, metering: bool);
+    /// Play from the current position at `rate` times normal speed, going
+    /// backwards when negative, and back to normal with 1.0. Audio is left
+    /// out while seeking unless `audible`.
+    fn set_rate(&self, rate: f64, audible: bool);
+    /// Get a track expected to play next ready so the transition doesn't
+    /// wait on slow storage or decoders
+    fn preload(&self, track: &Track);
+    /// Buffering for the audio output, used from the next track
+    fn set_output_buffer(&self, buffer: OutputBuffer);
+    /// How carefully audio is converted for the output, used from the next
+    /// track
+    fn set_resampler_quality(&self, quality: ResamplerQuality);
+    /// Quality to stream remote tracks at, used from the next track
+    fn set_streaming_quality(&self, quality: StreamingQuality);
+    /// Time left until the track from the last play() is louder than the
+    /// one it's crossfading with. Zero once it is, or when nothing was
+    /// playing before it.
+    fn handover_delay(&self) -> Duration;
 
     fn as_any(&self) -> &(dyn Any + 'static);
 }
 
+/// How the 0.0-1.0 volume passed to the backend maps onto the sink volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeCurve {
+    Linear,
+    Logarithmic,
+}
+
+/// What volume leveling does when a track's gain would push its peaks past
+/// full scale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClippingPrevention {
+    /// Apply the full gain and hold the peaks down with a limiter
+    Limiter,
+    /// Apply only as much gain as the peaks allow
+    ReduceGain,
+}
+
+/// The signal chain as a track plays through it: ReplayGain leveling, the
+/// limiters, then the volume
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GainStages {
+    /// Gain from the track's ReplayGain tags in dB, None with leveling off
+    pub replay_gain_db: Option<f64>,
+    /// Whether leveling's limiter holds down peaks the gain pushes past full
+    /// scale
+    pub clip_limiter: bool,
+    /// Whether the volume limit's peak limiter is in
+    pub peak_limiter: bool,
+    /// Output volume in dB, minus infinity when silent
+    pub volume_db: f64,
+    /// Latest peak and RMS level of each channel in dBFS, after the
+    /// limiters and before the volume
+    pub peak_db: Vec<f64>,
+    pub rms_db: Vec<f64>,
+    /// The track's audio as it's decoded, and as the output takes it once
+    /// converted and resampled. None until they're worked out.
+    pub source_format: Option<AudioFormat>,
+    pub output_format: Option<AudioFormat>,
+}
+
+/// Sample rate, sample format and channels of raw audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub rate: u32,
+    /// Significant bits per sample
+    pub depth: u32,
+    pub float: bool,
+    pub channels: u32,
+}
+
+impl std::fmt::Display for AudioFormat {
+    /// Such as "44.1 kHz · 16-bit · Stereo"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rate = self.rate as f64 / 1000.0;
+        let float = if self.float { " float" } else { "" };
+        let channels = match self.channels {
+            1 => "Mono".to_string(),
+            2 => "Stereo".to_string(),
+            n => format!("{} channels", n),
+        };
+        write!(
+            f,
+            "{} kHz · {}-bit{} · {}",
+            rate, self.depth, float, channels
+        )
+    }
+}
+
+/// How carefully sample rates and formats are converted for the output,
+/// trading CPU for accuracy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResamplerQuality {
+    /// Cheapest filter and no dithering, for slow machines
+    Fast,
+    /// GStreamer's defaults
+    #[default]
+    Medium,
+    /// Longest filter and noise-shaped dithering, like SoX's very high
+    /// quality
+    High,
+}
+
+/// How much audio the output holds ahead of what's heard. More rides out a
+/// busy system without dropouts; less makes pause and volume act sooner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputBuffer {
+    /// All the audio the output holds
+    pub buffer: Duration,
+    /// Each piece of it handed to the device
+    pub latency: Duration,
+}
+
+impl Default for OutputBuffer {
+    // GStreamer's own defaults
+    fn default() -> Self {
+        Self {
+            buffer: Duration::from_millis(200),
+            latency: Duration::from_millis(10),
+        }
+    }
+}
+
+// Shuffle weight kept by tracks that are always skipped, so they still turn
+// up, just rarely near the front
+const MIN_SHUFFLE_WEIGHT: f64 = 0.1;
+
+/// How far into a track Previous restarts it instead of going back
+const DEFAULT_RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+
+// Tracks after the current one sent to upcoming-track subscribers
+const UPCOMING_TRACKS: usize = 2;
+
+/// How the queue is reordered while shuffle is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShuffleMode {
+    Random,
+    /// Random, but never the same artist or album twice in a row when the
+    /// queue allows it
+    SmartSpacing,
+    /// Whole albums in random order, each played through in track order
+    Albums,
+}
+
 impl AudioPlayer {
     pub fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let backend = Arc::new(LocalAudioBackend::new()?);
+        let backends: Vec<Arc<dyn AudioBackend>> = vec![
+            Arc::new(LocalAudioBackend::new(PipelineKind::Playbin)?),
+            Arc::new(LocalAudioBackend::new(PipelineKind::Playbin3)?),
+        ];
 
+        let (upcoming, _) = broadcast::channel(16);
         Ok(Self {
-            backend,
+            backends,
+            active_backend: Arc::new(RwLock::new(0)),
+            format_backends: Arc::new(RwLock::new(HashMap::new())),
             queue: Arc::new(RwLock::new(Queue::new(Vec::new()))),
             current_track: Arc::new(RwLock::new(None)),
+            restart_threshold: Arc::new(RwLock::new(DEFAULT_RESTART_THRESHOLD)),
+            trims: Arc::new(RwLock::new(HashMap::new())),
+            start_rate: Arc::new(RwLock::new(None)),
+            upcoming,
         })
     }
 
-    pub fn load_queue(&self, tracks: Vec<PlayableItem>) {
+    /// The tracks coming up after the current one, each time the queue or
+    /// the place in it changes
+    pub fn subscribe_upcoming(&self) -> broadcast::Receiver<Vec<Track>> {
+        self.upcoming.subscribe()
+    }
+
+    fn queue_changed(&self) {
+        let upcoming = self.queue.read().upcoming(UPCOMING_TRACKS);
+        // Nobody listening isn't an error
+        let _ = self.upcoming.send(upcoming);
+    }
+
+    pub fn load_queue(&self, tracks: Vec<QueueEntry>) {
+        let mut queue = self.queue.write();
+        let shuffle = queue.shuffle_mode();
+        let skip_rates = std::mem::take(&mut queue.skip_rates);
+        let exclusions = std::mem::take(&mut queue.exclusions);
+        let repeat = queue.repeat;
+        *queue = Queue::new(tracks);
+        queue.skip_rates = skip_rates;
+        queue.exclusions = exclusions;
+        queue.repeat = repeat;
+        queue.set_shuffle(shuffle);
+        drop(queue);
+        self.queue_changed();
+    }
+
+    /// Load `tracks` already shuffled with `mode`. Nothing is current yet,
+    /// so the whole queue is shuffled; turning shuffle off restores the
+    /// order `tracks` came in.
+    pub fn load_shuffled(&self, tracks: Vec<QueueEntry>, mode: ShuffleMode) {
         let mut queue = self.queue.write();
+        let skip_rates = std::mem::take(&mut queue.skip_rates);
+        let exclusions = std::mem::take(&mut queue.exclusions);
+        let repeat = queue.repeat;
         *queue = Queue::new(tracks);
+        queue.skip_rates = skip_rates;
+        queue.exclusions = exclusions;
+        queue.repeat = repeat;
+        queue.set_shuffle(Some(mode));
+        drop(queue);
+        self.queue_changed();
+    }
+
+    /// Turn shuffle on with `mode`, or off with None to restore the order
+    /// the queue was loaded in
+    pub fn set_shuffle(&self, mode: Option<ShuffleMode>) {
+        self.queue.write().set_shuffle(mode);
+        self.queue_changed();
+    }
+
+    /// Go round to the start of the queue after its end, or stop there
+    pub fn set_repeat(&self, repeat: bool) {
+        self.queue.write().repeat = repeat;
+        self.queue_changed();
+    }
+
+    /// Share of plays each track was skipped in, by track ID. Shuffle moves
+    /// often skipped tracks towards the end.
+    pub fn set_skip_rates(&self, skip_rates: HashMap<String, f32>) {
+        self.queue.write().skip_rates = skip_rates;
+    }
+
+    /// Tracks shuffle leaves out
+    pub fn set_shuffle_exclusions(&self, exclusions: ShuffleExclusions) {
+        self.queue.write().exclusions = exclusions;
+    }
+
+    /// Where tracks start and stop playing, by track ID
+    pub fn set_trims(&self, trims: HashMap<String, Trim>) {
+        *self.trims.write() = trims;
+    }
+
+    /// Set where one track starts and stops playing, or clear it with None
+    pub fn set_trim(&self, track_id: &str, trim: Option<Trim>) {
+        match trim {
+            Some(trim) => self.trims.write().insert(track_id.to_string(), trim),
+            None => self.trims.write().remove(track_id),
+        };
+    }
+
+    /// Play the track with the ID given at the rate given from when it
+    /// starts, rather than changing its speed as it plays. None plays
+    /// every track at normal speed.
+    pub fn set_start_rate(&self, rate: Option<(String, f64)>) {
+        *self.start_rate.write() = rate;
+    }
+
+    pub fn trim(&self, track_id: &str) -> Option<Trim> {
+        self.trims.read().get(track_id).copied()
     }
 
+    /// Where the current track starts playing
+    pub fn trim_start(&self) -> Duration {
+        self.get_current_track()
+            .and_then(|track| self.trim(&track.id))
+            .map_or(Duration::ZERO, |trim| Duration::from_millis(trim.start_ms))
+    }
+
+    /// Where the current track stops playing, when not at its end
+    pub fn trim_end(&self) -> Option<Duration> {
+        let trim = self.trim(&self.get_current_track()?.id)?;
+        trim.end_ms.map(Duration::from_millis)
+    }
+
+    fn backend(&self) -> &Arc<dyn AudioBackend> {
+        &self.backends[*self.active_backend.read()]
+    }
+
+    /// Backend to try first for `track`: the one that last managed its
+    /// format, otherwise the first
+    fn preferred_backend(&self, track: &Track) -> usize {
+        track_format(track)
+            .and_then(|format| self.format_backends.read().get(format).copied())
+            .unwrap_or(0)
+    }
+
+    /// Play `track`, falling back to the other backends when the preferred
+    /// one can't decode it. The error is only reported once all have failed.
     pub fn play(&self, track: &Track) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        self.backend.play(track)?;
-        *self.current_track.write() = Some(track.clone());
-        Ok(())
+        self.play_from(track, Duration::ZERO)
+    }
+
+    /// `play` from `position` into the track, or from its trimmed start
+    /// when that's later
+    fn play_from(
+        &self,
+        track: &Track,
+        position: Duration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let start = self
+            .trim(&track.id)
+            .map_or(Duration::ZERO, |trim| Duration::from_millis(trim.start_ms))
+            .max(position);
+        let rate = match &*self.start_rate.read() {
+            Some((track_id, rate)) if *track_id == track.id => *rate,
+            _ => 1.0,
+        };
+        let preferred = self.preferred_backend(track);
+        let order =
+            std::iter::once(preferred).chain((0..self.backends.len()).filter(|&i| i != preferred));
+
+        let mut last_error = None;
+        for index in order {
+            let backend = &self.backends[index];
+            if let Err(e) = backend.play(track, start, rate) {
+                eprintln!("{} couldn't play {}: {}", backend.name(), track.title, e);
+                last_error = Some(e);
+                continue;
+            }
+
+            let previous = std::mem::replace(&mut *self.active_backend.write(), index);
+            if previous != index {
+                self.backends[previous].stop();
+            }
+            if let Some(format) = track_format(track) {
+                if index != preferred {
+                    println!("Using {} for {} from now on", backend.name(), format);
+                }
+                self.format_backends.write().insert(format.to_string(), index);
+            }
+            *self.current_track.write() = Some(track.clone());
+            self.queue_changed();
+
+            // Get the upcoming queue item ready while this one plays
+            if let Some(next_track) = self.peek_next() {
+                self.backends[self.preferred_backend(&next_track)].preload(&next_track);
+            }
+            return Ok(());
+        }
+
+        Err(last_error.unwrap_or_else(|| "No audio backend available".into()))
     }
 
     pub fn stop(&self) {
-        self.backend.stop();
+        self.backend().stop();
         *self.current_track.write() = None;
     }
 
     pub fn pause(&self) {
-        self.backend.pause();
+        self.backend().pause();
     }
 
     pub fn resume(&self) {
-        self.backend.resume();
+        self.backend().resume();
     }
 
+    /// Advance the queue and return the track to play next. Starting
+    /// playback is left to the caller so it can handle failures.
     pub fn next(&self) -> Option<Track> {
-        let mut queue = self.queue.write();
-        if let Some(next_track) = queue.next() {
-            let _ = self.play(&next_track);
-            Some(next_track)
-        } else {
-            None
-        }
+        self.queue.write().next()
     }
 
+    /// Step the queue back and return the track to play. Past the restart
+    /// threshold, or at the start of a queue that doesn't repeat, the
+    /// current track is restarted instead and None returned.
     pub fn previous(&self) -> Option<Track> {
-        let mut queue = self.queue.write();
-        if let Some(prev_track) = queue.previous() {
-            let _ = self.play(&prev_track);
-            Some(prev_track)
-        } else {
-            None
+        let threshold = *self.restart_threshold.read();
+        let elapsed = self.get_position().unwrap_or_default();
+        let start = self.trim_start();
+        let restarts = elapsed.saturating_sub(start) > threshold;
+        if !threshold.is_zero() && restarts && self.get_current_track().is_some() {
+            self.set_position(start);
+            return None;
+        }
+
+        let previous = self.queue.write().previous();
+        if previous.is_none() && self.get_current_track().is_some() {
+            self.set_position(start);
         }
+        previous
+    }
+
+    /// How far into a track Previous restarts it. Zero always goes back.
+    pub fn set_restart_threshold(&self, threshold: Duration) {
+        *self.restart_threshold.write() = threshold;
     }
 
-    pub fn get_queue(&self) -> Vec<PlayableItem> {
+    /// Exclude a track that failed to play from further queue navigation
+    pub fn mark_unavailable(&self, track_id: &str) {
+        self.queue.write().mark_unavailable(track_id);
+        self.queue_changed();
+    }
+
+    /// Exclude every queued track from `provider`, e.g. once it has been
+    /// unregistered. Returns whether the current track is one of them.
+    pub fn drop_provider(&self, provider: &str) -> bool {
+        let track_ids = self.queue.write().mark_provider_unavailable(provider);
+        self.queue_changed();
+        self.get_current_track()
+            .is_some_and(|track| track_ids.contains(&track.id))
+    }
+
+    pub fn peek_next(&self) -> Option<Track> {
+        self.queue.read().peek_next().cloned()
+    }
+
+    /// Queue `tracks` as a block after the rest of the block playing now
+    pub fn queue_after_current_unit(&self, tracks: Vec<QueueEntry>) {
+        self.queue.write().add_after_current_unit(tracks);
+        self.queue_changed();
+    }
+
+    /// Add `tracks` to the end of the queue
+    pub fn enqueue_tracks(&self, tracks: Vec<QueueEntry>) {
+        self.queue.write().append(tracks);
+        self.queue_changed();
+    }
+
+    pub fn get_queue(&self) -> Vec<QueueEntry> {
         self.queue.read().get_tracks().to_vec()
     }
 
+    /// Up to `count` of the entries coming up after the current track
+    pub fn upcoming_entries(&self, count: usize) -> Vec<QueueEntry> {
+        self.queue.read().upcoming_entries(count)
+    }
+
+    /// The current track and everything after it
+    pub fn remaining_queue(&self) -> Vec<PlayableItem> {
+        self.queue.read().remaining()
+    }
+
+    /// Seconds of music queued after the current track
+    pub fn upcoming_duration(&self) -> u32 {
+        self.queue.read().upcoming_duration()
+    }
+
     pub fn is_playing(&self) -> bool {
-        self.backend.is_playing()
+        self.backend().is_playing()
     }
 
     pub fn get_position(&self) -> Option<Duration> {
-        self.backend.get_position()
+        self.backend().get_position()
     }
 
     pub fn set_position(&self, position: Duration) {
-        self.backend.set_position(position)
+        self.backend().set_position(position)
     }
 
     pub fn get_duration(&self) -> Option<Duration> {
-        self.backend.get_duration()
+        self.backend().get_duration()
     }
 
     pub fn get_current_track(&self) -> Option<Track> {
@@ -108,55 +527,499 @@ impl AudioPlayer {
     }
 
     pub fn set_volume(&self, volume: f64) {
-        self.backend.set_volume(volume);
+        for backend in &self.backends {
+            backend.set_volume(volume);
+        }
+    }
+
+    pub fn set_volume_curve(&self, curve: VolumeCurve) {
+        for backend in &self.backends {
+            backend.set_volume_curve(curve);
+        }
+    }
+
+    pub fn set_fade_duration(&self, duration: Duration) {
+        for backend in &self.backends {
+            backend.set_fade_duration(duration);
+        }
+    }
+
+    pub fn set_volume_limit(&self, limit: Option<f64>) {
+        for backend in &self.backends {
+            backend.set_volume_limit(limit);
+        }
+    }
+
+    pub fn gain_stages(&self) -> Option<GainStages> {
+        self.backend().gain_stages()
+    }
+
+    pub fn set_metering(&self, metering: bool) {
+        for backend in &self.backends {
+            backend.set_metering(metering);
+        }
+    }
+
+    pub fn set_normalization(&self, normalization: Option<ClippingPrevention>) {
+        for backend in &self.backends {
+            backend.set_normalization(normalization);
+        }
+    }
+
+    pub fn set_album_gain(&self, album_gain: bool) {
+        for backend in &self.backends {
+            backend.set_album_gain(album_gain);
+        }
+    }
+
+    pub fn set_crossfade(&self, crossfade: Option<Duration>) {
+        for backend in &self.backends {
+            backend.set_crossfade(crossfade);
+        }
+    }
+
+    pub fn set_output_buffer(&self, buffer: OutputBuffer) {
+        for backend in &self.backends {
+            backend.set_output_buffer(buffer);
+        }
+    }
+
+    pub fn set_resampler_quality(&self, quality: ResamplerQuality) {
+        for backend in &self.backends {
+            backend.set_resampler_quality(quality);
+        }
+    }
+
+    pub fn set_streaming_quality(&self, quality: StreamingQuality) {
+        for backend in &self.backends {
+            backend.set_streaming_quality(quality);
+        }
+    }
+
+    pub fn handover_delay(&self) -> Duration {
+        self.backend().handover_delay()
+    }
+
+    /// Play `track` again on a fresh pipeline from `position`, such as after
+    /// the output stopped taking audio
+    pub fn restart_at(
+        &self,
+        track: &Track,
+        position: Duration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.play_from(track, position)
+    }
+
+    pub fn set_rate(&self, rate: f64, audible: bool) {
+        self.backend().set_rate(rate, audible);
+    }
+}
+
+/// File format of a local track, which decides the backend to use
+fn track_format(track: &Track) -> Option<&str> {
+    match &track.source {
+        PlaybackSource::Local { file_format, .. } => Some(file_format),
+        _ => None,
     }
 }
 
 #[derive(Debug)]
 pub struct Queue {
-    tracks: Vec<PlayableItem>,
+    tracks: Vec<QueueEntry>,
     current_index: Option<usize>,
+    unavailable: HashSet<String>,
+    shuffle: Option<ShuffleMode>,
+    // Load order, kept while shuffled so turning shuffle off restores it
+    original_order: Option<Vec<QueueEntry>>,
+    skip_rates: HashMap<String, f32>,
+    // Block each track was queued in by track ID, such as an album or a
+    // playlist. Tracks loaded with the queue are all in block 0.
+    units: HashMap<String, u64>,
+    next_unit: u64,
+    // Left out of the shuffled order
+    exclusions: ShuffleExclusions,
+    // Whether Next goes round to the start after the end
+    repeat: bool,
 }
 
 impl Queue {
-    pub fn new(tracks: Vec<PlayableItem>) -> Self {
+    pub fn new(tracks: Vec<QueueEntry>) -> Self {
         Self {
             tracks,
             current_index: None,
+            unavailable: HashSet::new(),
+            shuffle: None,
+            original_order: None,
+            skip_rates: HashMap::new(),
+            units: HashMap::new(),
+            next_unit: 1,
+            exclusions: ShuffleExclusions::default(),
+            repeat: false,
         }
     }
 
-    pub fn next(&mut self) -> Option<Track> {
-        if self.tracks.is_empty() {
-            return None;
+    fn unit_at(&self, index: usize) -> u64 {
+        self.units
+            .get(&self.tracks[index].track.id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Add `items` as a new block right after the block the current track
+    /// is in, instead of right after the current track: an album queued
+    /// this way plays once the album playing now has finished. With nothing
+    /// playing they go at the end.
+    pub fn add_after_current_unit(&mut self, items: Vec<QueueEntry>) {
+        let unit = self.next_unit;
+        self.next_unit += 1;
+        for item in &items {
+            self.units.insert(item.track.id.clone(), unit);
+        }
+
+        let position = match self.current_index {
+            Some(current) => {
+                let current_unit = self.unit_at(current);
+                (current + 1..self.tracks.len())
+                    .find(|&index| self.unit_at(index) != current_unit)
+                    .unwrap_or(self.tracks.len())
+            }
+            None => self.tracks.len(),
+        };
+
+        // Turning shuffle off keeps the block, at the end of the load order
+        if let Some(original) = &mut self.original_order {
+            original.extend(items.iter().cloned());
+        }
+        self.tracks.splice(position..position, items);
+    }
+
+    /// Add `items` after everything else, in the load order too
+    pub fn append(&mut self, items: Vec<QueueEntry>) {
+        if let Some(original) = &mut self.original_order {
+            original.extend(items.iter().cloned());
+        }
+        self.tracks.extend(items);
+    }
+
+    fn shuffle_weight(&self, item: &QueueEntry) -> f64 {
+        let skip_rate = self.skip_rates.get(&item.track.id).copied().unwrap_or(0.0);
+        (1.0 - skip_rate as f64).max(MIN_SHUFFLE_WEIGHT)
+    }
+
+    pub fn shuffle_mode(&self) -> Option<ShuffleMode> {
+        self.shuffle
+    }
+
+    /// Reorder the queue for `mode`. The current track stays current and
+    /// everything else is shuffled after it.
+    pub fn set_shuffle(&mut self, mode: Option<ShuffleMode>) {
+        let current = self.current_index.map(|idx| self.tracks[idx].clone());
+        // Only the current entry is left out of the shuffle; other entries of
+        // the same track are shuffled like any other
+        let current_position = match &self.original_order {
+            None => self.current_index,
+            Some(original) => current.as_ref().and_then(|current| {
+                original
+                    .iter()
+                    .position(|item| item.track.id == current.track.id)
+            }),
+        };
+        let original = match self.original_order.take() {
+            Some(original) => original,
+            None => self.tracks.clone(),
+        };
+
+        match mode {
+            Some(mode) => {
+                let rest: Vec<QueueEntry> = original
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, _)| Some(*idx) != current_position)
+                    .map(|(_, item)| item.clone())
+                    .collect();
+                // Excluded tracks come back when shuffle is turned off
+                let rest = self.exclusions.filter(rest);
+                let weight = |item: &QueueEntry| self.shuffle_weight(item);
+                let rest = match mode {
+                    ShuffleMode::Random => weighted_shuffle(rest, weight),
+                    ShuffleMode::SmartSpacing => {
+                        spaced_shuffle(rest, current.as_ref().map(|item| &item.track), weight)
+                    }
+                    ShuffleMode::Albums => {
+                        album_shuffle(rest, current.as_ref().map(|item| &item.track), weight)
+                    }
+                };
+
+                self.tracks = current.iter().cloned().chain(rest).collect();
+                self.current_index = current.map(|_| 0);
+                self.original_order = Some(original);
+            }
+            None => {
+                self.current_index = current.and_then(|current| {
+                    original
+                        .iter()
+                        .position(|item| item.track.id == current.track.id)
+                });
+                self.tracks = original;
+            }
         }
+        self.shuffle = mode;
+    }
 
-        self.current_index = Some(match self.current_index {
-            Some(idx) if idx + 1 < self.tracks.len() => idx + 1,
-            _ => 0,
-        });
+    pub fn next(&mut self) -> Option<Track> {
+        let start = self.current_index;
+        // Walk at most one full lap so an all-unavailable queue terminates
+        for _ in 0..self.tracks.len() {
+            self.current_index = Some(match self.current_index {
+                Some(idx) if idx + 1 < self.tracks.len() => idx + 1,
+                // The end, where the queue stops unless it repeats
+                Some(_) if !self.repeat => {
+                    self.current_index = start;
+                    return None;
+                }
+                _ => 0,
+            });
 
-        self.current_track().cloned()
+            if self.current_is_available() {
+                return self.current_track().cloned();
+            }
+        }
+
+        None
     }
 
     pub fn previous(&mut self) -> Option<Track> {
-        if self.tracks.is_empty() {
-            return None;
+        let start = self.current_index;
+        for _ in 0..self.tracks.len() {
+            self.current_index = Some(match self.current_index {
+                Some(idx) if idx > 0 => idx - 1,
+                // The start, which only wraps around when the queue repeats
+                Some(_) if !self.repeat => {
+                    self.current_index = start;
+                    return None;
+                }
+                _ => self.tracks.len() - 1,
+            });
+
+            if self.current_is_available() {
+                return self.current_track().cloned();
+            }
         }
 
-        self.current_index = Some(match self.current_index {
-            Some(idx) if idx > 0 => idx - 1,
-            _ => self.tracks.len() - 1,
-        });
+        None
+    }
+
+    pub fn mark_unavailable(&mut self, track_id: &str) {
+        self.unavailable.insert(track_id.to_string());
+    }
 
-        self.current_track().cloned()
+    /// Mark every track from `provider` unavailable and return their IDs
+    pub fn mark_provider_unavailable(&mut self, provider: &str) -> HashSet<String> {
+        let track_ids: HashSet<String> = self
+            .tracks
+            .iter()
+            .filter(|item| item.provider == provider)
+            .map(|item| item.track.id.clone())
+            .collect();
+        self.unavailable.extend(track_ids.iter().cloned());
+        track_ids
+    }
+
+    fn current_is_available(&self) -> bool {
+        self.current_track()
+            .map_or(false, |track| !self.unavailable.contains(&track.id))
+    }
+
+    pub fn peek_next(&self) -> Option<&Track> {
+        self.after_current()
+            .map(|entry| &entry.track)
+            .find(|track| !self.unavailable.contains(&track.id))
+    }
+
+    /// Entries after the current one in the order Next reaches them: to the
+    /// end of the queue, then round from the start when it repeats
+    fn after_current(&self) -> impl Iterator<Item = &QueueEntry> + '_ {
+        let len = self.tracks.len();
+        let start = self.current_index.map_or(0, |idx| idx + 1);
+        let count = if self.repeat {
+            len
+        } else {
+            len.saturating_sub(start)
+        };
+        (0..count).map(move |offset| &self.tracks[(start + offset) % len])
     }
 
     pub fn current_track(&self) -> Option<&Track> {
         self.current_index.map(|idx| &self.tracks[idx].track)
     }
 
-    pub fn get_tracks(&self) -> &[PlayableItem] {
+    /// Up to `count` tracks in the order Next would reach them, leaving out
+    /// ones that failed to play
+    pub fn upcoming(&self, count: usize) -> Vec<Track> {
+        self.upcoming_entries(count)
+            .into_iter()
+            .map(|entry| entry.item.track)
+            .collect()
+    }
+
+    /// The same as upcoming, with why each track was queued
+    pub fn upcoming_entries(&self, count: usize) -> Vec<QueueEntry> {
+        self.after_current()
+            .filter(|entry| !self.unavailable.contains(&entry.track.id))
+            .filter(|entry| {
+                self.current_track()
+                    .map_or(true, |current| current.id != entry.track.id)
+            })
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    /// Tracks from the current one to the end, leaving out ones that failed
+    /// to play. Empty until playback has started.
+    pub fn remaining(&self) -> Vec<PlayableItem> {
+        let Some(start) = self.current_index else {
+            return Vec::new();
+        };
+        self.tracks[start..]
+            .iter()
+            .filter(|item| !self.unavailable.contains(&item.track.id))
+            .map(|entry| entry.item.clone())
+            .collect()
+    }
+
+    /// Total length in seconds of the tracks after the current one, or of
+    /// the whole queue before playback starts
+    pub fn upcoming_duration(&self) -> u32 {
+        let start = self.current_index.map_or(0, |idx| idx + 1);
+        self.tracks
+            .get(start..)
+            .unwrap_or_default()
+            .iter()
+            .filter(|item| !self.unavailable.contains(&item.track.id))
+            .map(|item| item.track.duration)
+            .sum()
+    }
+
+    pub fn get_tracks(&self) -> &[QueueEntry] {
         &self.tracks
     }
 }
+
+/// Random order where tracks with a higher `weight` tend to come first
+fn weighted_shuffle(
+    items: Vec<QueueEntry>,
+    weight: impl Fn(&QueueEntry) -> f64,
+) -> Vec<QueueEntry> {
+    // Sorting by u^(1/w) for uniform u draws items in proportion to weight
+    let mut rng = rand::thread_rng();
+    let mut keyed: Vec<(f64, QueueEntry)> = items
+        .into_iter()
+        .map(|item| (rng.gen::<f64>().powf(1.0 / weight(&item)), item))
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Shuffle so that, wherever possible, no track shares an artist or album
+/// with the one before it, starting after `previous`. Each pick is random
+/// among the tracks allowed next, weighted by how many tracks their artist
+/// still has, so a dominant artist is spread across the whole queue
+/// instead of bunching up at the end, and by the track's own `weight`.
+fn spaced_shuffle(
+    mut remaining: Vec<QueueEntry>,
+    previous: Option<&Track>,
+    weight: impl Fn(&QueueEntry) -> f64,
+) -> Vec<QueueEntry> {
+    let mut rng = rand::thread_rng();
+    remaining.shuffle(&mut rng);
+
+    let mut artist_counts: HashMap<String, usize> = HashMap::new();
+    for item in &remaining {
+        *artist_counts.entry(item.track.artist.clone()).or_default() += 1;
+    }
+
+    let mut result = Vec::with_capacity(remaining.len());
+    let mut last: Option<(String, String)> =
+        previous.map(|track| (track.artist.clone(), track.album.clone()));
+
+    while !remaining.is_empty() {
+        let allowed: Vec<usize> = (0..remaining.len())
+            .filter(|&idx| {
+                let track = &remaining[idx].track;
+                match &last {
+                    Some((artist, album)) => track.artist != *artist && track.album != *album,
+                    None => true,
+                }
+            })
+            .collect();
+
+        // Nothing fits, e.g. only one artist is left, so take any track
+        let pick = match WeightedIndex::new(
+            allowed
+                .iter()
+                .map(|&idx| {
+                    let item = &remaining[idx];
+                    artist_counts[&item.track.artist] as f64 * weight(item)
+                }),
+        ) {
+            Ok(weights) => allowed[weights.sample(&mut rng)],
+            Err(_) => 0,
+        };
+
+        let item = remaining.swap_remove(pick);
+        if let Some(count) = artist_counts.get_mut(&item.track.artist) {
+            *count -= 1;
+        }
+        last = Some((item.track.artist.clone(), item.track.album.clone()));
+        result.push(item);
+    }
+
+    result
+}
+
+/// Album a track is shuffled with. Compilations go by title alone, since
+/// their tracks each have their own artist.
+fn album_of(track: &Track) -> (String, String) {
+    let artist = match track.release_type {
+        Some(ReleaseType::Compilation) => String::new(),
+        _ => track.artist.to_lowercase(),
+    };
+    (artist, track.album.to_lowercase())
+}
+
+/// Shuffle whole albums, keeping each album's tracks in disc and track
+/// order. The rest of the album of `previous` plays first. Albums with a
+/// higher average `weight` tend to come earlier.
+fn album_shuffle(
+    items: Vec<QueueEntry>,
+    previous: Option<&Track>,
+    weight: impl Fn(&QueueEntry) -> f64,
+) -> Vec<QueueEntry> {
+    let mut albums: Vec<((String, String), Vec<QueueEntry>)> = Vec::new();
+    for item in items {
+        let key = album_of(&item.track);
+        match albums.iter_mut().find(|(album, _)| *album == key) {
+            Some((_, tracks)) => tracks.push(item),
+            None => albums.push((key, vec![item])),
+        }
+    }
+    let current_album = previous.map(album_of);
+
+    let mut rng = rand::thread_rng();
+    let mut keyed: Vec<(f64, Vec<QueueEntry>)> = albums
+        .into_iter()
+        .map(|(album, mut tracks)| {
+            tracks.sort_by_key(|item| (item.track.disc_number, item.track.track_number));
+            let key = if current_album.as_ref() == Some(&album) {
+                f64::INFINITY
+            } else {
+                let mean = tracks.iter().map(&weight).sum::<f64>() / tracks.len() as f64;
+                rng.gen::<f64>().powf(1.0 / mean)
+            };
+            (key, tracks)
+        })
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().flat_map(|(_, tracks)| tracks).collect()
+}
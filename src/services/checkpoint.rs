@@ -0,0 +1,58 @@
+//! Playback state written to disk every few seconds and removed on a clean
+//! exit, so one still there at launch means Nova didn't close properly. The
+//! queue and position are all there is to save: playlist edits, plays and
+//! ratings go to the user store as they happen.
+
+use crate::platform;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub saved_at: DateTime<Utc>,
+    /// The current track and everything queued after it, by track ID
+    pub queue: Vec<String>,
+    /// Seconds into the current track
+    pub position: u64,
+}
+
+fn checkpoint_path() -> PathBuf {
+    platform::state_dir().join("checkpoint.json")
+}
+
+/// Replace the checkpoint with `checkpoint`. It's written to another file
+/// and renamed over the old one, so a crash mid-write leaves the last one.
+pub fn save(checkpoint: &Checkpoint) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let path = checkpoint_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("json.part");
+    fs::write(&partial, serde_json::to_vec(checkpoint)?)?;
+    fs::rename(&partial, &path)?;
+    Ok(())
+}
+
+/// The checkpoint left by a session that didn't exit cleanly, if any
+pub fn load() -> Option<Checkpoint> {
+    let data = fs::read(checkpoint_path()).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(checkpoint) => Some(checkpoint),
+        Err(e) => {
+            eprintln!("Ignoring unreadable checkpoint: {}", e);
+            None
+        }
+    }
+}
+
+/// Remove the checkpoint, on a clean exit or when there's nothing to save
+pub fn clear() {
+    if let Err(e) = fs::remove_file(checkpoint_path()) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            eprintln!("Failed to remove checkpoint: {}", e);
+        }
+    }
+}
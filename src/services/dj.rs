@@ -0,0 +1,75 @@
+//! DJ mode: transitions that crossfade on the beat, nudging the incoming
+//! track's tempo to the outgoing one's, and picking what plays next by
+//! tempo and key when the queue runs out.
+
+use crate::services::models::{PlayableItem, TrackTempo};
+use rand::seq::SliceRandom;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Furthest a track's tempo is sped up or slowed down to match, as a share
+/// of its own. More than this starts to be heard.
+pub const MAX_TEMPO_CHANGE: f64 = 0.04;
+
+// Beats a crossfade lasts, four bars of four
+const CROSSFADE_BEATS: f64 = 16.0;
+
+const MIN_CROSSFADE: Duration = Duration::from_secs(4);
+const MAX_CROSSFADE: Duration = Duration::from_secs(12);
+
+/// Rate to play a track of `bpm` at to match `target` beats per minute,
+/// counting half and double time as a match, or None when that takes more
+/// than MAX_TEMPO_CHANGE
+pub fn tempo_rate(target: f32, bpm: f32) -> Option<f64> {
+    if target <= 0.0 || bpm <= 0.0 {
+        return None;
+    }
+    [1.0, 2.0, 0.5]
+        .into_iter()
+        .map(|factor| f64::from(target) / (f64::from(bpm) * factor))
+        .filter(|rate| (rate - 1.0).abs() <= MAX_TEMPO_CHANGE)
+        .min_by(|a, b| (a - 1.0).abs().total_cmp(&(b - 1.0).abs()))
+}
+
+/// Crossfade lasting CROSSFADE_BEATS at `bpm`, kept within reason for very
+/// slow or fast tracks
+pub fn beat_crossfade(bpm: f32) -> Duration {
+    let beat = 60.0 / f64::from(bpm.max(1.0));
+    Duration::from_secs_f64(beat * CROSSFADE_BEATS).clamp(MIN_CROSSFADE, MAX_CROSSFADE)
+}
+
+/// A track from `candidates` to follow one by `artist` in `tempo`'s key,
+/// playing at `bpm` after any tempo change. Tracks that can be beat-matched
+/// come first, then those in a compatible key, then those by someone else.
+/// Tracks in `recent` and ones never analyzed are left out.
+pub fn pick_next(
+    bpm: Option<f32>,
+    tempo: &TrackTempo,
+    artist: &str,
+    candidates: &[PlayableItem],
+    tempos: &HashMap<String, TrackTempo>,
+    recent: &HashSet<String>,
+) -> Option<PlayableItem> {
+    let mut scored: Vec<(u32, &PlayableItem)> = candidates
+        .iter()
+        .filter(|item| !recent.contains(&item.track.id))
+        .filter_map(|item| {
+            let candidate = tempos.get(&item.track.id)?;
+            let matched = bpm
+                .zip(candidate.bpm)
+                .is_some_and(|(bpm, other)| tempo_rate(bpm, other).is_some());
+            let in_key = tempo
+                .key
+                .zip(candidate.key)
+                .is_some_and(|(key, other)| key.is_compatible(&other));
+            let other_artist = !item.track.artist.eq_ignore_ascii_case(artist);
+            let score = u32::from(matched) * 4 + u32::from(in_key) * 2 + u32::from(other_artist);
+            Some((score, item))
+        })
+        .collect();
+
+    // Shuffled first so the same track doesn't always lead to the same one
+    scored.shuffle(&mut rand::thread_rng());
+    scored.sort_by(|a, b| b.0.cmp(&a.0));
+    scored.first().map(|(_, item)| (*item).clone())
+}
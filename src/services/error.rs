@@ -7,6 +7,7 @@ pub enum ServiceError {
     NotFound(String),
     AuthenticationError(String),
     NetworkError(String),
+    AlreadyRegistered(String),
 }
 
 impl fmt::Display for ServiceError {
@@ -16,6 +17,9 @@ impl fmt::Display for ServiceError {
             ServiceError::NotFound(msg) => write!(f, "Not found: {}", msg),
             ServiceError::AuthenticationError(msg) => write!(f, "Authentication error: {}", msg),
             ServiceError::NetworkError(msg) => write!(f, "Network error: {}", msg),
+            ServiceError::AlreadyRegistered(name) => {
+                write!(f, "Provider {} is already registered", name)
+            }
         }
     }
 }
@@ -0,0 +1,154 @@
+//! Export of the whole library to CSV or JSON, for analysis in other tools
+//! or moving to another player. Nova's own data (play counts, ratings) is
+//! included alongside the tags.
+
+use crate::services::models::{PlayableItem, PlaybackSource};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// Format for a file name, going by its extension. Anything that isn't
+    /// .json is written as CSV.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Csv,
+        }
+    }
+}
+
+/// One row of the export
+#[derive(Debug, Serialize)]
+struct ExportedTrack<'a> {
+    id: &'a str,
+    title: &'a str,
+    artist: &'a str,
+    album: &'a str,
+    track_number: Option<u32>,
+    disc_number: Option<u32>,
+    year: Option<u32>,
+    genre: Option<&'a str>,
+    /// Length in seconds
+    duration: u32,
+    explicit: bool,
+    provider: &'a str,
+    format: Option<&'a str>,
+    path: Option<String>,
+    play_count: u32,
+    rating: Option<u8>,
+}
+
+// Column order of the CSV, matching ExportedTrack
+const CSV_HEADER: &[&str] = &[
+    "id",
+    "title",
+    "artist",
+    "album",
+    "track_number",
+    "disc_number",
+    "year",
+    "genre",
+    "duration",
+    "explicit",
+    "provider",
+    "format",
+    "path",
+    "play_count",
+    "rating",
+];
+
+/// Write `tracks` to `path` with their play counts and ratings, returning
+/// how many were written
+pub fn export_library(
+    tracks: &[PlayableItem],
+    play_counts: &HashMap<String, u32>,
+    ratings: &HashMap<String, u8>,
+    path: &Path,
+    format: ExportFormat,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let rows: Vec<ExportedTrack> = tracks
+        .iter()
+        .map(|item| {
+            let track = &item.track;
+            let (format, path) = match &track.source {
+                PlaybackSource::Local {
+                    path, file_format, ..
+                } => (
+                    Some(file_format.as_str()),
+                    Some(path.to_string_lossy().into_owned()),
+                ),
+                _ => (None, None),
+            };
+            ExportedTrack {
+                id: &track.id,
+                title: &track.title,
+                artist: &track.artist,
+                album: &track.album,
+                track_number: track.track_number,
+                disc_number: track.disc_number,
+                year: track.release_year,
+                genre: track.genre.as_deref(),
+                duration: track.duration,
+                explicit: track.explicit,
+                provider: &item.provider,
+                format,
+                path,
+                play_count: play_counts.get(&track.id).copied().unwrap_or(0),
+                rating: ratings.get(&track.id).copied(),
+            }
+        })
+        .collect();
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Json => serde_json::to_writer_pretty(&mut writer, &rows)?,
+        ExportFormat::Csv => write_csv(&mut writer, &rows)?,
+    }
+    writer.flush()?;
+    Ok(rows.len())
+}
+
+fn write_csv(writer: &mut impl Write, rows: &[ExportedTrack]) -> std::io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER.join(","))?;
+    for row in rows {
+        let optional = |value: Option<u32>| value.map(|v| v.to_string()).unwrap_or_default();
+        let fields = [
+            csv_field(row.id),
+            csv_field(row.title),
+            csv_field(row.artist),
+            csv_field(row.album),
+            optional(row.track_number),
+            optional(row.disc_number),
+            optional(row.year),
+            csv_field(row.genre.unwrap_or_default()),
+            row.duration.to_string(),
+            row.explicit.to_string(),
+            csv_field(row.provider),
+            csv_field(row.format.unwrap_or_default()),
+            csv_field(row.path.as_deref().unwrap_or_default()),
+            row.play_count.to_string(),
+            optional(row.rating.map(u32::from)),
+        ];
+        writeln!(writer, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// Quote a field when it holds a separator, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
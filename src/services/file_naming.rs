@@ -0,0 +1,290 @@
+//! Where a file goes when it's moved into the library, from a template such
+//! as "{artist}/{album}/{track:02} {title}". Each "/" starts a folder and
+//! the file keeps its own extension.
+
+use crate::services::models::{PlaybackSource, Track};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Artist,
+    Album,
+    Title,
+    Track,
+    Disc,
+    Year,
+    Genre,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "artist" => Some(Field::Artist),
+            "album" => Some(Field::Album),
+            "title" => Some(Field::Title),
+            "track" => Some(Field::Track),
+            "disc" => Some(Field::Disc),
+            "year" => Some(Field::Year),
+            "genre" => Some(Field::Genre),
+            _ => None,
+        }
+    }
+
+    fn value(self, track: &Track) -> String {
+        let number = |n: Option<u32>| n.map(|n| n.to_string()).unwrap_or_default();
+        match self {
+            Field::Artist => track.artist.clone(),
+            Field::Album => track.album.clone(),
+            Field::Title => track.title.clone(),
+            Field::Track => number(track.track_number),
+            Field::Disc => number(track.disc_number),
+            Field::Year => number(track.release_year),
+            Field::Genre => track.genre.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// A field, zero-padded to the width when it's a number
+    Field(Field, usize),
+    Separator,
+}
+
+/// A parsed file naming template
+#[derive(Debug, Clone)]
+pub struct NamingTemplate {
+    parts: Vec<Part>,
+}
+
+impl NamingTemplate {
+    /// Parse `template`, failing on unknown fields and unclosed braces
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '/' | '\\' => {
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Separator);
+                }
+                '{' => {
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => return Err(format!("Unclosed '{{' in \"{}\"", template)),
+                        }
+                    }
+                    parts.push(Self::parse_field(&spec)?);
+                }
+                '}' => return Err(format!("Unopened '}}' in \"{}\"", template)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        if !parts.iter().any(|part| matches!(part, Part::Field(..))) {
+            return Err("The template needs at least one field, such as {title}".to_string());
+        }
+        if parts.last() == Some(&Part::Separator) {
+            return Err("The template can't end in a folder".to_string());
+        }
+        Ok(Self { parts })
+    }
+
+    fn parse_field(spec: &str) -> Result<Part, String> {
+        let (name, width) = match spec.split_once(':') {
+            Some((name, width)) => {
+                let width = width
+                    .parse()
+                    .map_err(|_| format!("\"{}\" isn't a width in {{{}}}", width, spec))?;
+                (name, width)
+            }
+            None => (spec, 0),
+        };
+        let field = Field::parse(name.trim()).ok_or_else(|| {
+            format!(
+                "Unknown field {{{}}}. Use artist, album, title, track, disc, year or genre.",
+                name
+            )
+        })?;
+        Ok(Part::Field(field, width))
+    }
+
+    /// Path of `track`'s file under a library folder, without its extension
+    pub fn render(&self, track: &Track) -> PathBuf {
+        let mut path = PathBuf::new();
+        let mut component = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => component.push_str(text),
+                Part::Field(field, width) => {
+                    let value = field.value(track);
+                    // Slashes in a tag would otherwise start a folder
+                    let value = sanitize(&value);
+                    if value.chars().all(|c| c.is_ascii_digit()) && !value.is_empty() {
+                        component.push_str(&format!("{:0>width$}", value, width = width));
+                    } else {
+                        component.push_str(&value);
+                    }
+                }
+                Part::Separator => path.push(finish_component(&std::mem::take(&mut component))),
+            }
+        }
+        path.push(finish_component(&component));
+        path
+    }
+}
+
+// Characters no filesystem Nova runs on allows in a name
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect()
+}
+
+// Empty fields leave stray spaces, and Windows drops trailing dots
+fn finish_component(component: &str) -> String {
+    let trimmed = component.trim().trim_end_matches('.').trim();
+    match trimmed {
+        "" | "." | ".." => "Unknown".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Where `template` puts the file at `source`, tagged as `track`, under
+/// `library`
+fn destination(source: &Path, track: &Track, library: &Path, template: &NamingTemplate) -> PathBuf {
+    let mut dest = library.join(template.render(track));
+    if let Some(extension) = source.extension() {
+        let mut name = dest
+            .file_name()
+            .map(|n| n.to_os_string())
+            .unwrap_or_default();
+        name.push(".");
+        name.push(extension.to_ascii_lowercase());
+        dest.set_file_name(name);
+    }
+    dest
+}
+
+/// Move the file at `source` to `dest`, making its folders. Fails rather
+/// than replace a file already there.
+pub fn move_file(source: &Path, dest: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if dest.exists() && !same_file(source, dest) {
+        return Err(format!("{} is already there", dest.display()).into());
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Renaming only works within one filesystem, and Downloads is often on
+    // another
+    if let Err(e) = fs::rename(source, dest) {
+        if e.kind() == io::ErrorKind::NotFound {
+            return Err(e.into());
+        }
+        // Copied under a hidden name and renamed into place, so the library
+        // never sees half a file
+        let name = dest.file_name().ok_or("Destination has no file name")?;
+        let partial = dest.with_file_name(format!(".{}.part", name.to_string_lossy()));
+        if let Err(e) = fs::copy(source, &partial).and_then(|_| fs::rename(&partial, dest)) {
+            let _ = fs::remove_file(&partial);
+            return Err(e.into());
+        }
+        if let Err(e) = fs::remove_file(source) {
+            eprintln!("Copied {:?} but couldn't remove it: {}", source, e);
+        }
+    }
+    Ok(())
+}
+
+/// Move the file at `source`, tagged as `track`, to where `template` puts
+/// it under `library`. Returns where it went.
+pub fn move_into_library(
+    source: &Path,
+    track: &Track,
+    library: &Path,
+    template: &NamingTemplate,
+) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+    let dest = destination(source, track, library, template);
+    move_file(source, &dest)?;
+    Ok(dest)
+}
+
+/// A library file and where the template puts it
+#[derive(Debug, Clone)]
+pub struct PlannedMove {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// What organizing the library would do, worked out without touching it
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub moves: Vec<PlannedMove>,
+    /// Files left where they are because another file has their name
+    pub conflicts: Vec<PathBuf>,
+}
+
+/// Where `template` puts each local file of `tracks`, staying in the
+/// library folder of `roots` it's in. Files already in place are left out.
+pub fn plan_moves(tracks: &[Track], roots: &[PathBuf], template: &NamingTemplate) -> Plan {
+    let mut plan = Plan::default();
+    let mut seen = HashSet::new();
+    let mut taken = HashSet::new();
+    for track in tracks {
+        let PlaybackSource::Local { path, .. } = &track.source else {
+            continue;
+        };
+        // Chapters of one file share it, and it's named after the first
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        let Some(root) = roots.iter().find(|root| path.starts_with(root)) else {
+            continue;
+        };
+        let to = destination(path, track, root, template);
+        if &to == path {
+            continue;
+        }
+        if (to.exists() && !same_file(path, &to)) || !taken.insert(to.clone()) {
+            plan.conflicts.push(path.clone());
+            continue;
+        }
+        plan.moves.push(PlannedMove {
+            from: path.clone(),
+            to,
+        });
+    }
+    plan
+}
+
+// Case-insensitive filesystems report a file renamed only in case as
+// already existing
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
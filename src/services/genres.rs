@@ -0,0 +1,207 @@
+//! Genre normalization. Genre tags are messy ("Alt Rock", "alternative
+//! rock", "Rock/Alt"), so tags are grouped into canonical genres, some of
+//! which sit under a parent (Rock ▸ Alternative). Files keep their tags;
+//! genres are normalized whenever tracks are handed out, so changing the
+//! mappings applies straight away without a rescan.
+
+use crate::services::models::Track;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+/// Separator between a genre and its parent, in mappings and for display
+pub const HIERARCHY_SEPARATOR: &str = " ▸ ";
+
+// Canonical genres with their parent and the tags that mean them. Tags are
+// matched by their words in any order, so "Rock/Alt" finds "alt rock".
+const BUILT_IN_GENRES: &[(&str, Option<&str>, &[&str])] = &[
+    ("Rock", None, &["rock", "rock and roll", "rock n roll"]),
+    ("Alternative", Some("Rock"), &["alternative", "alternative rock", "alt"]),
+    ("Indie", Some("Rock"), &["indie", "indie rock"]),
+    ("Punk", Some("Rock"), &["punk", "punk rock"]),
+    ("Progressive Rock", Some("Rock"), &["progressive rock", "progressive"]),
+    ("Hard Rock", Some("Rock"), &["hard rock"]),
+    ("Metal", None, &["metal", "heavy metal"]),
+    ("Pop", None, &["pop", "pop music"]),
+    ("Synth-Pop", Some("Pop"), &["synth pop", "synthpop"]),
+    ("Indie Pop", Some("Pop"), &["indie pop"]),
+    ("Electronic", None, &["electronic", "electronica", "electro", "edm"]),
+    ("House", Some("Electronic"), &["house", "deep house"]),
+    ("Techno", Some("Electronic"), &["techno"]),
+    ("Ambient", Some("Electronic"), &["ambient"]),
+    ("Drum & Bass", Some("Electronic"), &["drum and bass", "dnb"]),
+    ("Hip-Hop", None, &["hip hop", "hiphop", "rap", "hip hop rap"]),
+    ("R&B", None, &["r and b", "rnb", "rhythm and blues"]),
+    ("Soul", Some("R&B"), &["soul"]),
+    ("Jazz", None, &["jazz"]),
+    ("Blues", None, &["blues"]),
+    ("Classical", None, &["classical", "classic", "orchestral"]),
+    ("Folk", None, &["folk"]),
+    ("Country", None, &["country"]),
+    ("Reggae", None, &["reggae"]),
+    ("Soundtrack", None, &["soundtrack", "score", "film score", "ost"]),
+];
+
+// Abbreviations expanded before matching
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("alt", "alternative"),
+    ("prog", "progressive"),
+    ("trad", "traditional"),
+    ("n", "and"),
+];
+
+// None while normalization is turned off
+static GENRES: RwLock<Option<GenreMap>> = RwLock::new(None);
+
+/// Tags grouped into canonical genres, with each genre's parent
+#[derive(Debug, Clone, Default)]
+pub struct GenreMap {
+    // Matching key of a tag -> canonical genre
+    aliases: HashMap<String, String>,
+    // Canonical genre -> its parent
+    parents: HashMap<String, String>,
+}
+
+impl GenreMap {
+    /// The built-in genres with the user's `mappings` on top. Each mapping
+    /// reads "tag = Genre" or "tag = Parent ▸ Genre"; a plain ">" works as
+    /// the separator too.
+    pub fn new(mappings: &[String]) -> Self {
+        let mut map = Self::default();
+        for (genre, parent, aliases) in BUILT_IN_GENRES {
+            map.aliases.insert(matching_key(genre), genre.to_string());
+            for alias in *aliases {
+                map.aliases.insert(matching_key(alias), genre.to_string());
+            }
+            if let Some(parent) = parent {
+                map.parents.insert(genre.to_string(), parent.to_string());
+            }
+        }
+
+        for mapping in mappings {
+            let Some((tag, levels)) = parse_mapping(mapping) else {
+                eprintln!("Ignoring genre mapping {:?}", mapping);
+                continue;
+            };
+            for level in &levels {
+                map.aliases.insert(matching_key(level), level.to_string());
+            }
+            for pair in levels.windows(2) {
+                map.parents.insert(pair[1].to_string(), pair[0].to_string());
+            }
+            if let Some(genre) = levels.last() {
+                map.aliases.insert(matching_key(tag), genre.to_string());
+            }
+        }
+        map
+    }
+
+    /// Canonical genre for a raw tag. Tags that aren't known are tidied up
+    /// so different spellings of them still end up together.
+    pub fn normalize(&self, tag: &str) -> Option<String> {
+        let key = matching_key(tag);
+        if key.is_empty() {
+            return None;
+        }
+        Some(
+            self.aliases
+                .get(&key)
+                .cloned()
+                .unwrap_or_else(|| title_case(tag)),
+        )
+    }
+
+    /// `genre` with its ancestors, outermost first, e.g. "Rock ▸ Alternative"
+    pub fn path(&self, genre: &str) -> String {
+        let mut path = vec![genre];
+        let mut current = genre;
+        while let Some(parent) = self.parents.get(current) {
+            // A user mapping could make a loop
+            if path.contains(&parent.as_str()) {
+                break;
+            }
+            path.push(parent);
+            current = parent;
+        }
+        path.reverse();
+        path.join(HIERARCHY_SEPARATOR)
+    }
+}
+
+/// Normalize genres with the built-in genres and `mappings` from now on,
+/// or leave tags as they are when `mappings` is None
+pub fn set_genre_mappings(mappings: Option<&[String]>) {
+    *GENRES.write() = mappings.map(GenreMap::new);
+}
+
+/// Give each track its canonical genre
+pub fn normalize_genres<'a>(tracks: impl IntoIterator<Item = &'a mut Track>) {
+    let genres = GENRES.read();
+    let Some(genres) = genres.as_ref() else {
+        return;
+    };
+    for track in tracks {
+        track.genre = track.genre.as_deref().and_then(|tag| genres.normalize(tag));
+    }
+}
+
+/// A genre with its ancestors, for display
+pub fn genre_path(genre: &str) -> String {
+    match GENRES.read().as_ref() {
+        Some(genres) => genres.path(genre),
+        None => genre.to_string(),
+    }
+}
+
+/// Split "tag = Parent ▸ Genre" into its tag and the genres from the
+/// outermost in
+fn parse_mapping(mapping: &str) -> Option<(&str, Vec<&str>)> {
+    let (tag, target) = mapping.split_once('=')?;
+    let tag = tag.trim();
+    let levels: Vec<&str> = target
+        .split(['▸', '>'])
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .collect();
+    (!tag.is_empty() && !levels.is_empty()).then_some((tag, levels))
+}
+
+/// Lowercase words of a tag with punctuation dropped, abbreviations
+/// expanded and the words sorted, so word order and separators don't matter
+fn matching_key(tag: &str) -> String {
+    let spaced: String = tag
+        .to_lowercase()
+        .replace('&', " and ")
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let mut words: Vec<&str> = spaced
+        .split_whitespace()
+        .map(|word| {
+            ABBREVIATIONS
+                .iter()
+                .find(|(short, _)| *short == word)
+                .map_or(word, |(_, long)| *long)
+        })
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+    words.join(" ")
+}
+
+/// "post-rock", "Post Rock" and "POST ROCK" all become "Post Rock"
+fn title_case(tag: &str) -> String {
+    tag.split(|c: char| !c.is_alphanumeric() && c != '&')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
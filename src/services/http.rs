@@ -0,0 +1,300 @@
+//! HTTP handling shared by everything that goes online, so each provider
+//! and fetcher gets the same resilience without its own copy of it:
+//!
+//! - failed requests are retried with exponential backoff, honouring the
+//!   Retry-After of a 429 or 503 and holding back every request to that host
+//!   meanwhile
+//! - hosts that ask for it get their requests spaced out, e.g. MusicBrainz
+//!   allows one a second
+//! - identical GETs in flight at the same time share one response, and small
+//!   successful ones are kept for a few minutes, or on disk for metadata
+//!   services (see response_cache), which also answer when offline
+
+use crate::services::response_cache;
+use parking_lot::Mutex;
+use rand::Rng;
+use reqwest::header::{AUTHORIZATION, RETRY_AFTER};
+use reqwest::{IntoUrl, Method, Request, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// A server asking for a longer wait than this gets its answer passed on
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+// Least time between requests to hosts that publish a rate limit
+const HOST_INTERVALS: &[(&str, Duration)] = &[("musicbrainz.org", Duration::from_secs(1))];
+
+const CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+const MAX_CACHE_ENTRIES: usize = 256;
+const MAX_CACHED_BYTES: usize = 256 * 1024;
+
+type InFlight = Arc<OnceCell<Result<Fetched, String>>>;
+
+/// State every client shares, so limits hold across providers
+#[derive(Default)]
+struct Shared {
+    // Earliest each host may be sent the next request
+    next_slot: Mutex<HashMap<String, Instant>>,
+    cache: Mutex<HashMap<String, (Instant, Fetched)>>,
+    in_flight: Mutex<HashMap<String, InFlight>>,
+}
+
+fn shared() -> &'static Shared {
+    static SHARED: OnceLock<Shared> = OnceLock::new();
+    SHARED.get_or_init(Shared::default)
+}
+
+/// A response read into memory
+#[derive(Debug, Clone)]
+pub struct Fetched {
+    pub status: StatusCode,
+    pub body: Vec<u8>,
+}
+
+impl Fetched {
+    fn from_disk(body: Vec<u8>) -> Self {
+        Self {
+            status: StatusCode::OK,
+            body,
+        }
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// A reqwest client that sends through the shared retry, rate limiting
+/// and caching
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+}
+
+impl HttpClient {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    /// Send `request`, retrying as needed. The body is left to be read, so
+    /// this suits downloads; nothing is shared or cached.
+    pub async fn send(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<Response, Box<dyn Error + Send + Sync>> {
+        self.execute(request.build()?).await
+    }
+
+    /// Send `request` and read the whole response. A GET without
+    /// credentials shares the response of an identical one in flight, and
+    /// is answered from the cache while a recent success is there.
+    pub async fn fetch(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<Fetched, Box<dyn Error + Send + Sync>> {
+        let request = request.build()?;
+        if request.method() != Method::GET || request.headers().contains_key(AUTHORIZATION) {
+            return read(self.execute(request).await?).await;
+        }
+
+        let key = request.url().to_string();
+        let disk_ttl = request.url().host_str().and_then(response_cache::ttl_for);
+        if let Some(fetched) = cached(&key) {
+            return Ok(fetched);
+        }
+        if let Some(ttl) = disk_ttl {
+            if let Some(body) = response_cache::load(&key, Some(ttl)).await {
+                return Ok(Fetched::from_disk(body));
+            }
+        }
+        let cell = shared()
+            .in_flight
+            .lock()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let result = cell
+            .get_or_init(|| async {
+                let result = match self.execute(request).await {
+                    Ok(response) => read(response).await,
+                    Err(e) => Err(e),
+                };
+                if let Ok(fetched) = &result {
+                    store(&key, fetched);
+                    if disk_ttl.is_some() && fetched.status.is_success() {
+                        response_cache::store(&key, fetched.body.clone());
+                    }
+                }
+                result.map_err(|e| e.to_string())
+            })
+            .await
+            .clone();
+
+        // Later requests go to the cache, or out again if it failed
+        let mut in_flight = shared().in_flight.lock();
+        if in_flight
+            .get(&key)
+            .is_some_and(|current| Arc::ptr_eq(current, &cell))
+        {
+            in_flight.remove(&key);
+        }
+        drop(in_flight);
+
+        // Offline, an old answer is better than none
+        if result.is_err() && disk_ttl.is_some() {
+            if let Some(body) = response_cache::load(&key, None).await {
+                return Ok(Fetched::from_disk(body));
+            }
+        }
+        result.map_err(Into::into)
+    }
+
+    async fn execute(&self, request: Request) -> Result<Response, Box<dyn Error + Send + Sync>> {
+        let host = request.url().host_str().unwrap_or_default().to_string();
+        // PROPFIND only reads, like GET
+        let retryable = request.method().is_idempotent() || request.method().as_str() == "PROPFIND";
+
+        let mut attempt = 1;
+        loop {
+            wait_for_turn(&host).await;
+            // A streamed body can't be sent twice
+            let Some(attempt_request) = request.try_clone().filter(|_| retryable) else {
+                return Ok(self.client.execute(request).await?);
+            };
+
+            let result = self.client.execute(attempt_request).await;
+            let delay = match &result {
+                Ok(response) => retry_delay(response, attempt),
+                Err(e) if e.is_timeout() || e.is_connect() => Some(backoff(attempt)),
+                Err(_) => None,
+            };
+            match delay {
+                Some(delay) if attempt < MAX_ATTEMPTS => {
+                    eprintln!("Retrying request to {} in {:?}", host, delay);
+                    hold_host(&host, delay);
+                    attempt += 1;
+                }
+                _ => return Ok(result?),
+            }
+        }
+    }
+}
+
+async fn read(response: Response) -> Result<Fetched, Box<dyn Error + Send + Sync>> {
+    let status = response.status();
+    let body = response.bytes().await?.to_vec();
+    Ok(Fetched { status, body })
+}
+
+/// How long to wait before trying `response`'s request again, or None when
+/// it shouldn't be retried
+fn retry_delay(response: &Response, attempt: u32) -> Option<Duration> {
+    let status = response.status();
+    if matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        // Only the seconds form is used by the services Nova talks to
+        let asked = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        return match asked {
+            Some(asked) if asked > MAX_RETRY_AFTER => None,
+            Some(asked) => Some(asked),
+            None => Some(backoff(attempt)),
+        };
+    }
+    status.is_server_error().then(|| backoff(attempt))
+}
+
+/// Exponential backoff with up to half as much again at random, so clients
+/// that failed together don't retry together
+fn backoff(attempt: u32) -> Duration {
+    let base = BASE_BACKOFF
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF);
+    let jitter = rand::thread_rng().gen_range(0..=base.as_millis() as u64 / 2);
+    base + Duration::from_millis(jitter)
+}
+
+fn min_interval(host: &str) -> Duration {
+    HOST_INTERVALS
+        .iter()
+        .find(|(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))
+        .map_or(Duration::ZERO, |(_, interval)| *interval)
+}
+
+/// Wait until `host` may be sent another request, and book the slot
+async fn wait_for_turn(host: &str) {
+    let wait = {
+        let mut slots = shared().next_slot.lock();
+        let now = Instant::now();
+        let slot = slots
+            .get(host)
+            .copied()
+            .filter(|slot| *slot > now)
+            .unwrap_or(now);
+        slots.insert(host.to_string(), slot + min_interval(host));
+        slot - now
+    };
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Keep every request to `host` back for `delay`
+fn hold_host(host: &str, delay: Duration) {
+    let until = Instant::now() + delay;
+    let mut slots = shared().next_slot.lock();
+    let slot = slots.entry(host.to_string()).or_insert(until);
+    *slot = (*slot).max(until);
+}
+
+/// Forget every kept response, in memory and on disk, returning the bytes
+/// freed on disk
+pub async fn clear_cache() -> std::io::Result<u64> {
+    shared().cache.lock().clear();
+    tokio::task::spawn_blocking(response_cache::clear)
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+fn cached(key: &str) -> Option<Fetched> {
+    let cache = shared().cache.lock();
+    let (stored, fetched) = cache.get(key)?;
+    (stored.elapsed() < CACHE_TTL).then(|| fetched.clone())
+}
+
+fn store(key: &str, fetched: &Fetched) {
+    if !fetched.status.is_success() || fetched.body.len() > MAX_CACHED_BYTES {
+        return;
+    }
+    let mut cache = shared().cache.lock();
+    cache.retain(|_, (stored, _)| stored.elapsed() < CACHE_TTL);
+    if cache.len() < MAX_CACHE_ENTRIES {
+        cache.insert(key.to_string(), (Instant::now(), fetched.clone()));
+    }
+}
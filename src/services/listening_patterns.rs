@@ -0,0 +1,162 @@
+//! Listening patterns by time of day and day of the week, learned from the
+//! play history. Home uses them to bring forward what usually gets played
+//! around now, so morning playlists come first in the morning.
+
+use crate::services::models::HistoryEntry;
+use chrono::{DateTime, Datelike, Local, Timelike, Utc, Weekday};
+use std::collections::HashMap;
+
+// Plays every item is assumed to have made at the usual rate, so an item
+// played a couple of times doesn't jump to the top
+const PRIOR_PLAYS: f32 = 5.0;
+
+// How much a play at the same time on the other kind of day (weekday or
+// weekend) counts
+const OTHER_DAY_WEIGHT: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DayPart {
+    Morning,
+    Afternoon,
+    Evening,
+    Night,
+}
+
+impl DayPart {
+    fn from_hour(hour: u32) -> Self {
+        match hour {
+            5..=11 => Self::Morning,
+            12..=16 => Self::Afternoon,
+            17..=21 => Self::Evening,
+            _ => Self::Night,
+        }
+    }
+}
+
+/// When music is played, as far as listening habits go: the part of the
+/// day, on a weekday or at the weekend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListeningTime {
+    pub day_part: DayPart,
+    pub weekend: bool,
+}
+
+impl ListeningTime {
+    pub fn now() -> Self {
+        Self::of(Utc::now())
+    }
+
+    /// The listening time of `time` in the local time zone
+    pub fn of(time: DateTime<Utc>) -> Self {
+        let local = time.with_timezone(&Local);
+        Self {
+            day_part: DayPart::from_hour(local.hour()),
+            weekend: matches!(local.weekday(), Weekday::Sat | Weekday::Sun),
+        }
+    }
+
+    /// How much a play at `other` counts towards listening at this time
+    fn similarity(&self, other: &Self) -> f32 {
+        if self.day_part != other.day_part {
+            0.0
+        } else if self.weekend == other.weekend {
+            1.0
+        } else {
+            OTHER_DAY_WEIGHT
+        }
+    }
+}
+
+/// Plays of an item in all, and those around the time the patterns are for
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Plays {
+    pub all: f32,
+    pub around_now: f32,
+}
+
+impl Plays {
+    pub fn add(&mut self, other: Plays) {
+        self.all += other.all;
+        self.around_now += other.around_now;
+    }
+}
+
+/// Plays of each track, album and artist around one listening time
+#[derive(Debug, Clone, Default)]
+pub struct ListeningPatterns {
+    tracks: HashMap<String, Plays>,
+    // By lowercase title and name, as the history has them
+    albums: HashMap<String, Plays>,
+    artists: HashMap<String, Plays>,
+    // Share of all plays that were around the listening time
+    baseline: f32,
+}
+
+impl ListeningPatterns {
+    pub fn new(history: &[HistoryEntry], at: ListeningTime) -> Self {
+        let mut patterns = Self::default();
+        let mut around_now = 0.0;
+        for entry in history {
+            let plays = Plays {
+                all: 1.0,
+                around_now: at.similarity(&ListeningTime::of(entry.played_at)),
+            };
+            around_now += plays.around_now;
+            for (map, key) in [
+                (&mut patterns.tracks, entry.track_id.clone()),
+                (&mut patterns.albums, entry.album.to_lowercase()),
+                (&mut patterns.artists, entry.artist.to_lowercase()),
+            ] {
+                map.entry(key).or_default().add(plays);
+            }
+        }
+        if !history.is_empty() {
+            patterns.baseline = around_now / history.len() as f32;
+        }
+        patterns
+    }
+
+    pub fn track(&self, track_id: &str) -> Plays {
+        self.tracks.get(track_id).copied().unwrap_or_default()
+    }
+
+    /// Plays of all of `track_ids` together, such as a playlist's
+    pub fn tracks<'a>(&self, track_ids: impl IntoIterator<Item = &'a String>) -> Plays {
+        let mut plays = Plays::default();
+        for track_id in track_ids {
+            plays.add(self.track(track_id));
+        }
+        plays
+    }
+
+    pub fn album(&self, title: &str) -> Plays {
+        self.albums
+            .get(&title.to_lowercase())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn artist(&self, name: &str) -> Plays {
+        self.artists
+            .get(&name.to_lowercase())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// How many times more likely `plays` are to be around now than music
+    /// in general; 1.0 means no different. Items with few plays stay close
+    /// to 1.0.
+    pub fn lift(&self, plays: Plays) -> f32 {
+        if self.baseline <= 0.0 {
+            return 1.0;
+        }
+        let share = (plays.around_now + PRIOR_PLAYS * self.baseline) / (plays.all + PRIOR_PLAYS);
+        share / self.baseline
+    }
+
+    /// Plays counted up or down by how much they happen around now, for
+    /// ranking items against each other
+    pub fn score(&self, plays: Plays) -> f32 {
+        plays.all * self.lift(plays)
+    }
+}
@@ -1,46 +1,262 @@
-use crate::services::models::Track;
-use crate::services::audio_player::AudioBackend;
+use crate::platform;
+use crate::services::models::{PlaybackSource, Segment, Track};
+use crate::services::audio_player::{
+    AudioBackend, AudioFormat, ClippingPrevention, GainStages, OutputBuffer, ResamplerQuality,
+    VolumeCurve,
+};
+use crate::services::{resources, stream_cache, StreamingQuality};
+use crate::services::webdav;
+use super::limiter;
 use async_trait::async_trait;
 use gstreamer as gst;
+use gstreamer_audio as gst_audio;
 use gstreamer::prelude::*;
 use gst::glib;
 use parking_lot::RwLock;
 use std::any::Any;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+// Dynamic range covered by the logarithmic volume curve
+const VOLUME_RANGE_DB: f64 = 60.0;
+
+// Fades are done in steps of this length
+const FADE_STEP: Duration = Duration::from_millis(10);
+const DEFAULT_FADE: Duration = Duration::from_millis(200);
+
+// Shortest dip around a seek, even with fades turned off, so the jump in
+// the waveform isn't heard as a click
+const SEEK_RAMP: Duration = Duration::from_millis(30);
+
+// dB leveling may take peaks over full scale when a limiter catches them.
+// rglimiter starts compressing 6 dB below full scale.
+const NORMALIZATION_HEADROOM: f64 = 6.0;
+
+// Names of the signal chain's stages, to read them back while playing
+const REPLAY_GAIN_STAGE: &str = "nova-replaygain";
+const CLIP_LIMITER_STAGE: &str = "nova-clip-limiter";
+const PEAK_LIMITER_STAGE: &str = "nova-peak-limiter";
+const METER_STAGE: &str = "nova-meter";
+
+// How often the level meter at the end of the chain reports
+const METER_INTERVAL: Duration = Duration::from_millis(50);
+
+/// GStreamer element that decodes and plays a file. playbin3 picks its
+/// decoders differently, so it can play files playbin fails on and back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineKind {
+    Playbin,
+    Playbin3,
+}
+
+impl PipelineKind {
+    fn element(self) -> &'static str {
+        match self {
+            PipelineKind::Playbin => "playbin",
+            PipelineKind::Playbin3 => "playbin3",
+        }
+    }
+}
+
+// Elements playback can't do without
+const REQUIRED_ELEMENTS: &[&str] = &["playbin", "audioconvert", "audioresample"];
+
+/// GStreamer elements that playback needs but aren't installed. The audio
+/// outputs count as one, as any of them will do.
+pub fn missing_elements() -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    gst::init()?;
+
+    let mut missing: Vec<String> = REQUIRED_ELEMENTS
+        .iter()
+        .filter(|name| gst::ElementFactory::find(name).is_none())
+        .map(|name| name.to_string())
+        .collect();
+    let sinks = platform::audio_sinks();
+    if !sinks.iter().any(|name| gst::ElementFactory::find(name).is_some()) {
+        missing.push(sinks.join(" or "));
+    }
+    Ok(missing)
+}
+
+/// Sink volume for a 0.0-1.0 volume on `curve`
+fn sink_volume(volume: f64, curve: VolumeCurve) -> f64 {
+    let volume = volume.clamp(0.0, 1.0);
+    match curve {
+        VolumeCurve::Linear => volume,
+        VolumeCurve::Logarithmic if volume <= 0.0 => 0.0,
+        VolumeCurve::Logarithmic => gst_audio::StreamVolume::convert_volume(
+            gst_audio::StreamVolumeFormat::Db,
+            gst_audio::StreamVolumeFormat::Linear,
+            (volume - 1.0) * VOLUME_RANGE_DB,
+        ),
+    }
+}
+
+/// Volume ramps that replace instant starts, stops and jumps, so they don't
+/// click. Ramps run on their own thread and a newer one cancels an older one.
+#[derive(Debug, Clone)]
+struct Fader {
+    duration: Arc<RwLock<Duration>>,
+    generation: Arc<AtomicU64>,
+    volume: Arc<RwLock<f64>>,
+    volume_curve: Arc<RwLock<VolumeCurve>>,
+    // Most the volume may be set to, when limited
+    limit: Arc<RwLock<Option<f64>>>,
+}
+
+impl Fader {
+    /// Claim the pipeline's volume, cancelling any ramp still running
+    fn start(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn is_current(&self, id: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) == id
+    }
+
+    /// Set the pipeline to `level` (0.0-1.0) of the user's volume
+    fn set_level(&self, pipeline: &gst::Element, level: f64) {
+        let volume = match *self.limit.read() {
+            Some(limit) => self.volume.read().min(limit),
+            None => *self.volume.read(),
+        };
+        let volume = sink_volume(volume, *self.volume_curve.read());
+        pipeline.set_property("volume", volume * level);
+    }
+
+    /// The fade duration set for play, pause, stop and seek
+    fn length(&self) -> Duration {
+        *self.duration.read()
+    }
+
+    /// Ramp from `from` to `to` of the user's volume over `length`. Returns
+    /// false if a newer ramp took over; ramps without an `id` can't be
+    /// cancelled.
+    fn ramp(
+        &self,
+        pipeline: &gst::Element,
+        id: Option<u64>,
+        length: Duration,
+        from: f64,
+        to: f64,
+    ) -> bool {
+        let steps = (length.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+        for step in 1..=steps {
+            if id.is_some_and(|id| !self.is_current(id)) {
+                return false;
+            }
+            let progress = step as f64 / steps as f64;
+            self.set_level(pipeline, from + (to - from) * progress);
+            std::thread::sleep(FADE_STEP);
+        }
+        true
+    }
+
+    /// Fade `pipeline` out over `length` and hand it to `then`, on a
+    /// background thread. Without a fade `then` runs right away.
+    fn fade_out<F>(&self, pipeline: gst::Element, id: Option<u64>, length: Duration, then: F)
+    where
+        F: FnOnce(&gst::Element) + Send + 'static,
+    {
+        if length.is_zero() {
+            then(&pipeline);
+            return;
+        }
+        let fader = self.clone();
+        std::thread::spawn(move || {
+            if fader.ramp(&pipeline, id, length, 1.0, 0.0) {
+                then(&pipeline);
+            }
+        });
+    }
+
+    /// Fade `pipeline` in from silence over `length` on a background thread
+    fn fade_in(&self, pipeline: gst::Element, id: u64, length: Duration) {
+        if length.is_zero() {
+            self.set_level(&pipeline, 1.0);
+            return;
+        }
+        let fader = self.clone();
+        std::thread::spawn(move || fader.ramp(&pipeline, Some(id), length, 0.0, 1.0));
+    }
+}
 
 #[derive(Debug)]
 pub struct LocalAudioBackend {
+    kind: PipelineKind,
     pipeline: Arc<RwLock<Option<gst::Element>>>,
     is_playing: Arc<RwLock<bool>>,
     current_duration: Arc<RwLock<Option<Duration>>>,
     current_path: Arc<RwLock<Option<PathBuf>>>,
+    // The part of the file the current track covers, for chapter tracks
+    segment: Arc<RwLock<Option<Segment>>>,
+    normalization: Arc<RwLock<Option<ClippingPrevention>>>,
+    album_gain: Arc<RwLock<bool>>,
+    fader: Fader,
+    // Fade between a track and the one following it, when not the fader's
+    crossfade: Arc<RwLock<Option<Duration>>>,
+    // Pipeline for the next track, paused with its first audio decoded,
+    // and the URI it plays
+    prerolled: Arc<RwLock<Option<(String, gst::Element)>>>,
+    // Latest peak and RMS dB of each channel from the level meter, which
+    // only reports while metering is on
+    levels: Arc<RwLock<(Vec<f64>, Vec<f64>)>>,
+    metering: Arc<RwLock<bool>>,
+    // When the current pipeline gets louder than the one fading out before
+    // it, while they crossfade
+    handover: Arc<RwLock<Option<Instant>>>,
+    output_buffer: Arc<RwLock<OutputBuffer>>,
+    resampler_quality: Arc<RwLock<ResamplerQuality>>,
+    streaming_quality: Arc<RwLock<StreamingQuality>>,
 }
 
 impl LocalAudioBackend {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+    pub fn new(kind: PipelineKind) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         // Initialize GStreamer
         gst::init()?;
 
         Ok(Self {
+            kind,
             pipeline: Arc::new(RwLock::new(None)),
             is_playing: Arc::new(RwLock::new(false)),
             current_duration: Arc::new(RwLock::new(None)),
             current_path: Arc::new(RwLock::new(None)),
+            segment: Arc::new(RwLock::new(None)),
+            normalization: Arc::new(RwLock::new(None)),
+            album_gain: Arc::new(RwLock::new(false)),
+            fader: Fader {
+                duration: Arc::new(RwLock::new(DEFAULT_FADE)),
+                generation: Arc::new(AtomicU64::new(0)),
+                volume: Arc::new(RwLock::new(1.0)),
+                volume_curve: Arc::new(RwLock::new(VolumeCurve::Logarithmic)),
+                limit: Arc::new(RwLock::new(None)),
+            },
+            crossfade: Arc::new(RwLock::new(None)),
+            prerolled: Arc::new(RwLock::new(None)),
+            levels: Arc::new(RwLock::new((Vec::new(), Vec::new()))),
+            metering: Arc::new(RwLock::new(false)),
+            handover: Arc::new(RwLock::new(None)),
+            output_buffer: Arc::new(RwLock::new(OutputBuffer::default())),
+            resampler_quality: Arc::new(RwLock::new(ResamplerQuality::default())),
+            streaming_quality: Arc::new(RwLock::new(StreamingQuality::High)),
         })
     }
 
     fn setup_pipeline(&self, uri: &str) -> Result<gst::Element, Box<dyn std::error::Error + Send + Sync>> {
         // Create playbin element
-        let playbin = gst::ElementFactory::make("playbin")
+        let playbin = gst::ElementFactory::make(self.kind.element())
             .name("player")
             .build()
-            .map_err(|e| format!("Failed to create playbin: {}", e))?;
+            .map_err(|e| format!("Failed to create {}: {}", self.kind.element(), e))?;
 
         // Set up the bus message handling
         let pipeline_weak = playbin.downgrade();
         let is_playing = Arc::clone(&self.is_playing);
+        let levels = Arc::clone(&self.levels);
+        let current = Arc::clone(&self.pipeline);
+        let handover = Arc::clone(&self.handover);
         playbin
             .bus()
             .unwrap()
@@ -61,6 +277,25 @@ impl LocalAudioBackend {
                             pipeline.set_state(gst::State::Null).unwrap();
                             *is_playing.write() = false;
                         }
+                        gst::MessageView::Element(element) => {
+                            let is_current = current.read().as_ref() == Some(&pipeline);
+                            let handed_over =
+                                handover.read().map_or(true, |at| Instant::now() >= at);
+                            // Only the pipeline being heard the most moves the meter
+                            if is_current != handed_over {
+                                return gst::glib::ControlFlow::Continue;
+                            }
+                            if let Some(s) = element.structure().filter(|s| s.name() == "level") {
+                                let channels = |field: &str| {
+                                    s.get::<glib::ValueArray>(field)
+                                        .map(|values| {
+                                            values.iter().filter_map(|v| v.get().ok()).collect()
+                                        })
+                                        .unwrap_or_default()
+                                };
+                                *levels.write() = (channels("peak"), channels("rms"));
+                            }
+                        }
                         gst::MessageView::StateChanged(state) => {
                             // Compare the source object with our pipeline
                             let is_our_pipeline = state
@@ -83,20 +318,217 @@ impl LocalAudioBackend {
             })
             .expect("Failed to add bus watch");
 
-        // Set up audio properties
+        // Sign in to WebDAV servers. The HTTP source fetches byte ranges as
+        // playback seeks, so streams start without downloading the file.
+        playbin.connect("source-setup", false, |values| {
+            let source = values[1].get::<gst::Element>().ok()?;
+            if source.find_property("user-id").is_none() {
+                return None;
+            }
+            let uri = source.property::<Option<String>>("location")?;
+            let (user, password) = webdav::credentials_for(&uri)?;
+            source.set_property("user-id", user);
+            source.set_property("user-pw", password);
+            None
+        });
+
+        // The converters playbin puts in front of the sink, and those in the
+        // audio filter, all work to the same quality
+        if let Some(bin) = playbin.downcast_ref::<gst::Bin>() {
+            Self::apply_resampler_quality(bin, *self.resampler_quality.read());
+        }
+
+        // Set up audio properties. Playback starts silent and fades in.
         playbin.set_property("uri", uri);
-        playbin.set_property("volume", 1.0);
+        self.fader.set_level(&playbin, 0.0);
 
-        // Configure audio sink
-        let audio_sink = gst::ElementFactory::make("autoaudiosink")
-            .build()
-            .map_err(|e| format!("Failed to create audio sink: {}", e))?;
+        // Configure audio sink, using the first one this platform has
+        let audio_sink = platform::audio_sinks()
+            .iter()
+            .find_map(|name| gst::ElementFactory::make(name).build().ok())
+            .ok_or("Failed to create audio sink: no usable output found")?;
+        Self::apply_output_buffer(&audio_sink, *self.output_buffer.read());
 
         playbin.set_property("audio-sink", &audio_sink);
 
+        let normalization = *self.normalization.read();
+        let album_gain = *self.album_gain.read();
+        let limit = self.fader.limit.read().is_some();
+        let metering = *self.metering.read();
+        match Self::audio_filter(normalization, album_gain, limit, metering) {
+            Ok(filter) => playbin.set_property("audio-filter", &filter),
+            Err(e) => eprintln!("Playing without leveling, the peak limiter or metering: {}", e),
+        }
+
         Ok(playbin)
     }
 
+    /// Set the buffering of `sink`, or of the device sink inside it once
+    /// autoaudiosink has picked one
+    fn apply_output_buffer(sink: &gst::Element, output: OutputBuffer) {
+        // Each piece has to fit in the buffer at least twice over
+        let buffer = output.buffer.as_micros() as i64;
+        let latency = (output.latency.as_micros() as i64).min(buffer / 2);
+        let apply = move |element: &gst::Element| {
+            if element.find_property("buffer-time").is_some()
+                && element.find_property("latency-time").is_some()
+            {
+                element.set_property("buffer-time", buffer);
+                element.set_property("latency-time", latency);
+            }
+        };
+        apply(sink);
+        if let Some(bin) = sink.downcast_ref::<gst::Bin>() {
+            bin.connect_deep_element_added(move |_, _, element| apply(element));
+        }
+    }
+
+    /// Set the quality of every resampler and sample format converter in
+    /// `bin`, including ones added after
+    fn apply_resampler_quality(bin: &gst::Bin, quality: ResamplerQuality) {
+        // audioresample goes from 0 to 10 and defaults to 4; 10 matches
+        // SoX's very high quality
+        let (resample, dithering) = match quality {
+            ResamplerQuality::Fast => (0, "none"),
+            ResamplerQuality::Medium => (4, "tpdf"),
+            ResamplerQuality::High => (10, "tpdf-hf"),
+        };
+        fn apply(element: &gst::Element, resample: i32, dithering: &str) {
+            match element.factory().map(|factory| factory.name()).as_deref() {
+                Some("audioresample") => element.set_property("quality", resample),
+                Some("audioconvert") => element.set_property_from_str("dithering", dithering),
+                _ => {}
+            }
+            // Bins added whole, like the audio filter, bring their own
+            if let Some(bin) = element.downcast_ref::<gst::Bin>() {
+                let _ = bin
+                    .iterate_recurse()
+                    .foreach(|child| apply(&child, resample, dithering));
+            }
+        }
+        apply(bin.upcast_ref(), resample, dithering);
+        bin.connect_deep_element_added(move |_, _, element| apply(element, resample, dithering));
+    }
+
+    /// Format of the raw audio going into `element`, once it's settled
+    fn input_format(element: &gst::Element) -> Option<AudioFormat> {
+        let caps = element.static_pad("sink")?.current_caps()?;
+        let info = gst_audio::AudioInfo::from_caps(&caps).ok()?;
+        Some(AudioFormat {
+            rate: info.rate(),
+            depth: info.depth(),
+            float: info.format_info().is_float(),
+            channels: info.channels(),
+        })
+    }
+
+    /// DSP stages for keeping the pitch when the rate changes, ReplayGain
+    /// leveling, by album gain when `album_gain`, and the look-ahead peak
+    /// limiter, then a level meter, reporting when `metering`. They go in
+    /// as the pipeline is built, so changes apply from the next track.
+    fn audio_filter(
+        normalization: Option<ClippingPrevention>,
+        album_gain: bool,
+        limit: bool,
+        metering: bool,
+    ) -> Result<gst::Element, Box<dyn std::error::Error + Send + Sync>> {
+        let mut stages = vec!["audioconvert".to_string()];
+        // Passes audio straight through at normal speed
+        if gst::ElementFactory::find("scaletempo").is_some() {
+            stages.push("scaletempo".to_string());
+        }
+        match normalization {
+            Some(ClippingPrevention::Limiter) => {
+                stages.push(format!(
+                    "rgvolume name={} album-mode={} headroom={}",
+                    REPLAY_GAIN_STAGE, album_gain, NORMALIZATION_HEADROOM
+                ));
+                stages.push(format!("rglimiter name={}", CLIP_LIMITER_STAGE));
+            }
+            Some(ClippingPrevention::ReduceGain) => {
+                stages.push(format!(
+                    "rgvolume name={} album-mode={} headroom=0",
+                    REPLAY_GAIN_STAGE, album_gain
+                ));
+            }
+            None => {}
+        }
+        if limit {
+            stages.push("audioconvert".to_string());
+            stages.push(format!(
+                "capsfilter name={} caps=\"{}\"",
+                PEAK_LIMITER_STAGE,
+                limiter::CAPS
+            ));
+        }
+        stages.push(format!(
+            "level name={} post-messages={} interval={}",
+            METER_STAGE,
+            metering,
+            METER_INTERVAL.as_nanos()
+        ));
+        stages.push("audioconvert".to_string());
+
+        let bin = gst::parse::bin_from_description(&stages.join(" ! "), true)?;
+        if let Some(stage) = bin.by_name(PEAK_LIMITER_STAGE) {
+            limiter::attach(&stage)?;
+        }
+        Ok(bin.upcast())
+    }
+
+    /// The pipeline prerolled for `uri`, if that's what was readied. One
+    /// readied for anything else is shut down.
+    fn take_preroll(&self, uri: &str) -> Option<gst::Element> {
+        let (prerolled_uri, pipeline) = self.prerolled.write().take()?;
+        if prerolled_uri == uri {
+            return Some(pipeline);
+        }
+        let _ = pipeline.set_state(gst::State::Null);
+        None
+    }
+
+    /// Start or stop the level meter of `pipeline` reporting
+    fn set_meter_reporting(pipeline: &gst::Element, metering: bool) {
+        if let Some(meter) = pipeline
+            .downcast_ref::<gst::Bin>()
+            .and_then(|bin| bin.by_name(METER_STAGE))
+        {
+            meter.set_property("post-messages", metering);
+        }
+    }
+
+    /// Shut down the pipeline readied for the next track, as it was built
+    /// with settings that have since changed
+    fn discard_preroll(&self) {
+        if let Some((_, pipeline)) = self.prerolled.write().take() {
+            let _ = pipeline.set_state(gst::State::Null);
+        }
+    }
+
+    /// Play the file from `start` at `rate` times normal speed, to `stop`
+    /// when it's only a segment of it. The seek has to wait for the
+    /// pipeline to preroll, and its stop ends the stream where the segment
+    /// does. Nothing has been heard yet, so the flush can't be either.
+    fn start_at(
+        pipeline: &gst::Element,
+        start: Duration,
+        stop: Option<Duration>,
+        rate: f64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Self::ensure_state_change(pipeline, gst::State::Paused)?;
+        let flags = gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE;
+        let start = gst::ClockTime::from_nseconds(start.as_nanos() as u64);
+        let (stop_type, stop) = match stop {
+            Some(stop) => (
+                gst::SeekType::Set,
+                gst::ClockTime::from_nseconds(stop.as_nanos() as u64),
+            ),
+            None => (gst::SeekType::End, gst::ClockTime::ZERO),
+        };
+        pipeline.seek(rate, flags, gst::SeekType::Set, start, stop_type, stop)?;
+        Self::ensure_state_change(pipeline, gst::State::Playing)
+    }
+
     fn get_position_from_pipeline(pipeline: &gst::Element) -> Option<Duration> {
         let position = pipeline.query_position::<gst::ClockTime>();
         position.map(|p| Duration::from_nanos(p.nseconds()))
@@ -107,6 +539,18 @@ impl LocalAudioBackend {
         duration.map(|d| Duration::from_nanos(d.nseconds()))
     }
 
+    /// Stop, fading the pipeline out over `length`
+    fn stop_over(&self, length: Duration) {
+        // The old pipeline fades out on its own while the next one starts
+        if let Some(pipeline) = self.pipeline.write().take() {
+            self.fader.fade_out(pipeline, None, length, |pipeline| {
+                let _ = Self::ensure_state_change(pipeline, gst::State::Null);
+            });
+        }
+        *self.is_playing.write() = false;
+        *self.current_duration.write() = None;
+    }
+
     fn ensure_state_change(
         pipeline: &gst::Element,
         state: gst::State,
@@ -138,60 +582,134 @@ impl LocalAudioBackend {
 }
 
 impl AudioBackend for LocalAudioBackend {
-    fn play(&self, track: &Track) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    fn name(&self) -> &'static str {
+        self.kind.element()
+    }
+
+    fn play(
+        &self,
+        track: &Track,
+        start: Duration,
+        rate: f64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // A track still playing fades out as this one fades in, and the
+        // linear ramps cross halfway through
+        let crossfading = *self.is_playing.read() && self.pipeline.read().is_some();
+        let length = match *self.crossfade.read() {
+            Some(crossfade) if crossfading => crossfade,
+            _ => self.fader.length(),
+        };
+        *self.handover.write() =
+            (crossfading && !length.is_zero()).then(|| Instant::now() + length / 2);
+
         // Stop any currently playing audio
-        self.stop();
+        self.stop_over(length);
 
-        // Get the file path from the track's source
-        if let crate::services::models::PlaybackSource::Local { path, .. } = &track.source {
-            // Update the current path
-            *self.current_path.write() = Some(path.clone());
+        // Get the file path or URL from the track's source
+        let (uri, local_path, segment) = match &track.source {
+            PlaybackSource::Local { path, segment, .. } => {
+                // Create properly encoded URI from path
+                let uri = glib::filename_to_uri(path, None)
+                    .map_err(|e| format!("Failed to create URI from path: {}", e))?;
+                (uri.to_string(), Some(path.clone()), *segment)
+            }
+            // A copy fetched ahead of time plays without the network
+            PlaybackSource::Stream {
+                url,
+                low_quality_url,
+            } => match stream_cache::cached_file(url) {
+                Some(path) => {
+                    let uri = glib::filename_to_uri(&path, None)
+                        .map_err(|e| format!("Failed to create URI from path: {}", e))?;
+                    (uri.to_string(), None, None)
+                }
+                None => {
+                    // Metered connections take the smaller stream, when
+                    // the provider has one
+                    let url = match *self.streaming_quality.read() {
+                        StreamingQuality::Low => low_quality_url.as_ref().unwrap_or(url),
+                        StreamingQuality::High => url,
+                    };
+                    (url.clone(), None, None)
+                }
+            },
+            _ => return Err("Not a local or streamed audio source".into()),
+        };
 
-            // Create properly encoded URI from path
-            let uri = glib::filename_to_uri(path, None)
-                .map_err(|e| format!("Failed to create URI from path: {}", e))?;
+        // Update the current path
+        *self.current_path.write() = local_path;
+        *self.segment.write() = segment;
 
-            // Setup new pipeline
-            let pipeline = self.setup_pipeline(&uri)?;
+        // Meter readings carry on from the outgoing track while it's the
+        // louder one
+        if self.handover.read().is_none() {
+            *self.levels.write() = (Vec::new(), Vec::new());
+        }
 
-            // Set to playing state
-            Self::ensure_state_change(&pipeline, gst::State::Playing)?;
+        // Use the pipeline readied for this track, or set up a new one
+        let pipeline = match self.take_preroll(&uri) {
+            Some(pipeline) => pipeline,
+            None => self.setup_pipeline(&uri)?,
+        };
 
-            // Store pipeline and update state
-            *self.pipeline.write() = Some(pipeline);
-            *self.is_playing.write() = true;
+        // Set to playing state. A pipeline that can't decode the file is
+        // shut down so the next backend can take over cleanly.
+        let fade = self.fader.start();
+        let started = match segment {
+            Some(segment) => Self::start_at(
+                &pipeline,
+                Duration::from_millis(segment.start_ms) + start,
+                Some(Duration::from_millis(segment.end_ms)),
+                rate,
+            ),
+            None if !start.is_zero() || rate != 1.0 => Self::start_at(&pipeline, start, None, rate),
+            None => Self::ensure_state_change(&pipeline, gst::State::Playing),
+        };
+        if let Err(e) = started {
+            let _ = pipeline.set_state(gst::State::Null);
+            return Err(e);
+        }
+        self.fader.fade_in(pipeline.clone(), fade, length);
 
-            // Get and store duration
-            if let Some(pipeline) = &*self.pipeline.read() {
-                *self.current_duration.write() = Self::get_duration_from_pipeline(pipeline);
-            }
+        // Store pipeline and update state
+        *self.pipeline.write() = Some(pipeline);
+        *self.is_playing.write() = true;
 
-            Ok(())
-        } else {
-            Err("Not a local audio source".into())
+        // Get and store duration
+        if let Some(segment) = segment {
+            *self.current_duration.write() = Some(Duration::from_millis(segment.duration_ms()));
+        } else if let Some(pipeline) = &*self.pipeline.read() {
+            *self.current_duration.write() = Self::get_duration_from_pipeline(pipeline);
         }
+
+        Ok(())
     }
 
     fn stop(&self) {
-        if let Some(pipeline) = self.pipeline.write().take() {
-            let _ = Self::ensure_state_change(&pipeline, gst::State::Null);
-        }
-        *self.is_playing.write() = false;
-        *self.current_duration.write() = None;
+        self.stop_over(self.fader.length());
     }
 
     fn pause(&self) {
         if let Some(pipeline) = &*self.pipeline.read() {
-            if let Ok(()) = Self::ensure_state_change(pipeline, gst::State::Paused) {
-                *self.is_playing.write() = false;
-            }
+            let fade = self.fader.start();
+            *self.is_playing.write() = false;
+            let pause = |pipeline: &gst::Element| {
+                if let Err(e) = Self::ensure_state_change(pipeline, gst::State::Paused) {
+                    eprintln!("Failed to pause: {}", e);
+                }
+            };
+            self.fader
+                .fade_out(pipeline.clone(), Some(fade), self.fader.length(), pause);
         }
     }
 
     fn resume(&self) {
         if let Some(pipeline) = &*self.pipeline.read() {
+            let fade = self.fader.start();
+            self.fader.set_level(pipeline, 0.0);
             if let Ok(()) = Self::ensure_state_change(pipeline, gst::State::Playing) {
                 *self.is_playing.write() = true;
+                self.fader.fade_in(pipeline.clone(), fade, self.fader.length());
             }
         }
     }
@@ -201,21 +719,47 @@ impl AudioBackend for LocalAudioBackend {
     }
 
     fn get_position(&self) -> Option<Duration> {
-        if let Some(pipeline) = &*self.pipeline.read() {
-            Self::get_position_from_pipeline(pipeline)
-        } else {
-            None
-        }
+        let position = Self::get_position_from_pipeline(self.pipeline.read().as_ref()?)?;
+        // Positions are relative to the start of a chapter track
+        let start = self.segment.read().map_or(0, |segment| segment.start_ms);
+        Some(position.saturating_sub(Duration::from_millis(start)))
     }
 
     fn set_position(&self, position: Duration) {
-        if let Some(pipeline) = &*self.pipeline.read() {
-            let position = position.as_nanos() as u64;
-            let _ = pipeline.seek_simple(
-                gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT | gst::SeekFlags::ACCURATE,
-                gst::ClockTime::from_nseconds(position),
-            );
+        let Some(pipeline) = self.pipeline.read().clone() else {
+            return;
+        };
+
+        // Dip the volume around the jump, unless paused where it's silent
+        let position = gst::ClockTime::from_nseconds(position.as_nanos() as u64);
+        let segment = *self.segment.read();
+        let seek = move |pipeline: &gst::Element| {
+            let flags = gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT | gst::SeekFlags::ACCURATE;
+            // A chapter track keeps its stop, so it still ends where it should
+            let _ = match segment {
+                Some(segment) => pipeline.seek(
+                    1.0,
+                    flags,
+                    gst::SeekType::Set,
+                    gst::ClockTime::from_mseconds(segment.start_ms) + position,
+                    gst::SeekType::Set,
+                    gst::ClockTime::from_mseconds(segment.end_ms),
+                ),
+                None => pipeline.seek_simple(flags, position),
+            };
+        };
+        if !self.is_playing() {
+            seek(&pipeline);
+            return;
         }
+
+        let fade = self.fader.start();
+        let fader = self.fader.clone();
+        let length = self.fader.length().max(SEEK_RAMP);
+        self.fader.fade_out(pipeline, Some(fade), length, move |pipeline| {
+            seek(pipeline);
+            fader.fade_in(pipeline.clone(), fade, length);
+        });
     }
 
     fn get_duration(&self) -> Option<Duration> {
@@ -223,9 +767,186 @@ impl AudioBackend for LocalAudioBackend {
     }
 
     fn set_volume(&self, volume: f64) {
+        *self.fader.volume.write() = volume.clamp(0.0, 1.0);
+        if let Some(pipeline) = &*self.pipeline.read() {
+            self.fader.set_level(pipeline, 1.0);
+        }
+    }
+
+    fn set_volume_curve(&self, curve: VolumeCurve) {
+        *self.fader.volume_curve.write() = curve;
         if let Some(pipeline) = &*self.pipeline.read() {
-            pipeline.set_property("volume", volume.clamp(0.0, 1.0));
+            self.fader.set_level(pipeline, 1.0);
+        }
+    }
+
+    fn set_fade_duration(&self, duration: Duration) {
+        *self.fader.duration.write() = duration;
+    }
+
+    fn set_volume_limit(&self, limit: Option<f64>) {
+        // The next track's pipeline has the peak limiter or not
+        if limit.is_some() != self.fader.limit.read().is_some() {
+            self.discard_preroll();
+        }
+        *self.fader.limit.write() = limit.map(|limit| limit.clamp(0.0, 1.0));
+        if let Some(pipeline) = &*self.pipeline.read() {
+            self.fader.set_level(pipeline, 1.0);
+        }
+    }
+
+    fn set_normalization(&self, normalization: Option<ClippingPrevention>) {
+        // Like the limiter, it applies from the next track
+        *self.normalization.write() = normalization;
+        self.discard_preroll();
+    }
+
+    fn set_album_gain(&self, album_gain: bool) {
+        *self.album_gain.write() = album_gain;
+        self.discard_preroll();
+    }
+
+    fn set_crossfade(&self, crossfade: Option<Duration>) {
+        *self.crossfade.write() = crossfade;
+    }
+
+    fn set_metering(&self, metering: bool) {
+        *self.metering.write() = metering;
+        if let Some(pipeline) = self.pipeline.read().as_ref() {
+            Self::set_meter_reporting(pipeline, metering);
+        }
+        if let Some((_, pipeline)) = self.prerolled.read().as_ref() {
+            Self::set_meter_reporting(pipeline, metering);
+        }
+        if !metering {
+            *self.levels.write() = (Vec::new(), Vec::new());
+        }
+    }
+
+    fn set_output_buffer(&self, buffer: OutputBuffer) {
+        *self.output_buffer.write() = buffer;
+        self.discard_preroll();
+    }
+
+    fn set_resampler_quality(&self, quality: ResamplerQuality) {
+        *self.resampler_quality.write() = quality;
+        self.discard_preroll();
+    }
+
+    fn set_streaming_quality(&self, quality: StreamingQuality) {
+        *self.streaming_quality.write() = quality;
+    }
+
+    fn handover_delay(&self) -> Duration {
+        self.handover
+            .read()
+            .map_or(Duration::ZERO, |at| at.saturating_duration_since(Instant::now()))
+    }
+
+    fn gain_stages(&self) -> Option<GainStages> {
+        let pipeline = self.pipeline.read().clone()?;
+        let bin = pipeline.downcast_ref::<gst::Bin>()?;
+        let replay_gain_db = bin
+            .by_name(REPLAY_GAIN_STAGE)
+            .map(|stage| stage.property::<f64>("result-gain"));
+        let volume = pipeline.property::<f64>("volume");
+        let (peak_db, rms_db) = self.levels.read().clone();
+        // The audio filter comes straight after decoding, and the sink
+        // takes whatever conversion made of it
+        let format_into = |property: &str| {
+            pipeline
+                .property::<Option<gst::Element>>(property)
+                .as_ref()
+                .and_then(Self::input_format)
+        };
+        Some(GainStages {
+            replay_gain_db,
+            clip_limiter: bin.by_name(CLIP_LIMITER_STAGE).is_some(),
+            peak_limiter: bin.by_name(PEAK_LIMITER_STAGE).is_some(),
+            volume_db: 20.0 * volume.log10(),
+            peak_db,
+            rms_db,
+            source_format: format_into("audio-filter"),
+            output_format: format_into("audio-sink"),
+        })
+    }
+
+    fn set_rate(&self, rate: f64, audible: bool) {
+        let Some(pipeline) = self.pipeline.read().clone() else {
+            return;
+        };
+        let Some(position) = pipeline.query_position::<gst::ClockTime>() else {
+            return;
+        };
+
+        let mut flags = gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE;
+        if !audible && rate != 1.0 {
+            flags |= gst::SeekFlags::TRICKMODE | gst::SeekFlags::TRICKMODE_NO_AUDIO;
+        }
+        // A chapter track stays within its part of the file either way
+        let segment = *self.segment.read();
+        let start = segment.map_or(gst::ClockTime::ZERO, |segment| {
+            gst::ClockTime::from_mseconds(segment.start_ms)
+        });
+        let (stop_type, stop) = match segment {
+            Some(segment) => (
+                gst::SeekType::Set,
+                gst::ClockTime::from_mseconds(segment.end_ms),
+            ),
+            None => (gst::SeekType::End, gst::ClockTime::ZERO),
+        };
+
+        // Backwards plays from the start of the range up to where it is now
+        let result = if rate < 0.0 {
+            pipeline.seek(rate, flags, gst::SeekType::Set, start, gst::SeekType::Set, position)
+        } else {
+            pipeline.seek(rate, flags, gst::SeekType::Set, position, stop_type, stop)
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to play at {}x: {}", rate, e);
+        }
+    }
+
+    fn preload(&self, track: &Track) {
+        // Low-memory mode leaves the page cache to the tracks being played
+        if resources::low_memory() {
+            return;
+        }
+        let PlaybackSource::Local { path, .. } = &track.source else {
+            return;
+        };
+        let uri = match glib::filename_to_uri(path, None) {
+            Ok(uri) => uri.to_string(),
+            Err(e) => {
+                eprintln!("Failed to create URI from path: {}", e);
+                return;
+            }
+        };
+        if self
+            .prerolled
+            .read()
+            .as_ref()
+            .is_some_and(|(prerolled, _)| *prerolled == uri)
+        {
+            return;
+        }
+        self.discard_preroll();
+
+        // Paused, the pipeline opens the file and decodes up to the first
+        // audio, so the track starts without waiting on storage or decoders
+        let pipeline = match self.setup_pipeline(&uri) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                eprintln!("Failed to get {:?} ready: {}", path, e);
+                return;
+            }
+        };
+        if let Err(e) = pipeline.set_state(gst::State::Paused) {
+            eprintln!("Failed to get {:?} ready: {}", path, e);
+            let _ = pipeline.set_state(gst::State::Null);
+            return;
         }
+        *self.prerolled.write() = Some((uri, pipeline));
     }
 
     fn as_any(&self) -> &(dyn Any + 'static) {
@@ -0,0 +1,176 @@
+//! Chapters embedded in long files, such as a live set or DJ mix in one
+//! file, shown as tracks of their own. Each chapter track plays its segment
+//! of the file. MP3 chapters come from ID3 CHAP frames and FLAC ones from
+//! the embedded cue sheet.
+
+use crate::services::models::{PlaybackSource, Segment, Track};
+use sha1::{Digest, Sha1};
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::StandardTagKey;
+use symphonia::core::probe::Hint;
+
+static SPLIT_CHAPTERS: AtomicBool = AtomicBool::new(false);
+
+// Cue sheet lead-out markers: 170 on CDs, 255 otherwise
+const LEAD_OUT_TRACKS: [u32; 2] = [170, 255];
+
+pub fn set_split_chapters(enabled: bool) {
+    SPLIT_CHAPTERS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn split_chapters() -> bool {
+    SPLIT_CHAPTERS.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Chapter {
+    title: Option<String>,
+    start_ms: u64,
+    end_ms: u64,
+}
+
+/// `track` as one track per chapter when splitting is on and its file has
+/// more than one chapter, otherwise as it is
+pub fn split(track: Track) -> Vec<Track> {
+    if !split_chapters() {
+        return vec![track];
+    }
+    let PlaybackSource::Local {
+        path, file_format, ..
+    } = &track.source
+    else {
+        return vec![track];
+    };
+
+    let chapters = match file_format.as_str() {
+        "mp3" => id3_chapters(path),
+        "flac" => cue_chapters(path),
+        _ => Vec::new(),
+    };
+    if chapters.len() < 2 {
+        return vec![track];
+    }
+    println!("Splitting {:?} into {} chapters", path, chapters.len());
+
+    // Chapters of an untagged file are grouped under the file's title
+    let album = if track.album == "Unknown Album" {
+        track.title.clone()
+    } else {
+        track.album.clone()
+    };
+    let total = chapters.len() as u32;
+    chapters
+        .into_iter()
+        .enumerate()
+        .map(|(index, chapter)| {
+            let number = index as u32 + 1;
+            let segment = Segment {
+                start_ms: chapter.start_ms,
+                end_ms: chapter.end_ms,
+            };
+            let mut source = track.source.clone();
+            if let PlaybackSource::Local { segment: part, .. } = &mut source {
+                *part = Some(segment);
+            }
+            Track {
+                id: chapter_id(&track.id, number),
+                title: chapter
+                    .title
+                    .unwrap_or_else(|| format!("{} (Part {})", track.title, number)),
+                album: album.clone(),
+                duration: (segment.duration_ms() / 1000) as u32,
+                track_number: Some(number),
+                track_total: Some(total),
+                // The file's lyrics are for the whole set
+                lyrics: None,
+                source,
+                ..track.clone()
+            }
+        })
+        .collect()
+}
+
+/// Chapters from ID3v2 CHAP frames, in order of their start
+fn id3_chapters(path: &Path) -> Vec<Chapter> {
+    let Ok(tag) = id3::Tag::read_from_path(path) else {
+        return Vec::new();
+    };
+    let mut chapters: Vec<Chapter> = tag
+        .chapters()
+        .filter(|chapter| chapter.end_time > chapter.start_time)
+        .map(|chapter| Chapter {
+            title: chapter
+                .frames
+                .iter()
+                .find(|frame| frame.id() == "TIT2")
+                .and_then(|frame| frame.content().text())
+                .map(str::to_string),
+            start_ms: chapter.start_time as u64,
+            end_ms: chapter.end_time as u64,
+        })
+        .collect();
+    chapters.sort_by_key(|chapter| chapter.start_ms);
+    chapters
+}
+
+/// Chapters from a FLAC cue sheet. Each one runs until the next starts,
+/// and the last until the end of the file.
+fn cue_chapters(path: &Path) -> Vec<Chapter> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension("flac");
+    let Ok(probed) = symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &Default::default(),
+        &Default::default(),
+    ) else {
+        return Vec::new();
+    };
+
+    let format = probed.format;
+    let Some(params) = format.default_track().map(|track| &track.codec_params) else {
+        return Vec::new();
+    };
+    let (Some(rate), Some(frames)) = (params.sample_rate, params.n_frames) else {
+        return Vec::new();
+    };
+    let to_ms = |frames: u64| frames * 1000 / rate as u64;
+
+    let starts: Vec<(u64, Option<String>)> = format
+        .cues()
+        .iter()
+        .filter(|cue| !LEAD_OUT_TRACKS.contains(&cue.index))
+        .map(|cue| {
+            let title = cue
+                .tags
+                .iter()
+                .find(|tag| tag.std_key == Some(StandardTagKey::TrackTitle))
+                .map(|tag| tag.value.to_string());
+            (to_ms(cue.start_ts), title)
+        })
+        .collect();
+    let end = to_ms(frames);
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, (start_ms, title))| Chapter {
+            title: title.clone(),
+            start_ms: *start_ms,
+            end_ms: starts.get(index + 1).map_or(end, |(next, _)| *next),
+        })
+        .filter(|chapter| chapter.end_ms > chapter.start_ms)
+        .collect()
+}
+
+/// ID of chapter `number` of the file whose own ID is `file_id`
+pub(super) fn chapter_id(file_id: &str, number: u32) -> String {
+    format!("{:x}", Sha1::digest(format!("{}#{}", file_id, number)))
+}
@@ -1,12 +1,65 @@
-use crate::services::models::{Album, Artist, Artwork, ArtworkSource, PlaybackSource, Track};
+use super::chapters::chapter_id;
+use super::scanner::file_id;
+use crate::services::models::{
+    Album, Artist, Artwork, ArtworkSource, Availability, LyricsMatch, MovedTrack, PlaybackSource,
+    ReleaseType, Segment, Thumbnails, Track,
+};
+use crate::services::resources;
+use crate::services::writeback::TagChange;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, OptionalExtension};
+use rusqlite::{params, params_from_iter, OptionalExtension};
 use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+// Album columns in the order album_from_row expects, with artwork falling
+// back to the first track that has some
+const ALBUM_SELECT: &str = "SELECT a.id, a.title, a.artist, a.year,
+        COALESCE(a.artwork_id, (
+            SELECT t.artwork_id
+            FROM tracks t
+            WHERE t.album = a.title AND t.artist = a.artist
+            AND t.artwork_id IS NOT NULL
+            ORDER BY t.track_number ASC
+            LIMIT 1
+        )),
+        COALESCE(a.artwork_path, (
+            SELECT t.artwork_path
+            FROM tracks t
+            WHERE t.album = a.title AND t.artist = a.artist
+            AND t.artwork_path IS NOT NULL
+            ORDER BY t.track_number ASC
+            LIMIT 1
+        )),
+        a.release_type
+    FROM albums a";
+
+/// Size and modification time of a file when it was indexed, to tell
+/// whether it has changed since
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: u64,
+    // Seconds since the epoch. Missing for files indexed before it was kept.
+    pub modified: Option<i64>,
+}
+
+impl FileStamp {
+    pub fn of(metadata: &std::fs::Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|since| since.as_secs() as i64);
+        Self {
+            size: metadata.len(),
+            modified,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Database {
     pool: Arc<Pool<SqliteConnectionManager>>,
@@ -24,19 +77,20 @@ impl Database {
                     | rusqlite::OpenFlags::SQLITE_OPEN_SHARED_CACHE,
             )
             .with_init(|conn| {
-                conn.execute_batch(
+                conn.execute_batch(&format!(
                     "PRAGMA journal_mode = OFF;  -- No need for journaling in memory
                      PRAGMA synchronous = OFF;   -- No need for fsync
                      PRAGMA temp_store = MEMORY;
-                     PRAGMA cache_size = 10000;  -- Increased cache size for memory
+                     PRAGMA cache_size = {};     -- Smaller in low-memory mode
                      PRAGMA busy_timeout = 60000;",
-                )?;
+                    resources::db_cache_size()
+                ))?;
                 Ok(())
             });
 
         // Create pool with appropriate size
         let pool = Pool::builder()
-            .max_size(4)
+            .max_size(resources::db_pool_size())
             .min_idle(Some(1))
             .build(manager)?;
 
@@ -64,8 +118,11 @@ impl Database {
                     file_path TEXT NOT NULL,
                     file_format TEXT NOT NULL,
                     file_size INTEGER NOT NULL,
-                    artwork_data BLOB,
-                    artwork_path TEXT
+                    artwork_id TEXT,
+                    artwork_path TEXT,
+                    explicit INTEGER NOT NULL DEFAULT 0,
+                    track_total INTEGER,
+                    file_mtime INTEGER
                 );
 
                 CREATE TABLE IF NOT EXISTS albums (
@@ -73,7 +130,7 @@ impl Database {
                     title TEXT NOT NULL,
                     artist TEXT NOT NULL,
                     year INTEGER,
-                    artwork_data BLOB,
+                    artwork_id TEXT,
                     artwork_path TEXT,
                     UNIQUE(title, artist)
                 );
@@ -81,8 +138,47 @@ impl Database {
                 CREATE TABLE IF NOT EXISTS artists (
                     id TEXT PRIMARY KEY,
                     name TEXT NOT NULL UNIQUE,
-                    artwork_data BLOB,
+                    artwork_id TEXT,
                     artwork_path TEXT
+                );
+
+                -- Shared by every track, album and artist with the same art
+                CREATE TABLE IF NOT EXISTS thumbnails (
+                    id TEXT PRIMARY KEY,
+                    small BLOB NOT NULL,
+                    medium BLOB NOT NULL,
+                    large BLOB NOT NULL
+                );
+
+                -- Lyrics from the files' tags, indexed for full-text search
+                CREATE TABLE IF NOT EXISTS lyrics (
+                    track_id TEXT PRIMARY KEY,
+                    text TEXT NOT NULL
+                );
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS lyrics_fts USING fts5(
+                    text, content='lyrics', content_rowid='rowid'
+                );
+
+                -- Keep the index in step with the lyrics table
+                CREATE TRIGGER IF NOT EXISTS lyrics_ai AFTER INSERT ON lyrics BEGIN
+                    INSERT INTO lyrics_fts(rowid, text) VALUES (new.rowid, new.text);
+                END;
+                CREATE TRIGGER IF NOT EXISTS lyrics_ad AFTER DELETE ON lyrics BEGIN
+                    INSERT INTO lyrics_fts(lyrics_fts, rowid, text)
+                    VALUES ('delete', old.rowid, old.text);
+                END;
+                CREATE TRIGGER IF NOT EXISTS lyrics_au AFTER UPDATE ON lyrics BEGIN
+                    INSERT INTO lyrics_fts(lyrics_fts, rowid, text)
+                    VALUES ('delete', old.rowid, old.text);
+                    INSERT INTO lyrics_fts(rowid, text) VALUES (new.rowid, new.text);
+                END;
+
+                -- Part of the file each chapter track plays
+                CREATE TABLE IF NOT EXISTS track_segments (
+                    track_id TEXT PRIMARY KEY,
+                    start_ms INTEGER NOT NULL,
+                    end_ms INTEGER NOT NULL
                 );",
             )?;
 
@@ -95,6 +191,7 @@ impl Database {
                  CREATE INDEX IF NOT EXISTS idx_artists_name ON artists(name);
                  CREATE INDEX IF NOT EXISTS idx_tracks_search ON tracks(title, artist, album);
                  CREATE INDEX IF NOT EXISTS idx_albums_search ON albums(title, artist);
+                 CREATE INDEX IF NOT EXISTS idx_albums_year ON albums(year);
                  CREATE INDEX IF NOT EXISTS idx_artists_search ON artists(name);",
             )?;
 
@@ -134,8 +231,11 @@ impl Database {
                 file_path TEXT NOT NULL,
                 file_format TEXT NOT NULL,
                 file_size INTEGER NOT NULL,
-                artwork_data BLOB,
-                artwork_path TEXT
+                artwork_id TEXT,
+                artwork_path TEXT,
+                explicit INTEGER NOT NULL DEFAULT 0,
+                track_total INTEGER,
+                file_mtime INTEGER
             );
 
             CREATE TABLE IF NOT EXISTS albums (
@@ -143,7 +243,7 @@ impl Database {
                 title TEXT NOT NULL,
                 artist TEXT NOT NULL,
                 year INTEGER,
-                artwork_data BLOB,
+                artwork_id TEXT,
                 artwork_path TEXT,
                 UNIQUE(title, artist)
             );
@@ -151,9 +251,16 @@ impl Database {
             CREATE TABLE IF NOT EXISTS artists (
                 id TEXT PRIMARY KEY,
                 name TEXT NOT NULL UNIQUE,
-                artwork_data BLOB,
+                artwork_id TEXT,
                 artwork_path TEXT
             );
+
+            CREATE TABLE IF NOT EXISTS thumbnails (
+                id TEXT PRIMARY KEY,
+                small BLOB NOT NULL,
+                medium BLOB NOT NULL,
+                large BLOB NOT NULL
+            );
         ",
         )?;
 
@@ -168,24 +275,39 @@ impl Database {
         }
 
         // Add artwork columns to tracks if they don't exist
-        if !column_exists(&conn, "tracks", "artwork_data") {
-            conn.execute("ALTER TABLE tracks ADD COLUMN artwork_data BLOB", [])?;
+        if !column_exists(&conn, "tracks", "artwork_id") {
+            conn.execute("ALTER TABLE tracks ADD COLUMN artwork_id TEXT", [])?;
         }
         if !column_exists(&conn, "tracks", "artwork_path") {
             conn.execute("ALTER TABLE tracks ADD COLUMN artwork_path TEXT", [])?;
         }
+        if !column_exists(&conn, "tracks", "explicit") {
+            conn.execute(
+                "ALTER TABLE tracks ADD COLUMN explicit INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+        if !column_exists(&conn, "tracks", "track_total") {
+            conn.execute("ALTER TABLE tracks ADD COLUMN track_total INTEGER", [])?;
+        }
+        if !column_exists(&conn, "tracks", "file_mtime") {
+            conn.execute("ALTER TABLE tracks ADD COLUMN file_mtime INTEGER", [])?;
+        }
 
         // Add artwork columns to albums if they don't exist
-        if !column_exists(&conn, "albums", "artwork_data") {
-            conn.execute("ALTER TABLE albums ADD COLUMN artwork_data BLOB", [])?;
+        if !column_exists(&conn, "albums", "artwork_id") {
+            conn.execute("ALTER TABLE albums ADD COLUMN artwork_id TEXT", [])?;
         }
         if !column_exists(&conn, "albums", "artwork_path") {
             conn.execute("ALTER TABLE albums ADD COLUMN artwork_path TEXT", [])?;
         }
+        if !column_exists(&conn, "albums", "release_type") {
+            conn.execute("ALTER TABLE albums ADD COLUMN release_type TEXT", [])?;
+        }
 
         // Add artwork columns to artists if they don't exist
-        if !column_exists(&conn, "artists", "artwork_data") {
-            conn.execute("ALTER TABLE artists ADD COLUMN artwork_data BLOB", [])?;
+        if !column_exists(&conn, "artists", "artwork_id") {
+            conn.execute("ALTER TABLE artists ADD COLUMN artwork_id TEXT", [])?;
         }
         if !column_exists(&conn, "artists", "artwork_path") {
             conn.execute("ALTER TABLE artists ADD COLUMN artwork_path TEXT", [])?;
@@ -201,6 +323,7 @@ impl Database {
             CREATE INDEX IF NOT EXISTS idx_artists_name ON artists(name);
             CREATE INDEX IF NOT EXISTS idx_tracks_search ON tracks(title, artist, album);
             CREATE INDEX IF NOT EXISTS idx_albums_search ON albums(title, artist);
+            CREATE INDEX IF NOT EXISTS idx_albums_year ON albums(year);
             CREATE INDEX IF NOT EXISTS idx_artists_search ON artists(name);
         ",
         )?;
@@ -223,7 +346,7 @@ impl Database {
         let mut conn = self.pool.get()?;
         conn.execute_batch("PRAGMA busy_timeout = 10000;")?;
         let mut stmt = conn.prepare(
-            "SELECT id, title, artist, album, duration, track_number, disc_number, release_year, genre, file_path, file_format, file_size, artwork_data, artwork_path
+            "SELECT id, title, artist, album, duration, track_number, disc_number, release_year, genre, file_path, file_format, file_size, artwork_id, artwork_path, explicit, track_total
             FROM tracks
             WHERE title LIKE ?1 OR artist LIKE ?1 OR album LIKE ?1
             LIMIT ?2 OFFSET ?3",
@@ -231,10 +354,11 @@ impl Database {
 
         let search_pattern = format!("%{}%", query);
         println!("Using search pattern: {}", search_pattern);
-        let tracks: Vec<Track> = stmt
+        let mut tracks: Vec<Track> = stmt
             .query_map(
                 params![search_pattern, limit as i64, offset as i64],
                 |row| {
+                    let path = PathBuf::from(row.get::<_, String>(9)?);
                     Ok(Track {
                         id: row.get(0)?,
                         title: row.get(1)?,
@@ -242,11 +366,16 @@ impl Database {
                         album: row.get(3)?,
                         duration: row.get(4)?,
                         track_number: row.get(5)?,
+                        track_total: row.get(15)?,
                         disc_number: row.get(6)?,
                         release_year: row.get(7)?,
                         genre: row.get(8)?,
+                        explicit: row.get(14)?,
+                        lyrics: None,
+                        release_type: None,
                         artwork: Artwork {
-                            thumbnail: row.get(12)?,
+                            id: row.get(12)?,
+                            thumbnails: None,
                             full_art: match row.get::<_, Option<String>>(13)? {
                                 Some(path) if !path.is_empty() => ArtworkSource::Local {
                                     path: Path::new(&path).to_path_buf(),
@@ -257,13 +386,17 @@ impl Database {
                         source: PlaybackSource::Local {
                             file_format: row.get(10)?,
                             file_size: row.get(11)?,
-                            path: Path::new(&row.get::<_, String>(9)?).to_path_buf(),
+                            path,
+                            segment: None,
                         },
+                        availability: Availability::Local,
                     })
                 },
             )?
             .filter_map(Result::ok)
             .collect();
+        Self::attach_thumbnails(&conn, tracks.iter_mut().map(|track| &mut track.artwork))?;
+        Self::attach_segments(&conn, tracks.iter_mut())?;
 
         println!("Found {} tracks", tracks.len());
         Ok(tracks)
@@ -273,9 +406,10 @@ impl Database {
         println!("Getting all tracks");
         let mut conn = self.pool.get()?;
         conn.execute_batch("PRAGMA busy_timeout = 10000;")?;
-        let mut stmt = conn.prepare("SELECT id, title, artist, album, duration, track_number, disc_number, release_year, genre, file_path, file_format, file_size, artwork_data, artwork_path FROM tracks")?;
-        let tracks: Vec<Track> = stmt
+        let mut stmt = conn.prepare("SELECT id, title, artist, album, duration, track_number, disc_number, release_year, genre, file_path, file_format, file_size, artwork_id, artwork_path, explicit, track_total FROM tracks")?;
+        let mut tracks: Vec<Track> = stmt
             .query_map([], |row| {
+                let path = PathBuf::from(row.get::<_, String>(9)?);
                 Ok(Track {
                     id: row.get(0)?,
                     title: row.get(1)?,
@@ -283,11 +417,16 @@ impl Database {
                     album: row.get(3)?,
                     duration: row.get(4)?,
                     track_number: row.get(5)?,
+                    track_total: row.get(15)?,
                     disc_number: row.get(6)?,
                     release_year: row.get(7)?,
                     genre: row.get(8)?,
+                    explicit: row.get(14)?,
+                    lyrics: None,
+                    release_type: None,
                     artwork: Artwork {
-                        thumbnail: row.get(12)?,
+                        id: row.get(12)?,
+                        thumbnails: None,
                         full_art: match row.get::<_, Option<String>>(13)? {
                             Some(path) if !path.is_empty() => ArtworkSource::Local {
                                 path: Path::new(&path).to_path_buf(),
@@ -298,12 +437,16 @@ impl Database {
                     source: PlaybackSource::Local {
                         file_format: row.get(10)?,
                         file_size: row.get(11)?,
-                        path: Path::new(&row.get::<_, String>(9)?).to_path_buf(),
+                        path,
+                        segment: None,
                     },
+                    availability: Availability::Local,
                 })
             })?
             .filter_map(Result::ok)
             .collect();
+        Self::attach_thumbnails(&conn, tracks.iter_mut().map(|track| &mut track.artwork))?;
+        Self::attach_segments(&conn, tracks.iter_mut())?;
 
         println!("Found {} total tracks", tracks.len());
         Ok(tracks)
@@ -316,12 +459,12 @@ impl Database {
         println!("Inserting artist: {}", artist.name);
         let mut conn = self.pool.get()?;
         conn.execute_batch("PRAGMA busy_timeout = 10000;")?;
+        if let Some(artwork) = &artist.artwork {
+            Self::store_thumbnails(&conn, artwork)?;
+        }
         conn.execute(
-            "INSERT OR REPLACE INTO artists (id, name, artwork_data, artwork_path) VALUES (?, ?, ?, ?)",
-            params![artist.id, artist.name, match &artist.artwork {
-                Some(Artwork { thumbnail: Some(data), .. }) => Some(data as &[u8]),
-                _ => None,
-            }, match &artist.artwork {
+            "INSERT OR REPLACE INTO artists (id, name, artwork_id, artwork_path) VALUES (?, ?, ?, ?)",
+            params![artist.id, artist.name, artist.artwork.as_ref().and_then(|artwork| artwork.id.as_deref()), match &artist.artwork {
                 Some(Artwork { full_art: ArtworkSource::Local { path }, .. }) => path.to_str().unwrap_or_default(),
                 _ => "",
             }],
@@ -336,17 +479,17 @@ impl Database {
         println!("Inserting album: {} by {}", album.title, album.artist);
         let mut conn = self.pool.get()?;
         conn.execute_batch("PRAGMA busy_timeout = 10000;")?;
+        if let Some(artwork) = &album.artwork {
+            Self::store_thumbnails(&conn, artwork)?;
+        }
         conn.execute(
-            "INSERT OR REPLACE INTO albums (id, title, artist, year, artwork_data, artwork_path) VALUES (?, ?, ?, ?, ?, ?)",
+            "INSERT OR REPLACE INTO albums (id, title, artist, year, artwork_id, artwork_path) VALUES (?, ?, ?, ?, ?, ?)",
             params![
                 album.id,
                 album.title,
                 album.artist,
                 album.year,
-                match &album.artwork {
-                    Some(Artwork { thumbnail: Some(data), .. }) => Some(data as &[u8]),
-                    _ => None,
-                },
+                album.artwork.as_ref().and_then(|artwork| artwork.id.as_deref()),
                 match &album.artwork {
                     Some(Artwork { full_art: ArtworkSource::Local { path }, .. }) => path.to_str().unwrap_or_default(),
                     _ => "",
@@ -359,7 +502,7 @@ impl Database {
     pub fn get_all_artists(&self) -> Result<Vec<Artist>, Box<dyn std::error::Error + Send + Sync>> {
         let mut conn = self.pool.get()?;
         let mut stmt = conn.prepare(
-            "SELECT a.id, a.name, COALESCE(a.artwork_data, t.artwork_data) as final_artwork_data,
+            "SELECT a.id, a.name, COALESCE(a.artwork_id, t.artwork_id) as final_artwork_id,
                     COALESCE(a.artwork_path, t.artwork_path) as final_artwork_path
              FROM artists a
              LEFT JOIN tracks t ON a.name = t.artist
@@ -367,14 +510,16 @@ impl Database {
              GROUP BY a.id",
         )?;
 
-        let artists: Vec<Artist> = stmt
+        let mut artists: Vec<Artist> = stmt
             .query_map([], |row| {
                 Ok(Artist {
                     id: row.get(0)?,
                     name: row.get(1)?,
                     albums: Vec::new(),
+                    sources: Vec::new(),
                     artwork: Some(Artwork {
-                        thumbnail: row.get(2)?,
+                        id: row.get(2)?,
+                        thumbnails: None,
                         full_art: match row.get::<_, Option<String>>(3)? {
                             Some(path) => ArtworkSource::Local {
                                 path: PathBuf::from(path),
@@ -386,6 +531,7 @@ impl Database {
             })?
             .filter_map(Result::ok)
             .collect();
+        Self::attach_thumbnails(&conn, artists.iter_mut().filter_map(|a| a.artwork.as_mut()))?;
 
         Ok(artists)
     }
@@ -395,14 +541,14 @@ impl Database {
         let tx = conn.transaction()?;
 
         let sql = "SELECT a.id, a.title, a.artist, a.year,
-                   COALESCE(a.artwork_data, (
-                       SELECT t.artwork_data
+                   COALESCE(a.artwork_id, (
+                       SELECT t.artwork_id
                        FROM tracks t
                        WHERE t.album = a.title AND t.artist = a.artist
-                       AND t.artwork_data IS NOT NULL
+                       AND t.artwork_id IS NOT NULL
                        ORDER BY t.track_number ASC
                        LIMIT 1
-                   )) as final_artwork_data,
+                   )) as final_artwork_id,
                    COALESCE(a.artwork_path, (
                        SELECT t.artwork_path
                        FROM tracks t
@@ -410,22 +556,26 @@ impl Database {
                        AND t.artwork_path IS NOT NULL
                        ORDER BY t.track_number ASC
                        LIMIT 1
-                   )) as final_artwork_path
+                   )) as final_artwork_path,
+                   a.release_type
             FROM albums a
             WHERE a.title != 'Unknown Album'";
 
         let mut stmt = tx.prepare(sql)?;
-        let albums = stmt
+        let mut albums = stmt
             .query_map([], |row| {
                 Ok(Album {
                     id: row.get(0)?,
                     title: row.get(1)?,
                     artist: row.get(2)?,
                     year: row.get(3)?,
+                    release_type: Self::release_type(row, 6)?,
                     art_url: None,
                     tracks: Vec::new(),
+                    sources: Vec::new(),
                     artwork: Some(Artwork {
-                        thumbnail: row.get(4)?,
+                        id: row.get(4)?,
+                        thumbnails: None,
                         full_art: match row.get::<_, Option<String>>(5)? {
                             Some(path) => ArtworkSource::Local {
                                 path: PathBuf::from(path),
@@ -439,6 +589,7 @@ impl Database {
             .collect::<Vec<_>>();
 
         drop(stmt);
+        Self::attach_thumbnails(&tx, albums.iter_mut().filter_map(|a| a.artwork.as_mut()))?;
         tx.commit()?;
 
         println!("Found {} total albums", albums.len());
@@ -455,13 +606,13 @@ impl Database {
         let mut conn = self.pool.get()?;
         let mut stmt = conn.prepare(
             "SELECT a.id, a.name,
-                    COALESCE(a.artwork_data, (
-                        SELECT t.artwork_data
+                    COALESCE(a.artwork_id, (
+                        SELECT t.artwork_id
                         FROM tracks t
                         WHERE t.artist = a.name
                         ORDER BY t.track_number ASC
                         LIMIT 1
-                    )) as final_artwork_data,
+                    )) as final_artwork_id,
                     COALESCE(a.artwork_path, (
                         SELECT t.artwork_path
                         FROM tracks t
@@ -477,7 +628,7 @@ impl Database {
 
         let search_pattern = format!("%{}%", query);
         println!("Using search pattern: {}", search_pattern);
-        let artists: Vec<Artist> = stmt
+        let mut artists: Vec<Artist> = stmt
             .query_map(
                 params![search_pattern, limit as i64, offset as i64],
                 |row| {
@@ -485,8 +636,10 @@ impl Database {
                         id: row.get(0)?,
                         name: row.get(1)?,
                         albums: Vec::new(),
+                        sources: Vec::new(),
                         artwork: Some(Artwork {
-                            thumbnail: row.get(2)?,
+                            id: row.get(2)?,
+                            thumbnails: None,
                             full_art: match row.get::<_, Option<String>>(3)? {
                                 Some(path) => ArtworkSource::Local {
                                     path: PathBuf::from(path),
@@ -499,6 +652,7 @@ impl Database {
             )?
             .filter_map(Result::ok)
             .collect();
+        Self::attach_thumbnails(&conn, artists.iter_mut().filter_map(|a| a.artwork.as_mut()))?;
 
         println!("Found {} artists", artists.len());
         Ok(artists)
@@ -514,20 +668,21 @@ impl Database {
         let mut conn = self.pool.get()?;
         let mut stmt = conn.prepare(
             "SELECT a.id, a.title, a.artist, a.year,
-                    COALESCE(a.artwork_data, (
-                        SELECT t.artwork_data
+                    COALESCE(a.artwork_id, (
+                        SELECT t.artwork_id
                         FROM tracks t
                         WHERE t.album = a.title AND t.artist = a.artist
                         ORDER BY t.track_number ASC
                         LIMIT 1
-                    )) as final_artwork_data,
+                    )) as final_artwork_id,
                     COALESCE(a.artwork_path, (
                         SELECT t.artwork_path
                         FROM tracks t
                         WHERE t.album = a.title AND t.artist = a.artist
                         ORDER BY t.track_number ASC
                         LIMIT 1
-                    )) as final_artwork_path
+                    )) as final_artwork_path,
+                    a.release_type
              FROM albums a
              WHERE (a.title LIKE ?1 OR a.artist LIKE ?1)
              AND a.title != 'Unknown Album'
@@ -537,7 +692,7 @@ impl Database {
 
         let search_pattern = format!("%{}%", query);
         println!("Using search pattern: {}", search_pattern);
-        let albums: Vec<Album> = stmt
+        let mut albums: Vec<Album> = stmt
             .query_map(
                 params![search_pattern, limit as i64, offset as i64],
                 |row| {
@@ -546,10 +701,13 @@ impl Database {
                         title: row.get(1)?,
                         artist: row.get(2)?,
                         year: row.get(3)?,
+                        release_type: Self::release_type(row, 6)?,
                         art_url: None,
                         tracks: Vec::new(),
+                        sources: Vec::new(),
                         artwork: Some(Artwork {
-                            thumbnail: row.get::<_, Option<Vec<u8>>>(4)?,
+                            id: row.get(4)?,
+                            thumbnails: None,
                             full_art: match row.get::<_, Option<String>>(5)? {
                                 Some(path) => ArtworkSource::Local {
                                     path: PathBuf::from(path),
@@ -562,6 +720,7 @@ impl Database {
             )?
             .filter_map(Result::ok)
             .collect();
+        Self::attach_thumbnails(&conn, albums.iter_mut().filter_map(|a| a.artwork.as_mut()))?;
 
         println!("Found {} albums", albums.len());
         Ok(albums)
@@ -580,7 +739,7 @@ impl Database {
         let artist_id = format!("{:x}", hasher.finalize());
 
         tx.execute(
-            "INSERT OR IGNORE INTO artists (id, name, artwork_data, artwork_path)
+            "INSERT OR IGNORE INTO artists (id, name, artwork_id, artwork_path)
              VALUES (?, ?, NULL, NULL)",
             params![artist_id, artist],
         )?;
@@ -606,7 +765,7 @@ impl Database {
         let album_id = format!("{:x}", hasher.finalize());
 
         tx.execute(
-            "INSERT OR IGNORE INTO albums (id, title, artist, year, artwork_data, artwork_path)
+            "INSERT OR IGNORE INTO albums (id, title, artist, year, artwork_id, artwork_path)
              VALUES (?, ?, ?, ?, NULL, NULL)",
             params![album_id, title, artist, year],
         )?;
@@ -623,19 +782,14 @@ impl Database {
         let mut conn = self.pool.get()?;
         let tx = conn.transaction()?;
 
+        Self::store_thumbnails(&tx, artwork)?;
         tx.execute(
             "UPDATE artists SET
-                artwork_data = ?,
+                artwork_id = ?,
                 artwork_path = ?
              WHERE name = ?",
             params![
-                match &artwork {
-                    Artwork {
-                        thumbnail: Some(data),
-                        ..
-                    } => Some(data as &[u8]),
-                    _ => None,
-                },
+                artwork.id,
                 match &artwork.full_art {
                     ArtworkSource::Local { path } => path.to_str().unwrap_or_default(),
                     _ => "",
@@ -656,19 +810,14 @@ impl Database {
         let mut conn = self.pool.get()?;
         let tx = conn.transaction()?;
 
+        Self::store_thumbnails(&tx, artwork)?;
         tx.execute(
             "UPDATE albums SET
-                artwork_data = ?,
+                artwork_id = ?,
                 artwork_path = ?
              WHERE title = ? AND artist = ?",
             params![
-                match &artwork {
-                    Artwork {
-                        thumbnail: Some(data),
-                        ..
-                    } => Some(data as &[u8]),
-                    _ => None,
-                },
+                artwork.id,
                 match &artwork.full_art {
                     ArtworkSource::Local { path } => path.to_str().unwrap_or_default(),
                     _ => "",
@@ -681,17 +830,11 @@ impl Database {
         // Update artwork for all tracks of this album as well
         tx.execute(
             "UPDATE tracks SET
-                artwork_data = ?,
+                artwork_id = ?,
                 artwork_path = ?
              WHERE album = ? AND artist = ?",
             params![
-                match &artwork {
-                    Artwork {
-                        thumbnail: Some(data),
-                        ..
-                    } => Some(data as &[u8]),
-                    _ => None,
-                },
+                artwork.id,
                 match &artwork.full_art {
                     ArtworkSource::Local { path } => path.to_str().unwrap_or_default(),
                     _ => "",
@@ -712,23 +855,23 @@ impl Database {
         // Process albums
         {
             let mut albums_query = tx.prepare(
-                "SELECT DISTINCT t.album, t.artist, t.artwork_data, t.artwork_path
+                "SELECT DISTINCT t.album, t.artist, t.artwork_id, t.artwork_path
                  FROM tracks t
-                 WHERE t.artwork_data IS NOT NULL OR t.artwork_path IS NOT NULL",
+                 WHERE t.artwork_id IS NOT NULL OR t.artwork_path IS NOT NULL",
             )?;
 
             let album_rows = albums_query.query_map([], |row| {
                 Ok((
                     row.get::<_, String>(0)?,          // album
                     row.get::<_, String>(1)?,          // artist
-                    row.get::<_, Option<Vec<u8>>>(2)?, // artwork_data
+                    row.get::<_, Option<String>>(2)?,  // artwork_id
                     row.get::<_, Option<String>>(3)?,  // artwork_path
                 ))
             })?;
 
             // Process each album
             for result in album_rows {
-                let (album, artist, artwork_data, artwork_path) = result?;
+                let (album, artist, artwork_id, artwork_path) = result?;
 
                 // Create album ID using Rust's SHA1
                 let mut hasher = Sha1::new();
@@ -737,9 +880,12 @@ impl Database {
 
                 // Update album entry
                 tx.execute(
-                    "INSERT OR REPLACE INTO albums (id, title, artist, artwork_data, artwork_path)
-                     VALUES (?, ?, ?, ?, ?)",
-                    params![album_id, album, artist, artwork_data, artwork_path],
+                    "INSERT INTO albums (id, title, artist, artwork_id, artwork_path)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT(id) DO UPDATE SET
+                        artwork_id = excluded.artwork_id,
+                        artwork_path = excluded.artwork_path",
+                    params![album_id, album, artist, artwork_id, artwork_path],
                 )?;
             }
         } // albums_query is dropped here
@@ -747,22 +893,22 @@ impl Database {
         // Process artists
         {
             let mut artists_query = tx.prepare(
-                "SELECT DISTINCT t.artist, t.artwork_data, t.artwork_path
+                "SELECT DISTINCT t.artist, t.artwork_id, t.artwork_path
                  FROM tracks t
-                 WHERE t.artwork_data IS NOT NULL OR t.artwork_path IS NOT NULL",
+                 WHERE t.artwork_id IS NOT NULL OR t.artwork_path IS NOT NULL",
             )?;
 
             let artist_rows = artists_query.query_map([], |row| {
                 Ok((
                     row.get::<_, String>(0)?,          // artist
-                    row.get::<_, Option<Vec<u8>>>(1)?, // artwork_data
+                    row.get::<_, Option<String>>(1)?,  // artwork_id
                     row.get::<_, Option<String>>(2)?,  // artwork_path
                 ))
             })?;
 
             // Process each artist
             for result in artist_rows {
-                let (artist, artwork_data, artwork_path) = result?;
+                let (artist, artwork_id, artwork_path) = result?;
 
                 // Create artist ID using Rust's SHA1
                 let mut hasher = Sha1::new();
@@ -771,9 +917,9 @@ impl Database {
 
                 // Update artist entry
                 tx.execute(
-                    "INSERT OR REPLACE INTO artists (id, name, artwork_data, artwork_path)
+                    "INSERT OR REPLACE INTO artists (id, name, artwork_id, artwork_path)
                      VALUES (?, ?, ?, ?)",
-                    params![artist_id, artist, artwork_data, artwork_path],
+                    params![artist_id, artist, artwork_id, artwork_path],
                 )?;
             }
         } // artists_query is dropped here
@@ -797,10 +943,27 @@ impl Database {
         const MAX_RETRIES: u32 = 5; // Increased retries
         let mut retry_count = 0;
 
+        // A file's tracks replace all it had, which differ in number when
+        // it's split into chapters or no longer is
+        let paths: HashSet<&Path> = tracks
+            .iter()
+            .filter_map(|track| match &track.source {
+                PlaybackSource::Local { path, .. } => Some(path.as_path()),
+                _ => None,
+            })
+            .collect();
+
         while retry_count < MAX_RETRIES {
             let tx = conn.transaction()?;
             let mut success = true;
 
+            for path in &paths {
+                tx.execute(
+                    "DELETE FROM tracks WHERE file_path = ?",
+                    params![path.to_str().unwrap_or_default()],
+                )?;
+            }
+
             // Process in a single transaction
             for track in tracks {
                 // Create artist ID
@@ -810,7 +973,7 @@ impl Database {
 
                 // Insert artist
                 tx.execute(
-                    "INSERT OR IGNORE INTO artists (id, name, artwork_data, artwork_path)
+                    "INSERT OR IGNORE INTO artists (id, name, artwork_id, artwork_path)
                      VALUES (?, ?, NULL, NULL)",
                     params![artist_id, track.artist],
                 )?;
@@ -822,53 +985,56 @@ impl Database {
 
                 // Insert album
                 tx.execute(
-                    "INSERT OR IGNORE INTO albums (id, title, artist, year, artwork_data, artwork_path)
+                    "INSERT OR IGNORE INTO albums (id, title, artist, year, artwork_id, artwork_path)
                      VALUES (?, ?, ?, ?, NULL, NULL)",
                     params![album_id, track.album, track.artist, track.release_year],
                 )?;
-
-                // Insert track
-                if let Err(e) = tx.execute(
-                    "INSERT OR REPLACE INTO tracks (
-                        id, title, artist, album, duration, track_number, disc_number,
-                        release_year, genre, file_path, file_format, file_size,
-                        artwork_data, artwork_path
-                    ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-                    params![
-                        track.id,
-                        track.title,
-                        track.artist,
-                        track.album,
-                        track.duration,
-                        track.track_number,
-                        track.disc_number,
-                        track.release_year,
-                        track.genre,
-                        match &track.source {
-                            PlaybackSource::Local { path, .. } => path.to_str().unwrap_or_default(),
-                            _ => "",
-                        },
-                        match &track.source {
-                            PlaybackSource::Local { file_format, .. } => file_format,
-                            _ => "",
-                        },
-                        match &track.source {
-                            PlaybackSource::Local { file_size, .. } => file_size,
-                            _ => &0,
-                        },
-                        match &track.artwork {
-                            Artwork {
-                                thumbnail: Some(data),
-                                ..
-                            } => Some(data as &[u8]),
-                            _ => None,
-                        },
-                        match &track.artwork.full_art {
-                            ArtworkSource::Local { path } => path.to_str().unwrap_or_default(),
-                            _ => "",
-                        },
-                    ],
-                ) {
+                Self::store_release_type(&tx, &album_id, track)?;
+
+                // Store the thumbnails first so the track can point at them
+                let result = Self::store_thumbnails(&tx, &track.artwork).and_then(|_| {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO tracks (
+                            id, title, artist, album, duration, track_number, disc_number,
+                            release_year, genre, file_path, file_format, file_size,
+                            artwork_id, artwork_path, explicit, track_total
+                        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                        params![
+                            track.id,
+                            track.title,
+                            track.artist,
+                            track.album,
+                            track.duration,
+                            track.track_number,
+                            track.disc_number,
+                            track.release_year,
+                            track.genre,
+                            match &track.source {
+                                PlaybackSource::Local { path, .. } => path.to_str().unwrap_or_default(),
+                                _ => "",
+                            },
+                            match &track.source {
+                                PlaybackSource::Local { file_format, .. } => file_format,
+                                _ => "",
+                            },
+                            match &track.source {
+                                PlaybackSource::Local { file_size, .. } => file_size,
+                                _ => &0,
+                            },
+                            track.artwork.id,
+                            match &track.artwork.full_art {
+                                ArtworkSource::Local { path } => path.to_str().unwrap_or_default(),
+                                _ => "",
+                            },
+                            track.explicit,
+                            track.track_total,
+                        ],
+                    )
+                    .and_then(|_| Self::store_lyrics(&tx, track))
+                    .and_then(|_| Self::store_segment(&tx, track))
+                    .and_then(|_| Self::store_file_mtime(&tx, track))
+                });
+                if let Err(e) = result {
                     success = false;
                     if e.to_string().contains("database is locked") {
                         break;
@@ -909,13 +1075,28 @@ impl Database {
 
         let mut conn = self.pool.get()?;
         let tx = conn.transaction()?;
+        Self::write_track(&tx, track)?;
+        tx.commit()?;
 
-        tx.execute(
+        println!(
+            "Successfully inserted track: {} - {}",
+            track.title, track.artist
+        );
+        Ok(())
+    }
+
+    /// Store `track` and what it carries, for insert_track and others that
+    /// write it as part of a bigger change. Its artist and album have to be
+    /// there already.
+    fn write_track(conn: &rusqlite::Connection, track: &Track) -> rusqlite::Result<()> {
+        Self::store_thumbnails(conn, &track.artwork)?;
+        Self::store_release_type(conn, &Self::album_id(&track.album, &track.artist), track)?;
+        conn.execute(
             "INSERT OR REPLACE INTO tracks (
                 id, title, artist, album, duration, track_number, disc_number,
                 release_year, genre, file_path, file_format, file_size,
-                artwork_data, artwork_path
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                artwork_id, artwork_path, explicit, track_total
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 track.id,
                 track.title,
@@ -938,29 +1119,226 @@ impl Database {
                     PlaybackSource::Local { file_size, .. } => file_size,
                     _ => &0,
                 },
-                match &track.artwork {
-                    Artwork {
-                        thumbnail: Some(data),
-                        ..
-                    } => Some(data as &[u8]),
-                    _ => None,
-                },
+                track.artwork.id,
                 match &track.artwork.full_art {
                     ArtworkSource::Local { path } => path.to_str().unwrap_or_default(),
                     _ => "",
                 },
+                track.explicit,
+                track.track_total,
             ],
         )?;
+        Self::store_lyrics(conn, track)?;
+        Self::store_segment(conn, track)?;
+        Self::store_file_mtime(conn, track)?;
+        Ok(())
+    }
+
+    /// Replace the tracks `path` had with `tracks`, for a file that changed,
+    /// all at once or not at all. There may be more or fewer of them once
+    /// chapters come into it.
+    pub fn replace_file_tracks(
+        &self,
+        path: &Path,
+        tracks: &[Track],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for track in tracks {
+            self.ensure_artist(&track.artist)?;
+            self.ensure_album(&track.album, &track.artist, track.release_year)?;
+        }
 
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let path = path.to_str().unwrap_or_default();
+        // The new tracks store their own again
+        for table in ["lyrics", "track_segments"] {
+            tx.execute(
+                &format!(
+                    "DELETE FROM {} WHERE track_id IN (SELECT id FROM tracks WHERE file_path = ?)",
+                    table
+                ),
+                params![path],
+            )?;
+        }
+        tx.execute("DELETE FROM tracks WHERE file_path = ?", params![path])?;
+        for track in tracks {
+            Self::write_track(&tx, track)?;
+        }
         tx.commit()?;
+        Ok(())
+    }
 
-        println!(
-            "Successfully inserted track: {} - {}",
-            track.title, track.artist
-        );
+    /// Point tracks at their files' new paths, all at once or not at all
+    pub fn relocate_files(&self, moved: &[MovedTrack]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for track in moved {
+            // Replacing whatever a watcher event added for the new path
+            // before this got to it
+            tx.execute(
+                "UPDATE OR REPLACE tracks SET id = ?, file_path = ? WHERE id = ?",
+                params![
+                    track.new_id,
+                    track.path.to_str().unwrap_or_default(),
+                    track.old_id
+                ],
+            )?;
+            for table in ["lyrics", "track_segments"] {
+                tx.execute(
+                    &format!(
+                        "UPDATE OR REPLACE {} SET track_id = ? WHERE track_id = ?",
+                        table
+                    ),
+                    params![track.new_id, track.old_id],
+                )?;
+            }
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    /// The tracks of the file at `from` with the IDs they get at `to`
+    pub fn tracks_moving(
+        &self,
+        from: &Path,
+        to: &Path,
+    ) -> Result<Vec<MovedTrack>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt =
+            conn.prepare_cached("SELECT id, track_number FROM tracks WHERE file_path = ?")?;
+        let rows = stmt
+            .query_map(params![from.to_str().unwrap_or_default()], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<u32>>(1)?))
+            })?
+            .filter_map(Result::ok);
+        let (old_file, new_file) = (file_id(from), file_id(to));
+        let moving = rows
+            .map(|(old_id, number)| {
+                let new_id = match number {
+                    _ if old_id == old_file => new_file.clone(),
+                    Some(number) if old_id == chapter_id(&old_file, number) => {
+                        chapter_id(&new_file, number)
+                    }
+                    _ => old_id.clone(),
+                };
+                MovedTrack {
+                    old_id,
+                    new_id,
+                    path: to.to_path_buf(),
+                }
+            })
+            .collect();
+        Ok(moving)
+    }
+
+    /// Apply `changes` to track `id`, moving it to the album and artist its
+    /// new tags name and dropping any it leaves empty. Returns whether the
+    /// track is in the library.
+    pub fn edit_track(
+        &self,
+        id: &str,
+        changes: &[TagChange],
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let Some(before) = self.get_track_by_id(id)? else {
+            return Ok(false);
+        };
+        let mut track = before.clone();
+        for change in changes {
+            change.apply_to(&mut track);
+        }
+        self.ensure_artist(&track.artist)?;
+        self.ensure_album(&track.album, &track.artist, track.release_year)?;
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE tracks SET title = ?, artist = ?, album = ?, genre = ? WHERE id = ?",
+            params![track.title, track.artist, track.album, track.genre, id],
+        )?;
+        tx.execute(
+            "DELETE FROM albums WHERE title = ? AND artist = ?
+             AND NOT EXISTS (SELECT 1 FROM tracks WHERE album = ? AND artist = ?)",
+            params![before.album, before.artist, before.album, before.artist],
+        )?;
+        tx.execute(
+            "DELETE FROM artists WHERE name = ?
+             AND NOT EXISTS (SELECT 1 FROM tracks WHERE artist = ?)",
+            params![before.artist, before.artist],
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// IDs of the tracks in the file at `path`, one for each chapter
+    pub fn track_ids_at(&self, path: &Path) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare_cached("SELECT id FROM tracks WHERE file_path = ?")?;
+        let ids = stmt
+            .query_map(params![path.to_str().unwrap_or_default()], |row| row.get(0))?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(ids)
+    }
+
+    /// Those of `ids` that are in the library
+    pub fn known_track_ids<'a>(
+        &self,
+        ids: impl IntoIterator<Item = &'a str>,
+    ) -> Result<HashSet<String>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let ids: Vec<&str> = ids.into_iter().collect();
+        let mut known = HashSet::new();
+        // A query for each batch, keeping under SQLite's limit on parameters
+        for batch in ids.chunks(500) {
+            let placeholders = vec!["?"; batch.len()].join(", ");
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id FROM tracks WHERE id IN ({})",
+                placeholders
+            ))?;
+            let found = stmt.query_map(params_from_iter(batch), |row| row.get::<_, String>(0))?;
+            known.extend(found.filter_map(Result::ok));
+        }
+        Ok(known)
+    }
+
+    /// File paths currently in the library along with their indexed size
+    pub fn get_indexed_files(
+        &self,
+    ) -> Result<HashMap<PathBuf, FileStamp>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT file_path, file_size, file_mtime FROM tracks")?;
+        let files = stmt
+            .query_map([], |row| {
+                Ok((
+                    PathBuf::from(row.get::<_, String>(0)?),
+                    FileStamp {
+                        size: row.get(1)?,
+                        modified: row.get(2)?,
+                    },
+                ))
+            })?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(files)
+    }
+
+    /// Every artwork file that a track, album or artist points at
+    pub fn artwork_paths(&self) -> Result<HashSet<PathBuf>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT artwork_path FROM tracks WHERE artwork_path IS NOT NULL
+             UNION SELECT artwork_path FROM albums WHERE artwork_path IS NOT NULL
+             UNION SELECT artwork_path FROM artists WHERE artwork_path IS NOT NULL",
+        )?;
+        let paths = stmt
+            .query_map([], |row| Ok(PathBuf::from(row.get::<_, String>(0)?)))?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(paths)
+    }
+
     pub fn remove_track_by_path(&self, path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
         println!("Attempting to remove track at path: {:?}", path);
         let mut conn = self.pool.get()?;
@@ -983,6 +1361,15 @@ impl Database {
 
         println!("Deleted {} track entries", rows_affected);
 
+        tx.execute(
+            "DELETE FROM lyrics WHERE track_id NOT IN (SELECT id FROM tracks)",
+            [],
+        )?;
+        tx.execute(
+            "DELETE FROM track_segments WHERE track_id NOT IN (SELECT id FROM tracks)",
+            [],
+        )?;
+
         // If we found track info, clean up orphaned albums and artists
         if let Some((artist, album)) = track_info {
             println!("Checking for orphaned album: {} by {}", album, artist);
@@ -1015,6 +1402,16 @@ impl Database {
                 let removed = tx.execute("DELETE FROM artists WHERE name = ?", params![artist])?;
                 println!("Removed {} artist entries", removed);
             }
+
+            // Drop thumbnails nothing points at anymore
+            tx.execute(
+                "DELETE FROM thumbnails WHERE id NOT IN (
+                    SELECT artwork_id FROM tracks WHERE artwork_id IS NOT NULL
+                    UNION SELECT artwork_id FROM albums WHERE artwork_id IS NOT NULL
+                    UNION SELECT artwork_id FROM artists WHERE artwork_id IS NOT NULL
+                )",
+                [],
+            )?;
         }
 
         tx.commit()?;
@@ -1022,6 +1419,458 @@ impl Database {
         Ok(())
     }
 
+    /// Save the thumbnails `artwork` carries, unless another item already did
+    fn store_thumbnails(
+        conn: &rusqlite::Connection,
+        artwork: &Artwork,
+    ) -> rusqlite::Result<()> {
+        if let (Some(id), Some(thumbnails)) = (&artwork.id, &artwork.thumbnails) {
+            conn.execute(
+                "INSERT OR IGNORE INTO thumbnails (id, small, medium, large)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![id, thumbnails.small, thumbnails.medium, thumbnails.large],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn album_id(title: &str, artist: &str) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(format!("{}:{}", title, artist).as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Record the release type a track's tags give its album, if any
+    fn store_release_type(
+        conn: &rusqlite::Connection,
+        album_id: &str,
+        track: &Track,
+    ) -> rusqlite::Result<usize> {
+        match track.release_type {
+            Some(release_type) => conn.execute(
+                "UPDATE albums SET release_type = ?1 WHERE id = ?2",
+                params![release_type.as_str(), album_id],
+            ),
+            None => Ok(0),
+        }
+    }
+
+    /// Save the lyrics `track` carries, or drop old ones it no longer has
+    fn store_lyrics(conn: &rusqlite::Connection, track: &Track) -> rusqlite::Result<usize> {
+        match &track.lyrics {
+            // An upsert rather than INSERT OR REPLACE, so the index triggers
+            // see an update instead of a silent delete
+            Some(text) => conn.execute(
+                "INSERT INTO lyrics (track_id, text) VALUES (?1, ?2)
+                 ON CONFLICT(track_id) DO UPDATE SET text = excluded.text",
+                params![track.id, text],
+            ),
+            None => conn.execute("DELETE FROM lyrics WHERE track_id = ?1", params![track.id]),
+        }
+    }
+
+    fn store_segment(conn: &rusqlite::Connection, track: &Track) -> rusqlite::Result<usize> {
+        match &track.source {
+            PlaybackSource::Local {
+                segment: Some(segment),
+                ..
+            } => conn.execute(
+                "INSERT OR REPLACE INTO track_segments (track_id, start_ms, end_ms)
+                 VALUES (?1, ?2, ?3)",
+                params![track.id, segment.start_ms, segment.end_ms],
+            ),
+            _ => conn.execute(
+                "DELETE FROM track_segments WHERE track_id = ?1",
+                params![track.id],
+            ),
+        }
+    }
+
+    /// Note when `track`'s file was last changed, so scans can tell it's
+    /// been edited even when its size is the same
+    fn store_file_mtime(conn: &rusqlite::Connection, track: &Track) -> rusqlite::Result<usize> {
+        let PlaybackSource::Local { path, .. } = &track.source else {
+            return Ok(0);
+        };
+        let modified = std::fs::metadata(path)
+            .ok()
+            .and_then(|metadata| FileStamp::of(&metadata).modified);
+        conn.execute(
+            "UPDATE tracks SET file_mtime = ?1 WHERE id = ?2",
+            params![modified, track.id],
+        )
+    }
+
+    /// Fill in the segment of tracks read from rows that are chapters of
+    /// their file
+    fn attach_segments<'a>(
+        conn: &rusqlite::Connection,
+        tracks: impl IntoIterator<Item = &'a mut Track>,
+    ) -> rusqlite::Result<()> {
+        let mut stmt = conn
+            .prepare_cached("SELECT start_ms, end_ms FROM track_segments WHERE track_id = ?1")?;
+        for track in tracks {
+            let found = stmt
+                .query_row(params![track.id], |row| {
+                    Ok(Segment {
+                        start_ms: row.get(0)?,
+                        end_ms: row.get(1)?,
+                    })
+                })
+                .optional()?;
+            if let PlaybackSource::Local { segment, .. } = &mut track.source {
+                *segment = found;
+            }
+        }
+        Ok(())
+    }
+
+    /// FTS5 query matching every word of `query`, the last one as a prefix
+    /// so results follow typing. Words are quoted so punctuation in them
+    /// isn't read as query syntax.
+    fn lyrics_match_query(query: &str) -> Option<String> {
+        let terms: Vec<String> = query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect();
+        if terms.is_empty() {
+            return None;
+        }
+        Some(format!("{}*", terms.join(" ")))
+    }
+
+    /// Tracks whose lyrics match `query`, best match first, each with an
+    /// excerpt around the matched words
+    pub fn search_lyrics(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<(Track, String)>, Box<dyn Error + Send + Sync>> {
+        let Some(match_query) = Self::lyrics_match_query(query) else {
+            return Ok(Vec::new());
+        };
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT t.id, t.title, t.artist, t.album, t.duration, t.track_number,
+                    t.disc_number, t.release_year, t.genre, t.file_path, t.file_format,
+                    t.file_size, t.artwork_id, t.artwork_path, t.explicit, t.track_total,
+                    snippet(lyrics_fts, 0, ?2, ?3, '…', 12)
+             FROM lyrics_fts
+             JOIN lyrics l ON l.rowid = lyrics_fts.rowid
+             JOIN tracks t ON t.id = l.track_id
+             WHERE lyrics_fts MATCH ?1
+             ORDER BY rank
+             LIMIT ?4",
+        )?;
+        let mut matches: Vec<(Track, String)> = stmt
+            .query_map(
+                params![
+                    match_query,
+                    LyricsMatch::HIGHLIGHT_START.to_string(),
+                    LyricsMatch::HIGHLIGHT_END.to_string(),
+                    limit as i64
+                ],
+                |row| Ok((Self::track_from_row(row)?, row.get(16)?)),
+            )?
+            .filter_map(Result::ok)
+            .collect();
+        Self::attach_thumbnails(&conn, matches.iter_mut().map(|(track, _)| &mut track.artwork))?;
+        Self::attach_segments(&conn, matches.iter_mut().map(|(track, _)| track))?;
+
+        Ok(matches)
+    }
+
+    /// Fill in thumbnails for artwork read from a row, which only carries
+    /// the ID. Each distinct image is loaded once.
+    fn attach_thumbnails<'a>(
+        conn: &rusqlite::Connection,
+        artworks: impl IntoIterator<Item = &'a mut Artwork>,
+    ) -> rusqlite::Result<()> {
+        let mut stmt =
+            conn.prepare_cached("SELECT small, medium, large FROM thumbnails WHERE id = ?1")?;
+        let mut loaded: HashMap<String, Option<Thumbnails>> = HashMap::new();
+
+        for artwork in artworks {
+            let Some(id) = artwork.id.clone() else {
+                continue;
+            };
+            if !loaded.contains_key(&id) {
+                let thumbnails = stmt
+                    .query_row(params![id], |row| {
+                        Ok(Thumbnails {
+                            small: row.get(0)?,
+                            medium: row.get(1)?,
+                            large: row.get(2)?,
+                        })
+                    })
+                    .optional()?;
+                loaded.insert(id.clone(), thumbnails);
+            }
+            artwork.thumbnails = loaded[&id].clone();
+        }
+        Ok(())
+    }
+
+    fn track_from_row(row: &rusqlite::Row) -> rusqlite::Result<Track> {
+        let path = PathBuf::from(row.get::<_, String>(9)?);
+        Ok(Track {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            artist: row.get(2)?,
+            album: row.get(3)?,
+            duration: row.get(4)?,
+            track_number: row.get(5)?,
+            track_total: row.get(15)?,
+            disc_number: row.get(6)?,
+            release_year: row.get(7)?,
+            genre: row.get(8)?,
+            explicit: row.get(14)?,
+            lyrics: None,
+            release_type: None,
+            artwork: Artwork {
+                id: row.get(12)?,
+                thumbnails: None,
+                full_art: match row.get::<_, Option<String>>(13)? {
+                    Some(path) if !path.is_empty() => ArtworkSource::Local {
+                        path: Path::new(&path).to_path_buf(),
+                    },
+                    _ => ArtworkSource::None,
+                },
+            },
+            source: PlaybackSource::Local {
+                file_format: row.get(10)?,
+                file_size: row.get(11)?,
+                path,
+                segment: None,
+            },
+            // Scans and the file watcher drop tracks whose files are gone
+            availability: Availability::Local,
+        })
+    }
+
+    /// Release type from a nullable column; untagged albums are albums
+    fn release_type(row: &rusqlite::Row, index: usize) -> rusqlite::Result<ReleaseType> {
+        Ok(row
+            .get::<_, Option<String>>(index)?
+            .and_then(|value| ReleaseType::from_tag(&value))
+            .unwrap_or_default())
+    }
+
+    fn album_from_row(row: &rusqlite::Row) -> rusqlite::Result<Album> {
+        Ok(Album {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            artist: row.get(2)?,
+            year: row.get(3)?,
+            release_type: Self::release_type(row, 6)?,
+            art_url: None,
+            tracks: Vec::new(),
+            sources: Vec::new(),
+            artwork: Some(Artwork {
+                id: row.get(4)?,
+                thumbnails: None,
+                full_art: match row.get::<_, Option<String>>(5)? {
+                    Some(path) => ArtworkSource::Local {
+                        path: PathBuf::from(path),
+                    },
+                    None => ArtworkSource::None,
+                },
+            }),
+        })
+    }
+
+    fn artist_from_row(row: &rusqlite::Row) -> rusqlite::Result<Artist> {
+        Ok(Artist {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            albums: Vec::new(),
+            sources: Vec::new(),
+            artwork: Some(Artwork {
+                id: row.get(2)?,
+                thumbnails: None,
+                full_art: match row.get::<_, Option<String>>(3)? {
+                    Some(path) => ArtworkSource::Local {
+                        path: PathBuf::from(path),
+                    },
+                    None => ArtworkSource::None,
+                },
+            }),
+        })
+    }
+
+    /// The album a track was indexed under, looked up from its metadata
+    pub fn find_album(
+        &self,
+        title: &str,
+        artist: &str,
+    ) -> Result<Option<Album>, Box<dyn Error + Send + Sync>> {
+        let conn = self.get_connection()?;
+        let mut album = conn
+            .query_row(
+                &format!("{} WHERE a.title = ?1 AND a.artist = ?2", ALBUM_SELECT),
+                params![title, artist],
+                Self::album_from_row,
+            )
+            .optional()?;
+        Self::attach_thumbnails(&conn, album.as_mut().and_then(|a| a.artwork.as_mut()))?;
+
+        Ok(album)
+    }
+
+    /// The artist a track was indexed under, looked up from its metadata
+    pub fn find_artist(&self, name: &str) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>> {
+        let conn = self.get_connection()?;
+        let mut artist = conn
+            .query_row(
+                "SELECT id, name, artwork_id, artwork_path FROM artists WHERE name = ?1",
+                params![name],
+                Self::artist_from_row,
+            )
+            .optional()?;
+        Self::attach_thumbnails(&conn, artist.as_mut().and_then(|a| a.artwork.as_mut()))?;
+
+        Ok(artist)
+    }
+
+    pub fn get_album_by_id(
+        &self,
+        id: &str,
+    ) -> Result<Option<Album>, Box<dyn Error + Send + Sync>> {
+        let conn = self.get_connection()?;
+        let mut album = conn
+            .query_row(
+                &format!("{} WHERE a.id = ?1", ALBUM_SELECT),
+                params![id],
+                Self::album_from_row,
+            )
+            .optional()?;
+        Self::attach_thumbnails(&conn, album.as_mut().and_then(|a| a.artwork.as_mut()))?;
+
+        Ok(album)
+    }
+
+    pub fn get_artist_by_id(
+        &self,
+        id: &str,
+    ) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>> {
+        let conn = self.get_connection()?;
+        let mut artist = conn
+            .query_row(
+                "SELECT id, name, artwork_id, artwork_path FROM artists WHERE id = ?1",
+                params![id],
+                Self::artist_from_row,
+            )
+            .optional()?;
+        Self::attach_thumbnails(&conn, artist.as_mut().and_then(|a| a.artwork.as_mut()))?;
+
+        Ok(artist)
+    }
+
+    pub fn get_track_by_id(
+        &self,
+        id: &str,
+    ) -> Result<Option<Track>, Box<dyn Error + Send + Sync>> {
+        let conn = self.get_connection()?;
+        let mut track = conn
+            .query_row(
+                "SELECT id, title, artist, album, duration, track_number, disc_number,
+                        release_year, genre, file_path, file_format, file_size,
+                        artwork_id, artwork_path, explicit, track_total
+                 FROM tracks
+                 WHERE id = ?1",
+                params![id],
+                Self::track_from_row,
+            )
+            .optional()?;
+        Self::attach_thumbnails(&conn, track.as_mut().map(|track| &mut track.artwork))?;
+        Self::attach_segments(&conn, track.as_mut())?;
+
+        Ok(track)
+    }
+
+    /// Tracks on an album in disc and track order
+    pub fn get_album_tracks(
+        &self,
+        album_id: &str,
+    ) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT t.id, t.title, t.artist, t.album, t.duration, t.track_number, t.disc_number,
+                    t.release_year, t.genre, t.file_path, t.file_format, t.file_size,
+                    t.artwork_id, t.artwork_path, t.explicit, t.track_total
+             FROM tracks t
+             JOIN albums a ON t.album = a.title AND t.artist = a.artist
+             WHERE a.id = ?1
+             ORDER BY t.disc_number, t.track_number, t.title",
+        )?;
+        let mut tracks: Vec<Track> = stmt
+            .query_map(params![album_id], Self::track_from_row)?
+            .filter_map(Result::ok)
+            .collect();
+        Self::attach_thumbnails(&conn, tracks.iter_mut().map(|track| &mut track.artwork))?;
+        Self::attach_segments(&conn, tracks.iter_mut())?;
+
+        Ok(tracks)
+    }
+
+    /// An artist's albums, oldest first
+    pub fn get_artist_albums(
+        &self,
+        artist_id: &str,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "{} JOIN artists ar ON a.artist = ar.name
+             WHERE ar.id = ?1 AND a.title != 'Unknown Album'
+             ORDER BY a.year, a.title",
+            ALBUM_SELECT
+        ))?;
+        let mut albums: Vec<Album> = stmt
+            .query_map(params![artist_id], Self::album_from_row)?
+            .filter_map(Result::ok)
+            .collect();
+        Self::attach_thumbnails(&conn, albums.iter_mut().filter_map(|a| a.artwork.as_mut()))?;
+
+        Ok(albums)
+    }
+
+    /// Decades with albums released in them, such as 1990, oldest first
+    pub fn album_decades(&self) -> Result<Vec<u32>, Box<dyn Error + Send + Sync>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT (year / 10) * 10 FROM albums
+             WHERE year > 0 AND title != 'Unknown Album'
+             ORDER BY 1",
+        )?;
+        let decades = stmt
+            .query_map([], |row| row.get(0))?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(decades)
+    }
+
+    /// Albums released from `from` to `to`, both included, oldest first
+    pub fn albums_between(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE a.year BETWEEN ?1 AND ?2 AND a.title != 'Unknown Album'
+             ORDER BY a.year, a.artist, a.title",
+            ALBUM_SELECT
+        ))?;
+        let mut albums: Vec<Album> = stmt
+            .query_map(params![from, to], Self::album_from_row)?
+            .filter_map(Result::ok)
+            .collect();
+        Self::attach_thumbnails(&conn, albums.iter_mut().filter_map(|a| a.artwork.as_mut()))?;
+
+        Ok(albums)
+    }
+
     pub fn cleanup_database(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
         let mut conn = self.pool.get()?;
         let tx = conn.transaction()?;
@@ -0,0 +1,160 @@
+//! Look-ahead peak limiter for the playback chain. Audio comes out
+//! LOOKAHEAD late, so the gain is already down by the time a peak passes
+//! instead of the peak being cut off as it arrives, and it comes back up
+//! over RELEASE so the limiting isn't heard as pumping.
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_audio as gst_audio;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::time::Duration;
+
+// Peaks are held just below full scale
+const CEILING_DB: f64 = -1.0;
+
+// How far ahead peaks are seen, and how long the gain takes to go down
+const LOOKAHEAD: Duration = Duration::from_millis(5);
+
+// Time constant of the gain coming back up after a peak
+const RELEASE: Duration = Duration::from_millis(150);
+
+/// Format the limiter works in, for a capsfilter to put out
+pub(super) const CAPS: &str = "audio/x-raw,format=F32LE,layout=interleaved";
+
+/// Limit the audio coming out of `element`, which must put out CAPS
+pub(super) fn attach(element: &gst::Element) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let pad = element
+        .static_pad("src")
+        .ok_or("The limiter stage has no output")?;
+    let state: Mutex<Option<Limiter>> = Mutex::new(None);
+    pad.add_probe(
+        gst::PadProbeType::BUFFER
+            | gst::PadProbeType::EVENT_DOWNSTREAM
+            | gst::PadProbeType::EVENT_FLUSH,
+        move |pad, info| {
+            let mut state = state.lock();
+            match &mut info.data {
+                // After a seek, the delayed audio from before it is dropped
+                Some(gst::PadProbeData::Event(event))
+                    if event.type_() == gst::EventType::FlushStop =>
+                {
+                    *state = None;
+                }
+                Some(gst::PadProbeData::Buffer(buffer)) => {
+                    let Some(audio) = pad
+                        .current_caps()
+                        .and_then(|caps| gst_audio::AudioInfo::from_caps(&caps).ok())
+                    else {
+                        return gst::PadProbeReturn::Ok;
+                    };
+                    let (rate, channels) = (audio.rate(), audio.channels() as usize);
+                    let limiter = match state.take() {
+                        Some(limiter) if limiter.fits(rate, channels) => state.insert(limiter),
+                        _ => state.insert(Limiter::new(rate, channels)),
+                    };
+                    if let Ok(mut map) = buffer.make_mut().map_writable() {
+                        limiter.process(map.as_mut_slice());
+                    }
+                }
+                _ => {}
+            }
+            gst::PadProbeReturn::Ok
+        },
+    );
+    Ok(())
+}
+
+/// The gain is the lowest any frame in the look-ahead window needs to stay
+/// under the ceiling, released slowly and then averaged over the window,
+/// so it has ramped all the way down by the time that frame comes out
+struct Limiter {
+    rate: u32,
+    channels: usize,
+    ceiling: f32,
+    lookahead: usize,
+    // Share of the way back to unity gain covered each frame
+    release: f64,
+    frame: u64,
+    // Samples waiting to come out, lookahead - 1 frames of them
+    delay: VecDeque<f32>,
+    // Gains needed in the window by frame, rising from the front, so the
+    // front is the lowest
+    needed: VecDeque<(u64, f64)>,
+    released: f64,
+    // The window's released gains and their sum
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Limiter {
+    fn new(rate: u32, channels: usize) -> Self {
+        let lookahead = ((LOOKAHEAD.as_secs_f64() * rate as f64) as usize).max(1);
+        Self {
+            rate,
+            channels,
+            ceiling: 10f64.powf(CEILING_DB / 20.0) as f32,
+            lookahead,
+            release: 1.0 - (-1.0 / (RELEASE.as_secs_f64() * rate as f64)).exp(),
+            frame: 0,
+            delay: std::iter::repeat(0.0)
+                .take((lookahead - 1) * channels)
+                .collect(),
+            needed: VecDeque::new(),
+            released: 1.0,
+            window: std::iter::repeat(1.0).take(lookahead).collect(),
+            sum: lookahead as f64,
+        }
+    }
+
+    fn fits(&self, rate: u32, channels: usize) -> bool {
+        self.rate == rate && self.channels == channels
+    }
+
+    /// Limit interleaved little-endian f32 `samples` in place. What comes
+    /// out is the audio from the look-ahead before.
+    fn process(&mut self, samples: &mut [u8]) {
+        if self.channels == 0 {
+            return;
+        }
+        for frame in samples.chunks_exact_mut(4 * self.channels) {
+            let peak = frame
+                .chunks_exact(4)
+                .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            let needed = if peak > self.ceiling {
+                (self.ceiling / peak) as f64
+            } else {
+                1.0
+            };
+
+            while self.needed.back().is_some_and(|&(_, gain)| gain >= needed) {
+                self.needed.pop_back();
+            }
+            self.needed.push_back((self.frame, needed));
+            let window_start = (self.frame + 1).saturating_sub(self.lookahead as u64);
+            while self
+                .needed
+                .front()
+                .is_some_and(|&(frame, _)| frame < window_start)
+            {
+                self.needed.pop_front();
+            }
+            let lowest = self.needed.front().map_or(1.0, |&(_, gain)| gain);
+
+            self.released = lowest.min(self.released + (1.0 - self.released) * self.release);
+            self.sum += self.released - self.window.pop_front().unwrap_or(1.0);
+            self.window.push_back(self.released);
+            let gain = (self.sum / self.lookahead as f64) as f32;
+            self.frame += 1;
+
+            for sample in frame.chunks_exact_mut(4) {
+                let value = f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]);
+                self.delay.push_back(value);
+                let out = self.delay.pop_front().unwrap_or_default() * gain;
+                sample.copy_from_slice(&out.to_le_bytes());
+            }
+        }
+    }
+}
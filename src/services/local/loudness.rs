@@ -0,0 +1,204 @@
+//! Loudness analysis of local files: ReplayGain values from GStreamer's
+//! rganalysis element, and EBU R 128 loudness and true peak from
+//! ebur128level. Decoding a whole file takes a while, so this is meant for
+//! background jobs.
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::error::Error;
+use std::path::Path;
+
+/// ReplayGain values for one track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// Gain in dB to bring the track to the reference level
+    pub track_gain: f64,
+    /// Peak sample, where 1.0 is full scale
+    pub track_peak: f64,
+}
+
+/// Loudness level tracks are evened out to, the ReplayGain 2.0 reference
+pub const REFERENCE_LUFS: f64 = -18.0;
+
+/// EBU R 128 measurements of one track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessMeasurement {
+    /// Integrated loudness over the whole track, in LUFS
+    pub integrated_lufs: f64,
+    /// Highest true peak of any channel, in dBTP
+    pub true_peak_dbtp: f64,
+}
+
+impl LoudnessMeasurement {
+    /// Gain in dB that brings the track to REFERENCE_LUFS
+    pub fn normalization_gain(&self) -> f64 {
+        REFERENCE_LUFS - self.integrated_lufs
+    }
+
+    /// dB the true peak goes over full scale once the normalization gain is
+    /// applied, or None when it stays under
+    pub fn clipping(&self) -> Option<f64> {
+        let over = self.true_peak_dbtp + self.normalization_gain();
+        (over > 0.0).then_some(over)
+    }
+}
+
+/// Decode the file at `path` and measure its loudness. Blocks until the
+/// whole file has been decoded.
+pub fn analyze_loudness(path: &Path) -> Result<Loudness, Box<dyn Error + Send + Sync>> {
+    gst::init()?;
+    let analysis = gst::ElementFactory::make("rganalysis").build()?;
+    let pipeline = decode_into(path, &analysis)?;
+    pipeline.set_state(gst::State::Playing)?;
+    let result = wait_for_loudness(&pipeline);
+    pipeline.set_state(gst::State::Null)?;
+    result
+}
+
+/// Decode the file at `path` and measure its integrated loudness and true
+/// peak. Blocks until the whole file has been decoded.
+pub fn measure_loudness(path: &Path) -> Result<LoudnessMeasurement, Box<dyn Error + Send + Sync>> {
+    gst::init()?;
+    let meter = gst::ElementFactory::make("ebur128level")
+        .build()
+        .map_err(|_| "Measuring loudness needs the ebur128level GStreamer element")?;
+    let pipeline = decode_into(path, &meter)?;
+    pipeline.set_state(gst::State::Playing)?;
+    let result = wait_for_measurement(&pipeline);
+    pipeline.set_state(gst::State::Null)?;
+    result
+}
+
+/// Pipeline that decodes the file at `path` through `analysis` as fast as
+/// it can
+pub(super) fn decode_into(
+    path: &Path,
+    analysis: &gst::Element,
+) -> Result<gst::Pipeline, Box<dyn Error + Send + Sync>> {
+    // No clock, so the file is analyzed as fast as it decodes
+    let sink = gst::ElementFactory::make("fakesink")
+        .property("sync", false)
+        .build()?;
+    decode_through(path, &[analysis, &sink])
+}
+
+/// Pipeline that decodes the file at `path` through `stages`, the last of
+/// which is the sink
+pub(super) fn decode_through(
+    path: &Path,
+    stages: &[&gst::Element],
+) -> Result<gst::Pipeline, Box<dyn Error + Send + Sync>> {
+    let location = path.to_str().ok_or("Path is not valid UTF-8")?;
+    let pipeline = gst::Pipeline::new();
+    let src = gst::ElementFactory::make("filesrc")
+        .property("location", location)
+        .build()?;
+    let decode = gst::ElementFactory::make("decodebin").build()?;
+    let convert = gst::ElementFactory::make("audioconvert").build()?;
+    let resample = gst::ElementFactory::make("audioresample").build()?;
+
+    let chain: Vec<&gst::Element> = [&convert, &resample]
+        .into_iter()
+        .chain(stages.iter().copied())
+        .collect();
+    pipeline.add_many([&src, &decode])?;
+    pipeline.add_many(&chain)?;
+    src.link(&decode)?;
+    gst::Element::link_many(&chain)?;
+
+    // decodebin only adds its pads once it knows what the file holds
+    let convert_weak = convert.downgrade();
+    decode.connect_pad_added(move |_, pad| {
+        let Some(sink_pad) = convert_weak.upgrade().and_then(|c| c.static_pad("sink")) else {
+            return;
+        };
+        let is_audio = pad
+            .current_caps()
+            .and_then(|caps| caps.structure(0).map(|s| s.name().starts_with("audio/")))
+            .unwrap_or(false);
+        if is_audio && !sink_pad.is_linked() {
+            if let Err(e) = pad.link(&sink_pad) {
+                eprintln!("Failed to link decoded audio: {:?}", e);
+            }
+        }
+    });
+
+    Ok(pipeline)
+}
+
+fn wait_for_loudness(pipeline: &gst::Pipeline) -> Result<Loudness, Box<dyn Error + Send + Sync>> {
+    let bus = pipeline.bus().ok_or("Pipeline has no bus")?;
+    let mut track_gain = None;
+    let mut track_peak = None;
+
+    for msg in bus.iter_timed(gst::ClockTime::NONE) {
+        match msg.view() {
+            // rganalysis sends its results as tags just before the end
+            gst::MessageView::Tag(tag) => {
+                let tags = tag.tags();
+                if let Some(gain) = tags.get::<gst::tags::TrackGain>() {
+                    track_gain = Some(gain.get());
+                }
+                if let Some(peak) = tags.get::<gst::tags::TrackPeak>() {
+                    track_peak = Some(peak.get());
+                }
+            }
+            gst::MessageView::Eos(..) => break,
+            gst::MessageView::Error(err) => return Err(err.error().into()),
+            _ => {}
+        }
+    }
+
+    match (track_gain, track_peak) {
+        (Some(track_gain), Some(track_peak)) => Ok(Loudness {
+            track_gain,
+            track_peak,
+        }),
+        _ => Err("Analysis finished without a result".into()),
+    }
+}
+
+fn wait_for_measurement(
+    pipeline: &gst::Pipeline,
+) -> Result<LoudnessMeasurement, Box<dyn Error + Send + Sync>> {
+    let bus = pipeline.bus().ok_or("Pipeline has no bus")?;
+    let mut integrated_lufs = None;
+    let mut true_peak = None;
+
+    for msg in bus.iter_timed(gst::ClockTime::NONE) {
+        match msg.view() {
+            // ebur128level reports the loudness so far every second, so the
+            // last report covers the whole track
+            gst::MessageView::Element(element) => {
+                let Some(s) = element.structure().filter(|s| s.name() == "ebur128-level") else {
+                    continue;
+                };
+                if let Ok(loudness) = s.get::<f64>("global-loudness") {
+                    integrated_lufs = Some(loudness);
+                }
+                // One linear peak per channel
+                if let Ok(peaks) = s.get::<gst::Array>("true-peak") {
+                    let peak = peaks
+                        .iter()
+                        .filter_map(|value| value.get::<f64>().ok())
+                        .fold(0.0, f64::max);
+                    true_peak = Some(true_peak.map_or(peak, |highest: f64| highest.max(peak)));
+                }
+            }
+            gst::MessageView::Eos(..) => break,
+            gst::MessageView::Error(err) => return Err(err.error().into()),
+            _ => {}
+        }
+    }
+
+    match (integrated_lufs, true_peak) {
+        // Silence measures as minus infinity
+        (Some(integrated_lufs), Some(true_peak)) if integrated_lufs.is_finite() => {
+            Ok(LoudnessMeasurement {
+                integrated_lufs,
+                true_peak_dbtp: 20.0 * true_peak.max(f64::MIN_POSITIVE).log10(),
+            })
+        }
+        _ => Err("Measuring finished without a result".into()),
+    }
+}
@@ -1,16 +1,27 @@
 mod audio;
+mod chapters;
 mod database;
+mod limiter;
+mod loudness;
 mod scanner;
+mod tempo;
+mod thumbnails;
+mod transcode;
 mod watcher;
+mod waveform;
 
 use super::error::ServiceError;
-use super::models::{Artwork, ArtworkSource, PlaybackSource, SearchWeights};
+use super::models::{Artwork, ArtworkSource, PlaybackSource, RefreshMode, SearchWeights};
 use super::traits::MusicProvider;
-use crate::services::models::{Album, Artist, PlayableItem, SearchResults, Track};
+use crate::services::models::{
+    Album, Artist, LibraryChange, LyricsMatch, MovedFiles, MovedTrack, PlayableItem,
+    SearchResults, Track,
+};
 
-use crate::services::local::database::Database;
-use crate::services::local::scanner::FileScanner;
-use crate::services::local::watcher::{FileEvent, FileWatcher};
+use crate::services::local::database::{Database, FileStamp};
+use crate::services::file_naming;
+use crate::services::resources;
+use crate::services::writeback::TagChange;
 use async_trait::async_trait;
 use chrono::Utc;
 use crossbeam_channel::RecvTimeoutError;
@@ -23,52 +34,80 @@ use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use rayon::prelude::*;
 use rusqlite::{params, OptionalExtension};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use symphonia::core::codecs::CodecParameters;
 use symphonia::core::formats::{FormatOptions, FormatReader};
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{broadcast, mpsc, RwLock};
 
-pub use audio::LocalAudioBackend;
+pub use audio::{missing_elements, LocalAudioBackend, PipelineKind};
+pub use chapters::set_split_chapters;
+pub use loudness::{
+    analyze_loudness, measure_loudness, Loudness, LoudnessMeasurement, REFERENCE_LUFS,
+};
+pub use scanner::FileScanner;
+pub use tempo::analyze_tempo;
+pub use thumbnails::artwork_file;
+pub use transcode::export_snippet;
+pub use watcher::{FileEvent, FileWatcher};
+pub use waveform::{waveform_peaks, PEAK_INTERVAL_MS};
 
 #[derive(Debug, Clone)]
 pub struct LocalMusicProvider {
-    music_dir: PathBuf,
+    music_dirs: Vec<PathBuf>,
     db: Arc<RwLock<Database>>,
     event_sender: mpsc::Sender<FileEvent>,
+    changes: broadcast::Sender<LibraryChange>,
+    // Kept for as long as the provider, as dropping one stops it watching
+    _watchers: Arc<Vec<FileWatcher>>,
 }
 
+// Changes beyond this many unread by a subscriber are dropped for it
+const CHANGE_CAPACITY: usize = 64;
+
 impl LocalMusicProvider {
-    pub async fn new(music_dir: PathBuf) -> Result<Self, Box<dyn Error + Send + Sync>> {
+    pub async fn new(music_dirs: Vec<PathBuf>) -> Result<Self, Box<dyn Error + Send + Sync>> {
         println!(
-            "Initializing LocalMusicProvider with directory: {:?}",
-            music_dir
+            "Initializing LocalMusicProvider with directories: {:?}",
+            music_dirs
         );
 
         // Create channels for file events
         let (event_sender, mut event_receiver) = mpsc::channel(100);
 
-        // Create database and watcher
+        // Create database and watchers. A folder that can't be watched is
+        // still scanned, so losing access to one doesn't empty the library
         let db = Arc::new(RwLock::new(Database::new()?));
-        let _watcher = FileWatcher::new(music_dir.clone(), event_sender.clone())?;
+        let (changes, _) = broadcast::channel(CHANGE_CAPACITY);
+        let mut watchers = Vec::with_capacity(music_dirs.len());
+        for music_dir in &music_dirs {
+            match FileWatcher::new(music_dir.clone(), event_sender.clone()) {
+                Ok(watcher) => watchers.push(watcher),
+                Err(e) => eprintln!("Failed to watch {:?}: {}", music_dir, e),
+            }
+        }
 
         let provider = Self {
-            music_dir: music_dir.clone(),
+            music_dirs: music_dirs.clone(),
             db: db.clone(),
             event_sender,
+            changes: changes.clone(),
+            _watchers: Arc::new(watchers),
         };
 
         // Start background event processor
         let db_clone = db.clone();
+        let changes_clone = changes.clone();
         tokio::spawn(async move {
             println!("Starting file event processor");
             while let Some(event) = event_receiver.recv().await {
-                Self::handle_file_event(&event, &db_clone).await;
+                Self::handle_file_event(&event, &db_clone, &changes_clone).await;
             }
         });
 
@@ -76,39 +115,164 @@ impl LocalMusicProvider {
         let db_clone = db.clone();
         tokio::spawn(async move {
             println!("Starting music directory scan...");
-            if let Ok(files) = FileScanner::scan_directory(&music_dir) {
-                println!("Found {} music files", files.len());
-                Self::process_files_batch(&files, &db_clone).await;
-            }
+            let files = Self::scan_directories(&music_dirs);
+            println!("Found {} music files", files.len());
+            Self::process_files_batch(&files, &db_clone, &changes).await;
         });
 
         Ok(provider)
     }
 
     pub async fn rescan_library(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        println!("Rescanning music directory: {:?}", self.music_dir);
+        println!("Rescanning music directories: {:?}", self.music_dirs);
+        let started = SystemTime::now();
 
         // Scan files
-        let files = FileScanner::scan_directory(&self.music_dir)?;
+        let files = Self::scan_directories(&self.music_dirs);
         println!("Found {} music files", files.len());
+        let indexed = self.db.read().await.get_indexed_files()?;
 
         // Process files in background
-        Self::process_files_batch(&files, &self.db).await;
+        Self::process_files_batch(&files, &self.db, &self.changes).await;
+        self.remove_stale_tracks(&files, &indexed).await;
+        self.evict_unused_artwork(started).await;
         println!("Rescan complete");
 
         Ok(())
     }
 
-    async fn handle_file_event(event: &FileEvent, db: &Arc<RwLock<Database>>) {
+    /// Only process files that are new or whose size or modification time
+    /// changed since they were indexed, and drop tracks whose files are gone
+    pub async fn incremental_scan(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        println!("Incremental scan of music directories: {:?}", self.music_dirs);
+        let started = SystemTime::now();
+
+        let files = Self::scan_directories(&self.music_dirs);
+        let indexed = self.db.read().await.get_indexed_files()?;
+
+        let changed: Vec<PathBuf> = files
+            .iter()
+            .filter(|path| match (indexed.get(*path), std::fs::metadata(path)) {
+                (Some(stamp), Ok(metadata)) => FileStamp::of(&metadata) != *stamp,
+                _ => true,
+            })
+            .cloned()
+            .collect();
+        println!("Found {} new or changed music files", changed.len());
+
+        Self::process_files_batch(&changed, &self.db, &self.changes).await;
+        self.remove_stale_tracks(&files, &indexed).await;
+        self.evict_unused_artwork(started).await;
+        println!("Incremental scan complete");
+
+        Ok(())
+    }
+
+    /// Music files in all library folders. Folders that can't be read are
+    /// skipped with a warning rather than failing the whole scan.
+    fn scan_directories(music_dirs: &[PathBuf]) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for music_dir in music_dirs {
+            match FileScanner::scan_directory(music_dir) {
+                Ok(found) => files.extend(found),
+                Err(e) => eprintln!("Failed to scan {:?}: {}", music_dir, e),
+            }
+        }
+        files
+    }
+
+    async fn remove_stale_tracks(
+        &self,
+        files: &[PathBuf],
+        indexed: &HashMap<PathBuf, FileStamp>,
+    ) {
+        let found: HashSet<&PathBuf> = files.iter().collect();
+        let db = self.db.write().await;
+        let mut change = LibraryChange::default();
+
+        for path in indexed.keys().filter(|path| !found.contains(path)) {
+            let ids = db.track_ids_at(path).unwrap_or_default();
+            match db.remove_track_by_path(path) {
+                Ok(()) => change.removed.extend(ids),
+                Err(e) => eprintln!("Error removing stale track {:?}: {}", path, e),
+            }
+        }
+        Self::notify(&self.changes, change);
+    }
+
+    /// Drop cached art that no track uses any more, once the scan that
+    /// began at `started` has finished
+    async fn evict_unused_artwork(&self, started: SystemTime) {
+        let in_use = match self.db.read().await.artwork_paths() {
+            Ok(paths) => paths,
+            Err(e) => {
+                eprintln!("Failed to list artwork in use: {}", e);
+                return;
+            }
+        };
+        let result =
+            tokio::task::spawn_blocking(move || thumbnails::evict_unused(&in_use, started)).await;
+        if let Err(e) = result {
+            eprintln!("Artwork eviction stopped: {}", e);
+        }
+    }
+
+    /// Tell subscribers about `change`, unless nothing changed
+    fn notify(changes: &broadcast::Sender<LibraryChange>, change: LibraryChange) {
+        if !change.is_empty() {
+            // Nobody listening isn't an error
+            let _ = changes.send(change);
+        }
+    }
+
+    /// Remove folders that moving files out of left empty, up to the
+    /// library folder they're in
+    fn remove_emptied_folders(&self, moved: &[(PathBuf, PathBuf)]) {
+        for (from, _) in moved {
+            let Some(root) = self.music_dirs.iter().find(|root| from.starts_with(root)) else {
+                continue;
+            };
+            let mut dir = from.parent();
+            let inside_root = |dir: &&Path| *dir != root.as_path() && dir.starts_with(root);
+            while let Some(current) = dir.filter(inside_root) {
+                // Only succeeds while the folder is empty
+                if std::fs::remove_dir(current).is_err() {
+                    break;
+                }
+                dir = current.parent();
+            }
+        }
+    }
+
+    async fn handle_file_event(
+        event: &FileEvent,
+        db: &Arc<RwLock<Database>>,
+        changes: &broadcast::Sender<LibraryChange>,
+    ) {
         match event {
             FileEvent::Created(path) | FileEvent::Modified(path) => {
                 if FileScanner::is_music_file_public(path) {
                     tokio::task::yield_now().await;
                     if let Ok(track) = FileScanner::process_file(path).await {
+                        let tracks = chapters::split(track);
                         let mut db = db.write().await;
-                        if let Err(e) = db.insert_track(&track) {
+                        let before = db.track_ids_at(path).unwrap_or_default();
+                        if let Err(e) = db.replace_file_tracks(path, &tracks) {
                             eprintln!("Error inserting track: {}", e);
+                            return;
                         }
+                        // A file may gain or lose chapters
+                        let after: Vec<String> = tracks.into_iter().map(|t| t.id).collect();
+                        let removed = before.iter().filter(|id| !after.contains(id)).cloned();
+                        let removed = removed.collect();
+                        let (updated, added) =
+                            after.into_iter().partition(|id| before.contains(id));
+                        let change = LibraryChange {
+                            added,
+                            removed,
+                            updated,
+                        };
+                        Self::notify(changes, change);
                     }
                 }
             }
@@ -120,34 +284,70 @@ impl LocalMusicProvider {
                     )
                 }) {
                     let mut db = db.write().await;
-                    if let Err(e) = db.remove_track_by_path(path) {
-                        eprintln!("Error removing track: {}", e);
+                    let removed = db.track_ids_at(path).unwrap_or_default();
+                    match db.remove_track_by_path(path) {
+                        Ok(()) => Self::notify(
+                            changes,
+                            LibraryChange {
+                                removed,
+                                ..Default::default()
+                            },
+                        ),
+                        Err(e) => eprintln!("Error removing track: {}", e),
                     }
                 }
             }
         }
     }
 
-    async fn process_files_batch(files: &[PathBuf], db: &Arc<RwLock<Database>>) {
-        for chunk in files.chunks(5) {
+    async fn process_files_batch(
+        files: &[PathBuf],
+        db: &Arc<RwLock<Database>>,
+        changes: &broadcast::Sender<LibraryChange>,
+    ) {
+        // The batch size is looked up each time round, as the scan priority
+        // can change partway through
+        let mut remaining = files;
+        while !remaining.is_empty() {
+            let size = resources::scan_batch_size().min(remaining.len());
+            let (chunk, rest) = remaining.split_at(size);
+            remaining = rest;
             let mut tracks = Vec::with_capacity(chunk.len());
             
             for file in chunk {
                 tokio::task::yield_now().await;
                 if let Ok(track) = FileScanner::process_file(file).await {
-                    tracks.push(track);
+                    tracks.extend(chapters::split(track));
                 }
             }
 
             if !tracks.is_empty() {
                 let mut db = db.write().await;
-                if let Err(e) = db.batch_insert_tracks(&tracks) {
-                    eprintln!("Error inserting tracks batch: {}", e);
+                let ids = tracks.iter().map(|track| track.id.as_str());
+                let known = db.known_track_ids(ids).unwrap_or_default();
+                match db.batch_insert_tracks(&tracks) {
+                    Ok(()) => {
+                        let (updated, added) = tracks
+                            .into_iter()
+                            .map(|track| track.id)
+                            .partition(|id| known.contains(id));
+                        let change = LibraryChange {
+                            added,
+                            updated,
+                            ..Default::default()
+                        };
+                        Self::notify(changes, change);
+                    }
+                    Err(e) => eprintln!("Error inserting tracks batch: {}", e),
                 }
             }
             
-            // Yield to allow other tasks to run
-            tokio::task::yield_now().await;
+            // Yield to allow other tasks to run, and back off on slow devices
+            // or at a lower scan priority
+            match resources::background_pause() {
+                Some(pause) => tokio::time::sleep(pause).await,
+                None => tokio::task::yield_now().await,
+            }
         }
     }
 }
@@ -232,6 +432,191 @@ impl MusicProvider for LocalMusicProvider {
                 .collect(),
             albums,
             artists,
+            versions: HashMap::new(),
         })
     }
+
+    async fn search_lyrics(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<LyricsMatch>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        let matches = db.search_lyrics(query, limit)?;
+
+        Ok(matches
+            .into_iter()
+            .map(|(track, snippet)| LyricsMatch {
+                item: PlayableItem {
+                    track,
+                    provider: "local".to_string(),
+                    added_at: chrono::Utc::now(),
+                },
+                snippet,
+            })
+            .collect())
+    }
+
+    async fn find_album(
+        &self,
+        track: &Track,
+    ) -> Result<Option<Album>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        db.find_album(&track.album, &track.artist)
+    }
+
+    async fn find_artist(
+        &self,
+        track: &Track,
+    ) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        db.find_artist(&track.artist)
+    }
+
+    async fn get_track(&self, id: &str) -> Result<Option<Track>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        db.get_track_by_id(id)
+    }
+
+    async fn get_album(&self, id: &str) -> Result<Option<Album>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        db.get_album_by_id(id)
+    }
+
+    async fn get_artist(&self, id: &str) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        db.get_artist_by_id(id)
+    }
+
+    async fn get_album_tracks(
+        &self,
+        album_id: &str,
+    ) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        db.get_album_tracks(album_id)
+    }
+
+    async fn get_artist_albums(
+        &self,
+        artist_id: &str,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        db.get_artist_albums(artist_id)
+    }
+
+    async fn album_decades(&self) -> Result<Vec<u32>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        db.album_decades()
+    }
+
+    async fn albums_from_decade(
+        &self,
+        decade: u32,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.read().await;
+        db.albums_between(decade, decade + 9)
+    }
+
+    fn subscribe_changes(&self) -> Option<broadcast::Receiver<LibraryChange>> {
+        Some(self.changes.subscribe())
+    }
+
+    async fn refresh(&self, mode: RefreshMode) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match mode {
+            RefreshMode::Incremental => self.incremental_scan().await,
+            RefreshMode::Full => self.rescan_library().await,
+        }
+    }
+
+    async fn rescan_files(&self, paths: &[PathBuf]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // The same as the watcher seeing them change
+        for path in paths {
+            let event = if path.exists() {
+                FileEvent::Modified(path.clone())
+            } else {
+                FileEvent::Removed(path.clone())
+            };
+            Self::handle_file_event(&event, &self.db, &self.changes).await;
+        }
+        Ok(())
+    }
+
+    async fn move_files(
+        &self,
+        moves: &[(PathBuf, PathBuf)],
+    ) -> Result<MovedFiles, Box<dyn Error + Send + Sync>> {
+        // Worked out while the index still has the files at their old paths
+        let mut planned = Vec::with_capacity(moves.len());
+        {
+            let db = self.db.read().await;
+            for (from, to) in moves {
+                planned.push((from.clone(), to.clone(), db.tracks_moving(from, to)?));
+            }
+        }
+
+        let count = planned.len();
+        let (moved, error) = tokio::task::spawn_blocking(move || {
+            let mut moved = Vec::with_capacity(planned.len());
+            for (from, to, tracks) in planned {
+                if let Err(e) = file_naming::move_file(&from, &to) {
+                    let error = format!(
+                        "Moved {} of {} files, then couldn't move {}: {}",
+                        moved.len(),
+                        count,
+                        from.display(),
+                        e
+                    );
+                    return (moved, Some(error));
+                }
+                moved.push((from, to, tracks));
+            }
+            (moved, None)
+        })
+        .await?;
+
+        let tracks: Vec<MovedTrack> = moved
+            .iter()
+            .flat_map(|(_, _, tracks)| tracks.iter().cloned())
+            .collect();
+        self.db.write().await.relocate_files(&tracks)?;
+        let mut change = LibraryChange::default();
+        for track in &tracks {
+            if track.new_id == track.old_id {
+                change.updated.push(track.new_id.clone());
+            } else {
+                change.removed.push(track.old_id.clone());
+                change.added.push(track.new_id.clone());
+            }
+        }
+        Self::notify(&self.changes, change);
+        let paths: Vec<(PathBuf, PathBuf)> =
+            moved.into_iter().map(|(from, to, _)| (from, to)).collect();
+        self.remove_emptied_folders(&paths);
+
+        Ok(MovedFiles { tracks, error })
+    }
+
+    async fn edit_tracks(
+        &self,
+        edits: &[(String, Vec<TagChange>)],
+    ) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let db = self.db.write().await;
+        let mut edited = Vec::with_capacity(edits.len());
+        for (id, changes) in edits {
+            match db.edit_track(id, changes) {
+                Ok(true) => edited.push(id.clone()),
+                Ok(false) => {}
+                Err(e) => eprintln!("Failed to edit track {}: {}", id, e),
+            }
+        }
+        drop(db);
+        Self::notify(
+            &self.changes,
+            LibraryChange {
+                updated: edited.clone(),
+                ..Default::default()
+            },
+        );
+        Ok(edited)
+    }
 }
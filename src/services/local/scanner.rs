@@ -1,16 +1,24 @@
-use crate::services::models::{Artwork, ArtworkSource, PlaybackSource, Track};
+use crate::services::local::thumbnails;
+use crate::services::models::{
+    Artwork, ArtworkSource, Availability, PlaybackSource, ReleaseType, Track,
+};
 use sha1::{Digest, Sha1};
 use std::error::Error;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use symphonia::core::formats::{FormatOptions, FormatReader};
 use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
+use symphonia::core::meta::{MetadataOptions, StandardTagKey};
 use symphonia::core::probe::Hint;
 use walkdir::WalkDir;
 
 pub struct FileScanner;
 
+/// ID of the track in the file at `path`, which is where it's known by
+pub(super) fn file_id(path: &Path) -> String {
+    format!("{:x}", Sha1::digest(path.to_str().unwrap_or_default()))
+}
+
 impl FileScanner {
     pub fn scan_directory(path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error + Send + Sync>> {
         println!("Scanning directory: {:?}", path); // Add logging
@@ -54,6 +62,58 @@ impl FileScanner {
         Self::is_music_file(path)
     }
 
+    /// Number and total from a track number tag, which ID3 and MP4 write
+    /// as "5/12"
+    fn parse_position(value: &str) -> (Option<u32>, Option<u32>) {
+        let mut parts = value.split('/').map(|part| part.trim().parse().ok());
+        let number = parts.next().flatten();
+        (number, parts.next().flatten())
+    }
+
+    /// Read a parental advisory tag. iTunes writes ITUNESADVISORY (or the
+    /// MP4 rtng atom) as 1 or 4 for explicit and 2 for clean; other taggers
+    /// use a plain EXPLICIT flag. Returns None for unrelated tags.
+    fn parse_advisory(key: &str, value: &str) -> Option<bool> {
+        // Freeform and TXXX keys carry a prefix, e.g. ----:com.apple.iTunes:ITUNESADVISORY
+        let name = key.rsplit(':').next().unwrap_or(key).to_uppercase();
+        if !matches!(name.as_str(), "ITUNESADVISORY" | "RTNG" | "EXPLICIT") {
+            return None;
+        }
+
+        match value.trim().to_lowercase().as_str() {
+            "1" | "4" | "explicit" | "true" | "yes" => Some(true),
+            "0" | "2" | "clean" | "false" | "no" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Read a release type tag: the MusicBrainz release-group type, which
+    /// taggers write as RELEASETYPE or MUSICBRAINZ_ALBUMTYPE, or a
+    /// compilation flag (TCMP, cpil). Returns None for unrelated tags.
+    fn parse_release_type(
+        key: &str,
+        std_key: Option<StandardTagKey>,
+        value: &str,
+    ) -> Option<ReleaseType> {
+        let name = key.rsplit(':').next().unwrap_or(key).to_uppercase();
+        let is_compilation_flag = std_key == Some(StandardTagKey::Compilation)
+            || matches!(name.as_str(), "COMPILATION" | "TCMP" | "CPIL");
+        if is_compilation_flag {
+            return matches!(value.trim(), "1" | "true" | "yes").then_some(ReleaseType::Compilation);
+        }
+
+        let is_type_tag = std_key == Some(StandardTagKey::MusicBrainzReleaseType)
+            || matches!(
+                name.as_str(),
+                "RELEASETYPE" | "RELEASE_TYPE" | "MUSICBRAINZ_ALBUMTYPE" | "MUSICBRAINZ ALBUM TYPE"
+            );
+        if is_type_tag {
+            ReleaseType::from_tag(value)
+        } else {
+            None
+        }
+    }
+
     pub async fn process_file(path: &Path) -> Result<Track, Box<dyn Error + Send + Sync>> {
         println!("Processing file: {:?}", path);
 
@@ -66,9 +126,7 @@ impl FileScanner {
         }
 
         // Generate a unique ID for the track based on its path
-        let mut hasher = Sha1::new();
-        hasher.update(path.to_str().unwrap_or_default().as_bytes());
-        let id = format!("{:x}", hasher.finalize());
+        let id = file_id(path);
         
         tokio::task::yield_now().await;
 
@@ -102,9 +160,13 @@ impl FileScanner {
         let mut artist = String::from("Unknown Artist");
         let mut album = String::from("Unknown Album");
         let mut track_number = None;
+        let mut track_total = None;
         let mut disc_number = None;
         let mut release_year = None;
         let mut genre = None;
+        let mut explicit = false;
+        let mut lyrics = None;
+        let mut release_type = None;
         let mut duration = 0;
 
         tokio::task::yield_now().await;
@@ -112,6 +174,16 @@ impl FileScanner {
         // Get format metadata
         if let Some(metadata) = probed.format.metadata().current() {
             for tag in metadata.tags() {
+                if let Some(flag) = Self::parse_advisory(&tag.key, &tag.value.to_string()) {
+                    explicit = flag;
+                }
+                let parsed =
+                    Self::parse_release_type(&tag.key, tag.std_key, &tag.value.to_string());
+                // A compilation flag holds whatever order the tags come in
+                if parsed.is_some() && release_type != Some(ReleaseType::Compilation) {
+                    release_type = parsed;
+                }
+
                 match tag.std_key {
                     Some(symphonia::core::meta::StandardTagKey::TrackTitle) => {
                         title = tag.value.to_string();
@@ -123,7 +195,12 @@ impl FileScanner {
                         album = tag.value.to_string();
                     }
                     Some(symphonia::core::meta::StandardTagKey::TrackNumber) => {
-                        track_number = tag.value.to_string().parse().ok();
+                        let (number, total) = Self::parse_position(&tag.value.to_string());
+                        track_number = number;
+                        track_total = total.or(track_total);
+                    }
+                    Some(symphonia::core::meta::StandardTagKey::TrackTotal) => {
+                        track_total = tag.value.to_string().trim().parse().ok();
                     }
                     Some(symphonia::core::meta::StandardTagKey::DiscNumber) => {
                         disc_number = tag.value.to_string().parse().ok();
@@ -139,6 +216,9 @@ impl FileScanner {
                     Some(symphonia::core::meta::StandardTagKey::Genre) => {
                         genre = Some(tag.value.to_string());
                     }
+                    Some(symphonia::core::meta::StandardTagKey::Lyrics) => {
+                        lyrics = Some(tag.value.to_string());
+                    }
                     _ => {
                         // Handle non-standard tags
                         match tag.key.to_uppercase().as_str() {
@@ -158,7 +238,13 @@ impl FileScanner {
                                 album = tag.value.to_string();
                             }
                             "TRACKNUMBER" if track_number.is_none() => {
-                                track_number = tag.value.to_string().parse().ok();
+                                let (number, total) =
+                                    Self::parse_position(&tag.value.to_string());
+                                track_number = number;
+                                track_total = total.or(track_total);
+                            }
+                            "TRACKTOTAL" | "TOTALTRACKS" if track_total.is_none() => {
+                                track_total = tag.value.to_string().trim().parse().ok();
                             }
                             "DISCNUMBER" if disc_number.is_none() => {
                                 disc_number = tag.value.to_string().parse().ok();
@@ -174,6 +260,9 @@ impl FileScanner {
                             "GENRE" if genre.is_none() => {
                                 genre = Some(tag.value.to_string());
                             }
+                            "LYRICS" | "UNSYNCEDLYRICS" | "USLT" if lyrics.is_none() => {
+                                lyrics = Some(tag.value.to_string());
+                            }
                             _ => {}
                         }
                     }
@@ -199,20 +288,24 @@ impl FileScanner {
 
         tokio::task::yield_now().await;
 
-        // Extract artwork
+        // Extract artwork. Only thumbnails go into the database; the full
+        // image is referenced by path
+        let embedded = probed.format.metadata().current().and_then(|meta| {
+            meta.visuals()
+                .iter()
+                .find(|v| v.media_type.starts_with("image/"))
+                .map(|v| (v.media_type.clone(), v.data.to_vec()))
+        });
+
         let mut artwork = Artwork {
-            thumbnail: None,
+            id: None,
+            thumbnails: None,
             full_art: ArtworkSource::None,
         };
 
         // Try to get embedded artwork
-        if let Some(visual_meta) = probed.format.metadata().current().and_then(|meta| {
-            meta.visuals()
-                .iter()
-                .find(|v| v.media_type.starts_with("image/"))
-        }) {
-            artwork.thumbnail = Some(visual_meta.data.to_vec());
-            tokio::task::yield_now().await;
+        if let Some((media_type, data)) = embedded {
+            artwork = thumbnails::embedded_artwork(&media_type, data).await;
         } else {
             // Look for cover art files in the same directory
             if let Some(parent) = path.parent() {
@@ -234,7 +327,7 @@ impl FileScanner {
                 for filename in cover_filenames.iter() {
                     let cover_path = parent.join(filename);
                     if cover_path.exists() {
-                        artwork.full_art = ArtworkSource::Local { path: cover_path };
+                        artwork = thumbnails::file_artwork(cover_path).await;
                         break;
                     }
                     
@@ -253,6 +346,9 @@ impl FileScanner {
             .unwrap_or("unknown")
             .to_lowercase();
 
+        // Some taggers write an empty frame instead of leaving it out
+        let lyrics = lyrics.filter(|text| !text.trim().is_empty());
+
         println!("Successfully processed file: {} - {}", title, artist);
 
         Ok(Track {
@@ -262,15 +358,21 @@ impl FileScanner {
             album,
             duration,
             track_number,
+            track_total,
             disc_number,
             release_year,
             genre,
+            explicit,
+            lyrics,
+            release_type,
             artwork,
             source: PlaybackSource::Local {
                 file_format,
                 file_size,
                 path: path.to_path_buf(),
+                segment: None,
             },
+            availability: Availability::Local,
         })
     }
 }
@@ -0,0 +1,120 @@
+//! Tempo and key of local files for DJ mode. Both come from the tags when
+//! a DJ tool or tagger has written them. Otherwise the tempo is measured
+//! with GStreamer's bpmdetect element, while keys aren't detected and are
+//! left unknown.
+
+use super::loudness::decode_into;
+use crate::services::models::{MusicalKey, TrackTempo};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use mp4ameta::FreeformIdent;
+use std::error::Error;
+use std::path::Path;
+
+const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+// Beyond what dance music is played at, so readings out here are taken as
+// wrong
+const BPM_RANGE: std::ops::RangeInclusive<f32> = 40.0..=250.0;
+
+/// Tempo and key of the file at `path`. Blocks while the file is decoded
+/// when its tags have no tempo.
+pub fn analyze_tempo(path: &Path) -> Result<TrackTempo, Box<dyn Error + Send + Sync>> {
+    let mut tempo = tagged_tempo(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read tempo tags of {:?}: {}", path, e);
+        TrackTempo::default()
+    });
+    if tempo.bpm.is_none() {
+        tempo.bpm = Some(detect_bpm(path)?);
+    }
+    Ok(tempo)
+}
+
+/// Tempo and key from the file's tags: TBPM and TKEY frames in MP3s, BPM
+/// and INITIALKEY comments in FLAC and the tempo and initialkey atoms in
+/// MP4s
+fn tagged_tempo(path: &Path) -> Result<TrackTempo, Box<dyn Error + Send + Sync>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    let (bpm, key) = match extension.as_deref() {
+        Some("mp3") => {
+            let tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => {
+                    return Ok(TrackTempo::default())
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let text = |id: &str| {
+                tag.get(id)
+                    .and_then(|frame| frame.content().text())
+                    .map(str::to_string)
+            };
+            (text("TBPM"), text("TKEY"))
+        }
+        Some("flac") => {
+            let tag = metaflac::Tag::read_from_path(path)?;
+            let comment = |name: &str| {
+                tag.get_vorbis(name)
+                    .and_then(|mut values| values.next())
+                    .map(str::to_string)
+            };
+            (
+                comment("BPM"),
+                comment("INITIALKEY").or_else(|| comment("KEY")),
+            )
+        }
+        Some("m4a" | "mp4" | "m4b") => {
+            let tag = mp4ameta::Tag::read_from_path(path)?;
+            let key = tag
+                .strings_of(&FreeformIdent::new(ITUNES_MEAN, "initialkey"))
+                .next()
+                .map(str::to_string);
+            (tag.bpm().map(|bpm| bpm.to_string()), key)
+        }
+        _ => (None, None),
+    };
+
+    Ok(TrackTempo {
+        bpm: bpm
+            .and_then(|bpm| bpm.trim().parse::<f32>().ok())
+            .filter(|bpm| BPM_RANGE.contains(bpm)),
+        key: key.as_deref().and_then(MusicalKey::parse),
+    })
+}
+
+fn detect_bpm(path: &Path) -> Result<f32, Box<dyn Error + Send + Sync>> {
+    gst::init()?;
+    let detector = gst::ElementFactory::make("bpmdetect")
+        .build()
+        .map_err(|_| "Measuring tempo needs the bpmdetect GStreamer element")?;
+    let pipeline = decode_into(path, &detector)?;
+    pipeline.set_state(gst::State::Playing)?;
+    let result = wait_for_bpm(&pipeline);
+    pipeline.set_state(gst::State::Null)?;
+    result
+}
+
+fn wait_for_bpm(pipeline: &gst::Pipeline) -> Result<f32, Box<dyn Error + Send + Sync>> {
+    let bus = pipeline.bus().ok_or("Pipeline has no bus")?;
+    let mut bpm = None;
+
+    for msg in bus.iter_timed(gst::ClockTime::NONE) {
+        match msg.view() {
+            // bpmdetect refines its guess as it goes, so the last one counts
+            gst::MessageView::Tag(tag) => {
+                if let Some(value) = tag.tags().get::<gst::tags::BeatsPerMinute>() {
+                    bpm = Some(value.get() as f32);
+                }
+            }
+            gst::MessageView::Eos(..) => break,
+            gst::MessageView::Error(err) => return Err(err.error().into()),
+            _ => {}
+        }
+    }
+
+    bpm.filter(|bpm| BPM_RANGE.contains(bpm))
+        .ok_or_else(|| "No steady tempo was found".into())
+}
@@ -0,0 +1,192 @@
+use crate::platform;
+use crate::services::models::{Artwork, ArtworkSource, Thumbnails};
+use crate::services::resources;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use parking_lot::Mutex;
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+const JPEG_QUALITY: u8 = 85;
+
+// Tracks are scanned folder by folder, so neighbouring files usually share
+// their art. Keeping the last result around avoids decoding it again.
+static LAST_GENERATED: Mutex<Option<(String, Thumbnails)>> = Mutex::new(None);
+
+fn artwork_id(data: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(data);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Where embedded art is written so the database only has to keep a path
+fn artwork_cache_dir() -> PathBuf {
+    platform::cache_dir().join("artwork")
+}
+
+fn cache_full_art(
+    id: &str,
+    media_type: &str,
+    data: &[u8],
+) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+    let extension = match media_type {
+        "image/png" => "png",
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        "image/bmp" => "bmp",
+        _ => "img",
+    };
+
+    let dir = artwork_cache_dir();
+    let path = dir.join(format!("{}.{}", id, extension));
+    if !path.exists() {
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(&path, data)?;
+    }
+    Ok(path)
+}
+
+fn encode(image: &DynamicImage) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut data = Vec::new();
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY))?;
+    Ok(data)
+}
+
+/// Decode `data` and scale it to the standard sizes, cropped square like the
+/// cards show it
+fn generate(data: &[u8]) -> Result<Thumbnails, Box<dyn Error + Send + Sync>> {
+    let image = image::load_from_memory(data)?;
+
+    // Scale the full image once, then derive the smaller sizes from that
+    let (small, medium, large) = resources::thumbnail_sizes();
+    let large = image.resize_to_fill(large, large, FilterType::Lanczos3);
+    let medium = large.resize(medium, medium, FilterType::Lanczos3);
+    let small = large.resize(small, small, FilterType::Lanczos3);
+
+    Ok(Thumbnails {
+        small: encode(&small)?,
+        medium: encode(&medium)?,
+        large: encode(&large)?,
+    })
+}
+
+/// Thumbnails for the image with hash `id`, generated on the blocking pool
+/// so large images don't stall the scan
+async fn thumbnails_for(id: &str, data: Vec<u8>) -> Option<Thumbnails> {
+    if let Some((last_id, thumbnails)) = LAST_GENERATED.lock().as_ref() {
+        if last_id == id {
+            return Some(thumbnails.clone());
+        }
+    }
+
+    let result = tokio::task::spawn_blocking(move || generate(&data)).await;
+    match result {
+        Ok(Ok(thumbnails)) => {
+            // Low-memory mode doesn't hold on to any artwork
+            *LAST_GENERATED.lock() =
+                (!resources::low_memory()).then(|| (id.to_string(), thumbnails.clone()));
+            Some(thumbnails)
+        }
+        Ok(Err(e)) => {
+            eprintln!("Failed to generate thumbnails for artwork {}: {}", id, e);
+            None
+        }
+        Err(e) => {
+            eprintln!("Thumbnail generation task failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Artwork for an image embedded in a music file. The full image is moved
+/// out to the artwork cache and only referenced by path.
+pub async fn embedded_artwork(media_type: &str, data: Vec<u8>) -> Artwork {
+    let id = artwork_id(&data);
+    let full_art = match cache_full_art(&id, media_type, &data) {
+        Ok(path) => ArtworkSource::Local { path },
+        Err(e) => {
+            eprintln!("Failed to cache embedded artwork {}: {}", id, e);
+            ArtworkSource::None
+        }
+    };
+
+    // The ID points at the thumbnails, so it is only kept when they exist
+    let thumbnails = thumbnails_for(&id, data).await;
+    Artwork {
+        id: thumbnails.as_ref().map(|_| id),
+        thumbnails,
+        full_art,
+    }
+}
+
+/// Artwork for a cover image file next to the music
+pub async fn file_artwork(path: PathBuf) -> Artwork {
+    let mut artwork = Artwork {
+        id: None,
+        thumbnails: None,
+        full_art: ArtworkSource::Local { path: path.clone() },
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(data) => {
+            let id = artwork_id(&data);
+            artwork.thumbnails = thumbnails_for(&id, data).await;
+            artwork.id = artwork.thumbnails.as_ref().map(|_| id);
+        }
+        Err(e) => eprintln!("Failed to read cover art {:?}: {}", path, e),
+    }
+
+    artwork
+}
+
+/// A file holding `artwork`, for the desktop to show outside Nova. Art that
+/// only exists in memory is written to the artwork cache, named by its hash
+/// so the same image always gets the same path.
+pub fn artwork_file(artwork: &Artwork) -> Option<PathBuf> {
+    let cached = match &artwork.full_art {
+        ArtworkSource::Local { path } if path.exists() => return Some(path.clone()),
+        ArtworkSource::Embedded { format, data } => cache_full_art(&artwork_id(data), format, data),
+        // Streamed art is only kept as thumbnails
+        _ => {
+            let (id, thumbnails) = artwork.id.as_ref().zip(artwork.thumbnails.as_ref())?;
+            cache_full_art(
+                &format!("{}-{}", id, Thumbnails::LARGE),
+                "image/jpeg",
+                &thumbnails.large,
+            )
+        }
+    };
+    cached
+        .map_err(|e| eprintln!("Failed to export artwork: {}", e))
+        .ok()
+}
+
+/// Remove art from the artwork cache that nothing in `in_use` points at any
+/// more, such as the covers of tracks since removed. Files written after
+/// `since` are kept, as a scan still running may be about to use them.
+pub fn evict_unused(in_use: &HashSet<PathBuf>, since: SystemTime) {
+    let Ok(entries) = fs::read_dir(artwork_cache_dir()) else {
+        return;
+    };
+    // Covers downloaded from streaming providers share the folder but have
+    // no extension, and are left to their own cache
+    let unused = entries.flatten().map(|entry| entry.path()).filter(|path| {
+        path.extension().is_some()
+            && !in_use.contains(path)
+            && fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified < since)
+    });
+    for path in unused {
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("Failed to evict {:?} from the artwork cache: {}", path, e);
+        }
+    }
+}
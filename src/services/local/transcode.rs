@@ -0,0 +1,106 @@
+//! Transcoding of local audio for use outside Nova, such as a snippet to
+//! share. Like loudness analysis it decodes without a clock, so it runs as
+//! fast as the machine allows and blocks while it does.
+
+use super::loudness::decode_through;
+use crate::services::models::{PlaybackSource, Track};
+use crate::services::stream_cache;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Length of a shared snippet
+const SNIPPET_LENGTH: Duration = Duration::from_secs(30);
+
+/// Write SNIPPET_LENGTH of `track` around `position` to `dest` as Ogg Opus.
+/// Near either end the snippet is moved to fit in the track. Blocks until
+/// it's written.
+pub fn export_snippet(
+    track: &Track,
+    position: Duration,
+    dest: &Path,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (path, segment) = match &track.source {
+        PlaybackSource::Local { path, segment, .. } => (path.clone(), *segment),
+        PlaybackSource::Stream { url, .. } => (
+            stream_cache::cached_file(url).ok_or("The track isn't kept on this device")?,
+            None,
+        ),
+        _ => return Err("Only tracks on this device can be shared as audio".into()),
+    };
+
+    // Positions are in the file, which holds more than the track when it's
+    // a chapter
+    let first = segment.map_or(Duration::ZERO, |s| Duration::from_millis(s.start_ms));
+    // Without a known length a snippet near the end just comes out short
+    let last = match segment {
+        Some(segment) => Duration::from_millis(segment.end_ms),
+        None if track.duration > 0 => first + Duration::from_secs(track.duration.into()),
+        None => Duration::MAX,
+    };
+    let start = (first + position)
+        .saturating_sub(SNIPPET_LENGTH / 2)
+        .min(last.saturating_sub(SNIPPET_LENGTH))
+        .max(first);
+    let end = start.saturating_add(SNIPPET_LENGTH).min(last);
+
+    gst::init()?;
+    let pipeline = encode_pipeline(&path, dest)?;
+    let result = encode_range(&pipeline, start, end);
+    pipeline.set_state(gst::State::Null)?;
+    if result.is_err() {
+        let _ = fs::remove_file(dest);
+    }
+    result
+}
+
+/// Pipeline that decodes the file at `path` and writes it to `dest` as Ogg
+/// Opus
+fn encode_pipeline(
+    path: &Path,
+    dest: &Path,
+) -> Result<gst::Pipeline, Box<dyn Error + Send + Sync>> {
+    let dest = dest.to_str().ok_or("Path is not valid UTF-8")?;
+    let encode = gst::ElementFactory::make("opusenc")
+        .build()
+        .map_err(|_| "Sharing audio needs the opusenc GStreamer element")?;
+    let mux = gst::ElementFactory::make("oggmux").build()?;
+    let sink = gst::ElementFactory::make("filesink")
+        .property("location", dest)
+        .build()?;
+    decode_through(path, &[&encode, &mux, &sink])
+}
+
+/// Run `pipeline` over `start` to `end` of its file
+fn encode_range(
+    pipeline: &gst::Pipeline,
+    start: Duration,
+    end: Duration,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // The seek has to wait for the pipeline to preroll, and its stop ends
+    // the stream where the snippet does
+    pipeline.set_state(gst::State::Paused)?;
+    pipeline.state(gst::ClockTime::NONE).0?;
+    pipeline.seek(
+        1.0,
+        gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+        gst::SeekType::Set,
+        gst::ClockTime::from_nseconds(start.as_nanos() as u64),
+        gst::SeekType::Set,
+        gst::ClockTime::from_nseconds(end.as_nanos() as u64),
+    )?;
+    pipeline.set_state(gst::State::Playing)?;
+
+    let bus = pipeline.bus().ok_or("Pipeline has no bus")?;
+    for msg in bus.iter_timed(gst::ClockTime::NONE) {
+        match msg.view() {
+            gst::MessageView::Eos(..) => return Ok(()),
+            gst::MessageView::Error(err) => return Err(err.error().into()),
+            _ => {}
+        }
+    }
+    Err("Encoding stopped before the end".into())
+}
@@ -1,11 +1,13 @@
 use gtk::glib;
 use notify::{
     event::{CreateKind, ModifyKind, RemoveKind},
-    Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Result as NotifyResult, Watcher,
+    Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode,
+    Result as NotifyResult, Watcher,
 };
 use parking_lot::RwLock;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
 #[derive(Debug, Clone)]
@@ -15,9 +17,20 @@ pub enum FileEvent {
     Removed(PathBuf),
 }
 
+// How often the polling fallback rescans the music folder
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+// notify picks inotify on Linux, FSEvents on macOS and ReadDirectoryChangesW
+// on Windows. Polling covers folders those can't watch, e.g. network shares.
+#[derive(Debug)]
+enum WatcherBackend {
+    Native(RecommendedWatcher),
+    Polling(PollWatcher),
+}
+
 #[derive(Debug)]
 pub struct FileWatcher {
-    _watcher: RecommendedWatcher,
+    _watcher: WatcherBackend,
     event_sender: mpsc::Sender<FileEvent>,
 }
 
@@ -25,48 +38,19 @@ impl FileWatcher {
     pub fn new(path: PathBuf, event_sender: mpsc::Sender<FileEvent>) -> notify::Result<Self> {
         println!("Initializing file watcher for path: {:?}", path);
 
-        let event_sender_clone = event_sender.clone();
-        let mut watcher = notify::recommended_watcher(move |res: NotifyResult<Event>| {
-            if let Ok(event) = res {
-                println!("Raw watcher event: {:?}", event);
-
-                // Process events in background
-                let event_sender = event_sender_clone.clone();
-                let paths = event.paths.clone();
-                let kind = event.kind.clone();
-                
-                tokio::spawn(async move {
-                    for path in paths {
-                        let event = match kind {
-                            EventKind::Create(_) => {
-                                if path.exists() {
-                                    Some(FileEvent::Created(path))
-                                } else {
-                                    None
-                                }
-                            }
-                            EventKind::Modify(_) => {
-                                if path.exists() {
-                                    Some(FileEvent::Modified(path))
-                                } else {
-                                    Some(FileEvent::Removed(path))
-                                }
-                            }
-                            EventKind::Remove(_) => Some(FileEvent::Removed(path)),
-                            _ => None,
-                        };
-
-                        if let Some(event) = event {
-                            let _ = event_sender.send(event).await;
-                        }
-                    }
-                });
-            } else if let Err(e) = res {
-                eprintln!("Watch error: {:?}", e);
+        let watcher = match Self::watch_native(&path, event_sender.clone()) {
+            Ok(watcher) => WatcherBackend::Native(watcher),
+            Err(e) => {
+                eprintln!("Native file watching unavailable ({}), polling instead", e);
+                let sender = event_sender.clone();
+                let mut watcher = PollWatcher::new(
+                    move |res: NotifyResult<Event>| Self::handle_event(res, &sender),
+                    Config::default().with_poll_interval(POLL_INTERVAL),
+                )?;
+                watcher.watch(&path, RecursiveMode::Recursive)?;
+                WatcherBackend::Polling(watcher)
             }
-        })?;
-
-        watcher.watch(&path, RecursiveMode::Recursive)?;
+        };
         println!("File watcher initialized successfully");
 
         Ok(Self {
@@ -75,6 +59,54 @@ impl FileWatcher {
         })
     }
 
+    fn watch_native(
+        path: &Path,
+        sender: mpsc::Sender<FileEvent>,
+    ) -> notify::Result<RecommendedWatcher> {
+        let mut watcher = notify::recommended_watcher(move |res: NotifyResult<Event>| {
+            Self::handle_event(res, &sender)
+        })?;
+        watcher.watch(path, RecursiveMode::Recursive)?;
+        Ok(watcher)
+    }
+
+    fn handle_event(res: NotifyResult<Event>, event_sender: &mpsc::Sender<FileEvent>) {
+        match res {
+            Ok(event) => {
+                println!("Raw watcher event: {:?}", event);
+
+                // notify calls back on its own thread, outside the tokio
+                // runtime, so this can block until there's room
+                for path in event.paths {
+                    // Renames arrive as modifications of both the old and
+                    // new path on every backend
+                    let event = match event.kind {
+                        EventKind::Create(_) => {
+                            if path.exists() {
+                                Some(FileEvent::Created(path))
+                            } else {
+                                None
+                            }
+                        }
+                        EventKind::Modify(_) => {
+                            if path.exists() {
+                                Some(FileEvent::Modified(path))
+                            } else {
+                                Some(FileEvent::Removed(path))
+                            }
+                        }
+                        EventKind::Remove(_) => Some(FileEvent::Removed(path)),
+                        _ => None,
+                    };
+
+                    if let Some(event) = event {
+                        let _ = event_sender.blocking_send(event);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Watch error: {:?}", e),
+        }
+    }
 }
 
 // FileWatcher is not Clone anymore since it owns a unique event sender
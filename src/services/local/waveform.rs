@@ -0,0 +1,100 @@
+//! Peak levels of local files for drawing their waveform. Decoding takes a
+//! while, so the peaks are kept on disk by file and modification time and
+//! only worked out again when the file changes.
+
+use super::loudness::decode_into;
+use crate::platform;
+use gst::glib;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use sha1::{Digest, Sha1};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Length of audio each peak covers
+pub const PEAK_INTERVAL_MS: u64 = 50;
+
+fn cache_dir() -> PathBuf {
+    platform::cache_dir().join("waveforms")
+}
+
+// Named after the file and when it last changed, so edited files are
+// decoded again
+fn cache_file(path: &Path) -> Option<PathBuf> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    let seconds = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let key = format!("{}:{}", path.display(), seconds);
+    Some(cache_dir().join(format!("{:x}", Sha1::digest(key))))
+}
+
+/// Loudest sample in each PEAK_INTERVAL_MS of the file at `path`, from 0.0
+/// for silence to 1.0 for full scale. Blocks while the file is decoded
+/// unless its peaks are cached.
+pub fn waveform_peaks(path: &Path) -> Result<Vec<f32>, Box<dyn Error + Send + Sync>> {
+    let cached = cache_file(path);
+    if let Some(data) = cached.as_ref().and_then(|file| fs::read(file).ok()) {
+        return Ok(data.into_iter().map(|peak| peak as f32 / 255.0).collect());
+    }
+
+    let peaks = decode_peaks(path)?;
+    if let Some(file) = cached {
+        // One byte a peak is plenty to draw from
+        let data: Vec<u8> = peaks
+            .iter()
+            .map(|peak| (peak.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        if let Err(e) = fs::create_dir_all(cache_dir()).and_then(|_| fs::write(&file, data)) {
+            eprintln!("Failed to cache the waveform of {:?}: {}", path, e);
+        }
+    }
+    Ok(peaks)
+}
+
+fn decode_peaks(path: &Path) -> Result<Vec<f32>, Box<dyn Error + Send + Sync>> {
+    gst::init()?;
+    let level = gst::ElementFactory::make("level")
+        .property("interval", PEAK_INTERVAL_MS * 1_000_000)
+        .property("post-messages", true)
+        .build()?;
+    let pipeline = decode_into(path, &level)?;
+    pipeline.set_state(gst::State::Playing)?;
+    let result = wait_for_peaks(&pipeline);
+    pipeline.set_state(gst::State::Null)?;
+    result
+}
+
+fn wait_for_peaks(pipeline: &gst::Pipeline) -> Result<Vec<f32>, Box<dyn Error + Send + Sync>> {
+    let bus = pipeline.bus().ok_or("Pipeline has no bus")?;
+    let mut peaks = Vec::new();
+
+    for msg in bus.iter_timed(gst::ClockTime::NONE) {
+        match msg.view() {
+            gst::MessageView::Element(element) => {
+                let Some(s) = element.structure().filter(|s| s.name() == "level") else {
+                    continue;
+                };
+                // dB per channel, where the loudest channel is drawn
+                let db = s
+                    .get::<glib::ValueArray>("peak")
+                    .map(|values| {
+                        values
+                            .iter()
+                            .filter_map(|value| value.get::<f64>().ok())
+                            .fold(f64::NEG_INFINITY, f64::max)
+                    })
+                    .unwrap_or(f64::NEG_INFINITY);
+                peaks.push(10f64.powf(db / 20.0).min(1.0) as f32);
+            }
+            gst::MessageView::Eos(..) => break,
+            gst::MessageView::Error(err) => return Err(err.error().into()),
+            _ => {}
+        }
+    }
+
+    if peaks.is_empty() {
+        return Err("The file has no audio to draw".into());
+    }
+    Ok(peaks)
+}
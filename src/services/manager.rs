@@ -1,35 +1,288 @@
+use super::aggregation;
 use super::error::ServiceError;
+use super::genres::normalize_genres;
 use super::models::{Album, Artist, PlayableItem, Track};
+use super::network::NetworkConditions;
+use super::query_cache::{QueryCache, SearchKey};
+use super::ranking;
 use super::traits::MusicProvider;
-use crate::services::models::{SearchResults, SearchWeights};
+use super::writeback::{apply_tag_edits, TagChange};
+use crate::services::models::{
+    ContentFilter, LibraryChange, LyricsMatch, MovedFiles, RefreshMode, SearchResults,
+    SearchWeights,
+};
 use async_trait::async_trait;
 use chrono::Utc;
 use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+
+/// Shared handle to a registered provider. Work already running against a
+/// provider keeps it alive after it's unregistered.
+pub type ProviderHandle = Arc<dyn MusicProvider + Send + Sync + 'static>;
+
+// Events beyond this many unread by a subscriber are dropped for it. Scans
+// send a library change for every few files.
+const EVENT_CAPACITY: usize = 64;
+
+/// Change to the set of registered providers or to one's tracks, by
+/// provider name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderEvent {
+    Registered(String),
+    Replaced(String),
+    Unregistered(String),
+    LibraryChanged(String, LibraryChange),
+}
 
 #[derive(Debug)]
 pub struct ServiceManager {
-    providers: Arc<RwLock<HashMap<String, Box<dyn MusicProvider + Send + Sync + 'static>>>>,
+    providers: Arc<RwLock<HashMap<String, ProviderHandle>>>,
+    network: Arc<RwLock<NetworkConditions>>,
+    content_filter: Arc<RwLock<ContentFilter>>,
+    events: broadcast::Sender<ProviderEvent>,
+    cache: QueryCache,
+    /// Goes up with every library change, so listings cached before it
+    /// aren't used
+    generation: Arc<AtomicU64>,
 }
 
 impl ServiceManager {
     pub fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
         Self {
             providers: Arc::new(RwLock::new(HashMap::new())),
+            network: Arc::new(RwLock::new(NetworkConditions::default())),
+            content_filter: Arc::new(RwLock::new(ContentFilter::Allow)),
+            events,
+            cache: QueryCache::new(),
+            generation: Arc::new(AtomicU64::new(0)),
         }
     }
 
+    /// Provider registrations, replacements and removals, and changes to
+    /// their libraries, from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ProviderEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: ProviderEvent) {
+        // Listings may have come from a provider that's gone
+        self.cache.clear();
+        // Nobody listening isn't an error
+        let _ = self.events.send(event);
+    }
+
+    /// Pass on `provider`'s library changes as events under `name` until it
+    /// stops sending them, which it does once it's dropped
+    fn forward_changes(&self, name: &str, provider: &ProviderHandle) {
+        let Some(mut changes) = provider.subscribe_changes() else {
+            return;
+        };
+        let name = name.to_string();
+        let events = self.events.clone();
+        let generation = self.generation.clone();
+        tokio::spawn(async move {
+            loop {
+                let change = match changes.recv().await {
+                    Ok(change) => change,
+                    // Some tracks changed, but which isn't known
+                    Err(broadcast::error::RecvError::Lagged(_)) => LibraryChange::default(),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                generation.fetch_add(1, Ordering::Relaxed);
+                let _ = events.send(ProviderEvent::LibraryChanged(name.clone(), change));
+            }
+        });
+    }
+
+    pub async fn set_network_conditions(&self, conditions: NetworkConditions) {
+        *self.network.write().await = conditions;
+    }
+
+    /// Connection state remote providers should respect when streaming or
+    /// downloading
+    pub async fn network_conditions(&self) -> NetworkConditions {
+        *self.network.read().await
+    }
+
+    pub async fn set_content_filter(&self, filter: ContentFilter) {
+        *self.content_filter.write().await = filter;
+        self.cache.clear();
+    }
+
+    /// Drop cached listings and searches after a change the library's
+    /// version doesn't cover, such as new genre mappings
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    pub async fn content_filter(&self) -> ContentFilter {
+        *self.content_filter.read().await
+    }
+
+    /// Whether explicit tracks should be left out of listings
+    async fn hides_explicit(&self) -> bool {
+        self.content_filter().await == ContentFilter::Hide
+    }
+
+    /// Add a provider under `name`, failing if one is already registered
+    /// there. Use replace_provider to swap it out.
     pub async fn register_provider(
         &self,
         name: &str,
         provider: Box<dyn MusicProvider + Send + Sync>,
-    ) {
-        let mut providers = self.providers.write().await;
-        providers.insert(name.to_string(), provider);
+    ) -> Result<(), ServiceError> {
+        {
+            let mut providers = self.providers.write().await;
+            if providers.contains_key(name) {
+                return Err(ServiceError::AlreadyRegistered(name.to_string()));
+            }
+            let provider: ProviderHandle = Arc::from(provider);
+            self.forward_changes(name, &provider);
+            providers.insert(name.to_string(), provider);
+        }
+        self.emit(ProviderEvent::Registered(name.to_string()));
+        Ok(())
     }
 
-    pub async fn get_all_tracks(&self) -> Result<Vec<PlayableItem>, ServiceError> {
+    /// Register `provider` under `name` whether or not one is there yet,
+    /// returning the provider it replaced
+    pub async fn replace_provider(
+        &self,
+        name: &str,
+        provider: Box<dyn MusicProvider + Send + Sync>,
+    ) -> Option<ProviderHandle> {
+        let provider: ProviderHandle = Arc::from(provider);
+        self.forward_changes(name, &provider);
+        let previous = self
+            .providers
+            .write()
+            .await
+            .insert(name.to_string(), provider);
+        self.emit(match previous {
+            Some(_) => ProviderEvent::Replaced(name.to_string()),
+            None => ProviderEvent::Registered(name.to_string()),
+        });
+        previous
+    }
+
+    /// Remove the provider registered under `name` and return it, so the
+    /// caller can finish with it before it's dropped
+    pub async fn unregister_provider(&self, name: &str) -> Option<ProviderHandle> {
+        let removed = self.providers.write().await.remove(name);
+        if removed.is_some() {
+            self.emit(ProviderEvent::Unregistered(name.to_string()));
+        }
+        removed
+    }
+
+    /// Handle to the provider registered under `name`
+    pub async fn provider(&self, name: &str) -> Option<ProviderHandle> {
+        self.providers.read().await.get(name).cloned()
+    }
+
+    pub async fn provider_names(&self) -> Vec<String> {
+        let providers = self.providers.read().await;
+        let mut names: Vec<String> = providers.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub async fn refresh_provider(
+        &self,
+        name: &str,
+        mode: RefreshMode,
+    ) -> Result<(), ServiceError> {
+        let providers = self.providers.read().await;
+        let provider = providers
+            .get(name)
+            .ok_or_else(|| ServiceError::NotFound(format!("Provider {}", name)))?;
+
+        let result = provider
+            .refresh(mode)
+            .await
+            .map_err(|e| ServiceError::ProviderError(format!("{}: {}", name, e)));
+        // Not every provider can say when its library changed
+        self.cache.clear();
+        result
+    }
+
+    /// Move files of `name`'s tracks, see MusicProvider::move_files
+    pub async fn move_files(
+        &self,
+        name: &str,
+        moves: &[(PathBuf, PathBuf)],
+    ) -> Result<MovedFiles, ServiceError> {
+        let providers = self.providers.read().await;
+        let provider = providers
+            .get(name)
+            .ok_or_else(|| ServiceError::NotFound(format!("Provider {}", name)))?;
+
+        provider
+            .move_files(moves)
+            .await
+            .map_err(|e| ServiceError::ProviderError(format!("{}: {}", name, e)))
+    }
+
+    /// Scan files of `name`'s tracks again, see MusicProvider::rescan_files
+    pub async fn rescan_files(&self, name: &str, paths: &[PathBuf]) -> Result<(), ServiceError> {
+        let providers = self.providers.read().await;
+        let provider = providers
+            .get(name)
+            .ok_or_else(|| ServiceError::NotFound(format!("Provider {}", name)))?;
+
+        provider
+            .rescan_files(paths)
+            .await
+            .map_err(|e| ServiceError::ProviderError(format!("{}: {}", name, e)))
+    }
+
+    /// Apply tag edits to `name`'s tracks, see MusicProvider::edit_tracks
+    pub async fn edit_tracks(
+        &self,
+        name: &str,
+        edits: &[(String, Vec<TagChange>)],
+    ) -> Result<Vec<String>, ServiceError> {
+        let providers = self.providers.read().await;
+        let provider = providers
+            .get(name)
+            .ok_or_else(|| ServiceError::NotFound(format!("Provider {}", name)))?;
+
+        let result = provider
+            .edit_tracks(edits)
+            .await
+            .map_err(|e| ServiceError::ProviderError(format!("{}: {}", name, e)));
+        // Cached results still have the old tags
+        self.cache.clear();
+        result
+    }
+
+    /// Refresh every provider, continuing past failures and reporting the
+    /// first one
+    pub async fn refresh_all(&self, mode: RefreshMode) -> Result<(), ServiceError> {
+        let mut first_error = None;
+
+        for name in self.provider_names().await {
+            if let Err(e) = self.refresh_provider(&name, mode).await {
+                eprintln!("Error refreshing {}: {}", name, e);
+                first_error.get_or_insert(e);
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Every track as its provider has it: explicit tracks aren't hidden and
+    /// genres and tags are left as they are. For work on the library itself,
+    /// such as exports, rather than for showing it.
+    pub async fn get_library_tracks(&self) -> Result<Vec<PlayableItem>, ServiceError> {
         let mut all_tracks = Vec::new();
         let providers = self.providers.read().await;
 
@@ -51,13 +304,29 @@ impl ServiceManager {
         Ok(all_tracks)
     }
 
+    pub async fn get_all_tracks(&self) -> Result<Vec<PlayableItem>, ServiceError> {
+        let mut all_tracks = self.get_library_tracks().await?;
+        if self.hides_explicit().await {
+            all_tracks.retain(|item| !item.track.explicit);
+        }
+        apply_tag_edits(all_tracks.iter_mut().map(|item| &mut item.track));
+        normalize_genres(all_tracks.iter_mut().map(|item| &mut item.track));
+
+        Ok(all_tracks)
+    }
+
     pub async fn get_all_artists(&self) -> Result<Vec<Artist>, ServiceError> {
         let mut all_artists = Vec::new();
         let providers = self.providers.read().await;
+        let version = self.generation.load(Ordering::Relaxed);
+        if let Some(artists) = self.cache.artists(version) {
+            return Ok(artists);
+        }
 
         for (provider_name, provider) in providers.iter() {
             match provider.get_artists().await {
-                Ok(artists) => {
+                Ok(mut artists) => {
+                    aggregation::tag_artists(&mut artists, provider_name);
                     all_artists.extend(artists);
                 }
                 Err(e) => {
@@ -66,22 +335,28 @@ impl ServiceManager {
             }
         }
 
+        // Combine the same artist from different providers
+        let mut all_artists = aggregation::merge_artists(all_artists);
+
         // Sort artists by name
         all_artists.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
 
-        // Remove duplicates (if any)
-        all_artists.dedup_by(|a, b| a.name == b.name);
-
+        self.cache.store_artists(all_artists.clone(), version);
         Ok(all_artists)
     }
 
     pub async fn get_all_albums(&self) -> Result<Vec<Album>, ServiceError> {
         let mut all_albums = Vec::new();
         let providers = self.providers.read().await;
+        let version = self.generation.load(Ordering::Relaxed);
+        if let Some(albums) = self.cache.albums(version) {
+            return Ok(albums);
+        }
 
         for (provider_name, provider) in providers.iter() {
             match provider.get_albums().await {
-                Ok(albums) => {
+                Ok(mut albums) => {
+                    aggregation::tag_albums(&mut albums, provider_name);
                     all_albums.extend(albums);
                 }
                 Err(e) => {
@@ -90,6 +365,9 @@ impl ServiceManager {
             }
         }
 
+        // Combine the same album from different providers
+        let mut all_albums = aggregation::merge_albums(all_albums);
+
         // Sort albums by title
         all_albums.sort_by(|a, b| {
             let a_sort = (a.artist.to_lowercase(), a.title.to_lowercase());
@@ -97,12 +375,254 @@ impl ServiceManager {
             a_sort.cmp(&b_sort)
         });
 
-        // Remove duplicates (if any)
-        all_albums.dedup_by(|a, b| a.title == b.title && a.artist == b.artist);
+        self.cache.store_albums(all_albums.clone(), version);
+        Ok(all_albums)
+    }
+
+    /// Decades with albums released in them across all providers, oldest
+    /// first
+    pub async fn album_decades(&self) -> Result<Vec<u32>, ServiceError> {
+        let mut decades = Vec::new();
+        let providers = self.providers.read().await;
+
+        for (provider_name, provider) in providers.iter() {
+            match provider.album_decades().await {
+                Ok(found) => decades.extend(found),
+                Err(e) => eprintln!("Error getting decades from {}: {}", provider_name, e),
+            }
+        }
+
+        decades.sort_unstable();
+        decades.dedup();
+        Ok(decades)
+    }
+
+    /// Albums from all providers released in the ten years from `decade`,
+    /// oldest first
+    pub async fn albums_from_decade(&self, decade: u32) -> Result<Vec<Album>, ServiceError> {
+        let mut all_albums = Vec::new();
+        let providers = self.providers.read().await;
 
+        for (provider_name, provider) in providers.iter() {
+            match provider.albums_from_decade(decade).await {
+                Ok(mut albums) => {
+                    aggregation::tag_albums(&mut albums, provider_name);
+                    all_albums.extend(albums);
+                }
+                Err(e) => eprintln!("Error getting albums from {}: {}", provider_name, e),
+            }
+        }
+
+        let mut all_albums = aggregation::merge_albums(all_albums);
+        all_albums.sort_by(|a, b| {
+            let a_sort = (a.year, a.artist.to_lowercase(), a.title.to_lowercase());
+            let b_sort = (b.year, b.artist.to_lowercase(), b.title.to_lowercase());
+            a_sort.cmp(&b_sort)
+        });
         Ok(all_albums)
     }
 
+    /// Provider names with `preferred` moved to the front
+    async fn lookup_order(&self, preferred: Option<&str>) -> Vec<String> {
+        let mut names = self.provider_names().await;
+        if let Some(preferred) = preferred {
+            if let Some(pos) = names.iter().position(|name| name == preferred) {
+                let name = names.remove(pos);
+                names.insert(0, name);
+            }
+        }
+        names
+    }
+
+    /// Look a track up by ID in whichever provider still has it
+    pub async fn get_track(&self, track_id: &str) -> Result<PlayableItem, ServiceError> {
+        let providers = self.providers.read().await;
+
+        for (provider_name, provider) in providers.iter() {
+            match provider.get_track(track_id).await {
+                Ok(Some(mut track)) => {
+                    apply_tag_edits([&mut track]);
+                    normalize_genres([&mut track]);
+                    return Ok(PlayableItem {
+                        track,
+                        provider: provider_name.clone(),
+                        added_at: Utc::now(),
+                    });
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("Error getting track from {}: {}", provider_name, e);
+                }
+            }
+        }
+
+        Err(ServiceError::NotFound(format!("Track {}", track_id)))
+    }
+
+    /// An album from `provider` along with its tracks
+    pub async fn album_details(
+        &self,
+        provider: &str,
+        album_id: &str,
+    ) -> Result<(Album, Vec<PlayableItem>), ServiceError> {
+        let providers = self.providers.read().await;
+        let source = providers
+            .get(provider)
+            .ok_or_else(|| ServiceError::NotFound(format!("Provider {}", provider)))?;
+        let provider_error = |e: Box<dyn Error + Send + Sync>| {
+            ServiceError::ProviderError(format!("{}: {}", provider, e))
+        };
+
+        let mut album = source
+            .get_album(album_id)
+            .await
+            .map_err(provider_error)?
+            .ok_or_else(|| ServiceError::NotFound(format!("Album {}", album_id)))?;
+        aggregation::tag_albums(std::slice::from_mut(&mut album), provider);
+
+        let tracks = source
+            .get_album_tracks(album_id)
+            .await
+            .map_err(provider_error)?;
+        let hide_explicit = self.hides_explicit().await;
+        let mut items: Vec<PlayableItem> = tracks
+            .into_iter()
+            .filter(|track| !(hide_explicit && track.explicit))
+            .map(|track| PlayableItem {
+                track,
+                provider: provider.to_string(),
+                added_at: Utc::now(),
+            })
+            .collect();
+        apply_tag_edits(items.iter_mut().map(|item| &mut item.track));
+        normalize_genres(items.iter_mut().map(|item| &mut item.track));
+
+        Ok((album, items))
+    }
+
+    /// An artist from `provider` along with their albums
+    pub async fn artist_details(
+        &self,
+        provider: &str,
+        artist_id: &str,
+    ) -> Result<(Artist, Vec<Album>), ServiceError> {
+        let providers = self.providers.read().await;
+        let source = providers
+            .get(provider)
+            .ok_or_else(|| ServiceError::NotFound(format!("Provider {}", provider)))?;
+        let provider_error = |e: Box<dyn Error + Send + Sync>| {
+            ServiceError::ProviderError(format!("{}: {}", provider, e))
+        };
+
+        let mut artist = source
+            .get_artist(artist_id)
+            .await
+            .map_err(provider_error)?
+            .ok_or_else(|| ServiceError::NotFound(format!("Artist {}", artist_id)))?;
+        aggregation::tag_artists(std::slice::from_mut(&mut artist), provider);
+
+        let mut albums = source
+            .get_artist_albums(artist_id)
+            .await
+            .map_err(provider_error)?;
+        aggregation::tag_albums(&mut albums, provider);
+
+        Ok((artist, albums))
+    }
+
+    /// Every track on an artist's albums from `provider`, album by album
+    pub async fn artist_tracks(
+        &self,
+        provider: &str,
+        artist_id: &str,
+    ) -> Result<Vec<PlayableItem>, ServiceError> {
+        let providers = self.providers.read().await;
+        let source = providers
+            .get(provider)
+            .ok_or_else(|| ServiceError::NotFound(format!("Provider {}", provider)))?;
+        let provider_error = |e: Box<dyn Error + Send + Sync>| {
+            ServiceError::ProviderError(format!("{}: {}", provider, e))
+        };
+
+        let albums = source
+            .get_artist_albums(artist_id)
+            .await
+            .map_err(provider_error)?;
+        let hide_explicit = self.hides_explicit().await;
+        let mut items = Vec::new();
+        for album in albums {
+            let tracks = source
+                .get_album_tracks(&album.id)
+                .await
+                .map_err(provider_error)?;
+            items.extend(
+                tracks
+                    .into_iter()
+                    .filter(|track| !(hide_explicit && track.explicit))
+                    .map(|track| PlayableItem {
+                        track,
+                        provider: provider.to_string(),
+                        added_at: Utc::now(),
+                    }),
+            );
+        }
+        apply_tag_edits(items.iter_mut().map(|item| &mut item.track));
+        normalize_genres(items.iter_mut().map(|item| &mut item.track));
+
+        Ok(items)
+    }
+
+    /// The album a track belongs to along with its tracks. The track's own
+    /// provider is asked first, then every other provider.
+    pub async fn album_for_track(
+        &self,
+        track: &Track,
+        provider: Option<&str>,
+    ) -> Result<(Album, Vec<PlayableItem>), ServiceError> {
+        for name in self.lookup_order(provider).await {
+            let found = {
+                let providers = self.providers.read().await;
+                match providers.get(&name) {
+                    Some(provider) => provider.find_album(track).await,
+                    None => continue,
+                }
+            };
+
+            match found {
+                Ok(Some(album)) => return self.album_details(&name, &album.id).await,
+                Ok(None) => {}
+                Err(e) => eprintln!("Error looking up album in {}: {}", name, e),
+            }
+        }
+
+        Err(ServiceError::NotFound(format!("Album {}", track.album)))
+    }
+
+    /// The artist a track belongs to along with their albums
+    pub async fn artist_for_track(
+        &self,
+        track: &Track,
+        provider: Option<&str>,
+    ) -> Result<(Artist, Vec<Album>), ServiceError> {
+        for name in self.lookup_order(provider).await {
+            let found = {
+                let providers = self.providers.read().await;
+                match providers.get(&name) {
+                    Some(provider) => provider.find_artist(track).await,
+                    None => continue,
+                }
+            };
+
+            match found {
+                Ok(Some(artist)) => return self.artist_details(&name, &artist.id).await,
+                Ok(None) => {}
+                Err(e) => eprintln!("Error looking up artist in {}: {}", name, e),
+            }
+        }
+
+        Err(ServiceError::NotFound(format!("Artist {}", track.artist)))
+    }
+
     pub async fn search_all(
         &self,
         query: &str,
@@ -113,17 +633,23 @@ impl ServiceManager {
         println!("ServiceManager::search_all called with query: {}", query);
         let weights = weights.unwrap_or_default();
         let providers = self.providers.read().await;
+        let version = self.generation.load(Ordering::Relaxed);
+        let key = SearchKey::new(query, &weights, limit, offset);
+        if let Some(results) = self.cache.search(&key, version) {
+            return Ok(results);
+        }
         println!("Number of registered providers: {}", providers.len());
         let mut all_results = SearchResults {
             tracks: Vec::new(),
             albums: Vec::new(),
             artists: Vec::new(),
+            versions: HashMap::new(),
         };
 
         for (provider_name, provider) in providers.iter() {
             println!("Searching provider: {}", provider_name);
             match provider.search_all(query, &weights, limit, offset).await {
-                Ok(results) => {
+                Ok(mut results) => {
                     println!(
                         "Got results from {}: {} tracks, {} albums, {} artists",
                         provider_name,
@@ -131,6 +657,8 @@ impl ServiceManager {
                         results.albums.len(),
                         results.artists.len()
                     );
+                    aggregation::tag_albums(&mut results.albums, provider_name);
+                    aggregation::tag_artists(&mut results.artists, provider_name);
                     all_results.tracks.extend(results.tracks);
                     all_results.albums.extend(results.albums);
                     all_results.artists.extend(results.artists);
@@ -141,12 +669,83 @@ impl ServiceManager {
             }
         }
 
+        if self.hides_explicit().await {
+            all_results.tracks.retain(|item| !item.track.explicit);
+        }
+        apply_tag_edits(all_results.tracks.iter_mut().map(|item| &mut item.track));
+        normalize_genres(all_results.tracks.iter_mut().map(|item| &mut item.track));
+        (all_results.tracks, all_results.versions) = aggregation::merge_tracks(all_results.tracks);
+        all_results.albums = aggregation::merge_albums(all_results.albums);
+        all_results.artists = aggregation::merge_artists(all_results.artists);
+        ranking::rank_results(&mut all_results, query, &weights);
+
         println!(
             "Total results: {} tracks, {} albums, {} artists",
             all_results.tracks.len(),
             all_results.albums.len(),
             all_results.artists.len()
         );
+        self.cache.store_search(key, all_results.clone(), version);
         Ok(all_results)
     }
+
+    /// Tracks matching `query` across all providers, most relevant first.
+    /// Used to queue a whole search rather than the page's first few tracks.
+    pub async fn search_tracks(
+        &self,
+        query: &str,
+        weights: Option<SearchWeights>,
+        limit: usize,
+    ) -> Result<Vec<PlayableItem>, ServiceError> {
+        let weights = weights.unwrap_or_default();
+        let providers = self.providers.read().await;
+        let mut tracks = Vec::new();
+
+        for (provider_name, provider) in providers.iter() {
+            match provider.search_tracks(query, limit, 0).await {
+                Ok(found) => tracks.extend(found.into_iter().map(|track| PlayableItem {
+                    track,
+                    provider: provider_name.clone(),
+                    added_at: Utc::now(),
+                })),
+                Err(e) => eprintln!("Error searching tracks in {}: {}", provider_name, e),
+            }
+        }
+
+        if self.hides_explicit().await {
+            tracks.retain(|item| !item.track.explicit);
+        }
+        apply_tag_edits(tracks.iter_mut().map(|item| &mut item.track));
+        normalize_genres(tracks.iter_mut().map(|item| &mut item.track));
+        // A track found in several places is queued once, from its best copy
+        let (mut tracks, _) = aggregation::merge_tracks(tracks);
+        ranking::rank_tracks(&mut tracks, query, &weights);
+        tracks.truncate(limit);
+        Ok(tracks)
+    }
+
+    /// Tracks whose lyrics match `query` across all providers
+    pub async fn search_lyrics(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<LyricsMatch>, ServiceError> {
+        let providers = self.providers.read().await;
+        let mut matches = Vec::new();
+
+        for (provider_name, provider) in providers.iter() {
+            match provider.search_lyrics(query, limit).await {
+                Ok(found) => matches.extend(found),
+                Err(e) => eprintln!("Error searching lyrics in {}: {}", provider_name, e),
+            }
+        }
+
+        if self.hides_explicit().await {
+            matches.retain(|found| !found.item.track.explicit);
+        }
+        apply_tag_edits(matches.iter_mut().map(|found| &mut found.item.track));
+        normalize_genres(matches.iter_mut().map(|found| &mut found.item.track));
+        matches.truncate(limit);
+        Ok(matches)
+    }
 }
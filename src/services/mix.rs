@@ -0,0 +1,74 @@
+//! Mixes made to fit a length of time, e.g. a 45-minute mix for a run.
+//! Picking tracks whose durations add up to a budget is a knapsack
+//! problem; a random greedy pick followed by a few rounds of swaps gets
+//! within seconds of the budget for any real library, fast.
+
+use crate::services::models::PlayableItem;
+use rand::seq::SliceRandom;
+
+// Rounds of swaps before settling on the mix
+const MAX_PASSES: usize = 4;
+
+/// Pick tracks from `candidates` that add up to as close to `budget`
+/// seconds as possible without going over. Picks are random, so asking
+/// twice makes two different mixes.
+pub fn fit_to_duration(mut candidates: Vec<PlayableItem>, budget: u32) -> Vec<PlayableItem> {
+    candidates.retain(|item| item.track.duration > 0 && item.track.duration <= budget);
+    candidates.shuffle(&mut rand::thread_rng());
+
+    let mut chosen = Vec::new();
+    let mut unused = Vec::new();
+    let mut total = 0;
+    for item in candidates {
+        if total + item.track.duration <= budget {
+            total += item.track.duration;
+            chosen.push(item);
+        } else {
+            unused.push(item);
+        }
+    }
+
+    for _ in 0..MAX_PASSES {
+        let mut improved = false;
+        for index in 0..chosen.len() {
+            let gap = budget - total;
+            if gap == 0 {
+                return chosen;
+            }
+
+            // Swap in the longest unused track that still fits in its place
+            let current = chosen[index].track.duration;
+            let best = unused
+                .iter()
+                .enumerate()
+                .filter(|(_, item)| {
+                    item.track.duration > current && item.track.duration - current <= gap
+                })
+                .max_by_key(|(_, item)| item.track.duration)
+                .map(|(position, _)| position);
+            if let Some(position) = best {
+                total = total - current + unused[position].track.duration;
+                std::mem::swap(&mut chosen[index], &mut unused[position]);
+                improved = true;
+            }
+        }
+
+        // Swaps can leave room for a short track that didn't fit before
+        let gap = budget - total;
+        if let Some(position) = unused
+            .iter()
+            .position(|item| item.track.duration <= gap)
+        {
+            let item = unused.swap_remove(position);
+            total += item.track.duration;
+            chosen.push(item);
+            improved = true;
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    chosen
+}
@@ -1,13 +1,47 @@
+pub mod aggregation;
+pub mod album_gaps;
+pub mod artist_info;
+pub mod audio_devices;
+pub mod checkpoint;
+pub mod dj;
 pub mod error;
+pub mod export;
+pub mod file_naming;
+pub mod genres;
+pub mod http;
+pub mod listening_patterns;
 pub mod local;
 pub mod manager;
+pub mod mix;
 pub mod models;
+pub mod network;
+pub mod playlist_import;
+pub mod plugins;
+pub mod power;
+pub mod query_cache;
+pub mod ranking;
+pub mod recap;
+pub mod remote_art;
+pub mod resources;
+pub mod response_cache;
+pub mod scrobbler;
+pub mod secrets;
+pub mod stream_cache;
+pub mod tag_cleanup;
+pub mod tasks;
 pub mod traits;
+pub mod updates;
+pub mod user_store;
+pub mod webdav;
+pub mod writeback;
 pub mod audio_player;
 
+pub use artist_info::ArtistInfo;
 pub use error::ServiceError;
 pub use local::LocalMusicProvider;
-pub use manager::ServiceManager;
+pub use manager::{ProviderEvent, ServiceManager};
 pub use models::{Album, Artist, PlayableItem, Track};
+pub use network::{MeteredPolicy, NetworkConditions, StreamingQuality};
 pub use traits::MusicProvider;
+pub use user_store::UserStore;
 pub use audio_player::AudioPlayer;
@@ -1,13 +1,47 @@
+use crate::services::resources;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Artwork {
-    pub thumbnail: Option<Vec<u8>>,
+    // Hash of the source image, shared by every item using the same art
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub thumbnails: Option<Thumbnails>,
     pub full_art: ArtworkSource,
 }
 
+/// Standard-size JPEG renditions of a piece of artwork, generated once at
+/// scan time so cards never have to decode the full image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Thumbnails {
+    pub small: Vec<u8>,
+    pub medium: Vec<u8>,
+    pub large: Vec<u8>,
+}
+
+impl Thumbnails {
+    pub const SMALL: u32 = 64;
+    pub const MEDIUM: u32 = 160;
+    pub const LARGE: u32 = 320;
+
+    /// The smallest thumbnail that still covers `size` pixels, at the sizes
+    /// thumbnails are generated at in the current memory mode
+    pub fn for_size(&self, size: i32) -> &[u8] {
+        let (small, medium, _) = resources::thumbnail_sizes();
+        match size.max(0) as u32 {
+            size if size <= small => &self.small,
+            size if size <= medium => &self.medium,
+            _ => &self.large,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ArtworkSource {
     Embedded {
@@ -24,6 +58,82 @@ pub enum ArtworkSource {
     None,
 }
 
+/// Part of a file that plays as a track of its own, such as one chapter
+/// of a live set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Segment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+impl Segment {
+    pub fn duration_ms(&self) -> u64 {
+        self.end_ms.saturating_sub(self.start_ms)
+    }
+}
+
+/// Where the user wants a track to start and stop playing, e.g. to skip a
+/// long intro or a silent stretch before a hidden track
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Trim {
+    pub start_ms: u64,
+    /// None plays to the end
+    pub end_ms: Option<u64>,
+}
+
+/// Tracks a provider added, removed or changed, by track ID. All empty
+/// when it can't be told which tracks changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LibraryChange {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+impl LibraryChange {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+
+    /// Take in `other`, which came after this
+    pub fn merge(&mut self, other: &LibraryChange) {
+        self.added.extend_from_slice(&other.added);
+        self.removed.extend_from_slice(&other.removed);
+        self.updated.extend_from_slice(&other.updated);
+    }
+
+    /// Every track ID the change touches
+    pub fn ids(&self) -> impl Iterator<Item = &String> {
+        self.added.iter().chain(&self.removed).chain(&self.updated)
+    }
+}
+
+/// A track whose file moved, which gives it a new ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MovedTrack {
+    pub old_id: String,
+    pub new_id: String,
+    /// Where its file is now
+    pub path: PathBuf,
+}
+
+/// What moving a provider's files did
+#[derive(Debug, Clone, Default)]
+pub struct MovedFiles {
+    pub tracks: Vec<MovedTrack>,
+    /// Why it stopped before the last file, when it did
+    pub error: Option<String>,
+}
+
+/// A named place in a track to come back to, such as where a long mix or
+/// audiobook was left off
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    /// From the start of the track
+    pub position_ms: u64,
+    pub name: String,
+}
+
 // Playback source information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PlaybackSource {
@@ -31,6 +141,9 @@ pub enum PlaybackSource {
         file_format: String,
         file_size: u64,
         path: PathBuf,
+        // Set when the track is only part of the file
+        #[serde(default)]
+        segment: Option<Segment>,
     },
     Spotify {
         track_id: String,
@@ -40,6 +153,41 @@ pub enum PlaybackSource {
         video_id: String,
         stream_url: String,
     },
+    /// Audio a provider serves from a URL, such as a plugin's
+    Stream {
+        url: String,
+        // A smaller stream to play instead on metered connections
+        #[serde(default)]
+        low_quality_url: Option<String>,
+    },
+}
+
+impl PlaybackSource {
+    /// Whether playback depends on a network connection
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, PlaybackSource::Local { .. })
+    }
+}
+
+/// Whether a track will play, and whether it needs a connection to. Set by
+/// the provider the track comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Availability {
+    /// A file on this device
+    Local,
+    /// Streamed, with a copy kept on this device
+    Cached,
+    /// Only plays with a network connection
+    #[default]
+    StreamOnly,
+    /// Won't play, e.g. its file is gone or the stream was withdrawn
+    Unavailable,
+}
+
+impl Availability {
+    pub fn plays_offline(self) -> bool {
+        matches!(self, Self::Local | Self::Cached)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,11 +198,27 @@ pub struct Track {
     pub album: String,
     pub duration: u32,
     pub track_number: Option<u32>,
+    // Tracks on the disc, from a TRACKTOTAL tag or a "5/12" track number
+    #[serde(default)]
+    pub track_total: Option<u32>,
     pub disc_number: Option<u32>,
     pub release_year: Option<u32>,
     pub genre: Option<String>,
+    // Parental advisory flag from the file's tags or the provider
+    #[serde(default)]
+    pub explicit: bool,
+    // Unsynced lyrics from the file's tags. They are kept in their own
+    // table for search and not loaded back with the track.
+    #[serde(default)]
+    pub lyrics: Option<String>,
+    // Release type from the file's tags. It is kept on the album and not
+    // loaded back with the track.
+    #[serde(default)]
+    pub release_type: Option<ReleaseType>,
     pub artwork: Artwork,
     pub source: PlaybackSource,
+    #[serde(default)]
+    pub availability: Availability,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,11 +228,70 @@ pub struct PlayableItem {
     pub added_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Playlist {
-    pub id: String,
-    pub name: String,
-    pub items: Vec<PlayableItem>,
+/// Why a track is in the queue, shown with it in the queue list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueContext {
+    Album(String),
+    Artist(String),
+    Playlist(String),
+    /// The query it was found by
+    Search(String),
+    /// Found online to fill a gap in the album
+    MissingTrack(String),
+    /// Put back from a session that ended with it queued
+    Restored,
+    /// Picked by DJ mode to follow the track before it
+    Dj,
+}
+
+impl QueueContext {
+    pub fn label(&self) -> String {
+        match self {
+            Self::Album(title) => format!("From the album {}", title),
+            Self::Artist(name) => format!("From the artist {}", name),
+            Self::Playlist(name) => format!("From the playlist {}", name),
+            Self::Search(query) => format!("From a search for “{}”", query),
+            Self::MissingTrack(album) => format!("Missing from {}", album),
+            Self::Restored => "From your last session".to_string(),
+            Self::Dj => "Picked by DJ mode".to_string(),
+        }
+    }
+}
+
+/// A queued item and why it was queued. It stands in for the item, so the
+/// queue reads its track and provider straight through it.
+#[derive(Debug, Clone)]
+pub struct QueueEntry {
+    pub item: PlayableItem,
+    pub context: QueueContext,
+}
+
+impl QueueEntry {
+    /// `items` queued together for the same reason
+    pub fn with_context(items: Vec<PlayableItem>, context: &QueueContext) -> Vec<Self> {
+        items
+            .into_iter()
+            .map(|item| Self {
+                item,
+                context: context.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Deref for QueueEntry {
+    type Target = PlayableItem;
+
+    fn deref(&self) -> &PlayableItem {
+        &self.item
+    }
+}
+
+/// One provider's copy of an album or artist
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemSource {
+    pub provider: String,
+    pub id: String, // Provider-specific ID
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,9 +300,61 @@ pub struct Album {
     pub title: String,
     pub artist: String,
     pub year: Option<u32>,
+    #[serde(default)]
+    pub release_type: ReleaseType,
     pub art_url: Option<String>,
     pub tracks: Vec<String>, // Track IDs
     pub artwork: Option<Artwork>,
+    // Providers this album is available from, filled in by the service manager
+    #[serde(default)]
+    pub sources: Vec<ItemSource>,
+}
+
+/// What kind of release an album is, following MusicBrainz's release-group
+/// types. Albums without a type tag count as albums.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ReleaseType {
+    #[default]
+    Album,
+    Ep,
+    Single,
+    Live,
+    Compilation,
+}
+
+impl ReleaseType {
+    /// Parse a release type tag such as "album", "EP" or MusicBrainz's
+    /// "album; live". Secondary types win over the primary one, as a live
+    /// album belongs with the live recordings.
+    pub fn from_tag(value: &str) -> Option<Self> {
+        let value = value.to_lowercase();
+        let types: Vec<&str> = value
+            .split(|c: char| matches!(c, ';' | ',' | '/' | '+'))
+            .map(str::trim)
+            .collect();
+
+        [
+            ("compilation", Self::Compilation),
+            ("live", Self::Live),
+            ("single", Self::Single),
+            ("ep", Self::Ep),
+            ("album", Self::Album),
+        ]
+        .into_iter()
+        .find(|(name, _)| types.contains(name))
+        .map(|(_, release_type)| release_type)
+    }
+
+    /// Value stored in the database
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Album => "album",
+            Self::Ep => "ep",
+            Self::Single => "single",
+            Self::Live => "live",
+            Self::Compilation => "compilation",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -88,6 +363,298 @@ pub struct Artist {
     pub name: String,
     pub albums: Vec<String>, // Album IDs
     pub artwork: Option<Artwork>,
+    // Providers this artist is available from, filled in by the service manager
+    #[serde(default)]
+    pub sources: Vec<ItemSource>,
+}
+
+/// Kind of library item that can be pinned to the sidebar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PinnedKind {
+    Album,
+    Artist,
+    Playlist,
+    PlaylistFolder,
+}
+
+impl PinnedKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PinnedKind::Album => "album",
+            PinnedKind::Artist => "artist",
+            PinnedKind::Playlist => "playlist",
+            PinnedKind::PlaylistFolder => "playlist-folder",
+        }
+    }
+
+    pub fn from_name(value: &str) -> Option<Self> {
+        match value {
+            "album" => Some(PinnedKind::Album),
+            "artist" => Some(PinnedKind::Artist),
+            "playlist" => Some(PinnedKind::Playlist),
+            "playlist-folder" => Some(PinnedKind::PlaylistFolder),
+            _ => None,
+        }
+    }
+}
+
+/// An album, artist or playlist the user pinned to the sidebar
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinnedItem {
+    pub kind: PinnedKind,
+    pub provider: String,
+    pub item_id: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+}
+
+/// A track that was played, as remembered by the play history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub track_id: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: u32,
+    pub played_at: DateTime<Utc>,
+}
+
+/// A track found by its lyrics, with an excerpt around the match. Matched
+/// words in the excerpt are wrapped in HIGHLIGHT_START and HIGHLIGHT_END.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LyricsMatch {
+    pub item: PlayableItem,
+    pub snippet: String,
+}
+
+impl LyricsMatch {
+    pub const HIGHLIGHT_START: char = '\u{2}';
+    pub const HIGHLIGHT_END: char = '\u{3}';
+}
+
+/// A user playlist; tracks are kept by ID and looked up when shown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playlist {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub track_ids: Vec<String>,
+    /// The folder a folder playlist mirrors. These follow the files on disk
+    /// and can't be edited.
+    #[serde(default)]
+    pub folder: Option<PathBuf>,
+    /// The playlist folder the user filed this under, which groups it on
+    /// the Playlists page
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub playback: PlaylistPlayback,
+}
+
+/// Playback a playlist sets up when it's played, each left to the app's
+/// own settings when None
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaylistPlayback {
+    pub shuffle: Option<bool>,
+    /// How long each track fades into the next
+    pub crossfade: Option<Duration>,
+    /// Level by the album's ReplayGain rather than each track's, keeping
+    /// the loudness differences within an album
+    pub album_gain: Option<bool>,
+}
+
+/// A key on the Camelot wheel, where keys next to each other or sharing a
+/// number mix without clashing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicalKey {
+    /// Position on the wheel, 1-12
+    pub number: u8,
+    pub minor: bool,
+}
+
+impl MusicalKey {
+    /// Read a key as taggers write it: Camelot such as "8A", or a name such
+    /// as "Am", "C#m", "Eb" or "F# minor"
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Some(number) = value
+            .strip_suffix(['A', 'a', 'B', 'b'])
+            .and_then(|number| number.parse::<u8>().ok())
+            .filter(|number| (1..=12).contains(number))
+        {
+            let minor = value.ends_with(['A', 'a']);
+            return Some(Self { number, minor });
+        }
+
+        let mut chars = value.chars();
+        let mut pitch: i32 = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let mut rest = chars.as_str();
+        if let Some(after) = rest.strip_prefix(['#', '♯']) {
+            pitch += 1;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix(['b', '♭']) {
+            pitch -= 1;
+            rest = after;
+        }
+        let mode = rest.trim().to_lowercase();
+        let minor = match mode.as_str() {
+            "" | "maj" | "major" => false,
+            "m" | "min" | "minor" => true,
+            _ => return None,
+        };
+
+        // C major is 8B, and each fifth up is one step round the wheel. A
+        // minor key sits at the same number as its relative major.
+        let major_pitch = if minor { pitch + 3 } else { pitch };
+        let number = (7 * major_pitch.rem_euclid(12) + 7) % 12 + 1;
+        Some(Self {
+            number: number as u8,
+            minor,
+        })
+    }
+
+    /// Whether the keys mix: the same number, or a step round the wheel in
+    /// the same mode
+    pub fn is_compatible(&self, other: &MusicalKey) -> bool {
+        if self.number == other.number {
+            return true;
+        }
+        let steps = (i32::from(self.number) - i32::from(other.number)).rem_euclid(12);
+        self.minor == other.minor && (steps == 1 || steps == 11)
+    }
+
+    /// Camelot notation, such as "8A"
+    pub fn camelot(&self) -> String {
+        format!("{}{}", self.number, if self.minor { 'A' } else { 'B' })
+    }
+}
+
+/// Tempo and key of a track, from its tags or analysis
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TrackTempo {
+    pub bpm: Option<f32>,
+    pub key: Option<MusicalKey>,
+}
+
+/// What was last known of a track in a playlist, to find it again in the
+/// library once it's gone, such as after its file moved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownTrack {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: u32,
+}
+
+/// A track that is skipped in most of its plays, suggested for cleanup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedTrack {
+    pub track_id: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub plays: u32,
+    pub skips: u32,
+}
+
+/// Listening totals for a group of tracks, such as an album, from the play
+/// history and ratings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlayStats {
+    pub plays: u32,
+    pub last_played: Option<DateTime<Utc>>,
+    /// Mean stars of the rated tracks, None when none are rated
+    pub average_rating: Option<f32>,
+}
+
+/// Tracks and albums the user never wants to come up in shuffle or mixes,
+/// like skits or Christmas albums out of season
+#[derive(Debug, Clone, Default)]
+pub struct ShuffleExclusions {
+    pub tracks: HashSet<String>,
+    /// Keys from `album_keys`
+    pub albums: HashSet<String>,
+}
+
+impl ShuffleExclusions {
+    /// Key that marks an album as excluded
+    pub fn album_key(album: &Album) -> String {
+        Self::key(&album.artist, &album.title)
+    }
+
+    /// Keys an album with `tracks` is excluded by: its own, and one for each
+    /// artist of its tracks, as tracks on compilations or with guests have
+    /// artists of their own
+    pub fn album_keys(album: &Album, tracks: &[Track]) -> Vec<String> {
+        let mut keys = vec![Self::album_key(album)];
+        for track in tracks {
+            let key = Self::key(&track.artist, &track.album);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    fn key(artist: &str, title: &str) -> String {
+        format!("{}\t{}", artist.to_lowercase(), title.to_lowercase())
+    }
+
+    pub fn excludes(&self, track: &Track) -> bool {
+        self.tracks.contains(&track.id)
+            || self.albums.contains(&Self::key(&track.artist, &track.album))
+    }
+
+    /// `items` without the excluded ones, unless that would leave nothing:
+    /// shuffling an excluded album on purpose still plays it
+    pub fn filter(&self, items: Vec<QueueEntry>) -> Vec<QueueEntry> {
+        if !items.iter().any(|item| !self.excludes(&item.track)) {
+            return items;
+        }
+        items
+            .into_iter()
+            .filter(|item| !self.excludes(&item.track))
+            .collect()
+    }
+}
+
+/// What to do with tracks marked as explicit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentFilter {
+    /// Treat explicit tracks like any other
+    Allow,
+    /// Leave explicit tracks out of browsing and search
+    Hide,
+    /// List explicit tracks but skip them during queue playback
+    Skip,
+}
+
+impl ContentFilter {
+    pub fn from_setting(value: &str) -> Self {
+        match value {
+            "hide" => ContentFilter::Hide,
+            "skip" => ContentFilter::Skip,
+            _ => ContentFilter::Allow,
+        }
+    }
+}
+
+/// How thoroughly a provider should re-read its library
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshMode {
+    /// Only pick up new, changed and removed items
+    Incremental,
+    /// Re-read every item from scratch
+    Full,
 }
 
 #[derive(Debug, Clone)]
@@ -95,6 +662,9 @@ pub struct SearchResults {
     pub tracks: Vec<PlayableItem>,
     pub albums: Vec<Album>,
     pub artists: Vec<Artist>,
+    // Other copies of each track listed, by the ID of the copy kept. Filled
+    // in by the service manager when it merges providers' results.
+    pub versions: HashMap<String, Vec<PlayableItem>>,
 }
 
 #[derive(Debug, Clone)]
@@ -102,6 +672,19 @@ pub struct SearchWeights {
     pub track_weight: f32,
     pub album_weight: f32,
     pub artist_weight: f32,
+    /// Multipliers for results coming from specific providers, keyed by
+    /// provider name. Providers without an entry use 1.0.
+    pub provider_weights: HashMap<String, f32>,
+    /// Multiplier for fields that match the query exactly
+    pub exact_weight: f32,
+    /// Multiplier for matches on the artist of tracks and albums
+    pub artist_match_weight: f32,
+    /// How much recently played tracks that match are lifted, 0.0 for not
+    /// at all
+    pub recent_weight: f32,
+    /// How recently tracks were played by ID, from 1.0 for the last one
+    /// towards 0.0. Only filled in when `recent_weight` is used.
+    pub recently_played: HashMap<String, f32>,
 }
 
 impl Default for SearchWeights {
@@ -110,10 +693,21 @@ impl Default for SearchWeights {
             track_weight: 1.0,
             album_weight: 1.0,
             artist_weight: 1.0,
+            provider_weights: HashMap::new(),
+            exact_weight: 1.0,
+            artist_match_weight: 1.0,
+            recent_weight: 0.0,
+            recently_played: HashMap::new(),
         }
     }
 }
 
+impl SearchWeights {
+    pub fn provider_weight(&self, provider: &str) -> f32 {
+        self.provider_weights.get(provider).copied().unwrap_or(1.0)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ScoredResult {
     pub score: f32,
@@ -0,0 +1,58 @@
+/// What to do with network-heavy work while on a metered connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeteredPolicy {
+    Unrestricted,
+    ReduceQuality,
+    NoDownloads,
+}
+
+impl MeteredPolicy {
+    pub fn from_setting(value: &str) -> Self {
+        match value {
+            "unrestricted" => MeteredPolicy::Unrestricted,
+            "no-downloads" => MeteredPolicy::NoDownloads,
+            _ => MeteredPolicy::ReduceQuality,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamingQuality {
+    Low,
+    High,
+}
+
+/// Snapshot of the current connection as reported by the network monitor
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkConditions {
+    pub available: bool,
+    pub metered: bool,
+    pub policy: MeteredPolicy,
+}
+
+impl Default for NetworkConditions {
+    fn default() -> Self {
+        Self {
+            available: true,
+            metered: false,
+            policy: MeteredPolicy::ReduceQuality,
+        }
+    }
+}
+
+impl NetworkConditions {
+    /// Quality remote providers should request when streaming
+    pub fn streaming_quality(&self) -> StreamingQuality {
+        match (self.metered, self.policy) {
+            (true, MeteredPolicy::ReduceQuality | MeteredPolicy::NoDownloads) => {
+                StreamingQuality::Low
+            }
+            _ => StreamingQuality::High,
+        }
+    }
+
+    /// Whether background downloads (caching, artwork, metadata) may run
+    pub fn downloads_allowed(&self) -> bool {
+        self.available && !(self.metered && self.policy == MeteredPolicy::NoDownloads)
+    }
+}
@@ -0,0 +1,144 @@
+//! Import of M3U playlists made by other players. Entries are matched to
+//! the library by file path first, then by the artist and title in their
+//! #EXTINF line, since paths rarely survive moving between machines.
+
+use crate::services::models::{PlayableItem, PlaybackSource};
+use gtk::glib;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// One entry of a playlist file
+#[derive(Debug, Clone, Default)]
+struct PlaylistEntry {
+    path: Option<PathBuf>,
+    artist: Option<String>,
+    title: Option<String>,
+}
+
+/// A playlist file matched against the library
+#[derive(Debug, Clone)]
+pub struct ImportedPlaylist {
+    /// The file's name without its extension
+    pub name: String,
+    /// IDs of the matched tracks, in the file's order
+    pub track_ids: Vec<String>,
+    /// Entries with no match in the library
+    pub missing: usize,
+}
+
+/// Read the M3U or M3U8 playlist at `path` and match its entries to
+/// `tracks`. Blocks while reading.
+pub fn import_playlist(
+    path: &Path,
+    tracks: &[PlayableItem],
+) -> Result<ImportedPlaylist, Box<dyn Error + Send + Sync>> {
+    let bytes = fs::read(path)?;
+    // Plain .m3u files are often in a legacy encoding, which only costs
+    // the odd accented letter
+    let text = String::from_utf8_lossy(&bytes);
+    let base = path.parent().unwrap_or(Path::new(""));
+    let entries = parse_m3u(&text, base);
+    if entries.is_empty() {
+        return Err("The file has no tracks in it".into());
+    }
+
+    let mut by_path = HashMap::new();
+    let mut by_name = HashMap::new();
+    for item in tracks {
+        let track = &item.track;
+        if let PlaybackSource::Local { path, .. } = &track.source {
+            by_path.entry(normalize_path(path)).or_insert(&track.id);
+        }
+        by_name
+            .entry(name_key(&track.artist, &track.title))
+            .or_insert(&track.id);
+    }
+
+    let mut track_ids = Vec::new();
+    let mut missing = 0;
+    for entry in &entries {
+        let by_file = entry
+            .path
+            .as_ref()
+            .and_then(|path| by_path.get(&normalize_path(path)));
+        let by_tags = match (&entry.artist, &entry.title) {
+            (Some(artist), Some(title)) => by_name.get(&name_key(artist, title)),
+            _ => None,
+        };
+        match by_file.or(by_tags) {
+            Some(id) => track_ids.push(id.to_string()),
+            None => missing += 1,
+        }
+    }
+
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Imported Playlist".to_string());
+    Ok(ImportedPlaylist {
+        name,
+        track_ids,
+        missing,
+    })
+}
+
+/// Entries of an M3U playlist, with relative paths taken from `base`
+fn parse_m3u(text: &str, base: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut pending = PlaylistEntry::default();
+    for line in text.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // "#EXTINF:<seconds>,<artist> - <title>"
+            let display = info.split_once(',').map_or("", |(_, name)| name.trim());
+            match display.split_once(" - ") {
+                Some((artist, title)) => {
+                    pending.artist = Some(artist.trim().to_string());
+                    pending.title = Some(title.trim().to_string());
+                }
+                None => pending.title = Some(display.to_string()),
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        // Streams have no place in a library playlist, but their #EXTINF
+        // can still name a track
+        pending.path = if line.starts_with("file://") {
+            glib::filename_from_uri(line).ok().map(|(path, _)| path)
+        } else if line.contains("://") {
+            None
+        } else {
+            Some(base.join(line.replace('\\', "/")))
+        };
+        entries.push(std::mem::take(&mut pending));
+    }
+    entries
+}
+
+/// `path` with `.` and `..` resolved, so differently written paths to the
+/// same file match without touching the disk
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+fn name_key(artist: &str, title: &str) -> (String, String) {
+    (artist.trim().to_lowercase(), title.trim().to_lowercase())
+}
@@ -0,0 +1,416 @@
+//! Providers added by plugins, so services like Tidal or Qobuz can be
+//! added without changes to Nova. A plugin is a program Nova starts and
+//! talks to over its standard input and output, one JSON message per line:
+//!
+//! ```text
+//! → {"id": 1, "method": "search_tracks", "params": {"query": "…", "limit": 20, "offset": 0}}
+//! ← {"id": 1, "result": [...]}
+//! ← {"id": 1, "error": "Not signed in"}
+//! ```
+//!
+//! Methods and their parameters mirror MusicProvider, and results use the
+//! same JSON as Nova's models. Tracks a plugin streams have a `Stream`
+//! source with a URL GStreamer can play, and optionally a
+//! `low_quality_url` played instead on metered connections. The first
+//! request is always "hello" with the API version Nova speaks; the plugin
+//! answers with its own and is only used when they match.
+//!
+//! Each plugin is a folder in the plugins directory with a plugin.json
+//! manifest naming the program to run. Plugins only run once turned on.
+//!
+//! ```json
+//! {"id": "tidal", "name": "Tidal", "version": "0.1", "api_version": 1, "command": "tidal-plugin"}
+//! ```
+
+use crate::platform;
+use crate::services::models::{
+    Album, Artist, LyricsMatch, PlayableItem, RefreshMode, SearchResults, SearchWeights, Track,
+};
+use crate::services::traits::MusicProvider;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+/// Version of the plugin protocol. Changes that would break existing
+/// plugins bump it.
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "plugin.json";
+
+// Names Nova's own providers are registered under, which plugins can't take
+const BUILT_IN_PROVIDERS: &[&str] = &["local", "webdav"];
+
+// Longest a plugin gets to answer, since it's usually waiting on a service
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A plugin's plugin.json
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginManifest {
+    /// Unique name, also used as the provider name
+    pub id: String,
+    /// Name shown to the user
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub version: String,
+    /// Plugin protocol version the plugin speaks
+    pub api_version: u32,
+    /// Program to run, relative to the plugin's folder
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Folder the manifest was found in
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+impl PluginManifest {
+    /// Whether the plugin speaks the protocol this version of Nova does
+    pub fn is_compatible(&self) -> bool {
+        self.api_version == PLUGIN_API_VERSION
+    }
+}
+
+/// Folder plugins are installed in, one folder per plugin
+pub fn plugins_dir() -> PathBuf {
+    platform::data_dir().join("plugins")
+}
+
+/// Manifests of the installed plugins, by name. Folders without a readable
+/// manifest are skipped.
+pub fn discover_plugins() -> Vec<PluginManifest> {
+    let Ok(entries) = std::fs::read_dir(plugins_dir()) else {
+        return Vec::new();
+    };
+
+    let mut plugins: Vec<PluginManifest> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let path = entry.path().join(MANIFEST_FILE);
+            let manifest = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| {
+                    serde_json::from_str::<PluginManifest>(&text).map_err(|e| e.to_string())
+                });
+            match manifest {
+                Ok(manifest)
+                    if BUILT_IN_PROVIDERS.contains(&manifest.id.as_str())
+                        || manifest.id.is_empty() =>
+                {
+                    eprintln!("Plugin in {:?} has a reserved id", entry.path());
+                    None
+                }
+                Ok(manifest) => Some(PluginManifest {
+                    dir: entry.path(),
+                    ..manifest
+                }),
+                Err(e) => {
+                    eprintln!("Skipping plugin manifest {:?}: {}", path, e);
+                    None
+                }
+            }
+        })
+        .collect();
+    plugins.sort_by_key(|plugin| plugin.name.to_lowercase());
+    plugins
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    id: u64,
+    method: &'a str,
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    id: u64,
+    #[serde(default)]
+    result: Value,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Hello {
+    api_version: u32,
+}
+
+#[derive(Debug)]
+struct PluginProcess {
+    // Kept so the plugin is stopped along with the provider
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    next_id: u64,
+}
+
+/// A running plugin, used as a provider
+#[derive(Debug)]
+pub struct PluginProvider {
+    manifest: PluginManifest,
+    // Requests go one at a time, so responses come back in order
+    process: Mutex<PluginProcess>,
+}
+
+impl PluginProvider {
+    /// Start the plugin and check it speaks this version of the protocol
+    pub async fn start(manifest: PluginManifest) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if !manifest.is_compatible() {
+            return Err(format!(
+                "{} is for plugin API {}, Nova has {}",
+                manifest.name, manifest.api_version, PLUGIN_API_VERSION
+            )
+            .into());
+        }
+
+        let mut child = Command::new(manifest.dir.join(&manifest.command))
+            .args(&manifest.args)
+            .current_dir(&manifest.dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or("Plugin has no input")?;
+        let stdout = child.stdout.take().ok_or("Plugin has no output")?;
+
+        let provider = Self {
+            manifest,
+            process: Mutex::new(PluginProcess {
+                _child: child,
+                stdin,
+                stdout: BufReader::new(stdout).lines(),
+                next_id: 1,
+            }),
+        };
+
+        let hello: Hello = provider
+            .call("hello", json!({ "api_version": PLUGIN_API_VERSION }))
+            .await?;
+        if hello.api_version != PLUGIN_API_VERSION {
+            return Err(format!(
+                "{} speaks plugin API {}, Nova has {}",
+                provider.manifest.name, hello.api_version, PLUGIN_API_VERSION
+            )
+            .into());
+        }
+        Ok(provider)
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let mut process = self.process.lock().await;
+        let id = process.next_id;
+        process.next_id += 1;
+
+        let mut line = serde_json::to_string(&Request { id, method, params })?;
+        line.push('\n');
+        process.stdin.write_all(line.as_bytes()).await?;
+        process.stdin.flush().await?;
+
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, read_response(&mut process, id))
+            .await
+            .map_err(|_| format!("{} didn't answer {} in time", self.manifest.name, method))??
+            .ok_or_else(|| format!("{} stopped", self.manifest.name))?;
+
+        match response.error {
+            Some(error) => Err(format!("{}: {}", self.manifest.name, error).into()),
+            None => Ok(serde_json::from_value(response.result)?),
+        }
+    }
+
+    fn playable(&self, tracks: Vec<Track>) -> Vec<PlayableItem> {
+        tracks
+            .into_iter()
+            .map(|track| PlayableItem {
+                track,
+                provider: self.manifest.id.clone(),
+                added_at: Utc::now(),
+            })
+            .collect()
+    }
+}
+
+/// The response to request `id`, or None when the plugin has exited
+async fn read_response(
+    process: &mut PluginProcess,
+    id: u64,
+) -> Result<Option<Response>, std::io::Error> {
+    while let Some(line) = process.stdout.next_line().await? {
+        // Anything else on stdout is the plugin's own business
+        if let Ok(response) = serde_json::from_str::<Response>(&line) {
+            if response.id == id {
+                return Ok(Some(response));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn page(query: &str, limit: usize, offset: usize) -> Value {
+    json!({ "query": query, "limit": limit, "offset": offset })
+}
+
+#[async_trait]
+impl MusicProvider for PluginProvider {
+    async fn get_tracks(&self) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        self.call("get_tracks", json!({})).await
+    }
+
+    async fn get_albums(&self) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        self.call("get_albums", json!({})).await
+    }
+
+    async fn get_artists(&self) -> Result<Vec<Artist>, Box<dyn Error + Send + Sync>> {
+        self.call("get_artists", json!({})).await
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        self.search_tracks(query, limit, offset).await
+    }
+
+    async fn search_tracks(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        self.call("search_tracks", page(query, limit, offset)).await
+    }
+
+    async fn search_albums(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        self.call("search_albums", page(query, limit, offset)).await
+    }
+
+    async fn search_artists(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Artist>, Box<dyn Error + Send + Sync>> {
+        self.call("search_artists", page(query, limit, offset))
+            .await
+    }
+
+    async fn search_all(
+        &self,
+        query: &str,
+        _weights: &SearchWeights,
+        limit: usize,
+        offset: usize,
+    ) -> Result<SearchResults, Box<dyn Error + Send + Sync>> {
+        let tracks = self.search_tracks(query, limit, offset).await?;
+        let albums = self.search_albums(query, limit, offset).await?;
+        let artists = self.search_artists(query, limit, offset).await?;
+
+        Ok(SearchResults {
+            tracks: self.playable(tracks),
+            albums,
+            artists,
+            versions: HashMap::new(),
+        })
+    }
+
+    async fn search_lyrics(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<LyricsMatch>, Box<dyn Error + Send + Sync>> {
+        #[derive(Deserialize)]
+        struct Match {
+            track: Track,
+            snippet: String,
+        }
+
+        let matches: Vec<Match> = self
+            .call("search_lyrics", json!({ "query": query, "limit": limit }))
+            .await?;
+        Ok(matches
+            .into_iter()
+            .map(|found| LyricsMatch {
+                item: PlayableItem {
+                    track: found.track,
+                    provider: self.manifest.id.clone(),
+                    added_at: Utc::now(),
+                },
+                snippet: found.snippet,
+            })
+            .collect())
+    }
+
+    async fn find_album(
+        &self,
+        track: &Track,
+    ) -> Result<Option<Album>, Box<dyn Error + Send + Sync>> {
+        self.call("find_album", json!({ "track": track })).await
+    }
+
+    async fn find_artist(
+        &self,
+        track: &Track,
+    ) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>> {
+        self.call("find_artist", json!({ "track": track })).await
+    }
+
+    async fn get_track(&self, id: &str) -> Result<Option<Track>, Box<dyn Error + Send + Sync>> {
+        self.call("get_track", json!({ "id": id })).await
+    }
+
+    async fn get_album(&self, id: &str) -> Result<Option<Album>, Box<dyn Error + Send + Sync>> {
+        self.call("get_album", json!({ "id": id })).await
+    }
+
+    async fn get_artist(&self, id: &str) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>> {
+        self.call("get_artist", json!({ "id": id })).await
+    }
+
+    async fn get_album_tracks(
+        &self,
+        album_id: &str,
+    ) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        self.call("get_album_tracks", json!({ "album_id": album_id }))
+            .await
+    }
+
+    async fn get_artist_albums(
+        &self,
+        artist_id: &str,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        self.call("get_artist_albums", json!({ "artist_id": artist_id }))
+            .await
+    }
+
+    async fn refresh(&self, mode: RefreshMode) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mode = match mode {
+            RefreshMode::Incremental => "incremental",
+            RefreshMode::Full => "full",
+        };
+        let _: Value = self.call("refresh", json!({ "mode": mode })).await?;
+        Ok(())
+    }
+}
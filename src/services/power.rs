@@ -0,0 +1,43 @@
+//! Whether the computer is running on battery, as UPower reports it on the
+//! system bus. Without UPower it's taken to be on mains power.
+
+use gtk::prelude::*;
+use gtk::{gio, glib};
+
+const UPOWER_NAME: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const UPOWER_INTERFACE: &str = "org.freedesktop.UPower";
+
+/// Watches the power source for as long as it's kept
+#[derive(Debug)]
+pub struct BatteryWatch {
+    proxy: gio::DBusProxy,
+}
+
+impl BatteryWatch {
+    /// Start watching, calling `on_change` on the main context whenever
+    /// UPower's properties change
+    pub async fn start(on_change: impl Fn() + 'static) -> Result<Self, glib::Error> {
+        let proxy = gio::DBusProxy::for_bus_future(
+            gio::BusType::System,
+            gio::DBusProxyFlags::DO_NOT_AUTO_START,
+            None,
+            UPOWER_NAME,
+            UPOWER_PATH,
+            UPOWER_INTERFACE,
+        )
+        .await?;
+        proxy.connect_local("g-properties-changed", false, move |_| {
+            on_change();
+            None
+        });
+        Ok(Self { proxy })
+    }
+
+    pub fn on_battery(&self) -> bool {
+        self.proxy
+            .cached_property("OnBattery")
+            .and_then(|value| value.get::<bool>())
+            .unwrap_or(false)
+    }
+}
@@ -0,0 +1,171 @@
+//! Listings and searches the manager has put together across providers,
+//! kept for a short while so going back and forth between pages doesn't
+//! ask every provider again. Each is tagged with the library version it
+//! came from and is dropped once a provider reports a change.
+
+use crate::services::models::{Album, Artist, SearchResults, SearchWeights};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+// How long a listing is used, for providers that can't tell when they
+// change
+const TTL: Duration = Duration::from_secs(30);
+
+// Searches kept, the oldest being dropped first
+const SEARCH_CAPACITY: usize = 32;
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    stored: Instant,
+    version: u64,
+}
+
+#[derive(Debug)]
+struct Slot<K, V> {
+    entries: Mutex<HashMap<K, Entry<V>>>,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Slot<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+        }
+    }
+
+    fn get(&self, key: &K, version: u64) -> Option<V> {
+        let mut entries = self.entries.lock();
+        let entry = entries.get(key)?;
+        if entry.version == version && entry.stored.elapsed() < TTL {
+            return Some(entry.value.clone());
+        }
+        entries.remove(key);
+        None
+    }
+
+    fn insert(&self, key: K, value: V, version: u64) {
+        let mut entries = self.entries.lock();
+        entries.retain(|_, entry| entry.version == version && entry.stored.elapsed() < TTL);
+        if entries.len() >= self.capacity {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stored)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                value,
+                stored: Instant::now(),
+                version,
+            },
+        );
+    }
+
+    fn clear(&self) {
+        self.entries.lock().clear();
+    }
+}
+
+/// What a search was asked with, as a key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SearchKey {
+    query: String,
+    limit: usize,
+    offset: usize,
+    /// Bits of the track, album, artist, exact, artist match and recent
+    /// weights
+    weights: [u32; 6],
+    /// Bits of the provider weights, sorted by provider
+    provider_weights: Vec<(String, u32)>,
+    /// Bits of how recently tracks were played, sorted by track
+    recently_played: Vec<(String, u32)>,
+}
+
+impl SearchKey {
+    pub fn new(query: &str, weights: &SearchWeights, limit: usize, offset: usize) -> Self {
+        let mut provider_weights: Vec<(String, u32)> = weights
+            .provider_weights
+            .iter()
+            .map(|(name, weight)| (name.clone(), weight.to_bits()))
+            .collect();
+        provider_weights.sort();
+        let mut recently_played: Vec<(String, u32)> = weights
+            .recently_played
+            .iter()
+            .map(|(id, recency)| (id.clone(), recency.to_bits()))
+            .collect();
+        recently_played.sort();
+        Self {
+            query: query.to_string(),
+            limit,
+            offset,
+            weights: [
+                weights.track_weight.to_bits(),
+                weights.album_weight.to_bits(),
+                weights.artist_weight.to_bits(),
+                weights.exact_weight.to_bits(),
+                weights.artist_match_weight.to_bits(),
+                weights.recent_weight.to_bits(),
+            ],
+            provider_weights,
+            recently_played,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct QueryCache {
+    albums: Slot<(), Vec<Album>>,
+    artists: Slot<(), Vec<Artist>>,
+    searches: Slot<SearchKey, SearchResults>,
+}
+
+impl QueryCache {
+    pub fn new() -> Self {
+        Self {
+            albums: Slot::new(1),
+            artists: Slot::new(1),
+            searches: Slot::new(SEARCH_CAPACITY),
+        }
+    }
+
+    pub fn albums(&self, version: u64) -> Option<Vec<Album>> {
+        self.albums.get(&(), version)
+    }
+
+    pub fn store_albums(&self, albums: Vec<Album>, version: u64) {
+        self.albums.insert((), albums, version);
+    }
+
+    pub fn artists(&self, version: u64) -> Option<Vec<Artist>> {
+        self.artists.get(&(), version)
+    }
+
+    pub fn store_artists(&self, artists: Vec<Artist>, version: u64) {
+        self.artists.insert((), artists, version);
+    }
+
+    pub fn search(&self, key: &SearchKey, version: u64) -> Option<SearchResults> {
+        self.searches.get(key, version)
+    }
+
+    pub fn store_search(&self, key: SearchKey, results: SearchResults, version: u64) {
+        self.searches.insert(key, results, version);
+    }
+
+    /// Drop everything, for changes the library version doesn't cover such
+    /// as providers coming and going
+    pub fn clear(&self) {
+        self.albums.clear();
+        self.artists.clear();
+        self.searches.clear();
+    }
+}
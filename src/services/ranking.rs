@@ -0,0 +1,266 @@
+use crate::services::models::{
+    Album, Artist, PlayableItem, ScoredResult, SearchResultType, SearchResults, SearchWeights,
+};
+use std::cmp::Ordering;
+use std::error::Error;
+
+// Most a track played just now is lifted by, before the recent weight
+const RECENT_BONUS: f32 = 600.0;
+
+/// Ready-made sets of weights for search, chosen in preferences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RankingProfile {
+    Balanced,
+    /// Put exact title and name matches well ahead of partial ones
+    ExactTitles,
+    /// Put artists, and tracks and albums by a matching artist, first
+    Artists,
+    /// Lift matching tracks that were played lately
+    RecentlyPlayed,
+    /// Weights written by the user, see `parse_weights`
+    Custom,
+}
+
+impl RankingProfile {
+    pub fn from_setting(value: &str) -> Self {
+        match value {
+            "exact-titles" => RankingProfile::ExactTitles,
+            "artists" => RankingProfile::Artists,
+            "recently-played" => RankingProfile::RecentlyPlayed,
+            "custom" => RankingProfile::Custom,
+            _ => RankingProfile::Balanced,
+        }
+    }
+
+    /// The profile's weights. Custom ones start out balanced, to be
+    /// replaced by `parse_weights`.
+    pub fn weights(self) -> SearchWeights {
+        let mut weights = SearchWeights::default();
+        match self {
+            RankingProfile::Balanced | RankingProfile::Custom => {}
+            RankingProfile::ExactTitles => weights.exact_weight = 3.0,
+            RankingProfile::Artists => {
+                weights.artist_weight = 2.0;
+                weights.artist_match_weight = 3.0;
+            }
+            RankingProfile::RecentlyPlayed => weights.recent_weight = 1.0,
+        }
+        weights
+    }
+}
+
+/// Weights from a config such as "artist=2 exact=1.5 provider.local=2",
+/// with anything left out kept balanced. The keys are track, album,
+/// artist, exact, artist-match, recent and provider.<name>.
+pub fn parse_weights(config: &str) -> Result<SearchWeights, Box<dyn Error + Send + Sync>> {
+    let mut weights = SearchWeights::default();
+    for setting in config.split(|c: char| c == ',' || c.is_whitespace()) {
+        if setting.is_empty() {
+            continue;
+        }
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("“{}” has no value", setting))?;
+        let value: f32 = value
+            .parse()
+            .map_err(|_| format!("“{}” isn't a number", value))?;
+        if !value.is_finite() || value < 0.0 {
+            return Err(format!("“{}” must be 0 or more", key).into());
+        }
+        match key {
+            "track" => weights.track_weight = value,
+            "album" => weights.album_weight = value,
+            "artist" => weights.artist_weight = value,
+            "exact" => weights.exact_weight = value,
+            "artist-match" => weights.artist_match_weight = value,
+            "recent" => weights.recent_weight = value,
+            _ => {
+                let provider = key
+                    .strip_prefix("provider.")
+                    .filter(|provider| !provider.is_empty())
+                    .ok_or_else(|| format!("“{}” isn't a weight", key))?;
+                weights.provider_weights.insert(provider.to_string(), value);
+            }
+        }
+    }
+    Ok(weights)
+}
+
+/// Relevance of a track to the query, scaled by the track and provider weights
+pub fn score_track(item: &PlayableItem, query: &str, weights: &SearchWeights) -> f32 {
+    let query = query.to_lowercase();
+    let track = &item.track;
+
+    // Primary matches (high weight for track-specific fields)
+    let title_exact = if track.title.to_lowercase() == query {
+        1200.0
+    } else {
+        0.0
+    };
+    let title_contains = if track.title.to_lowercase().contains(&query) {
+        600.0
+    } else {
+        0.0
+    };
+
+    // Secondary matches (lower weight for related fields)
+    let artist_exact = if track.artist.to_lowercase() == query {
+        300.0
+    } else {
+        0.0
+    };
+    let artist_contains = if track.artist.to_lowercase().contains(&query) {
+        150.0
+    } else {
+        0.0
+    };
+    let album_exact = if track.album.to_lowercase() == query {
+        200.0
+    } else {
+        0.0
+    };
+    let album_contains = if track.album.to_lowercase().contains(&query) {
+        100.0
+    } else {
+        0.0
+    };
+
+    let mut score = title_exact * weights.exact_weight
+        + title_contains
+        + (artist_exact * weights.exact_weight + artist_contains) * weights.artist_match_weight
+        + album_exact * weights.exact_weight
+        + album_contains;
+
+    // Only tracks that match at all are lifted for being played lately
+    if score > 0.0 {
+        let recency = weights
+            .recently_played
+            .get(&track.id)
+            .copied()
+            .unwrap_or(0.0);
+        score += recency * RECENT_BONUS * weights.recent_weight;
+    }
+
+    score * weights.track_weight * weights.provider_weight(&item.provider)
+}
+
+/// Relevance of an artist to the query, scaled by the artist weight
+pub fn score_artist(artist: &Artist, query: &str, weights: &SearchWeights) -> f32 {
+    let query = query.to_lowercase();
+
+    // Primary matches (high weight for artist-specific fields)
+    let name_exact = if artist.name.to_lowercase() == query {
+        1200.0
+    } else {
+        0.0
+    };
+    let name_contains = if artist.name.to_lowercase().contains(&query) {
+        600.0
+    } else {
+        0.0
+    };
+
+    (name_exact * weights.exact_weight + name_contains) * weights.artist_weight
+}
+
+/// Relevance of an album to the query, scaled by the album weight
+pub fn score_album(album: &Album, query: &str, weights: &SearchWeights) -> f32 {
+    let query = query.to_lowercase();
+
+    // Primary matches (high weight for album-specific fields)
+    let title_exact = if album.title.to_lowercase() == query {
+        1200.0
+    } else {
+        0.0
+    };
+    let title_contains = if album.title.to_lowercase().contains(&query) {
+        600.0
+    } else {
+        0.0
+    };
+
+    // Secondary matches (lower weight for related fields)
+    let artist_exact = if album.artist.to_lowercase() == query {
+        300.0
+    } else {
+        0.0
+    };
+    let artist_contains = if album.artist.to_lowercase().contains(&query) {
+        150.0
+    } else {
+        0.0
+    };
+
+    // Additional score for release year if query is a year
+    let year_score = if let Some(year) = album.year {
+        if query == year.to_string() {
+            400.0
+        } else {
+            0.0
+        }
+    } else {
+        0.0
+    };
+
+    (title_exact * weights.exact_weight
+        + title_contains
+        + (artist_exact * weights.exact_weight + artist_contains) * weights.artist_match_weight
+        + year_score)
+        * weights.album_weight
+}
+
+fn by_score_desc(a: f32, b: f32) -> Ordering {
+    b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+}
+
+/// Order tracks from most to least relevant
+pub fn rank_tracks(tracks: &mut [PlayableItem], query: &str, weights: &SearchWeights) {
+    tracks.sort_by(|a, b| {
+        by_score_desc(
+            score_track(a, query, weights),
+            score_track(b, query, weights),
+        )
+    });
+}
+
+/// Order every section of the results from most to least relevant
+pub fn rank_results(results: &mut SearchResults, query: &str, weights: &SearchWeights) {
+    rank_tracks(&mut results.tracks, query, weights);
+    results.artists.sort_by(|a, b| {
+        by_score_desc(
+            score_artist(a, query, weights),
+            score_artist(b, query, weights),
+        )
+    });
+    results.albums.sort_by(|a, b| {
+        by_score_desc(
+            score_album(a, query, weights),
+            score_album(b, query, weights),
+        )
+    });
+}
+
+/// The single most relevant result across all sections
+pub fn top_result(
+    results: &SearchResults,
+    query: &str,
+    weights: &SearchWeights,
+) -> Option<ScoredResult> {
+    let tracks = results.tracks.iter().map(|item| ScoredResult {
+        score: score_track(item, query, weights),
+        result_type: SearchResultType::Track(item.clone()),
+    });
+    let artists = results.artists.iter().map(|artist| ScoredResult {
+        score: score_artist(artist, query, weights),
+        result_type: SearchResultType::Artist(artist.clone()),
+    });
+    let albums = results.albums.iter().map(|album| ScoredResult {
+        score: score_album(album, query, weights),
+        result_type: SearchResultType::Album(album.clone()),
+    });
+
+    tracks
+        .chain(artists)
+        .chain(albums)
+        .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal))
+}
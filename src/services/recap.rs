@@ -0,0 +1,272 @@
+//! Listening recaps for a month or a year, worked out from the play
+//! history on this device alone, and written out as a page to share
+
+use crate::services::models::HistoryEntry;
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Utc};
+use std::collections::{HashMap, HashSet};
+
+// Entries in each top list
+const TOP_COUNT: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecapPeriod {
+    Month { year: i32, month: u32 },
+    Year(i32),
+}
+
+impl RecapPeriod {
+    pub fn this_month() -> Self {
+        let today = Local::now().date_naive();
+        Self::Month {
+            year: today.year(),
+            month: today.month(),
+        }
+    }
+
+    pub fn last_month() -> Self {
+        match Self::this_month() {
+            Self::Month { year, month: 1 } => Self::Month {
+                year: year - 1,
+                month: 12,
+            },
+            Self::Month { year, month } => Self::Month {
+                year,
+                month: month - 1,
+            },
+            year => year,
+        }
+    }
+
+    pub fn this_year() -> Self {
+        Self::Year(Local::now().year())
+    }
+
+    pub fn last_year() -> Self {
+        Self::Year(Local::now().year() - 1)
+    }
+
+    /// "March 2025" or "2025"
+    pub fn title(&self) -> String {
+        match *self {
+            Self::Month { year, month } => NaiveDate::from_ymd_opt(year, month, 1)
+                .map(|date| date.format("%B %Y").to_string())
+                .unwrap_or_else(|| year.to_string()),
+            Self::Year(year) => year.to_string(),
+        }
+    }
+
+    /// When the period starts
+    pub fn start(&self) -> Option<DateTime<Utc>> {
+        self.bounds().map(|(start, _)| start)
+    }
+
+    /// Start and end of the period in local time
+    fn bounds(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let (start, end) = match *self {
+            Self::Month { year, month } => {
+                let (next_year, next_month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                (
+                    NaiveDate::from_ymd_opt(year, month, 1)?,
+                    NaiveDate::from_ymd_opt(next_year, next_month, 1)?,
+                )
+            }
+            Self::Year(year) => (
+                NaiveDate::from_ymd_opt(year, 1, 1)?,
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+            ),
+        };
+        let local = |date: NaiveDate| {
+            Local
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+        };
+        Some((local(start)?, local(end)?))
+    }
+}
+
+/// A track among the most played
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopTrack {
+    pub title: String,
+    pub artist: String,
+    pub plays: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recap {
+    pub title: String,
+    pub plays: u32,
+    pub minutes: u32,
+    pub days_listened: usize,
+    pub top_artists: Vec<(String, u32)>,
+    pub top_tracks: Vec<TopTrack>,
+    /// Artists and tracks first played in the period
+    pub new_artists: usize,
+    pub new_tracks: usize,
+    /// Whether the history reaches back to the start of the period, so
+    /// the numbers cover all of it
+    pub complete: bool,
+}
+
+impl Recap {
+    /// Recap of `period` from `history`, oldest first, with skipped plays
+    /// already left out
+    pub fn build(period: RecapPeriod, history: &[HistoryEntry]) -> Self {
+        let mut recap = Self {
+            title: period.title(),
+            ..Self::default()
+        };
+        let Some((start, end)) = period.bounds() else {
+            return recap;
+        };
+        recap.complete = history
+            .first()
+            .is_some_and(|entry| entry.played_at <= start);
+
+        let mut earlier_artists = HashSet::new();
+        let mut earlier_tracks = HashSet::new();
+        let mut artists: HashMap<&str, u32> = HashMap::new();
+        let mut tracks: HashMap<&str, (u32, &HistoryEntry)> = HashMap::new();
+        let mut days = HashSet::new();
+        let mut new_artists = HashSet::new();
+        let mut new_tracks = HashSet::new();
+        let mut seconds = 0u64;
+        for entry in history {
+            if entry.played_at < start {
+                earlier_artists.insert(entry.artist.as_str());
+                earlier_tracks.insert(entry.track_id.as_str());
+                continue;
+            }
+            if entry.played_at >= end {
+                break;
+            }
+            recap.plays += 1;
+            seconds += u64::from(entry.duration);
+            days.insert(entry.played_at.with_timezone(&Local).date_naive());
+            *artists.entry(&entry.artist).or_default() += 1;
+            tracks.entry(&entry.track_id).or_insert((0, entry)).0 += 1;
+            if !earlier_artists.contains(entry.artist.as_str()) {
+                new_artists.insert(entry.artist.as_str());
+            }
+            if !earlier_tracks.contains(entry.track_id.as_str()) {
+                new_tracks.insert(entry.track_id.as_str());
+            }
+        }
+        recap.minutes = (seconds / 60) as u32;
+        recap.days_listened = days.len();
+        recap.new_artists = new_artists.len();
+        recap.new_tracks = new_tracks.len();
+
+        let mut artists: Vec<(String, u32)> = artists
+            .into_iter()
+            .map(|(artist, plays)| (artist.to_string(), plays))
+            .collect();
+        artists.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        artists.truncate(TOP_COUNT);
+        recap.top_artists = artists;
+
+        let mut tracks: Vec<TopTrack> = tracks
+            .into_values()
+            .map(|(plays, entry)| TopTrack {
+                title: entry.title.clone(),
+                artist: entry.artist.clone(),
+                plays,
+            })
+            .collect();
+        tracks.sort_by(|a, b| b.plays.cmp(&a.plays).then_with(|| a.title.cmp(&b.title)));
+        tracks.truncate(TOP_COUNT);
+        recap.top_tracks = tracks;
+        recap
+    }
+
+    /// The recap as a page that stands alone, with nothing loaded from
+    /// elsewhere
+    pub fn to_html(&self) -> String {
+        let list = |items: Vec<String>| {
+            items
+                .iter()
+                .map(|item| format!("<li>{}</li>", item))
+                .collect::<String>()
+        };
+        let artists = list(
+            self.top_artists
+                .iter()
+                .map(|(artist, plays)| format!("{} <span>{} plays</span>", escape(artist), plays))
+                .collect(),
+        );
+        let tracks = list(
+            self.top_tracks
+                .iter()
+                .map(|track| {
+                    format!(
+                        "{} <span>{} • {} plays</span>",
+                        escape(&track.title),
+                        escape(&track.artist),
+                        track.plays
+                    )
+                })
+                .collect(),
+        );
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Nova Recap – {title}</title>
+<style>
+body {{ font-family: sans-serif; background: #1e1b2e; color: #f4f1ff; margin: 0; }}
+main {{ max-width: 36em; margin: 2em auto; padding: 2em; border-radius: 1.5em;
+       background: linear-gradient(160deg, #4a3aa8, #a23a78); }}
+h1 {{ margin-top: 0; }}
+.numbers {{ display: flex; gap: 2em; flex-wrap: wrap; }}
+.numbers strong {{ display: block; font-size: 2em; }}
+span {{ opacity: 0.75; font-size: 0.9em; }}
+footer {{ opacity: 0.6; font-size: 0.8em; margin-top: 2em; }}
+</style>
+</head>
+<body>
+<main>
+<h1>{title}</h1>
+<div class="numbers">
+<p><strong>{minutes}</strong>minutes</p>
+<p><strong>{plays}</strong>plays</p>
+<p><strong>{days}</strong>days listening</p>
+</div>
+<p>Discovered {new_artists} new artists and {new_tracks} new tracks.</p>
+<h2>Top Artists</h2>
+<ol>{artists}</ol>
+<h2>Top Tracks</h2>
+<ol>{tracks}</ol>
+<footer>{note}Made by Nova from listening on this device</footer>
+</main>
+</body>
+</html>
+"#,
+            title = escape(&self.title),
+            minutes = self.minutes,
+            plays = self.plays,
+            days = self.days_listened,
+            new_artists = self.new_artists,
+            new_tracks = self.new_tracks,
+            artists = artists,
+            tracks = tracks,
+            note = if self.complete {
+                ""
+            } else {
+                "Older plays are no longer kept, so this covers part of the period. "
+            },
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
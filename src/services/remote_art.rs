@@ -0,0 +1,98 @@
+//! Covers from streaming providers, downloaded once and kept on disk so
+//! they show straight away next time, and while offline. A cover that
+//! fails to download isn't asked for again until RETRY_INTERVAL has
+//! passed, so a missing image doesn't cost a request for every card.
+
+use crate::platform;
+use crate::services::http::HttpClient;
+use parking_lot::Mutex;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Anything bigger isn't a cover
+const MAX_ART_BYTES: usize = 16 * 1024 * 1024;
+
+fn client() -> &'static HttpClient {
+    static CLIENT: OnceLock<HttpClient> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(20))
+            .build()
+            .unwrap_or_default();
+        HttpClient::new(client)
+    })
+}
+
+// When each cover last failed to download, by file
+fn failures() -> &'static Mutex<HashMap<PathBuf, Instant>> {
+    static FAILURES: OnceLock<Mutex<HashMap<PathBuf, Instant>>> = OnceLock::new();
+    FAILURES.get_or_init(Default::default)
+}
+
+/// Where the cover at `url` is kept. Providers give a `cache_key` when the
+/// same image is served from changing URLs, such as signed ones.
+fn file_for(url: &str, cache_key: Option<&str>) -> PathBuf {
+    let key = cache_key.unwrap_or(url);
+    platform::cache_dir()
+        .join("artwork")
+        .join(format!("{:x}", Sha1::digest(key)))
+}
+
+/// The downloaded copy of the cover at `url`, if there is one
+pub fn cached_art(url: &str, cache_key: Option<&str>) -> Option<PathBuf> {
+    let path = file_for(url, cache_key);
+    path.is_file().then_some(path)
+}
+
+/// Download the cover at `url` into the cache, or find it already there
+pub async fn fetch_art(
+    url: &str,
+    cache_key: Option<&str>,
+) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+    let path = file_for(url, cache_key);
+    if path.is_file() {
+        return Ok(path);
+    }
+    if let Some(failed) = failures().lock().get(&path) {
+        if failed.elapsed() < RETRY_INTERVAL {
+            return Err("The cover failed to download a moment ago".into());
+        }
+    }
+
+    let result = download(url, &path).await;
+    match &result {
+        Ok(()) => failures().lock().remove(&path),
+        Err(_) => failures().lock().insert(path.clone(), Instant::now()),
+    };
+    result.map(|()| path)
+}
+
+async fn download(url: &str, path: &PathBuf) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let client = client();
+    let fetched = client.fetch(client.get(url)).await?;
+    if !fetched.status.is_success() {
+        return Err(format!("The server answered {}", fetched.status).into());
+    }
+    if fetched.body.is_empty() || fetched.body.len() > MAX_ART_BYTES {
+        return Err(format!("Not a cover: {} bytes", fetched.body.len()).into());
+    }
+    // Error pages are sometimes served with a success status
+    image::guess_format(&fetched.body)?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Written aside and moved into place, so a half-written file never
+    // counts as cached
+    let partial = path.with_extension("part");
+    fs::write(&partial, &fetched.body)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Low-memory mode for old laptops and single-board computers. It trades
+//! speed and artwork quality for a smaller footprint: no artwork is kept
+//! around in memory, the database cache is smaller, thumbnails are smaller
+//! and background work is spread out.
+//!
+//! Scan priority throttles library scans the same way, for anyone who'd
+//! rather a scan took longer than made the rest of the computer slower.
+
+use crate::services::models::Thumbnails;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::Duration;
+
+static LOW_MEMORY: AtomicBool = AtomicBool::new(false);
+static SCAN_PRIORITY: AtomicU8 = AtomicU8::new(ScanPriority::Full as u8);
+
+// SQLite cache_size values; negative values are in KiB rather than pages
+const DB_CACHE_SIZE: i64 = 10000;
+const LOW_MEMORY_DB_CACHE_SIZE: i64 = -2048;
+
+// Pause between scan batches so a scan doesn't hog a slow CPU
+const LOW_MEMORY_SCAN_PAUSE: Duration = Duration::from_millis(50);
+
+// Pauses between scan batches at the lower scan priorities
+const LOW_PRIORITY_SCAN_PAUSE: Duration = Duration::from_millis(50);
+const IDLE_PRIORITY_SCAN_PAUSE: Duration = Duration::from_millis(250);
+
+// Files read between pauses
+const SCAN_BATCH_SIZE: usize = 5;
+
+/// How hard library scans work the computer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanPriority {
+    /// A file at a time with long pauses, leaving the CPU and disk to
+    /// everything else
+    Idle,
+    /// Batches with short pauses between them
+    Low,
+    /// As fast as the files can be read
+    Full,
+}
+
+impl ScanPriority {
+    pub fn from_setting(value: &str) -> Self {
+        match value {
+            "idle" => ScanPriority::Idle,
+            "low" => ScanPriority::Low,
+            _ => ScanPriority::Full,
+        }
+    }
+}
+
+pub fn set_low_memory(enabled: bool) {
+    LOW_MEMORY.store(enabled, Ordering::Relaxed);
+}
+
+pub fn low_memory() -> bool {
+    LOW_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Takes effect from the next batch, so a scan already running slows down
+/// or speeds up too
+pub fn set_scan_priority(priority: ScanPriority) {
+    SCAN_PRIORITY.store(priority as u8, Ordering::Relaxed);
+}
+
+fn scan_priority() -> ScanPriority {
+    match SCAN_PRIORITY.load(Ordering::Relaxed) {
+        p if p == ScanPriority::Idle as u8 => ScanPriority::Idle,
+        p if p == ScanPriority::Low as u8 => ScanPriority::Low,
+        _ => ScanPriority::Full,
+    }
+}
+
+/// cache_size PRAGMA for new database connections
+pub fn db_cache_size() -> i64 {
+    if low_memory() {
+        LOW_MEMORY_DB_CACHE_SIZE
+    } else {
+        DB_CACHE_SIZE
+    }
+}
+
+/// How many database connections to keep open
+pub fn db_pool_size() -> u32 {
+    if low_memory() {
+        2
+    } else {
+        4
+    }
+}
+
+/// Edge lengths of the small, medium and large thumbnails to generate
+pub fn thumbnail_sizes() -> (u32, u32, u32) {
+    if low_memory() {
+        (48, 96, 160)
+    } else {
+        (Thumbnails::SMALL, Thumbnails::MEDIUM, Thumbnails::LARGE)
+    }
+}
+
+/// Pause between batches of background work, if any. The longer of the
+/// scan priority's and low-memory mode's wins.
+pub fn background_pause() -> Option<Duration> {
+    let priority_pause = match scan_priority() {
+        ScanPriority::Idle => Some(IDLE_PRIORITY_SCAN_PAUSE),
+        ScanPriority::Low => Some(LOW_PRIORITY_SCAN_PAUSE),
+        ScanPriority::Full => None,
+    };
+    priority_pause.max(low_memory().then_some(LOW_MEMORY_SCAN_PAUSE))
+}
+
+/// Files a scan reads between pauses
+pub fn scan_batch_size() -> usize {
+    match scan_priority() {
+        ScanPriority::Idle => 1,
+        ScanPriority::Low | ScanPriority::Full => SCAN_BATCH_SIZE,
+    }
+}
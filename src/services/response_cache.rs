@@ -0,0 +1,122 @@
+//! Metadata responses kept on disk, so lookups made before come back at once
+//! and still work offline. Each response is a file named after its URL and
+//! dated by its modification time; how long one stays fresh depends on the
+//! service it came from. Past MAX_CACHE_BYTES the oldest are dropped.
+
+use crate::platform;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Room for tens of thousands of lookups
+const MAX_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+
+// How long responses from each host are used before asking again. Hosts
+// not listed aren't kept on disk.
+const HOST_TTLS: &[(&str, Duration)] = &[
+    // Biographies and similar artists change now and then
+    ("audioscrobbler.com", DAY.saturating_mul(7)),
+    ("musicbrainz.org", DAY.saturating_mul(30)),
+];
+
+fn cache_dir() -> PathBuf {
+    platform::cache_dir().join("responses")
+}
+
+fn file_for(url: &str) -> PathBuf {
+    cache_dir().join(format!("{:x}", Sha1::digest(url)))
+}
+
+/// How long responses from `host` stay fresh, or None when they aren't kept
+pub fn ttl_for(host: &str) -> Option<Duration> {
+    HOST_TTLS
+        .iter()
+        .find(|(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))
+        .map(|(_, ttl)| *ttl)
+}
+
+/// The kept response for `url` if it's younger than `max_age`, or of any age
+/// when that's None
+pub async fn load(url: &str, max_age: Option<Duration>) -> Option<Vec<u8>> {
+    let path = file_for(url);
+    tokio::task::spawn_blocking(move || read_fresh(&path, max_age))
+        .await
+        .ok()
+        .flatten()
+}
+
+fn read_fresh(path: &Path, max_age: Option<Duration>) -> Option<Vec<u8>> {
+    if let Some(max_age) = max_age {
+        let age = fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age > max_age {
+            return None;
+        }
+    }
+    fs::read(path).ok()
+}
+
+/// Keep `body` as the response for `url`, in the background
+pub fn store(url: &str, body: Vec<u8>) {
+    let (url, path) = (url.to_string(), file_for(url));
+    tokio::task::spawn_blocking(move || {
+        let result = fs::create_dir_all(cache_dir()).and_then(|_| {
+            // Written under another name first, so a reader never sees half
+            let partial = path.with_extension("part");
+            fs::write(&partial, body)?;
+            fs::rename(partial, path)
+        });
+        match result {
+            Ok(()) => evict(),
+            Err(e) => eprintln!("Failed to keep response for {}: {}", url, e),
+        }
+    });
+}
+
+/// Remove the responses kept longest ago until the cache fits its bound
+fn evict() {
+    let Ok(entries) = fs::read_dir(cache_dir()) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_none())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    files.sort();
+
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    for (_, size, path) in files {
+        if total <= MAX_CACHE_BYTES {
+            break;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => total -= size,
+            Err(e) => eprintln!("Failed to evict {:?} from the response cache: {}", path, e),
+        }
+    }
+}
+
+/// Delete every kept response, returning the bytes freed
+pub fn clear() -> io::Result<u64> {
+    let entries = match fs::read_dir(cache_dir()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut freed = 0;
+    for entry in entries.flatten() {
+        let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        match fs::remove_file(entry.path()) {
+            Ok(()) => freed += size,
+            Err(e) => eprintln!("Failed to delete {:?}: {}", entry.path(), e),
+        }
+    }
+    Ok(freed)
+}
@@ -0,0 +1,162 @@
+//! Listens sent to ListenBrainz. Every listen goes into the user store
+//! first and is sent from there, so ones made offline or while the service
+//! is down go out later, oldest first.
+
+use crate::services::http::HttpClient;
+use crate::services::models::Track;
+use crate::services::UserStore;
+use chrono::{DateTime, Utc};
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Client, StatusCode};
+use serde_json::json;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+const SUBMIT_URL: &str = "https://api.listenbrainz.org/1/submit-listens";
+
+// Listens sent in one request
+const BATCH_SIZE: usize = 50;
+
+// Tracks shorter than this are never counted, and longer ones count once
+// half of them or LISTEN_THRESHOLD has played, whichever comes first
+const MIN_TRACK_LENGTH: Duration = Duration::from_secs(30);
+const LISTEN_THRESHOLD: Duration = Duration::from_secs(4 * 60);
+
+/// Whether `played` of a track `length` long counts as a listen
+pub fn is_listen(length: Duration, played: Duration) -> bool {
+    length > MIN_TRACK_LENGTH && played >= (length / 2).min(LISTEN_THRESHOLD)
+}
+
+/// A listen waiting to be sent
+#[derive(Debug, Clone)]
+pub struct PendingListen {
+    pub id: i64,
+    pub artist: String,
+    pub title: String,
+    pub album: String,
+    pub duration: u32,
+    pub listened_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct Scrobbler {
+    client: HttpClient,
+    store: Arc<UserStore>,
+    token: String,
+    // Held while sending, so the same listens don't go out twice
+    sending: tokio::sync::Mutex<()>,
+}
+
+impl Scrobbler {
+    pub fn new(store: Arc<UserStore>, token: &str) -> Self {
+        Self {
+            client: HttpClient::new(Client::new()),
+            store,
+            token: token.to_string(),
+            sending: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Queue a listen of `track` that started at `listened_at`
+    pub fn record(
+        &self,
+        track: &Track,
+        listened_at: DateTime<Utc>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.store.queue_listen(track, listened_at)
+    }
+
+    /// Send every queued listen, returning how many went out. Stops at the
+    /// first batch that fails, leaving it and the rest for the next try.
+    /// Listens the service refuses are dropped.
+    pub async fn flush(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        // Another flush is already sending them
+        let Ok(_sending) = self.sending.try_lock() else {
+            return Ok(0);
+        };
+
+        let mut sent = 0;
+        loop {
+            let pending = self.store.pending_listens(BATCH_SIZE)?;
+            if pending.is_empty() {
+                return Ok(sent);
+            }
+            let status = self.submit(&pending).await?;
+            if status == StatusCode::BAD_REQUEST && pending.len() > 1 {
+                // The service doesn't say which listens it refused, so
+                // the batch goes again one at a time to find them
+                for listen in &pending {
+                    let status = self.submit(std::slice::from_ref(listen)).await?;
+                    if accepted(status)? {
+                        sent += 1;
+                    } else {
+                        eprintln!(
+                            "ListenBrainz refused the listen of {} by {}, dropping it",
+                            listen.title, listen.artist
+                        );
+                    }
+                    self.store.remove_listens(&[listen.id])?;
+                }
+                continue;
+            }
+            if accepted(status)? {
+                sent += pending.len();
+            } else {
+                // Only ever a single listen, as batches are split up above
+                eprintln!(
+                    "ListenBrainz refused the listen of {} by {}, dropping it",
+                    pending[0].title, pending[0].artist
+                );
+            }
+            let ids: Vec<i64> = pending.iter().map(|listen| listen.id).collect();
+            self.store.remove_listens(&ids)?;
+        }
+    }
+
+    async fn submit(
+        &self,
+        listens: &[PendingListen],
+    ) -> Result<StatusCode, Box<dyn Error + Send + Sync>> {
+        let payload: Vec<_> = listens
+            .iter()
+            .map(|listen| {
+                json!({
+                    "listened_at": listen.listened_at.timestamp(),
+                    "track_metadata": {
+                        "artist_name": listen.artist,
+                        "track_name": listen.title,
+                        "release_name": listen.album,
+                        "additional_info": {
+                            "duration_ms": u64::from(listen.duration) * 1000,
+                            "media_player": "Nova",
+                        },
+                    },
+                })
+            })
+            .collect();
+        let listen_type = if listens.len() == 1 {
+            "single"
+        } else {
+            "import"
+        };
+        let request = self
+            .client
+            .request(reqwest::Method::POST, SUBMIT_URL)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .json(&json!({ "listen_type": listen_type, "payload": payload }));
+        Ok(self.client.send(request).await?.status())
+    }
+}
+
+/// Whether the service took what was sent, or refused it as it is. Errors
+/// are for answers where trying again later may work.
+fn accepted(status: StatusCode) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    match status {
+        status if status.is_success() => Ok(true),
+        // Retrying a listen the service won't take never helps
+        StatusCode::BAD_REQUEST => Ok(false),
+        StatusCode::UNAUTHORIZED => Err("ListenBrainz didn't accept the token".into()),
+        status => Err(format!("ListenBrainz answered {}", status).into()),
+    }
+}
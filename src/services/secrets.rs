@@ -0,0 +1,86 @@
+//! Passwords and tokens, kept in the system's secret store (the Secret
+//! Service, or the secrets portal inside Flatpak) rather than in the
+//! settings, which any app of the user can read
+
+use crate::config::APP_ID;
+use oo7::{Keyring, Secret};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+
+/// Password of the WebDAV server
+pub const WEBDAV_PASSWORD: &str = "webdav-password";
+
+/// User token for sending listens to ListenBrainz
+pub const LISTENBRAINZ_TOKEN: &str = "listenbrainz-token";
+
+fn attributes(key: &str) -> HashMap<&str, &str> {
+    HashMap::from([("application", APP_ID), ("key", key)])
+}
+
+// What the user's keyring app lists the secret as
+fn label(key: &str) -> &'static str {
+    match key {
+        WEBDAV_PASSWORD => "Nova WebDAV password",
+        LISTENBRAINZ_TOKEN => "Nova ListenBrainz token",
+        _ => "Nova",
+    }
+}
+
+fn changes() -> &'static broadcast::Sender<&'static str> {
+    static CHANGES: OnceLock<broadcast::Sender<&'static str>> = OnceLock::new();
+    CHANGES.get_or_init(|| broadcast::channel(16).0)
+}
+
+/// Keys of secrets as they're stored from now on
+pub fn subscribe() -> broadcast::Receiver<&'static str> {
+    changes().subscribe()
+}
+
+/// The secret kept under `key`, if there is one
+pub async fn load(key: &'static str) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    // The keyring talks D-Bus on the runtime, whichever loop awaits this
+    tokio::spawn(async move {
+        let keyring = Keyring::new().await?;
+        keyring.unlock().await?;
+        let items = keyring.search_items(&attributes(key)).await?;
+        let Some(item) = items.first() else {
+            return Ok(None);
+        };
+        let secret = item.secret().await?;
+        Ok(Some(String::from_utf8(secret.as_bytes().to_vec())?))
+    })
+    .await?
+}
+
+/// Keep `value` under `key`, or remove the secret when it's empty
+pub async fn store(key: &'static str, value: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+    tokio::spawn(async move {
+        let keyring = Keyring::new().await?;
+        keyring.unlock().await?;
+        if value.is_empty() {
+            keyring.delete(&attributes(key)).await?;
+        } else {
+            keyring
+                .create_item(label(key), &attributes(key), Secret::text(&value), true)
+                .await?;
+        }
+        Ok::<_, Box<dyn Error + Send + Sync>>(())
+    })
+    .await??;
+    let _ = changes().send(key);
+    Ok(())
+}
+
+/// `store` for the startup migrations, which run before the main loop.
+/// Blocks until the secret is stored.
+pub fn store_blocking(
+    key: &'static str,
+    value: String,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let runtime = tokio::runtime::Handle::current();
+    std::thread::spawn(move || runtime.block_on(store(key, value)))
+        .join()
+        .map_err(|_| "Storing the secret panicked")?
+}
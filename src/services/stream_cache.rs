@@ -0,0 +1,178 @@
+//! Copies of streamed tracks kept on disk. The tracks coming up next in the
+//! queue are fetched ahead of time, so a patchy connection doesn't cut into
+//! playback; the audio backend plays the copy when there is one. The cache
+//! is bounded, and the copies used longest ago make way for new ones.
+
+use crate::platform;
+use crate::services::http::HttpClient;
+use crate::services::models::{PlaybackSource, Track};
+use crate::services::webdav;
+use crate::services::ServiceManager;
+use parking_lot::Mutex;
+use reqwest::Client;
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+
+/// Queued tracks fetched ahead of the one playing
+pub const PREFETCH_AHEAD: usize = 2;
+
+// Disk space the copies may take up in all
+const MAX_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+// Larger files are streamed rather than kept, so one long mix can't push
+// everything else out
+const MAX_FILE_BYTES: u64 = MAX_CACHE_BYTES / 4;
+
+fn cache_dir() -> PathBuf {
+    platform::cache_dir().join("streams")
+}
+
+fn file_for(url: &str) -> PathBuf {
+    cache_dir().join(format!("{:x}", Sha1::digest(url)))
+}
+
+/// The copy of the stream at `url`, if one has been fetched. It counts as
+/// used, so it's among the last to be evicted.
+pub fn cached_file(url: &str) -> Option<PathBuf> {
+    let path = file_for(url);
+    let file = File::options().append(true).open(&path).ok()?;
+    // Eviction goes by modification time
+    let _ = file.set_modified(SystemTime::now());
+    Some(path)
+}
+
+/// Fetches the streamed tracks that come up next in the queue into the
+/// cache, while the network conditions allow downloads
+#[derive(Debug, Clone)]
+pub struct CachePrefetcher {
+    client: HttpClient,
+    manager: Arc<ServiceManager>,
+    // URLs being fetched now
+    in_flight: Arc<Mutex<HashSet<String>>>,
+}
+
+impl CachePrefetcher {
+    pub fn new(manager: Arc<ServiceManager>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(Self {
+            client: HttpClient::new(Client::builder().build()?),
+            manager,
+            in_flight: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+
+    /// Prefetch the upcoming tracks each time `upcoming` reports them,
+    /// until its sender is gone
+    pub fn run(self, mut upcoming: broadcast::Receiver<Vec<Track>>) {
+        tokio::spawn(async move {
+            loop {
+                match upcoming.recv().await {
+                    Ok(tracks) => self.prefetch(tracks).await,
+                    // Only the latest queue matters
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn prefetch(&self, tracks: Vec<Track>) {
+        if !self.manager.network_conditions().await.downloads_allowed() {
+            return;
+        }
+        for track in tracks.into_iter().take(PREFETCH_AHEAD) {
+            let PlaybackSource::Stream { url, .. } = track.source else {
+                continue;
+            };
+            let title = track.title;
+            if file_for(&url).exists() || !self.in_flight.lock().insert(url.clone()) {
+                continue;
+            }
+
+            let prefetcher = self.clone();
+            tokio::spawn(async move {
+                match prefetcher.download(&url).await {
+                    Ok(bytes) => println!("Cached {} ({} bytes) for offline play", title, bytes),
+                    Err(e) => eprintln!("Failed to cache {}: {}", title, e),
+                }
+                prefetcher.in_flight.lock().remove(&url);
+            });
+        }
+    }
+
+    /// Fetch the stream at `url` into the cache, returning its size
+    async fn download(&self, url: &str) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let mut request = self.client.get(url);
+        if let Some((user, password)) = webdav::credentials_for(url) {
+            request = request.basic_auth(user, Some(password));
+        }
+        let mut response = self.client.send(request).await?.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_FILE_BYTES)
+        {
+            return Err("Too large to keep".into());
+        }
+
+        // Written under another name first, so a cut-off download is never
+        // taken for a copy
+        let path = file_for(url);
+        let partial = path.with_extension("part");
+        tokio::fs::create_dir_all(cache_dir()).await?;
+        let mut file = tokio::fs::File::create(&partial).await?;
+        let mut written = 0;
+        let result: Result<(), Box<dyn Error + Send + Sync>> = async {
+            while let Some(chunk) = response.chunk().await? {
+                written += chunk.len() as u64;
+                if written > MAX_FILE_BYTES {
+                    return Err("Too large to keep".into());
+                }
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(e);
+        }
+        tokio::fs::rename(&partial, &path).await?;
+
+        tokio::task::spawn_blocking(evict).await?;
+        Ok(written)
+    }
+}
+
+/// Remove the copies used longest ago until the cache fits its bound
+fn evict() {
+    let Ok(entries) = fs::read_dir(cache_dir()) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_none())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    files.sort();
+
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    for (_, size, path) in files {
+        if total <= MAX_CACHE_BYTES {
+            break;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => total -= size,
+            Err(e) => eprintln!("Failed to evict {:?} from the stream cache: {}", path, e),
+        }
+    }
+}
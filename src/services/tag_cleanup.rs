@@ -0,0 +1,148 @@
+//! Guesses for tracks tagged with no artist or album, taken from where the
+//! file sits. Libraries are mostly laid out as Artist/Album/01 - Title or
+//! Artist - Album/01 Title, sometimes with a disc folder inside the album,
+//! and a file name like "Artist - Title" names the artist itself. Guesses
+//! are only ever offered; nothing changes until the user accepts them.
+
+use crate::services::models::{PlaybackSource, Track};
+use crate::services::writeback::TagChange;
+use std::path::{Path, PathBuf};
+
+const UNKNOWN_ARTIST: &str = "Unknown Artist";
+const UNKNOWN_ALBUM: &str = "Unknown Album";
+
+// Folder names that say nothing about the music in them
+const GENERIC_FOLDERS: [&str; 8] = [
+    "music",
+    "downloads",
+    "unknown",
+    "unknown artist",
+    "unknown album",
+    "misc",
+    "various",
+    "new folder",
+];
+
+/// Values proposed for a track's missing tags
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub track: Track,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+impl Suggestion {
+    /// The tag changes accepting the suggestion makes
+    pub fn changes(&self) -> Vec<TagChange> {
+        let artist = self.artist.clone().map(TagChange::Artist);
+        let album = self.album.clone().map(TagChange::Album);
+        artist.into_iter().chain(album).collect()
+    }
+}
+
+/// Whether `track` is missing its artist or album
+pub fn needs_cleanup(track: &Track) -> bool {
+    matches!(track.source, PlaybackSource::Local { .. })
+        && (track.artist == UNKNOWN_ARTIST || track.album == UNKNOWN_ALBUM)
+}
+
+/// Guess the missing artist and album of `track` from its folders and file
+/// name. Folders at or above the library folders in `roots` are ignored.
+pub fn suggest(track: &Track, roots: &[PathBuf]) -> Option<Suggestion> {
+    let PlaybackSource::Local { path, .. } = &track.source else {
+        return None;
+    };
+    let (folder_artist, folder_album) = guess_from_folders(path, roots);
+    let file_artist = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(guess_from_file_name);
+
+    let artist = (track.artist == UNKNOWN_ARTIST)
+        .then(|| file_artist.or(folder_artist))
+        .flatten();
+    let album = (track.album == UNKNOWN_ALBUM)
+        .then_some(folder_album)
+        .flatten();
+    if artist.is_none() && album.is_none() {
+        return None;
+    }
+    Some(Suggestion {
+        track: track.clone(),
+        artist,
+        album,
+    })
+}
+
+/// Artist and album from the folders holding `path`
+fn guess_from_folders(path: &Path, roots: &[PathBuf]) -> (Option<String>, Option<String>) {
+    let inside_library = |dir: &Path| !roots.iter().any(|root| root.starts_with(dir));
+    let mut folders = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| inside_library(dir))
+        .filter_map(|dir| dir.file_name()?.to_str())
+        .skip_while(|name| is_disc_folder(name));
+
+    let Some(album_folder) = folders.next() else {
+        return (None, None);
+    };
+    // "Artist - Album" names both
+    if let Some((artist, album)) = album_folder.split_once(" - ") {
+        if let (Some(artist), Some(album)) = (clean_name(artist), clean_name(album)) {
+            return (Some(artist), Some(album));
+        }
+    }
+    let album = clean_name(album_folder);
+    let artist = folders.next().and_then(clean_name);
+    (artist, album)
+}
+
+/// The artist in a file name like "01 - Artist - Title"
+fn guess_from_file_name(stem: &str) -> Option<String> {
+    let stem = stem.trim_start_matches(|c: char| c.is_ascii_digit() || " .-_".contains(c));
+    let (artist, title) = stem.split_once(" - ")?;
+    if title.trim().is_empty() {
+        return None;
+    }
+    clean_name(artist)
+}
+
+/// Whether a folder only splits an album into discs, e.g. "CD1" or "Disc 2"
+fn is_disc_folder(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["cd", "disc", "disk"].iter().any(|prefix| {
+        name.strip_prefix(prefix).is_some_and(|rest| {
+            let rest = rest.trim();
+            !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit())
+        })
+    })
+}
+
+/// `name` without bracketed extras like "[FLAC]" or "(2004)", a leading
+/// year, or underscores for spaces. None when nothing useful is left.
+fn clean_name(name: &str) -> Option<String> {
+    let mut cleaned = String::with_capacity(name.len());
+    let mut depth = 0;
+    for c in name.chars() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth = (depth - 1).max(0),
+            '_' if depth == 0 => cleaned.push(' '),
+            _ if depth == 0 => cleaned.push(c),
+            _ => {}
+        }
+    }
+
+    // "2004 - Album"
+    let cleaned = match cleaned.trim().split_once(" - ") {
+        Some((year, rest)) if year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()) => {
+            rest.to_string()
+        }
+        _ => cleaned,
+    };
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let meaningful = cleaned.chars().any(char::is_alphabetic)
+        && !GENERIC_FOLDERS.contains(&cleaned.to_lowercase().as_str());
+    meaningful.then_some(cleaned)
+}
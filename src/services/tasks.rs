@@ -0,0 +1,84 @@
+//! Long jobs run over the whole library in the background, such as
+//! embedding album art. Each kind of job runs once at a time, says how far
+//! along it is and can be stopped between files.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How far along a job is, in files or whatever else it counts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Default)]
+struct TaskState {
+    progress: Progress,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// The jobs running now, by kind
+#[derive(Debug, Clone, Default)]
+pub struct TaskManager {
+    running: Arc<Mutex<HashMap<&'static str, TaskState>>>,
+}
+
+impl TaskManager {
+    /// Start a job of `kind`, or None when one is already running. It
+    /// counts as running until the returned task is dropped.
+    pub fn start(&self, kind: &'static str) -> Option<Task> {
+        let mut running = self.running.lock();
+        if running.contains_key(kind) {
+            return None;
+        }
+        let state = TaskState::default();
+        let cancelled = state.cancelled.clone();
+        running.insert(kind, state);
+        Some(Task {
+            kind,
+            cancelled,
+            running: self.running.clone(),
+        })
+    }
+
+    /// Progress of the running job of `kind`, if there is one
+    pub fn progress(&self, kind: &str) -> Option<Progress> {
+        self.running.lock().get(kind).map(|state| state.progress)
+    }
+
+    /// Ask the running job of `kind` to stop after what it's doing now
+    pub fn cancel(&self, kind: &str) {
+        if let Some(state) = self.running.lock().get(kind) {
+            state.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A running job, which the job itself holds on to
+#[derive(Debug)]
+pub struct Task {
+    kind: &'static str,
+    cancelled: Arc<AtomicBool>,
+    running: Arc<Mutex<HashMap<&'static str, TaskState>>>,
+}
+
+impl Task {
+    pub fn set_progress(&self, done: usize, total: usize) {
+        if let Some(state) = self.running.lock().get_mut(self.kind) {
+            state.progress = Progress { done, total };
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.running.lock().remove(self.kind);
+    }
+}
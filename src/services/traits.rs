@@ -1,9 +1,14 @@
 use super::models::{Album, Artist, Track};
-use crate::services::models::{SearchResults, SearchWeights};
+use crate::services::models::{
+    LibraryChange, LyricsMatch, MovedFiles, RefreshMode, SearchResults, SearchWeights,
+};
+use crate::services::writeback::TagChange;
 use crate::services::PlayableItem;
 use async_trait::async_trait;
 use chrono::Utc;
 use std::error::Error;
+use std::path::PathBuf;
+use tokio::sync::broadcast;
 
 #[async_trait]
 pub trait MusicProvider: std::fmt::Debug + Send + Sync {
@@ -45,4 +50,101 @@ pub trait MusicProvider: std::fmt::Debug + Send + Sync {
         limit: usize,
         offset: usize,
     ) -> Result<SearchResults, Box<dyn Error + Send + Sync>>;
+
+    /// Tracks whose lyrics match the query. Providers that don't have
+    /// lyrics find nothing.
+    async fn search_lyrics(
+        &self,
+        _query: &str,
+        _limit: usize,
+    ) -> Result<Vec<LyricsMatch>, Box<dyn Error + Send + Sync>> {
+        Ok(Vec::new())
+    }
+
+    /// Album a track belongs to, matched on the track's metadata
+    async fn find_album(
+        &self,
+        track: &Track,
+    ) -> Result<Option<Album>, Box<dyn Error + Send + Sync>>;
+
+    /// Artist a track belongs to, matched on the track's metadata
+    async fn find_artist(
+        &self,
+        track: &Track,
+    ) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>>;
+
+    async fn get_track(&self, id: &str) -> Result<Option<Track>, Box<dyn Error + Send + Sync>>;
+
+    async fn get_album(&self, id: &str) -> Result<Option<Album>, Box<dyn Error + Send + Sync>>;
+
+    async fn get_artist(&self, id: &str) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>>;
+
+    async fn get_album_tracks(
+        &self,
+        album_id: &str,
+    ) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>>;
+
+    async fn get_artist_albums(
+        &self,
+        artist_id: &str,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>>;
+
+    /// Decades with albums released in them, such as 1990, oldest first
+    async fn album_decades(&self) -> Result<Vec<u32>, Box<dyn Error + Send + Sync>> {
+        let mut decades: Vec<u32> = self
+            .get_albums()
+            .await?
+            .iter()
+            .filter_map(|album| album.year)
+            .filter(|year| *year > 0)
+            .map(|year| year / 10 * 10)
+            .collect();
+        decades.sort_unstable();
+        decades.dedup();
+        Ok(decades)
+    }
+
+    /// Albums released in the ten years from `decade`
+    async fn albums_from_decade(
+        &self,
+        decade: u32,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        let mut albums = self.get_albums().await?;
+        albums.retain(|album| album.year.is_some_and(|year| year / 10 * 10 == decade));
+        Ok(albums)
+    }
+
+    /// Tracks added, removed or changed from now on. Providers that can't
+    /// tell have None, and listings of them are looked up again once the
+    /// manager's copies expire.
+    fn subscribe_changes(&self) -> Option<broadcast::Receiver<LibraryChange>> {
+        None
+    }
+
+    async fn refresh(&self, mode: RefreshMode) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+    /// Move files of this provider's tracks from each first path to the
+    /// second, keeping its index in step. Stops at the first file that
+    /// can't be moved, keeping those moved before it.
+    async fn move_files(
+        &self,
+        _moves: &[(PathBuf, PathBuf)],
+    ) -> Result<MovedFiles, Box<dyn Error + Send + Sync>> {
+        Err("This provider has no files to move".into())
+    }
+
+    /// Pick up files of this provider's tracks at `paths` being removed or
+    /// put back, without scanning the rest of the library
+    async fn rescan_files(&self, _paths: &[PathBuf]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Err("This provider has no files to scan".into())
+    }
+
+    /// Apply tag edits made in the app to this provider's tracks, by track
+    /// ID. Returns the IDs of the tracks that were edited.
+    async fn edit_tracks(
+        &self,
+        _edits: &[(String, Vec<TagChange>)],
+    ) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        Err("This provider's tracks can't be edited".into())
+    }
 }
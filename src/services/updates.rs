@@ -0,0 +1,102 @@
+//! Checking GitHub for a newer release of Nova. Only the latest published
+//! release is asked for, and nothing is downloaded; how an update arrives
+//! depends on how Nova was installed.
+
+use crate::platform;
+use crate::services::http::HttpClient;
+use reqwest::header::{ACCEPT, USER_AGENT};
+use reqwest::Client;
+use serde::Deserialize;
+use std::error::Error;
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/jumpers775/Nova/releases/latest";
+
+/// A published release
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    #[serde(rename = "tag_name")]
+    pub version: String,
+    pub name: Option<String>,
+    /// Release notes in Markdown
+    #[serde(rename = "body", default)]
+    pub notes: String,
+    #[serde(rename = "html_url")]
+    pub url: String,
+}
+
+/// The latest release if it's newer than `current`, the running version
+pub async fn newer_release(current: &str) -> Result<Option<Release>, Box<dyn Error + Send + Sync>> {
+    let client = HttpClient::new(Client::new());
+    let request = client
+        .get(LATEST_RELEASE_URL)
+        // GitHub turns away requests without one
+        .header(USER_AGENT, format!("Nova/{}", current))
+        .header(ACCEPT, "application/vnd.github+json");
+    let fetched = client.fetch(request).await?;
+    if !fetched.status.is_success() {
+        return Err(format!("GitHub answered {}", fetched.status).into());
+    }
+    let release: Release = fetched.json()?;
+    Ok(is_newer(&release.version, current).then_some(release))
+}
+
+/// Whether version `candidate` comes after `current`. Both may start with
+/// "v", and anything after a "-" or "+" is ignored.
+fn is_newer(candidate: &str, current: &str) -> bool {
+    fn parts(version: &str) -> Vec<u64> {
+        version
+            .trim()
+            .trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    }
+    let (mut candidate, mut current) = (parts(candidate), parts(current));
+    let len = candidate.len().max(current.len());
+    candidate.resize(len, 0);
+    current.resize(len, 0);
+    candidate > current
+}
+
+/// How this copy of Nova gets updated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Flatpak,
+    /// Installed by the system's package manager
+    Package,
+    /// Built or installed by hand
+    Manual,
+}
+
+impl Delivery {
+    pub fn detect() -> Self {
+        if platform::is_sandboxed() {
+            return Self::Flatpak;
+        }
+        let packaged = std::env::current_exe()
+            .is_ok_and(|exe| exe.starts_with("/usr") && !exe.starts_with("/usr/local"));
+        if packaged {
+            Self::Package
+        } else {
+            Self::Manual
+        }
+    }
+
+    /// What to do to get the update
+    pub fn instructions(self) -> &'static str {
+        match self {
+            Self::Flatpak => {
+                "Updates arrive through Flatpak. Update from your software center, or run \
+                 “flatpak update”."
+            }
+            Self::Package => {
+                "Nova came from your distribution, which ships updates once it has packaged \
+                 them. Update through your software center or package manager."
+            }
+            Self::Manual => "Download or build the new release from its page on GitHub.",
+        }
+    }
+}
@@ -0,0 +1,1434 @@
+use crate::migrations::{migrate, Migration};
+use crate::platform;
+use crate::services::artist_info::ArtistInfo;
+use crate::services::models::{
+    Album, Bookmark, HistoryEntry, KnownTrack, MovedTrack, MusicalKey, PinnedItem, PinnedKind,
+    PlayStats, Playlist, PlaylistPlayback, ShuffleExclusions, SkippedTrack, Track, TrackTempo,
+    Trim,
+};
+use crate::services::recap::RecapPeriod;
+use crate::services::scrobbler::PendingListen;
+use crate::services::writeback::{PendingWriteBack, TagChange};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Oldest play history entries beyond this many are dropped, once they're
+// from before the longest recap period
+const HISTORY_LIMIT: i64 = 500;
+
+// Changes to stores made by earlier versions. New tables are created by
+// initialize; changes to existing ones go here, numbered on from the last.
+const MIGRATIONS: &[Migration<Connection>] = &[
+    Migration {
+        version: 1,
+        description: "track skips in play history",
+        apply: add_skipped_column,
+    },
+    Migration {
+        version: 2,
+        description: "playlist folders",
+        apply: add_playlist_groups,
+    },
+    Migration {
+        version: 3,
+        description: "playlist playback settings",
+        apply: add_playlist_playback,
+    },
+];
+
+// Plays a track needs before its skip rate is trusted
+const MIN_PLAYS_FOR_SKIP_RATE: i64 = 3;
+
+/// On-disk store for state the user creates (pins, history, ...), kept apart
+/// from the in-memory library index so it survives restarts and rescans
+#[derive(Debug)]
+pub struct UserStore {
+    pool: Arc<Pool<SqliteConnectionManager>>,
+    // Play counts by track, read once and dropped whenever a play is recorded,
+    // so tooltips don't each query the history
+    play_count_cache: Mutex<Option<HashMap<String, u32>>>,
+}
+
+impl UserStore {
+    /// Location of the store inside the user's data directory
+    pub fn default_path() -> PathBuf {
+        platform::data_dir().join("user.db")
+    }
+
+    /// Check the store at `path` can be read and isn't damaged. A store
+    /// that doesn't exist yet is fine.
+    pub fn check_integrity(path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let result: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+        if result == "ok" {
+            Ok(())
+        } else {
+            Err(result.into())
+        }
+    }
+
+    /// Move a damaged store at `path` aside and start a fresh one there.
+    /// The old file is kept next to it in case it can be recovered by hand.
+    pub fn rebuild(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if path.exists() {
+            let backup = path.with_extension(format!("db.damaged-{}", Utc::now().timestamp()));
+            std::fs::rename(path, &backup)?;
+            println!("Moved damaged user store to {:?}", backup);
+        }
+        Self::new(path)
+    }
+
+    pub fn new(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        println!("Opening user store at {:?}", path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.execute_batch("PRAGMA busy_timeout = 10000;")?;
+            Ok(())
+        });
+        let pool = Pool::builder().max_size(2).build(manager)?;
+
+        let store = Self {
+            pool: Arc::new(pool),
+            play_count_cache: Mutex::new(None),
+        };
+        store.initialize()?;
+        Ok(store)
+    }
+
+    fn initialize(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS pinned_items (
+                kind TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                item_id TEXT NOT NULL,
+                title TEXT NOT NULL,
+                subtitle TEXT,
+                position INTEGER NOT NULL,
+                PRIMARY KEY (kind, provider, item_id)
+            );
+
+            CREATE TABLE IF NOT EXISTS play_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                track_id TEXT NOT NULL,
+                title TEXT NOT NULL,
+                artist TEXT NOT NULL,
+                album TEXT NOT NULL,
+                duration INTEGER NOT NULL,
+                played_at TEXT NOT NULL,
+                skipped INTEGER NOT NULL DEFAULT 0
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_play_history_track ON play_history(track_id);
+
+            CREATE TABLE IF NOT EXISTS playlists (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS playlist_tracks (
+                playlist_id TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                track_id TEXT NOT NULL,
+                PRIMARY KEY (playlist_id, position)
+            );
+
+            -- Queue left unfinished at quit, to be restored on the next launch
+            CREATE TABLE IF NOT EXISTS saved_queue (
+                position INTEGER PRIMARY KEY,
+                track_id TEXT NOT NULL
+            );
+
+            -- Edits and ratings made in Nova, whether or not the file has them
+            CREATE TABLE IF NOT EXISTS track_tags (
+                track_id TEXT NOT NULL,
+                field TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (track_id, field)
+            );
+
+            -- Changes still to be written to the files' tags
+            CREATE TABLE IF NOT EXISTS pending_write_backs (
+                track_id TEXT NOT NULL,
+                field TEXT NOT NULL,
+                path TEXT NOT NULL,
+                value TEXT NOT NULL,
+                queued_at TEXT NOT NULL,
+                PRIMARY KEY (track_id, field)
+            );
+
+            -- Tracks and albums kept out of shuffle and mixes. Albums are
+            -- keyed by ShuffleExclusions::album_keys.
+            CREATE TABLE IF NOT EXISTS shuffle_exclusions (
+                kind TEXT NOT NULL,
+                item_key TEXT NOT NULL,
+                PRIMARY KEY (kind, item_key)
+            );
+
+            -- Where tracks start and stop playing, when not the whole way
+            CREATE TABLE IF NOT EXISTS track_trims (
+                track_id TEXT PRIMARY KEY,
+                start_ms INTEGER NOT NULL,
+                end_ms INTEGER
+            );
+
+            -- Named places in tracks, several to a track
+            CREATE TABLE IF NOT EXISTS track_bookmarks (
+                track_id TEXT NOT NULL,
+                position_ms INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                PRIMARY KEY (track_id, position_ms)
+            );
+
+            -- Biographies and similar artists fetched online, by lowercase name
+            CREATE TABLE IF NOT EXISTS artist_info (
+                artist TEXT PRIMARY KEY,
+                bio TEXT,
+                similar TEXT NOT NULL,
+                url TEXT,
+                fetched_at TEXT NOT NULL
+            );
+
+            -- Titles and artists of playlist tracks as last seen, for finding
+            -- them again after their files move
+            CREATE TABLE IF NOT EXISTS known_tracks (
+                track_id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                artist TEXT NOT NULL,
+                album TEXT NOT NULL,
+                duration INTEGER NOT NULL
+            );
+
+            -- Tempo and Camelot key of tracks for DJ mode. Tracks analyzed
+            -- without finding a tempo have a NULL bpm, so they aren't tried
+            -- again.
+            CREATE TABLE IF NOT EXISTS track_tempos (
+                track_id TEXT PRIMARY KEY,
+                bpm REAL,
+                musical_key TEXT
+            );
+
+            -- Listens still to be sent to ListenBrainz
+            CREATE TABLE IF NOT EXISTS pending_listens (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                artist TEXT NOT NULL,
+                title TEXT NOT NULL,
+                album TEXT NOT NULL,
+                duration INTEGER NOT NULL,
+                listened_at TEXT NOT NULL
+            );",
+        )?;
+
+        migrate("user store", &*conn, MIGRATIONS)
+    }
+
+    /// Pinned items in sidebar order
+    pub fn pinned_items(&self) -> Result<Vec<PinnedItem>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT kind, provider, item_id, title, subtitle
+             FROM pinned_items
+             ORDER BY position",
+        )?;
+        let items = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            })?
+            .filter_map(Result::ok)
+            .filter_map(|(kind, provider, item_id, title, subtitle)| {
+                // Skip kinds written by a newer version
+                Some(PinnedItem {
+                    kind: PinnedKind::from_name(&kind)?,
+                    provider,
+                    item_id,
+                    title,
+                    subtitle,
+                })
+            })
+            .collect();
+
+        Ok(items)
+    }
+
+    pub fn is_pinned(
+        &self,
+        kind: PinnedKind,
+        provider: &str,
+        item_id: &str,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM pinned_items WHERE kind = ?1 AND provider = ?2 AND item_id = ?3",
+            params![kind.as_str(), provider, item_id],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Pin an item below the existing pins
+    pub fn pin(&self, item: &PinnedItem) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO pinned_items (kind, provider, item_id, title, subtitle, position)
+             VALUES (?1, ?2, ?3, ?4, ?5,
+                     (SELECT COALESCE(MAX(position), -1) + 1 FROM pinned_items))",
+            params![
+                item.kind.as_str(),
+                item.provider,
+                item.item_id,
+                item.title,
+                item.subtitle
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn unpin(&self, item: &PinnedItem) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "DELETE FROM pinned_items WHERE kind = ?1 AND provider = ?2 AND item_id = ?3",
+            params![item.kind.as_str(), item.provider, item.item_id],
+        )?;
+        Ok(())
+    }
+
+    /// Store a new sidebar order; `items` lists every pin top to bottom
+    pub fn reorder_pins(&self, items: &[PinnedItem]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for (position, item) in items.iter().enumerate() {
+            tx.execute(
+                "UPDATE pinned_items SET position = ?1
+                 WHERE kind = ?2 AND provider = ?3 AND item_id = ?4",
+                params![
+                    position as i64,
+                    item.kind.as_str(),
+                    item.provider,
+                    item.item_id
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Remember that `track` started playing
+    pub fn record_play(&self, track: &Track) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO play_history (track_id, title, artist, album, duration, played_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                track.id,
+                track.title,
+                track.artist,
+                track.album,
+                track.duration,
+                Utc::now().to_rfc3339()
+            ],
+        )?;
+        // The longest recap goes back to the start of last year
+        let recap_start = RecapPeriod::last_year()
+            .start()
+            .map(|start| start.to_rfc3339())
+            .unwrap_or_default();
+        tx.execute(
+            "DELETE FROM play_history WHERE played_at < ?2 AND id NOT IN (
+                SELECT id FROM play_history ORDER BY id DESC LIMIT ?1
+            )",
+            params![HISTORY_LIMIT, recap_start],
+        )?;
+        tx.commit()?;
+        self.play_count_cache.lock().take();
+        Ok(())
+    }
+
+    /// How many times a track has been played, as far back as the history goes
+    pub fn play_count(&self, track_id: &str) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        let mut cache = self.play_count_cache.lock();
+        if cache.is_none() {
+            *cache = Some(self.play_counts()?);
+        }
+        Ok(cache
+            .as_ref()
+            .and_then(|counts| counts.get(track_id).copied())
+            .unwrap_or(0))
+    }
+
+    /// Number of times each track has been played, for tracks played at all
+    pub fn play_counts(&self) -> Result<HashMap<String, u32>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt =
+            conn.prepare("SELECT track_id, COUNT(*) FROM play_history GROUP BY track_id")?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?)))?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(counts)
+    }
+
+    /// Plays, the last play and the average rating across `track_ids`
+    pub fn play_stats(
+        &self,
+        track_ids: &[String],
+    ) -> Result<PlayStats, Box<dyn Error + Send + Sync>> {
+        let mut stats = PlayStats::default();
+        if track_ids.is_empty() {
+            return Ok(stats);
+        }
+        let ids = track_ids.iter().map(String::as_str);
+        let placeholders = vec!["?"; track_ids.len()].join(", ");
+        let conn = self.pool.get()?;
+
+        let (plays, last_played) = conn.query_row(
+            &format!(
+                "SELECT COUNT(*), MAX(played_at) FROM play_history WHERE track_id IN ({})",
+                placeholders
+            ),
+            params_from_iter(ids.clone()),
+            |row| Ok((row.get::<_, u32>(0)?, row.get::<_, Option<String>>(1)?)),
+        )?;
+        stats.plays = plays;
+        stats.last_played = last_played
+            .and_then(|played_at| DateTime::parse_from_rfc3339(&played_at).ok())
+            .map(|time| time.with_timezone(&Utc));
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT value FROM track_tags WHERE field = ? AND track_id IN ({})",
+            placeholders
+        ))?;
+        let field = TagChange::Rating(0).field();
+        let ratings: Vec<u8> = stmt
+            .query_map(params_from_iter(std::iter::once(field).chain(ids)), |row| {
+                row.get::<_, String>(0)
+            })?
+            .filter_map(Result::ok)
+            .filter_map(|value| value.parse().ok())
+            .collect();
+        if !ratings.is_empty() {
+            let total: u32 = ratings.iter().map(|&stars| stars as u32).sum();
+            stats.average_rating = Some(total as f32 / ratings.len() as f32);
+        }
+        Ok(stats)
+    }
+
+    /// Ratings given in Nova, in stars by track ID
+    pub fn ratings(&self) -> Result<HashMap<String, u8>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT track_id, value FROM track_tags WHERE field = ?1")?;
+        let ratings = stmt
+            .query_map(params![TagChange::Rating(0).field()], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .filter_map(Result::ok)
+            .filter_map(|(track_id, value)| Some((track_id, value.parse().ok()?)))
+            .collect();
+        Ok(ratings)
+    }
+
+    /// Mark the latest play of a track as skipped
+    pub fn record_skip(&self, track_id: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE play_history SET skipped = 1
+             WHERE id = (SELECT MAX(id) FROM play_history WHERE track_id = ?1)",
+            params![track_id],
+        )?;
+        Ok(())
+    }
+
+    /// Share of plays that were skipped, for tracks played often enough for
+    /// the share to mean something
+    pub fn skip_rates(&self) -> Result<HashMap<String, f32>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT track_id, COUNT(*), SUM(skipped)
+             FROM play_history
+             GROUP BY track_id
+             HAVING COUNT(*) >= ?1",
+        )?;
+        let rates = stmt
+            .query_map(params![MIN_PLAYS_FOR_SKIP_RATE], |row| {
+                let plays: i64 = row.get(1)?;
+                let skips: i64 = row.get(2)?;
+                Ok((row.get::<_, String>(0)?, skips as f32 / plays as f32))
+            })?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(rates)
+    }
+
+    /// Tracks skipped in at least half their plays, most skipped first
+    pub fn often_skipped(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<SkippedTrack>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT track_id, MAX(title), MAX(artist), MAX(album), COUNT(*) AS plays,
+                    SUM(skipped) AS skips
+             FROM play_history
+             GROUP BY track_id
+             HAVING plays >= ?1 AND skips * 2 >= plays
+             ORDER BY CAST(skips AS REAL) / plays DESC, skips DESC
+             LIMIT ?2",
+        )?;
+        let tracks = stmt
+            .query_map(params![MIN_PLAYS_FOR_SKIP_RATE, limit as i64], |row| {
+                Ok(SkippedTrack {
+                    track_id: row.get(0)?,
+                    title: row.get(1)?,
+                    artist: row.get(2)?,
+                    album: row.get(3)?,
+                    plays: row.get(4)?,
+                    skips: row.get(5)?,
+                })
+            })?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(tracks)
+    }
+
+    /// Forget the skips of a track, e.g. once the user decided to keep it
+    pub fn clear_skips(&self, track_id: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE play_history SET skipped = 0 WHERE track_id = ?1",
+            params![track_id],
+        )?;
+        Ok(())
+    }
+
+    /// Carry everything kept about `moved` tracks over to their new IDs
+    pub fn rekey_tracks(&self, moved: &[MovedTrack]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for track in moved.iter().filter(|track| track.new_id != track.old_id) {
+            let ids = params![track.new_id, track.old_id];
+            for table in ["play_history", "playlist_tracks", "saved_queue"] {
+                tx.execute(
+                    &format!("UPDATE {} SET track_id = ? WHERE track_id = ?", table),
+                    ids,
+                )?;
+            }
+            // What was kept under the old ID wins over anything added
+            // under the new one since the file moved
+            for table in [
+                "track_tags",
+                "track_trims",
+                "track_bookmarks",
+                "known_tracks",
+                "track_tempos",
+            ] {
+                tx.execute(
+                    &format!(
+                        "UPDATE OR REPLACE {} SET track_id = ? WHERE track_id = ?",
+                        table
+                    ),
+                    ids,
+                )?;
+            }
+            tx.execute(
+                "UPDATE OR REPLACE pending_write_backs SET track_id = ?, path = ?
+                 WHERE track_id = ?",
+                params![track.new_id, track.path.to_string_lossy(), track.old_id],
+            )?;
+            tx.execute(
+                "UPDATE OR REPLACE shuffle_exclusions SET item_key = ?
+                 WHERE kind = 'track' AND item_key = ?",
+                ids,
+            )?;
+        }
+        tx.commit()?;
+        self.play_count_cache.lock().take();
+        Ok(())
+    }
+
+    /// Most recently played tracks first
+    pub fn recent_history(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT track_id, title, artist, album, duration, played_at
+             FROM play_history
+             ORDER BY id DESC
+             LIMIT ?1",
+        )?;
+        let entries = stmt
+            .query_map(params![limit as i64], Self::history_entry)?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Every kept play that wasn't skipped, oldest first
+    pub fn listened_history(&self) -> Result<Vec<HistoryEntry>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT track_id, title, artist, album, duration, played_at
+             FROM play_history
+             WHERE skipped = 0
+             ORDER BY id ASC",
+        )?;
+        let entries = stmt
+            .query_map([], Self::history_entry)?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(entries)
+    }
+
+    fn history_entry(row: &rusqlite::Row) -> rusqlite::Result<HistoryEntry> {
+        let played_at: String = row.get(5)?;
+        Ok(HistoryEntry {
+            track_id: row.get(0)?,
+            title: row.get(1)?,
+            artist: row.get(2)?,
+            album: row.get(3)?,
+            duration: row.get(4)?,
+            played_at: DateTime::parse_from_rfc3339(&played_at)
+                .map(|time| time.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now()),
+        })
+    }
+
+    /// Create a playlist holding `track_ids` in order and return its ID
+    pub fn create_playlist(
+        &self,
+        name: &str,
+        track_ids: &[String],
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let playlist_id = Self::insert_playlist(&tx, name, track_ids)?;
+        tx.commit()?;
+
+        Ok(playlist_id)
+    }
+
+    fn insert_playlist(
+        conn: &Connection,
+        name: &str,
+        track_ids: &[String],
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let created_at = Utc::now();
+
+        let mut hasher = Sha1::new();
+        hasher.update(format!("{}:{}", name, created_at.to_rfc3339()).as_bytes());
+        let playlist_id = format!("{:x}", hasher.finalize());
+
+        conn.execute(
+            "INSERT INTO playlists (id, name, created_at) VALUES (?1, ?2, ?3)",
+            params![playlist_id, name, created_at.to_rfc3339()],
+        )?;
+        for (position, track_id) in track_ids.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO playlist_tracks (playlist_id, position, track_id)
+                 VALUES (?1, ?2, ?3)",
+                params![playlist_id, position as i64, track_id],
+            )?;
+        }
+        Ok(playlist_id)
+    }
+
+    /// Add `track_ids` to the end of the playlists in `add`, leaving out
+    /// ones already there, take them out of those in `remove` and make a new
+    /// playlist of them for each name in `create`, all or nothing
+    pub fn update_playlists(
+        &self,
+        track_ids: &[String],
+        add: &[String],
+        remove: &[String],
+        create: &[String],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for playlist_id in add {
+            for track_id in track_ids {
+                tx.execute(
+                    "INSERT INTO playlist_tracks (playlist_id, position, track_id)
+                     SELECT ?1, COALESCE(MAX(position), -1) + 1, ?2
+                     FROM playlist_tracks WHERE playlist_id = ?1
+                     HAVING COALESCE(SUM(track_id = ?2), 0) = 0",
+                    params![playlist_id, track_id],
+                )?;
+            }
+        }
+        // Positions only order the tracks, so the gaps left don't matter
+        for playlist_id in remove {
+            for track_id in track_ids {
+                tx.execute(
+                    "DELETE FROM playlist_tracks WHERE playlist_id = ?1 AND track_id = ?2",
+                    params![playlist_id, track_id],
+                )?;
+            }
+        }
+        for name in create {
+            Self::insert_playlist(&tx, name, track_ids)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Take the tracks at `indices` in the playlist's order out of it
+    pub fn remove_tracks(
+        &self,
+        playlist_id: &str,
+        indices: &[usize],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let positions: Vec<i64> = tx
+            .prepare(
+                "SELECT position FROM playlist_tracks WHERE playlist_id = ?1 ORDER BY position",
+            )?
+            .query_map(params![playlist_id], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for position in indices.iter().filter_map(|&index| positions.get(index)) {
+            tx.execute(
+                "DELETE FROM playlist_tracks WHERE playlist_id = ?1 AND position = ?2",
+                params![playlist_id, position],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// All playlists, newest first
+    pub fn playlists(&self) -> Result<Vec<Playlist>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT id FROM playlists ORDER BY created_at DESC")?;
+        let ids: Vec<String> = stmt
+            .query_map([], |row| row.get(0))?
+            .filter_map(Result::ok)
+            .collect();
+
+        ids.iter().map(|id| Self::load_playlist(&conn, id)).collect()
+    }
+
+    pub fn playlist(&self, playlist_id: &str) -> Result<Playlist, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        Self::load_playlist(&conn, playlist_id)
+    }
+
+    fn load_playlist(
+        conn: &Connection,
+        playlist_id: &str,
+    ) -> Result<Playlist, Box<dyn Error + Send + Sync>> {
+        let (name, created_at, group, playback) = conn.query_row(
+            "SELECT name, created_at, group_name, shuffle, crossfade_ms, album_gain
+             FROM playlists WHERE id = ?1",
+            params![playlist_id],
+            |row| {
+                let playback = PlaylistPlayback {
+                    shuffle: row.get(3)?,
+                    crossfade: row
+                        .get::<_, Option<i64>>(4)?
+                        .map(|ms| Duration::from_millis(ms.max(0) as u64)),
+                    album_gain: row.get(5)?,
+                };
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    playback,
+                ))
+            },
+        )?;
+
+        let mut stmt = conn.prepare(
+            "SELECT track_id FROM playlist_tracks WHERE playlist_id = ?1 ORDER BY position",
+        )?;
+        let track_ids = stmt
+            .query_map(params![playlist_id], |row| row.get(0))?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(Playlist {
+            id: playlist_id.to_string(),
+            name,
+            created_at: DateTime::parse_from_rfc3339(&created_at)
+                .map(|time| time.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now()),
+            track_ids,
+            folder: None,
+            group,
+            playback,
+        })
+    }
+
+    /// Rename the playlist, and its pin in the sidebar. Fails when another
+    /// playlist already has the name.
+    pub fn rename_playlist(
+        &self,
+        playlist_id: &str,
+        name: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let taken: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM playlists WHERE name = ?1 AND id != ?2",
+            params![name, playlist_id],
+            |row| row.get(0),
+        )?;
+        if taken > 0 {
+            return Err(format!("A playlist named “{}” already exists", name).into());
+        }
+        tx.execute(
+            "UPDATE playlists SET name = ?1 WHERE id = ?2",
+            params![name, playlist_id],
+        )?;
+        tx.execute(
+            "UPDATE pinned_items SET title = ?1 WHERE kind = ?2 AND item_id = ?3",
+            params![name, PinnedKind::Playlist.as_str(), playlist_id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Copy the playlist's tracks, folder and playback settings into a new
+    /// one called `name`, and return its ID
+    pub fn duplicate_playlist(
+        &self,
+        playlist_id: &str,
+        name: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let playlist = Self::load_playlist(&tx, playlist_id)?;
+        let copy_id = Self::insert_playlist(&tx, name, &playlist.track_ids)?;
+        tx.execute(
+            "UPDATE playlists SET (group_name, shuffle, crossfade_ms, album_gain) =
+             (SELECT group_name, shuffle, crossfade_ms, album_gain FROM playlists WHERE id = ?1)
+             WHERE id = ?2",
+            params![playlist_id, copy_id],
+        )?;
+        tx.commit()?;
+        Ok(copy_id)
+    }
+
+    /// Delete the playlist and its pin in the sidebar. The tracks stay in
+    /// the library.
+    pub fn delete_playlist(&self, playlist_id: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM playlist_tracks WHERE playlist_id = ?1",
+            params![playlist_id],
+        )?;
+        tx.execute("DELETE FROM playlists WHERE id = ?1", params![playlist_id])?;
+        tx.execute(
+            "DELETE FROM pinned_items WHERE kind = ?1 AND item_id = ?2",
+            params![PinnedKind::Playlist.as_str(), playlist_id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// File the playlist under the playlist folder `group`, or take it out
+    /// of its folder when that's None
+    pub fn set_playlist_group(
+        &self,
+        playlist_id: &str,
+        group: Option<&str>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE playlists SET group_name = ?1 WHERE id = ?2",
+            params![group, playlist_id],
+        )?;
+        Ok(())
+    }
+
+    /// Save how the playlist plays, which applies the next time it's played
+    pub fn set_playlist_playback(
+        &self,
+        playlist_id: &str,
+        playback: &PlaylistPlayback,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE playlists SET shuffle = ?1, crossfade_ms = ?2, album_gain = ?3 WHERE id = ?4",
+            params![
+                playback.shuffle,
+                playback.crossfade.map(|crossfade| crossfade.as_millis() as i64),
+                playback.album_gain,
+                playlist_id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Replace the playlist's tracks with `track_ids` in order. Reordering
+    /// and removing tracks both come down to this.
+    pub fn set_playlist_tracks(
+        &self,
+        playlist_id: &str,
+        track_ids: &[String],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM playlist_tracks WHERE playlist_id = ?1",
+            params![playlist_id],
+        )?;
+        for (position, track_id) in track_ids.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO playlist_tracks (playlist_id, position, track_id)
+                 VALUES (?1, ?2, ?3)",
+                params![playlist_id, position as i64, track_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Note what `tracks` are, for finding them again if they go missing
+    pub fn remember_tracks(&self, tracks: &[Track]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for track in tracks {
+            tx.execute(
+                "INSERT OR REPLACE INTO known_tracks (track_id, title, artist, album, duration)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    track.id,
+                    track.title,
+                    track.artist,
+                    track.album,
+                    track.duration
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// What is known of `track_ids`, from playlists or else the play
+    /// history. Tracks never seen are left out.
+    pub fn known_tracks(
+        &self,
+        track_ids: &[String],
+    ) -> Result<HashMap<String, KnownTrack>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT title, artist, album, duration FROM known_tracks WHERE track_id = ?1
+             UNION ALL
+             SELECT * FROM (
+                 SELECT title, artist, album, duration FROM play_history
+                 WHERE track_id = ?1 ORDER BY id DESC
+             )
+             LIMIT 1",
+        )?;
+        let mut known = HashMap::new();
+        for track_id in track_ids {
+            let track = stmt
+                .query_row(params![track_id], |row| {
+                    Ok(KnownTrack {
+                        title: row.get(0)?,
+                        artist: row.get(1)?,
+                        album: row.get(2)?,
+                        duration: row.get(3)?,
+                    })
+                })
+                .optional()?;
+            if let Some(track) = track {
+                known.insert(track_id.clone(), track);
+            }
+        }
+        Ok(known)
+    }
+
+    /// Tempo and key of every analyzed track by ID
+    pub fn track_tempos(
+        &self,
+    ) -> Result<HashMap<String, TrackTempo>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT track_id, bpm, musical_key FROM track_tempos")?;
+        let tempos = stmt
+            .query_map([], |row| {
+                let key: Option<String> = row.get(2)?;
+                Ok((
+                    row.get::<_, String>(0)?,
+                    TrackTempo {
+                        bpm: row.get::<_, Option<f64>>(1)?.map(|bpm| bpm as f32),
+                        key: key.as_deref().and_then(MusicalKey::parse),
+                    },
+                ))
+            })?
+            .filter_map(Result::ok)
+            .collect();
+
+        Ok(tempos)
+    }
+
+    pub fn set_track_tempo(
+        &self,
+        track_id: &str,
+        tempo: &TrackTempo,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO track_tempos (track_id, bpm, musical_key)
+             VALUES (?1, ?2, ?3)",
+            params![
+                track_id,
+                tempo.bpm.map(f64::from),
+                tempo.key.map(|key| key.camelot())
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Remember `track_ids` as the queue to restore on the next launch
+    pub fn save_queue(&self, track_ids: &[String]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM saved_queue", [])?;
+        for (position, track_id) in track_ids.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO saved_queue (position, track_id) VALUES (?1, ?2)",
+                params![position as i64, track_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The saved queue, if any. It is cleared so it's only restored once.
+    pub fn take_saved_queue(&self) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let track_ids: Vec<String> = tx
+            .prepare("SELECT track_id FROM saved_queue ORDER BY position")?
+            .query_map([], |row| row.get(0))?
+            .filter_map(Result::ok)
+            .collect();
+        tx.execute("DELETE FROM saved_queue", [])?;
+        tx.commit()?;
+        Ok(track_ids)
+    }
+
+    /// Keep a change made to a track in Nova
+    pub fn set_track_tag(
+        &self,
+        track_id: &str,
+        change: &TagChange,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO track_tags (track_id, field, value) VALUES (?1, ?2, ?3)",
+            params![track_id, change.field(), change.value()],
+        )?;
+        Ok(())
+    }
+
+    /// Changes made to tracks in Nova, by track
+    pub fn track_tags(
+        &self,
+    ) -> Result<HashMap<String, Vec<TagChange>>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT track_id, field, value FROM track_tags")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .filter_map(Result::ok);
+
+        let mut changes: HashMap<String, Vec<TagChange>> = HashMap::new();
+        for (track_id, field, value) in rows {
+            if let Some(change) = TagChange::from_field(&field, value) {
+                changes.entry(track_id).or_default().push(change);
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Queue `change` to be written to the file at `path`, replacing any
+    /// older change to the same field that hasn't been written yet
+    pub fn queue_write_back(
+        &self,
+        track_id: &str,
+        path: &Path,
+        change: &TagChange,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO pending_write_backs (track_id, field, path, value, queued_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                track_id,
+                change.field(),
+                path.to_string_lossy(),
+                change.value(),
+                Utc::now().to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Keep a listen of `track` to be sent
+    pub fn queue_listen(
+        &self,
+        track: &Track,
+        listened_at: DateTime<Utc>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO pending_listens (artist, title, album, duration, listened_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                track.artist,
+                track.title,
+                track.album,
+                track.duration,
+                listened_at.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Up to `limit` listens waiting to be sent, oldest first
+    pub fn pending_listens(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<PendingListen>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let pending = conn
+            .prepare(
+                "SELECT id, artist, title, album, duration, listened_at FROM pending_listens
+                 ORDER BY id LIMIT ?1",
+            )?
+            .query_map(params![limit as i64], |row| {
+                let listened_at: String = row.get(5)?;
+                Ok(PendingListen {
+                    id: row.get(0)?,
+                    artist: row.get(1)?,
+                    title: row.get(2)?,
+                    album: row.get(3)?,
+                    duration: row.get(4)?,
+                    listened_at: DateTime::parse_from_rfc3339(&listened_at)
+                        .map(|time| time.with_timezone(&Utc))
+                        .unwrap_or_else(|_| Utc::now()),
+                })
+            })?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(pending)
+    }
+
+    /// Forget listens that have been sent
+    pub fn remove_listens(&self, ids: &[i64]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for id in ids {
+            tx.execute("DELETE FROM pending_listens WHERE id = ?1", params![id])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Changes waiting to be written to files, oldest first
+    pub fn pending_write_backs(
+        &self,
+    ) -> Result<Vec<PendingWriteBack>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let pending = conn
+            .prepare(
+                "SELECT track_id, field, path, value FROM pending_write_backs
+                 ORDER BY queued_at",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .filter_map(Result::ok)
+            .filter_map(|(track_id, field, path, value)| {
+                Some(PendingWriteBack {
+                    track_id,
+                    path: PathBuf::from(path),
+                    change: TagChange::from_field(&field, value)?,
+                })
+            })
+            .collect();
+        Ok(pending)
+    }
+
+    /// Drop a change from the queue once it's in the file
+    pub fn finish_write_back(
+        &self,
+        track_id: &str,
+        field: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "DELETE FROM pending_write_backs WHERE track_id = ?1 AND field = ?2",
+            params![track_id, field],
+        )?;
+        Ok(())
+    }
+
+    /// Info fetched earlier for the artist called `name`, however old
+    pub fn artist_info(
+        &self,
+        name: &str,
+    ) -> Result<Option<ArtistInfo>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT bio, similar, url, fetched_at FROM artist_info WHERE artist = ?1",
+        )?;
+        let mut rows = stmt.query(params![name.to_lowercase()])?;
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+
+        let similar: String = row.get(1)?;
+        let fetched_at: String = row.get(3)?;
+        Ok(Some(ArtistInfo {
+            name: name.to_string(),
+            bio: row.get(0)?,
+            // Artist names don't hold newlines, so they separate the list
+            similar: similar.lines().map(str::to_string).collect(),
+            url: row.get(2)?,
+            fetched_at: DateTime::parse_from_rfc3339(&fetched_at)
+                .map(|time| time.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now()),
+        }))
+    }
+
+    /// Keep info fetched for an artist, replacing what was there
+    pub fn save_artist_info(&self, info: &ArtistInfo) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO artist_info (artist, bio, similar, url, fetched_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                info.name.to_lowercase(),
+                info.bio,
+                info.similar.join("\n"),
+                info.url,
+                info.fetched_at.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Start and stop points set on tracks, by track ID
+    pub fn track_trims(&self) -> Result<HashMap<String, Trim>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT track_id, start_ms, end_ms FROM track_trims")?;
+        let trims = stmt
+            .query_map([], |row| {
+                let trim = Trim {
+                    start_ms: row.get::<_, i64>(1)? as u64,
+                    end_ms: row.get::<_, Option<i64>>(2)?.map(|end| end as u64),
+                };
+                Ok((row.get::<_, String>(0)?, trim))
+            })?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(trims)
+    }
+
+    /// Bookmarks set in tracks in the order they come, by track ID
+    pub fn track_bookmarks(
+        &self,
+    ) -> Result<HashMap<String, Vec<Bookmark>>, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT track_id, position_ms, name FROM track_bookmarks
+             ORDER BY track_id, position_ms",
+        )?;
+        let mut bookmarks: HashMap<String, Vec<Bookmark>> = HashMap::new();
+        let rows = stmt.query_map([], |row| {
+            let bookmark = Bookmark {
+                position_ms: row.get::<_, i64>(1)? as u64,
+                name: row.get(2)?,
+            };
+            Ok((row.get::<_, String>(0)?, bookmark))
+        })?;
+        for (track_id, bookmark) in rows.filter_map(Result::ok) {
+            bookmarks.entry(track_id).or_default().push(bookmark);
+        }
+        Ok(bookmarks)
+    }
+
+    /// Replace a track's bookmarks with `bookmarks`
+    pub fn set_track_bookmarks(
+        &self,
+        track_id: &str,
+        bookmarks: &[Bookmark],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM track_bookmarks WHERE track_id = ?1",
+            params![track_id],
+        )?;
+        for bookmark in bookmarks {
+            tx.execute(
+                "INSERT OR REPLACE INTO track_bookmarks (track_id, position_ms, name)
+                 VALUES (?1, ?2, ?3)",
+                params![track_id, bookmark.position_ms as i64, bookmark.name],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Set where a track starts and stops playing, or play all of it again
+    /// with None
+    pub fn set_track_trim(
+        &self,
+        track_id: &str,
+        trim: Option<Trim>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        match trim {
+            Some(trim) => conn.execute(
+                "INSERT OR REPLACE INTO track_trims (track_id, start_ms, end_ms)
+                 VALUES (?1, ?2, ?3)",
+                params![
+                    track_id,
+                    trim.start_ms as i64,
+                    trim.end_ms.map(|end| end as i64)
+                ],
+            )?,
+            None => conn.execute(
+                "DELETE FROM track_trims WHERE track_id = ?1",
+                params![track_id],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// Everything kept out of shuffle and mixes
+    pub fn shuffle_exclusions(&self) -> Result<ShuffleExclusions, Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT kind, item_key FROM shuffle_exclusions")?;
+        let mut exclusions = ShuffleExclusions::default();
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .filter_map(Result::ok);
+        for (kind, key) in rows {
+            match kind.as_str() {
+                "track" => exclusions.tracks.insert(key),
+                "album" => exclusions.albums.insert(key),
+                _ => false,
+            };
+        }
+        Ok(exclusions)
+    }
+
+    /// Keep a track out of shuffle and mixes, or let it back in
+    pub fn set_track_excluded(
+        &self,
+        track: &Track,
+        excluded: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.set_excluded("track", &track.id, excluded)
+    }
+
+    /// Keep an album with `tracks` out of shuffle and mixes, or let it back in
+    pub fn set_album_excluded(
+        &self,
+        album: &Album,
+        tracks: &[Track],
+        excluded: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for key in ShuffleExclusions::album_keys(album, tracks) {
+            self.set_excluded("album", &key, excluded)?;
+        }
+        Ok(())
+    }
+
+    fn set_excluded(
+        &self,
+        kind: &str,
+        key: &str,
+        excluded: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let conn = self.pool.get()?;
+        if excluded {
+            conn.execute(
+                "INSERT OR IGNORE INTO shuffle_exclusions (kind, item_key) VALUES (?1, ?2)",
+                params![kind, key],
+            )?;
+        } else {
+            conn.execute(
+                "DELETE FROM shuffle_exclusions WHERE kind = ?1 AND item_key = ?2",
+                params![kind, key],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Stores created before skips were tracked lack the column; newer ones have
+// it from the start
+fn add_skipped_column(conn: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let has_skipped: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('play_history') WHERE name = 'skipped'",
+        [],
+        |row| row.get(0),
+    )?;
+    if has_skipped == 0 {
+        conn.execute(
+            "ALTER TABLE play_history ADD COLUMN skipped INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_playlist_groups(conn: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+    conn.execute("ALTER TABLE playlists ADD COLUMN group_name TEXT", [])?;
+    Ok(())
+}
+
+// Left NULL, each setting follows the app's own
+fn add_playlist_playback(conn: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+    conn.execute_batch(
+        "ALTER TABLE playlists ADD COLUMN shuffle INTEGER;
+         ALTER TABLE playlists ADD COLUMN crossfade_ms INTEGER;
+         ALTER TABLE playlists ADD COLUMN album_gain INTEGER;",
+    )?;
+    Ok(())
+}
@@ -0,0 +1,760 @@
+//! Music from a WebDAV server, such as a Nextcloud music folder. The file
+//! list is walked with PROPFIND and cached along with each folder's ETag,
+//! so a refresh skips folders that haven't changed and only reads the tags
+//! of files whose ETag has. Tags come from the start of each file, fetched
+//! with a range request. Playback streams the file over HTTP; GStreamer
+//! asks for byte ranges as it seeks, so nothing is downloaded up front.
+
+use crate::platform;
+use crate::services::http::HttpClient;
+use crate::services::models::{
+    Album, Artist, Artwork, ArtworkSource, Availability, LibraryChange, LyricsMatch, PlayableItem,
+    PlaybackSource, RefreshMode, ReleaseType, SearchResults, SearchWeights, Track,
+};
+use crate::services::traits::MusicProvider;
+use async_trait::async_trait;
+use chrono::Utc;
+use gtk::glib;
+use parking_lot::RwLock as SyncRwLock;
+use reqwest::header::{CONTENT_TYPE, RANGE};
+use reqwest::{Client, Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::Arc;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{StandardTagKey, Tag};
+use symphonia::core::probe::Hint;
+use tokio::sync::{broadcast, Mutex, RwLock};
+
+// Bytes read from the start of a file for its tags. Enough for the usual
+// tags; files with large cover art embedded first fall back to their path.
+const TAG_BYTES: usize = 256 * 1024;
+
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "m4a", "ogg", "opus", "wav"];
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop>
+    <d:resourcetype/>
+    <d:getetag/>
+    <d:getcontentlength/>
+  </d:prop>
+</d:propfind>"#;
+
+const DAV: &str = "DAV:";
+
+/// Where the music is and how to sign in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebDavConfig {
+    /// Folder to index, e.g. https://cloud.example.com/remote.php/dav/files/me/Music/
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+// Login for the configured server, for the audio backend to stream with
+static CREDENTIALS: SyncRwLock<Option<WebDavConfig>> = SyncRwLock::new(None);
+
+/// Username and password to stream `uri` with, when it's on the configured
+/// WebDAV server
+pub fn credentials_for(uri: &str) -> Option<(String, String)> {
+    let config = CREDENTIALS.read();
+    let config = config.as_ref()?;
+    let (server, stream) = (Url::parse(&config.url).ok()?, Url::parse(uri).ok()?);
+    (server.origin() == stream.origin()).then(|| (config.username.clone(), config.password.clone()))
+}
+
+/// Stop handing out the login, once the provider is turned off
+pub fn forget_credentials() {
+    *CREDENTIALS.write() = None;
+}
+
+/// A file or folder from a PROPFIND listing
+#[derive(Debug)]
+struct DavEntry {
+    href: String,
+    is_folder: bool,
+    etag: String,
+}
+
+/// The file list as last synced, by href
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Index {
+    folders: HashMap<String, IndexedFolder>,
+    files: BTreeMap<String, IndexedFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedFolder {
+    etag: String,
+    folders: Vec<String>,
+    files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedFile {
+    etag: String,
+    track: Track,
+}
+
+impl Index {
+    /// Copy `href` and everything under it from `old`, for a folder whose
+    /// ETag hasn't changed
+    fn keep_folder(&mut self, old: &Index, href: &str) -> bool {
+        let Some(folder) = old.folders.get(href) else {
+            return false;
+        };
+        for file in &folder.files {
+            if let Some(indexed) = old.files.get(file) {
+                self.files.insert(file.clone(), indexed.clone());
+            }
+        }
+        for child in &folder.folders {
+            self.keep_folder(old, child);
+        }
+        self.folders.insert(href.to_string(), folder.clone());
+        true
+    }
+
+    fn tracks(&self) -> impl Iterator<Item = &Track> {
+        self.files.values().map(|file| &file.track)
+    }
+
+    /// Tracks added, removed or read again going from this to `new`
+    fn changes_to(&self, new: &Index) -> LibraryChange {
+        let mut change = LibraryChange::default();
+        for (href, file) in &new.files {
+            match self.files.get(href) {
+                None => change.added.push(file.track.id.clone()),
+                Some(known) if known.etag != file.etag => {
+                    change.updated.push(file.track.id.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        for (href, file) in &self.files {
+            if !new.files.contains_key(href) {
+                change.removed.push(file.track.id.clone());
+            }
+        }
+        change
+    }
+}
+
+#[derive(Debug)]
+pub struct WebDavProvider {
+    config: WebDavConfig,
+    root: Url,
+    client: HttpClient,
+    index: Arc<RwLock<Index>>,
+    // One sync at a time
+    syncing: Arc<Mutex<()>>,
+    cache_path: PathBuf,
+    changes: broadcast::Sender<LibraryChange>,
+}
+
+impl WebDavProvider {
+    /// Provider for `config`, with the file list from last time. Nothing is
+    /// asked of the server until it's refreshed, which is left for once it's
+    /// registered so what the sync finds is passed on as library changes.
+    pub async fn new(config: WebDavConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut root = Url::parse(&config.url)?;
+        if !root.path().ends_with('/') {
+            root.set_path(&format!("{}/", root.path()));
+        }
+
+        let key = format!(
+            "{:x}",
+            Sha1::digest(format!("{}\n{}", root, config.username))
+        );
+        let cache_path = platform::cache_dir()
+            .join("webdav")
+            .join(format!("{}.json", key));
+        let index: Option<Index> = std::fs::read(&cache_path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok());
+
+        *CREDENTIALS.write() = Some(config.clone());
+
+        Ok(Self {
+            config,
+            root,
+            client: HttpClient::new(
+                Client::builder()
+                    .timeout(std::time::Duration::from_secs(60))
+                    .build()?,
+            ),
+            index: Arc::new(RwLock::new(index.unwrap_or_default())),
+            syncing: Arc::new(Mutex::new(())),
+            cache_path,
+            changes: broadcast::channel(16).0,
+        })
+    }
+
+    /// Bring the file list up to date. Folders whose ETag is unchanged are
+    /// kept without listing them again, unless `full` is set.
+    async fn sync(&self, full: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _syncing = self.syncing.lock().await;
+        let old = if full {
+            Index::default()
+        } else {
+            self.index.read().await.clone()
+        };
+
+        let mut new = Index::default();
+        let mut pending = vec![self.root.path().to_string()];
+        let (mut listed, mut read) = (0, 0);
+        while let Some(href) = pending.pop() {
+            let mut entries = self.propfind(&href).await?;
+            listed += 1;
+
+            // The folder itself comes first
+            let own = entries
+                .iter()
+                .position(|entry| same_path(&entry.href, &href))
+                .unwrap_or(0);
+            let own = entries.remove(own);
+
+            let mut folder = IndexedFolder {
+                etag: own.etag,
+                folders: Vec::new(),
+                files: Vec::new(),
+            };
+            for entry in entries {
+                if entry.is_folder {
+                    let unchanged = old
+                        .folders
+                        .get(&entry.href)
+                        .is_some_and(|known| known.etag == entry.etag);
+                    if !(unchanged && new.keep_folder(&old, &entry.href)) {
+                        pending.push(entry.href.clone());
+                    }
+                    folder.folders.push(entry.href);
+                    continue;
+                }
+                if !is_audio_file(&entry.href) {
+                    continue;
+                }
+
+                let indexed = match old.files.get(&entry.href) {
+                    Some(known) if known.etag == entry.etag => known.clone(),
+                    _ => {
+                        read += 1;
+                        IndexedFile {
+                            track: self.read_track(&entry.href).await,
+                            etag: entry.etag,
+                        }
+                    }
+                };
+                new.files.insert(entry.href.clone(), indexed);
+                folder.files.push(entry.href);
+            }
+            new.folders.insert(href, folder);
+        }
+
+        println!(
+            "WebDAV sync listed {} folders and read {} new or changed files, {} tracks in all",
+            listed,
+            read,
+            new.files.len()
+        );
+        let change = {
+            let mut index = self.index.write().await;
+            let change = index.changes_to(&new);
+            *index = new.clone();
+            change
+        };
+        self.save_index(&new);
+        if !change.is_empty() {
+            // Nobody listening isn't an error
+            let _ = self.changes.send(change);
+        }
+        Ok(())
+    }
+
+    fn save_index(&self, index: &Index) {
+        let result = self
+            .cache_path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_vec(index).map_err(|e| e.to_string()))
+            .and_then(|data| std::fs::write(&self.cache_path, data).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("Failed to save WebDAV file list: {}", e);
+        }
+    }
+
+    fn url_for(&self, href: &str) -> Result<Url, Box<dyn Error + Send + Sync>> {
+        Ok(self.root.join(href)?)
+    }
+
+    fn request(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, url)
+            .basic_auth(&self.config.username, Some(&self.config.password))
+    }
+
+    /// The folder at `href` and what's directly in it
+    async fn propfind(&self, href: &str) -> Result<Vec<DavEntry>, Box<dyn Error + Send + Sync>> {
+        let request = self
+            .request(Method::from_bytes(b"PROPFIND")?, self.url_for(href)?)
+            .header("Depth", "1")
+            .header(CONTENT_TYPE, "application/xml")
+            .body(PROPFIND_BODY);
+        let response = self.client.send(request).await?;
+        if response.status() != StatusCode::MULTI_STATUS {
+            return Err(format!("Listing {} failed: {}", href, response.status()).into());
+        }
+        parse_multistatus(&response.text().await?)
+    }
+
+    /// Track for the file at `href`, from the tags at the start of the file
+    /// or, failing that, from its path
+    async fn read_track(&self, href: &str) -> Track {
+        let url = match self.url_for(href) {
+            Ok(url) => url,
+            Err(_) => return track_from_path(href, href.to_string()),
+        };
+        let track = track_from_path(href, url.to_string());
+        match self.fetch_start(&url).await {
+            Ok(data) => {
+                let extension = extension(href).unwrap_or_default();
+                tokio::task::spawn_blocking(move || read_tags(data, &extension, track))
+                    .await
+                    .unwrap_or_else(|_| track_from_path(href, url.to_string()))
+            }
+            Err(e) => {
+                eprintln!("Failed to read tags of {}: {}", href, e);
+                track
+            }
+        }
+    }
+
+    /// The first TAG_BYTES of a file. Servers that ignore the range send the
+    /// whole file, so reading stops once there's enough.
+    async fn fetch_start(&self, url: &Url) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let request = self
+            .request(Method::GET, url.clone())
+            .header(RANGE, format!("bytes=0-{}", TAG_BYTES - 1));
+        let mut response = self.client.send(request).await?.error_for_status()?;
+        let mut data = Vec::with_capacity(TAG_BYTES);
+        while let Some(chunk) = response.chunk().await? {
+            data.extend_from_slice(&chunk);
+            if data.len() >= TAG_BYTES {
+                data.truncate(TAG_BYTES);
+                break;
+            }
+        }
+        Ok(data)
+    }
+
+    async fn albums(&self) -> Vec<Album> {
+        let index = self.index.read().await;
+        let mut albums: BTreeMap<(String, String), Album> = BTreeMap::new();
+        for track in index.tracks() {
+            let key = (track.artist.to_lowercase(), track.album.to_lowercase());
+            let album = albums.entry(key).or_insert_with(|| Album {
+                id: album_id(&track.artist, &track.album),
+                title: track.album.clone(),
+                artist: track.artist.clone(),
+                year: track.release_year,
+                release_type: track.release_type.unwrap_or(ReleaseType::Album),
+                art_url: None,
+                tracks: Vec::new(),
+                artwork: None,
+                sources: Vec::new(),
+            });
+            album.tracks.push(track.id.clone());
+        }
+        albums.into_values().collect()
+    }
+
+    async fn artists(&self) -> Vec<Artist> {
+        let mut artists: BTreeMap<String, Artist> = BTreeMap::new();
+        for album in self.albums().await {
+            let artist = artists
+                .entry(album.artist.to_lowercase())
+                .or_insert_with(|| Artist {
+                    id: artist_id(&album.artist),
+                    name: album.artist.clone(),
+                    albums: Vec::new(),
+                    artwork: None,
+                    sources: Vec::new(),
+                });
+            artist.albums.push(album.id);
+        }
+        artists.into_values().collect()
+    }
+}
+
+fn album_id(artist: &str, title: &str) -> String {
+    let key = format!(
+        "webdav-album\n{}\n{}",
+        artist.to_lowercase(),
+        title.to_lowercase()
+    );
+    format!("{:x}", Sha1::digest(key))
+}
+
+fn artist_id(name: &str) -> String {
+    format!(
+        "{:x}",
+        Sha1::digest(format!("webdav-artist\n{}", name.to_lowercase()))
+    )
+}
+
+fn matches(query: &str, fields: &[&str]) -> bool {
+    let query = query.to_lowercase();
+    fields
+        .iter()
+        .any(|field| field.to_lowercase().contains(&query))
+}
+
+fn paginate<T>(items: impl Iterator<Item = T>, limit: usize, offset: usize) -> Vec<T> {
+    items.skip(offset).take(limit).collect()
+}
+
+fn extension(href: &str) -> Option<String> {
+    let name = href.rsplit('/').next()?;
+    let (_, extension) = name.rsplit_once('.')?;
+    Some(extension.to_lowercase())
+}
+
+fn is_audio_file(href: &str) -> bool {
+    extension(href).is_some_and(|extension| AUDIO_EXTENSIONS.contains(&extension.as_str()))
+}
+
+fn unescape(segment: &str) -> String {
+    glib::uri_unescape_string(segment, None::<&str>)
+        .map(|text| text.to_string())
+        .unwrap_or_else(|| segment.to_string())
+}
+
+/// Whether two hrefs name the same path, however they're escaped
+fn same_path(a: &str, b: &str) -> bool {
+    unescape(a.trim_end_matches('/')) == unescape(b.trim_end_matches('/'))
+}
+
+/// Track with what the path says, for files laid out as
+/// Artist/Album/01 Title.flac
+fn track_from_path(href: &str, url: String) -> Track {
+    let mut segments = href.trim_end_matches('/').rsplit('/').map(unescape);
+    let file = segments.next().unwrap_or_default();
+    let album = segments
+        .next()
+        .unwrap_or_else(|| "Unknown Album".to_string());
+    let artist = segments
+        .next()
+        .unwrap_or_else(|| "Unknown Artist".to_string());
+
+    let stem = file
+        .rsplit_once('.')
+        .map_or(file.as_str(), |(stem, _)| stem);
+    let digits: String = stem.chars().take_while(char::is_ascii_digit).collect();
+    let title = stem[digits.len()..]
+        .trim_start_matches(|c: char| c == ' ' || c == '-' || c == '.' || c == '_')
+        .to_string();
+
+    Track {
+        id: format!("{:x}", Sha1::digest(&url)),
+        title: if title.is_empty() {
+            stem.to_string()
+        } else {
+            title
+        },
+        artist,
+        album,
+        duration: 0,
+        track_number: digits.parse().ok(),
+        track_total: None,
+        disc_number: None,
+        release_year: None,
+        genre: None,
+        explicit: false,
+        lyrics: None,
+        release_type: None,
+        artwork: Artwork {
+            id: None,
+            thumbnails: None,
+            full_art: ArtworkSource::None,
+        },
+        source: PlaybackSource::Stream {
+            url,
+            low_quality_url: None,
+        },
+        availability: Availability::StreamOnly,
+    }
+}
+
+/// `track` with whatever tags the start of the file has
+fn read_tags(data: Vec<u8>, extension: &str, mut track: Track) -> Track {
+    let source = MediaSourceStream::new(Box::new(Cursor::new(data)), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(extension);
+    let Ok(mut probed) = symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &Default::default(),
+        &Default::default(),
+    ) else {
+        return track;
+    };
+
+    // ID3 tags are read by the probe; other formats carry their own
+    let mut tags: Vec<Tag> = Vec::new();
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            tags.extend(revision.tags().iter().cloned());
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend(revision.tags().iter().cloned());
+    }
+
+    for tag in tags {
+        let value = tag.value.to_string();
+        match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => track.title = value,
+            Some(StandardTagKey::Artist) => track.artist = value,
+            Some(StandardTagKey::Album) => track.album = value,
+            Some(StandardTagKey::TrackNumber) => {
+                let mut parts = value.split('/').map(|n| n.trim().parse().ok());
+                track.track_number = parts.next().flatten();
+                if let Some(total) = parts.next().flatten() {
+                    track.track_total = Some(total);
+                }
+            }
+            Some(StandardTagKey::TrackTotal) => track.track_total = value.trim().parse().ok(),
+            Some(StandardTagKey::DiscNumber) => {
+                track.disc_number = value.split('/').next().and_then(|n| n.parse().ok())
+            }
+            Some(StandardTagKey::Date) => {
+                track.release_year = value.split('-').next().and_then(|y| y.parse().ok())
+            }
+            Some(StandardTagKey::Genre) => track.genre = Some(value),
+            _ => {}
+        }
+    }
+
+    if let Some(params) = probed.format.tracks().first().map(|t| &t.codec_params) {
+        if let (Some(frames), Some(rate)) = (params.n_frames, params.sample_rate) {
+            track.duration = (frames as f64 / rate as f64) as u32;
+        }
+    }
+    track
+}
+
+fn parse_multistatus(xml: &str) -> Result<Vec<DavEntry>, Box<dyn Error + Send + Sync>> {
+    let document = roxmltree::Document::parse(xml)?;
+    let entries = document
+        .descendants()
+        .filter(|node| node.has_tag_name((DAV, "response")))
+        .filter_map(|response| {
+            let href = response
+                .children()
+                .find(|node| node.has_tag_name((DAV, "href")))?
+                .text()?
+                .to_string();
+            // Properties the server couldn't give come in their own propstat
+            let prop = response
+                .children()
+                .filter(|node| node.has_tag_name((DAV, "propstat")))
+                .find(|propstat| {
+                    propstat.children().any(|node| {
+                        node.has_tag_name((DAV, "status"))
+                            && node.text().is_some_and(|status| status.contains(" 200"))
+                    })
+                })?
+                .children()
+                .find(|node| node.has_tag_name((DAV, "prop")))?;
+            let property = |name: &str| {
+                prop.children()
+                    .find(|node| node.has_tag_name((DAV, name)))
+                    .and_then(|node| node.text())
+                    .unwrap_or_default()
+                    .trim()
+                    .trim_matches('"')
+                    .to_string()
+            };
+            Some(DavEntry {
+                is_folder: prop
+                    .descendants()
+                    .any(|node| node.has_tag_name((DAV, "collection"))),
+                etag: property("getetag"),
+                href,
+            })
+        })
+        .collect();
+    Ok(entries)
+}
+
+#[async_trait]
+impl MusicProvider for WebDavProvider {
+    async fn get_tracks(&self) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        Ok(self.index.read().await.tracks().cloned().collect())
+    }
+
+    fn subscribe_changes(&self) -> Option<broadcast::Receiver<LibraryChange>> {
+        Some(self.changes.subscribe())
+    }
+
+    async fn get_albums(&self) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        Ok(self.albums().await)
+    }
+
+    async fn get_artists(&self) -> Result<Vec<Artist>, Box<dyn Error + Send + Sync>> {
+        Ok(self.artists().await)
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        self.search_tracks(query, limit, offset).await
+    }
+
+    async fn search_tracks(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        let index = self.index.read().await;
+        let found = index
+            .tracks()
+            .filter(|track| matches(query, &[&track.title, &track.artist, &track.album]))
+            .cloned();
+        Ok(paginate(found, limit, offset))
+    }
+
+    async fn search_albums(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        let albums = self.albums().await.into_iter();
+        let found = albums.filter(|album| matches(query, &[&album.title, &album.artist]));
+        Ok(paginate(found, limit, offset))
+    }
+
+    async fn search_artists(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Artist>, Box<dyn Error + Send + Sync>> {
+        let artists = self.artists().await.into_iter();
+        let found = artists.filter(|artist| matches(query, &[&artist.name]));
+        Ok(paginate(found, limit, offset))
+    }
+
+    async fn search_all(
+        &self,
+        query: &str,
+        _weights: &SearchWeights,
+        limit: usize,
+        offset: usize,
+    ) -> Result<SearchResults, Box<dyn Error + Send + Sync>> {
+        let tracks = self.search_tracks(query, limit, offset).await?;
+        Ok(SearchResults {
+            tracks: tracks
+                .into_iter()
+                .map(|track| PlayableItem {
+                    track,
+                    provider: "webdav".to_string(),
+                    added_at: Utc::now(),
+                })
+                .collect(),
+            albums: self.search_albums(query, limit, offset).await?,
+            artists: self.search_artists(query, limit, offset).await?,
+            versions: HashMap::new(),
+        })
+    }
+
+    async fn search_lyrics(
+        &self,
+        _query: &str,
+        _limit: usize,
+    ) -> Result<Vec<LyricsMatch>, Box<dyn Error + Send + Sync>> {
+        Ok(Vec::new())
+    }
+
+    async fn find_album(
+        &self,
+        track: &Track,
+    ) -> Result<Option<Album>, Box<dyn Error + Send + Sync>> {
+        let id = album_id(&track.artist, &track.album);
+        Ok(self.albums().await.into_iter().find(|album| album.id == id))
+    }
+
+    async fn find_artist(
+        &self,
+        track: &Track,
+    ) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>> {
+        self.get_artist(&artist_id(&track.artist)).await
+    }
+
+    async fn get_track(&self, id: &str) -> Result<Option<Track>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .index
+            .read()
+            .await
+            .tracks()
+            .find(|track| track.id == id)
+            .cloned())
+    }
+
+    async fn get_album(&self, id: &str) -> Result<Option<Album>, Box<dyn Error + Send + Sync>> {
+        Ok(self.albums().await.into_iter().find(|album| album.id == id))
+    }
+
+    async fn get_artist(&self, id: &str) -> Result<Option<Artist>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .artists()
+            .await
+            .into_iter()
+            .find(|artist| artist.id == id))
+    }
+
+    async fn get_album_tracks(
+        &self,
+        album_id: &str,
+    ) -> Result<Vec<Track>, Box<dyn Error + Send + Sync>> {
+        let Some(album) = self.get_album(album_id).await? else {
+            return Ok(Vec::new());
+        };
+        let index = self.index.read().await;
+        let mut tracks: Vec<Track> = index
+            .tracks()
+            .filter(|track| album.tracks.contains(&track.id))
+            .cloned()
+            .collect();
+        tracks.sort_by_key(|track| (track.disc_number, track.track_number));
+        Ok(tracks)
+    }
+
+    async fn get_artist_albums(
+        &self,
+        artist_id: &str,
+    ) -> Result<Vec<Album>, Box<dyn Error + Send + Sync>> {
+        let Some(artist) = self.get_artist(artist_id).await? else {
+            return Ok(Vec::new());
+        };
+        let albums = self.albums().await.into_iter();
+        Ok(albums
+            .filter(|album| artist.albums.contains(&album.id))
+            .collect())
+    }
+
+    async fn refresh(&self, mode: RefreshMode) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.sync(mode == RefreshMode::Full).await
+    }
+}
@@ -0,0 +1,743 @@
+//! Writing changes to tracks back into the tags of local files. Nova's own
+//! database always has the change; the policy decides which kinds are also
+//! written to the file. Writes wait in a queue in the user store, so files
+//! on read-only or network mounts are left alone until they can be written.
+//! ReplayGain tags from loudness analysis and album art for files without
+//! any are written here too.
+
+use crate::platform;
+use crate::services::local::{analyze_loudness, Loudness};
+use crate::services::models::{PlaybackSource, Track};
+use crate::services::resources;
+use crate::services::tasks::Task;
+use crate::services::user_store::UserStore;
+use id3::frame::{ExtendedText, Picture, PictureType, Popularimeter};
+use id3::{Tag, TagLike, Version};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use mp4ameta::{Data, FreeformIdent, Img};
+use parking_lot::{Mutex, RwLock};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// Name Nova's ratings and play counts are kept under in POPM frames
+const POPM_USER: &str = "nova";
+
+// POPM ratings for 0-5 stars, as most players read them
+const POPM_RATINGS: [u8; 6] = [0, 1, 64, 128, 196, 255];
+
+// ReplayGain tag names, as Vorbis comments and ID3 TXXX frames use them.
+// MP4 files have them lower-case in iTunes freeform atoms.
+const TRACK_GAIN_TAG: &str = "REPLAYGAIN_TRACK_GAIN";
+const TRACK_PEAK_TAG: &str = "REPLAYGAIN_TRACK_PEAK";
+const ALBUM_GAIN_TAG: &str = "REPLAYGAIN_ALBUM_GAIN";
+const ALBUM_PEAK_TAG: &str = "REPLAYGAIN_ALBUM_PEAK";
+const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+// Rating and play count names in Vorbis comments, and lower-case in MP4
+// freeform atoms
+const RATING_TAG: &str = "RATING";
+const PLAY_COUNT_TAG: &str = "PLAYCOUNT";
+
+// Art bigger than this on disk is scaled down before it's embedded, even
+// within the size limit, as it's copied into every track of the album
+const MAX_EMBEDDED_ART_BYTES: usize = 1024 * 1024;
+
+const EMBEDDED_ART_QUALITY: u8 = 90;
+
+// Held while a file's tags are being written, so two jobs never write the
+// same file at once
+static WRITE_LOCK: Mutex<()> = parking_lot::const_mutex(());
+
+// Edits made in Nova, by track. They're laid over tracks as they're handed
+// out, so they show whether or not the files were written.
+static TAG_EDITS: RwLock<BTreeMap<String, Vec<TagChange>>> = RwLock::new(BTreeMap::new());
+
+/// A change to one tag of a track
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagChange {
+    Title(String),
+    Artist(String),
+    Album(String),
+    Genre(String),
+    /// Stars, 0-5
+    Rating(u8),
+    PlayCount(u32),
+}
+
+/// The kinds of change the policy is set for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Edit,
+    Rating,
+    PlayCount,
+}
+
+impl TagChange {
+    pub fn kind(&self) -> ChangeKind {
+        match self {
+            Self::Rating(_) => ChangeKind::Rating,
+            Self::PlayCount(_) => ChangeKind::PlayCount,
+            _ => ChangeKind::Edit,
+        }
+    }
+
+    /// Name the change is stored under; a newer change to the same field
+    /// replaces an older one
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::Title(_) => "title",
+            Self::Artist(_) => "artist",
+            Self::Album(_) => "album",
+            Self::Genre(_) => "genre",
+            Self::Rating(_) => "rating",
+            Self::PlayCount(_) => "play-count",
+        }
+    }
+
+    pub fn value(&self) -> String {
+        match self {
+            Self::Title(value) | Self::Artist(value) | Self::Album(value) | Self::Genre(value) => {
+                value.clone()
+            }
+            Self::Rating(stars) => stars.to_string(),
+            Self::PlayCount(count) => count.to_string(),
+        }
+    }
+
+    /// Show the change on `track`. Ratings and play counts aren't shown on
+    /// tracks.
+    pub fn apply_to(&self, track: &mut Track) {
+        match self {
+            Self::Title(value) => track.title = value.clone(),
+            Self::Artist(value) => track.artist = value.clone(),
+            Self::Album(value) => track.album = value.clone(),
+            Self::Genre(value) => track.genre = Some(value.clone()),
+            Self::Rating(_) | Self::PlayCount(_) => {}
+        }
+    }
+
+    pub fn from_field(field: &str, value: String) -> Option<Self> {
+        match field {
+            "title" => Some(Self::Title(value)),
+            "artist" => Some(Self::Artist(value)),
+            "album" => Some(Self::Album(value)),
+            "genre" => Some(Self::Genre(value)),
+            "rating" => value
+                .parse()
+                .ok()
+                .map(|stars: u8| Self::Rating(stars.min(5))),
+            "play-count" => value.parse().ok().map(Self::PlayCount),
+            _ => None,
+        }
+    }
+}
+
+/// Which changes are written to files rather than kept only in the database
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteBackPolicy {
+    pub edits: bool,
+    pub ratings: bool,
+    pub play_counts: bool,
+    /// Also write files on network mounts, which can be slow or shared
+    pub network_files: bool,
+}
+
+impl WriteBackPolicy {
+    pub fn writes(&self, kind: ChangeKind) -> bool {
+        match kind {
+            ChangeKind::Edit => self.edits,
+            ChangeKind::Rating => self.ratings,
+            ChangeKind::PlayCount => self.play_counts,
+        }
+    }
+}
+
+/// A change waiting to be written to a file
+#[derive(Debug, Clone)]
+pub struct PendingWriteBack {
+    pub track_id: String,
+    pub path: PathBuf,
+    pub change: TagChange,
+}
+
+/// Keep `change` to `track` in the database and queue it for its file when
+/// the policy says so. Play counts already live in the play history.
+pub fn record_change(
+    store: &UserStore,
+    policy: WriteBackPolicy,
+    track: &Track,
+    change: TagChange,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if change.kind() != ChangeKind::PlayCount {
+        store.set_track_tag(&track.id, &change)?;
+    }
+    if change.kind() == ChangeKind::Edit {
+        let mut edits = TAG_EDITS.write();
+        let changes = edits.entry(track.id.clone()).or_default();
+        changes.retain(|kept| kept.field() != change.field());
+        changes.push(change.clone());
+    }
+
+    // A chapter's rating or play count isn't the whole file's
+    if let PlaybackSource::Local {
+        path,
+        segment: None,
+        ..
+    } = &track.source
+    {
+        if policy.writes(change.kind()) {
+            store.queue_write_back(&track.id, path, &change)?;
+        }
+    }
+    Ok(())
+}
+
+/// Show the edits kept in `store` on tracks from now on
+pub fn load_tag_edits(store: &UserStore) {
+    match store.track_tags() {
+        Ok(tags) => {
+            *TAG_EDITS.write() = tags
+                .into_iter()
+                .map(|(track_id, mut changes)| {
+                    changes.retain(|change| change.kind() == ChangeKind::Edit);
+                    (track_id, changes)
+                })
+                .collect();
+        }
+        Err(e) => eprintln!("Failed to load tag edits: {}", e),
+    }
+}
+
+/// Give each track the edits made to it in Nova
+pub fn apply_tag_edits<'a>(tracks: impl IntoIterator<Item = &'a mut Track>) {
+    let edits = TAG_EDITS.read();
+    if edits.is_empty() {
+        return;
+    }
+    for track in tracks {
+        for change in edits.get(&track.id).into_iter().flatten() {
+            change.apply_to(track);
+        }
+    }
+}
+
+/// Write whatever queued changes can be written now and return how many
+/// were. Changes the policy no longer allows, those for files that are
+/// read-only or on a network mount the policy skips, and those for the
+/// `playing` file stay queued.
+pub fn flush(store: &UserStore, policy: WriteBackPolicy, playing: Option<&Path>) -> usize {
+    // Another job is writing; whatever is queued waits for the next flush
+    let Some(_guard) = WRITE_LOCK.try_lock() else {
+        return 0;
+    };
+
+    let pending = match store.pending_write_backs() {
+        Ok(pending) => pending,
+        Err(e) => {
+            eprintln!("Failed to load pending tag writes: {}", e);
+            return 0;
+        }
+    };
+
+    let mut written = 0;
+    for item in pending {
+        if !policy.writes(item.change.kind())
+            || !can_write(&item.path, policy)
+            || playing == Some(item.path.as_path())
+        {
+            continue;
+        }
+
+        match write_change(&item.path, &item.change) {
+            Ok(true) => written += 1,
+            // Kept in the database only
+            Ok(false) => println!("Can't write tags to {:?}, keeping them in Nova", item.path),
+            Err(e) => {
+                eprintln!("Failed to write tags to {:?}: {}", item.path, e);
+                continue;
+            }
+        }
+        if let Err(e) = store.finish_write_back(&item.track_id, item.change.field()) {
+            eprintln!("Failed to update pending tag writes: {}", e);
+        }
+    }
+
+    written
+}
+
+fn can_write(path: &Path, policy: WriteBackPolicy) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+    let mount = platform::mount_info(path);
+    !metadata.permissions().readonly()
+        && !mount.read_only
+        && (policy.network_files || !mount.network)
+}
+
+/// Write `change` into the file's tags. Returns false for formats whose
+/// tags can't be written.
+fn write_change(path: &Path, change: &TagChange) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    match extension(path).as_deref() {
+        Some("mp3") => write_id3_change(path, change)?,
+        Some("flac") => write_vorbis_change(path, change)?,
+        Some("m4a" | "mp4" | "m4b") => write_mp4_change(path, change)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn write_id3_change(path: &Path, change: &TagChange) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut tag = read_id3(path)?;
+
+    match change {
+        TagChange::Title(value) => tag.set_title(value.as_str()),
+        TagChange::Artist(value) => tag.set_artist(value.as_str()),
+        TagChange::Album(value) => tag.set_album(value.as_str()),
+        TagChange::Genre(value) => tag.set_genre(value.as_str()),
+        TagChange::Rating(stars) => {
+            let mut popm = popularimeter(&tag);
+            popm.rating = POPM_RATINGS[usize::from(*stars).min(5)];
+            tag.add_frame(popm);
+        }
+        TagChange::PlayCount(count) => {
+            let mut popm = popularimeter(&tag);
+            popm.counter = u64::from(*count);
+            tag.add_frame(popm);
+        }
+    }
+
+    tag.write_to_path(path, Version::Id3v24)?;
+    Ok(())
+}
+
+fn write_vorbis_change(
+    path: &Path,
+    change: &TagChange,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut tag = metaflac::Tag::read_from_path(path)?;
+    let (name, value) = match change {
+        TagChange::Title(value) => ("TITLE", value.clone()),
+        TagChange::Artist(value) => ("ARTIST", value.clone()),
+        TagChange::Album(value) => ("ALBUM", value.clone()),
+        TagChange::Genre(value) => ("GENRE", value.clone()),
+        TagChange::Rating(stars) => (RATING_TAG, rating_percent(*stars).to_string()),
+        TagChange::PlayCount(count) => (PLAY_COUNT_TAG, count.to_string()),
+    };
+    tag.set_vorbis(name, vec![value]);
+    tag.save()?;
+    Ok(())
+}
+
+fn write_mp4_change(path: &Path, change: &TagChange) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut tag = mp4ameta::Tag::read_from_path(path)?;
+    match change {
+        TagChange::Title(value) => tag.set_title(value.as_str()),
+        TagChange::Artist(value) => tag.set_artist(value.as_str()),
+        TagChange::Album(value) => tag.set_album(value.as_str()),
+        TagChange::Genre(value) => tag.set_genre(value.as_str()),
+        TagChange::Rating(stars) => {
+            let name = RATING_TAG.to_ascii_lowercase();
+            let value = rating_percent(*stars).to_string();
+            tag.set_data(FreeformIdent::new(ITUNES_MEAN, &name), Data::Utf8(value));
+        }
+        TagChange::PlayCount(count) => {
+            let name = PLAY_COUNT_TAG.to_ascii_lowercase();
+            let value = count.to_string();
+            tag.set_data(FreeformIdent::new(ITUNES_MEAN, &name), Data::Utf8(value));
+        }
+    }
+    tag.write_to_path(path)?;
+    Ok(())
+}
+
+/// Stars as the 0-100 rating Vorbis comments and MP4 atoms hold
+fn rating_percent(stars: u8) -> u8 {
+    stars.min(5) * 20
+}
+
+/// Nova's POPM frame from `tag`, or an empty one
+fn popularimeter(tag: &Tag) -> Popularimeter {
+    tag.frames()
+        .filter_map(|frame| frame.content().popularimeter())
+        .find(|popm| popm.user == POPM_USER)
+        .cloned()
+        .unwrap_or_else(|| Popularimeter {
+            user: POPM_USER.to_string(),
+            rating: 0,
+            counter: 0,
+        })
+}
+
+/// Lower-case extension of `path`, which decides how its tags are written
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+/// The file's ID3 tag, or a new one if it has none
+fn read_id3(path: &Path) -> Result<Tag, Box<dyn Error + Send + Sync>> {
+    match Tag::read_from_path(path) {
+        Ok(tag) => Ok(tag),
+        Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => Ok(Tag::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether ReplayGain tags can be written to files like `path`
+pub fn supports_replay_gain(path: &Path) -> bool {
+    matches!(
+        extension(path).as_deref(),
+        Some("mp3" | "flac" | "m4a" | "mp4" | "m4b")
+    )
+}
+
+/// Whether the file at `path` already has track ReplayGain tags
+pub fn has_replay_gain(path: &Path) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    match extension(path).as_deref() {
+        Some("mp3") => Ok(read_id3(path)?
+            .extended_texts()
+            .any(|text| text.description.eq_ignore_ascii_case(TRACK_GAIN_TAG))),
+        Some("flac") => Ok(metaflac::Tag::read_from_path(path)?
+            .get_vorbis(TRACK_GAIN_TAG)
+            .is_some_and(|mut values| values.next().is_some())),
+        Some("m4a" | "mp4" | "m4b") => {
+            let name = TRACK_GAIN_TAG.to_ascii_lowercase();
+            let ident = FreeformIdent::new(ITUNES_MEAN, &name);
+            Ok(mp4ameta::Tag::read_from_path(path)?
+                .data_of(&ident)
+                .next()
+                .is_some())
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Write ReplayGain tags for the track's `loudness` and its `album`'s into
+/// the file at `path`: TXXX frames in MP3s, Vorbis comments in FLAC and
+/// freeform atoms in MP4s
+pub fn write_replay_gain(
+    path: &Path,
+    loudness: &Loudness,
+    album: &Loudness,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let values = [
+        (TRACK_GAIN_TAG, format!("{:.2} dB", loudness.track_gain)),
+        (TRACK_PEAK_TAG, format!("{:.6}", loudness.track_peak)),
+        (ALBUM_GAIN_TAG, format!("{:.2} dB", album.track_gain)),
+        (ALBUM_PEAK_TAG, format!("{:.6}", album.track_peak)),
+    ];
+
+    let _guard = WRITE_LOCK.lock();
+    match extension(path).as_deref() {
+        Some("mp3") => {
+            let mut tag = read_id3(path)?;
+            for (name, value) in values {
+                tag.add_frame(ExtendedText {
+                    description: name.to_string(),
+                    value,
+                });
+            }
+            tag.write_to_path(path, Version::Id3v24)?;
+        }
+        Some("flac") => {
+            let mut tag = metaflac::Tag::read_from_path(path)?;
+            for (name, value) in values {
+                tag.set_vorbis(name, vec![value]);
+            }
+            tag.save()?;
+        }
+        Some("m4a" | "mp4" | "m4b") => {
+            let mut tag = mp4ameta::Tag::read_from_path(path)?;
+            for (name, value) in values {
+                let name = name.to_ascii_lowercase();
+                tag.set_data(FreeformIdent::new(ITUNES_MEAN, &name), Data::Utf8(value));
+            }
+            tag.write_to_path(path)?;
+        }
+        _ => return Err(format!("Can't write ReplayGain tags to {:?}", path).into()),
+    }
+    Ok(())
+}
+
+/// Outcome of a ReplayGain batch job
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayGainReport {
+    pub written: usize,
+    /// Files that already had ReplayGain tags
+    pub already_tagged: usize,
+    /// Formats without ReplayGain support and files that can't be written
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// The loudness of a whole album from its tracks' loudness and lengths in
+/// seconds: the tracks' gains averaged by the energy they carry over their
+/// length, and the highest peak
+fn album_loudness(tracks: &[(Loudness, u32)]) -> Option<Loudness> {
+    if tracks.is_empty() {
+        return None;
+    }
+    // A track of unknown length still counts for something
+    let weight = |length: u32| f64::from(length.max(1));
+    let total: f64 = tracks.iter().map(|(_, length)| weight(*length)).sum();
+    let energy: f64 = tracks
+        .iter()
+        .map(|(loudness, length)| weight(*length) * 10f64.powf(-loudness.track_gain / 10.0))
+        .sum();
+    Some(Loudness {
+        track_gain: -10.0 * (energy / total).log10(),
+        track_peak: tracks
+            .iter()
+            .map(|(loudness, _)| loudness.track_peak)
+            .fold(0.0, f64::max),
+    })
+}
+
+/// Analyze each album in `albums` that has files without ReplayGain tags,
+/// and write track and album tags to those files. Every file of the album
+/// is analyzed, as the album gain covers them all. `albums` holds each
+/// album's files with their lengths in seconds. Files are handled one at a
+/// time, as decoding them is slow.
+pub fn write_replay_gain_batch(
+    albums: &[Vec<(PathBuf, u32)>],
+    policy: WriteBackPolicy,
+) -> ReplayGainReport {
+    let mut report = ReplayGainReport::default();
+    for files in albums {
+        let mut untagged = Vec::new();
+        for (path, _) in files {
+            if !supports_replay_gain(path) || !can_write(path, policy) {
+                report.skipped += 1;
+                continue;
+            }
+            match has_replay_gain(path) {
+                Ok(true) => report.already_tagged += 1,
+                Ok(false) => untagged.push(path),
+                Err(e) => {
+                    eprintln!("Failed to read tags of {:?}: {}", path, e);
+                    report.failed += 1;
+                }
+            }
+        }
+        if untagged.is_empty() {
+            continue;
+        }
+
+        let mut measured = HashMap::new();
+        for (path, length) in files {
+            match analyze_loudness(path) {
+                Ok(loudness) => {
+                    measured.insert(path, (loudness, *length));
+                }
+                Err(e) => eprintln!("Failed to analyze the loudness of {:?}: {}", path, e),
+            }
+            if let Some(pause) = resources::background_pause() {
+                std::thread::sleep(pause);
+            }
+        }
+        let tracks: Vec<(Loudness, u32)> = measured.values().copied().collect();
+        let Some(album) = album_loudness(&tracks) else {
+            report.failed += untagged.len();
+            continue;
+        };
+
+        for path in untagged {
+            let Some((loudness, _)) = measured.get(path) else {
+                report.failed += 1;
+                continue;
+            };
+            match write_replay_gain(path, loudness, &album) {
+                Ok(()) => report.written += 1,
+                Err(e) => {
+                    eprintln!("Failed to write ReplayGain tags to {:?}: {}", path, e);
+                    report.failed += 1;
+                }
+            }
+        }
+    }
+    report
+}
+
+/// Outcome of an artwork embedding job
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArtworkReport {
+    pub written: usize,
+    /// Files that already had art of their own
+    pub already_embedded: usize,
+    /// Formats art can't be embedded in and files that can't be written
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Art ready to be put into files
+struct EmbeddableArt {
+    mime_type: &'static str,
+    data: Vec<u8>,
+}
+
+/// Whether art can be embedded into files like `path`
+pub fn supports_embedded_art(path: &Path) -> bool {
+    supports_replay_gain(path)
+}
+
+/// Whether the file at `path` already has embedded art
+pub fn has_embedded_art(path: &Path) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    match extension(path).as_deref() {
+        Some("mp3") => Ok(read_id3(path)?.pictures().next().is_some()),
+        Some("flac") => Ok(metaflac::Tag::read_from_path(path)?
+            .pictures()
+            .next()
+            .is_some()),
+        Some("m4a" | "mp4" | "m4b") => Ok(mp4ameta::Tag::read_from_path(path)?.artwork().is_some()),
+        _ => Ok(false),
+    }
+}
+
+/// The image at `art_path` as it's embedded: as it is when it's a JPEG or
+/// PNG within the limits, else scaled to fit `max_size` pixels a side and
+/// made a JPEG
+fn prepare_art(
+    art_path: &Path,
+    max_size: u32,
+) -> Result<EmbeddableArt, Box<dyn Error + Send + Sync>> {
+    let data = std::fs::read(art_path)?;
+    let format = image::guess_format(&data)?;
+    let image = image::load_from_memory_with_format(&data, format)?;
+
+    let fits = image.width() <= max_size && image.height() <= max_size;
+    if fits && data.len() <= MAX_EMBEDDED_ART_BYTES {
+        match format {
+            ImageFormat::Jpeg => {
+                return Ok(EmbeddableArt {
+                    mime_type: "image/jpeg",
+                    data,
+                })
+            }
+            ImageFormat::Png => {
+                return Ok(EmbeddableArt {
+                    mime_type: "image/png",
+                    data,
+                })
+            }
+            _ => {}
+        }
+    }
+
+    let image = if fits {
+        image
+    } else {
+        image.resize(max_size, max_size, FilterType::Lanczos3)
+    };
+    let mut data = Vec::new();
+    DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(JpegEncoder::new_with_quality(
+        &mut data,
+        EMBEDDED_ART_QUALITY,
+    ))?;
+    Ok(EmbeddableArt {
+        mime_type: "image/jpeg",
+        data,
+    })
+}
+
+/// Write `art` into the file at `path` as its front cover
+fn embed_art(path: &Path, art: &EmbeddableArt) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let _guard = WRITE_LOCK.lock();
+    match extension(path).as_deref() {
+        Some("mp3") => {
+            let mut tag = read_id3(path)?;
+            tag.add_frame(Picture {
+                mime_type: art.mime_type.to_string(),
+                picture_type: PictureType::CoverFront,
+                description: String::new(),
+                data: art.data.clone(),
+            });
+            tag.write_to_path(path, Version::Id3v24)?;
+        }
+        Some("flac") => {
+            let mut tag = metaflac::Tag::read_from_path(path)?;
+            tag.add_picture(
+                art.mime_type,
+                metaflac::block::PictureType::CoverFront,
+                art.data.clone(),
+            );
+            tag.save()?;
+        }
+        Some("m4a" | "mp4" | "m4b") => {
+            let mut tag = mp4ameta::Tag::read_from_path(path)?;
+            let image = match art.mime_type {
+                "image/png" => Img::png(art.data.clone()),
+                _ => Img::jpeg(art.data.clone()),
+            };
+            tag.set_artwork(image);
+            tag.write_to_path(path)?;
+        }
+        _ => return Err(format!("Can't embed art in {:?}", path).into()),
+    }
+    Ok(())
+}
+
+/// Embed art into each file in `files` that has none, where `files` pairs
+/// each file with the album art it's shown with. Each piece of art is
+/// prepared once for all the files sharing it. Stops early when `task` is
+/// cancelled, leaving the rest of the files as they are.
+pub fn embed_artwork_batch(
+    files: &[(PathBuf, PathBuf)],
+    max_size: u32,
+    policy: WriteBackPolicy,
+    task: &Task,
+) -> ArtworkReport {
+    let mut report = ArtworkReport::default();
+    let mut by_art: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+    for (path, art_path) in files {
+        if !supports_embedded_art(path) || !can_write(path, policy) {
+            report.skipped += 1;
+            continue;
+        }
+        match has_embedded_art(path) {
+            Ok(true) => report.already_embedded += 1,
+            Ok(false) => by_art.entry(art_path).or_default().push(path),
+            Err(e) => {
+                eprintln!("Failed to read tags of {:?}: {}", path, e);
+                report.failed += 1;
+            }
+        }
+    }
+
+    let total = by_art.values().map(Vec::len).sum();
+    let mut done = 0;
+    task.set_progress(done, total);
+    for (art_path, paths) in by_art {
+        if task.is_cancelled() {
+            break;
+        }
+        done += paths.len();
+        let art = match prepare_art(art_path, max_size) {
+            Ok(art) => art,
+            Err(e) => {
+                eprintln!("Failed to prepare art {:?} for embedding: {}", art_path, e);
+                report.failed += paths.len();
+                continue;
+            }
+        };
+        for path in paths {
+            match embed_art(path, &art) {
+                Ok(()) => report.written += 1,
+                Err(e) => {
+                    eprintln!("Failed to embed art in {:?}: {}", path, e);
+                    report.failed += 1;
+                }
+            }
+        }
+        task.set_progress(done, total);
+
+        if let Some(pause) = resources::background_pause() {
+            std::thread::sleep(pause);
+        }
+    }
+    report
+}
@@ -0,0 +1,151 @@
+use crate::services::models::{Bookmark, Track};
+use crate::window::imp;
+use crate::window::utils::format::format_duration;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use std::time::Duration;
+
+/// Fill `list` with a row per bookmark in `track`, which jumps there, and a
+/// button beside it to remove it. `done` runs after either, to close the
+/// menu holding them.
+pub(crate) fn fill_bookmark_list<F>(this: &imp::NovaWindow, list: &gtk::Box, track: &Track, done: F)
+where
+    F: Fn() + Clone + 'static,
+{
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+    let Some(player) = this.player.borrow().clone() else {
+        return;
+    };
+    for bookmark in player.bookmarks(&track.id) {
+        let time = format_duration((bookmark.position_ms / 1000) as u32);
+        let jump_button = gtk::Button::builder()
+            .label(format!("{}  {}", time, bookmark.name))
+            .hexpand(true)
+            .css_classes(["flat"])
+            .build();
+        if let Some(label) = jump_button.child().and_downcast::<gtk::Label>() {
+            label.set_xalign(0.0);
+            label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            label.set_max_width_chars(30);
+        }
+        let remove_button = gtk::Button::builder()
+            .icon_name("edit-delete-symbolic")
+            .tooltip_text("Remove Bookmark")
+            .css_classes(["flat"])
+            .build();
+
+        let obj_weak = this.obj().downgrade();
+        let (track_clone, position_ms, done_clone) =
+            (track.clone(), bookmark.position_ms, done.clone());
+        jump_button.connect_clicked(move |_| {
+            done_clone();
+            if let Some(obj) = obj_weak.upgrade() {
+                jump_to(obj.imp(), &track_clone, position_ms);
+            }
+        });
+        let obj_weak = this.obj().downgrade();
+        let (track_clone, done_clone) = (track.clone(), done.clone());
+        remove_button.connect_clicked(move |_| {
+            done_clone();
+            if let Some(obj) = obj_weak.upgrade() {
+                remove_bookmark(obj.imp(), &track_clone, position_ms);
+            }
+        });
+
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        row.append(&jump_button);
+        row.append(&remove_button);
+        list.append(&row);
+    }
+}
+
+fn jump_to(this: &imp::NovaWindow, track: &Track, position_ms: u64) {
+    let Some(player) = this.player.borrow().clone() else {
+        return;
+    };
+    if let Err(e) = player.play_from(track, Duration::from_millis(position_ms)) {
+        eprintln!("Failed to jump to a bookmark in {}: {}", track.title, e);
+        this.show_toast(&format!("Couldn't play “{}”", track.title));
+    }
+}
+
+/// Ask for a name for a bookmark where `track` is now, and add it. Only
+/// the track playing has a place to bookmark.
+pub(crate) fn prompt_bookmark(this: &imp::NovaWindow, track: &Track) {
+    let Some(player) = this.player.borrow().clone() else {
+        return;
+    };
+    let Some(position) = player.position() else {
+        return;
+    };
+    let time = format_duration(position.as_secs() as u32);
+
+    let name_row = adw::EntryRow::builder()
+        .title("Name")
+        .text(format!("Left off at {}", time))
+        .activates_default(true)
+        .build();
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&name_row);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Add Bookmark")
+        .body(format!("At {} in “{}”", time, track.title))
+        .extra_child(&list)
+        .default_response("add")
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("add", "_Add")]);
+    dialog.set_response_appearance("add", adw::ResponseAppearance::Suggested);
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    let track = track.clone();
+    dialog.connect_response(Some("add"), move |_, _| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let name = name_row.text().trim().to_string();
+        let name = if name.is_empty() {
+            format!("Left off at {}", time)
+        } else {
+            name
+        };
+        let Some(player) = obj.imp().player.borrow().clone() else {
+            return;
+        };
+        let position_ms = position.as_millis() as u64;
+        // One bookmark to a spot, the newer name replacing the older
+        let mut bookmarks = player.bookmarks(&track.id);
+        bookmarks.retain(|bookmark| bookmark.position_ms != position_ms);
+        bookmarks.push(Bookmark { position_ms, name });
+        save_bookmarks(obj.imp(), &track, bookmarks);
+    });
+    dialog.present(Some(&*this.obj()));
+}
+
+fn remove_bookmark(this: &imp::NovaWindow, track: &Track, position_ms: u64) {
+    let Some(player) = this.player.borrow().clone() else {
+        return;
+    };
+    let mut bookmarks = player.bookmarks(&track.id);
+    bookmarks.retain(|bookmark| bookmark.position_ms != position_ms);
+    save_bookmarks(this, track, bookmarks);
+}
+
+fn save_bookmarks(this: &imp::NovaWindow, track: &Track, bookmarks: Vec<Bookmark>) {
+    if let Some(store) = this.user_store.borrow().as_ref() {
+        if let Err(e) = store.set_track_bookmarks(&track.id, &bookmarks) {
+            eprintln!("Failed to save bookmarks of {}: {}", track.title, e);
+            this.show_toast("Couldn't save the bookmark");
+            return;
+        }
+    }
+    if let Some(player) = this.player.borrow().as_ref() {
+        player.set_bookmarks(&track.id, bookmarks);
+    }
+}
@@ -1,69 +1,23 @@
-use crate::services::models::{Artwork, ArtworkSource, PlayableItem, Track};
+use crate::services::aggregation::{differs_in_quality, is_lossless};
+use crate::services::models::{Availability, ItemSource, PlayableItem, PlaybackSource, Track};
 use crate::services::{Album, Artist};
+use crate::window::components::details::{
+    album_excluded_from_shuffle, attach_card_menu, attach_go_to_menu, play_album_after_current,
+    shuffle_album, shuffle_artist, toggle_album_shuffle_exclusion, CardAction,
+};
+use crate::window::components::playing_marks::create_playing_mark;
+use crate::window::components::reveal::show_album_in_files;
+use crate::window::utils::tooltip::{
+    album_tooltip, artist_tooltip, attach_tooltip, track_tooltip,
+};
 use crate::window::utils::ui::create_artwork_image;
 use adw::prelude::*;
 use adw::subclass::prelude::*;
-use gdk_pixbuf::Pixbuf;
 use gtk::prelude::*;
-use gtk::{gio, glib, pango};
+use gtk::pango;
 use chrono::Utc;
 
 pub fn create_track_card(track: &Track, is_large: bool, window: &impl IsA<gtk::Window>) -> gtk::Box {
-    // Helper function to create a placeholder image with the right size
-    fn create_placeholder_image(size: i32) -> gtk::Image {
-        let image = gtk::Image::from_icon_name("audio-x-generic-symbolic");
-        image.set_pixel_size(size);
-        image.add_css_class("album-art");
-        image
-    }
-
-    // Helper function to create artwork image
-    fn create_artwork_image(artwork: &Artwork, size: i32) -> gtk::Image {
-        match artwork {
-            Artwork {
-                thumbnail: Some(data),
-                ..
-            } => {
-                let bytes = glib::Bytes::from(data);
-                let stream = gio::MemoryInputStream::from_bytes(&bytes);
-                if let Ok(pixbuf) = Pixbuf::from_stream(&stream, None::<&gio::Cancellable>) {
-                    if let Some(scaled) =
-                        pixbuf.scale_simple(size, size, gdk_pixbuf::InterpType::Bilinear)
-                    {
-                        let paintable = gtk::gdk::Texture::for_pixbuf(&scaled);
-                        let image = gtk::Image::from_paintable(Some(&paintable));
-                        image.add_css_class("album-art");
-                        image
-                    } else {
-                        create_placeholder_image(size)
-                    }
-                } else {
-                    create_placeholder_image(size)
-                }
-            }
-            Artwork {
-                thumbnail: None,
-                full_art: ArtworkSource::Local { path },
-            } => {
-                if let Ok(pixbuf) = Pixbuf::from_file(path) {
-                    if let Some(scaled) =
-                        pixbuf.scale_simple(size, size, gdk_pixbuf::InterpType::Bilinear)
-                    {
-                        let paintable = gtk::gdk::Texture::for_pixbuf(&scaled);
-                        let image = gtk::Image::from_paintable(Some(&paintable));
-                        image.add_css_class("album-art");
-                        image
-                    } else {
-                        create_placeholder_image(size)
-                    }
-                } else {
-                    create_placeholder_image(size)
-                }
-            }
-            _ => create_placeholder_image(size),
-        }
-    }
-
     if is_large {
         let container = gtk::Box::new(gtk::Orientation::Vertical, 12);
         container.set_hexpand(true);
@@ -108,6 +62,14 @@ pub fn create_track_card(track: &Track, is_large: bool, window: &impl IsA<gtk::W
 
         labels.append(&title);
         labels.append(&type_label);
+        let badges = create_track_badges(track);
+        if badges.first_child().is_some() {
+            badges.set_halign(gtk::Align::Center);
+            labels.append(&badges);
+        }
+        if track.availability == Availability::Unavailable {
+            content.add_css_class("unavailable");
+        }
 
         content.append(&art);
         content.append(&labels);
@@ -129,6 +91,8 @@ pub fn create_track_card(track: &Track, is_large: bool, window: &impl IsA<gtk::W
             }
         });
         content.add_controller(click_controller);
+        attach_track_menu(&content, track, window);
+        attach_track_tooltip(&content, track, window);
 
         container.append(&content);
         container
@@ -150,11 +114,28 @@ pub fn create_track_card(track: &Track, is_large: bool, window: &impl IsA<gtk::W
         artist.add_css_class("track-artist");
         artist.set_halign(gtk::Align::Start);
 
-        labels.append(&title);
+        let badges = create_track_badges(track);
+        if badges.first_child().is_some() {
+            let title_row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            title_row.append(&title);
+            title_row.append(&badges);
+            labels.append(&title_row);
+        } else {
+            labels.append(&title);
+        }
         labels.append(&artist);
+        if track.availability == Availability::Unavailable {
+            card.add_css_class("unavailable");
+        }
 
         card.append(&art);
         card.append(&labels);
+        if let Some(window) = window.dynamic_cast_ref::<super::super::NovaWindow>() {
+            let mark = create_playing_mark(window.imp(), &card, &track.id);
+            mark.set_hexpand(true);
+            mark.set_halign(gtk::Align::End);
+            card.append(&mark);
+        }
 
         // Add click handling
         let track_info = track.clone();
@@ -173,11 +154,182 @@ pub fn create_track_card(track: &Track, is_large: bool, window: &impl IsA<gtk::W
             }
         });
         card.add_controller(click_controller);
+        attach_track_menu(&card, track, window);
+        attach_track_tooltip(&card, track, window);
 
         card
     }
 }
 
+/// Explicit and availability markers for a track, empty if it has none
+fn create_track_badges(track: &Track) -> gtk::Box {
+    let badges = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    badges.set_valign(gtk::Align::Center);
+    if track.explicit {
+        badges.append(&create_explicit_badge());
+    }
+    if let Some(badge) = create_availability_badge(track.availability) {
+        badges.append(&badge);
+    }
+    badges
+}
+
+/// Small "E" marker shown next to explicit tracks
+fn create_explicit_badge() -> gtk::Label {
+    let badge = gtk::Label::new(Some("E"));
+    badge.set_tooltip_text(Some("Explicit"));
+    badge.set_valign(gtk::Align::Center);
+    badge.add_css_class("explicit-badge");
+    badge
+}
+
+/// Icon saying whether a track plays without a connection. Files on this
+/// device are the usual case and go unmarked.
+fn create_availability_badge(availability: Availability) -> Option<gtk::Image> {
+    let (icon, tooltip) = match availability {
+        Availability::Local => return None,
+        Availability::Cached => ("folder-download-symbolic", "Available offline"),
+        Availability::StreamOnly => ("network-wireless-symbolic", "Needs a connection to play"),
+        Availability::Unavailable => ("action-unavailable-symbolic", "Unavailable"),
+    };
+    let badge = gtk::Image::from_icon_name(icon);
+    badge.set_tooltip_text(Some(tooltip));
+    badge.set_valign(gtk::Align::Center);
+    badge.add_css_class("availability-badge");
+    Some(badge)
+}
+
+/// Markers for a search result found in several places: whether a lossless
+/// copy is what plays, and whether it's in the library as well as online.
+/// `others` are the copies left out for `item`. None if there's nothing to
+/// point out.
+pub(crate) fn create_version_badges(
+    item: &PlayableItem,
+    others: &[PlayableItem],
+) -> Option<gtk::Box> {
+    if others.is_empty() {
+        return None;
+    }
+    let badges = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    badges.set_valign(gtk::Align::Center);
+
+    if is_lossless(&item.track) && differs_in_quality(item, others) {
+        badges.append(&create_version_badge("Lossless available", "lossless"));
+    }
+
+    let copies: Vec<&PlayableItem> = std::iter::once(item).chain(others).collect();
+    let in_library = copies.iter().any(|copy| !copy.track.source.is_remote());
+    let online = copies.iter().any(|copy| copy.track.source.is_remote());
+    let mut providers: Vec<&str> = Vec::new();
+    for copy in &copies {
+        if !providers.contains(&copy.provider.as_str()) {
+            providers.push(&copy.provider);
+        }
+    }
+    if in_library && online {
+        badges.append(&create_version_badge("In library", "library"));
+    } else if providers.len() > 1 {
+        let label = format!("{} sources", providers.len());
+        badges.append(&create_version_badge(&label, "sources"));
+    }
+
+    if badges.first_child().is_none() {
+        return None;
+    }
+    let tooltip: Vec<String> = copies.iter().map(|copy| describe_copy(copy)).collect();
+    badges.set_tooltip_text(Some(&format!("Plays from\n{}", tooltip.join("\n"))));
+    Some(badges)
+}
+
+fn create_version_badge(label: &str, kind: &str) -> gtk::Label {
+    let badge = gtk::Label::new(Some(label));
+    badge.set_valign(gtk::Align::Center);
+    badge.add_css_class("version-badge");
+    badge.add_css_class(kind);
+    badge
+}
+
+/// Where a copy of a track comes from and its format, such as "Local · FLAC"
+fn describe_copy(item: &PlayableItem) -> String {
+    let format = match &item.track.source {
+        PlaybackSource::Local { file_format, .. } => file_format.to_uppercase(),
+        PlaybackSource::Spotify { .. } => "Spotify".to_string(),
+        PlaybackSource::YouTube { .. } => "YouTube".to_string(),
+        PlaybackSource::Stream { .. } => "Stream".to_string(),
+    };
+    format!("{} · {}", provider_label(&item.provider), format)
+}
+
+fn attach_track_menu(card: &gtk::Box, track: &Track, window: &impl IsA<gtk::Window>) {
+    if let Some(window) = window.dynamic_cast_ref::<super::super::NovaWindow>() {
+        let track = track.clone();
+        attach_go_to_menu(card, window, move || Some((track.clone(), None)));
+    }
+}
+
+/// Hover tooltip with the track's metadata and how often it has been played
+fn attach_track_tooltip(card: &gtk::Box, track: &Track, window: &impl IsA<gtk::Window>) {
+    let window_weak = window.upcast_ref::<gtk::Window>().downgrade();
+    let track = track.clone();
+    attach_tooltip(card, move || {
+        let play_count = window_weak.upgrade().and_then(|window| {
+            let window = window.downcast::<super::super::NovaWindow>().ok()?;
+            let store = window.imp().user_store.borrow().clone()?;
+            store.play_count(&track.id).ok()
+        });
+        track_tooltip(&track, play_count)
+    });
+}
+
+/// "Shuffle Artist" on right-click, played from the artist's first source
+fn attach_artist_shuffle(card: &gtk::Box, artist: &Artist) {
+    if let Some(source) = artist.sources.first().cloned() {
+        attach_card_menu(
+            card,
+            vec![CardAction::new("Shuffle Artist", move |window| {
+                shuffle_artist(window, &source.provider, &source.id)
+            })],
+        );
+    }
+}
+
+/// "Shuffle Album" and "Play After Current Album" on right-click, played
+/// from the album's first source, a toggle to keep the album out of
+/// shuffle, and "Show in Files" for albums in the library
+fn attach_album_menu(card: &gtk::Box, album: &Album) {
+    let mut actions = Vec::new();
+    if let Some(source) = album.sources.first().cloned() {
+        let queue_source = source.clone();
+        actions.push(CardAction::new("Shuffle Album", move |window| {
+            shuffle_album(window, &source.provider, &source.id)
+        }));
+        actions.push(CardAction::new("Play After Current Album", move |window| {
+            play_album_after_current(window, &queue_source.provider, &queue_source.id)
+        }));
+    }
+
+    let label_album = album.clone();
+    let toggle_album = album.clone();
+    actions.push(CardAction::dynamic(
+        move |window| {
+            if album_excluded_from_shuffle(window, &label_album) {
+                "Allow in Shuffle"
+            } else {
+                "Never Play in Shuffle"
+            }
+        },
+        move |window| toggle_album_shuffle_exclusion(window, &toggle_album),
+    ));
+
+    let local = album.sources.iter().find(|source| source.provider == "local");
+    if let Some(local) = local.cloned() {
+        actions.push(CardAction::new("Show in Files", move |window| {
+            show_album_in_files(window, &local.provider, &local.id)
+        }));
+    }
+    attach_card_menu(card, actions);
+}
+
 pub(crate) fn create_artist_card(
     artist: &Artist, // Change to take Artist struct directly
     is_large: bool,
@@ -226,6 +378,9 @@ pub(crate) fn create_artist_card(
 
         labels.append(&name_label);
         labels.append(&type_label);
+        if let Some(sources) = create_sources_expander(&artist.sources) {
+            labels.append(&sources);
+        }
 
         content.append(&art);
         content.append(&labels);
@@ -237,6 +392,9 @@ pub(crate) fn create_artist_card(
             println!("Clicked on artist: '{}'", artist_name);
         });
         content.add_controller(click_controller);
+        let artist_info = artist.clone();
+        attach_tooltip(&content, move || artist_tooltip(&artist_info));
+        attach_artist_shuffle(&content, artist);
 
         container.append(&content);
         container
@@ -261,6 +419,9 @@ pub(crate) fn create_artist_card(
 
         card.append(&art);
         card.append(&name_label);
+        if let Some(sources) = create_sources_expander(&artist.sources) {
+            card.append(&sources);
+        }
 
         let artist_name = artist.name.clone();
         let click_controller = gtk::GestureClick::new();
@@ -268,6 +429,9 @@ pub(crate) fn create_artist_card(
             println!("Clicked on artist: '{}'", artist_name);
         });
         card.add_controller(click_controller);
+        let artist_info = artist.clone();
+        attach_tooltip(&card, move || artist_tooltip(&artist_info));
+        attach_artist_shuffle(&card, artist);
 
         card
     }
@@ -317,6 +481,9 @@ pub(crate) fn create_album_card(album: &Album, is_large: bool) -> gtk::Box {
 
         labels.append(&title_label);
         labels.append(&type_label);
+        if let Some(sources) = create_sources_expander(&album.sources) {
+            labels.append(&sources);
+        }
 
         content.append(&art);
         content.append(&labels);
@@ -328,6 +495,9 @@ pub(crate) fn create_album_card(album: &Album, is_large: bool) -> gtk::Box {
             println!("Clicked on album: '{}' by '{}'", album_info.0, album_info.1);
         });
         content.add_controller(click_controller);
+        let album_info = album.clone();
+        attach_tooltip(&content, move || album_tooltip(&album_info));
+        attach_album_menu(&content, album);
 
         container.append(&content);
         container
@@ -371,6 +541,9 @@ pub(crate) fn create_album_card(album: &Album, is_large: bool) -> gtk::Box {
 
         labels.append(&title_label);
         labels.append(&artist_label);
+        if let Some(sources) = create_sources_expander(&album.sources) {
+            labels.append(&sources);
+        }
 
         card.append(&art);
         card.append(&labels);
@@ -381,11 +554,53 @@ pub(crate) fn create_album_card(album: &Album, is_large: bool) -> gtk::Box {
             println!("Clicked on album: '{}' by '{}'", album_info.0, album_info.1);
         });
         card.add_controller(click_controller);
+        let album_info = album.clone();
+        attach_tooltip(&card, move || album_tooltip(&album_info));
+        attach_album_menu(&card, album);
 
         card
     }
 }
 
+/// Collapsed list of the providers an album or artist is available from.
+/// Items with a single source don't get one.
+fn create_sources_expander(sources: &[ItemSource]) -> Option<gtk::Expander> {
+    if sources.len() < 2 {
+        return None;
+    }
+
+    let list = gtk::Box::new(gtk::Orientation::Vertical, 2);
+    for source in sources {
+        let label = gtk::Label::new(Some(&provider_label(&source.provider)));
+        label.set_halign(gtk::Align::Start);
+        label.add_css_class("caption");
+        label.add_css_class("dim-label");
+        list.append(&label);
+    }
+
+    let expander = gtk::Expander::new(Some(&format!("{} sources", sources.len())));
+    expander.set_halign(gtk::Align::Center);
+    expander.add_css_class("sources-expander");
+    expander.set_child(Some(&list));
+    Some(expander)
+}
+
+/// Name of a provider as shown to the user
+pub(crate) fn provider_label(provider: &str) -> String {
+    match provider {
+        "local" => "Local".to_string(),
+        "webdav" => "WebDAV".to_string(),
+        // Plugin IDs, such as "jellyfin"
+        other => {
+            let mut chars = other.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+    }
+}
+
 pub(crate) fn create_type_label(result_type: &str, artist: Option<&str>) -> gtk::Label {
     let label_text = match (result_type, artist) {
         ("Artist", _) => "Artist".to_string(),
@@ -0,0 +1,94 @@
+//! Browsing albums by the decade they came out in. Home lists the decades
+//! the library has, and each opens the Albums page narrowed to it until
+//! Show All puts every album back.
+
+use crate::window::components::cards::create_album_card;
+use crate::window::components::navigation::show_page;
+use crate::window::components::search::create_loading_indicator;
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+
+/// "50s" for the decades of the last century, "2010s" for the rest
+fn decade_label(decade: u32) -> String {
+    if (1920..2000).contains(&decade) {
+        format!("{}s", decade % 100)
+    } else {
+        format!("{}s", decade)
+    }
+}
+
+/// A button for each of `decades`, opening its albums
+pub(crate) fn create_decade_shelf(decades: &[u32]) -> gtk::FlowBox {
+    let shelf = gtk::FlowBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .max_children_per_line(12)
+        .row_spacing(6)
+        .column_spacing(6)
+        .valign(gtk::Align::Start)
+        .build();
+    for decade in decades {
+        let button = gtk::Button::builder()
+            .label(decade_label(*decade))
+            .action_name("win.show-decade")
+            .action_target(&decade.to_variant())
+            .css_classes(["pill"])
+            .build();
+        shelf.append(&button);
+    }
+    shelf
+}
+
+/// Put every album back when the decade banner's Show All is clicked
+pub(crate) fn setup_decade_banner(this: &imp::NovaWindow) {
+    let obj_weak = this.obj().downgrade();
+    this.albums_decade_banner.connect_button_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            obj.imp().load_albums();
+        }
+    });
+}
+
+/// Show the albums released in `decade` on the Albums page
+pub(crate) fn show_decade(this: &imp::NovaWindow, decade: u32) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+    show_page(this, "albums");
+
+    let grid = this.albums_grid.clone();
+    while let Some(child) = grid.first_child() {
+        grid.remove(&child);
+    }
+    grid.append(&create_loading_indicator());
+    this.albums_stack.set_visible_child_name("content");
+    this.albums_decade_banner
+        .set_title(&format!("Albums from the {}", decade_label(decade)));
+    this.albums_decade_banner.set_revealed(true);
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let albums = manager
+            .albums_from_decade(decade)
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load albums from the {}s: {}", decade, e);
+                Vec::new()
+            });
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        while let Some(child) = grid.first_child() {
+            grid.remove(&child);
+        }
+        for album in &albums {
+            let child = gtk::FlowBoxChild::new();
+            child.set_child(Some(&create_album_card(album, false)));
+            grid.append(&child);
+        }
+        if albums.is_empty() {
+            obj.imp().show_toast("No albums from then are left");
+        }
+    });
+}
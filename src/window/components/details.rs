@@ -0,0 +1,1075 @@
+use crate::services::models::{
+    Album, Artist, ItemSource, PinnedItem, PinnedKind, PlayStats, PlayableItem, PlaybackSource,
+    QueueContext, ReleaseType, ShuffleExclusions, Track,
+};
+use crate::services::album_gaps::{describe_missing, fill_gaps, missing_tracks, MissingTrack};
+use crate::services::artist_info::fetch_artist_info;
+use crate::services::{ArtistInfo, ServiceError, UserStore};
+use crate::window::components::bookmarks::{fill_bookmark_list, prompt_bookmark};
+use crate::window::components::cards::{create_album_card, create_track_card};
+use crate::window::components::loudness::show_loudness;
+use crate::window::components::playlist_picker::show_playlist_picker;
+use crate::window::components::reveal::show_track_in_files;
+use crate::window::components::sidebar::is_pinned;
+use crate::window::components::trash::confirm_trash;
+use crate::window::components::trim_editor::show_trim_editor;
+use crate::window::imp;
+use crate::window::utils::format::{format_count_of, format_length, format_time_ago};
+use crate::window::utils::ui::{create_artwork_image, create_placeholder_image};
+use adw::subclass::prelude::*;
+use gtk::glib;
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+// Sections of the artist page in order, with the release types in each
+const ARTIST_SECTIONS: &[(&str, &[ReleaseType])] = &[
+    ("Albums", &[ReleaseType::Album]),
+    ("EPs & Singles", &[ReleaseType::Ep, ReleaseType::Single]),
+    ("Live", &[ReleaseType::Live]),
+    ("Compilations", &[ReleaseType::Compilation]),
+];
+
+// Search results looked through for an album's missing tracks
+const MISSING_SEARCH_LIMIT: usize = 100;
+
+/// Open the album page for the album `track` belongs to. `provider` is
+/// asked first when the track's origin is known.
+pub(crate) fn show_album_for_track(
+    this: &imp::NovaWindow,
+    track: &Track,
+    provider: Option<String>,
+) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    let track = track.clone();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager.album_for_track(&track, provider.as_deref()).await;
+        if let Some(obj) = obj_weak.upgrade() {
+            open_album_page(obj.imp(), result, &track.album);
+        }
+    });
+}
+
+/// Open the artist page for the artist `track` belongs to
+pub(crate) fn show_artist_for_track(
+    this: &imp::NovaWindow,
+    track: &Track,
+    provider: Option<String>,
+) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    let track = track.clone();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager.artist_for_track(&track, provider.as_deref()).await;
+        if let Some(obj) = obj_weak.upgrade() {
+            open_artist_page(obj.imp(), result, &track.artist);
+        }
+    });
+}
+
+/// Open the album page for a known album
+pub(crate) fn show_album(this: &imp::NovaWindow, provider: &str, album_id: &str) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    let provider = provider.to_string();
+    let album_id = album_id.to_string();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager.album_details(&provider, &album_id).await;
+        if let Some(obj) = obj_weak.upgrade() {
+            open_album_page(obj.imp(), result, &album_id);
+        }
+    });
+}
+
+/// Open the artist page for a known artist
+pub(crate) fn show_artist(this: &imp::NovaWindow, provider: &str, artist_id: &str) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    let provider = provider.to_string();
+    let artist_id = artist_id.to_string();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager.artist_details(&provider, &artist_id).await;
+        if let Some(obj) = obj_weak.upgrade() {
+            open_artist_page(obj.imp(), result, &artist_id);
+        }
+    });
+}
+
+/// Play an album's tracks shuffled
+pub(crate) fn shuffle_album(this: &imp::NovaWindow, provider: &str, album_id: &str) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    let provider = provider.to_string();
+    let album_id = album_id.to_string();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager.album_details(&provider, &album_id).await;
+        if let Some(obj) = obj_weak.upgrade() {
+            match result {
+                Ok((album, tracks)) => obj
+                    .imp()
+                    .play_shuffled(tracks, QueueContext::Album(album.title)),
+                Err(e) => {
+                    eprintln!("Failed to shuffle album {}: {}", album_id, e);
+                    obj.imp().show_toast("Couldn't load the album to shuffle");
+                }
+            }
+        }
+    });
+}
+
+/// Play every track by an artist shuffled
+pub(crate) fn shuffle_artist(this: &imp::NovaWindow, provider: &str, artist_id: &str) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    let provider = provider.to_string();
+    let artist_id = artist_id.to_string();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager.artist_tracks(&provider, &artist_id).await;
+        if let Some(obj) = obj_weak.upgrade() {
+            match result {
+                Ok(tracks) => {
+                    let name = tracks
+                        .first()
+                        .map(|item| item.track.artist.clone())
+                        .unwrap_or_default();
+                    obj.imp().play_shuffled(tracks, QueueContext::Artist(name));
+                }
+                Err(e) => {
+                    eprintln!("Failed to shuffle artist {}: {}", artist_id, e);
+                    obj.imp().show_toast("Couldn't load the artist to shuffle");
+                }
+            }
+        }
+    });
+}
+
+/// Queue a whole album to play once the album playing now has finished
+pub(crate) fn play_album_after_current(this: &imp::NovaWindow, provider: &str, album_id: &str) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    let provider = provider.to_string();
+    let album_id = album_id.to_string();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager.album_details(&provider, &album_id).await;
+        if let Some(obj) = obj_weak.upgrade() {
+            match result {
+                Ok((album, tracks)) => {
+                    obj.imp()
+                        .play_after_current_unit(tracks, QueueContext::Album(album.title.clone()));
+                    obj.imp()
+                        .show_toast(&format!("{} will play after this album", album.title));
+                }
+                Err(e) => {
+                    eprintln!("Failed to queue album {}: {}", album_id, e);
+                    obj.imp().show_toast("Couldn't load the album to queue");
+                }
+            }
+        }
+    });
+}
+
+/// Whether `album` is kept out of shuffle and mixes
+pub(crate) fn album_excluded_from_shuffle(this: &imp::NovaWindow, album: &Album) -> bool {
+    let key = ShuffleExclusions::album_key(album);
+    shuffle_exclusions(this).is_some_and(|exclusions| exclusions.albums.contains(&key))
+}
+
+/// Whether `track` itself is kept out of shuffle and mixes. Its album can
+/// be left out separately.
+pub(crate) fn track_excluded_from_shuffle(this: &imp::NovaWindow, track: &Track) -> bool {
+    shuffle_exclusions(this).is_some_and(|exclusions| exclusions.tracks.contains(&track.id))
+}
+
+fn shuffle_exclusions(this: &imp::NovaWindow) -> Option<ShuffleExclusions> {
+    let store = this.user_store.borrow().clone()?;
+    store
+        .shuffle_exclusions()
+        .map_err(|e| eprintln!("Failed to load shuffle exclusions: {}", e))
+        .ok()
+}
+
+/// Keep `album` out of shuffle and mixes, or let it back in. Its tracks are
+/// loaded first, as they're excluded by their own artists.
+pub(crate) fn toggle_album_shuffle_exclusion(this: &imp::NovaWindow, album: &Album) {
+    let (Some(store), Some(manager)) = (
+        this.user_store.borrow().clone(),
+        this.service_manager.borrow().clone(),
+    ) else {
+        return;
+    };
+    let Some(source) = album.sources.first().cloned() else {
+        return;
+    };
+    let excluded = !album_excluded_from_shuffle(this, album);
+    let album = album.clone();
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let tracks: Vec<Track> = match manager.album_details(&source.provider, &source.id).await {
+            Ok((_, items)) => items.into_iter().map(|item| item.track).collect(),
+            Err(e) => {
+                eprintln!("Failed to load the tracks of {}: {}", album.title, e);
+                Vec::new()
+            }
+        };
+        let result = store.set_album_excluded(&album, &tracks, excluded);
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        match result {
+            Ok(()) if excluded => {
+                this.show_toast(&format!("{} won't play in shuffle", album.title))
+            }
+            Ok(()) => this.show_toast(&format!("{} can play in shuffle again", album.title)),
+            Err(e) => {
+                eprintln!(
+                    "Failed to update shuffle exclusion for {}: {}",
+                    album.title, e
+                );
+                this.show_toast("Couldn't change whether the album plays in shuffle");
+            }
+        }
+    });
+}
+
+/// Keep `track` out of shuffle and mixes, or let it back in
+pub(crate) fn toggle_track_shuffle_exclusion(this: &imp::NovaWindow, track: &Track) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+    let excluded = !track_excluded_from_shuffle(this, track);
+    match store.set_track_excluded(track, excluded) {
+        Ok(()) if excluded => this.show_toast(&format!("{} won't play in shuffle", track.title)),
+        Ok(()) => this.show_toast(&format!("{} can play in shuffle again", track.title)),
+        Err(e) => {
+            eprintln!(
+                "Failed to update shuffle exclusion for {}: {}",
+                track.title, e
+            );
+            this.show_toast("Couldn't change whether the track plays in shuffle");
+        }
+    }
+}
+
+fn open_album_page(
+    this: &imp::NovaWindow,
+    result: Result<(Album, Vec<PlayableItem>), ServiceError>,
+    name: &str,
+) {
+    match result {
+        Ok((album, tracks)) => {
+            populate_album_page(this, &album, &tracks);
+            this.main_stack.set_visible_child_name("album-detail");
+            this.sidebar_list.unselect_all();
+            this.home_button.remove_css_class("selected");
+        }
+        Err(e) => {
+            eprintln!("Failed to open album {}: {}", name, e);
+            this.show_toast(&format!("Couldn't find the album '{}'", name));
+        }
+    }
+}
+
+fn open_artist_page(
+    this: &imp::NovaWindow,
+    result: Result<(Artist, Vec<Album>), ServiceError>,
+    name: &str,
+) {
+    match result {
+        Ok((artist, albums)) => {
+            populate_artist_page(this, &artist, &albums);
+            this.main_stack.set_visible_child_name("artist-detail");
+            this.sidebar_list.unselect_all();
+            this.home_button.remove_css_class("selected");
+        }
+        Err(e) => {
+            eprintln!("Failed to open artist {}: {}", name, e);
+            this.show_toast(&format!("Couldn't find the artist '{}'", name));
+        }
+    }
+}
+
+fn set_art(container: &gtk::Box, art: gtk::Image) {
+    while let Some(child) = container.first_child() {
+        container.remove(&child);
+    }
+    art.add_css_class("large-image");
+    container.append(&art);
+}
+
+/// Show whether the page's item is pinned on its pin button
+pub(crate) fn update_pin_button(button: &gtk::Button, pinned: bool) {
+    if pinned {
+        button.add_css_class("accent");
+        button.set_tooltip_text(Some("Unpin from Sidebar"));
+    } else {
+        button.remove_css_class("accent");
+        button.set_tooltip_text(Some("Pin to Sidebar"));
+    }
+}
+
+/// Pin entry for an album or artist, using its first source
+fn pinned_item(
+    kind: PinnedKind,
+    sources: &[ItemSource],
+    title: &str,
+    subtitle: Option<&str>,
+) -> Option<PinnedItem> {
+    let source = sources.first()?;
+    Some(PinnedItem {
+        kind,
+        provider: source.provider.clone(),
+        item_id: source.id.clone(),
+        title: title.to_string(),
+        subtitle: subtitle.map(str::to_string),
+    })
+}
+
+fn populate_album_page(this: &imp::NovaWindow, album: &Album, tracks: &[PlayableItem]) {
+    let art = match (&album.artwork, tracks.first()) {
+        (Some(artwork), _) => create_artwork_image(artwork, 200),
+        (None, Some(item)) => create_artwork_image(&item.track.artwork, 200),
+        (None, None) => create_placeholder_image(200),
+    };
+    set_art(&this.album_detail_art_box, art);
+
+    this.album_detail_title.set_text(&album.title);
+    this.album_detail_artist.set_label(&album.artist);
+
+    // The track count is part of the stats line
+    let year = album.year.map(|year| year.to_string()).unwrap_or_default();
+    this.album_detail_info.set_visible(!year.is_empty());
+    this.album_detail_info.set_text(&year);
+
+    // Remember a track from the album so the artist button can route from it
+    this.album_detail_track
+        .replace(tracks.first().map(|item| item.track.clone()));
+    this.album_detail_stat_tracks
+        .replace(tracks.iter().map(|item| item.track.clone()).collect());
+    update_detail_stats(this);
+    show_missing_tracks(this, missing_tracks(&this.album_detail_stat_tracks.borrow()));
+
+    let pin = pinned_item(PinnedKind::Album, &album.sources, &album.title, Some(&album.artist));
+    update_pin_button(
+        &this.album_detail_pin,
+        pin.as_ref().is_some_and(|pin| is_pinned(this, pin)),
+    );
+    this.album_detail_pin.set_sensitive(pin.is_some());
+    this.album_detail_shuffle
+        .set_sensitive(pin.is_some() && !tracks.is_empty());
+    this.album_detail_pin_item.replace(pin);
+
+    let tracks_box = &this.album_detail_tracks;
+    while let Some(child) = tracks_box.first_child() {
+        tracks_box.remove(&child);
+    }
+    let window = this.obj();
+    for item in tracks {
+        let card = create_track_card(&item.track, false, &*window);
+        tracks_box.append(&card);
+    }
+}
+
+fn populate_artist_page(this: &imp::NovaWindow, artist: &Artist, albums: &[Album]) {
+    let art = match artist.artwork {
+        Some(ref artwork) => create_artwork_image(artwork, 200),
+        None => {
+            let image = gtk::Image::from_icon_name("avatar-default-symbolic");
+            image.set_pixel_size(200);
+            image
+        }
+    };
+    set_art(&this.artist_detail_art_box, art);
+
+    this.artist_detail_name.set_text(&artist.name);
+    this.artist_detail_info.set_text(&match albums.len() {
+        1 => "1 album".to_string(),
+        n => format!("{} albums", n),
+    });
+
+    let pin = pinned_item(PinnedKind::Artist, &artist.sources, &artist.name, None);
+    update_pin_button(
+        &this.artist_detail_pin,
+        pin.as_ref().is_some_and(|pin| is_pinned(this, pin)),
+    );
+    this.artist_detail_pin.set_sensitive(pin.is_some());
+    this.artist_detail_shuffle
+        .set_sensitive(pin.is_some() && !albums.is_empty());
+    this.artist_detail_pin_item.replace(pin);
+    this.artist_detail_id.replace(Some(artist.id.clone()));
+    load_artist_info(this, artist);
+    load_artist_stats(this, artist);
+
+    for container in [&this.artist_detail_sections, &this.artist_detail_filters] {
+        while let Some(child) = container.first_child() {
+            container.remove(&child);
+        }
+    }
+
+    let mut sections = Vec::new();
+    for (title, release_types) in ARTIST_SECTIONS {
+        let section_albums: Vec<&Album> = albums
+            .iter()
+            .filter(|album| release_types.contains(&album.release_type))
+            .collect();
+        if section_albums.is_empty() {
+            continue;
+        }
+        let section = create_album_section(title, &section_albums);
+        this.artist_detail_sections.append(&section);
+        sections.push((*title, section));
+    }
+
+    // Filters only help once there's more than one kind of release
+    let filters = &this.artist_detail_filters;
+    filters.set_visible(sections.len() > 1);
+    if sections.len() < 2 {
+        return;
+    }
+    let section_boxes: Vec<gtk::Box> =
+        sections.iter().map(|(_, section)| section.clone()).collect();
+    let all_button = gtk::ToggleButton::builder().label("All").active(true).build();
+    let boxes = section_boxes.clone();
+    all_button.connect_toggled(move |button| {
+        if button.is_active() {
+            boxes.iter().for_each(|section| section.set_visible(true));
+        }
+    });
+    filters.append(&all_button);
+
+    for (title, section) in sections {
+        let button = gtk::ToggleButton::builder()
+            .label(title)
+            .group(&all_button)
+            .build();
+        let boxes = section_boxes.clone();
+        button.connect_toggled(move |button| {
+            if button.is_active() {
+                for other in &boxes {
+                    other.set_visible(*other == section);
+                }
+            }
+        });
+        filters.append(&button);
+    }
+}
+
+/// Total up the artist's tracks for the stats line, which stays hidden
+/// until they're loaded
+fn load_artist_stats(this: &imp::NovaWindow, artist: &Artist) {
+    this.artist_detail_stat_tracks.replace(Vec::new());
+    update_detail_stats(this);
+    let (Some(manager), Some(source)) = (
+        this.service_manager.borrow().clone(),
+        artist.sources.first().cloned(),
+    ) else {
+        return;
+    };
+
+    let name = artist.name.clone();
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let tracks = match manager.artist_tracks(&source.provider, &source.id).await {
+            Ok(items) => items.into_iter().map(|item| item.track).collect(),
+            Err(e) => {
+                eprintln!("Failed to load tracks for {}: {}", name, e);
+                return;
+            }
+        };
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        // Another artist may have been opened in the meantime
+        if this.artist_detail_name.text() != name {
+            return;
+        }
+        this.artist_detail_stat_tracks.replace(tracks);
+        update_detail_stats(this);
+    });
+}
+
+fn show_missing_tracks(this: &imp::NovaWindow, missing: Vec<MissingTrack>) {
+    this.album_detail_gaps.set_visible(!missing.is_empty());
+    if !missing.is_empty() {
+        let count = match missing.len() {
+            1 => "1 track missing".to_string(),
+            n => format!("{} tracks missing", n),
+        };
+        this.album_detail_gaps_label
+            .set_text(&format!("{}: {}", count, describe_missing(&missing)));
+    }
+    this.album_detail_missing.replace(missing);
+}
+
+/// Search streaming providers for the tracks missing from the album on the
+/// album page, and queue the ones found
+pub(crate) fn queue_missing_tracks(this: &imp::NovaWindow) {
+    let missing = this.album_detail_missing.borrow().clone();
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+    if missing.is_empty() {
+        return;
+    }
+    let album = this.album_detail_title.text().to_string();
+    let artist = this.album_detail_artist.label().unwrap_or_default().to_string();
+
+    this.album_detail_fill_gaps.set_sensitive(false);
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let query = format!("{} {}", artist, album);
+        let result = manager
+            .search_tracks(&query, None, MISSING_SEARCH_LIMIT)
+            .await;
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        this.album_detail_fill_gaps.set_sensitive(true);
+
+        let found = match result {
+            Ok(found) => found,
+            Err(e) => {
+                eprintln!("Failed to search for missing tracks of {}: {}", album, e);
+                this.show_toast("Couldn't search for the missing tracks");
+                return;
+            }
+        };
+        // The library's own copies are the ones missing them
+        let streamed = found
+            .into_iter()
+            .filter(|item| item.track.source.is_remote())
+            .collect();
+        let fills = fill_gaps(&missing, &album, &artist, streamed);
+        if fills.is_empty() {
+            this.show_toast("None of the missing tracks were found online");
+            return;
+        }
+        let message = format!("Queued {} of {} missing tracks", fills.len(), missing.len());
+        if let Some(player) = this.player.borrow().as_ref() {
+            player.enqueue_tracks(fills, QueueContext::MissingTrack(album));
+        }
+        this.show_toast(&message);
+    });
+}
+
+/// Refresh the stats lines of the album and artist pages, such as after a
+/// play, so they stay current while open
+pub(crate) fn update_detail_stats(this: &imp::NovaWindow) {
+    let store = this.user_store.borrow().clone();
+    for (label, tracks) in [
+        (&this.album_detail_stats, &this.album_detail_stat_tracks),
+        (&this.artist_detail_stats, &this.artist_detail_stat_tracks),
+    ] {
+        let tracks = tracks.borrow();
+        label.set_visible(!tracks.is_empty());
+        if !tracks.is_empty() {
+            label.set_text(&stats_text(store.as_deref(), &tracks));
+        }
+    }
+}
+
+/// "12 tracks • 48:10 • 4.2 ★ average • 31 plays • last played Mar 3, 21:40"
+fn stats_text(store: Option<&UserStore>, tracks: &[Track]) -> String {
+    let length: u32 = tracks.iter().map(|track| track.duration).sum();
+    let mut parts = vec![
+        format_count_of(tracks.len(), "{} track", "{} tracks"),
+        format_length(length),
+    ];
+
+    let track_ids: Vec<String> = tracks.iter().map(|track| track.id.clone()).collect();
+    let stats = match store.map(|store| store.play_stats(&track_ids)) {
+        Some(Ok(stats)) => stats,
+        Some(Err(e)) => {
+            eprintln!("Failed to load play stats: {}", e);
+            PlayStats::default()
+        }
+        None => PlayStats::default(),
+    };
+    if let Some(rating) = stats.average_rating {
+        parts.push(format!("{:.1} ★ average", rating));
+    }
+    parts.push(match stats.plays {
+        0 => "never played".to_string(),
+        n => format_count_of(n as usize, "{} play", "{} plays"),
+    });
+    if let Some(last_played) = stats.last_played {
+        parts.push(format!("last played {}", format_time_ago(last_played)));
+    }
+    parts.join(" • ")
+}
+
+/// Show the artist's bio and similar artists: cached info straight away,
+/// then fresh info from the network when there's none yet or it's old
+fn load_artist_info(this: &imp::NovaWindow, artist: &Artist) {
+    let name = artist.name.as_str();
+    this.artist_detail_bio.set_visible(false);
+    this.artist_detail_similar_box.set_visible(false);
+
+    let store = this.user_store.borrow().clone();
+    let cached = store.as_ref().and_then(|store| {
+        store.artist_info(name).unwrap_or_else(|e| {
+            eprintln!("Failed to load cached info for {}: {}", name, e);
+            None
+        })
+    });
+    if let Some(info) = &cached {
+        show_artist_info(this, &artist.id, info);
+    }
+
+    let settings = this.settings();
+    if !settings.boolean("network-metadata") || cached.as_ref().is_some_and(|info| !info.is_stale())
+    {
+        return;
+    }
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let api_key = settings.string("lastfm-api-key").to_string();
+    let name = name.to_string();
+    let artist_id = artist.id.clone();
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        // Offline or on a data plan, the cached info will do
+        if !manager.network_conditions().await.downloads_allowed() {
+            return;
+        }
+
+        let lookup_name = name.clone();
+        let result = tokio::spawn(async move {
+            fetch_artist_info(&lookup_name, Some(api_key.as_str())).await
+        })
+        .await;
+        let info = match result {
+            Ok(Ok(info)) => info,
+            Ok(Err(e)) => {
+                eprintln!("Failed to fetch info for {}: {}", name, e);
+                return;
+            }
+            Err(e) => {
+                eprintln!("Artist info lookup for {} stopped: {}", name, e);
+                return;
+            }
+        };
+        if let Some(store) = &store {
+            if let Err(e) = store.save_artist_info(&info) {
+                eprintln!("Failed to cache info for {}: {}", name, e);
+            }
+        }
+
+        if let Some(obj) = obj_weak.upgrade() {
+            show_artist_info(obj.imp(), &artist_id, &info);
+        }
+    });
+}
+
+/// Whether the artist page still shows the artist with `artist_id`. It may
+/// have moved on while info was loading.
+fn shows_artist(this: &imp::NovaWindow, artist_id: &str) -> bool {
+    this.artist_detail_id.borrow().as_deref() == Some(artist_id)
+}
+
+fn show_artist_info(this: &imp::NovaWindow, artist_id: &str, info: &ArtistInfo) {
+    if !shows_artist(this, artist_id) {
+        return;
+    }
+    this.artist_detail_bio
+        .set_text(info.bio.as_deref().unwrap_or_default());
+    this.artist_detail_bio.set_visible(info.bio.is_some());
+
+    clear_similar_artists(this);
+    this.artist_detail_similar_box
+        .set_visible(!info.similar.is_empty());
+    if info.similar.is_empty() {
+        return;
+    }
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    // Artists in the library link to their page; the rest are just names
+    let similar = info.similar.clone();
+    let artist_id = artist_id.to_string();
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let library = manager.get_all_artists().await.unwrap_or_else(|e| {
+            eprintln!("Failed to load artists to link similar artists: {}", e);
+            Vec::new()
+        });
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        if !shows_artist(this, &artist_id) {
+            return;
+        }
+        // Cached and fresh info can both get here; only the last is shown
+        clear_similar_artists(this);
+
+        for name in &similar {
+            let source = library
+                .iter()
+                .find(|artist| artist.name.eq_ignore_ascii_case(name))
+                .and_then(|artist| artist.sources.first().cloned());
+            let button = gtk::Button::with_label(name);
+            button.add_css_class("pill");
+            button.add_css_class("small");
+            match source {
+                Some(source) => {
+                    let obj_weak = obj.downgrade();
+                    button.connect_clicked(move |_| {
+                        if let Some(obj) = obj_weak.upgrade() {
+                            show_artist(obj.imp(), &source.provider, &source.id);
+                        }
+                    });
+                }
+                None => {
+                    button.set_sensitive(false);
+                    button.set_tooltip_text(Some("Not in your library"));
+                }
+            }
+            this.artist_detail_similar.append(&button);
+        }
+    });
+}
+
+fn clear_similar_artists(this: &imp::NovaWindow) {
+    let similar_box = &this.artist_detail_similar;
+    while let Some(child) = similar_box.first_child() {
+        similar_box.remove(&child);
+    }
+}
+
+/// A titled grid of album cards for one section of the artist page
+fn create_album_section(title: &str, albums: &[&Album]) -> gtk::Box {
+    let section = gtk::Box::new(gtk::Orientation::Vertical, 12);
+
+    let label = gtk::Label::builder().label(title).xalign(0.0).build();
+    label.add_css_class("title-2");
+    label.add_css_class("section-title");
+    section.append(&label);
+
+    let grid = gtk::FlowBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .homogeneous(true)
+        .max_children_per_line(6)
+        .min_children_per_line(2)
+        .row_spacing(12)
+        .column_spacing(12)
+        .valign(gtk::Align::Start)
+        .build();
+    grid.add_css_class("content-grid");
+    for album in albums {
+        let card = create_album_card(album, false);
+        let child = gtk::FlowBoxChild::new();
+        child.set_child(Some(&card));
+        grid.append(&child);
+    }
+    section.append(&grid);
+
+    section
+}
+
+/// Add a right-click menu with "Go to Album" and "Go to Artist" to `widget`.
+/// `current` supplies the track at the time the menu is opened, so the same
+/// menu works for fixed rows and for the now-playing bar.
+pub(crate) fn attach_go_to_menu<F>(
+    widget: &impl IsA<gtk::Widget>,
+    window: &crate::window::NovaWindow,
+    current: F,
+) where
+    F: Fn() -> Option<(Track, Option<String>)> + 'static,
+{
+    let album_button = gtk::Button::with_label("Go to Album");
+    album_button.add_css_class("flat");
+    let artist_button = gtk::Button::with_label("Go to Artist");
+    artist_button.add_css_class("flat");
+    // Labelled when the menu opens, depending on the track
+    let shuffle_button = gtk::Button::new();
+    shuffle_button.add_css_class("flat");
+    // Only files can be measured
+    let loudness_button = gtk::Button::with_label("Loudness…");
+    loudness_button.add_css_class("flat");
+    let trim_button = gtk::Button::with_label("Start and End…");
+    trim_button.add_css_class("flat");
+    let playlist_button = gtk::Button::with_label("Add to Playlist…");
+    playlist_button.add_css_class("flat");
+    let reveal_button = gtk::Button::with_label("Show in Files");
+    reveal_button.add_css_class("flat");
+    let trash_button = gtk::Button::with_label("Move File to Trash…");
+    trash_button.add_css_class("flat");
+    // Only the track playing has a place to bookmark
+    let bookmark_button = gtk::Button::with_label("Add Bookmark…");
+    bookmark_button.add_css_class("flat");
+    // Filled with the track's bookmarks when the menu opens
+    let bookmark_separator = gtk::Separator::new(gtk::Orientation::Horizontal);
+    let bookmark_list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+
+    let menu = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    menu.append(&album_button);
+    menu.append(&artist_button);
+    menu.append(&playlist_button);
+    menu.append(&shuffle_button);
+    menu.append(&loudness_button);
+    menu.append(&trim_button);
+    menu.append(&bookmark_button);
+    menu.append(&reveal_button);
+    menu.append(&trash_button);
+    menu.append(&bookmark_separator);
+    menu.append(&bookmark_list);
+
+    let popover = gtk::Popover::new();
+    popover.set_child(Some(&menu));
+    popover.set_has_arrow(false);
+    popover.set_parent(widget);
+
+    // The popover isn't a regular child, so it has to be detached by hand
+    let popover_clone = popover.clone();
+    widget.connect_destroy(move |_| popover_clone.unparent());
+
+    let current = Rc::new(current);
+    // Where the menu was opened, for the playlist picker to open there too
+    let clicked_at = Rc::new(Cell::new((0, 0)));
+
+    let window_weak = window.downgrade();
+    let popover_clone = popover.clone();
+    let current_clone = current.clone();
+    album_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        if let (Some(window), Some((track, provider))) = (window_weak.upgrade(), current_clone()) {
+            show_album_for_track(window.imp(), &track, provider);
+        }
+    });
+
+    let window_weak = window.downgrade();
+    let popover_clone = popover.clone();
+    let current_clone = current.clone();
+    artist_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        if let (Some(window), Some((track, provider))) = (window_weak.upgrade(), current_clone()) {
+            show_artist_for_track(window.imp(), &track, provider);
+        }
+    });
+
+    let window_weak = window.downgrade();
+    let popover_clone = popover.clone();
+    let current_clone = current.clone();
+    shuffle_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        if let (Some(window), Some((track, _))) = (window_weak.upgrade(), current_clone()) {
+            toggle_track_shuffle_exclusion(window.imp(), &track);
+        }
+    });
+
+    let window_weak = window.downgrade();
+    let popover_clone = popover.clone();
+    let current_clone = current.clone();
+    loudness_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        if let (Some(window), Some((track, _))) = (window_weak.upgrade(), current_clone()) {
+            show_loudness(window.imp(), &track);
+        }
+    });
+
+    let window_weak = window.downgrade();
+    let popover_clone = popover.clone();
+    let current_clone = current.clone();
+    trim_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        if let (Some(window), Some((track, _))) = (window_weak.upgrade(), current_clone()) {
+            show_trim_editor(window.imp(), &track);
+        }
+    });
+
+    let window_weak = window.downgrade();
+    let popover_clone = popover.clone();
+    let current_clone = current.clone();
+    reveal_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        if let (Some(window), Some((track, _))) = (window_weak.upgrade(), current_clone()) {
+            show_track_in_files(window.imp(), &track);
+        }
+    });
+
+    let window_weak = window.downgrade();
+    let popover_clone = popover.clone();
+    let current_clone = current.clone();
+    trash_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        if let (Some(window), Some((track, _))) = (window_weak.upgrade(), current_clone()) {
+            confirm_trash(window.imp(), &track);
+        }
+    });
+
+    let window_weak = window.downgrade();
+    let popover_clone = popover.clone();
+    let current_clone = current.clone();
+    bookmark_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        if let (Some(window), Some((track, _))) = (window_weak.upgrade(), current_clone()) {
+            prompt_bookmark(window.imp(), &track);
+        }
+    });
+
+    let window_weak = window.downgrade();
+    let widget_weak = widget.upcast_ref::<gtk::Widget>().downgrade();
+    let popover_clone = popover.clone();
+    let current_clone = current.clone();
+    let clicked_at_clone = clicked_at.clone();
+    playlist_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        let (Some(window), Some(widget), Some((track, _))) = (
+            window_weak.upgrade(),
+            widget_weak.upgrade(),
+            current_clone(),
+        ) else {
+            return;
+        };
+        let (x, y) = clicked_at_clone.get();
+        let point = gtk::gdk::Rectangle::new(x, y, 1, 1);
+        show_playlist_picker(window.imp(), &widget, point, vec![track]);
+    });
+
+    let window_weak = window.downgrade();
+    let click = gtk::GestureClick::new();
+    click.set_button(gtk::gdk::BUTTON_SECONDARY);
+    click.connect_pressed(move |gesture, _, x, y| {
+        let (Some(window), Some((track, _))) = (window_weak.upgrade(), current()) else {
+            return;
+        };
+        shuffle_button.set_label(if track_excluded_from_shuffle(window.imp(), &track) {
+            "Allow in Shuffle"
+        } else {
+            "Never Play in Shuffle"
+        });
+        let is_file = matches!(track.source, PlaybackSource::Local { .. });
+        loudness_button.set_visible(is_file);
+        trim_button.set_visible(is_file);
+        reveal_button.set_visible(is_file);
+        trash_button.set_visible(is_file);
+        let player = window.imp().player.borrow().clone();
+        let is_playing = player
+            .and_then(|player| player.current_track())
+            .is_some_and(|current| current.id == track.id);
+        bookmark_button.set_visible(is_playing);
+        let popover_weak = popover.downgrade();
+        fill_bookmark_list(window.imp(), &bookmark_list, &track, move || {
+            if let Some(popover) = popover_weak.upgrade() {
+                popover.popdown();
+            }
+        });
+        bookmark_separator.set_visible(bookmark_list.first_child().is_some());
+        clicked_at.set((x as i32, y as i32));
+        gesture.set_state(gtk::EventSequenceState::Claimed);
+        popover.set_pointing_to(Some(&gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+        popover.popup();
+    });
+    widget.add_controller(click);
+}
+
+/// An entry in a card's right-click menu
+pub(crate) struct CardAction {
+    label: Box<dyn Fn(&imp::NovaWindow) -> &'static str>,
+    run: Box<dyn Fn(&imp::NovaWindow)>,
+}
+
+impl CardAction {
+    pub(crate) fn new(label: &'static str, run: impl Fn(&imp::NovaWindow) + 'static) -> Self {
+        Self::dynamic(move |_| label, run)
+    }
+
+    /// An entry whose label is worked out each time the menu opens, such
+    /// as one that toggles something
+    pub(crate) fn dynamic(
+        label: impl Fn(&imp::NovaWindow) -> &'static str + 'static,
+        run: impl Fn(&imp::NovaWindow) + 'static,
+    ) -> Self {
+        Self {
+            label: Box::new(label),
+            run: Box::new(run),
+        }
+    }
+}
+
+/// Add a right-click menu with `actions` to an album, artist or playlist
+/// card. Cards don't know their window, so it's looked up from the card
+/// when an action runs.
+pub(crate) fn attach_card_menu(widget: &impl IsA<gtk::Widget>, actions: Vec<CardAction>) {
+    let menu = gtk::Box::new(gtk::Orientation::Vertical, 0);
+
+    let popover = gtk::Popover::new();
+    popover.set_child(Some(&menu));
+    popover.set_has_arrow(false);
+    popover.set_parent(widget);
+
+    // The popover isn't a regular child, so it has to be detached by hand
+    let popover_clone = popover.clone();
+    widget.connect_destroy(move |_| popover_clone.unparent());
+
+    let window_of = |widget: &glib::WeakRef<gtk::Widget>| {
+        widget
+            .upgrade()
+            .and_then(|widget| widget.root())
+            .and_then(|root| root.downcast::<crate::window::NovaWindow>().ok())
+    };
+
+    let mut entries = Vec::with_capacity(actions.len());
+    for action in actions {
+        let action = Rc::new(action);
+        let button = gtk::Button::new();
+        button.add_css_class("flat");
+        menu.append(&button);
+
+        let widget_weak = widget.upcast_ref::<gtk::Widget>().downgrade();
+        let popover_clone = popover.clone();
+        let action_clone = action.clone();
+        button.connect_clicked(move |_| {
+            popover_clone.popdown();
+            if let Some(window) = window_of(&widget_weak) {
+                (action_clone.run)(window.imp());
+            }
+        });
+        entries.push((button, action));
+    }
+
+    let widget_weak = widget.upcast_ref::<gtk::Widget>().downgrade();
+    let click = gtk::GestureClick::new();
+    click.set_button(gtk::gdk::BUTTON_SECONDARY);
+    click.connect_pressed(move |gesture, _, x, y| {
+        let Some(window) = window_of(&widget_weak) else {
+            return;
+        };
+        for (button, action) in &entries {
+            button.set_label((action.label)(window.imp()));
+        }
+        gesture.set_state(gtk::EventSequenceState::Claimed);
+        popover.set_pointing_to(Some(&gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+        popover.popup();
+    });
+    widget.add_controller(click);
+}
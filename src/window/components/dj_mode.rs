@@ -0,0 +1,223 @@
+use crate::services::models::{PlaybackSource, QueueContext, Track, TrackTempo};
+use crate::services::{dj, local, resources, UserStore};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+// Recently played tracks DJ mode won't pick again
+const RECENT_LIMIT: usize = 100;
+
+/// Beat-match and pick tracks as they start while DJ mode is on
+pub(crate) fn setup_dj_mode(this: &imp::NovaWindow) {
+    let obj_weak = this.obj().downgrade();
+    this.settings()
+        .connect_changed(Some("dj-mode"), move |settings, key| {
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            let this = obj.imp();
+            if settings.boolean(key) {
+                analyze_tempos(this);
+                let track = this
+                    .player
+                    .borrow()
+                    .as_ref()
+                    .and_then(|p| p.current_track());
+                if let Some(track) = track {
+                    track_started(this, track);
+                }
+            } else {
+                this.dj_match.take();
+                if let Some(player) = this.player.borrow().as_ref() {
+                    player.set_transition(None);
+                    player.set_start_tempo(None);
+                }
+            }
+        });
+
+    if let Some(player) = this.player.borrow().as_ref() {
+        let obj_weak = this.obj().downgrade();
+        player.connect_track_started(move |track| {
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            if obj.imp().settings().boolean("dj-mode") {
+                track_started(obj.imp(), track.clone());
+            }
+        });
+    }
+}
+
+/// Measure the tempo of local tracks not yet looked at, so DJ mode has
+/// tracks to choose from
+pub(crate) fn analyze_tempos(this: &imp::NovaWindow) {
+    if !this.settings().boolean("dj-mode") || this.tempo_analysis_running.get() {
+        return;
+    }
+    let (Some(store), Some(manager)) = (
+        this.user_store.borrow().clone(),
+        this.service_manager.borrow().clone(),
+    ) else {
+        return;
+    };
+
+    this.tempo_analysis_running.set(true);
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let known = store.track_tempos().unwrap_or_default();
+        // Chapter tracks share their file, which is measured once for all
+        let mut files: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        match manager.get_all_tracks().await {
+            Ok(items) => {
+                for item in items {
+                    if let PlaybackSource::Local { path, .. } = item.track.source {
+                        if !known.contains_key(&item.track.id) {
+                            files.entry(path).or_default().push(item.track.id);
+                        }
+                    }
+                }
+            }
+            Err(e) => eprintln!("Failed to list tracks for tempo analysis: {}", e),
+        }
+
+        let analyzed = tokio::task::spawn_blocking(move || {
+            for (path, track_ids) in files {
+                let tempo = measure(&path);
+                for track_id in &track_ids {
+                    if let Err(e) = store.set_track_tempo(track_id, &tempo) {
+                        eprintln!("Failed to save tempo of {}: {}", track_id, e);
+                    }
+                }
+                if let Some(pause) = resources::background_pause() {
+                    std::thread::sleep(pause);
+                }
+            }
+        })
+        .await;
+        if let Err(e) = analyzed {
+            eprintln!("Tempo analysis stopped: {}", e);
+        }
+
+        if let Some(obj) = obj_weak.upgrade() {
+            obj.imp().tempo_analysis_running.set(false);
+        }
+    });
+}
+
+/// Tempo and key of the file at `path`, or nothing known when it can't be
+/// measured so it isn't tried again
+fn measure(path: &std::path::Path) -> TrackTempo {
+    local::analyze_tempo(path).unwrap_or_else(|e| {
+        eprintln!("Failed to analyze the tempo of {:?}: {}", path, e);
+        TrackTempo::default()
+    })
+}
+
+/// Tempo of `track`, measured now if it's a local file not yet analyzed
+async fn tempo_of(
+    store: &UserStore,
+    tempos: &HashMap<String, TrackTempo>,
+    track: &Track,
+) -> TrackTempo {
+    if let Some(tempo) = tempos.get(&track.id) {
+        return *tempo;
+    }
+    let PlaybackSource::Local { path, .. } = &track.source else {
+        return TrackTempo::default();
+    };
+    let path = path.clone();
+    let tempo = match tokio::task::spawn_blocking(move || measure(&path)).await {
+        Ok(tempo) => tempo,
+        Err(e) => {
+            // Not saved, so it's tried again
+            eprintln!("Tempo analysis of {} stopped: {}", track.title, e);
+            return TrackTempo::default();
+        }
+    };
+    if let Err(e) = store.set_track_tempo(&track.id, &tempo) {
+        eprintln!("Failed to save tempo of {}: {}", track.id, e);
+    }
+    tempo
+}
+
+/// Set the crossfade into the next track to `track`'s beat, queue a track
+/// to follow when nothing else will, and match the next track's tempo to
+/// it before it starts
+fn track_started(this: &imp::NovaWindow, track: Track) {
+    let (Some(store), Some(manager)) = (
+        this.user_store.borrow().clone(),
+        this.service_manager.borrow().clone(),
+    ) else {
+        return;
+    };
+    let matched = this.dj_match.take();
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let tempos = store.track_tempos().unwrap_or_default();
+        let tempo = tempo_of(&store, &tempos, &track).await;
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        let Some(player) = this.player.borrow().clone() else {
+            return;
+        };
+        // Another track may have started while this one was measured
+        if player.current_track().map(|current| current.id) != Some(track.id.clone()) {
+            return;
+        }
+
+        // A track matched before it started plays at the tempo it was
+        // matched to
+        let bpm = match matched {
+            Some((track_id, bpm)) if track_id == track.id => Some(bpm),
+            _ => tempo.bpm,
+        };
+        player.set_transition(bpm.map(dj::beat_crossfade));
+
+        if player.upcoming_entries(1).is_empty() {
+            let candidates = match manager.get_all_tracks().await {
+                Ok(items) => items,
+                Err(e) => {
+                    eprintln!("Failed to list tracks for DJ mode: {}", e);
+                    return;
+                }
+            };
+            let mut recent: HashSet<String> = store
+                .recent_history(RECENT_LIMIT)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|entry| entry.track_id)
+                .collect();
+            recent.insert(track.id.clone());
+            if let Some(item) =
+                dj::pick_next(bpm, &tempo, &track.artist, &candidates, &tempos, &recent)
+            {
+                player.enqueue_tracks(vec![item], QueueContext::Dj);
+            }
+        }
+
+        // Measure what's next now, so its rate can be set before it starts.
+        // Changing it as the track plays means a seek that's heard.
+        let Some(next) = player.upcoming_entries(1).into_iter().next() else {
+            player.set_start_tempo(None);
+            return;
+        };
+        let next_tempo = tempo_of(&store, &tempos, &next.track).await;
+        let rate = bpm
+            .zip(next_tempo.bpm)
+            .and_then(|(bpm, next_bpm)| dj::tempo_rate(bpm, next_bpm));
+        // Too late if the next track started while it was measured
+        if player.current_track().map(|current| current.id) != Some(track.id) {
+            return;
+        }
+        let next_id = next.track.id;
+        player.set_start_tempo(rate.map(|rate| (next_id.clone(), rate)));
+        this.dj_match
+            .replace(rate.and(bpm).map(|bpm| (next_id, bpm)));
+    });
+}
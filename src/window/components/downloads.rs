@@ -0,0 +1,179 @@
+use crate::library_folders::library_folders;
+use crate::platform;
+use crate::services::file_naming::{move_into_library, NamingTemplate};
+use crate::services::local::{FileEvent, FileScanner, FileWatcher};
+use crate::services::models::RefreshMode;
+use crate::window::imp;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// Quiet time after the last change before a file counts as downloaded.
+// Browsers write to a temporary name and rename it when done, but files
+// saved some other way grow in place.
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// Watches the Downloads folder while the setting is on
+#[derive(Debug)]
+pub(crate) struct DownloadsWatch {
+    _watcher: FileWatcher,
+}
+
+/// Offer to move audio files that land in Downloads into the library
+pub(crate) fn setup_downloads_watch(this: &imp::NovaWindow) {
+    update_downloads_watch(this);
+    let obj_weak = this.obj().downgrade();
+    this.settings()
+        .connect_changed(Some("watch-downloads"), move |_, _| {
+            if let Some(obj) = obj_weak.upgrade() {
+                update_downloads_watch(obj.imp());
+            }
+        });
+}
+
+fn update_downloads_watch(this: &imp::NovaWindow) {
+    // Dropping the watcher closes the channel, which ends the loop below
+    this.downloads_watch.replace(None);
+    if !this.settings().boolean("watch-downloads") {
+        return;
+    }
+
+    let dir = platform::download_dir();
+    if !platform::can_read_dir(&dir) {
+        eprintln!("Can't read {:?} to watch for downloads", dir);
+        this.show_toast("Nova can't read the Downloads folder");
+        return;
+    }
+    let (sender, mut receiver) = mpsc::channel(100);
+    let watcher = match FileWatcher::new(dir.clone(), sender) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Failed to watch {:?}: {}", dir, e);
+            return;
+        }
+    };
+    this.downloads_watch
+        .replace(Some(DownloadsWatch { _watcher: watcher }));
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        // How many changes each file still settling has seen, so only the
+        // timer started by the last one offers it
+        let settling: Rc<RefCell<HashMap<PathBuf, u64>>> = Rc::default();
+        let offered: Rc<RefCell<HashSet<PathBuf>>> = Rc::default();
+        while let Some(event) = receiver.recv().await {
+            let path = match event {
+                FileEvent::Created(path) | FileEvent::Modified(path) => path,
+                FileEvent::Removed(path) => {
+                    settling.borrow_mut().remove(&path);
+                    continue;
+                }
+            };
+            if !FileScanner::is_music_file_public(&path) || offered.borrow().contains(&path) {
+                continue;
+            }
+
+            let change = {
+                let mut settling = settling.borrow_mut();
+                let change = settling.entry(path.clone()).or_insert(0);
+                *change += 1;
+                *change
+            };
+            let obj_weak = obj_weak.clone();
+            let settling = settling.clone();
+            let offered = offered.clone();
+            glib::timeout_add_local_once(SETTLE_TIME, move || {
+                let mut pending = settling.borrow_mut();
+                if pending.get(&path) != Some(&change) {
+                    return;
+                }
+                pending.remove(&path);
+                drop(pending);
+                if let (Some(obj), true) = (obj_weak.upgrade(), path.exists()) {
+                    offered.borrow_mut().insert(path.clone());
+                    offer_import(obj.imp(), path);
+                }
+            });
+        }
+    });
+}
+
+fn offer_import(this: &imp::NovaWindow, path: PathBuf) {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let toast = adw::Toast::builder()
+        .title(glib::markup_escape_text(&format!("Downloaded {}", name)))
+        .button_label("Move to Library")
+        .timeout(10)
+        .build();
+    let obj_weak = this.obj().downgrade();
+    toast.connect_button_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            import_download(obj.imp(), path.clone());
+        }
+    });
+    this.toast_overlay.add_toast(toast);
+}
+
+/// Move `path` into the first library folder, named from its tags by the
+/// file naming template, then scan for it
+fn import_download(this: &imp::NovaWindow, path: PathBuf) {
+    let template = match NamingTemplate::parse(&this.settings().string("file-naming-template")) {
+        Ok(template) => template,
+        Err(e) => {
+            this.show_toast(&format!("Check the file naming template: {}", e));
+            return;
+        }
+    };
+    let Some(library) = library_folders(&this.settings()).into_iter().next() else {
+        return;
+    };
+    let manager = this.service_manager.borrow().clone();
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let source = path.clone();
+        let result = tokio::spawn(async move {
+            let track = FileScanner::process_file(&source).await?;
+            tokio::task::spawn_blocking(move || {
+                move_into_library(&source, &track, &library, &template)
+            })
+            .await?
+        })
+        .await;
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        match result {
+            Ok(Ok(dest)) => {
+                println!("Moved {:?} to {:?}", path, dest);
+                obj.imp().show_toast("Moved to the library");
+                if let Some(manager) = manager {
+                    if let Err(e) = manager
+                        .refresh_provider("local", RefreshMode::Incremental)
+                        .await
+                    {
+                        eprintln!("Failed to scan for {:?}: {}", dest, e);
+                    }
+                }
+            }
+            Ok(Err(e)) => {
+                eprintln!("Failed to move {:?} into the library: {}", path, e);
+                obj.imp()
+                    .show_toast(&format!("Couldn't move the file: {}", e));
+            }
+            Err(e) => {
+                eprintln!("Moving {:?} stopped: {}", path, e);
+                obj.imp().show_toast("Couldn't move the file");
+            }
+        }
+    });
+}
@@ -0,0 +1,101 @@
+use crate::services::export::{export_library, ExportFormat};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::{gio, glib};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Ask where to save the library export; the file name's extension picks
+/// CSV or JSON
+pub(crate) fn choose_export_file(this: &imp::NovaWindow) {
+    let filters = gio::ListStore::new::<gtk::FileFilter>();
+    for (name, pattern) in [("CSV", "*.csv"), ("JSON", "*.json")] {
+        let filter = gtk::FileFilter::new();
+        filter.set_name(Some(name));
+        filter.add_pattern(pattern);
+        filters.append(&filter);
+    }
+
+    let dialog = gtk::FileDialog::builder()
+        .title("Export Library")
+        .modal(true)
+        .initial_name("nova-library.csv")
+        .filters(&filters)
+        .build();
+
+    let obj_weak = this.obj().downgrade();
+    dialog.save(
+        Some(&*this.obj()),
+        gio::Cancellable::NONE,
+        move |result| match result {
+            Ok(file) => {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return;
+                };
+                match file.path() {
+                    Some(path) => export_to(obj.imp(), path),
+                    None => obj.imp().show_toast("Choose a file on this device"),
+                }
+            }
+            Err(e) => {
+                if !e.matches(gtk::DialogError::Dismissed) {
+                    eprintln!("Failed to choose an export file: {}", e);
+                }
+            }
+        },
+    );
+}
+
+fn export_to(this: &imp::NovaWindow, path: PathBuf) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+    let store = this.user_store.borrow().clone();
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let tracks = match manager.get_library_tracks().await {
+            Ok(tracks) => tracks,
+            Err(e) => {
+                eprintln!("Failed to load tracks to export: {}", e);
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.imp().show_toast("Couldn't load the library to export");
+                }
+                return;
+            }
+        };
+
+        let export_path = path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            // Without the store the tags still go out, just without counts
+            let (play_counts, ratings) = match &store {
+                Some(store) => (
+                    store.play_counts().unwrap_or_default(),
+                    store.ratings().unwrap_or_default(),
+                ),
+                None => (HashMap::new(), HashMap::new()),
+            };
+            let format = ExportFormat::from_path(&export_path);
+            export_library(&tracks, &play_counts, &ratings, &export_path, format)
+        })
+        .await;
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        match result {
+            Ok(Ok(count)) => obj
+                .imp()
+                .show_toast(&format!("Exported {} tracks", count)),
+            Ok(Err(e)) => {
+                eprintln!("Failed to export the library to {:?}: {}", path, e);
+                obj.imp().show_toast("Couldn't export the library");
+            }
+            Err(e) => {
+                eprintln!("Library export stopped: {}", e);
+                obj.imp().show_toast("Couldn't export the library");
+            }
+        }
+    });
+}
@@ -0,0 +1,169 @@
+use crate::services::audio_player::{AudioFormat, GainStages};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use std::time::Duration;
+
+// How often the dialog reads the chain back while it's open
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Levels at or below this show as an empty meter
+const METER_FLOOR_DB: f64 = -60.0;
+
+struct StageRows {
+    source: adw::ActionRow,
+    replay_gain: adw::ActionRow,
+    clip_limiter: adw::ActionRow,
+    peak_limiter: adw::ActionRow,
+    volume: adw::ActionRow,
+    output: adw::ActionRow,
+    meters: gtk::Box,
+}
+
+/// Show each stage of the signal chain with the gain it applies, and a
+/// level meter for what comes out of it
+pub(crate) fn show_gain_stages(this: &imp::NovaWindow) {
+    let Some(player) = this.player.borrow().clone() else {
+        return;
+    };
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    let stage_row = |title: &str| {
+        let row = adw::ActionRow::builder().title(title).build();
+        list.append(&row);
+        row
+    };
+    let rows = StageRows {
+        source: stage_row("Source"),
+        replay_gain: stage_row("ReplayGain"),
+        clip_limiter: stage_row("Clipping Limiter"),
+        peak_limiter: stage_row("Peak Limiter"),
+        volume: stage_row("Volume"),
+        output: stage_row("Output"),
+        meters: gtk::Box::new(gtk::Orientation::Vertical, 6),
+    };
+
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    content.append(&list);
+    content.append(&rows.meters);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Signal Chain")
+        .body("Stages in the order the sound passes through them")
+        .extra_child(&content)
+        .build();
+    dialog.add_response("close", "_Close");
+
+    // The meter only reports while it's looked at
+    player.set_metering(true);
+    let player_clone = player.clone();
+    dialog.connect_closed(move |_| player_clone.set_metering(false));
+
+    update(&rows, player.gain_stages().as_ref());
+    let dialog_weak = dialog.downgrade();
+    glib::timeout_add_local(REFRESH_INTERVAL, move || {
+        if dialog_weak.upgrade().is_none() {
+            return glib::ControlFlow::Break;
+        }
+        update(&rows, player.gain_stages().as_ref());
+        glib::ControlFlow::Continue
+    });
+    dialog.present(Some(&*this.obj()));
+}
+
+fn update(rows: &StageRows, stages: Option<&GainStages>) {
+    let Some(stages) = stages else {
+        for row in [
+            &rows.source,
+            &rows.replay_gain,
+            &rows.clip_limiter,
+            &rows.peak_limiter,
+            &rows.volume,
+            &rows.output,
+        ] {
+            row.set_subtitle("Nothing is playing");
+        }
+        set_meters(&rows.meters, &[], &[]);
+        return;
+    };
+
+    let format = |format: Option<AudioFormat>| {
+        format.map_or("Starting…".to_string(), |format| format.to_string())
+    };
+    rows.source.set_subtitle(&format(stages.source_format));
+    rows.replay_gain.set_subtitle(&match stages.replay_gain_db {
+        Some(gain) => format!("{:+.1} dB", gain),
+        None => "Off".to_string(),
+    });
+    rows.clip_limiter
+        .set_subtitle(if stages.clip_limiter { "On" } else { "Off" });
+    rows.peak_limiter
+        .set_subtitle(if stages.peak_limiter { "On" } else { "Off" });
+    rows.volume.set_subtitle(&if stages.volume_db.is_finite() {
+        format!("{:+.1} dB", stages.volume_db)
+    } else {
+        "Silent".to_string()
+    });
+    // Converted and resampled to what the device takes
+    rows.output.set_subtitle(&format(stages.output_format));
+    set_meters(&rows.meters, &stages.peak_db, &stages.rms_db);
+}
+
+/// One meter per channel, filled to its RMS level and labelled with its
+/// peak in dB
+fn set_meters(meters: &gtk::Box, peak_db: &[f64], rms_db: &[f64]) {
+    // Channels only change with the track, so the meters are rebuilt then
+    let mut count = 0;
+    let mut child = meters.first_child();
+    while let Some(widget) = child {
+        count += 1;
+        child = widget.next_sibling();
+    }
+    if count != peak_db.len() {
+        while let Some(widget) = meters.first_child() {
+            meters.remove(&widget);
+        }
+        for _ in peak_db {
+            let meter = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+            meter.append(
+                &gtk::LevelBar::builder()
+                    .min_value(0.0)
+                    .max_value(1.0)
+                    .hexpand(true)
+                    .valign(gtk::Align::Center)
+                    .build(),
+            );
+            meter.append(
+                &gtk::Label::builder()
+                    .width_chars(9)
+                    .xalign(1.0)
+                    .css_classes(["numeric", "dim-label"])
+                    .build(),
+            );
+            meters.append(&meter);
+        }
+    }
+
+    let mut meter = meters.first_child();
+    for (&peak, &rms) in peak_db.iter().zip(rms_db) {
+        let Some(widget) = meter else {
+            break;
+        };
+        let level = ((rms - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0);
+        if let Some(bar) = widget.first_child().and_downcast::<gtk::LevelBar>() {
+            bar.set_value(level);
+        }
+        if let Some(label) = widget.last_child().and_downcast::<gtk::Label>() {
+            label.set_label(&if peak > METER_FLOOR_DB {
+                format!("{:.1} dB", peak)
+            } else {
+                "−∞ dB".to_string()
+            });
+        }
+        meter = widget.next_sibling();
+    }
+}
@@ -0,0 +1,201 @@
+use crate::services::models::{HistoryEntry, SkippedTrack};
+use crate::window::imp;
+use crate::window::utils::format::{format_duration, format_time_ago};
+use crate::window::utils::tooltip::{attach_tooltip, TooltipBuilder};
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+
+// Entries shown in the History tab of the queue flap
+const HISTORY_DISPLAY_LIMIT: usize = 100;
+
+// Entries shown in the Often Skipped tab of the queue flap
+const SKIPPED_DISPLAY_LIMIT: usize = 50;
+
+/// Refill the History tab from the user store
+pub(crate) fn reload_history(this: &imp::NovaWindow) {
+    let entries = match this.user_store.borrow().as_ref() {
+        Some(store) => store
+            .recent_history(HISTORY_DISPLAY_LIMIT)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load play history: {}", e);
+                Vec::new()
+            }),
+        None => Vec::new(),
+    };
+
+    let list = &this.history_list;
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+
+    for entry in &entries {
+        list.append(&create_history_row(this, entry));
+    }
+
+    this.history_add_all_button.set_sensitive(!entries.is_empty());
+    this.history_entries.replace(entries);
+}
+
+fn create_history_row(this: &imp::NovaWindow, entry: &HistoryEntry) -> adw::ActionRow {
+    let row = adw::ActionRow::builder()
+        .title(glib::markup_escape_text(&entry.title))
+        .subtitle(glib::markup_escape_text(&format!(
+            "{} • {}",
+            entry.artist,
+            format_time_ago(entry.played_at)
+        )))
+        .build();
+    row.add_css_class("history-row");
+
+    let obj_weak = this.obj().downgrade();
+    let info = entry.clone();
+    attach_tooltip(&row, move || {
+        let play_count = obj_weak.upgrade().and_then(|obj| {
+            let store = obj.imp().user_store.borrow().clone()?;
+            store.play_count(&info.track_id).ok()
+        });
+        TooltipBuilder::new(&info.title)
+            .row("Artist", &info.artist)
+            .row("Album", &info.album)
+            .row("Duration", format_duration(info.duration))
+            .optional_row("Plays", play_count.map(|count| count.to_string()))
+    });
+
+    let play_button = gtk::Button::from_icon_name("media-playback-start-symbolic");
+    play_button.set_tooltip_text(Some("Play Again"));
+    play_button.set_valign(gtk::Align::Center);
+    play_button.add_css_class("flat");
+    play_button.add_css_class("circular");
+
+    let obj_weak = this.obj().downgrade();
+    let track_id = entry.track_id.clone();
+    let title = entry.title.clone();
+    play_button.connect_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            play_again(obj.imp(), &track_id, &title);
+        }
+    });
+    row.add_suffix(&play_button);
+
+    row
+}
+
+/// Refill the Often Skipped tab from the user store
+pub(crate) fn reload_skipped(this: &imp::NovaWindow) {
+    let tracks = match this.user_store.borrow().as_ref() {
+        Some(store) => store
+            .often_skipped(SKIPPED_DISPLAY_LIMIT)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load skipped tracks: {}", e);
+                Vec::new()
+            }),
+        None => Vec::new(),
+    };
+
+    let list = &this.skipped_list;
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+
+    for track in &tracks {
+        list.append(&create_skipped_row(this, track));
+    }
+}
+
+fn create_skipped_row(this: &imp::NovaWindow, track: &SkippedTrack) -> adw::ActionRow {
+    let row = adw::ActionRow::builder()
+        .title(glib::markup_escape_text(&track.title))
+        .subtitle(glib::markup_escape_text(&format!(
+            "{} • Skipped {} of {} plays",
+            track.artist, track.skips, track.plays
+        )))
+        .build();
+    row.add_css_class("history-row");
+
+    let keep_button = gtk::Button::from_icon_name("object-select-symbolic");
+    keep_button.set_tooltip_text(Some("Keep"));
+    keep_button.set_valign(gtk::Align::Center);
+    keep_button.add_css_class("flat");
+    keep_button.add_css_class("circular");
+
+    // Keeping a track forgets its skips so it stops being suggested
+    let obj_weak = this.obj().downgrade();
+    let track_id = track.track_id.clone();
+    keep_button.connect_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            let this = obj.imp();
+            if let Some(store) = this.user_store.borrow().as_ref() {
+                if let Err(e) = store.clear_skips(&track_id) {
+                    eprintln!("Failed to clear skips of {}: {}", track_id, e);
+                }
+            }
+            reload_skipped(this);
+        }
+    });
+    row.add_suffix(&keep_button);
+
+    row
+}
+
+/// Look the track up again, since its file or stream may have moved since it
+/// was played, and start it
+fn play_again(this: &imp::NovaWindow, track_id: &str, title: &str) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    let track_id = track_id.to_string();
+    let title = title.to_string();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager.get_track(&track_id).await;
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+
+        match result {
+            Ok(item) => {
+                if let Some(player) = this.player.borrow().as_ref() {
+                    if let Err(e) = player.play_track(&item.track) {
+                        eprintln!("Failed to replay {}: {}", title, e);
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to find {} for replay: {}", title, e);
+                this.show_toast(&format!("'{}' is no longer in your library", title));
+            }
+        }
+    });
+}
+
+/// Save the whole history, most recent first, as a new playlist
+pub(crate) fn add_history_to_playlist(this: &imp::NovaWindow) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+
+    let track_ids: Vec<String> = this
+        .history_entries
+        .borrow()
+        .iter()
+        .map(|entry| entry.track_id.clone())
+        .collect();
+    if track_ids.is_empty() {
+        return;
+    }
+
+    let name = format!(
+        "Recently Played {}",
+        chrono::Local::now().format("%Y-%m-%d")
+    );
+    match store.create_playlist(&name, &track_ids) {
+        Ok(_) => this.show_toast(&format!("Saved {} tracks to '{}'", track_ids.len(), name)),
+        Err(e) => {
+            eprintln!("Failed to create playlist from history: {}", e);
+            this.show_toast("Couldn't create the playlist");
+        }
+    }
+}
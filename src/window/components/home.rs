@@ -0,0 +1,252 @@
+use crate::services::listening_patterns::{ListeningPatterns, ListeningTime, Plays};
+use crate::window::components::cards::{create_album_card, create_artist_card, create_track_card};
+use crate::window::components::decades::create_decade_shelf;
+use crate::window::components::playlists::{create_playlist_row, show_playlist};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use std::collections::HashSet;
+
+// Plays the listening patterns are learned from, as many as the history
+// keeps
+const PATTERN_HISTORY_LIMIT: usize = 500;
+
+// Items on each shelf
+const SHELF_SIZE: usize = 12;
+
+/// A section of the Home page, with the plays of everything on it
+struct Shelf {
+    title: &'static str,
+    content: gtk::Widget,
+    plays: Plays,
+}
+
+/// Refill the Home page from the play history and the library. With
+/// "context-aware-home" on, shelves and what's on them are ordered by what
+/// usually gets played at this time of day.
+pub(crate) fn reload_home(this: &imp::NovaWindow) {
+    let (Some(manager), Some(store)) = (
+        this.service_manager.borrow().clone(),
+        this.user_store.borrow().clone(),
+    ) else {
+        return;
+    };
+
+    let context_aware = this.settings().boolean("context-aware-home");
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        // The history is read and learned from off the main thread
+        let loaded = tokio::task::spawn_blocking(move || {
+            let history = store
+                .recent_history(PATTERN_HISTORY_LIMIT)
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to load play history for Home: {}", e);
+                    Vec::new()
+                });
+            let playlists = store.playlists().unwrap_or_else(|e| {
+                eprintln!("Failed to load playlists for Home: {}", e);
+                Vec::new()
+            });
+            let patterns = ListeningPatterns::new(&history, ListeningTime::now());
+            (history, playlists, patterns)
+        })
+        .await;
+        let (history, playlists, patterns) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("Loading Home stopped: {}", e);
+                return;
+            }
+        };
+        let rank = |plays: Plays| {
+            if context_aware {
+                patterns.score(plays)
+            } else {
+                plays.all
+            }
+        };
+
+        let mut seen = HashSet::new();
+        let mut recent = Vec::new();
+        for entry in &history {
+            if recent.len() == SHELF_SIZE {
+                break;
+            }
+            if seen.insert(entry.track_id.as_str()) {
+                if let Ok(item) = manager.get_track(&entry.track_id).await {
+                    recent.push(item.track);
+                }
+            }
+        }
+
+        let mut albums: Vec<_> = manager
+            .get_all_albums()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|album| (patterns.album(&album.title), album))
+            .filter(|(plays, _)| plays.all > 0.0)
+            .collect();
+        albums.sort_by(|(a, _), (b, _)| rank(*b).total_cmp(&rank(*a)));
+        albums.truncate(SHELF_SIZE);
+
+        let mut artists: Vec<_> = manager
+            .get_all_artists()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|artist| (patterns.artist(&artist.name), artist))
+            .filter(|(plays, _)| plays.all > 0.0)
+            .collect();
+        artists.sort_by(|(a, _), (b, _)| rank(*b).total_cmp(&rank(*a)));
+        artists.truncate(SHELF_SIZE);
+
+        let mut playlists: Vec<_> = playlists
+            .into_iter()
+            .map(|playlist| (patterns.tracks(&playlist.track_ids), playlist))
+            .filter(|(plays, _)| plays.all > 0.0)
+            .collect();
+        playlists.sort_by(|(a, _), (b, _)| rank(*b).total_cmp(&rank(*a)));
+        playlists.truncate(SHELF_SIZE);
+
+        let decades = manager.album_decades().await.unwrap_or_else(|e| {
+            eprintln!("Failed to load decades for Home: {}", e);
+            Vec::new()
+        });
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+
+        let mut shelves = Vec::new();
+        if !recent.is_empty() {
+            let grid = card_grid();
+            for track in &recent {
+                append_card(&grid, &create_track_card(track, false, &obj));
+            }
+            shelves.push(Shelf {
+                title: "Recently Played",
+                content: grid.upcast(),
+                plays: patterns.tracks(recent.iter().map(|track| &track.id)),
+            });
+        }
+        if !playlists.is_empty() {
+            let list = gtk::ListBox::builder()
+                .selection_mode(gtk::SelectionMode::None)
+                .build();
+            list.add_css_class("boxed-list");
+            let mut plays = Plays::default();
+            for (playlist_plays, playlist) in &playlists {
+                let playlist_id = playlist.id.clone();
+                let row = create_playlist_row(this, playlist, Vec::new(), move |this| {
+                    show_playlist(this, &playlist_id)
+                });
+                list.append(&row);
+                plays.add(*playlist_plays);
+            }
+            shelves.push(Shelf {
+                title: "Your Playlists",
+                content: list.upcast(),
+                plays,
+            });
+        }
+        if !albums.is_empty() {
+            let grid = card_grid();
+            let mut plays = Plays::default();
+            for (album_plays, album) in &albums {
+                append_card(&grid, &create_album_card(album, false));
+                plays.add(*album_plays);
+            }
+            shelves.push(Shelf {
+                title: "Albums You Play",
+                content: grid.upcast(),
+                plays,
+            });
+        }
+        if !artists.is_empty() {
+            let grid = card_grid();
+            let mut plays = Plays::default();
+            for (artist_plays, artist) in &artists {
+                append_card(&grid, &create_artist_card(artist, false));
+                plays.add(*artist_plays);
+            }
+            shelves.push(Shelf {
+                title: "Artists You Play",
+                content: grid.upcast(),
+                plays,
+            });
+        }
+        // One decade has nothing to pick between
+        if decades.len() > 1 {
+            shelves.push(Shelf {
+                title: "Decades",
+                content: create_decade_shelf(&decades).upcast(),
+                plays: Plays::default(),
+            });
+        }
+
+        // Shelves keep their usual order when they're played alike
+        if context_aware {
+            shelves.sort_by(|a, b| patterns.lift(b.plays).total_cmp(&patterns.lift(a.plays)));
+        }
+        show_shelves(this, shelves);
+    });
+}
+
+fn show_shelves(this: &imp::NovaWindow, shelves: Vec<Shelf>) {
+    let container = &this.home_shelves;
+    while let Some(child) = container.first_child() {
+        container.remove(&child);
+    }
+
+    if shelves.is_empty() {
+        let search_button = gtk::Button::builder()
+            .label("Find Music")
+            .action_name("win.focus-search")
+            .halign(gtk::Align::Center)
+            .css_classes(["pill", "suggested-action"])
+            .build();
+        let placeholder = adw::StatusPage::builder()
+            .icon_name("user-home-symbolic")
+            .title("Nothing Played Yet")
+            .description("Music you play shows up here")
+            .child(&search_button)
+            .vexpand(true)
+            .build();
+        container.append(&placeholder);
+        return;
+    }
+
+    for shelf in shelves {
+        let section = gtk::Box::new(gtk::Orientation::Vertical, 12);
+        let label = gtk::Label::builder().label(shelf.title).xalign(0.0).build();
+        label.add_css_class("title-2");
+        label.add_css_class("section-title");
+        section.append(&label);
+        section.append(&shelf.content);
+        container.append(&section);
+    }
+}
+
+fn card_grid() -> gtk::FlowBox {
+    let grid = gtk::FlowBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .homogeneous(true)
+        .max_children_per_line(6)
+        .min_children_per_line(2)
+        .row_spacing(12)
+        .column_spacing(12)
+        .valign(gtk::Align::Start)
+        .build();
+    grid.add_css_class("content-grid");
+    grid
+}
+
+fn append_card(grid: &gtk::FlowBox, card: &gtk::Box) {
+    let child = gtk::FlowBoxChild::new();
+    child.set_child(Some(card));
+    grid.append(&child);
+}
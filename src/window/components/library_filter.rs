@@ -0,0 +1,77 @@
+//! Filtering the Artists and Albums grids in place. Typing on either page
+//! opens its filter rather than the global search, unless "type-to-filter"
+//! is off.
+
+use crate::window::imp;
+use adw::subclass::prelude::*;
+use gtk::prelude::*;
+
+/// Filter the grids by what's typed in their filter fields
+pub(crate) fn setup_library_filters(this: &imp::NovaWindow) {
+    for (grid, revealer, entry) in [
+        (
+            &this.artists_grid,
+            &this.artists_filter_revealer,
+            &this.artists_filter_entry,
+        ),
+        (
+            &this.albums_grid,
+            &this.albums_filter_revealer,
+            &this.albums_filter_entry,
+        ),
+    ] {
+        let entry_clone = entry.get();
+        grid.set_filter_func(move |child| {
+            let query = entry_clone.text().trim().to_lowercase();
+            query.is_empty() || card_text(child.upcast_ref()).contains(&query)
+        });
+
+        let grid_clone = grid.get();
+        entry.connect_search_changed(move |_| grid_clone.invalidate_filter());
+
+        // Escape clears the filter and puts it away
+        let grid_clone = grid.get();
+        let revealer_clone = revealer.get();
+        entry.connect_stop_search(move |entry| {
+            entry.set_text("");
+            revealer_clone.set_reveal_child(false);
+            grid_clone.grab_focus();
+        });
+    }
+}
+
+/// Open the filter of the page showing, if it has one, starting it with
+/// `ch`. Returns whether it did.
+pub(crate) fn start_page_filter(this: &imp::NovaWindow, ch: char) -> bool {
+    if !this.settings().boolean("type-to-filter") {
+        return false;
+    }
+    let (revealer, entry) = match this.main_stack.visible_child_name().as_deref() {
+        Some("artists") => (&this.artists_filter_revealer, &this.artists_filter_entry),
+        Some("albums") => (&this.albums_filter_revealer, &this.albums_filter_entry),
+        _ => return false,
+    };
+    revealer.set_reveal_child(true);
+    entry.grab_focus();
+    entry.set_text(&ch.to_string());
+    entry.set_position(-1);
+    true
+}
+
+/// Text of every label in a card, lowercased, for matching a filter
+fn card_text(widget: &gtk::Widget) -> String {
+    let mut text = String::new();
+    let mut pending = vec![widget.clone()];
+    while let Some(widget) = pending.pop() {
+        if let Some(label) = widget.downcast_ref::<gtk::Label>() {
+            text.push_str(&label.label().to_lowercase());
+            text.push('\n');
+        }
+        let mut child = widget.first_child();
+        while let Some(next) = child {
+            child = next.next_sibling();
+            pending.push(next);
+        }
+    }
+    text
+}
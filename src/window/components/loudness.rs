@@ -0,0 +1,82 @@
+use crate::services::audio_player::ClippingPrevention;
+use crate::services::local::{measure_loudness, LoudnessMeasurement, REFERENCE_LUFS};
+use crate::services::models::{PlaybackSource, Track};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+
+/// Measure the loudness and true peak of `track` and show them, with a
+/// warning when leveling it would clip
+pub(crate) fn show_loudness(this: &imp::NovaWindow, track: &Track) {
+    let PlaybackSource::Local { path, segment, .. } = &track.source else {
+        this.show_toast("Loudness can only be measured for files in your library");
+        return;
+    };
+
+    let dialog = adw::AlertDialog::builder()
+        .heading(track.title.as_str())
+        .body("Measuring…")
+        .build();
+    dialog.add_response("close", "_Close");
+    dialog.present(Some(&*this.obj()));
+
+    let path = path.clone();
+    let whole_file = segment.is_some();
+    let normalization = this.normalization();
+    let dialog_weak = dialog.downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let measured = match tokio::task::spawn_blocking(move || measure_loudness(&path)).await {
+            Ok(result) => result,
+            Err(e) => Err(e.into()),
+        };
+        let Some(dialog) = dialog_weak.upgrade() else {
+            return;
+        };
+        match measured {
+            Ok(measurement) => dialog.set_body(&describe(&measurement, normalization, whole_file)),
+            Err(e) => {
+                eprintln!("Failed to measure loudness: {}", e);
+                dialog.set_body(&format!("Couldn't measure this track: {}", e));
+            }
+        }
+    });
+}
+
+/// The measurements, and what leveling does about any clipping
+fn describe(
+    measurement: &LoudnessMeasurement,
+    normalization: Option<ClippingPrevention>,
+    whole_file: bool,
+) -> String {
+    let mut lines = vec![
+        format!("Loudness: {:.1} LUFS", measurement.integrated_lufs),
+        format!("True peak: {:+.1} dBTP", measurement.true_peak_dbtp),
+        format!(
+            "Leveling gain: {:+.1} dB to reach {} LUFS",
+            measurement.normalization_gain(),
+            REFERENCE_LUFS
+        ),
+    ];
+    // Chapters share their file
+    if whole_file {
+        lines.push("Measured over the whole file this chapter is in".to_string());
+    }
+
+    if let Some(over) = measurement.clipping() {
+        let warning = match normalization {
+            Some(ClippingPrevention::Limiter) => format!(
+                "Leveling takes its peaks {:.1} dB past full scale. The limiter holds them down.",
+                over
+            ),
+            Some(ClippingPrevention::ReduceGain) => format!(
+                "Leveling would clip it, so its gain is reduced by {:.1} dB.",
+                over
+            ),
+            None => format!("Leveling it would clip by {:.1} dB.", over),
+        };
+        lines.push(String::new());
+        lines.push(warning);
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,90 @@
+use adw::prelude::*;
+use gtk::glib;
+
+// Scroll speed in pixels per second
+const MARQUEE_SPEED: f64 = 30.0;
+// Minimum time for a single pass so short overflows don't jitter
+const MARQUEE_MIN_DURATION: u32 = 2000;
+
+/// Scrolls a label back and forth when its text is wider than the
+/// `ScrolledWindow` it lives in.
+#[derive(Debug, Clone)]
+pub struct Marquee {
+    adjustment: gtk::Adjustment,
+    animation: adw::TimedAnimation,
+}
+
+impl Marquee {
+    /// Attach a marquee to the scrolled window wrapping `label`
+    pub fn for_label(label: &gtk::Label) -> Option<Self> {
+        let scroll = label
+            .ancestor(gtk::ScrolledWindow::static_type())
+            .and_downcast::<gtk::ScrolledWindow>()?;
+        scroll.set_policy(gtk::PolicyType::External, gtk::PolicyType::Never);
+
+        let adjustment = scroll.hadjustment();
+        let adjustment_clone = adjustment.clone();
+        let target = adw::CallbackAnimationTarget::new(move |value| {
+            let overflow = adjustment_clone.upper() - adjustment_clone.page_size();
+            adjustment_clone.set_value(value * overflow.max(0.0));
+        });
+
+        let animation = adw::TimedAnimation::new(&scroll, 0.0, 1.0, MARQUEE_MIN_DURATION, target);
+        animation.set_alternate(true);
+        animation.set_repeat_count(0);
+        animation.set_easing(adw::Easing::EaseInOutSine);
+
+        let marquee = Self {
+            adjustment,
+            animation,
+        };
+
+        // Re-evaluate whenever the label is resized or the text changes. The
+        // adjustment owns this handler, so it mustn't hold the animation.
+        let animation_weak = marquee.animation.downgrade();
+        marquee.adjustment.connect_changed(move |adjustment| {
+            if let Some(animation) = animation_weak.upgrade() {
+                let adjustment = adjustment.clone();
+                Self {
+                    adjustment,
+                    animation,
+                }
+                .update();
+            }
+        });
+
+        Some(marquee)
+    }
+
+    /// Start over from the beginning of the text
+    pub fn restart(&self) {
+        self.animation.reset();
+        self.adjustment.set_value(0.0);
+        self.update();
+    }
+
+    fn update(&self) {
+        let overflow = self.adjustment.upper() - self.adjustment.page_size();
+
+        if overflow <= 1.0 {
+            if self.animation.state() == adw::AnimationState::Playing {
+                self.animation.reset();
+            }
+            self.adjustment.set_value(0.0);
+            return;
+        }
+
+        let duration = ((overflow / MARQUEE_SPEED) * 1000.0) as u32;
+        self.animation.set_duration(duration.max(MARQUEE_MIN_DURATION));
+
+        if self.animation.state() != adw::AnimationState::Playing {
+            // Give the reader a moment before the text starts moving
+            let animation = self.animation.clone();
+            glib::timeout_add_local_once(std::time::Duration::from_millis(1500), move || {
+                if animation.state() == adw::AnimationState::Idle {
+                    animation.play();
+                }
+            });
+        }
+    }
+}
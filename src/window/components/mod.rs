@@ -1,3 +1,37 @@
+pub mod bookmarks;
 pub mod cards;
+pub mod decades;
+pub mod details;
+pub mod dj_mode;
+pub mod downloads;
+pub mod export;
+pub mod gain_stages;
+pub mod history;
+pub mod home;
+pub mod library_filter;
+pub mod loudness;
+pub mod marquee;
+pub mod mpris;
+pub mod navigation;
+pub mod organize;
+pub mod scrobbling;
 pub mod search;
+pub mod session;
 pub mod playback;
+pub mod player_bar;
+pub mod playing_marks;
+pub mod playlist_import;
+pub mod playlist_playback;
+pub mod playlist_picker;
+pub mod playlist_tidy;
+pub mod playlists;
+pub mod queue_list;
+pub mod recap;
+pub mod recovery;
+pub mod reveal;
+pub mod share;
+pub mod sidebar;
+pub mod tag_cleanup;
+pub mod trash;
+pub mod trim_editor;
+pub mod updates;
@@ -0,0 +1,297 @@
+//! MPRIS on the session bus, so the desktop's media controls, lock screen
+//! and media keys can see and drive what Nova plays. Album art is handed
+//! over as a file:// URI into the artwork cache, which stays put for as long
+//! as the art does.
+//!
+//! BlueZ passes the same to Bluetooth headphones and car stereos over AVRCP
+//! (with mpris-proxy), including their absolute volume, which maps onto
+//! Volume both ways.
+
+use crate::config::APP_ID;
+use crate::services::local::artwork_file;
+use crate::services::models::{ArtworkSource, Track};
+use crate::window::components::playback::Player;
+use crate::window::{imp, NovaWindow};
+use adw::subclass::prelude::*;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.nova";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const ROOT_INTERFACE: &str = "org.mpris.MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+// Seeking and track lists aren't offered, so their methods are left out
+const INTROSPECTION: &str = r#"<node>
+  <interface name="org.mpris.MediaPlayer2">
+    <method name="Raise"/>
+    <method name="Quit"/>
+    <property name="CanQuit" type="b" access="read"/>
+    <property name="CanRaise" type="b" access="read"/>
+    <property name="HasTrackList" type="b" access="read"/>
+    <property name="Identity" type="s" access="read"/>
+    <property name="DesktopEntry" type="s" access="read"/>
+    <property name="SupportedUriSchemes" type="as" access="read"/>
+    <property name="SupportedMimeTypes" type="as" access="read"/>
+  </interface>
+  <interface name="org.mpris.MediaPlayer2.Player">
+    <method name="Next"/>
+    <method name="Previous"/>
+    <method name="Pause"/>
+    <method name="PlayPause"/>
+    <method name="Stop"/>
+    <method name="Play"/>
+    <property name="PlaybackStatus" type="s" access="read"/>
+    <property name="Rate" type="d" access="read"/>
+    <property name="Metadata" type="a{sv}" access="read"/>
+    <property name="Volume" type="d" access="readwrite"/>
+    <property name="Position" type="x" access="read"/>
+    <property name="MinimumRate" type="d" access="read"/>
+    <property name="MaximumRate" type="d" access="read"/>
+    <property name="CanGoNext" type="b" access="read"/>
+    <property name="CanGoPrevious" type="b" access="read"/>
+    <property name="CanPlay" type="b" access="read"/>
+    <property name="CanPause" type="b" access="read"/>
+    <property name="CanSeek" type="b" access="read"/>
+    <property name="CanControl" type="b" access="read"/>
+  </interface>
+</node>"#;
+
+/// Publish `player` over MPRIS and keep the desktop told about track and
+/// play state changes
+pub(crate) fn setup_mpris(this: &imp::NovaWindow, player: &Player) {
+    let node = match gio::DBusNodeInfo::for_xml(INTROSPECTION) {
+        Ok(node) => node,
+        Err(e) => {
+            eprintln!("Failed to parse the MPRIS interfaces: {}", e);
+            return;
+        }
+    };
+
+    // Set once the bus is ours, for sending changes
+    let connection: Rc<RefCell<Option<gio::DBusConnection>>> = Rc::default();
+
+    let window = this.obj().downgrade();
+    let player_clone = player.clone();
+    let connection_clone = connection.clone();
+    gio::bus_own_name(
+        gio::BusType::Session,
+        BUS_NAME,
+        gio::BusNameOwnerFlags::NONE,
+        move |bus, _| match register(&bus, &node, &window, &player_clone) {
+            Ok(()) => {
+                connection_clone.replace(Some(bus));
+            }
+            Err(e) => eprintln!("Failed to publish MPRIS: {}", e),
+        },
+        |_, _| {},
+        |_, name| eprintln!("Couldn't own {}, media controls won't show Nova", name),
+    );
+
+    let connection_clone = connection.clone();
+    player.connect_track_started(move |track| {
+        if let Some(bus) = &*connection_clone.borrow() {
+            let metadata = metadata(Some(track));
+            emit_changed(
+                bus,
+                &[
+                    ("Metadata", metadata),
+                    ("PlaybackStatus", "Playing".to_variant()),
+                ],
+            );
+        }
+    });
+    let connection_clone = connection.clone();
+    player.connect_playing_changed(move |playing| {
+        if let Some(bus) = &*connection_clone.borrow() {
+            let status = if playing { "Playing" } else { "Paused" };
+            emit_changed(bus, &[("PlaybackStatus", status.to_variant())]);
+        }
+    });
+    // Headphones keep their own volume display in step with this
+    player.connect_volume_changed(move |volume| {
+        if let Some(bus) = &*connection.borrow() {
+            emit_changed(bus, &[("Volume", volume.to_variant())]);
+        }
+    });
+}
+
+fn register(
+    bus: &gio::DBusConnection,
+    node: &gio::DBusNodeInfo,
+    window: &glib::WeakRef<NovaWindow>,
+    player: &Player,
+) -> Result<(), glib::Error> {
+    let missing = || glib::Error::new(gio::IOErrorEnum::NotFound, "MPRIS interface missing");
+
+    let root = node.lookup_interface(ROOT_INTERFACE).ok_or_else(missing)?;
+    let window = window.clone();
+    bus.register_object(OBJECT_PATH, &root)
+        .method_call(move |_, _, _, _, method, _, invocation| {
+            if let Some(window) = window.upgrade() {
+                match method {
+                    "Raise" => window.present(),
+                    "Quit" => window.close(),
+                    _ => {}
+                }
+            }
+            invocation.return_value(None);
+        })
+        .property(|_, _, _, _, property| root_property(property))
+        .build()?;
+
+    let interface = node
+        .lookup_interface(PLAYER_INTERFACE)
+        .ok_or_else(missing)?;
+    let player_clone = player.clone();
+    let player_setter = player.clone();
+    let player = player.clone();
+    bus.register_object(OBJECT_PATH, &interface)
+        .method_call(move |_, _, _, _, method, _, invocation| {
+            let player = &player_clone;
+            match method {
+                "Next" => player.next(),
+                "Previous" => player.previous(),
+                "Pause" | "Stop" => player.pause(),
+                "PlayPause" if player.is_playing() => player.pause(),
+                // Nothing to resume before a track has been picked
+                "Play" | "PlayPause" if player.current_track().is_some() => player.resume(),
+                _ => {}
+            }
+            invocation.return_value(None);
+        })
+        .property(move |_, _, _, _, property| player_property(&player, property))
+        .set_property(move |_, _, _, _, property, value| {
+            // Volume is the only one that can be set, e.g. by headphones
+            // over AVRCP
+            match (property, value.get::<f64>()) {
+                ("Volume", Some(volume)) => {
+                    player_setter.set_volume(volume);
+                    true
+                }
+                _ => false,
+            }
+        })
+        .build()?;
+    Ok(())
+}
+
+fn root_property(name: &str) -> glib::Variant {
+    match name {
+        "CanQuit" | "CanRaise" => true.to_variant(),
+        "HasTrackList" => false.to_variant(),
+        "Identity" => "Nova".to_variant(),
+        "DesktopEntry" => APP_ID.to_variant(),
+        // SupportedUriSchemes and SupportedMimeTypes: nothing is opened
+        // from outside
+        _ => Vec::<String>::new().to_variant(),
+    }
+}
+
+fn player_property(player: &Player, name: &str) -> glib::Variant {
+    match name {
+        "PlaybackStatus" => playback_status(player).to_variant(),
+        "Metadata" => metadata(player.current_track().as_ref()),
+        "Volume" => player.volume().to_variant(),
+        "Position" => {
+            let position = player.position().unwrap_or_default();
+            (position.as_micros() as i64).to_variant()
+        }
+        "Rate" | "MinimumRate" | "MaximumRate" => 1.0.to_variant(),
+        "CanSeek" => false.to_variant(),
+        // CanGoNext, CanGoPrevious, CanPlay, CanPause and CanControl
+        _ => true.to_variant(),
+    }
+}
+
+fn playback_status(player: &Player) -> &'static str {
+    if player.current_track().is_none() {
+        "Stopped"
+    } else if player.is_playing() {
+        "Playing"
+    } else {
+        "Paused"
+    }
+}
+
+/// MPRIS metadata for `track`, or the empty track
+fn metadata(track: Option<&Track>) -> glib::Variant {
+    let dict = glib::VariantDict::new(None);
+    let Some(track) = track else {
+        if let Some(path) = object_path(NO_TRACK) {
+            dict.insert_value("mpris:trackid", &path);
+        }
+        return dict.end();
+    };
+
+    // Object paths only allow letters, digits and underscores
+    let id: String = track
+        .id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = format!("/{}/track/t{}", APP_ID.replace('.', "/"), id);
+    if let Some(path) = object_path(&path) {
+        dict.insert_value("mpris:trackid", &path);
+    }
+    dict.insert_value(
+        "mpris:length",
+        &(track.duration as i64 * 1_000_000).to_variant(),
+    );
+    dict.insert_value("xesam:title", &track.title.to_variant());
+    dict.insert_value("xesam:artist", &vec![track.artist.clone()].to_variant());
+    dict.insert_value("xesam:album", &track.album.to_variant());
+    if let Some(number) = track.track_number {
+        dict.insert_value("xesam:trackNumber", &(number as i32).to_variant());
+    }
+    if let Some(number) = track.disc_number {
+        dict.insert_value("xesam:discNumber", &(number as i32).to_variant());
+    }
+    if let Some(genre) = &track.genre {
+        dict.insert_value("xesam:genre", &vec![genre.clone()].to_variant());
+    }
+    if let Some(url) = art_url(track) {
+        dict.insert_value("mpris:artUrl", &url.to_variant());
+    }
+    dict.end()
+}
+
+fn object_path(path: &str) -> Option<glib::Variant> {
+    glib::variant::ObjectPath::try_from(path.to_string())
+        .ok()
+        .map(|path| path.to_variant())
+}
+
+/// Where the desktop can load the art of `track` from: a file when there is
+/// one, otherwise the image's own address
+fn art_url(track: &Track) -> Option<String> {
+    if let Some(path) = artwork_file(&track.artwork) {
+        return Some(gio::File::for_path(path).uri().to_string());
+    }
+    match &track.artwork.full_art {
+        ArtworkSource::Remote { url, .. } => Some(url.clone()),
+        _ => None,
+    }
+}
+
+/// Tell listeners the player's `changed` properties have new values
+fn emit_changed(bus: &gio::DBusConnection, changed: &[(&str, glib::Variant)]) {
+    let changed: HashMap<String, glib::Variant> = changed
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect();
+    let parameters = (PLAYER_INTERFACE, changed, Vec::<String>::new()).to_variant();
+    if let Err(e) = bus.emit_signal(
+        None,
+        OBJECT_PATH,
+        "org.freedesktop.DBus.Properties",
+        "PropertiesChanged",
+        Some(&parameters),
+    ) {
+        eprintln!("Failed to send MPRIS changes: {}", e);
+    }
+}
@@ -0,0 +1,99 @@
+//! Moving between pages. The page a search replaces is remembered, so
+//! leaving the search goes back to where it started, and so is the last
+//! library page, to open again on the next launch.
+
+use crate::window::components::playlists::reload_playlists;
+use crate::window::imp;
+use adw::subclass::prelude::*;
+use gtk::prelude::*;
+
+// Sidebar rows in order, for selecting the row of a page again
+const SIDEBAR_PAGES: [&str; 4] = ["artists", "albums", "playlists", "liked"];
+
+// Pages that show without an item picked first, so can be opened at startup
+const STARTUP_PAGES: [&str; 5] = ["home", "artists", "albums", "playlists", "liked"];
+
+/// Show the search page, remembering the page it replaces
+pub(crate) fn show_search_page(this: &imp::NovaWindow) {
+    let current = this.main_stack.visible_child_name();
+    if let Some(name) = current.filter(|name| name != "search") {
+        this.page_before_search.replace(Some(name.to_string()));
+    }
+    this.main_stack.set_visible_child_name("search");
+    this.home_button.remove_css_class("selected");
+    this.sidebar_list.unselect_all();
+}
+
+/// Move to the search field from anywhere, keeping what was typed before
+pub(crate) fn focus_search(this: &imp::NovaWindow) {
+    show_search_page(this);
+    this.header_search_entry.grab_focus();
+}
+
+/// Clear the search and go back to the page it replaced
+pub(crate) fn leave_search(this: &imp::NovaWindow) {
+    let previous = this
+        .page_before_search
+        .take()
+        .unwrap_or_else(|| "home".to_string());
+    this.header_search_entry.set_text("");
+    show_page(this, &previous);
+    // Out of the search field, so typing starts a new search
+    this.main_stack.grab_focus();
+}
+
+/// Show the page `name`, marking it in the sidebar
+pub(crate) fn show_page(this: &imp::NovaWindow, name: &str) {
+    this.main_stack.set_visible_child_name(name);
+
+    if name == "home" {
+        this.home_button.add_css_class("selected");
+        this.sidebar_list.unselect_all();
+    } else {
+        this.home_button.remove_css_class("selected");
+        if let Some(index) = SIDEBAR_PAGES.iter().position(|page| *page == name) {
+            let row = this.sidebar_list.row_at_index(index as i32);
+            this.sidebar_list.select_row(row.as_ref());
+        }
+    }
+}
+
+/// Keep "last-page" up to date with the library page on screen. Detail
+/// pages and the search leave it at the page they were opened from.
+pub(crate) fn setup_page_memory(this: &imp::NovaWindow) {
+    let settings = this.settings();
+    this.main_stack
+        .connect_visible_child_name_notify(move |stack| {
+            let Some(name) = stack.visible_child_name() else {
+                return;
+            };
+            if STARTUP_PAGES.contains(&name.as_str()) && settings.string("last-page") != name {
+                if let Err(e) = settings.set_string("last-page", &name) {
+                    eprintln!("Failed to remember the page {}: {}", name, e);
+                }
+            }
+        });
+}
+
+/// Open the page picked in "startup-page", loading it first
+pub(crate) fn show_startup_page(this: &imp::NovaWindow) {
+    let settings = this.settings();
+    let page = match settings.string("startup-page").as_str() {
+        // Playback is picked up from the checkpoint once the providers are
+        // up, see restore_checkpoint
+        "resume" => settings.string("last-page").to_string(),
+        page => page.to_string(),
+    };
+    let page = if STARTUP_PAGES.contains(&page.as_str()) {
+        page
+    } else {
+        "home".to_string()
+    };
+    match page.as_str() {
+        "artists" => this.load_artists(),
+        "albums" => this.load_albums(),
+        "playlists" => reload_playlists(this),
+        _ => {}
+    }
+    show_page(this, &page);
+}
@@ -0,0 +1,219 @@
+use crate::library_folders::library_folders;
+use crate::services::file_naming::{plan_moves, NamingTemplate, Plan};
+use crate::services::models::Track;
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Moves listed in the preview; the rest are only counted
+const PREVIEW_ROWS: usize = 200;
+
+/// Preview where the file naming template puts each library file, then
+/// move them there
+pub(crate) fn show_organize_files(this: &imp::NovaWindow) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        // Every file is organized, whatever the library view hides
+        let tracks: Vec<Track> = match manager.get_library_tracks().await {
+            Ok(items) => items
+                .into_iter()
+                .filter(|item| item.provider == "local")
+                .map(|item| item.track)
+                .collect(),
+            Err(e) => {
+                eprintln!("Failed to load tracks to organize: {}", e);
+                Vec::new()
+            }
+        };
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        if tracks.is_empty() {
+            obj.imp().show_toast("No files in the library to organize");
+        } else {
+            present_preview(obj.imp(), tracks);
+        }
+    });
+}
+
+fn present_preview(this: &imp::NovaWindow, tracks: Vec<Track>) {
+    let roots = library_folders(&this.settings());
+
+    let template_row = adw::EntryRow::builder()
+        .title("File Names")
+        .text(this.settings().string("file-naming-template").as_str())
+        .show_apply_button(true)
+        .build();
+    let template_list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    template_list.add_css_class("boxed-list");
+    template_list.append(&template_row);
+
+    let summary = gtk::Label::builder().wrap(true).xalign(0.0).build();
+    let rows = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    rows.add_css_class("boxed-list");
+    let scroller = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .min_content_height(320)
+        .child(&rows)
+        .build();
+
+    let content = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(12)
+        .build();
+    content.append(&template_list);
+    content.append(&summary);
+    content.append(&scroller);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Organize Files")
+        .body(
+            "Files are moved within their library folder to where the template puts them. \
+             Nothing changes until you choose Organize.",
+        )
+        .extra_child(&content)
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("organize", "_Organize")]);
+    dialog.set_response_appearance("organize", adw::ResponseAppearance::Suggested);
+    dialog.set_close_response("cancel");
+
+    let plan: Rc<RefCell<Plan>> = Rc::default();
+    let update = {
+        let dialog = dialog.downgrade();
+        let plan = plan.clone();
+        move |template: &str| {
+            let Some(dialog) = dialog.upgrade() else {
+                return;
+            };
+            while let Some(row) = rows.first_child() {
+                rows.remove(&row);
+            }
+            let new_plan = match NamingTemplate::parse(template) {
+                Ok(template) => plan_moves(&tracks, &roots, &template),
+                Err(e) => {
+                    summary.set_label(&e);
+                    plan.replace(Plan::default());
+                    dialog.set_response_enabled("organize", false);
+                    return;
+                }
+            };
+
+            summary.set_label(&describe(&new_plan));
+            for planned in new_plan.moves.iter().take(PREVIEW_ROWS) {
+                let row = adw::ActionRow::builder()
+                    .title(glib::markup_escape_text(&relative(&planned.to, &roots)))
+                    .subtitle(glib::markup_escape_text(&format!(
+                        "from {}",
+                        relative(&planned.from, &roots)
+                    )))
+                    .build();
+                rows.append(&row);
+            }
+            dialog.set_response_enabled("organize", !new_plan.moves.is_empty());
+            plan.replace(new_plan);
+        }
+    };
+    update(&template_row.text());
+
+    let settings = this.settings();
+    template_row.connect_apply(move |row| {
+        let template = row.text();
+        if let Err(e) = settings.set_string("file-naming-template", &template) {
+            eprintln!("Failed to save the file naming template: {}", e);
+        }
+        update(&template);
+    });
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("organize"), move |_, _| {
+        if let Some(obj) = obj_weak.upgrade() {
+            organize(obj.imp(), plan.take());
+        }
+    });
+
+    dialog.present(Some(&*this.obj()));
+}
+
+fn describe(plan: &Plan) -> String {
+    let mut text = match plan.moves.len() {
+        0 => "Every file is already where the template puts it.".to_string(),
+        1 => "1 file will move.".to_string(),
+        n if n > PREVIEW_ROWS => format!(
+            "{} files will move. The first {} are shown.",
+            n, PREVIEW_ROWS
+        ),
+        n => format!("{} files will move.", n),
+    };
+    if !plan.conflicts.is_empty() {
+        text.push_str(&format!(
+            " {} stay where they are because another file has their new name.",
+            plan.conflicts.len()
+        ));
+    }
+    text
+}
+
+/// `path` within its library folder
+fn relative(path: &Path, roots: &[PathBuf]) -> String {
+    roots
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+fn organize(this: &imp::NovaWindow, plan: Plan) {
+    let (Some(manager), Some(store)) = (
+        this.service_manager.borrow().clone(),
+        this.user_store.borrow().clone(),
+    ) else {
+        return;
+    };
+    let moves: Vec<(PathBuf, PathBuf)> = plan
+        .moves
+        .into_iter()
+        .map(|planned| (planned.from, planned.to))
+        .collect();
+    this.show_toast(&format!("Moving {} files…", moves.len()));
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let count = moves.len();
+        let result = manager.move_files("local", &moves).await;
+        // Track IDs come from the paths, so plays, ratings and playlists
+        // follow the files to their new ones
+        if let Ok(moved) = &result {
+            if let Err(e) = store.rekey_tracks(&moved.tracks) {
+                eprintln!("Failed to carry track details over to moved files: {}", e);
+            }
+        }
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let error = match result {
+            Ok(moved) => moved.error,
+            Err(e) => Some(e.to_string()),
+        };
+        match error {
+            None => obj.imp().show_toast(&format!("Organized {} files", count)),
+            Some(e) => {
+                eprintln!("Failed to organize files: {}", e);
+                obj.imp()
+                    .show_toast(&format!("Couldn't organize every file: {}", e));
+            }
+        }
+    });
+}
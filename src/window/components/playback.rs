@@ -1,11 +1,209 @@
-use crate::services::audio_player::AudioPlayer;
-use crate::services::models::Track;
-use gtk::glib;
+use crate::services::audio_player::{
+    AudioPlayer, ClippingPrevention, GainStages, OutputBuffer, ResamplerQuality, ShuffleMode,
+    VolumeCurve,
+};
+use crate::services::models::{
+    Bookmark, PlayableItem, PlaylistPlayback, QueueContext, QueueEntry, ShuffleExclusions, Track,
+    Trim,
+};
+use crate::services::StreamingQuality;
+use crate::window::components::marquee::Marquee;
+use crate::window::utils::format::{format_duration, format_length};
+use adw::prelude::*;
+use chrono::{DateTime, Utc};
+use gettextrs::gettext;
 use gtk::glib::ControlFlow;
 use gtk::prelude::*;
-use std::cell::RefCell;
+use gtk::{gio, glib};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+/// Volume change in percent for a single scroll step or keyboard shortcut
+pub const VOLUME_STEP: f64 = 2.0;
+
+// Length of each half of the now-playing crossfade in milliseconds
+const CROSSFADE_DURATION: u32 = 150;
+
+// Unreadable tracks skipped in a row before queue playback gives up
+const MAX_SKIP_ATTEMPTS: usize = 5;
+
+// Leaving a track before this share of it has played counts as a skip
+const SKIP_THRESHOLD: f64 = 0.3;
+
+// Speeds holding next or previous builds up through, one per step
+const SCRUB_SPEEDS: [f64; 3] = [2.0, 4.0, 8.0];
+const SCRUB_STEP: Duration = Duration::from_millis(1500);
+
+// Position moves between progress ticks larger than this are seeks, and
+// don't count as played
+const MAX_PLAYED_STEP: Duration = Duration::from_secs(2);
+
+// Playing without the position moving for this long counts as a stall,
+// such as the output no longer taking audio after an underrun
+const STALL_TIMEOUT: Duration = Duration::from_secs(4);
+
+// Stalls recovered from in one track before playback gives up
+const MAX_STALL_RECOVERIES: u32 = 3;
+
+/// A track that has stopped playing, with how much of it really played
+#[derive(Debug, Clone)]
+pub struct Playthrough {
+    pub track: Track,
+    pub started_at: DateTime<Utc>,
+    pub played: Duration,
+}
+
+/// How long the current track has really played so far, added up from
+/// progress ticks so time paused and stretches seeked over don't count
+#[derive(Debug)]
+struct PlayedTime {
+    track: Track,
+    started_at: DateTime<Utc>,
+    played: Duration,
+    last_position: Duration,
+    last_tick: Instant,
+}
+
+impl PlayedTime {
+    fn new(track: &Track) -> Self {
+        Self {
+            track: track.clone(),
+            started_at: Utc::now(),
+            played: Duration::ZERO,
+            last_position: Duration::ZERO,
+            last_tick: Instant::now(),
+        }
+    }
+
+    fn tick(&mut self, position: Duration) {
+        let step = position
+            .checked_sub(self.last_position)
+            .filter(|step| *step <= MAX_PLAYED_STEP);
+        // Scrubbing moves faster than time passes, and counts as no more
+        if let Some(step) = step {
+            self.played += step.min(self.last_tick.elapsed());
+        }
+        self.last_position = position;
+        self.last_tick = Instant::now();
+    }
+}
+
+/// Watches the position while playing. An output that stops taking audio
+/// often leaves the pipeline stuck without posting an error.
+#[derive(Debug)]
+struct StallWatch {
+    position: Option<Duration>,
+    since: Instant,
+}
+
+impl StallWatch {
+    fn new() -> Self {
+        Self {
+            position: None,
+            since: Instant::now(),
+        }
+    }
+
+    /// The last position reached, once it has stayed there STALL_TIMEOUT
+    fn check(&mut self, position: Option<Duration>) -> Option<Duration> {
+        if position.is_some() && position != self.position {
+            self.position = position;
+            self.since = Instant::now();
+            return None;
+        }
+        if self.since.elapsed() < STALL_TIMEOUT {
+            return None;
+        }
+        self.since = Instant::now();
+        Some(self.position.unwrap_or_default())
+    }
+}
+
+/// The track fading out while the next one fades in, shown until the next
+/// one is the louder
+#[derive(Debug, Clone)]
+struct Handover {
+    at: Instant,
+    // Where the outgoing track was when the next one started, and its length
+    position: Duration,
+    duration: Duration,
+    started: Instant,
+}
+
+/// Callbacks run with a track, e.g. whenever one starts playing
+#[derive(Default, Clone)]
+struct TrackHandlers(Rc<RefCell<Vec<Box<dyn Fn(&Track)>>>>);
+
+impl TrackHandlers {
+    fn emit(&self, track: &Track) {
+        for handler in self.0.borrow().iter() {
+            handler(track);
+        }
+    }
+}
+
+/// Callbacks run whenever playback starts or stops, with whether it plays
+#[derive(Default, Clone)]
+struct PlayingHandlers(Rc<RefCell<Vec<Box<dyn Fn(bool)>>>>);
+
+impl PlayingHandlers {
+    fn emit(&self, playing: bool) {
+        for handler in self.0.borrow().iter() {
+            handler(playing);
+        }
+    }
+}
+
+/// Callbacks run when a track stops playing, whichever way it stopped
+#[derive(Default, Clone)]
+struct PlaythroughHandlers(Rc<RefCell<Vec<Box<dyn Fn(&Playthrough)>>>>);
+
+impl PlaythroughHandlers {
+    fn emit(&self, playthrough: &Playthrough) {
+        for handler in self.0.borrow().iter() {
+            handler(playthrough);
+        }
+    }
+}
+
+/// Callbacks run whenever the queue or the place in it changes
+#[derive(Default, Clone)]
+struct QueueHandlers(Rc<RefCell<Vec<Box<dyn Fn()>>>>);
+
+impl QueueHandlers {
+    fn emit(&self) {
+        for handler in self.0.borrow().iter() {
+            handler();
+        }
+    }
+}
+
+impl std::fmt::Debug for QueueHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "QueueHandlers({})", self.0.borrow().len())
+    }
+}
+
+impl std::fmt::Debug for PlaythroughHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PlaythroughHandlers({})", self.0.borrow().len())
+    }
+}
+
+impl std::fmt::Debug for PlayingHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PlayingHandlers({})", self.0.borrow().len())
+    }
+}
+
+impl std::fmt::Debug for TrackHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TrackHandlers({})", self.0.borrow().len())
+    }
+}
 
 #[derive(Debug)]
 pub struct Player {
@@ -16,13 +214,53 @@ pub struct Player {
     current_song_label: gtk::Label,
     current_artist_label: gtk::Label,
     current_album_art: gtk::Image,
+    now_playing_box: gtk::Box,
+    queue_peek_label: gtk::Label,
+    queue_remaining_label: gtk::Label,
+    // Seconds queued after the current track, kept so progress ticks don't
+    // have to add up the whole queue
+    queue_upcoming: Rc<Cell<u32>>,
+    toast_overlay: adw::ToastOverlay,
+    title_marquee: Option<Marquee>,
+    artist_marquee: Option<Marquee>,
+    // Half of the now-playing crossfade that is running, if any
+    now_playing_fade: Rc<RefCell<Option<adw::TimedAnimation>>>,
     is_playing: Rc<RefCell<bool>>,
     is_muted: Rc<RefCell<bool>>,
+    paused_by_network: Rc<RefCell<bool>>,
+    // The output that went away and where the track was, while paused
+    // waiting for it
+    paused_by_output: Rc<RefCell<Option<(String, Duration)>>>,
+    skip_explicit: Rc<RefCell<bool>>,
     last_volume: Rc<RefCell<f64>>,
+    // Most the volume may be turned up to, and whether turning it up past
+    // that has been pointed out yet
+    volume_limit: Rc<Cell<Option<f64>>>,
+    limit_warned: Rc<Cell<bool>>,
     progress_bar: gtk::Scale,
     current_time_label: gtk::Label,
     total_time_label: gtk::Label,
     progress_update_source_id: RefCell<Option<glib::SourceId>>,
+    // Speeds up the seek while next or previous is held
+    scrub_source: Rc<RefCell<Option<glib::SourceId>>>,
+    audible_scrub: Rc<Cell<bool>>,
+    track_started: TrackHandlers,
+    track_skipped: TrackHandlers,
+    trim_changed: TrackHandlers,
+    playing_changed: PlayingHandlers,
+    played: Rc<RefCell<Option<PlayedTime>>>,
+    track_finished: PlaythroughHandlers,
+    queue_changed: QueueHandlers,
+    // Stalls recovered from in the current track
+    stall_recoveries: Rc<Cell<u32>>,
+    handover: Rc<RefCell<Option<Handover>>>,
+    // How long before the end the next track starts, set by the playlist
+    // being played
+    crossfade: Rc<Cell<Option<Duration>>>,
+    // Crossfade into the next track set by DJ mode, over the playlist's
+    transition: Rc<Cell<Option<Duration>>>,
+    // Bookmarks by track ID, in the order they come in the track
+    bookmarks: Rc<RefCell<HashMap<String, Vec<Bookmark>>>>,
 }
 
 impl Clone for Player {
@@ -35,13 +273,40 @@ impl Clone for Player {
             current_song_label: self.current_song_label.clone(),
             current_artist_label: self.current_artist_label.clone(),
             current_album_art: self.current_album_art.clone(),
+            now_playing_box: self.now_playing_box.clone(),
+            queue_peek_label: self.queue_peek_label.clone(),
+            queue_remaining_label: self.queue_remaining_label.clone(),
+            queue_upcoming: self.queue_upcoming.clone(),
+            toast_overlay: self.toast_overlay.clone(),
+            title_marquee: self.title_marquee.clone(),
+            artist_marquee: self.artist_marquee.clone(),
+            now_playing_fade: self.now_playing_fade.clone(),
             is_playing: self.is_playing.clone(),
             is_muted: self.is_muted.clone(),
+            paused_by_network: self.paused_by_network.clone(),
+            paused_by_output: self.paused_by_output.clone(),
+            skip_explicit: self.skip_explicit.clone(),
             last_volume: self.last_volume.clone(),
+            volume_limit: self.volume_limit.clone(),
+            limit_warned: self.limit_warned.clone(),
             progress_bar: self.progress_bar.clone(),
             current_time_label: self.current_time_label.clone(),
             total_time_label: self.total_time_label.clone(),
             progress_update_source_id: RefCell::new(None),
+            scrub_source: self.scrub_source.clone(),
+            audible_scrub: self.audible_scrub.clone(),
+            track_started: self.track_started.clone(),
+            track_skipped: self.track_skipped.clone(),
+            trim_changed: self.trim_changed.clone(),
+            playing_changed: self.playing_changed.clone(),
+            played: self.played.clone(),
+            track_finished: self.track_finished.clone(),
+            queue_changed: self.queue_changed.clone(),
+            stall_recoveries: self.stall_recoveries.clone(),
+            handover: self.handover.clone(),
+            crossfade: self.crossfade.clone(),
+            transition: self.transition.clone(),
+            bookmarks: self.bookmarks.clone(),
         }
     }
 }
@@ -55,6 +320,10 @@ impl Player {
         current_song_label: gtk::Label,
         current_artist_label: gtk::Label,
         current_album_art: gtk::Image,
+        now_playing_box: gtk::Box,
+        queue_peek_label: gtk::Label,
+        queue_remaining_label: gtk::Label,
+        toast_overlay: adw::ToastOverlay,
         progress_bar: gtk::Scale,
         current_time_label: gtk::Label,
         total_time_label: gtk::Label,
@@ -64,6 +333,9 @@ impl Player {
         let is_muted = Rc::new(RefCell::new(false));
         let last_volume = Rc::new(RefCell::new(100.0));
 
+        let title_marquee = Marquee::for_label(&current_song_label);
+        let artist_marquee = Marquee::for_label(&current_artist_label);
+
         let player = Self {
             audio_player: audio_player.clone(),
             play_button: play_button.clone(),
@@ -72,13 +344,40 @@ impl Player {
             current_song_label,
             current_artist_label,
             current_album_art,
+            now_playing_box,
+            queue_peek_label,
+            queue_remaining_label,
+            queue_upcoming: Rc::new(Cell::new(0)),
+            toast_overlay,
+            title_marquee,
+            artist_marquee,
+            now_playing_fade: Rc::new(RefCell::new(None)),
             is_playing: is_playing.clone(),
             is_muted: is_muted.clone(),
+            paused_by_network: Rc::new(RefCell::new(false)),
+            paused_by_output: Rc::new(RefCell::new(None)),
+            skip_explicit: Rc::new(RefCell::new(false)),
             last_volume: last_volume.clone(),
+            volume_limit: Rc::new(Cell::new(None)),
+            limit_warned: Rc::new(Cell::new(false)),
             progress_bar: progress_bar.clone(),
             current_time_label,
             total_time_label,
             progress_update_source_id: RefCell::new(None),
+            scrub_source: Rc::new(RefCell::new(None)),
+            audible_scrub: Rc::new(Cell::new(true)),
+            track_started: TrackHandlers::default(),
+            track_skipped: TrackHandlers::default(),
+            trim_changed: TrackHandlers::default(),
+            playing_changed: PlayingHandlers::default(),
+            played: Rc::new(RefCell::new(None)),
+            track_finished: PlaythroughHandlers::default(),
+            queue_changed: QueueHandlers::default(),
+            stall_recoveries: Rc::new(Cell::new(0)),
+            handover: Rc::new(RefCell::new(None)),
+            crossfade: Rc::new(Cell::new(None)),
+            transition: Rc::new(Cell::new(None)),
+            bookmarks: Rc::new(RefCell::new(HashMap::new())),
         };
 
         // Set initial volume
@@ -89,6 +388,7 @@ impl Player {
         let last_volume_clone = last_volume.clone();
         let mute_button_clone = mute_button.clone();
         let audio_player_clone = audio_player.clone();
+        let player_clone = player.clone();
         volume_scale.connect_value_changed(move |scale| {
             let value = scale.value();
 
@@ -96,6 +396,7 @@ impl Player {
             if !*is_muted_clone.borrow() {
                 *last_volume_clone.borrow_mut() = value;
                 audio_player_clone.set_volume(value / 100.0);
+                player_clone.check_volume_limit(value / 100.0);
 
                 let icon = match value {
                     v if v <= 0.0 => "audio-volume-muted-symbolic",
@@ -149,6 +450,10 @@ impl Player {
                 audio_player_clone.pause();
                 player_clone.stop_progress_updates();
             }
+            // Handlers may look at the state, so let go of it first
+            let now_playing = *playing;
+            drop(playing);
+            player_clone.playing_changed.emit(now_playing);
         });
 
         // Set up progress bar handler
@@ -164,14 +469,352 @@ impl Player {
         progress_bar.set_draw_value(false);
         progress_bar.set_range(0.0, 100.0);
 
+        player.setup_volume_scroll();
+        player.setup_trim_menu();
+
         player
     }
 
-    fn format_duration(duration: Duration) -> String {
-        let total_seconds = duration.as_secs();
-        let minutes = total_seconds / 60;
-        let seconds = total_seconds % 60;
-        format!("{}:{:02}", minutes, seconds)
+    fn setup_volume_scroll(&self) {
+        // Scrolling over either volume widget nudges the volume in small steps
+        for widget in [
+            self.volume_scale.upcast_ref::<gtk::Widget>(),
+            self.mute_button.upcast_ref::<gtk::Widget>(),
+        ] {
+            let scroll_controller =
+                gtk::EventControllerScroll::new(gtk::EventControllerScrollFlags::VERTICAL);
+            scroll_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+
+            let player = self.clone();
+            scroll_controller.connect_scroll(move |_, _, dy| {
+                player.adjust_volume(-dy * VOLUME_STEP);
+                glib::Propagation::Stop
+            });
+            widget.add_controller(scroll_controller);
+        }
+    }
+
+    /// Right-clicking the progress bar sets where the current track starts
+    /// or stops playing, at the point clicked
+    fn setup_trim_menu(&self) {
+        let menu = gio::Menu::new();
+        menu.append(Some("Start Here"), Some("trim.start"));
+        menu.append(Some("Stop Here"), Some("trim.end"));
+        menu.append(Some("Play All of It"), Some("trim.clear"));
+        let popover = gtk::PopoverMenu::from_model(Some(&menu));
+        popover.set_parent(&self.progress_bar);
+        popover.set_has_arrow(false);
+
+        // Share of the track where the menu was opened
+        let clicked = Rc::new(Cell::new(0.0));
+        let actions = gio::SimpleActionGroup::new();
+        for name in ["start", "end", "clear"] {
+            let action = gio::SimpleAction::new(name, None);
+            let player = self.clone();
+            let clicked = clicked.clone();
+            action.connect_activate(move |_, _| player.set_trim_point(name, clicked.get()));
+            actions.add_action(&action);
+        }
+        self.progress_bar
+            .insert_action_group("trim", Some(&actions));
+
+        let gesture = gtk::GestureClick::builder()
+            .button(gtk::gdk::BUTTON_SECONDARY)
+            .build();
+        let player = self.clone();
+        gesture.connect_pressed(move |_, _, x, y| {
+            if player.current_track().is_none() {
+                return;
+            }
+            let width = player.progress_bar.width().max(1) as f64;
+            clicked.set((x / width).clamp(0.0, 1.0));
+            popover.set_pointing_to(Some(&gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+            popover.popup();
+        });
+        self.progress_bar.add_controller(gesture);
+    }
+
+    /// Move the current track's start or end (`point`) to `fraction` of the
+    /// way through it, or clear both
+    fn set_trim_point(&self, point: &str, fraction: f64) {
+        let Some(track) = self.current_track() else {
+            return;
+        };
+        let length = self.track_length(&track);
+        let at = (length.as_millis() as f64 * fraction) as u64;
+        let trim = self.audio_player.trim(&track.id).unwrap_or_default();
+
+        // A start after the end, or the other way round, drops the other one
+        let trim = match point {
+            "start" => Trim {
+                start_ms: at,
+                end_ms: trim.end_ms.filter(|&end| end > at),
+            },
+            "end" => Trim {
+                start_ms: if trim.start_ms < at { trim.start_ms } else { 0 },
+                end_ms: Some(at),
+            },
+            _ => Trim::default(),
+        };
+        self.set_trim(&track, (trim != Trim::default()).then_some(trim));
+    }
+
+    /// Length of `track` as the backend has it, or as tagged before it knows
+    fn track_length(&self, track: &Track) -> Duration {
+        self.audio_player
+            .get_duration()
+            .unwrap_or(Duration::from_secs(track.duration as u64))
+    }
+
+    /// Mark the current track's start and stop points below the progress
+    /// bar, and its bookmarks above it
+    fn show_marks(&self) {
+        self.progress_bar.clear_marks();
+        let Some(track) = self.current_track() else {
+            return;
+        };
+        let length = self.track_length(&track).as_millis() as f64;
+        if length <= 0.0 {
+            return;
+        }
+        let mark = |point: u64, position: gtk::PositionType| {
+            let value = point as f64 / length * 100.0;
+            self.progress_bar.add_mark(value.min(100.0), position, None);
+        };
+
+        if let Some(trim) = self.audio_player.trim(&track.id) {
+            let points = (trim.start_ms > 0)
+                .then_some(trim.start_ms)
+                .into_iter()
+                .chain(trim.end_ms);
+            for point in points {
+                mark(point, gtk::PositionType::Bottom);
+            }
+        }
+        for bookmark in self.bookmarks(&track.id) {
+            mark(bookmark.position_ms, gtk::PositionType::Top);
+        }
+    }
+
+    /// Bookmarks kept across sessions, by track ID
+    pub fn load_bookmarks(&self, bookmarks: HashMap<String, Vec<Bookmark>>) {
+        self.bookmarks.replace(bookmarks);
+        self.show_marks();
+    }
+
+    /// The track's bookmarks in the order they come
+    pub fn bookmarks(&self, track_id: &str) -> Vec<Bookmark> {
+        self.bookmarks
+            .borrow()
+            .get(track_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set_bookmarks(&self, track_id: &str, mut bookmarks: Vec<Bookmark>) {
+        bookmarks.sort_by_key(|bookmark| bookmark.position_ms);
+        let mut all = self.bookmarks.borrow_mut();
+        if bookmarks.is_empty() {
+            all.remove(track_id);
+        } else {
+            all.insert(track_id.to_string(), bookmarks);
+        }
+        drop(all);
+        self.show_marks();
+    }
+
+    /// Go to `position` in `track`, starting it first unless it's the one
+    /// playing
+    pub fn play_from(
+        &self,
+        track: &Track,
+        position: Duration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let is_current = self
+            .current_track()
+            .is_some_and(|current| current.id == track.id);
+        if !is_current {
+            self.play_track(track)?;
+        }
+        self.audio_player.set_position(position);
+        Ok(())
+    }
+
+    pub fn set_trims(&self, trims: HashMap<String, Trim>) {
+        self.audio_player.set_trims(trims);
+        self.show_marks();
+    }
+
+    /// Set where `track` starts and stops playing, or play all of it with
+    /// None
+    pub fn set_trim(&self, track: &Track, trim: Option<Trim>) {
+        self.audio_player.set_trim(&track.id, trim);
+        self.show_marks();
+        self.trim_changed.emit(track);
+    }
+
+    /// Where `track_id` starts and stops playing, if the user has set it
+    pub fn trim(&self, track_id: &str) -> Option<Trim> {
+        self.audio_player.trim(track_id)
+    }
+
+    pub fn adjust_volume(&self, delta: f64) {
+        // Changing the volume while muted unmutes first
+        if *self.is_muted.borrow() {
+            self.mute_button.emit_clicked();
+        }
+
+        let value = (self.volume_scale.value() + delta).clamp(0.0, 100.0);
+        self.volume_scale.set_value(value);
+    }
+
+    /// The volume from 0.0 to 1.0, as the volume slider shows it
+    pub fn volume(&self) -> f64 {
+        self.volume_scale.value() / 100.0
+    }
+
+    /// Move the volume slider to `volume`, from 0.0 to 1.0, as when
+    /// headphones change it. Small differences are left alone, so a change
+    /// echoed back doesn't move it again.
+    pub fn set_volume(&self, volume: f64) {
+        let value = (volume * 100.0).clamp(0.0, 100.0);
+        if (value - self.volume_scale.value()).abs() < 0.5 {
+            return;
+        }
+        if *self.is_muted.borrow() {
+            self.mute_button.emit_clicked();
+        }
+        self.volume_scale.set_value(value);
+    }
+
+    /// Run `handler` with the new volume, from 0.0 to 1.0, whenever it changes
+    pub fn connect_volume_changed<F: Fn(f64) + 'static>(&self, handler: F) {
+        self.volume_scale
+            .connect_value_changed(move |scale| handler(scale.value() / 100.0));
+    }
+
+    pub fn toggle_mute(&self) {
+        self.mute_button.emit_clicked();
+    }
+
+    pub fn set_volume_curve(&self, curve: VolumeCurve) {
+        self.audio_player.set_volume_curve(curve);
+    }
+
+    pub fn set_fade_duration(&self, duration: Duration) {
+        self.audio_player.set_fade_duration(duration);
+    }
+
+    pub fn set_output_buffer(&self, buffer: OutputBuffer) {
+        self.audio_player.set_output_buffer(buffer);
+    }
+
+    pub fn set_resampler_quality(&self, quality: ResamplerQuality) {
+        self.audio_player.set_resampler_quality(quality);
+    }
+
+    pub fn set_streaming_quality(&self, quality: StreamingQuality) {
+        self.audio_player.set_streaming_quality(quality);
+    }
+
+    /// Cap the 0.0-1.0 volume at `limit` and hold down loud peaks, or
+    /// neither with None
+    pub fn set_volume_limit(&self, limit: Option<f64>) {
+        self.audio_player.set_volume_limit(limit);
+        self.volume_limit.set(limit);
+        self.limit_warned.set(false);
+        self.check_volume_limit(self.volume_scale.value() / 100.0);
+    }
+
+    /// Level tracks by their ReplayGain tags, or play them as they are with
+    /// None
+    pub fn set_normalization(&self, normalization: Option<ClippingPrevention>) {
+        self.audio_player.set_normalization(normalization);
+    }
+
+    /// Point out once that `volume` is past the limit, until it's turned
+    /// back down below it
+    fn check_volume_limit(&self, volume: f64) {
+        let Some(limit) = self.volume_limit.get() else {
+            return;
+        };
+        if volume <= limit {
+            self.limit_warned.set(false);
+        } else if !self.limit_warned.replace(true) {
+            self.show_toast(&format!(
+                "Volume is capped at {}% to protect your hearing",
+                (limit * 100.0).round()
+            ));
+        }
+    }
+
+    /// Holding `button` seeks through the current track, forwards or
+    /// backwards, faster the longer it's held. Letting go plays on normally.
+    pub fn add_hold_to_seek(&self, button: &gtk::Button, forward: bool) {
+        let gesture = gtk::GestureLongPress::new();
+        gesture.set_propagation_phase(gtk::PropagationPhase::Capture);
+        let direction = if forward { 1.0 } else { -1.0 };
+
+        let player = self.clone();
+        gesture.connect_pressed(move |gesture, _, _| {
+            if !*player.is_playing.borrow() {
+                return;
+            }
+            // Claiming the press keeps the button from skipping as well
+            gesture.set_state(gtk::EventSequenceState::Claimed);
+            player.start_scrub(direction);
+        });
+        let player = self.clone();
+        gesture.connect_end(move |_, _| player.stop_scrub());
+        button.add_controller(gesture);
+    }
+
+    /// Whether seeking by holding next or previous plays the sped-up audio
+    /// or skips ahead silently
+    pub fn set_audible_seeking(&self, audible: bool) {
+        self.audible_scrub.set(audible);
+    }
+
+    fn start_scrub(&self, direction: f64) {
+        let audible = self.audible_scrub.get();
+        self.audio_player
+            .set_rate(direction * SCRUB_SPEEDS[0], audible);
+
+        let audio_player = self.audio_player.clone();
+        let mut faster = SCRUB_SPEEDS.into_iter().skip(1);
+        // Kept running at top speed, so letting go always has a source to remove
+        let source = glib::timeout_add_local(SCRUB_STEP, move || {
+            if let Some(speed) = faster.next() {
+                audio_player.set_rate(direction * speed, audible);
+            }
+            ControlFlow::Continue
+        });
+        if let Some(previous) = self.scrub_source.replace(Some(source)) {
+            previous.remove();
+        }
+    }
+
+    fn stop_scrub(&self) {
+        if let Some(source) = self.scrub_source.take() {
+            source.remove();
+            self.audio_player.set_rate(1.0, true);
+        }
+    }
+
+    pub fn set_restart_threshold(&self, threshold: Duration) {
+        self.audio_player.set_restart_threshold(threshold);
+    }
+
+    /// Shuffle the queue with `mode`, or restore its order with None
+    pub fn set_shuffle(&self, mode: Option<ShuffleMode>) {
+        self.audio_player.set_shuffle(mode);
+        self.update_queue_peek();
+    }
+
+    /// Go round to the start of the queue after its end, or stop there
+    pub fn set_repeat(&self, repeat: bool) {
+        self.audio_player.set_repeat(repeat);
+        self.update_queue_peek();
     }
 
     fn start_progress_updates(&self) {
@@ -185,15 +828,23 @@ impl Player {
         let current_time_label = self.current_time_label.clone();
         let total_time_label = self.total_time_label.clone();
         let is_playing = self.is_playing.clone();
+        let queue_remaining_label = self.queue_remaining_label.clone();
+        let queue_upcoming = self.queue_upcoming.clone();
+        let played = self.played.clone();
+        let handover = self.handover.clone();
+        let crossfade = self.crossfade.clone();
+        let transition = self.transition.clone();
         let weak_self = Rc::downgrade(&Rc::new(self.clone()));
+        let player = self.clone();
+        let mut stall = StallWatch::new();
 
         // Update position immediately before starting the timer
         if let Some(position) = audio_player.get_position() {
             if let Some(duration) = audio_player.get_duration() {
                 let progress = position.as_secs_f64() / duration.as_secs_f64() * 100.0;
                 progress_bar.set_value(progress);
-                current_time_label.set_text(&Self::format_duration(position));
-                total_time_label.set_text(&Self::format_duration(duration));
+                current_time_label.set_text(&format_duration(position.as_secs() as u32));
+                total_time_label.set_text(&format_duration(duration.as_secs() as u32));
             }
         }
 
@@ -206,14 +857,51 @@ impl Player {
                 return ControlFlow::Break;
             }
 
-            if let Some(position) = audio_player.get_position() {
+            let position = audio_player.get_position();
+            if let Some(at) = stall.check(position) {
+                if !player.recover_from_stall(at) {
+                    return ControlFlow::Break;
+                }
+            }
+
+            // The outgoing track is still the one heard most
+            if let Some(outgoing) = handover.borrow().as_ref() {
+                let position =
+                    (outgoing.position + outgoing.started.elapsed()).min(outgoing.duration);
+                let progress = position.as_secs_f64() / outgoing.duration.as_secs_f64() * 100.0;
+                progress_bar.set_value(progress);
+                current_time_label.set_text(&format_duration(position.as_secs() as u32));
+            }
+
+            if let Some(position) = position {
+                if let Some(played) = played.borrow_mut().as_mut() {
+                    played.tick(position);
+                }
                 if let Some(duration) = audio_player.get_duration() {
-                    let progress = position.as_secs_f64() / duration.as_secs_f64() * 100.0;
-                    progress_bar.set_value(progress);
-                    current_time_label.set_text(&Self::format_duration(position));
-                    total_time_label.set_text(&Self::format_duration(duration));
+                    if handover.borrow().is_none() {
+                        let progress = position.as_secs_f64() / duration.as_secs_f64() * 100.0;
+                        progress_bar.set_value(progress);
+                        current_time_label.set_text(&format_duration(position.as_secs() as u32));
+                        total_time_label.set_text(&format_duration(duration.as_secs() as u32));
+                    }
+                    Self::show_queue_remaining(
+                        &audio_player,
+                        &queue_remaining_label,
+                        queue_upcoming.get(),
+                    );
 
-                    if position >= duration {
+                    // A track set to stop early ends there
+                    let end = audio_player
+                        .trim_end()
+                        .map_or(duration, |end| end.min(duration));
+                    // With a crossfade the next track starts that long
+                    // before the end, when there is one
+                    let lead = transition
+                        .get()
+                        .or(crossfade.get())
+                        .filter(|_| !player.upcoming_entries(1).is_empty())
+                        .map_or(Duration::ZERO, |lead| lead.min(end / 2));
+                    if position + lead >= end {
                         if let Some(player) = weak_self.upgrade() {
                             // Clear the source ID first
                             player.progress_update_source_id.replace(None);
@@ -230,6 +918,39 @@ impl Player {
         self.progress_update_source_id.replace(Some(source_id));
     }
 
+    /// Start the current track again on a fresh pipeline from `position`
+    /// after playback stalled, or stop after MAX_STALL_RECOVERIES in one
+    /// track. Returns whether it's playing again.
+    fn recover_from_stall(&self, position: Duration) -> bool {
+        let Some(track) = self.audio_player.get_current_track() else {
+            return false;
+        };
+        // Stuck at the very end is the track ending short of its length
+        let ended = self
+            .audio_player
+            .get_duration()
+            .is_some_and(|duration| position + Duration::from_secs(1) >= duration);
+        if ended {
+            self.next();
+            return false;
+        }
+        let attempt = self.stall_recoveries.get() + 1;
+        self.stall_recoveries.set(attempt);
+        if attempt <= MAX_STALL_RECOVERIES {
+            eprintln!(
+                "Playback of {} stalled at {:?}, restarting the output",
+                track.title, position
+            );
+            match self.audio_player.restart_at(&track, position) {
+                Ok(()) => return true,
+                Err(e) => eprintln!("Failed to restart the output: {}", e),
+            }
+        }
+        self.set_playing(false);
+        self.show_toast("Playback stopped, the audio output isn't responding");
+        false
+    }
+
     fn stop_progress_updates(&self) {
         // Just clear the source ID and let it clean itself up
         self.progress_update_source_id.replace(None);
@@ -241,17 +962,29 @@ impl Player {
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Stop any existing progress updates before starting new track
         self.stop_progress_updates();
+        self.check_skipped(track);
+        self.finish_playthrough();
+        // Where the outgoing track is, for as long as it stays the louder
+        let outgoing = self.audio_player.get_position().zip(self.audio_player.get_duration());
         
         match self.audio_player.play(track) {
             Ok(_) => {
-                // Reset progress bar and time labels
-                self.progress_bar.set_value(0.0);
-                self.current_time_label.set_text("0:00");
-                self.total_time_label.set_text("0:00");
+                let delay = self.audio_player.handover_delay();
+                match outgoing.filter(|(_, duration)| !delay.is_zero() && !duration.is_zero()) {
+                    Some((position, duration)) => {
+                        self.hand_over_later(track, position, duration, delay)
+                    }
+                    None => self.hand_over(track),
+                }
                 
-                self.update_now_playing(track);
+                self.played.replace(Some(PlayedTime::new(track)));
+                self.stall_recoveries.set(0);
+                // Playing something else means the connection is no longer
+                // waited on
+                *self.paused_by_network.borrow_mut() = false;
                 // Start progress updates after everything is set up
                 self.set_playing(true);
+                self.track_started.emit(track);
                 Ok(())
             }
             Err(e) => {
@@ -265,6 +998,45 @@ impl Player {
         }
     }
 
+    /// Show `track` as playing, from its start
+    fn hand_over(&self, track: &Track) {
+        self.handover.replace(None);
+        self.progress_bar.set_value(0.0);
+        self.current_time_label.set_text("0:00");
+        self.total_time_label.set_text("0:00");
+        self.update_now_playing(track);
+    }
+
+    /// Keep showing the outgoing track, at `position` of `duration`, while
+    /// it fades out, and switch to `track` once that's the louder after
+    /// `delay`
+    fn hand_over_later(
+        &self,
+        track: &Track,
+        position: Duration,
+        duration: Duration,
+        delay: Duration,
+    ) {
+        let now = Instant::now();
+        let at = now + delay;
+        self.handover.replace(Some(Handover {
+            at,
+            position,
+            duration,
+            started: now,
+        }));
+
+        let player = self.clone();
+        let track = track.clone();
+        glib::timeout_add_local_once(delay, move || {
+            // A later track may have taken over in the meantime
+            let pending = player.handover.borrow().as_ref().is_some_and(|h| h.at == at);
+            if pending {
+                player.hand_over(&track);
+            }
+        });
+    }
+
     pub fn set_playing(&self, playing: bool) {
         *self.is_playing.borrow_mut() = playing;
         self.play_button.set_icon_name(if playing {
@@ -274,19 +1046,444 @@ impl Player {
             self.stop_progress_updates();
             "media-playback-start-symbolic"
         });
+        self.playing_changed.emit(playing);
     }
 
     pub fn is_playing(&self) -> bool {
         *self.is_playing.borrow()
     }
 
+    /// Skip explicit tracks when moving through the queue
+    pub fn set_skip_explicit(&self, skip: bool) {
+        *self.skip_explicit.borrow_mut() = skip;
+    }
+
+    /// Run `handler` every time a track successfully starts playing
+    pub fn connect_track_started<F: Fn(&Track) + 'static>(&self, handler: F) {
+        self.track_started.0.borrow_mut().push(Box::new(handler));
+    }
+
+    /// Run `handler` when a track is left for another one early on
+    pub fn connect_track_skipped<F: Fn(&Track) + 'static>(&self, handler: F) {
+        self.track_skipped.0.borrow_mut().push(Box::new(handler));
+    }
+
+    /// Run `handler` when a track stops playing, with how much of it played
+    pub fn connect_track_finished<F: Fn(&Playthrough) + 'static>(&self, handler: F) {
+        self.track_finished.0.borrow_mut().push(Box::new(handler));
+    }
+
+    /// Report how much of the current track played, now that it's over
+    fn finish_playthrough(&self) {
+        let Some(mut played) = self.played.take() else {
+            return;
+        };
+        if let Some(position) = self.audio_player.get_position() {
+            played.tick(position);
+        }
+        // When the next track follows on gaplessly, the last moments before
+        // it takes over fall after the last tick. A crossfade plays them
+        // out as it fades.
+        let tail = MAX_PLAYED_STEP.max(self.crossfade.get().unwrap_or_default());
+        if let Some(rest) = self.time_left().filter(|rest| *rest <= tail) {
+            played.played += rest;
+        }
+        self.track_finished.emit(&Playthrough {
+            track: played.track,
+            started_at: played.started_at,
+            played: played.played,
+        });
+    }
+
+    /// Time left until the current track ends, or stops early where it's
+    /// trimmed
+    fn time_left(&self) -> Option<Duration> {
+        let position = self.audio_player.get_position()?;
+        let duration = self.audio_player.get_duration()?;
+        let end = self
+            .audio_player
+            .trim_end()
+            .map_or(duration, |end| end.min(duration));
+        Some(end.saturating_sub(position))
+    }
+
+    fn near_end(&self) -> bool {
+        self.time_left().is_some_and(|rest| rest <= MAX_PLAYED_STEP)
+    }
+
+    /// Run `handler` whenever playback starts or stops
+    pub fn connect_playing_changed<F: Fn(bool) + 'static>(&self, handler: F) {
+        self.playing_changed.0.borrow_mut().push(Box::new(handler));
+    }
+
+    /// Run `handler` when the user moves where a track starts or stops
+    pub fn connect_trim_changed<F: Fn(&Track) + 'static>(&self, handler: F) {
+        self.trim_changed.0.borrow_mut().push(Box::new(handler));
+    }
+
+    /// Run `handler` when the queue or the place in it changes
+    pub fn connect_queue_changed<F: Fn() + 'static>(&self, handler: F) {
+        self.queue_changed.0.borrow_mut().push(Box::new(handler));
+    }
+
+    /// Report the current track as skipped if `next` replaces it before
+    /// SKIP_THRESHOLD of it has played
+    fn check_skipped(&self, next: &Track) {
+        let Some(current) = self.audio_player.get_current_track() else {
+            return;
+        };
+        if current.id == next.id {
+            return;
+        }
+
+        let (Some(position), Some(duration)) =
+            (self.audio_player.get_position(), self.audio_player.get_duration())
+        else {
+            return;
+        };
+        if !duration.is_zero() && position.as_secs_f64() / duration.as_secs_f64() < SKIP_THRESHOLD
+        {
+            self.track_skipped.emit(&current);
+        }
+    }
+
+    pub fn set_skip_rates(&self, skip_rates: HashMap<String, f32>) {
+        self.audio_player.set_skip_rates(skip_rates);
+    }
+
+    pub fn set_shuffle_exclusions(&self, exclusions: ShuffleExclusions) {
+        self.audio_player.set_shuffle_exclusions(exclusions);
+    }
+
+    pub fn subscribe_upcoming(&self) -> broadcast::Receiver<Vec<Track>> {
+        self.audio_player.subscribe_upcoming()
+    }
+
+    pub fn current_track(&self) -> Option<Track> {
+        self.audio_player.get_current_track()
+    }
+
+    /// Replace the queue without starting playback
+    pub fn load_queue(&self, items: Vec<PlayableItem>, context: QueueContext) {
+        self.audio_player
+            .load_queue(QueueEntry::with_context(items, &context));
+        self.update_queue_peek();
+    }
+
+    /// Up to `count` of the entries coming up after the current track
+    pub fn upcoming_entries(&self, count: usize) -> Vec<QueueEntry> {
+        self.audio_player.upcoming_entries(count)
+    }
+
+    fn update_queue_peek(&self) {
+        match self.audio_player.peek_next() {
+            Some(next) => self
+                .queue_peek_label
+                .set_text(&format!("Next: {} • {}", next.title, next.artist)),
+            None => self.queue_peek_label.set_text(""),
+        }
+
+        self.queue_upcoming.set(self.audio_player.upcoming_duration());
+        Self::show_queue_remaining(
+            &self.audio_player,
+            &self.queue_remaining_label,
+            self.queue_upcoming.get(),
+        );
+        self.queue_changed.emit();
+    }
+
+    /// Show how long the rest of the current track and the queue after it
+    /// take to play
+    fn show_queue_remaining(audio_player: &AudioPlayer, label: &gtk::Label, upcoming: u32) {
+        let current = match (audio_player.get_duration(), audio_player.get_position()) {
+            (Some(duration), Some(position)) => duration.saturating_sub(position).as_secs() as u32,
+            _ => 0,
+        };
+        let remaining = upcoming + current;
+        label.set_visible(remaining > 0);
+        label.set_text(&format_time_left(remaining));
+    }
+
+    /// Queue `items` to play once the album or playlist playing now is
+    /// over, or play them straight away when nothing is playing
+    pub fn play_after_current_unit(&self, items: Vec<PlayableItem>, context: QueueContext) {
+        if self.current_track().is_none() {
+            self.play_queue(items, context);
+            return;
+        }
+        self.audio_player
+            .queue_after_current_unit(QueueEntry::with_context(items, &context));
+        self.update_queue_peek();
+    }
+
+    /// Add `items` to the end of the queue, or play them straight away when
+    /// nothing is playing
+    pub fn enqueue_tracks(&self, items: Vec<PlayableItem>, context: QueueContext) {
+        if self.current_track().is_none() {
+            self.play_queue(items, context);
+            return;
+        }
+        self.audio_player
+            .enqueue_tracks(QueueEntry::with_context(items, &context));
+        self.update_queue_peek();
+    }
+
+    /// Replace the queue with `items` and start playing the first
+    pub fn play_queue(&self, items: Vec<PlayableItem>, context: QueueContext) {
+        self.set_playlist_playback(PlaylistPlayback::default());
+        self.audio_player
+            .load_queue(QueueEntry::with_context(items, &context));
+        self.next();
+    }
+
+    /// Replace the queue with `items` shuffled with `mode` and start playing
+    pub fn play_shuffled(
+        &self,
+        items: Vec<PlayableItem>,
+        context: QueueContext,
+        mode: ShuffleMode,
+    ) {
+        self.set_playlist_playback(PlaylistPlayback::default());
+        self.audio_player
+            .load_shuffled(QueueEntry::with_context(items, &context), mode);
+        self.next();
+    }
+
+    /// Replace the queue with a playlist's `items`, shuffled with `shuffle`
+    /// or in order with None, and play them the way `playback` says until
+    /// the queue is replaced again
+    pub fn play_playlist(
+        &self,
+        items: Vec<PlayableItem>,
+        context: QueueContext,
+        playback: PlaylistPlayback,
+        shuffle: Option<ShuffleMode>,
+    ) {
+        self.set_playlist_playback(playback);
+        let entries = QueueEntry::with_context(items, &context);
+        match shuffle {
+            Some(mode) => self.audio_player.load_shuffled(entries, mode),
+            None => {
+                self.audio_player.set_shuffle(None);
+                self.audio_player.load_queue(entries);
+            }
+        }
+        self.next();
+    }
+
+    /// Crossfade and level the way a playlist says, or the way the app's
+    /// settings do with the default
+    fn set_playlist_playback(&self, playback: PlaylistPlayback) {
+        self.crossfade.set(playback.crossfade);
+        self.audio_player
+            .set_crossfade(self.transition.get().or(playback.crossfade));
+        self.audio_player
+            .set_album_gain(playback.album_gain.unwrap_or(false));
+    }
+
+    /// Crossfade into the next track for `crossfade`, or as the playlist
+    /// says again with None
+    pub fn set_transition(&self, crossfade: Option<Duration>) {
+        self.transition.set(crossfade);
+        self.audio_player
+            .set_crossfade(crossfade.or(self.crossfade.get()));
+    }
+
+    /// Play the track with the ID given at the rate given times its speed
+    /// from when it starts, keeping its pitch, or every track as it is
+    /// with None
+    pub fn set_start_tempo(&self, tempo: Option<(String, f64)>) {
+        self.audio_player.set_start_rate(tempo);
+    }
+
+    /// Replace the queue with `items` and cue up the first one at
+    /// `position`, paused. It was playing before, so it doesn't count as
+    /// started again.
+    pub fn cue_queue(&self, items: Vec<PlayableItem>, context: QueueContext, position: Duration) {
+        self.set_playlist_playback(PlaylistPlayback::default());
+        self.audio_player
+            .load_queue(QueueEntry::with_context(items, &context));
+        let Some(track) = self.audio_player.next() else {
+            return;
+        };
+        if let Err(e) = self.audio_player.play(&track) {
+            eprintln!("Failed to cue {}: {}", track.title, e);
+            self.update_queue_peek();
+            return;
+        }
+        self.audio_player.pause();
+        self.audio_player.set_position(position);
+        self.update_now_playing(&track);
+        self.set_playing(false);
+    }
+
+    /// The current track and everything after it
+    pub fn remaining_queue(&self) -> Vec<PlayableItem> {
+        self.audio_player.remaining_queue()
+    }
+
+    pub fn position(&self) -> Option<Duration> {
+        self.audio_player.get_position()
+    }
+
+    /// Gain at each stage of the signal chain and the level coming out, or
+    /// None when nothing is loaded
+    pub fn gain_stages(&self) -> Option<GainStages> {
+        self.audio_player.gain_stages()
+    }
+
+    /// Have the level readings in `gain_stages` kept up to date, or not
+    pub fn set_metering(&self, metering: bool) {
+        self.audio_player.set_metering(metering);
+    }
+
+    /// What is left of the queue when it was left partway, that is when
+    /// anything comes after the current track
+    pub fn unfinished_queue(&self) -> Option<Vec<PlayableItem>> {
+        let remaining = self.audio_player.remaining_queue();
+        (remaining.len() > 1).then_some(remaining)
+    }
+
+    /// Stop playing from a provider that is going away and skip its tracks
+    /// in the rest of the queue
+    pub fn stop_provider(&self, provider: &str) {
+        if self.audio_player.drop_provider(provider) {
+            self.finish_playthrough();
+            self.audio_player.stop();
+            self.set_playing(false);
+            self.show_toast(&format!("Playback stopped, {} was turned off", provider));
+        }
+    }
+
+    pub fn pause(&self) {
+        self.audio_player.pause();
+        self.set_playing(false);
+    }
+
+    pub fn resume(&self) {
+        *self.paused_by_network.borrow_mut() = false;
+        self.audio_player.resume();
+        self.set_playing(true);
+    }
+
+    /// Pause remote playback that can't continue without a connection
+    pub fn handle_network_lost(&self) {
+        let is_remote = self
+            .audio_player
+            .get_current_track()
+            .map_or(false, |track| track.source.is_remote());
+
+        if is_remote && self.is_playing() {
+            self.pause();
+            *self.paused_by_network.borrow_mut() = true;
+            self.show_toast("Playback paused: network connection lost");
+        }
+    }
+
+    pub fn handle_network_restored(&self, resume: bool) {
+        if self.paused_by_network.replace(false) && resume {
+            self.resume();
+            self.show_toast("Connection restored, resuming playback");
+        }
+    }
+
+    /// Pause when the output the sound was going to goes away, rather than
+    /// carry on out of another one
+    pub fn handle_output_lost(&self, device: &str) {
+        if !self.is_playing() {
+            return;
+        }
+        let position = self.audio_player.get_position().unwrap_or_default();
+        self.pause();
+        *self.paused_by_output.borrow_mut() = Some((device.to_string(), position));
+        self.show_toast(&format!("Playback paused: {} disconnected", device));
+    }
+
+    /// Pick up playback paused by handle_output_lost once its output is
+    /// back, or another becomes the default
+    pub fn handle_output_added(&self, device: &str, is_default: bool, resume: bool) {
+        let returned = self
+            .paused_by_output
+            .borrow()
+            .as_ref()
+            .is_some_and(|(lost, _)| lost == device || is_default);
+        if !returned {
+            return;
+        }
+        let Some((_, position)) = self.paused_by_output.take() else {
+            return;
+        };
+        // Started again by hand meanwhile
+        if !resume || self.is_playing() {
+            return;
+        }
+
+        // The pipeline may have given up along with the output, in which
+        // case the track is started again where it was
+        if self.audio_player.get_position().is_none() {
+            let Some(track) = self.audio_player.get_current_track() else {
+                return;
+            };
+            if let Err(e) = self.audio_player.play(&track) {
+                eprintln!("Failed to resume {}: {}", track.title, e);
+                return;
+            }
+            self.audio_player.set_position(position);
+            self.set_playing(true);
+        } else {
+            self.resume();
+        }
+        self.show_toast(&format!("Resuming on {}", device));
+    }
+
     pub fn update_now_playing(&self, track: &Track) {
+        // A track changing mid-crossfade starts over from where it got to,
+        // rather than letting the older track land after this one
+        if let Some(running) = self.now_playing_fade.take() {
+            running.pause();
+        }
+
+        // Fade the old metadata out, swap it, then fade the new metadata in
+        let fade_out = adw::TimedAnimation::new(
+            &self.now_playing_box,
+            self.now_playing_box.opacity(),
+            0.0,
+            CROSSFADE_DURATION,
+            adw::PropertyAnimationTarget::new(&self.now_playing_box, "opacity"),
+        );
+
+        let player = self.clone();
+        let track = track.clone();
+        fade_out.connect_done(move |_| {
+            player.set_now_playing(&track);
+
+            let fade_in = adw::TimedAnimation::new(
+                &player.now_playing_box,
+                0.0,
+                1.0,
+                CROSSFADE_DURATION,
+                adw::PropertyAnimationTarget::new(&player.now_playing_box, "opacity"),
+            );
+            fade_in.play();
+            player.now_playing_fade.replace(Some(fade_in));
+        });
+        fade_out.play();
+        self.now_playing_fade.replace(Some(fade_out));
+    }
+
+    fn set_now_playing(&self, track: &Track) {
         self.current_song_label.set_text(&track.title);
         self.current_artist_label.set_text(&track.artist);
+        for marquee in [&self.title_marquee, &self.artist_marquee].into_iter().flatten() {
+            marquee.restart();
+        }
+
+        self.update_queue_peek();
+        self.show_marks();
 
         // Update album art
-        if let Some(data) = &track.artwork.thumbnail {
-            let bytes = glib::Bytes::from(data);
+        if let Some(thumbnails) = &track.artwork.thumbnails {
+            let bytes = glib::Bytes::from(thumbnails.for_size(96));
             let stream = gtk::gio::MemoryInputStream::from_bytes(&bytes);
             if let Ok(pixbuf) =
                 gdk_pixbuf::Pixbuf::from_stream(&stream, None::<&gtk::gio::Cancellable>)
@@ -307,18 +1504,64 @@ impl Player {
     }
 
     pub fn next(&self) {
-        if let Some(track) = self.audio_player.next() {
-            if let Err(e) = self.play_track(&track) {
-                println!("Error playing next track: {}", e);
-            }
-        }
+        self.play_from_queue(|audio_player| audio_player.next(), true);
     }
 
     pub fn previous(&self) {
-        if let Some(track) = self.audio_player.previous() {
-            if let Err(e) = self.play_track(&track) {
-                println!("Error playing previous track: {}", e);
+        // Going back from the start restarts the first track rather than
+        // ending anything
+        self.play_from_queue(|audio_player| audio_player.previous(), false);
+    }
+
+    /// Play the track `advance` picks from the queue, skipping over tracks
+    /// that fail to play until one works or the skip budget runs out. When
+    /// it picks none, the playthrough is over if `forward`.
+    fn play_from_queue(&self, advance: impl Fn(&AudioPlayer) -> Option<Track>, forward: bool) {
+        // Explicit tracks filtered out by the user don't use up the skip
+        // budget, but a full lap of the queue bounds the search
+        let mut failures = 0;
+        for _ in 0..=self.audio_player.get_queue().len() {
+            let track = match advance(&self.audio_player) {
+                Some(track) => track,
+                // Past the end of the queue the last track may still be
+                // going, or have just ended
+                None => {
+                    if forward && (!self.audio_player.is_playing() || self.near_end()) {
+                        self.finish_playthrough();
+                    }
+                    return;
+                }
+            };
+
+            if track.explicit && *self.skip_explicit.borrow() {
+                continue;
+            }
+
+            match self.play_track(&track) {
+                Ok(()) => return,
+                Err(e) => {
+                    eprintln!("Skipping unreadable track {}: {}", track.title, e);
+                    self.audio_player.mark_unavailable(&track.id);
+                    self.show_toast(&format!("Skipped “{}”: {}", track.title, e));
+
+                    failures += 1;
+                    if failures >= MAX_SKIP_ATTEMPTS {
+                        self.show_toast("Playback stopped after too many unreadable tracks");
+                        return;
+                    }
+                }
             }
         }
     }
+
+    fn show_toast(&self, message: &str) {
+        let toast = adw::Toast::new(message);
+        toast.set_timeout(3);
+        self.toast_overlay.add_toast(toast);
+    }
+}
+
+/// "1 hr 5 min left", rounded up to whole minutes
+fn format_time_left(seconds: u32) -> String {
+    gettext("{} left").replace("{}", &format_length(seconds.div_ceil(60) * 60))
 }
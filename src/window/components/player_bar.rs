@@ -0,0 +1,73 @@
+use crate::window::imp;
+use gtk::prelude::*;
+
+/// Arrangement of the playback controls at the bottom of the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerBarLayout {
+    /// Everything on a single row
+    Compact,
+    /// Larger artwork and a peek at the queue above the transport controls
+    Expanded,
+}
+
+impl PlayerBarLayout {
+    pub fn from_setting(value: &str) -> Self {
+        match value {
+            "expanded" => PlayerBarLayout::Expanded,
+            _ => PlayerBarLayout::Compact,
+        }
+    }
+}
+
+fn detach(widget: &gtk::Box) {
+    if let Some(parent) = widget.parent().and_downcast::<gtk::Box>() {
+        parent.remove(widget);
+    }
+}
+
+/// Rebuild the player bar around the shared now-playing, transport and
+/// volume sections so the Player keeps driving the same widgets.
+pub(crate) fn apply_player_bar_layout(this: &imp::NovaWindow, layout: PlayerBarLayout) {
+    let bar = this.playback_controls.get();
+    let now_playing = this.now_playing_box.get();
+    let buttons = this.playback_buttons_box.get();
+    let volume = this.volume_controls_box.get();
+
+    // Take the shared sections out, then drop whatever rows are left over
+    for section in [&now_playing, &buttons, &volume] {
+        detach(section);
+    }
+    while let Some(child) = bar.first_child() {
+        bar.remove(&child);
+    }
+
+    match layout {
+        PlayerBarLayout::Compact => {
+            bar.set_orientation(gtk::Orientation::Horizontal);
+            bar.remove_css_class("expanded");
+            now_playing.set_hexpand(false);
+            buttons.set_hexpand(false);
+            this.current_album_art.set_pixel_size(48);
+            this.queue_peek_label.set_visible(false);
+
+            bar.append(&now_playing);
+            bar.append(&buttons);
+            bar.append(&volume);
+        }
+        PlayerBarLayout::Expanded => {
+            bar.set_orientation(gtk::Orientation::Vertical);
+            bar.add_css_class("expanded");
+            now_playing.set_hexpand(true);
+            buttons.set_hexpand(true);
+            this.current_album_art.set_pixel_size(96);
+            this.queue_peek_label.set_visible(true);
+
+            let top_row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+            top_row.append(&now_playing);
+            top_row.append(&volume);
+
+            bar.append(&top_row);
+            bar.append(&buttons);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Marks on the playing track wherever it's listed: bars that bounce while
+//! it plays and a thin bar of how far it's got. Rows are kept by track ID
+//! as they're made, and the player's events move the mark between them.
+
+use crate::window::imp;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use gtk::prelude::*;
+use std::time::Duration;
+
+// How often the progress of the marked rows moves on
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+// Bars in the equalizer icon
+const EQUALIZER_BARS: usize = 3;
+
+/// A row listing a track, and the mark in it
+#[derive(Debug)]
+pub(crate) struct PlayingMark {
+    row: glib::WeakRef<gtk::Widget>,
+    mark: glib::WeakRef<gtk::Box>,
+}
+
+/// Make a mark for `row`, which lists the track `track_id`, for the caller
+/// to place in it. It only shows while that track is the current one.
+pub(crate) fn create_playing_mark(
+    this: &imp::NovaWindow,
+    row: &impl IsA<gtk::Widget>,
+    track_id: &str,
+) -> gtk::Box {
+    let mark = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(4)
+        .valign(gtk::Align::Center)
+        .visible(false)
+        .css_classes(["playing-mark"])
+        .build();
+    let equalizer = gtk::Box::builder()
+        .spacing(2)
+        .halign(gtk::Align::Center)
+        .height_request(16)
+        .build();
+    for _ in 0..EQUALIZER_BARS {
+        equalizer.append(
+            &gtk::Box::builder()
+                .valign(gtk::Align::End)
+                .css_classes(["equalizer-bar"])
+                .build(),
+        );
+    }
+    mark.append(&equalizer);
+    mark.append(
+        &gtk::ProgressBar::builder()
+            .width_request(32)
+            .css_classes(["playing-progress"])
+            .build(),
+    );
+
+    let mut marks = this.playing_marks.borrow_mut();
+    let rows = marks.entry(track_id.to_string()).or_default();
+    rows.retain(|entry| entry.row.upgrade().is_some());
+    rows.push(PlayingMark {
+        row: row.upcast_ref::<gtk::Widget>().downgrade(),
+        mark: mark.downgrade(),
+    });
+    drop(marks);
+
+    if current_track_id(this).as_deref() == Some(track_id) {
+        set_marked(row.upcast_ref(), &mark, true, is_playing(this));
+        update_progress(this);
+    }
+    mark
+}
+
+/// Follow the player, moving the mark to each track as it starts
+pub(crate) fn setup_playing_marks(this: &imp::NovaWindow) {
+    let Some(player) = this.player.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    player.connect_track_started(move |track| {
+        if let Some(obj) = obj_weak.upgrade() {
+            mark_track(obj.imp(), Some(&track.id));
+        }
+    });
+    let obj_weak = this.obj().downgrade();
+    player.connect_playing_changed(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            let this = obj.imp();
+            mark_track(this, current_track_id(this).as_deref());
+        }
+    });
+
+    let obj_weak = this.obj().downgrade();
+    glib::timeout_add_local(PROGRESS_INTERVAL, move || {
+        let Some(obj) = obj_weak.upgrade() else {
+            return glib::ControlFlow::Break;
+        };
+        if is_playing(obj.imp()) {
+            update_progress(obj.imp());
+        }
+        glib::ControlFlow::Continue
+    });
+}
+
+/// Show the mark on the rows of `track_id` only, dropping rows that are gone
+fn mark_track(this: &imp::NovaWindow, track_id: Option<&str>) {
+    let playing = is_playing(this);
+    let mut marks = this.playing_marks.borrow_mut();
+    marks.retain(|id, rows| {
+        rows.retain(|entry| {
+            let (Some(row), Some(mark)) = (entry.row.upgrade(), entry.mark.upgrade()) else {
+                return false;
+            };
+            set_marked(&row, &mark, Some(id.as_str()) == track_id, playing);
+            true
+        });
+        !rows.is_empty()
+    });
+    drop(marks);
+    update_progress(this);
+}
+
+fn set_marked(row: &gtk::Widget, mark: &gtk::Box, marked: bool, playing: bool) {
+    mark.set_visible(marked);
+    if marked {
+        row.add_css_class("playing");
+    } else {
+        row.remove_css_class("playing");
+    }
+    // The bars hold still while paused
+    if playing {
+        mark.remove_css_class("paused");
+    } else {
+        mark.add_css_class("paused");
+    }
+}
+
+/// Move the progress of the current track's rows to where it's playing
+fn update_progress(this: &imp::NovaWindow) {
+    let Some(player) = this.player.borrow().clone() else {
+        return;
+    };
+    let Some(track) = player.current_track() else {
+        return;
+    };
+    let fraction = match (player.position(), track.duration) {
+        (Some(position), length) if length > 0 => {
+            (position.as_secs_f64() / f64::from(length)).clamp(0.0, 1.0)
+        }
+        _ => 0.0,
+    };
+
+    let marks = this.playing_marks.borrow();
+    for entry in marks.get(&track.id).into_iter().flatten() {
+        if let Some(progress) = entry
+            .mark
+            .upgrade()
+            .and_then(|mark| mark.last_child())
+            .and_downcast::<gtk::ProgressBar>()
+        {
+            progress.set_fraction(fraction);
+        }
+    }
+}
+
+fn current_track_id(this: &imp::NovaWindow) -> Option<String> {
+    let player = this.player.borrow();
+    player.as_ref()?.current_track().map(|track| track.id)
+}
+
+fn is_playing(this: &imp::NovaWindow) -> bool {
+    this.player
+        .borrow()
+        .as_ref()
+        .is_some_and(|player| player.is_playing())
+}
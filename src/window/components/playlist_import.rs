@@ -0,0 +1,111 @@
+use crate::services::playlist_import::import_playlist;
+use crate::window::components::playlists::reload_playlists;
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::{gio, glib};
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Ask for an M3U playlist from another player and make a playlist of the
+/// tracks in it that are in the library
+pub(crate) fn choose_playlist_file(this: &imp::NovaWindow) {
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("M3U Playlists"));
+    for pattern in ["*.m3u", "*.m3u8", "*.M3U", "*.M3U8"] {
+        filter.add_pattern(pattern);
+    }
+    let filters = gio::ListStore::new::<gtk::FileFilter>();
+    filters.append(&filter);
+
+    let dialog = gtk::FileDialog::builder()
+        .title("Import Playlist")
+        .modal(true)
+        .filters(&filters)
+        .build();
+
+    let obj_weak = this.obj().downgrade();
+    dialog.open(
+        Some(&*this.obj()),
+        gio::Cancellable::NONE,
+        move |result| match result {
+            Ok(file) => {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return;
+                };
+                match file.path() {
+                    Some(path) => import_from(obj.imp(), path),
+                    None => obj.imp().show_toast("Choose a file on this device"),
+                }
+            }
+            Err(e) => {
+                if !e.matches(gtk::DialogError::Dismissed) {
+                    eprintln!("Failed to choose a playlist to import: {}", e);
+                }
+            }
+        },
+    );
+}
+
+fn import_from(this: &imp::NovaWindow, path: PathBuf) {
+    let (Some(manager), Some(store)) = (
+        this.service_manager.borrow().clone(),
+        this.user_store.borrow().clone(),
+    ) else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        // Matched against every track, whatever the library view hides
+        let tracks = match manager.get_library_tracks().await {
+            Ok(tracks) => tracks,
+            Err(e) => {
+                eprintln!("Failed to load tracks to match a playlist to: {}", e);
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.imp()
+                        .show_toast("Couldn't load the library to import into");
+                }
+                return;
+            }
+        };
+
+        let import_path = path.clone();
+        let result =
+            tokio::task::spawn_blocking(move || -> Result<_, Box<dyn Error + Send + Sync>> {
+                let imported = import_playlist(&import_path, &tracks)?;
+                if imported.track_ids.is_empty() {
+                    return Err("None of its tracks are in the library".into());
+                }
+                store.create_playlist(&imported.name, &imported.track_ids)?;
+                Ok(imported)
+            })
+            .await;
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        match result {
+            Ok(Ok(imported)) => {
+                reload_playlists(obj.imp());
+                let message = match imported.missing {
+                    0 => format!("Imported '{}'", imported.name),
+                    missing => format!(
+                        "Imported '{}' without {} tracks missing from the library",
+                        imported.name, missing
+                    ),
+                };
+                obj.imp().show_toast(&message);
+            }
+            Ok(Err(e)) => {
+                eprintln!("Failed to import the playlist {:?}: {}", path, e);
+                obj.imp()
+                    .show_toast(&format!("Couldn't import the playlist: {}", e));
+            }
+            Err(e) => {
+                eprintln!("Playlist import stopped: {}", e);
+                obj.imp().show_toast("Couldn't import the playlist");
+            }
+        }
+    });
+}
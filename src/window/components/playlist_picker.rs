@@ -0,0 +1,204 @@
+use crate::services::models::Track;
+use crate::window::components::playlists::{reload_playlists, show_playlist};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::{gdk, glib};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A playlist offered in the picker, and whether the tracks are to be in it
+struct Choice {
+    // None for a playlist made in the picker, created when it closes
+    playlist_id: Option<String>,
+    name: String,
+    // How many of the tracks it had when the picker opened
+    had: usize,
+    check: gtk::CheckButton,
+}
+
+/// Popover at `point` on `widget` to tick the playlists `tracks` are in,
+/// with a filter and an entry for a new playlist. Playlists with only some
+/// of them show as mixed until ticked or cleared. Changes are saved together
+/// once it closes.
+pub(crate) fn show_playlist_picker(
+    this: &imp::NovaWindow,
+    widget: &gtk::Widget,
+    point: gdk::Rectangle,
+    tracks: Vec<Track>,
+) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        this.show_toast("Playlists need the library database");
+        return;
+    };
+    let playlists = match store.playlists() {
+        Ok(playlists) => playlists,
+        Err(e) => {
+            eprintln!("Failed to load playlists: {}", e);
+            this.show_toast("Couldn't load your playlists");
+            return;
+        }
+    };
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    list.add_css_class("boxed-list");
+    let choices: Rc<RefCell<Vec<Choice>>> = Rc::default();
+    for playlist in playlists {
+        let had = tracks
+            .iter()
+            .filter(|track| playlist.track_ids.contains(&track.id))
+            .count();
+        let check = add_choice_row(&list, &playlist.name, had == tracks.len());
+        if had > 0 && had < tracks.len() {
+            check.set_inconsistent(true);
+            check.connect_toggled(|check| check.set_inconsistent(false));
+        }
+        choices.borrow_mut().push(Choice {
+            playlist_id: Some(playlist.id),
+            name: playlist.name,
+            had,
+            check,
+        });
+    }
+
+    let filter = gtk::SearchEntry::builder()
+        .placeholder_text("Filter Playlists")
+        .build();
+    let filter_clone = filter.clone();
+    list.set_filter_func(move |row| {
+        let query = filter_clone.text().to_lowercase();
+        row.child()
+            .and_downcast::<gtk::CheckButton>()
+            .and_then(|check| check.label())
+            .is_some_and(|name| name.to_lowercase().contains(query.trim()))
+    });
+    let list_clone = list.clone();
+    filter.connect_search_changed(move |_| list_clone.invalidate_filter());
+
+    // Typed names become playlists, ticked, when the picker closes
+    let new_entry = gtk::Entry::builder()
+        .placeholder_text("New Playlist…")
+        .secondary_icon_name("list-add-symbolic")
+        .build();
+    let list_clone = list.clone();
+    let choices_clone = choices.clone();
+    let add_new = move |entry: &gtk::Entry| {
+        let name = entry.text().trim().to_string();
+        let taken = choices_clone
+            .borrow()
+            .iter()
+            .any(|choice| choice.playlist_id.is_none() && choice.name == name);
+        if name.is_empty() || taken {
+            return;
+        }
+        let check = add_choice_row(&list_clone, &name, false);
+        check.set_active(true);
+        choices_clone.borrow_mut().push(Choice {
+            playlist_id: None,
+            name,
+            had: 0,
+            check,
+        });
+        entry.set_text("");
+    };
+    let add_new = Rc::new(add_new);
+    let add_new_clone = add_new.clone();
+    new_entry.connect_activate(move |entry| add_new_clone(entry));
+    new_entry.connect_icon_release(move |entry, _| add_new(entry));
+
+    let scroller = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(320)
+        .child(&list)
+        .build();
+    let content = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .width_request(260)
+        .build();
+    content.append(&filter);
+    content.append(&scroller);
+    content.append(&new_entry);
+
+    let popover = gtk::Popover::builder()
+        .child(&content)
+        .has_arrow(false)
+        .pointing_to(&point)
+        .build();
+    popover.set_parent(widget);
+
+    let obj_weak = this.obj().downgrade();
+    popover.connect_closed(move |popover| {
+        if let Some(obj) = obj_weak.upgrade() {
+            save_choices(obj.imp(), &tracks, &choices.borrow());
+        }
+        // Not a regular child, so it has to be detached by hand
+        let popover = popover.clone();
+        glib::idle_add_local_once(move || popover.unparent());
+    });
+
+    popover.popup();
+    filter.grab_focus();
+}
+
+fn add_choice_row(list: &gtk::ListBox, name: &str, active: bool) -> gtk::CheckButton {
+    let check = gtk::CheckButton::builder()
+        .label(name)
+        .active(active)
+        .build();
+    list.append(&check);
+    check
+}
+
+fn save_choices(this: &imp::NovaWindow, tracks: &[Track], choices: &[Choice]) {
+    let mut add = Vec::new();
+    let mut remove = Vec::new();
+    let mut create = Vec::new();
+    for choice in choices {
+        // Still mixed means left as it was
+        if choice.check.is_inconsistent() {
+            continue;
+        }
+        let active = choice.check.is_active();
+        match &choice.playlist_id {
+            None if active => create.push(choice.name.clone()),
+            Some(id) if active && choice.had < tracks.len() => add.push(id.clone()),
+            Some(id) if !active && choice.had > 0 => remove.push(id.clone()),
+            _ => {}
+        }
+    }
+    let changed = add.len() + remove.len() + create.len();
+    if changed == 0 {
+        return;
+    }
+
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+    let track_ids: Vec<String> = tracks.iter().map(|track| track.id.clone()).collect();
+    if let Err(e) = store.update_playlists(&track_ids, &add, &remove, &create) {
+        eprintln!("Failed to update playlists: {}", e);
+        this.show_toast("Couldn't update your playlists");
+        return;
+    }
+
+    reload_playlists(this);
+    // The open playlist page may show one of them
+    let open = this
+        .playlist_detail
+        .borrow()
+        .as_ref()
+        .map(|playlist| playlist.id.clone());
+    if let Some(open) = open.filter(|id| add.contains(id) || remove.contains(id)) {
+        if this.main_stack.visible_child_name().as_deref() == Some("playlist-detail") {
+            show_playlist(this, &open);
+        }
+    }
+    this.show_toast(&match changed {
+        1 => "Updated 1 playlist".to_string(),
+        n => format!("Updated {} playlists", n),
+    });
+}
@@ -0,0 +1,114 @@
+use crate::services::models::PlaylistPlayback;
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use std::time::Duration;
+
+// Longest crossfade offered, in seconds
+const MAX_CROSSFADE: f64 = 12.0;
+
+/// Choices for shuffle, in the order of the combo row
+const SHUFFLE_CHOICES: [(&str, Option<bool>); 3] = [
+    ("Leave As Is", None),
+    ("On", Some(true)),
+    ("Off", Some(false)),
+];
+
+/// Edit how the open playlist plays. The settings apply each time it's
+/// played, until something else replaces the queue.
+pub(crate) fn show_playlist_playback(this: &imp::NovaWindow) {
+    let Some(playlist) = this.playlist_detail.borrow().clone() else {
+        return;
+    };
+    let playback = playlist.playback;
+
+    let shuffle_row = adw::ComboRow::builder()
+        .title("Shuffle")
+        .model(&gtk::StringList::new(
+            &SHUFFLE_CHOICES.map(|(label, _)| label),
+        ))
+        .build();
+    let selected = SHUFFLE_CHOICES
+        .iter()
+        .position(|(_, shuffle)| *shuffle == playback.shuffle)
+        .unwrap_or(0);
+    shuffle_row.set_selected(selected as u32);
+
+    let crossfade_row = adw::SpinRow::builder()
+        .title("Crossfade")
+        .subtitle("Seconds each track fades into the next; 0 uses the fade duration")
+        .adjustment(&gtk::Adjustment::new(
+            playback
+                .crossfade
+                .map_or(0.0, |crossfade| crossfade.as_secs_f64()),
+            0.0,
+            MAX_CROSSFADE,
+            1.0,
+            2.0,
+            0.0,
+        ))
+        .build();
+
+    let album_gain_row = adw::SwitchRow::builder()
+        .title("Album Gain")
+        .subtitle("Level whole albums, keeping the differences between their tracks")
+        .active(playback.album_gain.unwrap_or(false))
+        .build();
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&shuffle_row);
+    list.append(&crossfade_row);
+    list.append(&album_gain_row);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Playback Settings")
+        .body(format!(
+            "How “{}” plays when you play it from its page",
+            playlist.name
+        ))
+        .extra_child(&list)
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("save", "_Save")]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("save"), move |_, _| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let crossfade = crossfade_row.value();
+        let playback = PlaylistPlayback {
+            shuffle: SHUFFLE_CHOICES
+                .get(shuffle_row.selected() as usize)
+                .and_then(|(_, shuffle)| *shuffle),
+            crossfade: (crossfade > 0.0).then(|| Duration::from_secs_f64(crossfade)),
+            album_gain: album_gain_row.is_active().then_some(true),
+        };
+        save_playback(obj.imp(), &playlist.id, playback);
+    });
+    dialog.present(Some(&*this.obj()));
+}
+
+fn save_playback(this: &imp::NovaWindow, playlist_id: &str, playback: PlaylistPlayback) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+    if let Err(e) = store.set_playlist_playback(playlist_id, &playback) {
+        eprintln!(
+            "Failed to save playback settings for {}: {}",
+            playlist_id, e
+        );
+        this.show_toast("Couldn't save the playback settings");
+        return;
+    }
+    if let Some(playlist) = this.playlist_detail.borrow_mut().as_mut() {
+        if playlist.id == playlist_id {
+            playlist.playback = playback;
+        }
+    }
+    this.show_toast("Playback settings saved");
+}
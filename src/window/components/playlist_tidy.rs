@@ -0,0 +1,252 @@
+use crate::services::models::{KnownTrack, PlayableItem, Track};
+use crate::window::components::playlists::{apply_edit, PlaylistEntry};
+use crate::window::imp;
+use crate::window::utils::format::format_count_of;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use std::collections::{HashMap, HashSet};
+
+// Results looked through for each missing track
+const SEARCH_LIMIT: usize = 20;
+
+// How far a track's length may be off to still count as the one that moved,
+// allowing for a re-encode or a trimmed silence
+const DURATION_TOLERANCE: u32 = 5;
+
+/// Offer to take duplicates and missing tracks out of the open playlist,
+/// and to find tracks whose files moved
+pub(crate) fn show_playlist_tidy(this: &imp::NovaWindow) {
+    let Some(playlist) = this.playlist_detail.borrow().clone() else {
+        return;
+    };
+    let entries = this.playlist_entries.borrow().clone();
+    let duplicates = tidy_entries(&entries, &HashMap::new(), true, false).duplicates;
+    let missing = entries.iter().filter(|entry| entry.track.is_none()).count();
+    if duplicates == 0 && missing == 0 {
+        this.show_toast("Nothing to tidy up in this playlist");
+        return;
+    }
+
+    let duplicates_row = adw::SwitchRow::builder()
+        .title("Remove Duplicates")
+        .subtitle(format_count_of(
+            duplicates,
+            "{} extra copy of a song",
+            "{} extra copies of songs",
+        ))
+        .active(duplicates > 0)
+        .sensitive(duplicates > 0)
+        .build();
+    let relink_row = adw::SwitchRow::builder()
+        .title("Find Moved Songs")
+        .subtitle("Look for missing songs in your library by title and artist")
+        .active(missing > 0)
+        .sensitive(missing > 0)
+        .build();
+    let missing_row = adw::SwitchRow::builder()
+        .title("Remove Missing Songs")
+        .subtitle(format_count_of(
+            missing,
+            "{} song is no longer in your library",
+            "{} songs are no longer in your library",
+        ))
+        .sensitive(missing > 0)
+        .build();
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&duplicates_row);
+    list.append(&relink_row);
+    list.append(&missing_row);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Tidy Up Playlist")
+        .body(format!("Choose what to fix in “{}”", playlist.name))
+        .extra_child(&list)
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("tidy", "_Tidy Up")]);
+    dialog.set_response_appearance("tidy", adw::ResponseAppearance::Suggested);
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("tidy"), move |_, _| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let (dedupe, remove_missing) = (duplicates_row.is_active(), missing_row.is_active());
+        if !relink_row.is_active() {
+            finish_tidy(
+                obj.imp(),
+                &playlist.id,
+                HashMap::new(),
+                dedupe,
+                remove_missing,
+            );
+            return;
+        }
+
+        let missing_ids: Vec<String> = entries
+            .iter()
+            .filter(|entry| entry.track.is_none())
+            .map(|entry| entry.track_id.clone())
+            .collect();
+        let playlist_id = playlist.id.clone();
+        let obj_weak = obj.downgrade();
+        glib::MainContext::default().spawn_local(async move {
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            let moved = find_moved(obj.imp(), &missing_ids).await;
+            finish_tidy(obj.imp(), &playlist_id, moved, dedupe, remove_missing);
+        });
+    });
+    dialog.present(Some(&*this.obj()));
+}
+
+/// The tracks now in the library that the missing `track_ids` moved to,
+/// matched by what was last known of them
+async fn find_moved(
+    this: &imp::NovaWindow,
+    track_ids: &[String],
+) -> HashMap<String, PlayableItem> {
+    let (Some(store), Some(manager)) = (
+        this.user_store.borrow().clone(),
+        this.service_manager.borrow().clone(),
+    ) else {
+        return HashMap::new();
+    };
+    let known = match store.known_tracks(track_ids) {
+        Ok(known) => known,
+        Err(e) => {
+            eprintln!("Failed to look up missing playlist tracks: {}", e);
+            return HashMap::new();
+        }
+    };
+
+    let mut moved = HashMap::new();
+    for (track_id, track) in known {
+        let query = format!("{} {}", track.artist, track.title);
+        let found = match manager.search_tracks(&query, None, SEARCH_LIMIT).await {
+            Ok(found) => found,
+            Err(e) => {
+                eprintln!("Failed to search for “{}”: {}", track.title, e);
+                continue;
+            }
+        };
+        if let Some(item) = found
+            .into_iter()
+            .find(|item| is_same_track(&track, &item.track))
+        {
+            moved.insert(track_id, item);
+        }
+    }
+    moved
+}
+
+fn is_same_track(known: &KnownTrack, track: &Track) -> bool {
+    let same = |a: &str, b: &str| a.trim().to_lowercase() == b.trim().to_lowercase();
+    same(&known.title, &track.title)
+        && same(&known.artist, &track.artist)
+        // Some providers don't know lengths, which can't rule a track out
+        && (known.duration == 0
+            || track.duration == 0
+            || known.duration.abs_diff(track.duration) <= DURATION_TOLERANCE)
+}
+
+/// What tidying up changed
+struct Tidied {
+    entries: Vec<PlaylistEntry>,
+    duplicates: usize,
+    missing: usize,
+    moved: usize,
+}
+
+/// `entries` with the tracks in `moved` put in place of the missing ones
+/// they replace, then taking out later copies of a track if `dedupe` and
+/// tracks still missing if `remove_missing`
+fn tidy_entries(
+    entries: &[PlaylistEntry],
+    moved: &HashMap<String, PlayableItem>,
+    dedupe: bool,
+    remove_missing: bool,
+) -> Tidied {
+    let mut tidied = Tidied {
+        entries: Vec::with_capacity(entries.len()),
+        duplicates: 0,
+        missing: 0,
+        moved: 0,
+    };
+    let mut seen = HashSet::new();
+    for entry in entries {
+        let entry = match (&entry.track, moved.get(&entry.track_id)) {
+            (None, Some(item)) => {
+                tidied.moved += 1;
+                PlaylistEntry {
+                    track_id: item.track.id.clone(),
+                    track: Some(item.track.clone()),
+                    provider: item.provider.clone(),
+                }
+            }
+            _ => entry.clone(),
+        };
+        if entry.track.is_none() && remove_missing {
+            tidied.missing += 1;
+        } else if !seen.insert(entry.track_id.clone()) && dedupe {
+            tidied.duplicates += 1;
+        } else {
+            tidied.entries.push(entry);
+        }
+    }
+    tidied
+}
+
+fn finish_tidy(
+    this: &imp::NovaWindow,
+    playlist_id: &str,
+    moved: HashMap<String, PlayableItem>,
+    dedupe: bool,
+    remove_missing: bool,
+) {
+    // The playlist may have been closed or edited while tracks were looked for
+    let open = this
+        .playlist_detail
+        .borrow()
+        .as_ref()
+        .is_some_and(|playlist| playlist.id == playlist_id);
+    if !open {
+        return;
+    }
+    let entries = this.playlist_entries.borrow().clone();
+    let tidied = tidy_entries(&entries, &moved, dedupe, remove_missing);
+
+    let mut changes = Vec::new();
+    if tidied.moved > 0 {
+        changes.push(format_count_of(
+            tidied.moved,
+            "Found {} moved song",
+            "Found {} moved songs",
+        ));
+    }
+    if tidied.duplicates > 0 {
+        changes.push(format_count_of(
+            tidied.duplicates,
+            "Removed {} duplicate",
+            "Removed {} duplicates",
+        ));
+    }
+    if tidied.missing > 0 {
+        changes.push(format_count_of(
+            tidied.missing,
+            "Removed {} missing song",
+            "Removed {} missing songs",
+        ));
+    }
+    if changes.is_empty() {
+        this.show_toast("Nothing was changed");
+        return;
+    }
+    apply_edit(this, tidied.entries, &changes.join(" • "));
+}
@@ -0,0 +1,1334 @@
+use crate::library_folders::{folder_playlists, library_folders};
+use crate::services::mix::fit_to_duration;
+use crate::services::models::{
+    Availability, LibraryChange, PinnedItem, PinnedKind, PlayableItem, Playlist, QueueContext,
+    ShuffleExclusions, Track,
+};
+use crate::window::components::details::{attach_card_menu, CardAction};
+use crate::window::components::navigation::show_page;
+use crate::window::components::playing_marks::create_playing_mark;
+use crate::window::components::playlist_picker::show_playlist_picker;
+use crate::window::components::playlist_playback::show_playlist_playback;
+use crate::window::components::playlist_tidy::show_playlist_tidy;
+use crate::window::components::sidebar::{is_pinned, reload_pinned_items, toggle_pin};
+use crate::window::imp;
+use crate::window::utils::format::{format_count_of, format_duration, format_length};
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use chrono::Utc;
+use gtk::{gdk, glib};
+use std::collections::{BTreeMap, HashSet};
+
+// Playlists and their folders live in the user store rather than with a
+// provider
+const USER_STORE_PROVIDER: &str = "user";
+
+/// A playlist track, with the library track it was found as if it still
+/// exists
+#[derive(Debug, Clone)]
+pub(crate) struct PlaylistEntry {
+    pub track_id: String,
+    pub track: Option<Track>,
+    // Where the track was found, empty when it wasn't
+    pub provider: String,
+}
+
+/// Refill the Playlists page from the user store
+pub(crate) fn reload_playlists(this: &imp::NovaWindow) {
+    let playlists = match this.user_store.borrow().as_ref() {
+        Some(store) => store.playlists().unwrap_or_else(|e| {
+            eprintln!("Failed to load playlists: {}", e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+
+    let list = &this.playlists_list;
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+
+    // Folders first, by name, then the playlists outside any folder
+    let mut folders: BTreeMap<&str, Vec<&Playlist>> = BTreeMap::new();
+    for playlist in &playlists {
+        if let Some(group) = &playlist.group {
+            folders.entry(group).or_default().push(playlist);
+        }
+    }
+    for (name, members) in folders {
+        list.append(&create_folder_row(this, name, &members));
+    }
+    for playlist in playlists.iter().filter(|playlist| playlist.group.is_none()) {
+        list.append(&create_stored_playlist_row(this, playlist));
+    }
+    list.set_visible(!playlists.is_empty());
+
+    update_playlists_stack(this);
+    reload_folder_playlists(this);
+}
+
+/// Refill the folder playlists from the library, when they are turned on
+fn reload_folder_playlists(this: &imp::NovaWindow) {
+    let settings = this.settings();
+    let manager = this.service_manager.borrow().clone();
+    let Some(manager) = manager.filter(|_| settings.boolean("folder-playlists")) else {
+        clear_folder_playlists(this);
+        return;
+    };
+
+    let roots = library_folders(&settings);
+    let depth = settings.int("folder-playlist-depth").max(1) as usize;
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let tracks = match manager.get_all_tracks().await {
+            Ok(tracks) => tracks,
+            Err(e) => {
+                eprintln!("Failed to load tracks for folder playlists: {}", e);
+                Vec::new()
+            }
+        };
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+
+        clear_folder_playlists(this);
+        for (playlist, tracks) in folder_playlists(&roots, &tracks, depth) {
+            let entries: Vec<PlaylistEntry> = tracks
+                .into_iter()
+                .map(|track| PlaylistEntry {
+                    track_id: track.id.clone(),
+                    track: Some(track),
+                    // Folders only hold local files
+                    provider: "local".to_string(),
+                })
+                .collect();
+            let page = playlist.clone();
+            let row = create_playlist_row(this, &playlist, Vec::new(), move |this| {
+                open_playlist_page(this, page.clone(), entries.clone())
+            });
+            row.add_prefix(&gtk::Image::from_icon_name("folder-music-symbolic"));
+            this.folder_playlists_list.append(&row);
+        }
+        this.folder_playlists_box
+            .set_visible(this.folder_playlists_list.first_child().is_some());
+        update_playlists_stack(this);
+    });
+}
+
+/// Bring the playlists in line with `change`, or with the whole library
+/// when it isn't known. Folder playlists are made again when tracks come
+/// or go, and the open playlist looks the tracks it holds up again.
+pub(crate) fn library_changed(this: &imp::NovaWindow, change: Option<&LibraryChange>) {
+    if change.map_or(true, |change| {
+        !change.added.is_empty() || !change.removed.is_empty()
+    }) {
+        reload_folder_playlists(this);
+    }
+
+    let (Some(playlist), Some(manager)) = (
+        this.playlist_detail.borrow().clone(),
+        this.service_manager.borrow().clone(),
+    ) else {
+        return;
+    };
+    let changed: Option<HashSet<String>> = change.map(|change| change.ids().cloned().collect());
+    let mut entries = this.playlist_entries.borrow().clone();
+    let count = entries.len();
+    let touched = move |id: &String| changed.as_ref().map_or(true, |ids| ids.contains(id));
+    if !entries.iter().any(|entry| touched(&entry.track_id)) {
+        return;
+    }
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        for entry in &mut entries {
+            if !touched(&entry.track_id) {
+                continue;
+            }
+            let item = manager.get_track(&entry.track_id).await.ok();
+            (entry.track, entry.provider) = match item {
+                Some(item) => (Some(item.track), item.provider),
+                None => (None, String::new()),
+            };
+        }
+        // Folder playlists only hold the files still there
+        if playlist.folder.is_some() {
+            entries.retain(|entry| entry.track.is_some());
+        }
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        let still_open = this
+            .playlist_detail
+            .borrow()
+            .as_ref()
+            .is_some_and(|open| open.id == playlist.id);
+        // Left alone if the playlist was edited or left in the meantime
+        if still_open && this.playlist_entries.borrow().len() == count {
+            this.playlist_entries.replace(entries);
+            populate_playlist_page(this);
+        }
+    });
+}
+
+fn clear_folder_playlists(this: &imp::NovaWindow) {
+    let list = &this.folder_playlists_list;
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+    this.folder_playlists_box.set_visible(false);
+    update_playlists_stack(this);
+}
+
+fn update_playlists_stack(this: &imp::NovaWindow) {
+    let empty = this.playlists_list.first_child().is_none()
+        && this.folder_playlists_list.first_child().is_none();
+    this.playlists_stack
+        .set_visible_child_name(if empty { "placeholder" } else { "content" });
+}
+
+/// Expandable row for the playlist folder `name`, holding `playlists`
+fn create_folder_row(
+    this: &imp::NovaWindow,
+    name: &str,
+    playlists: &[&Playlist],
+) -> adw::ExpanderRow {
+    let row = adw::ExpanderRow::builder()
+        .title(glib::markup_escape_text(name))
+        .subtitle(match playlists.len() {
+            1 => "1 playlist".to_string(),
+            n => format!("{} playlists", n),
+        })
+        .expanded(this.expanded_playlist_folders.borrow().contains(name))
+        .build();
+    row.add_prefix(&gtk::Image::from_icon_name("folder-symbolic"));
+    for playlist in playlists {
+        row.add_row(&create_stored_playlist_row(this, playlist));
+    }
+
+    let obj_weak = this.obj().downgrade();
+    let folder = name.to_string();
+    row.connect_expanded_notify(move |row| {
+        if let Some(obj) = obj_weak.upgrade() {
+            let mut expanded = obj.imp().expanded_playlist_folders.borrow_mut();
+            if row.is_expanded() {
+                expanded.insert(folder.clone());
+            } else {
+                expanded.remove(&folder);
+            }
+        }
+    });
+    add_folder_drop_target(this, &row, Some(name.to_string()));
+
+    let pin = PinnedItem {
+        kind: PinnedKind::PlaylistFolder,
+        provider: USER_STORE_PROVIDER.to_string(),
+        item_id: name.to_string(),
+        title: name.to_string(),
+        subtitle: None,
+    };
+    let pin_clone = pin.clone();
+    attach_card_menu(
+        &row,
+        vec![CardAction::dynamic(
+            move |this| {
+                if is_pinned(this, &pin_clone) {
+                    "Unpin from Sidebar"
+                } else {
+                    "Pin to Sidebar"
+                }
+            },
+            move |this| toggle_pin(this, &pin),
+        )],
+    );
+
+    row
+}
+
+/// Row for a playlist from the user store, which can be dragged into a
+/// folder or moved to one from its menu
+fn create_stored_playlist_row(this: &imp::NovaWindow, playlist: &Playlist) -> adw::ActionRow {
+    let playlist_id = playlist.id.clone();
+    let group = playlist.group.clone();
+    let move_action = CardAction::new("Move to Folder…", move |this| {
+        show_move_dialog(this, playlist_id.clone(), group.clone())
+    });
+    let pin = PinnedItem {
+        kind: PinnedKind::Playlist,
+        provider: USER_STORE_PROVIDER.to_string(),
+        item_id: playlist.id.clone(),
+        title: playlist.name.clone(),
+        subtitle: None,
+    };
+    let pin_clone = pin.clone();
+    let pin_action = CardAction::dynamic(
+        move |this| {
+            if is_pinned(this, &pin_clone) {
+                "Unpin from Sidebar"
+            } else {
+                "Pin to Sidebar"
+            }
+        },
+        move |this| toggle_pin(this, &pin),
+    );
+    let mut actions = vec![move_action, pin_action];
+    actions.extend(playlist_actions(&playlist.id));
+    let playlist_id = playlist.id.clone();
+    let row = create_playlist_row(this, playlist, actions, move |this| {
+        show_playlist(this, &playlist_id)
+    });
+
+    let drag_source = gtk::DragSource::new();
+    drag_source.set_actions(gdk::DragAction::MOVE);
+    let playlist_id = playlist.id.clone();
+    drag_source.connect_prepare(move |_, _, _| {
+        Some(gdk::ContentProvider::for_value(&playlist_id.to_value()))
+    });
+    let row_weak = row.downgrade();
+    drag_source.connect_drag_begin(move |source, _| {
+        if let Some(row) = row_weak.upgrade() {
+            source.set_icon(Some(&gtk::WidgetPaintable::new(Some(&row))), 0, 0);
+        }
+    });
+    row.add_controller(drag_source);
+
+    // Dropping on a playlist files the dragged one alongside it
+    add_folder_drop_target(this, &row, playlist.group.clone());
+    row
+}
+
+/// Let playlists be dropped on `widget` to move them to the folder `group`,
+/// or out of their folder when that's None
+fn add_folder_drop_target(
+    this: &imp::NovaWindow,
+    widget: &impl IsA<gtk::Widget>,
+    group: Option<String>,
+) {
+    let drop_target = gtk::DropTarget::new(String::static_type(), gdk::DragAction::MOVE);
+    let obj_weak = this.obj().downgrade();
+    drop_target.connect_drop(move |_, value, _, _| {
+        let (Some(obj), Ok(playlist_id)) = (obj_weak.upgrade(), value.get::<String>()) else {
+            return false;
+        };
+        let group = group.clone();
+        // Reloading replaces the rows, so wait until the drop has finished
+        glib::idle_add_local_once(move || {
+            move_to_folder(obj.imp(), &playlist_id, group.as_deref())
+        });
+        true
+    });
+    widget.add_controller(drop_target);
+}
+
+fn show_move_dialog(this: &imp::NovaWindow, playlist_id: String, current: Option<String>) {
+    let entry = adw::EntryRow::builder()
+        .title("Folder")
+        .text(current.unwrap_or_default())
+        .activates_default(true)
+        .build();
+    let rows = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    rows.add_css_class("boxed-list");
+    rows.append(&entry);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Move to Folder")
+        .body(
+            "Playlists with the same folder are shown together. Leave it empty to take the \
+             playlist out of its folder.",
+        )
+        .extra_child(&rows)
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("move", "_Move")]);
+    dialog.set_response_appearance("move", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("move"));
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("move"), move |_, _| {
+        if let Some(obj) = obj_weak.upgrade() {
+            let text = entry.text();
+            let group = Some(text.trim()).filter(|group| !group.is_empty());
+            move_to_folder(obj.imp(), &playlist_id, group);
+        }
+    });
+
+    dialog.present(Some(&*this.obj()));
+}
+
+fn move_to_folder(this: &imp::NovaWindow, playlist_id: &str, group: Option<&str>) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+    if let Err(e) = store.set_playlist_group(playlist_id, group) {
+        eprintln!("Failed to move playlist {}: {}", playlist_id, e);
+        this.show_toast("Couldn't move the playlist");
+        return;
+    }
+
+    // Show the playlist where it went
+    if let Some(group) = group {
+        this.expanded_playlist_folders
+            .borrow_mut()
+            .insert(group.to_string());
+    }
+    reload_playlists(this);
+}
+
+/// Renaming, copying and deleting the stored playlist `playlist_id`, for
+/// its menus on the Playlists page and in the sidebar
+pub(crate) fn playlist_actions(playlist_id: &str) -> Vec<CardAction> {
+    let rename_id = playlist_id.to_string();
+    let duplicate_id = playlist_id.to_string();
+    let delete_id = playlist_id.to_string();
+    vec![
+        CardAction::new("Rename…", move |this| {
+            prompt_rename_playlist(this, &rename_id)
+        }),
+        CardAction::new("Duplicate", move |this| {
+            duplicate_playlist(this, &duplicate_id)
+        }),
+        CardAction::new("Delete…", move |this| {
+            confirm_delete_playlist(this, &delete_id)
+        }),
+    ]
+}
+
+/// Names of the stored playlists, leaving out `except`
+fn playlist_names(this: &imp::NovaWindow, except: Option<&str>) -> HashSet<String> {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return HashSet::new();
+    };
+    store
+        .playlists()
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load playlists: {}", e);
+            Vec::new()
+        })
+        .into_iter()
+        .filter(|playlist| Some(playlist.id.as_str()) != except)
+        .map(|playlist| playlist.name)
+        .collect()
+}
+
+/// Ask for a new name for a playlist. Names other playlists have are
+/// turned down, so two can't be mixed up.
+fn prompt_rename_playlist(this: &imp::NovaWindow, playlist_id: &str) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+    let playlist = match store.playlist(playlist_id) {
+        Ok(playlist) => playlist,
+        Err(e) => {
+            eprintln!("Failed to load playlist {}: {}", playlist_id, e);
+            this.show_toast("Couldn't find the playlist");
+            return;
+        }
+    };
+    let taken = playlist_names(this, Some(playlist_id));
+
+    let name_row = adw::EntryRow::builder()
+        .title("Name")
+        .text(&playlist.name)
+        .activates_default(true)
+        .build();
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&name_row);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Rename Playlist")
+        .extra_child(&list)
+        .default_response("rename")
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("rename", "_Rename")]);
+    dialog.set_response_appearance("rename", adw::ResponseAppearance::Suggested);
+    dialog.set_response_enabled("rename", false);
+    dialog.set_close_response("cancel");
+    let dialog_weak = dialog.downgrade();
+    let current = playlist.name.clone();
+    name_row.connect_changed(move |row| {
+        let text = row.text();
+        let name = text.trim();
+        let conflict = taken.contains(name);
+        if conflict {
+            row.add_css_class("error");
+        } else {
+            row.remove_css_class("error");
+        }
+        if let Some(dialog) = dialog_weak.upgrade() {
+            let enabled = !name.is_empty() && name != current && !conflict;
+            dialog.set_response_enabled("rename", enabled);
+        }
+    });
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("rename"), move |_, _| {
+        if let Some(obj) = obj_weak.upgrade() {
+            let name = name_row.text().trim().to_string();
+            rename_with_undo(obj.imp(), playlist.id.clone(), playlist.name.clone(), &name);
+        }
+    });
+    dialog.present(Some(&*this.obj()));
+}
+
+/// The first of `base`, "`base` 2", "`base` 3" and so on that no playlist
+/// in `taken` has
+fn unused_name(taken: &HashSet<String>, base: &str) -> String {
+    if !taken.contains(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{} {}", base, n))
+        .find(|name| !taken.contains(name))
+        .unwrap_or_else(|| base.to_string())
+}
+
+/// Make a copy of a playlist beside it, under a name of its own
+fn duplicate_playlist(this: &imp::NovaWindow, playlist_id: &str) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+    let playlist = match store.playlist(playlist_id) {
+        Ok(playlist) => playlist,
+        Err(e) => {
+            eprintln!("Failed to load playlist {}: {}", playlist_id, e);
+            this.show_toast("Couldn't find the playlist");
+            return;
+        }
+    };
+    let name = unused_name(
+        &playlist_names(this, None),
+        &format!("{} (Copy)", playlist.name),
+    );
+    if let Err(e) = store.duplicate_playlist(playlist_id, &name) {
+        eprintln!("Failed to duplicate playlist {}: {}", playlist_id, e);
+        this.show_toast("Couldn't duplicate the playlist");
+        return;
+    }
+
+    // Show the copy where it went
+    if let Some(group) = playlist.group {
+        this.expanded_playlist_folders.borrow_mut().insert(group);
+    }
+    reload_playlists(this);
+    this.show_toast(&format!("Made a copy called “{}”", name));
+}
+
+/// Ask before deleting a playlist. Its tracks stay in the library.
+fn confirm_delete_playlist(this: &imp::NovaWindow, playlist_id: &str) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+    let playlist = match store.playlist(playlist_id) {
+        Ok(playlist) => playlist,
+        Err(e) => {
+            eprintln!("Failed to load playlist {}: {}", playlist_id, e);
+            this.show_toast("Couldn't find the playlist");
+            return;
+        }
+    };
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Delete Playlist?")
+        .body(format!(
+            "“{}” will be deleted. Its songs stay in your library.",
+            playlist.name
+        ))
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("delete", "_Delete")]);
+    dialog.set_response_appearance("delete", adw::ResponseAppearance::Destructive);
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("delete"), move |_, _| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        if let Err(e) = store.delete_playlist(&playlist.id) {
+            eprintln!("Failed to delete playlist {}: {}", playlist.id, e);
+            this.show_toast("Couldn't delete the playlist");
+            return;
+        }
+
+        // Leave the playlist's page if it's the one open
+        let open = this
+            .playlist_detail
+            .borrow()
+            .as_ref()
+            .is_some_and(|open| open.id == playlist.id);
+        if open {
+            this.playlist_detail.replace(None);
+            show_page(this, "playlists");
+        }
+        reload_playlists(this);
+        reload_pinned_items(this);
+        this.show_toast(&format!("Deleted “{}”", playlist.name));
+    });
+    dialog.present(Some(&*this.obj()));
+}
+
+fn song_count(count: usize) -> String {
+    format_count_of(count, "{} song", "{} songs")
+}
+
+/// Row that opens a playlist, with `more_actions` in its menu after the
+/// ones every playlist has
+pub(crate) fn create_playlist_row<F>(
+    this: &imp::NovaWindow,
+    playlist: &Playlist,
+    more_actions: Vec<CardAction>,
+    open: F,
+) -> adw::ActionRow
+where
+    F: Fn(&imp::NovaWindow) + 'static,
+{
+    let row = adw::ActionRow::builder()
+        .title(glib::markup_escape_text(&playlist.name))
+        .subtitle(song_count(playlist.track_ids.len()))
+        .activatable(true)
+        .build();
+    row.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+
+    let obj_weak = this.obj().downgrade();
+    row.connect_activated(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            open(obj.imp());
+        }
+    });
+
+    let track_ids = playlist.track_ids.clone();
+    let name = playlist.name.clone();
+    let mut actions = vec![CardAction::new("Play After Current Album", move |this| {
+        play_playlist_after_current(this, track_ids.clone(), &name)
+    })];
+    actions.extend(more_actions);
+    attach_card_menu(&row, actions);
+
+    row
+}
+
+/// Queue a whole playlist to play once the album or playlist playing now
+/// has finished. Tracks that can't be found any more are left out.
+fn play_playlist_after_current(this: &imp::NovaWindow, track_ids: Vec<String>, name: &str) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+    let context = QueueContext::Playlist(name.to_string());
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let mut items = Vec::with_capacity(track_ids.len());
+        for track_id in &track_ids {
+            match manager.get_track(track_id).await {
+                Ok(item) => items.push(item),
+                Err(e) => eprintln!("Leaving {} out of the queued playlist: {}", track_id, e),
+            }
+        }
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        if items.is_empty() {
+            obj.imp().show_toast("None of the playlist's tracks could be found");
+            return;
+        }
+        let count = items.len();
+        obj.imp().play_after_current_unit(items, context);
+        obj.imp()
+            .show_toast(&format!("{} will play after this album", song_count(count)));
+    });
+}
+
+/// Ask for a length and a source, then make a playlist that fits the
+/// length from the source's tracks
+pub(crate) fn show_mix_dialog(this: &imp::NovaWindow) {
+    let playlists = match this.user_store.borrow().as_ref() {
+        Some(store) => store.playlists().unwrap_or_else(|e| {
+            eprintln!("Failed to load playlists: {}", e);
+            Vec::new()
+        }),
+        None => {
+            this.show_toast("Mixes need the library database");
+            return;
+        }
+    };
+
+    let mut sources = vec!["Whole Library".to_string()];
+    sources.extend(playlists.iter().map(|playlist| playlist.name.clone()));
+    let source_refs: Vec<&str> = sources.iter().map(String::as_str).collect();
+    let source_row = adw::ComboRow::builder()
+        .title("From")
+        .model(&gtk::StringList::new(&source_refs))
+        .build();
+    let minutes_row = adw::SpinRow::builder()
+        .title("Minutes")
+        .adjustment(&gtk::Adjustment::new(45.0, 5.0, 600.0, 5.0, 15.0, 0.0))
+        .build();
+
+    let rows = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    rows.add_css_class("boxed-list");
+    rows.append(&source_row);
+    rows.append(&minutes_row);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Make a Mix")
+        .body("Pick tracks that add up to just under the length you choose.")
+        .extra_child(&rows)
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("make", "_Make Mix")]);
+    dialog.set_response_appearance("make", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("make"));
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("make"), move |_, _| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let minutes = minutes_row.value() as u32;
+        // The first choice is the whole library, the rest are the playlists
+        let source = (source_row.selected() as usize)
+            .checked_sub(1)
+            .and_then(|index| playlists.get(index))
+            .cloned();
+        make_mix(obj.imp(), source, minutes);
+    });
+
+    dialog.present(Some(&*this.obj()));
+}
+
+fn make_mix(this: &imp::NovaWindow, source: Option<Playlist>, minutes: u32) {
+    let (Some(manager), Some(store)) = (
+        this.service_manager.borrow().clone(),
+        this.user_store.borrow().clone(),
+    ) else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let candidates: Vec<PlayableItem> = match &source {
+            Some(playlist) => {
+                let mut items = Vec::with_capacity(playlist.track_ids.len());
+                for track_id in &playlist.track_ids {
+                    if let Ok(item) = manager.get_track(track_id).await {
+                        items.push(item);
+                    }
+                }
+                items
+            }
+            None => manager.get_all_tracks().await.unwrap_or_else(|e| {
+                eprintln!("Failed to load tracks for a mix: {}", e);
+                Vec::new()
+            }),
+        };
+        let exclusions = store.shuffle_exclusions().unwrap_or_else(|e| {
+            eprintln!("Failed to load shuffle exclusions: {}", e);
+            ShuffleExclusions::default()
+        });
+        let candidates = candidates
+            .into_iter()
+            .filter(|item| item.track.availability != Availability::Unavailable)
+            .filter(|item| !exclusions.excludes(&item.track))
+            .collect();
+
+        let mix = fit_to_duration(candidates, minutes * 60);
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        if mix.is_empty() {
+            this.show_toast("No tracks are short enough for a mix that long");
+            return;
+        }
+
+        let from = source.as_ref().map_or("Library", |playlist| playlist.name.as_str());
+        let name = format!("{}-minute mix from {}", minutes, from);
+        let track_ids: Vec<String> = mix.iter().map(|item| item.track.id.clone()).collect();
+        match store.create_playlist(&name, &track_ids) {
+            Ok(playlist_id) => {
+                let length: u32 = mix.iter().map(|item| item.track.duration).sum();
+                reload_playlists(this);
+                show_playlist(this, &playlist_id);
+                this.show_toast(&format!(
+                    "Made a {} mix of {}",
+                    format_length(length),
+                    song_count(mix.len())
+                ));
+            }
+            Err(e) => {
+                eprintln!("Failed to save mix: {}", e);
+                this.show_toast("Couldn't save the mix");
+            }
+        }
+    });
+}
+
+/// Ask for a name and make an empty playlist with it, then open it
+pub(crate) fn prompt_new_playlist(this: &imp::NovaWindow) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        this.show_toast("Playlists need the library database");
+        return;
+    };
+
+    let name_row = adw::EntryRow::builder()
+        .title("Name")
+        .activates_default(true)
+        .build();
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&name_row);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("New Playlist")
+        .extra_child(&list)
+        .default_response("create")
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("create", "C_reate")]);
+    dialog.set_response_appearance("create", adw::ResponseAppearance::Suggested);
+    dialog.set_response_enabled("create", false);
+    dialog.set_close_response("cancel");
+    let dialog_weak = dialog.downgrade();
+    name_row.connect_changed(move |row| {
+        if let Some(dialog) = dialog_weak.upgrade() {
+            dialog.set_response_enabled("create", !row.text().trim().is_empty());
+        }
+    });
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("create"), move |_, _| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        let name = name_row.text().trim().to_string();
+        match store.create_playlist(&name, &[]) {
+            Ok(playlist_id) => {
+                reload_playlists(this);
+                show_playlist(this, &playlist_id);
+            }
+            Err(e) => {
+                eprintln!("Failed to create playlist {}: {}", name, e);
+                this.show_toast("Couldn't create the playlist");
+            }
+        }
+    });
+    dialog.present(Some(&*this.obj()));
+}
+
+/// Open the page for a playlist, looking its tracks up in the library
+pub(crate) fn show_playlist(this: &imp::NovaWindow, playlist_id: &str) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+    let playlist = match store.playlist(playlist_id) {
+        Ok(playlist) => playlist,
+        Err(e) => {
+            eprintln!("Failed to load playlist {}: {}", playlist_id, e);
+            this.show_toast("Couldn't open the playlist");
+            return;
+        }
+    };
+
+    let manager = this.service_manager.borrow().clone();
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let mut entries = Vec::with_capacity(playlist.track_ids.len());
+        for track_id in &playlist.track_ids {
+            let item = match &manager {
+                Some(manager) => manager.get_track(track_id).await.ok(),
+                None => None,
+            };
+            let (track, provider) = match item {
+                Some(item) => (Some(item.track), item.provider),
+                None => (None, String::new()),
+            };
+            entries.push(PlaylistEntry {
+                track_id: track_id.clone(),
+                track,
+                provider,
+            });
+        }
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        // Noted so tracks can be found again if their files move
+        let found: Vec<Track> = entries
+            .iter()
+            .filter_map(|entry| entry.track.clone())
+            .collect();
+        if let Some(store) = obj.imp().user_store.borrow().as_ref() {
+            if let Err(e) = store.remember_tracks(&found) {
+                eprintln!("Failed to remember the tracks of {}: {}", playlist.id, e);
+            }
+        }
+        open_playlist_page(obj.imp(), playlist, entries);
+    });
+}
+
+fn open_playlist_page(this: &imp::NovaWindow, playlist: Playlist, entries: Vec<PlaylistEntry>) {
+    this.playlist_detail.replace(Some(playlist));
+    this.playlist_entries.replace(entries);
+    populate_playlist_page(this);
+    this.main_stack.set_visible_child_name("playlist-detail");
+    this.sidebar_list.unselect_all();
+    this.home_button.remove_css_class("selected");
+}
+
+fn populate_playlist_page(this: &imp::NovaWindow) {
+    let Some(playlist) = this.playlist_detail.borrow().clone() else {
+        return;
+    };
+    let entries = this.playlist_entries.borrow().clone();
+
+    this.playlist_detail_title.set_text(&playlist.name);
+
+    // Folder playlists follow the disk, so only stored playlists are edited
+    let editable = playlist.folder.is_none();
+    this.playlist_detail_title.set_editable(editable);
+    this.playlist_detail_remove.set_visible(editable);
+    this.playlist_detail_playback.set_visible(editable);
+    this.playlist_detail_tidy.set_visible(editable);
+    this.playlist_detail_queue.set_visible(editable);
+    this.playlist_detail_add.set_visible(editable);
+    this.playlist_detail_tracks.set_selection_mode(if editable {
+        gtk::SelectionMode::Multiple
+    } else {
+        gtk::SelectionMode::None
+    });
+
+    // Tracks that are gone from the library don't count towards the length
+    let total: u32 = entries
+        .iter()
+        .filter_map(|entry| entry.track.as_ref())
+        .map(|track| track.duration)
+        .sum();
+    this.playlist_detail_info.set_text(&format!(
+        "{} • {}",
+        song_count(entries.len()),
+        format_length(total)
+    ));
+
+    let list = &this.playlist_detail_tracks;
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+    for (index, entry) in entries.iter().enumerate() {
+        list.append(&create_entry_row(this, index, entry, editable));
+    }
+    this.playlist_detail_play
+        .set_sensitive(entries.iter().any(|entry| entry.track.is_some()));
+    this.playlist_detail_remove.set_sensitive(false);
+    this.playlist_detail_queue.set_sensitive(false);
+    this.playlist_detail_add.set_sensitive(false);
+}
+
+fn create_entry_row(
+    this: &imp::NovaWindow,
+    index: usize,
+    entry: &PlaylistEntry,
+    editable: bool,
+) -> adw::ActionRow {
+    let row = match &entry.track {
+        Some(track) => adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&track.title))
+            .subtitle(glib::markup_escape_text(&format!(
+                "{} • {}",
+                track.artist,
+                format_duration(track.duration)
+            )))
+            .build(),
+        None => {
+            let row = adw::ActionRow::builder()
+                .title("Unavailable Track")
+                .subtitle("No longer in your library")
+                .build();
+            row.add_css_class("dim-label");
+            row
+        }
+    };
+    if let Some(track) = &entry.track {
+        row.add_suffix(&create_playing_mark(this, &row, &track.id));
+    }
+    if !editable {
+        return row;
+    }
+    row.add_prefix(&gtk::Image::from_icon_name("list-drag-handle-symbolic"));
+
+    // Rows are dragged by their position in the playlist
+    let drag_source = gtk::DragSource::new();
+    drag_source.set_actions(gdk::DragAction::MOVE);
+    drag_source.connect_prepare(move |_, _, _| {
+        Some(gdk::ContentProvider::for_value(&(index as u32).to_value()))
+    });
+    let row_weak = row.downgrade();
+    drag_source.connect_drag_begin(move |source, _| {
+        if let Some(row) = row_weak.upgrade() {
+            source.set_icon(Some(&gtk::WidgetPaintable::new(Some(&row))), 0, 0);
+        }
+    });
+    row.add_controller(drag_source);
+
+    let drop_target = gtk::DropTarget::new(u32::static_type(), gdk::DragAction::MOVE);
+    let obj_weak = this.obj().downgrade();
+    drop_target.connect_drop(move |_, value, _, _| {
+        let (Some(obj), Ok(from)) = (obj_weak.upgrade(), value.get::<u32>()) else {
+            return false;
+        };
+        move_entry(obj.imp(), from as usize, index);
+        true
+    });
+    row.add_controller(drop_target);
+
+    row
+}
+
+/// Wire up renaming, selection and the selection's actions on the playlist
+/// page
+pub(crate) fn setup_playlist_page(this: &imp::NovaWindow) {
+    let obj_weak = this.obj().downgrade();
+    this.playlist_detail_title.connect_editing_notify(move |label| {
+        if label.is_editing() {
+            return;
+        }
+        if let Some(obj) = obj_weak.upgrade() {
+            rename_playlist(obj.imp(), label.text().trim());
+        }
+    });
+
+    let buttons = [
+        this.playlist_detail_remove.clone(),
+        this.playlist_detail_queue.clone(),
+        this.playlist_detail_add.clone(),
+    ];
+    this.playlist_detail_tracks
+        .connect_selected_rows_changed(move |list| {
+            let any = !list.selected_rows().is_empty();
+            for button in &buttons {
+                button.set_sensitive(any);
+            }
+        });
+
+    let obj_weak = this.obj().downgrade();
+    this.playlist_detail_play.connect_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            play_open_playlist(obj.imp());
+        }
+    });
+
+    let obj_weak = this.obj().downgrade();
+    this.playlist_detail_playback.connect_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            show_playlist_playback(obj.imp());
+        }
+    });
+
+    let obj_weak = this.obj().downgrade();
+    this.playlist_detail_tidy.connect_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            show_playlist_tidy(obj.imp());
+        }
+    });
+
+    let obj_weak = this.obj().downgrade();
+    this.playlist_detail_remove.connect_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            remove_selected(obj.imp());
+        }
+    });
+
+    let obj_weak = this.obj().downgrade();
+    this.playlist_detail_queue.connect_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            queue_selected(obj.imp());
+        }
+    });
+
+    let obj_weak = this.obj().downgrade();
+    this.playlist_detail_add.connect_clicked(move |button| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let tracks: Vec<Track> = selected_entries(obj.imp())
+            .into_iter()
+            .filter_map(|(_, entry)| entry.track)
+            .collect();
+        if tracks.is_empty() {
+            return;
+        }
+        let point = gdk::Rectangle::new(0, 0, button.width(), button.height());
+        show_playlist_picker(obj.imp(), button.upcast_ref(), point, tracks);
+    });
+
+    // Dropping between rows takes a playlist out of its folder
+    add_folder_drop_target(this, &*this.playlists_list, None);
+
+    for key in ["folder-playlists", "folder-playlist-depth", "library-folders"] {
+        let obj_weak = this.obj().downgrade();
+        this.settings().connect_changed(Some(key), move |_, _| {
+            if let Some(obj) = obj_weak.upgrade() {
+                reload_folder_playlists(obj.imp());
+            }
+        });
+    }
+}
+
+/// Toast for an edit that was already saved, with a button to revert it
+fn show_undo_toast<F>(this: &imp::NovaWindow, message: &str, undo: F)
+where
+    F: Fn(&imp::NovaWindow) + 'static,
+{
+    let toast = adw::Toast::builder()
+        .title(glib::markup_escape_text(message))
+        .button_label("Undo")
+        .build();
+
+    let obj_weak = this.obj().downgrade();
+    toast.connect_button_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            undo(obj.imp());
+        }
+    });
+    this.toast_overlay.add_toast(toast);
+}
+
+fn rename_playlist(this: &imp::NovaWindow, name: &str) {
+    let Some(playlist) = this.playlist_detail.borrow().clone() else {
+        return;
+    };
+    if name.is_empty() || name == playlist.name {
+        // Put back the current name, e.g. after the text was cleared
+        this.playlist_detail_title.set_text(&playlist.name);
+        return;
+    }
+
+    if playlist_names(this, Some(&playlist.id)).contains(name) {
+        this.playlist_detail_title.set_text(&playlist.name);
+        this.show_toast(&format!("There's already a playlist called “{}”", name));
+        return;
+    }
+    rename_with_undo(this, playlist.id, playlist.name, name);
+}
+
+/// Rename a playlist from `old_name` to `name`, with a toast to undo it
+fn rename_with_undo(this: &imp::NovaWindow, playlist_id: String, old_name: String, name: &str) {
+    if save_playlist_name(this, &playlist_id, name) {
+        show_undo_toast(this, &format!("Renamed to “{}”", name), move |this| {
+            save_playlist_name(this, &playlist_id, &old_name);
+        });
+    }
+}
+
+/// Store a new name and show it wherever the playlist is on screen
+fn save_playlist_name(this: &imp::NovaWindow, playlist_id: &str, name: &str) -> bool {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return false;
+    };
+    if let Err(e) = store.rename_playlist(playlist_id, name) {
+        eprintln!("Failed to rename playlist {}: {}", playlist_id, e);
+        this.show_toast("Couldn't rename the playlist");
+        return false;
+    }
+
+    let showing = match this.playlist_detail.borrow_mut().as_mut() {
+        Some(playlist) if playlist.id == playlist_id => {
+            playlist.name = name.to_string();
+            true
+        }
+        _ => false,
+    };
+    if showing {
+        this.playlist_detail_title.set_text(name);
+    }
+    reload_playlists(this);
+    reload_pinned_items(this);
+    true
+}
+
+fn move_entry(this: &imp::NovaWindow, from: usize, to: usize) {
+    let mut entries = this.playlist_entries.borrow().clone();
+    if from == to || from >= entries.len() {
+        return;
+    }
+
+    let entry = entries.remove(from);
+    let title = entry
+        .track
+        .as_ref()
+        .map_or_else(|| "track".to_string(), |track| format!("“{}”", track.title));
+    entries.insert(to.min(entries.len()), entry);
+    apply_edit(this, entries, &format!("Moved {}", title));
+}
+
+/// The selected tracks on the playlist page, with their places in it
+fn selected_entries(this: &imp::NovaWindow) -> Vec<(usize, PlaylistEntry)> {
+    let entries = this.playlist_entries.borrow();
+    this.playlist_detail_tracks
+        .selected_rows()
+        .iter()
+        .filter_map(|row| {
+            let index = row.index() as usize;
+            Some((index, entries.get(index)?.clone()))
+        })
+        .collect()
+}
+
+fn remove_selected(this: &imp::NovaWindow) {
+    let selected: Vec<usize> = selected_entries(this)
+        .into_iter()
+        .map(|(index, _)| index)
+        .collect();
+    let (Some(playlist), Some(store)) = (
+        this.playlist_detail.borrow().clone(),
+        this.user_store.borrow().clone(),
+    ) else {
+        return;
+    };
+    if selected.is_empty() {
+        return;
+    }
+    if let Err(e) = store.remove_tracks(&playlist.id, &selected) {
+        eprintln!("Failed to save playlist {}: {}", playlist.id, e);
+        this.show_toast("Couldn't save the playlist");
+        return;
+    }
+
+    let previous = this.playlist_entries.borrow().clone();
+    let entries: Vec<PlaylistEntry> = previous
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !selected.contains(index))
+        .map(|(_, entry)| entry.clone())
+        .collect();
+    show_saved_entries(this, &playlist.id, entries);
+    show_undo_toast(
+        this,
+        &format!("Removed {}", song_count(selected.len())),
+        move |this| {
+            save_playlist_entries(this, &playlist.id, previous.clone());
+        },
+    );
+}
+
+/// Play the open playlist from the start with its playback settings.
+/// Tracks that are gone from the library are left out.
+fn play_open_playlist(this: &imp::NovaWindow) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+    let Some(playlist) = this.playlist_detail.borrow().clone() else {
+        return;
+    };
+    let track_ids: Vec<String> = this
+        .playlist_entries
+        .borrow()
+        .iter()
+        .filter(|entry| entry.track.is_some())
+        .map(|entry| entry.track_id.clone())
+        .collect();
+    let context = QueueContext::Playlist(playlist.name.clone());
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let mut items = Vec::with_capacity(track_ids.len());
+        for track_id in &track_ids {
+            match manager.get_track(track_id).await {
+                Ok(item) => items.push(item),
+                Err(e) => eprintln!("Leaving {} out of the playlist: {}", track_id, e),
+            }
+        }
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        if items.is_empty() {
+            obj.imp().show_toast("None of the playlist's tracks could be found");
+            return;
+        }
+        obj.imp().play_playlist(items, context, playlist.playback);
+    });
+}
+
+/// Add the selected tracks that are still in the library to the end of
+/// the queue
+fn queue_selected(this: &imp::NovaWindow) {
+    // The page already has the tracks it found
+    let items: Vec<PlayableItem> = selected_entries(this)
+        .into_iter()
+        .filter_map(|(_, entry)| {
+            Some(PlayableItem {
+                track: entry.track?,
+                provider: entry.provider,
+                added_at: Utc::now(),
+            })
+        })
+        .collect();
+    if items.is_empty() {
+        this.show_toast("None of the selected tracks could be found");
+        return;
+    }
+    let context = QueueContext::Playlist(this.playlist_detail_title.text().to_string());
+    let count = items.len();
+    this.enqueue_tracks(items, context);
+    this.show_toast(&format!("Added {} to the queue", song_count(count)));
+}
+
+/// Save `entries` as the open playlist's tracks and offer to undo it
+pub(crate) fn apply_edit(this: &imp::NovaWindow, entries: Vec<PlaylistEntry>, message: &str) {
+    let Some(playlist) = this.playlist_detail.borrow().clone() else {
+        return;
+    };
+
+    let previous = this.playlist_entries.borrow().clone();
+    if save_playlist_entries(this, &playlist.id, entries) {
+        show_undo_toast(this, message, move |this| {
+            save_playlist_entries(this, &playlist.id, previous.clone());
+        });
+    }
+}
+
+/// Store the playlist's tracks and refresh its page if it is open
+fn save_playlist_entries(
+    this: &imp::NovaWindow,
+    playlist_id: &str,
+    entries: Vec<PlaylistEntry>,
+) -> bool {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return false;
+    };
+    let track_ids: Vec<String> = entries.iter().map(|entry| entry.track_id.clone()).collect();
+    if let Err(e) = store.set_playlist_tracks(playlist_id, &track_ids) {
+        eprintln!("Failed to save playlist {}: {}", playlist_id, e);
+        this.show_toast("Couldn't save the playlist");
+        return false;
+    }
+    show_saved_entries(this, playlist_id, entries);
+    true
+}
+
+/// Show `entries` as the playlist's saved tracks, on its page if it's open
+fn show_saved_entries(this: &imp::NovaWindow, playlist_id: &str, entries: Vec<PlaylistEntry>) {
+    let showing = this
+        .playlist_detail
+        .borrow()
+        .as_ref()
+        .is_some_and(|playlist| playlist.id == playlist_id);
+    if showing {
+        this.playlist_entries.replace(entries);
+        populate_playlist_page(this);
+    }
+    reload_playlists(this);
+}
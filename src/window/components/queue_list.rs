@@ -0,0 +1,69 @@
+use crate::services::models::{QueueContext, QueueEntry};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+
+// Entries shown in the Queue tab of the queue flap
+const QUEUE_DISPLAY_LIMIT: usize = 100;
+
+/// Fill the Queue tab with what comes after the current track, and keep it
+/// filled as the queue changes
+pub(crate) fn setup_queue_list(this: &imp::NovaWindow) {
+    if let Some(player) = this.player.borrow().as_ref() {
+        let obj_weak = this.obj().downgrade();
+        player.connect_queue_changed(move || {
+            if let Some(obj) = obj_weak.upgrade() {
+                reload_queue_list(obj.imp());
+            }
+        });
+    }
+    reload_queue_list(this);
+}
+
+fn reload_queue_list(this: &imp::NovaWindow) {
+    let entries = this
+        .player
+        .borrow()
+        .as_ref()
+        .map(|player| player.upcoming_entries(QUEUE_DISPLAY_LIMIT))
+        .unwrap_or_default();
+
+    let list = &this.queue_list;
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+
+    // Tracks queued together share one heading saying why
+    let mut context: Option<&QueueContext> = None;
+    for entry in &entries {
+        if context != Some(&entry.context) {
+            list.append(&create_context_row(&entry.context));
+            context = Some(&entry.context);
+        }
+        list.append(&create_queue_row(entry));
+    }
+}
+
+fn create_context_row(context: &QueueContext) -> gtk::ListBoxRow {
+    let label = gtk::Label::builder()
+        .label(context.label())
+        .xalign(0.0)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .css_classes(["caption-heading", "dim-label"])
+        .build();
+    gtk::ListBoxRow::builder()
+        .child(&label)
+        .activatable(false)
+        .selectable(false)
+        .css_classes(["queue-context"])
+        .build()
+}
+
+fn create_queue_row(entry: &QueueEntry) -> adw::ActionRow {
+    adw::ActionRow::builder()
+        .title(glib::markup_escape_text(&entry.track.title))
+        .subtitle(glib::markup_escape_text(&entry.track.artist))
+        .tooltip_text(entry.context.label())
+        .build()
+}
@@ -0,0 +1,244 @@
+use crate::services::recap::{Recap, RecapPeriod};
+use crate::window::imp;
+use crate::window::utils::format::format_count_of;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gettextrs::gettext;
+use gtk::{gdk, gio, glib, graphene};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// What a recap is saved as
+enum RecapFile {
+    Page(String),
+    Image(gdk::Texture),
+}
+
+/// Show a recap of this month's listening, with a choice of other periods
+/// and of saving it as a page or an image. Everything comes from the play
+/// history on this device.
+pub(crate) fn show_recap(this: &imp::NovaWindow) {
+    let history = match this.user_store.borrow().as_ref() {
+        Some(store) => store.listened_history().unwrap_or_else(|e| {
+            eprintln!("Failed to load play history: {}", e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    let periods = [
+        ("This Month", RecapPeriod::this_month()),
+        ("Last Month", RecapPeriod::last_month()),
+        ("This Year", RecapPeriod::this_year()),
+        ("Last Year", RecapPeriod::last_year()),
+    ];
+
+    let period_list = gtk::StringList::new(&periods.map(|(name, _)| name));
+    let period_row = adw::ComboRow::builder()
+        .title("Period")
+        .model(&period_list)
+        .build();
+    let period_group = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    period_group.append(&period_row);
+
+    let card_holder = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    content.append(&period_group);
+    content.append(&card_holder);
+
+    let recap = Rc::new(RefCell::new(Recap::default()));
+    let show_period = {
+        let recap = recap.clone();
+        let card_holder = card_holder.clone();
+        move |index: u32| {
+            let period = periods[index as usize].1;
+            let built = Recap::build(period, &history);
+            if let Some(child) = card_holder.first_child() {
+                card_holder.remove(&child);
+            }
+            card_holder.append(&create_recap_card(&built));
+            recap.replace(built);
+        }
+    };
+    show_period(0);
+    period_row.connect_selected_notify(move |row| show_period(row.selected()));
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Listening Recap")
+        .extra_child(&content)
+        .build();
+    dialog.add_response("close", "_Close");
+    dialog.add_response("page", "Save _Page…");
+    dialog.add_response("image", "Save _Image…");
+    dialog.set_response_appearance("image", adw::ResponseAppearance::Suggested);
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(None, move |_, response| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let recap = recap.borrow();
+        // The image is taken now, while the card is still on screen
+        let file = match response {
+            "page" => RecapFile::Page(recap.to_html()),
+            "image" => match card_holder.first_child().and_then(|card| render(&card)) {
+                Some(texture) => RecapFile::Image(texture),
+                None => {
+                    obj.imp().show_toast("Couldn't make an image of the recap");
+                    return;
+                }
+            },
+            _ => return,
+        };
+        choose_recap_file(obj.imp(), &recap.title, file);
+    });
+    dialog.present(Some(&*this.obj()));
+}
+
+fn create_recap_card(recap: &Recap) -> gtk::Box {
+    let card = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(12)
+        .css_classes(["card", "recap-card"])
+        .build();
+    let label = |text: &str, class: &str| {
+        let label = gtk::Label::builder()
+            .label(text)
+            .xalign(0.0)
+            .wrap(true)
+            .build();
+        if !class.is_empty() {
+            label.add_css_class(class);
+        }
+        card.append(&label);
+    };
+
+    let plays = |count: u32| format_count_of(count as usize, "{} play", "{} plays");
+
+    label(&recap.title, "title-1");
+    if recap.plays == 0 {
+        label(&gettext("Nothing was played in this period"), "dim-label");
+        return card;
+    }
+    label(
+        &gettext("{minutes} over {plays}, on {days}")
+            .replace(
+                "{minutes}",
+                &format_count_of(recap.minutes as usize, "{} minute", "{} minutes"),
+            )
+            .replace("{plays}", &plays(recap.plays))
+            .replace(
+                "{days}",
+                &format_count_of(recap.days_listened, "{} day", "{} days"),
+            ),
+        "title-4",
+    );
+    label(
+        &gettext("Discovered {artists} and {tracks}")
+            .replace(
+                "{artists}",
+                &format_count_of(recap.new_artists, "{} new artist", "{} new artists"),
+            )
+            .replace(
+                "{tracks}",
+                &format_count_of(recap.new_tracks, "{} new track", "{} new tracks"),
+            ),
+        "",
+    );
+
+    label(&gettext("Top Artists"), "heading");
+    for (rank, (artist, count)) in recap.top_artists.iter().enumerate() {
+        label(&format!("{}. {} · {}", rank + 1, artist, plays(*count)), "");
+    }
+    label(&gettext("Top Tracks"), "heading");
+    for (rank, track) in recap.top_tracks.iter().enumerate() {
+        label(
+            &format!(
+                "{}. {} – {} · {}",
+                rank + 1,
+                track.title,
+                track.artist,
+                plays(track.plays)
+            ),
+            "",
+        );
+    }
+    if !recap.complete {
+        label(
+            &gettext("Older plays are no longer kept, so this covers part of the period"),
+            "dim-label",
+        );
+    }
+    card
+}
+
+/// Draw `widget` as it looks now into a texture
+fn render(widget: &gtk::Widget) -> Option<gdk::Texture> {
+    let (width, height) = (widget.width(), widget.height());
+    let paintable = gtk::WidgetPaintable::new(Some(widget));
+    let snapshot = gtk::Snapshot::new();
+    paintable.snapshot(&snapshot, width.into(), height.into());
+    let node = snapshot.to_node()?;
+    let renderer = widget.native()?.renderer()?;
+    let bounds = graphene::Rect::new(0.0, 0.0, width as f32, height as f32);
+    Some(renderer.render_texture(&node, Some(&bounds)))
+}
+
+fn choose_recap_file(this: &imp::NovaWindow, title: &str, file: RecapFile) {
+    let (filter_name, extension) = match file {
+        RecapFile::Page(_) => ("Web Page", "html"),
+        RecapFile::Image(_) => ("PNG Image", "png"),
+    };
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some(filter_name));
+    filter.add_pattern(&format!("*.{}", extension));
+    let filters = gio::ListStore::new::<gtk::FileFilter>();
+    filters.append(&filter);
+
+    let name = format!("Nova Recap {}.{}", title, extension);
+    let dialog = gtk::FileDialog::builder()
+        .title("Save Recap")
+        .modal(true)
+        .initial_name(name.as_str())
+        .filters(&filters)
+        .build();
+
+    let obj_weak = this.obj().downgrade();
+    dialog.save(
+        Some(&*this.obj()),
+        gio::Cancellable::NONE,
+        move |result| match result {
+            Ok(dest) => {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return;
+                };
+                match dest.path() {
+                    Some(path) => save_recap(obj.imp(), file, path),
+                    None => obj.imp().show_toast("Choose a file on this device"),
+                }
+            }
+            Err(e) => {
+                if !e.matches(gtk::DialogError::Dismissed) {
+                    eprintln!("Failed to choose a recap file: {}", e);
+                }
+            }
+        },
+    );
+}
+
+fn save_recap(this: &imp::NovaWindow, file: RecapFile, path: PathBuf) {
+    let result = match file {
+        RecapFile::Page(html) => std::fs::write(&path, html).map_err(|e| e.to_string()),
+        RecapFile::Image(texture) => texture.save_to_png(&path).map_err(|e| e.to_string()),
+    };
+    match result {
+        Ok(()) => this.show_toast("Recap saved"),
+        Err(e) => {
+            eprintln!("Failed to save the recap to {:?}: {}", path, e);
+            this.show_toast(&format!("Couldn't save the recap: {}", e));
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Problems found while starting up that would otherwise end in a panic or
+//! an empty library with no explanation: a damaged database, missing
+//! GStreamer plugins or library folders that can't be read. Each is shown
+//! in turn once the window is up, with a way out.
+
+use crate::library_folders::{
+    choose_folder, folder_name, remove_library_folder, replace_library_folder,
+};
+use crate::services::writeback;
+use crate::services::UserStore;
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub enum Problem {
+    /// The history and playlist database failed its integrity check
+    DamagedDatabase { path: PathBuf, error: String },
+    /// Playback can't work; holds what is missing or the error
+    AudioUnavailable(String),
+    /// None of the library folders can be read
+    UnreadableLibrary(Vec<PathBuf>),
+}
+
+/// Queue `problem` to be shown, straight away if nothing else is
+pub(crate) fn report_problem(this: &imp::NovaWindow, problem: Problem) {
+    eprintln!("Startup problem: {:?}", problem);
+    this.pending_problems.borrow_mut().push_back(problem);
+    if this.obj().is_mapped() {
+        show_next_problem(this);
+    }
+}
+
+/// Show the oldest problem not dealt with yet, one dialog at a time
+pub(crate) fn show_next_problem(this: &imp::NovaWindow) {
+    if this.recovery_dialog_open.get() {
+        return;
+    }
+    let Some(problem) = this.pending_problems.borrow_mut().pop_front() else {
+        return;
+    };
+
+    let dialog = match &problem {
+        Problem::DamagedDatabase { path, error } => {
+            let dialog = adw::AlertDialog::builder()
+                .heading("Your Library Database Is Damaged")
+                .body(format!(
+                    "Nova couldn't read {} ({}). Rebuild it to start over with an empty \
+                     history and no playlists, or carry on without them for now.",
+                    path.display(),
+                    error
+                ))
+                .build();
+            dialog.add_responses(&[("continue", "_Continue Without"), ("rebuild", "_Rebuild")]);
+            dialog.set_response_appearance("rebuild", adw::ResponseAppearance::Destructive);
+            dialog.set_default_response(Some("rebuild"));
+            dialog
+        }
+        Problem::AudioUnavailable(missing) => {
+            let dialog = adw::AlertDialog::builder()
+                .heading("Playback Is Unavailable")
+                .body(format!(
+                    "Some GStreamer plugins Nova needs aren't installed: {}. You can still \
+                     browse your library; install them and restart Nova to play music.",
+                    missing
+                ))
+                .build();
+            dialog.add_responses(&[("continue", "_Continue")]);
+            dialog
+        }
+        Problem::UnreadableLibrary(folders) => {
+            let names: Vec<String> = folders.iter().map(|f| folder_name(f)).collect();
+            let dialog = adw::AlertDialog::builder()
+                .heading("Your Music Can't Be Read")
+                .body(format!(
+                    "Nova can't open {}. Choose where your music is now, or carry on without \
+                     the local library being watched.",
+                    names.join(", ")
+                ))
+                .build();
+            dialog.add_responses(&[
+                ("continue", "Continue _Without Watching"),
+                ("choose", "_Choose New Folder"),
+            ]);
+            dialog.set_response_appearance("choose", adw::ResponseAppearance::Suggested);
+            dialog.set_default_response(Some("choose"));
+            dialog
+        }
+    };
+    dialog.set_close_response("continue");
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(None, move |_, response| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        match (&problem, response) {
+            (Problem::DamagedDatabase { path, .. }, "rebuild") => match UserStore::rebuild(path) {
+                Ok(store) => {
+                    writeback::load_tag_edits(&store);
+                    this.user_store.replace(Some(Arc::new(store)));
+                    this.show_toast("Started a new library database");
+                }
+                Err(e) => {
+                    eprintln!("Failed to rebuild the user store: {}", e);
+                    this.show_toast("Couldn't rebuild the library database");
+                }
+            },
+            (Problem::UnreadableLibrary(folders), "choose") => {
+                // The chosen folder takes the place of all the unreadable ones
+                let settings = this.settings();
+                let folders = folders.clone();
+                choose_folder(
+                    Some(obj.upcast_ref()),
+                    "Choose Your Music Folder",
+                    None,
+                    move |new| {
+                        let Some((first, rest)) = folders.split_first() else {
+                            return;
+                        };
+                        for folder in rest {
+                            remove_library_folder(&settings, folder);
+                        }
+                        replace_library_folder(&settings, first, new);
+                    },
+                );
+            }
+            // Carrying on leaves things as they are: no history, no playback
+            // or a library that isn't scanned until the folders change
+            _ => {}
+        }
+
+        this.recovery_dialog_open.set(false);
+        show_next_problem(this);
+    });
+
+    this.recovery_dialog_open.set(true);
+    dialog.present(Some(&*this.obj()));
+}
@@ -0,0 +1,109 @@
+use crate::services::models::{PlaybackSource, Track};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::{gio, glib};
+use std::path::PathBuf;
+
+const FILE_MANAGER_NAME: &str = "org.freedesktop.FileManager1";
+const FILE_MANAGER_PATH: &str = "/org/freedesktop/FileManager1";
+
+/// Open the folder holding `track`'s file, with the file selected
+pub(crate) fn show_track_in_files(this: &imp::NovaWindow, track: &Track) {
+    if let PlaybackSource::Local { path, .. } = &track.source {
+        show_in_files(this, vec![path.clone()]);
+    }
+}
+
+/// Open the folder holding the album's files from `provider`, with them
+/// selected
+pub(crate) fn show_album_in_files(this: &imp::NovaWindow, provider: &str, album_id: &str) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+    let (provider, album_id) = (provider.to_string(), album_id.to_string());
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager.album_details(&provider, &album_id).await;
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let tracks = match result {
+            Ok((_, tracks)) => tracks,
+            Err(e) => {
+                eprintln!("Failed to load album {} to show: {}", album_id, e);
+                obj.imp().show_toast("Couldn't find the album's files");
+                return;
+            }
+        };
+
+        // Chapters share their file, and a file manager opens a window for
+        // every folder, so only files beside the first are selected
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for item in tracks {
+            let PlaybackSource::Local { path, .. } = item.track.source else {
+                continue;
+            };
+            let beside_first = paths
+                .first()
+                .map_or(true, |first| first.parent() == path.parent());
+            if beside_first && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            obj.imp()
+                .show_toast("This album has no files in your library");
+        } else {
+            show_in_files(obj.imp(), paths);
+        }
+    });
+}
+
+/// Ask the file manager to show `paths` selected in their folder. Without
+/// one that answers, the first one's folder is opened through the portal
+/// instead, without a selection.
+fn show_in_files(this: &imp::NovaWindow, paths: Vec<PathBuf>) {
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let Err(e) = show_items(&paths).await else {
+            return;
+        };
+        println!(
+            "No file manager showed {:?}, opening its folder: {}",
+            paths[0], e
+        );
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let launcher = gtk::FileLauncher::new(Some(&gio::File::for_path(&paths[0])));
+        if let Err(e) = launcher.open_containing_folder_future(Some(&obj)).await {
+            eprintln!("Failed to open the folder of {:?}: {}", paths[0], e);
+            obj.imp().show_toast("Couldn't open the folder");
+        }
+    });
+}
+
+/// FileManager1's ShowItems, which Files, Dolphin and most others answer
+async fn show_items(paths: &[PathBuf]) -> Result<(), glib::Error> {
+    let connection = gio::bus_get_future(gio::BusType::Session).await?;
+    let uris: Vec<String> = paths
+        .iter()
+        .map(|path| gio::File::for_path(path).uri().to_string())
+        .collect();
+    connection
+        .call_future(
+            Some(FILE_MANAGER_NAME),
+            FILE_MANAGER_PATH,
+            FILE_MANAGER_NAME,
+            "ShowItems",
+            // No startup ID to hand over
+            Some(&(uris, "").to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+        )
+        .await?;
+    Ok(())
+}
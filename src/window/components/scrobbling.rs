@@ -0,0 +1,114 @@
+use crate::services::scrobbler::{is_listen, Scrobbler};
+use crate::services::secrets;
+use crate::window::components::playback::Playthrough;
+use crate::window::imp;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use std::sync::Arc;
+use std::time::Duration;
+
+// How often listens that couldn't be sent are tried again
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Send listens to ListenBrainz while a token is set, keeping those made
+/// offline to send later
+pub(crate) fn setup_scrobbling(this: &imp::NovaWindow) {
+    load_scrobbler(this);
+    let mut secret_changes = secrets::subscribe();
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        while let Ok(key) = secret_changes.recv().await {
+            let Some(obj) = obj_weak.upgrade() else {
+                break;
+            };
+            if key == secrets::LISTENBRAINZ_TOKEN {
+                load_scrobbler(obj.imp());
+            }
+        }
+    });
+
+    if let Some(player) = this.player.borrow().as_ref() {
+        let obj_weak = this.obj().downgrade();
+        player.connect_track_finished(move |playthrough| {
+            if let Some(obj) = obj_weak.upgrade() {
+                record_listen(obj.imp(), playthrough);
+            }
+        });
+    }
+
+    let obj_weak = this.obj().downgrade();
+    glib::timeout_add_local(RETRY_INTERVAL, move || {
+        let Some(obj) = obj_weak.upgrade() else {
+            return glib::ControlFlow::Break;
+        };
+        send_listens(obj.imp());
+        glib::ControlFlow::Continue
+    });
+}
+
+fn load_scrobbler(this: &imp::NovaWindow) {
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let token = match secrets::load(secrets::LISTENBRAINZ_TOKEN).await {
+            Ok(token) => token.unwrap_or_default(),
+            Err(e) => {
+                eprintln!("Failed to read the ListenBrainz token: {}", e);
+                String::new()
+            }
+        };
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        let store = this.user_store.borrow().clone();
+        let scrobbler = match store {
+            Some(store) if !token.trim().is_empty() => {
+                Some(Arc::new(Scrobbler::new(store, token.trim())))
+            }
+            _ => None,
+        };
+        this.scrobbler.replace(scrobbler);
+        // Send what was left from last time
+        send_listens(this);
+    });
+}
+
+/// Queue `playthrough` to be sent if enough of it played
+fn record_listen(this: &imp::NovaWindow, playthrough: &Playthrough) {
+    let Some(scrobbler) = this.scrobbler.borrow().clone() else {
+        return;
+    };
+    let length = Duration::from_secs(playthrough.track.duration.into());
+    if !is_listen(length, playthrough.played) {
+        return;
+    }
+    if let Err(e) = scrobbler.record(&playthrough.track, playthrough.started_at) {
+        eprintln!(
+            "Failed to keep listen of {}: {}",
+            playthrough.track.title, e
+        );
+        return;
+    }
+    send_listens(this);
+}
+
+fn send_listens(this: &imp::NovaWindow) {
+    let (Some(scrobbler), Some(manager)) = (
+        this.scrobbler.borrow().clone(),
+        this.service_manager.borrow().clone(),
+    ) else {
+        return;
+    };
+    glib::MainContext::default().spawn_local(async move {
+        // They wait in the store until there's a connection
+        if !manager.network_conditions().await.available {
+            return;
+        }
+        match tokio::spawn(async move { scrobbler.flush().await }).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(sent)) => println!("Sent {} listens to ListenBrainz", sent),
+            Ok(Err(e)) => eprintln!("Listens kept to send later: {}", e),
+            Err(e) => eprintln!("Sending listens stopped: {}", e),
+        }
+    });
+}
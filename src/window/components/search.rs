@@ -1,6 +1,10 @@
-use crate::services::models::SearchResults;
-use crate::services::{Album, Artist, PlayableItem, Track};
-use crate::window::components::cards::{create_album_card, create_artist_card, create_track_card};
+use crate::services::models::{
+    LyricsMatch, QueueContext, SearchResultType, SearchResults, SearchWeights,
+};
+use crate::services::ranking;
+use crate::window::components::cards::{
+    create_album_card, create_artist_card, create_track_card, create_version_badges, provider_label,
+};
 use crate::window::imp;
 use adw::prelude::*;
 use adw::subclass::prelude::*;
@@ -8,6 +12,16 @@ use adw::Animation;
 use gtk::prelude::*;
 use gtk::{gio, glib};
 use std::collections::HashSet;
+use std::time::Duration;
+
+// Most tracks Play All and Shuffle All queue from a search
+const PLAY_ALL_LIMIT: usize = 500;
+
+// Pause in typing before the search runs
+pub(crate) const SEARCH_DELAY: Duration = Duration::from_millis(300);
+
+// Tracks shown in the Songs section, or for each provider when grouped
+const TRACKS_SHOWN: usize = 5;
 
 pub(crate) fn show_loading_state(this: &imp::NovaWindow) {
     // Clear any existing spinner
@@ -28,6 +42,7 @@ pub(crate) fn show_loading_state(this: &imp::NovaWindow) {
     }
     this.artists_section.set_visible(false);
     this.albums_section.set_visible(false);
+    this.search_filter_bar.set_visible(false);
 
     // Create spinner with vertical centering
     let spinner = gtk::Spinner::new();
@@ -46,7 +61,170 @@ pub(crate) fn show_loading_state(this: &imp::NovaWindow) {
     this.spinner_container.replace(Some(container));
 }
 
-pub(crate) fn update_search_results(this: &imp::NovaWindow, results: &SearchResults, query: &str) {
+/// Keep the provider chips and the Group by Source toggle in step with the
+/// results shown
+pub(crate) fn setup_search_filters(this: &imp::NovaWindow) {
+    this.settings()
+        .bind(
+            "group-search-by-provider",
+            &*this.search_group_toggle,
+            "active",
+        )
+        .build();
+    let obj_weak = this.obj().downgrade();
+    this.settings()
+        .connect_changed(Some("group-search-by-provider"), move |_, _| {
+            if let Some(obj) = obj_weak.upgrade() {
+                show_filtered_results(obj.imp());
+            }
+        });
+}
+
+/// Show `results`, narrowed to the provider picked earlier if they still
+/// have any from it
+pub(crate) fn update_search_results(
+    this: &imp::NovaWindow,
+    results: &SearchResults,
+    query: &str,
+    weights: &SearchWeights,
+) {
+    this.search_results.replace(Some(results.clone()));
+    update_provider_filter(this, results);
+    let provider = this.search_provider.borrow().clone();
+    show_results(
+        this,
+        &filter_by_provider(results, provider.as_deref()),
+        query,
+        weights,
+    );
+}
+
+/// Show the kept results again, after the provider or grouping changed
+fn show_filtered_results(this: &imp::NovaWindow) {
+    let results = this.search_results.borrow().clone();
+    let query = this.search_query.borrow().clone();
+    let (Some(results), Some(query)) = (results, query) else {
+        return;
+    };
+    let provider = this.search_provider.borrow().clone();
+    show_results(
+        this,
+        &filter_by_provider(&results, provider.as_deref()),
+        &query,
+        &this.search_weights(),
+    );
+}
+
+/// Providers with anything in `results`, the library on this device first
+fn result_providers(results: &SearchResults) -> Vec<String> {
+    let mut providers: Vec<String> = results
+        .tracks
+        .iter()
+        .chain(results.versions.values().flatten())
+        .map(|item| item.provider.clone())
+        .chain(
+            results
+                .albums
+                .iter()
+                .flat_map(|album| album.sources.iter().map(|s| s.provider.clone())),
+        )
+        .chain(
+            results
+                .artists
+                .iter()
+                .flat_map(|artist| artist.sources.iter().map(|s| s.provider.clone())),
+        )
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    providers.sort_by(|a, b| (a != "local", a).cmp(&(b != "local", b)));
+    providers
+}
+
+/// What of `results` comes from `provider`, or all of it for None
+fn filter_by_provider(results: &SearchResults, provider: Option<&str>) -> SearchResults {
+    let mut results = results.clone();
+    if let Some(provider) = provider {
+        // A track merged from several providers shows that provider's copy
+        let versions = std::mem::take(&mut results.versions);
+        results.tracks = std::mem::take(&mut results.tracks)
+            .into_iter()
+            .filter_map(|item| {
+                if item.provider == provider {
+                    return Some(item);
+                }
+                versions
+                    .get(&item.track.id)?
+                    .iter()
+                    .find(|other| other.provider == provider)
+                    .cloned()
+            })
+            .collect();
+        results
+            .albums
+            .retain(|album| album.sources.iter().any(|s| s.provider == provider));
+        results
+            .artists
+            .retain(|artist| artist.sources.iter().any(|s| s.provider == provider));
+    }
+    results
+}
+
+/// Rebuild the All / provider chips for the providers in `results`
+fn update_provider_filter(this: &imp::NovaWindow, results: &SearchResults) {
+    let providers = result_providers(results);
+    // A provider without results can't narrow them
+    let selected = this
+        .search_provider
+        .borrow()
+        .clone()
+        .filter(|provider| providers.contains(provider));
+    this.search_provider.replace(selected.clone());
+
+    let chips = &this.search_provider_box;
+    while let Some(child) = chips.first_child() {
+        chips.remove(&child);
+    }
+    let all = gtk::ToggleButton::builder()
+        .label("All")
+        .active(selected.is_none())
+        .css_classes(["pill"])
+        .build();
+    chips.append(&all);
+    let mut buttons = vec![(all, None)];
+    for provider in providers.iter() {
+        let chip = gtk::ToggleButton::builder()
+            .label(provider_label(provider))
+            .group(&buttons[0].0)
+            .active(selected.as_ref() == Some(provider))
+            .css_classes(["pill"])
+            .build();
+        chips.append(&chip);
+        buttons.push((chip, Some(provider.clone())));
+    }
+
+    for (button, provider) in buttons {
+        let obj_weak = this.obj().downgrade();
+        button.connect_toggled(move |button| {
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            if button.is_active() {
+                obj.imp().search_provider.replace(provider.clone());
+                show_filtered_results(obj.imp());
+            }
+        });
+    }
+    // One provider leaves nothing to choose between
+    this.search_filter_bar.set_visible(providers.len() > 1);
+}
+
+fn show_results(
+    this: &imp::NovaWindow,
+    results: &SearchResults,
+    query: &str,
+    weights: &SearchWeights,
+) {
     println!(
         "Updating search results with {} tracks, {} albums, {} artists",
         results.tracks.len(),
@@ -68,6 +246,8 @@ pub(crate) fn update_search_results(this: &imp::NovaWindow, results: &SearchResu
 
     this.search_stack
         .set_visible_child_name("search_results_scroll");
+    this.search_query.replace(Some(query.to_string()));
+    this.search_play_all_box.set_visible(!results.tracks.is_empty());
 
     // Clear previous results
     if let Some(child) = this.top_result_box.center_widget() {
@@ -89,44 +269,25 @@ pub(crate) fn update_search_results(this: &imp::NovaWindow, results: &SearchResu
     let track_section = this.tracks_box.parent().unwrap();
     track_section.set_visible(true);
 
-    // Sort and process tracks
-    let mut tracks = results.tracks.clone();
-    tracks.sort_by(|a, b| {
-        score_track(&b.track, query)
-            .partial_cmp(&score_track(&a.track, query))
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
+    // Sections arrive ranked by the service manager
+    let tracks = &results.tracks;
 
-    // Sort and filter artists
-    let mut filtered_artists: Vec<_> = results
+    let filtered_artists: Vec<_> = results
         .artists
         .iter()
         .filter(|artist| artist.name != "Unknown Artist")
         .collect();
 
-    filtered_artists.sort_by(|a, b| {
-        score_artist(b, query)
-            .partial_cmp(&score_artist(a, query))
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
-
-    // Sort and filter albums
-    let mut filtered_albums: Vec<_> = results
+    let filtered_albums: Vec<_> = results
         .albums
         .iter()
         .filter(|album| album.title != "Unknown Album")
         .collect();
 
-    filtered_albums.sort_by(|a, b| {
-        score_album(b, query)
-            .partial_cmp(&score_album(a, query))
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
-
     // Show top result based on relevance scoring
     if let Some(window) = this.obj().downcast_ref::<super::super::NovaWindow>() {
         if let Some(top_result) =
-            determine_top_result(results, query, window.upcast_ref::<gtk::Window>())
+            determine_top_result(results, query, weights, window.upcast_ref::<gtk::Window>())
         {
             this.top_result_box.set_center_widget(Some(&top_result));
             this.top_result_box.set_visible(true);
@@ -137,10 +298,46 @@ pub(crate) fn update_search_results(this: &imp::NovaWindow, results: &SearchResu
     // Update tracks section
     if !tracks.is_empty() {
         if let Some(window) = this.obj().downcast_ref::<super::super::NovaWindow>() {
-            for track in tracks.iter().take(5) {
-                let card =
-                    create_track_card(&track.track, false, window.upcast_ref::<gtk::Window>());
-                this.tracks_box.append(&card);
+            // Providers in the order of their best match
+            let mut providers: Vec<&str> = Vec::new();
+            for track in tracks {
+                if !providers.contains(&track.provider.as_str()) {
+                    providers.push(&track.provider);
+                }
+            }
+            let grouped =
+                this.settings().boolean("group-search-by-provider") && providers.len() > 1;
+            let groups: Vec<Option<&str>> = if grouped {
+                providers.into_iter().map(Some).collect()
+            } else {
+                vec![None]
+            };
+
+            for group in groups {
+                if let Some(provider) = group {
+                    let heading = gtk::Label::new(Some(&provider_label(provider)));
+                    heading.set_halign(gtk::Align::Start);
+                    heading.add_css_class("heading");
+                    heading.add_css_class("search-provider-heading");
+                    this.tracks_box.append(&heading);
+                }
+                let shown = tracks
+                    .iter()
+                    .filter(|track| group.map_or(true, |provider| track.provider == provider))
+                    .take(TRACKS_SHOWN);
+                for track in shown {
+                    let card =
+                        create_track_card(&track.track, false, window.upcast_ref::<gtk::Window>());
+                    let others = results.versions.get(&track.track.id);
+                    if let Some(badges) =
+                        others.and_then(|others| create_version_badges(track, others))
+                    {
+                        // Beside the title and artist, ahead of the playing mark
+                        let labels = card.first_child().and_then(|art| art.next_sibling());
+                        card.insert_child_after(&badges, labels.as_ref());
+                    }
+                    this.tracks_box.append(&card);
+                }
             }
             this.tracks_box.set_visible(true);
         }
@@ -169,172 +366,210 @@ pub(crate) fn update_search_results(this: &imp::NovaWindow, results: &SearchResu
     }
 }
 
-fn score_track(track: &Track, query: &str) -> f32 {
-    let query = query.to_lowercase();
+/// Search for `query` after `delay`, replacing any search still running.
+/// Typing waits for a pause; Enter searches straight away.
+pub(crate) fn start_search(this: &imp::NovaWindow, query: String, delay: Duration) {
+    // Increment version to invalidate previous searches
+    let current_version = this.search_version.get() + 1;
+    this.search_version.set(current_version);
+
+    // Handle empty query
+    if query.is_empty() {
+        this.search_stack
+            .set_visible_child_name("empty_search_page");
+        return;
+    }
 
-    // Primary matches (high weight for track-specific fields)
-    let title_exact = if track.title.to_lowercase() == query {
-        1200.0
-    } else {
-        0.0
-    };
-    let title_contains = if track.title.to_lowercase().contains(&query) {
-        600.0
+    // Check for existing results
+    let has_existing_results = this.top_result_box.center_widget().is_some()
+        || this.tracks_box.first_child().is_some()
+        || this.artists_box.first_child().is_some()
+        || this.albums_box.first_child().is_some();
+
+    // Check if we're on the empty search page
+    let is_empty_page = this
+        .search_stack
+        .visible_child_name()
+        .map_or(true, |name| name == "empty_search_page");
+
+    // Only show loading state if no existing results
+    if !has_existing_results || is_empty_page {
+        this.search_stack
+            .set_visible_child_name("search_results_scroll");
+        show_loading_state(this);
     } else {
-        0.0
-    };
+        this.search_stack
+            .set_visible_child_name("search_results_scroll");
+    }
 
-    // Secondary matches (lower weight for related fields)
-    let artist_exact = if track.artist.to_lowercase() == query {
-        300.0
-    } else {
-        0.0
-    };
-    let artist_contains = if track.artist.to_lowercase().contains(&query) {
-        150.0
-    } else {
-        0.0
-    };
-    let album_exact = if track.album.to_lowercase() == query {
-        200.0
-    } else {
-        0.0
-    };
-    let album_contains = if track.album.to_lowercase().contains(&query) {
-        100.0
-    } else {
-        0.0
-    };
+    // Cancel previous search if running
+    if let Some(handle) = this.current_search_handle.take() {
+        handle.abort();
+    }
 
-    title_exact + title_contains + artist_exact + artist_contains + album_exact + album_contains
-}
+    let obj_weak = this.obj().downgrade();
+    let handle = glib::MainContext::default().spawn_local(async move {
+        // Wait for debounce period
+        if !delay.is_zero() {
+            glib::timeout_future(delay).await;
+        }
 
-fn score_artist(artist: &Artist, query: &str) -> f32 {
-    let query = query.to_lowercase();
+        if let Some(obj) = obj_weak.upgrade() {
+            let this = obj.imp();
 
-    // Primary matches (high weight for artist-specific fields)
-    let name_exact = if artist.name.to_lowercase() == query {
-        1200.0
-    } else {
-        0.0
-    };
-    let name_contains = if artist.name.to_lowercase().contains(&query) {
-        600.0
-    } else {
-        0.0
-    };
+            // Check if this search is still relevant
+            if this.search_version.get() != current_version {
+                return;
+            }
 
-    name_exact + name_contains
-}
+            // "lyrics:" searches the lyrics instead of the metadata
+            if let Some(lyrics) = lyrics_query(&query) {
+                let manager = this.service_manager.borrow().clone();
+                if let Some(manager) = manager {
+                    match manager.search_lyrics(lyrics, 20).await {
+                        Ok(matches) => {
+                            if this.search_version.get() == current_version {
+                                update_lyrics_results(this, &matches);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Lyrics search error: {}", e);
+                            if this.search_version.get() == current_version {
+                                this.search_stack.set_visible_child_name("no_results_page");
+                            }
+                        }
+                    }
+                }
+                return;
+            }
 
-fn score_album(album: &Album, query: &str) -> f32 {
-    let query = query.to_lowercase();
+            // Perform search
+            let weights = this.search_weights();
+            if let Some(manager) = this.service_manager.borrow().as_ref() {
+                match manager.search_all(&query, Some(weights.clone()), 20, 0).await {
+                    Ok(results) => {
+                        // Verify search is still relevant
+                        if this.search_version.get() != current_version {
+                            return;
+                        }
+
+                        let obj_weak = obj_weak.clone();
+                        glib::MainContext::default().spawn_local(async move {
+                            if let Some(obj) = obj_weak.upgrade() {
+                                let this = obj.imp();
+                                update_search_results(this, &results, &query, &weights);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        eprintln!("Search error: {}", e);
+                        if this.search_version.get() == current_version {
+                            this.search_stack.set_visible_child_name("no_results_page");
+                        }
+                    }
+                }
+            }
+        }
+    });
 
-    // Primary matches (high weight for album-specific fields)
-    let title_exact = if album.title.to_lowercase() == query {
-        1200.0
-    } else {
-        0.0
-    };
-    let title_contains = if album.title.to_lowercase().contains(&query) {
-        600.0
-    } else {
-        0.0
-    };
+    this.current_search_handle.replace(Some(handle));
+}
 
-    // Secondary matches (lower weight for related fields)
-    let artist_exact = if album.artist.to_lowercase() == query {
-        300.0
-    } else {
-        0.0
-    };
-    let artist_contains = if album.artist.to_lowercase().contains(&query) {
-        150.0
-    } else {
-        0.0
-    };
+/// The lyrics to look for when `query` asks for a lyrics search, as in
+/// "lyrics: lonely road"
+pub(crate) fn lyrics_query(query: &str) -> Option<&str> {
+    const PREFIX: &str = "lyrics:";
+    let head = query.get(..PREFIX.len())?;
+    head.eq_ignore_ascii_case(PREFIX)
+        .then(|| query[PREFIX.len()..].trim())
+}
 
-    // Additional score for release year if query is a year
-    let year_score = if let Some(year) = album.year {
-        if query == year.to_string() {
-            400.0
-        } else {
-            0.0
+/// Pango markup for a lyrics excerpt with the matched words in bold
+fn snippet_markup(snippet: &str) -> String {
+    // Excerpts often span a line break, which reads fine as a slash
+    let snippet = snippet.trim().replace('\n', " / ");
+
+    let mut markup = String::new();
+    for (index, part) in snippet.split(LyricsMatch::HIGHLIGHT_START).enumerate() {
+        match part.split_once(LyricsMatch::HIGHLIGHT_END) {
+            Some((matched, rest)) if index > 0 => {
+                markup.push_str(&format!("<b>{}</b>", glib::markup_escape_text(matched)));
+                markup.push_str(&glib::markup_escape_text(rest));
+            }
+            _ => markup.push_str(&glib::markup_escape_text(part)),
         }
-    } else {
-        0.0
-    };
-
-    title_exact + title_contains + artist_exact + artist_contains + year_score
+    }
+    markup
 }
 
-fn determine_top_result(
-    results: &SearchResults,
-    query: &str,
-    window: &gtk::Window,
-) -> Option<gtk::Box> {
-    let mut best_result = None;
-    let mut best_score = -1.0;
-
-    // Score tracks
-    if let Some(track) = results.tracks.first() {
-        let score = score_track(&track.track, query);
-        if score > best_score {
-            best_score = score;
-            best_result = Some(create_track_card(&track.track, true, window));
-        }
+/// Show tracks found by their lyrics, each with the matching excerpt
+pub(crate) fn update_lyrics_results(this: &imp::NovaWindow, matches: &[LyricsMatch]) {
+    if let Some(container) = this.spinner_container.take() {
+        container.unparent();
     }
 
-    // Score artists
-    if let Some(artist) = results.artists.first() {
-        let score = score_artist(artist, query);
-        if score > best_score {
-            best_score = score;
-            best_result = Some(create_artist_card(artist, true));
-        }
+    if matches.is_empty() {
+        this.search_stack.set_visible_child_name("no_results_page");
+        return;
     }
 
-    // Score albums
-    if let Some(album) = results.albums.first() {
-        let score = score_album(album, query);
-        if score > best_score {
-            best_result = Some(create_album_card(album, true));
-        }
+    this.search_stack
+        .set_visible_child_name("search_results_scroll");
+    this.search_query.replace(None);
+    this.search_play_all_box.set_visible(false);
+    this.search_filter_bar.set_visible(false);
+
+    // Lyrics results are only tracks, so the other sections stay hidden
+    this.top_result_box.set_center_widget(None::<&gtk::Widget>);
+    if let Some(top_section) = this.top_result_box.parent().and_then(|p| p.parent()) {
+        top_section.set_visible(false);
+    }
+    this.artists_section.set_visible(false);
+    this.albums_section.set_visible(false);
+    while let Some(child) = this.tracks_box.first_child() {
+        this.tracks_box.remove(&child);
     }
 
-    best_result
+    let window = this.obj();
+    for found in matches {
+        let entry = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        entry.append(&create_track_card(
+            &found.item.track,
+            false,
+            window.upcast_ref::<gtk::Window>(),
+        ));
+
+        let snippet = gtk::Label::new(None);
+        snippet.set_markup(&snippet_markup(&found.snippet));
+        snippet.set_xalign(0.0);
+        snippet.set_wrap(true);
+        snippet.set_margin_start(12);
+        snippet.set_margin_bottom(6);
+        snippet.add_css_class("dim-label");
+        snippet.add_css_class("lyrics-snippet");
+        entry.append(&snippet);
+
+        this.tracks_box.append(&entry);
+    }
+    this.tracks_box.set_visible(true);
+    if let Some(track_section) = this.tracks_box.parent() {
+        track_section.set_visible(true);
+    }
 }
 
-fn score_item(primary: &str, secondary: &str, query: &str, weight: f32) -> f32 {
-    let query = query.to_lowercase();
-    let primary = primary.to_lowercase();
-    let secondary = secondary.to_lowercase();
-
-    let exact_match = if primary == query {
-        1000.0 * weight
-    } else {
-        0.0
-    };
-
-    let contains = if primary.contains(&query) {
-        500.0 * weight
-    } else {
-        0.0
-    };
-
-    let secondary_score = if !secondary.is_empty() {
-        if secondary == query {
-            250.0 * weight
-        } else if secondary.contains(&query) {
-            125.0 * weight
-        } else {
-            0.0
-        }
-    } else {
-        0.0
-    };
+fn determine_top_result(
+    results: &SearchResults,
+    query: &str,
+    weights: &SearchWeights,
+    window: &gtk::Window,
+) -> Option<gtk::Box> {
+    let best = ranking::top_result(results, query, weights)?;
 
-    exact_match + contains + secondary_score
+    Some(match best.result_type {
+        SearchResultType::Track(item) => create_track_card(&item.track, true, window),
+        SearchResultType::Artist(artist) => create_artist_card(&artist, true),
+        SearchResultType::Album(album) => create_album_card(&album, true),
+    })
 }
 
 pub(crate) fn create_loading_indicator() -> gtk::Box {
@@ -373,3 +608,49 @@ pub(crate) fn create_loading_indicator() -> gtk::Box {
 
     container
 }
+
+/// Queue every track matching the search shown, shuffled or in order of
+/// relevance. The page only shows the first few, so the search runs again
+/// with a higher limit.
+pub(crate) fn play_search_results(this: &imp::NovaWindow, shuffle: bool) {
+    let Some(query) = this.search_query.borrow().clone() else {
+        return;
+    };
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let weights = this.search_weights();
+    // Only what the results are narrowed to
+    let provider = this.search_provider.borrow().clone();
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let result = manager
+            .search_tracks(&query, Some(weights), PLAY_ALL_LIMIT)
+            .await
+            .map(|mut tracks| {
+                if let Some(provider) = &provider {
+                    tracks.retain(|item| &item.provider == provider);
+                }
+                tracks
+            });
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+
+        match result {
+            Ok(tracks) if tracks.is_empty() => this.show_toast("No songs to play"),
+            Ok(tracks) if shuffle => this.play_shuffled(tracks, QueueContext::Search(query)),
+            Ok(tracks) => {
+                if let Some(player) = &*this.player.borrow() {
+                    player.play_queue(tracks, QueueContext::Search(query));
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to queue search results: {}", e);
+                this.show_toast("Couldn't load the search results");
+            }
+        }
+    });
+}
@@ -0,0 +1,274 @@
+use crate::services::checkpoint::{self, Checkpoint};
+use crate::services::models::{PlayableItem, QueueContext};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use gtk::glib::Propagation;
+use std::time::Duration;
+
+/// What to do with a queue that is still going when Nova is closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnfinishedQueueAction {
+    Ask,
+    SavePlaylist,
+    Restore,
+    Discard,
+}
+
+impl UnfinishedQueueAction {
+    fn from_setting(value: &str) -> Self {
+        match value {
+            "save-playlist" => Self::SavePlaylist,
+            "restore" => Self::Restore,
+            "discard" => Self::Discard,
+            _ => Self::Ask,
+        }
+    }
+}
+
+/// Deal with an unfinished queue before the window closes. Returns Stop
+/// while the user is being asked; the window closes itself once they answer.
+pub(crate) fn handle_close_request(this: &imp::NovaWindow) -> Propagation {
+    // A queue that's resumed next time needs nothing doing with it
+    if this.quit_confirmed.get() || resumes_playback(this) {
+        return Propagation::Proceed;
+    }
+
+    let remaining = this
+        .player
+        .borrow()
+        .as_ref()
+        .and_then(|player| player.unfinished_queue());
+    let Some(remaining) = remaining else {
+        return Propagation::Proceed;
+    };
+    let track_ids: Vec<String> = remaining.iter().map(|item| item.track.id.clone()).collect();
+
+    match UnfinishedQueueAction::from_setting(&this.settings().string("unfinished-queue")) {
+        UnfinishedQueueAction::Ask => {
+            ask_about_queue(this, track_ids);
+            Propagation::Stop
+        }
+        action => {
+            keep_queue(this, action, &track_ids);
+            Propagation::Proceed
+        }
+    }
+}
+
+fn ask_about_queue(this: &imp::NovaWindow, track_ids: Vec<String>) {
+    let dialog = adw::AlertDialog::builder()
+        .heading("Keep Your Queue?")
+        .body(format!(
+            "{} tracks are still queued. Save them as a playlist, or pick up where you left \
+             off next time.",
+            track_ids.len()
+        ))
+        .build();
+    dialog.add_responses(&[
+        ("cancel", "_Cancel"),
+        ("discard", "_Discard"),
+        ("restore", "_Restore Next Time"),
+        ("save", "_Save as Playlist"),
+    ]);
+    dialog.set_response_appearance("discard", adw::ResponseAppearance::Destructive);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(None, move |_, response| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        let action = match response {
+            "save" => UnfinishedQueueAction::SavePlaylist,
+            "restore" => UnfinishedQueueAction::Restore,
+            "discard" => UnfinishedQueueAction::Discard,
+            // Cancelling keeps Nova open
+            _ => return,
+        };
+
+        keep_queue(this, action, &track_ids);
+        this.quit_confirmed.set(true);
+        obj.close();
+    });
+
+    dialog.present(Some(&*this.obj()));
+}
+
+fn keep_queue(this: &imp::NovaWindow, action: UnfinishedQueueAction, track_ids: &[String]) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+
+    match action {
+        UnfinishedQueueAction::SavePlaylist => {
+            let name = format!(
+                "Unfinished session {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M")
+            );
+            if let Err(e) = store.create_playlist(&name, track_ids) {
+                eprintln!("Failed to save the unfinished queue: {}", e);
+            }
+        }
+        UnfinishedQueueAction::Restore => {
+            if let Err(e) = store.save_queue(track_ids) {
+                eprintln!("Failed to keep the unfinished queue: {}", e);
+            }
+        }
+        UnfinishedQueueAction::Ask | UnfinishedQueueAction::Discard => {}
+    }
+}
+
+/// Put back the queue saved when Nova was last closed. Tracks are looked up
+/// again, so this runs once the providers are registered.
+pub(crate) fn restore_saved_queue(this: &imp::NovaWindow) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+    let track_ids = match store.take_saved_queue() {
+        Ok(track_ids) if !track_ids.is_empty() => track_ids,
+        Ok(_) => return,
+        Err(e) => {
+            eprintln!("Failed to load the saved queue: {}", e);
+            return;
+        }
+    };
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let mut items: Vec<PlayableItem> = Vec::with_capacity(track_ids.len());
+        for track_id in &track_ids {
+            match manager.get_track(track_id).await {
+                Ok(item) => items.push(item),
+                Err(e) => eprintln!("Dropping {} from the restored queue: {}", track_id, e),
+            }
+        }
+        if items.is_empty() {
+            return;
+        }
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        if let Some(player) = this.player.borrow().as_ref() {
+            let count = items.len();
+            player.load_queue(items, QueueContext::Restored);
+            this.show_toast(&format!("Restored {} tracks from your last session", count));
+        }
+    });
+}
+
+/// Save a checkpoint every "checkpoint-interval-seconds" for as long as the
+/// window is open
+pub(crate) fn schedule_checkpoint(this: &imp::NovaWindow) {
+    let interval = this.settings().int("checkpoint-interval-seconds").max(1) as u32;
+    let obj_weak = this.obj().downgrade();
+    glib::timeout_add_seconds_local_once(interval, move || {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        save_checkpoint(obj.imp());
+        schedule_checkpoint(obj.imp());
+    });
+}
+
+/// Whether the next launch picks playback up where this session leaves it
+fn resumes_playback(this: &imp::NovaWindow) -> bool {
+    this.settings().string("startup-page") == "resume"
+}
+
+/// Leave the queue and position for the next launch to resume from when
+/// the startup page says to, and otherwise nothing to restore
+pub(crate) fn end_session(this: &imp::NovaWindow) {
+    if resumes_playback(this) {
+        save_checkpoint(this);
+    } else {
+        checkpoint::clear();
+    }
+}
+
+fn save_checkpoint(this: &imp::NovaWindow) {
+    // Until the last session's checkpoint is restored, it's the one to keep
+    if this.pending_checkpoint.borrow().is_some() {
+        return;
+    }
+    let Some(player) = this.player.borrow().clone() else {
+        return;
+    };
+
+    let queue: Vec<String> = player
+        .remaining_queue()
+        .into_iter()
+        .map(|item| item.track.id)
+        .collect();
+    if queue.is_empty() {
+        checkpoint::clear();
+        return;
+    }
+    let checkpoint = Checkpoint {
+        saved_at: chrono::Utc::now(),
+        queue,
+        position: player.position().unwrap_or_default().as_secs(),
+    };
+    if let Err(e) = checkpoint::save(&checkpoint) {
+        eprintln!("Failed to save checkpoint: {}", e);
+    }
+}
+
+/// Put back the queue and position from a session that didn't exit
+/// cleanly, or from the last one when playback is resumed at startup.
+/// Tracks are looked up again, so this runs once the providers are
+/// registered.
+pub(crate) fn restore_checkpoint(this: &imp::NovaWindow) {
+    let Some(checkpoint) = this.pending_checkpoint.take() else {
+        return;
+    };
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+    println!("Restoring checkpoint from {}", checkpoint.saved_at);
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let mut items: Vec<PlayableItem> = Vec::with_capacity(checkpoint.queue.len());
+        for track_id in &checkpoint.queue {
+            match manager.get_track(track_id).await {
+                Ok(item) => items.push(item),
+                Err(e) => eprintln!("Dropping {} from the restored queue: {}", track_id, e),
+            }
+        }
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        let Some(player) = this.player.borrow().clone() else {
+            return;
+        };
+        if items.is_empty() {
+            return;
+        }
+
+        // The position only holds if the track it was in is still first
+        let position = if items[0].track.id == checkpoint.queue[0] {
+            Duration::from_secs(checkpoint.position)
+        } else {
+            Duration::ZERO
+        };
+        player.cue_queue(items, QueueContext::Restored, position);
+        if resumes_playback(this) {
+            // Now Playing, with the queue beside it
+            this.queue_toggle.set_active(true);
+            this.show_toast("Picked up where you left off");
+        } else {
+            this.show_toast("Restored your queue after Nova closed unexpectedly");
+        }
+    });
+}
@@ -0,0 +1,122 @@
+use crate::services::local::export_snippet;
+use crate::services::models::Track;
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::{gio, glib};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// What's playing, or None after telling the user nothing is
+fn now_playing(this: &imp::NovaWindow) -> Option<Track> {
+    let track = this
+        .player
+        .borrow()
+        .as_ref()
+        .and_then(|player| player.current_track());
+    if track.is_none() {
+        this.show_toast("Nothing is playing");
+    }
+    track
+}
+
+fn copy_text(this: &imp::NovaWindow, text: &str, message: &str) {
+    this.obj().clipboard().set_text(text);
+    this.show_toast(message);
+}
+
+/// Copy "Artist – Title" of what's playing
+pub(crate) fn copy_track_info(this: &imp::NovaWindow) {
+    if let Some(track) = now_playing(this) {
+        let text = format!("{} – {}", track.artist, track.title);
+        copy_text(this, &text, "Copied track info");
+    }
+}
+
+/// Copy a nova://track/ link to what's playing
+pub(crate) fn copy_track_link(this: &imp::NovaWindow) {
+    if let Some(track) = now_playing(this) {
+        let text = format!(
+            "nova://track/{}",
+            glib::Uri::escape_string(&track.id, None, false)
+        );
+        copy_text(this, &text, "Copied link");
+    }
+}
+
+/// Ask where to save a snippet of what's playing around where it is now,
+/// then encode it
+pub(crate) fn choose_snippet_file(this: &imp::NovaWindow) {
+    let Some(track) = now_playing(this) else {
+        return;
+    };
+    let position = this
+        .player
+        .borrow()
+        .as_ref()
+        .and_then(|player| player.position())
+        .unwrap_or_default();
+
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("Opus Audio"));
+    filter.add_pattern("*.opus");
+    let filters = gio::ListStore::new::<gtk::FileFilter>();
+    filters.append(&filter);
+
+    // Slashes would be taken for folders
+    let name = format!("{} - {}.opus", track.artist, track.title).replace('/', "-");
+    let dialog = gtk::FileDialog::builder()
+        .title("Export Snippet")
+        .modal(true)
+        .initial_name(name.as_str())
+        .filters(&filters)
+        .build();
+
+    let obj_weak = this.obj().downgrade();
+    dialog.save(
+        Some(&*this.obj()),
+        gio::Cancellable::NONE,
+        move |result| match result {
+            Ok(file) => {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return;
+                };
+                match file.path() {
+                    Some(path) => save_snippet(obj.imp(), track, position, path),
+                    None => obj.imp().show_toast("Choose a file on this device"),
+                }
+            }
+            Err(e) => {
+                if !e.matches(gtk::DialogError::Dismissed) {
+                    eprintln!("Failed to choose a snippet file: {}", e);
+                }
+            }
+        },
+    );
+}
+
+fn save_snippet(this: &imp::NovaWindow, track: Track, position: Duration, path: PathBuf) {
+    this.show_toast("Exporting snippet…");
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let dest = path.clone();
+        let result =
+            tokio::task::spawn_blocking(move || export_snippet(&track, position, &dest)).await;
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        match result {
+            Ok(Ok(())) => obj.imp().show_toast("Snippet exported"),
+            Ok(Err(e)) => {
+                eprintln!("Failed to export a snippet to {:?}: {}", path, e);
+                obj.imp()
+                    .show_toast(&format!("Couldn't export the snippet: {}", e));
+            }
+            Err(e) => {
+                eprintln!("Snippet export stopped: {}", e);
+                obj.imp().show_toast("Couldn't export the snippet");
+            }
+        }
+    });
+}
@@ -0,0 +1,167 @@
+use crate::services::models::{PinnedItem, PinnedKind};
+use crate::window::components::details::{attach_card_menu, show_album, show_artist, CardAction};
+use crate::window::components::playlists::{playlist_actions, reload_playlists, show_playlist};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::{gdk, glib};
+
+fn icon_for_kind(kind: PinnedKind) -> &'static str {
+    match kind {
+        PinnedKind::Album => "media-optical-symbolic",
+        PinnedKind::Artist => "avatar-default-symbolic",
+        PinnedKind::Playlist => "view-list-symbolic",
+        PinnedKind::PlaylistFolder => "folder-symbolic",
+    }
+}
+
+/// Rebuild the pinned rows below the fixed sidebar rows from the user store
+pub(crate) fn reload_pinned_items(this: &imp::NovaWindow) {
+    for row in this.pinned_rows.take() {
+        this.sidebar_list.remove(&row);
+    }
+
+    let items = match this.user_store.borrow().as_ref() {
+        Some(store) => store.pinned_items().unwrap_or_else(|e| {
+            eprintln!("Failed to load pinned items: {}", e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+
+    let rows: Vec<gtk::ListBoxRow> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| create_pinned_row(this, item, index))
+        .collect();
+    for row in &rows {
+        this.sidebar_list.append(row);
+    }
+
+    this.pinned_rows.replace(rows);
+    this.pinned_items.replace(items);
+}
+
+/// The pinned item shown by `row`, if it is one of the pinned rows
+pub(crate) fn pinned_item_for_row(
+    this: &imp::NovaWindow,
+    row: &gtk::ListBoxRow,
+) -> Option<PinnedItem> {
+    let index = this.pinned_rows.borrow().iter().position(|r| r == row)?;
+    this.pinned_items.borrow().get(index).cloned()
+}
+
+pub(crate) fn open_pinned_item(this: &imp::NovaWindow, item: &PinnedItem) {
+    match item.kind {
+        PinnedKind::Album => show_album(this, &item.provider, &item.item_id),
+        PinnedKind::Artist => show_artist(this, &item.provider, &item.item_id),
+        PinnedKind::Playlist => show_playlist(this, &item.item_id),
+        PinnedKind::PlaylistFolder => {
+            this.expanded_playlist_folders
+                .borrow_mut()
+                .insert(item.item_id.clone());
+            reload_playlists(this);
+            this.main_stack.set_visible_child_name("playlists");
+            this.home_button.remove_css_class("selected");
+        }
+    }
+}
+
+/// Pin `item` if it isn't pinned yet, otherwise unpin it
+pub(crate) fn toggle_pin(this: &imp::NovaWindow, item: &PinnedItem) {
+    let Some(store) = this.user_store.borrow().clone() else {
+        return;
+    };
+
+    let result = if is_pinned(this, item) {
+        store.unpin(item)
+    } else {
+        store.pin(item)
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to update pin for {}: {}", item.title, e);
+        this.show_toast("Couldn't update the sidebar");
+        return;
+    }
+
+    reload_pinned_items(this);
+}
+
+pub(crate) fn is_pinned(this: &imp::NovaWindow, item: &PinnedItem) -> bool {
+    this.pinned_items.borrow().iter().any(|pinned| {
+        pinned.kind == item.kind
+            && pinned.provider == item.provider
+            && pinned.item_id == item.item_id
+    })
+}
+
+/// Move the pin at `from` to `to` and persist the new order
+fn move_pin(this: &imp::NovaWindow, from: usize, to: usize) {
+    let mut items = this.pinned_items.borrow().clone();
+    if from >= items.len() || to >= items.len() || from == to {
+        return;
+    }
+    let item = items.remove(from);
+    items.insert(to, item);
+
+    if let Some(store) = this.user_store.borrow().as_ref() {
+        if let Err(e) = store.reorder_pins(&items) {
+            eprintln!("Failed to reorder pinned items: {}", e);
+        }
+    }
+
+    reload_pinned_items(this);
+}
+
+fn create_pinned_row(this: &imp::NovaWindow, item: &PinnedItem, index: usize) -> gtk::ListBoxRow {
+    let row = adw::ActionRow::builder()
+        .title(&item.title)
+        .activatable(true)
+        .build();
+    if let Some(ref subtitle) = item.subtitle {
+        row.set_subtitle(subtitle);
+    }
+    row.add_prefix(&gtk::Image::from_icon_name(icon_for_kind(item.kind)));
+    row.add_css_class("sidebar-row");
+    row.add_css_class("pinned-row");
+
+    // Drag onto another pinned row to reorder
+    let drag_source = gtk::DragSource::new();
+    drag_source.set_actions(gdk::DragAction::MOVE);
+    drag_source.connect_prepare(move |_, _, _| {
+        Some(gdk::ContentProvider::for_value(&(index as u32).to_value()))
+    });
+    row.add_controller(drag_source);
+
+    let drop_target = gtk::DropTarget::new(u32::static_type(), gdk::DragAction::MOVE);
+    let obj_weak = this.obj().downgrade();
+    drop_target.connect_drop(move |_, value, _, _| {
+        let (Some(obj), Ok(from)) = (obj_weak.upgrade(), value.get::<u32>()) else {
+            return false;
+        };
+        // Reloading replaces this row, so wait until the drop has finished
+        glib::idle_add_local_once(move || move_pin(obj.imp(), from as usize, index));
+        true
+    });
+    row.add_controller(drop_target);
+
+    // Right-click to unpin, and for playlists to rename, copy or delete
+    // them without opening them first
+    let pinned = item.clone();
+    let mut actions = vec![CardAction::new("Unpin", move |this| {
+        // Reloading replaces this row, so wait until the menu has closed
+        let obj_weak = this.obj().downgrade();
+        let pinned = pinned.clone();
+        glib::idle_add_local_once(move || {
+            if let Some(obj) = obj_weak.upgrade() {
+                toggle_pin(obj.imp(), &pinned);
+            }
+        });
+    })];
+    if item.kind == PinnedKind::Playlist {
+        actions.extend(playlist_actions(&item.item_id));
+    }
+    attach_card_menu(&row, actions);
+
+    row.upcast()
+}
@@ -0,0 +1,164 @@
+use crate::library_folders::library_folders;
+use crate::services::tag_cleanup::{needs_cleanup, suggest, Suggestion};
+use crate::services::writeback::{self, TagChange};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use std::collections::HashSet;
+
+/// Look for tracks without an artist or album and offer what their folder
+/// and file names suggest, for the user to accept or leave out
+pub(crate) fn show_tag_cleanup(this: &imp::NovaWindow) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+    let roots = library_folders(&this.settings());
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let tracks = match manager.get_all_tracks().await {
+            Ok(items) => items,
+            Err(e) => {
+                eprintln!("Failed to load tracks to clean up: {}", e);
+                Vec::new()
+            }
+        };
+        let missing: Vec<_> = tracks
+            .into_iter()
+            .map(|item| item.track)
+            .filter(needs_cleanup)
+            .collect();
+        let suggestions: Vec<Suggestion> = missing
+            .iter()
+            .filter_map(|track| suggest(track, &roots))
+            .collect();
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        if missing.is_empty() {
+            this.show_toast("Every track has an artist and album");
+        } else if suggestions.is_empty() {
+            this.show_toast(&format!(
+                "Nothing to suggest for the {} tracks missing an artist or album",
+                missing.len()
+            ));
+        } else {
+            present_suggestions(this, missing.len(), suggestions);
+        }
+    });
+}
+
+fn present_suggestions(this: &imp::NovaWindow, missing: usize, suggestions: Vec<Suggestion>) {
+    let rows = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    rows.add_css_class("boxed-list");
+
+    let mut checks = Vec::with_capacity(suggestions.len());
+    for suggestion in &suggestions {
+        let mut proposed = Vec::new();
+        if let Some(artist) = &suggestion.artist {
+            proposed.push(format!("Artist: {}", artist));
+        }
+        if let Some(album) = &suggestion.album {
+            proposed.push(format!("Album: {}", album));
+        }
+
+        let check = gtk::CheckButton::builder()
+            .active(true)
+            .valign(gtk::Align::Center)
+            .build();
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&suggestion.track.title))
+            .subtitle(glib::markup_escape_text(&proposed.join(" · ")))
+            .activatable_widget(&check)
+            .build();
+        row.add_prefix(&check);
+        rows.append(&row);
+        checks.push(check);
+    }
+
+    let scroller = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .min_content_height(320)
+        .child(&rows)
+        .build();
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Clean Up Unknown Artists and Albums")
+        .body(format!(
+            "{} of the {} tracks missing an artist or album have a guess from their folder and \
+             file names. Untick any that are wrong.",
+            suggestions.len(),
+            missing
+        ))
+        .extra_child(&scroller)
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("apply", "_Apply")]);
+    dialog.set_response_appearance("apply", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("apply"));
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("apply"), move |_, _| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let accepted: Vec<Suggestion> = suggestions
+            .iter()
+            .zip(&checks)
+            .filter(|(_, check)| check.is_active())
+            .map(|(suggestion, _)| suggestion.clone())
+            .collect();
+        apply_suggestions(obj.imp(), accepted);
+    });
+
+    dialog.present(Some(&*this.obj()));
+}
+
+/// Apply the accepted changes to the library, and record them like any
+/// other tag edit so they reach the files when the write-back policy allows
+fn apply_suggestions(this: &imp::NovaWindow, accepted: Vec<Suggestion>) {
+    let (Some(store), Some(manager)) = (
+        this.user_store.borrow().clone(),
+        this.service_manager.borrow().clone(),
+    ) else {
+        this.show_toast("Tag edits need the library database");
+        return;
+    };
+    let policy = this.write_back_policy();
+    let edits: Vec<(String, Vec<TagChange>)> = accepted
+        .iter()
+        .map(|suggestion| (suggestion.track.id.clone(), suggestion.changes()))
+        .collect();
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let edited: HashSet<String> = match manager.edit_tracks("local", &edits).await {
+            Ok(ids) => ids.into_iter().collect(),
+            Err(e) => {
+                eprintln!("Failed to apply tag suggestions: {}", e);
+                HashSet::new()
+            }
+        };
+        // Tracks the library no longer has are left alone
+        for suggestion in accepted.iter().filter(|s| edited.contains(&s.track.id)) {
+            let recorded = suggestion.changes().into_iter().try_for_each(|change| {
+                writeback::record_change(&store, policy, &suggestion.track, change)
+            });
+            if let Err(e) = recorded {
+                eprintln!("Failed to record edits to {}: {}", suggestion.track.title, e);
+            }
+        }
+
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        obj.imp().flush_write_backs();
+        obj.imp()
+            .show_toast(&format!("Updated {} of {} tracks", edited.len(), accepted.len()));
+    });
+}
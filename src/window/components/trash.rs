@@ -0,0 +1,159 @@
+use crate::services::models::{PlaybackSource, Track};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::{gio, glib};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Ask before moving `track`'s file to the trash
+pub(crate) fn confirm_trash(this: &imp::NovaWindow, track: &Track) {
+    let PlaybackSource::Local { path, segment, .. } = &track.source else {
+        return;
+    };
+    let path = path.clone();
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // Chapters share the file, so they all go with it
+    let body = if segment.is_some() {
+        format!(
+            "{} holds every chapter of this set, and all of them leave the library with it.",
+            file_name
+        )
+    } else {
+        format!(
+            "{} leaves the library. It can be restored from the trash.",
+            file_name
+        )
+    };
+    let dialog = adw::AlertDialog::builder()
+        .heading(format!("Move “{}” to the Trash?", track.title))
+        .body(body)
+        .build();
+    dialog.add_responses(&[("cancel", "_Cancel"), ("trash", "_Move to Trash")]);
+    dialog.set_response_appearance("trash", adw::ResponseAppearance::Destructive);
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    dialog.connect_response(Some("trash"), move |_, _| {
+        if let Some(obj) = obj_weak.upgrade() {
+            trash_file(obj.imp(), path.clone());
+        }
+    });
+    dialog.present(Some(&*this.obj()));
+}
+
+fn trash_file(this: &imp::NovaWindow, path: PathBuf) {
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let result = gio::File::for_path(&path)
+            .trash_future(glib::Priority::DEFAULT)
+            .await;
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to move {:?} to the trash: {}", path, e);
+            obj.imp()
+                .show_toast(&format!("Couldn't move the file to the trash: {}", e));
+            return;
+        }
+
+        println!("Moved {:?} to the trash", path);
+        rescan(obj.imp(), path.clone());
+        let toast = adw::Toast::builder()
+            .title("Moved to the trash")
+            .button_label("Undo")
+            .build();
+        let obj_weak = obj.downgrade();
+        toast.connect_button_clicked(move |_| {
+            if let Some(obj) = obj_weak.upgrade() {
+                restore_file(obj.imp(), path.clone());
+            }
+        });
+        obj.imp().toast_overlay.add_toast(toast);
+    });
+}
+
+fn restore_file(this: &imp::NovaWindow, path: PathBuf) {
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let dest = path.clone();
+        let result = tokio::task::spawn_blocking(move || restore_from_trash(&dest)).await;
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        match result {
+            Ok(Ok(())) => {
+                obj.imp().show_toast("Restored from the trash");
+                rescan(obj.imp(), path);
+            }
+            Ok(Err(e)) => {
+                eprintln!("Failed to restore {:?} from the trash: {}", path, e);
+                obj.imp()
+                    .show_toast(&format!("Couldn't restore the file: {}", e));
+            }
+            Err(e) => {
+                eprintln!("Restoring {:?} stopped: {}", path, e);
+                obj.imp().show_toast("Couldn't restore the file");
+            }
+        }
+    });
+}
+
+/// Drop the tracks of `path` when it's gone, or add them back when it
+/// came back
+fn rescan(this: &imp::NovaWindow, path: PathBuf) {
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+    glib::MainContext::default().spawn_local(async move {
+        if let Err(e) = manager.rescan_files("local", &[path]).await {
+            eprintln!("Failed to scan the library after trashing: {}", e);
+        }
+    });
+}
+
+/// Move the most recently trashed file that came from `path` back there.
+/// GIO has no call for this, so it goes through trash:///, which knows
+/// where each file came from on desktops that keep a freedesktop trash.
+fn restore_from_trash(path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if path.exists() {
+        return Err("Another file has taken its place".into());
+    }
+    let trash = gio::File::for_uri("trash:///");
+    let children = trash
+        .enumerate_children(
+            "standard::name,trash::orig-path,trash::deletion-date",
+            gio::FileQueryInfoFlags::NONE,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|_| "The trash can't be read here")?;
+
+    let mut latest: Option<(gio::File, glib::DateTime)> = None;
+    for info in children {
+        let info = info?;
+        let orig_path = info.attribute_byte_string("trash::orig-path");
+        if orig_path.as_deref().map(Path::new) != Some(path) {
+            continue;
+        }
+        let Some(deleted) = info.deletion_date() else {
+            continue;
+        };
+        if latest.as_ref().map_or(true, |(_, date)| deleted > *date) {
+            latest = Some((trash.child(info.name()), deleted));
+        }
+    }
+
+    let (trashed, _) = latest.ok_or("It's no longer in the trash")?;
+    trashed.move_(
+        &gio::File::for_path(path),
+        gio::FileCopyFlags::NONE,
+        gio::Cancellable::NONE,
+        None,
+    )?;
+    Ok(())
+}
@@ -0,0 +1,417 @@
+use crate::services::local::{waveform_peaks, PEAK_INTERVAL_MS};
+use crate::services::models::{PlaybackSource, Track, Trim};
+use crate::window::imp;
+use crate::window::utils::format::format_duration;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::glib;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+// Width of the waveform zoomed all the way out, in pixels
+const BASE_WIDTH: i32 = 520;
+const WAVEFORM_HEIGHT: i32 = 96;
+const MAX_ZOOM: i32 = 32;
+// Peaks below this, about -48 dBFS, count as silence
+const SILENCE_LEVEL: f32 = 0.004;
+// Drags shorter than this, in pixels, are clicks
+const CLICK_DISTANCE: f64 = 3.0;
+
+/// A track's waveform and the stretch of it selected to play
+struct Selection {
+    peaks: Vec<f32>,
+    start_ms: Cell<u64>,
+    end_ms: Cell<u64>,
+}
+
+impl Selection {
+    fn length_ms(&self) -> u64 {
+        self.peaks.len() as u64 * PEAK_INTERVAL_MS
+    }
+
+    fn set(&self, a: u64, b: u64) {
+        let length = self.length_ms();
+        self.start_ms.set(a.min(b).min(length));
+        self.end_ms.set(a.max(b).min(length));
+    }
+
+    /// Stretch from the first sound to the last
+    fn without_silence(&self) -> Option<(u64, u64)> {
+        let first = self.peaks.iter().position(|&peak| peak > SILENCE_LEVEL)?;
+        let last = self.peaks.iter().rposition(|&peak| peak > SILENCE_LEVEL)?;
+        Some((
+            first as u64 * PEAK_INTERVAL_MS,
+            (last as u64 + 1) * PEAK_INTERVAL_MS,
+        ))
+    }
+
+    /// The selection as a trim, or None when it covers the whole track
+    fn trim(&self) -> Option<Trim> {
+        let (start_ms, end_ms) = (self.start_ms.get(), self.end_ms.get());
+        // Within a peak of either end is as good as the end
+        let trim = Trim {
+            start_ms: if start_ms < PEAK_INTERVAL_MS {
+                0
+            } else {
+                start_ms
+            },
+            end_ms: (end_ms + PEAK_INTERVAL_MS < self.length_ms()).then_some(end_ms),
+        };
+        (trim != Trim::default()).then_some(trim)
+    }
+}
+
+/// Show the waveform of `track` to pick where it starts and stops playing,
+/// such as past a silent intro or before a corrupted ending. The selected
+/// stretch can be played before it's saved.
+pub(crate) fn show_trim_editor(this: &imp::NovaWindow, track: &Track) {
+    let PlaybackSource::Local { path, segment, .. } = &track.source else {
+        this.show_toast("Start and end can only be set for files in your library");
+        return;
+    };
+
+    let spinner = gtk::Spinner::builder()
+        .spinning(true)
+        .height_request(WAVEFORM_HEIGHT)
+        .build();
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    content.append(&spinner);
+
+    let dialog = adw::AlertDialog::builder()
+        .heading("Start and End")
+        .body(track.title.as_str())
+        .extra_child(&content)
+        .prefer_wide_layout(true)
+        .build();
+    dialog.add_responses(&[
+        ("cancel", "_Cancel"),
+        ("clear", "Play _All of It"),
+        ("save", "_Save"),
+    ]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_response_enabled("save", false);
+    dialog.set_close_response("cancel");
+
+    let obj_weak = this.obj().downgrade();
+    let track_clone = track.clone();
+    dialog.connect_response(Some("clear"), move |_, _| {
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        if let Some(player) = obj.imp().player.borrow().as_ref() {
+            player.set_trim(&track_clone, None);
+        }
+    });
+    dialog.present(Some(&*this.obj()));
+
+    let path = path.clone();
+    // Chapters are drawn from their stretch of the file
+    let segment = *segment;
+    let track = track.clone();
+    let obj_weak = this.obj().downgrade();
+    let dialog_weak = dialog.downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        let peaks = match tokio::task::spawn_blocking(move || waveform_peaks(&path)).await {
+            Ok(result) => result,
+            Err(e) => Err(e.into()),
+        };
+        let (Some(obj), Some(dialog)) = (obj_weak.upgrade(), dialog_weak.upgrade()) else {
+            return;
+        };
+        content.remove(&spinner);
+        let mut peaks = match peaks {
+            Ok(peaks) => peaks,
+            Err(e) => {
+                eprintln!("Failed to draw the waveform of {}: {}", track.title, e);
+                dialog.set_body(&format!("Couldn't read this track: {}", e));
+                return;
+            }
+        };
+        if let Some(segment) = segment {
+            let from = (segment.start_ms / PEAK_INTERVAL_MS) as usize;
+            let to = (segment.end_ms / PEAK_INTERVAL_MS) as usize;
+            peaks = peaks
+                .get(from.min(peaks.len())..to.min(peaks.len()))
+                .unwrap_or_default()
+                .to_vec();
+        }
+        if peaks.is_empty() {
+            dialog.set_body("This track has no audio to show");
+            return;
+        }
+
+        let selection = Rc::new(Selection {
+            peaks,
+            start_ms: Cell::new(0),
+            end_ms: Cell::new(0),
+        });
+        let trim = obj
+            .imp()
+            .player
+            .borrow()
+            .as_ref()
+            .and_then(|player| player.trim(&track.id))
+            .unwrap_or_default();
+        selection.set(trim.start_ms, trim.end_ms.unwrap_or(u64::MAX));
+
+        content.append(&build_editor(obj.imp(), &track, &selection));
+        dialog.set_response_enabled("save", true);
+
+        let obj_weak = obj.downgrade();
+        dialog.connect_response(Some("save"), move |_, _| {
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            if let Some(player) = obj.imp().player.borrow().as_ref() {
+                player.set_trim(&track, selection.trim());
+            }
+        });
+    });
+}
+
+fn build_editor(this: &imp::NovaWindow, track: &Track, selection: &Rc<Selection>) -> gtk::Box {
+    let area = gtk::DrawingArea::builder()
+        .content_width(BASE_WIDTH)
+        .content_height(WAVEFORM_HEIGHT)
+        .build();
+    let selection_clone = selection.clone();
+    area.set_draw_func(move |area, cr, width, height| {
+        draw_waveform(area, cr, width, height, &selection_clone);
+    });
+    let scroller = gtk::ScrolledWindow::builder()
+        .vscrollbar_policy(gtk::PolicyType::Never)
+        .min_content_width(BASE_WIDTH)
+        .child(&area)
+        .build();
+
+    let summary = gtk::Label::builder().xalign(0.0).hexpand(true).build();
+    summary.add_css_class("dim-label");
+    summary.add_css_class("numeric");
+    let update = {
+        let (area, summary, selection) = (area.clone(), summary.clone(), selection.clone());
+        Rc::new(move || {
+            summary.set_label(&describe(&selection));
+            area.queue_draw();
+        })
+    };
+    update();
+
+    // Dragging selects a stretch, and clicking moves the nearer end there
+    let drag = gtk::GestureDrag::new();
+    let (area_clone, selection_clone, update_clone) =
+        (area.clone(), selection.clone(), update.clone());
+    drag.connect_drag_update(move |gesture, dx, _| {
+        let Some((x, _)) = gesture.start_point() else {
+            return;
+        };
+        if dx.abs() < CLICK_DISTANCE {
+            return;
+        }
+        let length = selection_clone.length_ms();
+        selection_clone.set(
+            ms_at(&area_clone, x, length),
+            ms_at(&area_clone, x + dx, length),
+        );
+        update_clone();
+    });
+    let (area_clone, selection_clone, update_clone) =
+        (area.clone(), selection.clone(), update.clone());
+    drag.connect_drag_end(move |gesture, dx, _| {
+        let Some((x, _)) = gesture.start_point() else {
+            return;
+        };
+        if dx.abs() >= CLICK_DISTANCE {
+            return;
+        }
+        let at = ms_at(&area_clone, x, selection_clone.length_ms());
+        let (start, end) = (selection_clone.start_ms.get(), selection_clone.end_ms.get());
+        if at.abs_diff(start) <= at.abs_diff(end) {
+            selection_clone.set(at, end);
+        } else {
+            selection_clone.set(start, at);
+        }
+        update_clone();
+    });
+    area.add_controller(drag);
+
+    let silence_button = gtk::Button::builder()
+        .label("Skip Silence")
+        .tooltip_text("Select from the first sound to the last")
+        .sensitive(selection.without_silence().is_some())
+        .build();
+    let (selection_clone, update_clone) = (selection.clone(), update.clone());
+    silence_button.connect_clicked(move |_| {
+        if let Some((start, end)) = selection_clone.without_silence() {
+            selection_clone.set(start, end);
+            update_clone();
+        }
+    });
+
+    let play_button = gtk::Button::builder()
+        .icon_name("media-playback-start-symbolic")
+        .tooltip_text("Play Selection")
+        .build();
+    // Pauses playback once the selection has played
+    let stop_timer: Rc<RefCell<Option<glib::SourceId>>> = Rc::default();
+    let obj_weak = this.obj().downgrade();
+    let (track, selection_clone) = (track.clone(), selection.clone());
+    play_button.connect_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            play_selection(obj.imp(), &track, &selection_clone, &stop_timer);
+        }
+    });
+
+    let zoom_out_button = gtk::Button::builder()
+        .icon_name("zoom-out-symbolic")
+        .tooltip_text("Zoom Out")
+        .sensitive(false)
+        .build();
+    let zoom_in_button = gtk::Button::builder()
+        .icon_name("zoom-in-symbolic")
+        .tooltip_text("Zoom In")
+        .build();
+    let zoom = Rc::new(Cell::new(1));
+    for (button, zoom_in) in [(&zoom_out_button, false), (&zoom_in_button, true)] {
+        let (area, scroller, selection, zoom) = (
+            area.clone(),
+            scroller.clone(),
+            selection.clone(),
+            zoom.clone(),
+        );
+        let (zoom_out_button, zoom_in_button) = (zoom_out_button.clone(), zoom_in_button.clone());
+        button.connect_clicked(move |_| {
+            let level = if zoom_in {
+                (zoom.get() * 2).min(MAX_ZOOM)
+            } else {
+                (zoom.get() / 2).max(1)
+            };
+            zoom.set(level);
+            zoom_out_button.set_sensitive(level > 1);
+            zoom_in_button.set_sensitive(level < MAX_ZOOM);
+            area.set_content_width(BASE_WIDTH * level);
+
+            // Keep the start of the selection in view once the new width
+            // has been allocated
+            let at = selection.start_ms.get() as f64 / selection.length_ms().max(1) as f64;
+            let adjustment = scroller.hadjustment();
+            glib::idle_add_local_once(move || {
+                let width = (BASE_WIDTH * level) as f64;
+                adjustment.set_value(at * width - BASE_WIDTH as f64 / 4.0);
+            });
+        });
+    }
+
+    let controls = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    controls.append(&summary);
+    controls.append(&silence_button);
+    controls.append(&play_button);
+    controls.append(&zoom_out_button);
+    controls.append(&zoom_in_button);
+
+    let editor = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    editor.append(&scroller);
+    editor.append(&controls);
+    editor
+}
+
+/// Time in the track `x` pixels into the waveform
+fn ms_at(area: &gtk::DrawingArea, x: f64, length_ms: u64) -> u64 {
+    let fraction = (x / area.width().max(1) as f64).clamp(0.0, 1.0);
+    (fraction * length_ms as f64) as u64
+}
+
+fn describe(selection: &Selection) -> String {
+    let seconds = |ms: u64| format_duration((ms / 1000) as u32);
+    let (start, end) = (selection.start_ms.get(), selection.end_ms.get());
+    format!(
+        "Plays {} to {}, {} of {}",
+        seconds(start),
+        seconds(end),
+        seconds(end - start),
+        seconds(selection.length_ms())
+    )
+}
+
+/// A bar a pixel wide for the loudest peak under each column, faint where
+/// it's outside the selection
+fn draw_waveform(
+    area: &gtk::DrawingArea,
+    cr: &gtk::cairo::Context,
+    width: i32,
+    height: i32,
+    selection: &Selection,
+) {
+    let peaks = &selection.peaks;
+    let (columns, height) = (width.max(1) as usize, height as f64);
+    let length = selection.length_ms().max(1) as f64;
+    let start = selection.start_ms.get() as f64 / length * width as f64;
+    let end = selection.end_ms.get() as f64 / length * width as f64;
+    let color = area.color();
+    let set_alpha = |alpha: f64| {
+        cr.set_source_rgba(
+            color.red().into(),
+            color.green().into(),
+            color.blue().into(),
+            color.alpha() as f64 * alpha,
+        );
+    };
+
+    for column in 0..columns {
+        let from = column * peaks.len() / columns;
+        let to = ((column + 1) * peaks.len() / columns).clamp(from + 1, peaks.len());
+        let peak = peaks[from..to].iter().copied().fold(0.0, f32::max) as f64;
+        let bar = (peak * height).max(1.0);
+        let x = column as f64;
+        set_alpha(if x >= start && x < end { 1.0 } else { 0.3 });
+        cr.rectangle(x, (height - bar) / 2.0, 1.0, bar);
+        if let Err(e) = cr.fill() {
+            eprintln!("Failed to draw the waveform: {}", e);
+            return;
+        }
+    }
+
+    // Lines where it starts and stops
+    set_alpha(1.0);
+    for x in [start, end] {
+        cr.rectangle(x.min(width as f64 - 2.0).max(0.0), 0.0, 2.0, height);
+    }
+    if let Err(e) = cr.fill() {
+        eprintln!("Failed to draw the waveform: {}", e);
+    }
+}
+
+/// Play the selected stretch of `track`, pausing where it ends unless
+/// something else is playing by then
+fn play_selection(
+    this: &imp::NovaWindow,
+    track: &Track,
+    selection: &Selection,
+    stop_timer: &Rc<RefCell<Option<glib::SourceId>>>,
+) {
+    let Some(player) = this.player.borrow().clone() else {
+        return;
+    };
+    if let Some(timer) = stop_timer.take() {
+        timer.remove();
+    }
+    let (start, end) = (selection.start_ms.get(), selection.end_ms.get());
+    if let Err(e) = player.play_from(track, Duration::from_millis(start)) {
+        eprintln!("Failed to play a selection of {}: {}", track.title, e);
+        this.show_toast(&format!("Couldn't play “{}”", track.title));
+        return;
+    }
+
+    let track_id = track.id.clone();
+    let stop_timer_clone = stop_timer.clone();
+    let timer = glib::timeout_add_local_once(Duration::from_millis(end - start), move || {
+        stop_timer_clone.take();
+        let is_current = player
+            .current_track()
+            .is_some_and(|current| current.id == track_id);
+        if is_current && player.is_playing() {
+            player.pause();
+        }
+    });
+    stop_timer.replace(Some(timer));
+}
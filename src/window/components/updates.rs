@@ -0,0 +1,127 @@
+use crate::config::VERSION;
+use crate::services::updates::{newer_release, Delivery, Release};
+use crate::window::imp;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::{gio, glib};
+use std::time::Duration;
+
+// Least time between automatic checks
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Look for a newer release at startup, at most once a day, while
+/// "check-for-updates" is on
+pub(crate) fn setup_update_check(this: &imp::NovaWindow) {
+    check_if_due(this);
+    let obj_weak = this.obj().downgrade();
+    this.settings()
+        .connect_changed(Some("check-for-updates"), move |_, _| {
+            if let Some(obj) = obj_weak.upgrade() {
+                check_if_due(obj.imp());
+            }
+        });
+}
+
+fn check_if_due(this: &imp::NovaWindow) {
+    let settings = this.settings();
+    if !settings.boolean("check-for-updates") {
+        return;
+    }
+    let now = glib::real_time() / 1_000_000;
+    let last = settings.int64("last-update-check");
+    if now - last < CHECK_INTERVAL.as_secs() as i64 {
+        return;
+    }
+    let Some(manager) = this.service_manager.borrow().clone() else {
+        return;
+    };
+
+    let obj_weak = this.obj().downgrade();
+    glib::MainContext::default().spawn_local(async move {
+        if !manager.network_conditions().await.available {
+            return;
+        }
+        let result = tokio::spawn(async { newer_release(VERSION).await }).await;
+        let Some(obj) = obj_weak.upgrade() else {
+            return;
+        };
+        let this = obj.imp();
+        let release = match result {
+            Ok(Ok(release)) => release,
+            Ok(Err(e)) => {
+                eprintln!("Failed to check for updates: {}", e);
+                return;
+            }
+            Err(e) => {
+                eprintln!("Update check stopped: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = this.settings().set_int64("last-update-check", now) {
+            eprintln!("Failed to save the time of the update check: {}", e);
+        }
+        // Each release is only announced once
+        if let Some(release) = release {
+            if this.settings().string("announced-update") != release.version {
+                let _ = this
+                    .settings()
+                    .set_string("announced-update", &release.version);
+                show_update_toast(this, release);
+            }
+        }
+    });
+}
+
+fn show_update_toast(this: &imp::NovaWindow, release: Release) {
+    let toast = adw::Toast::builder()
+        .title(glib::markup_escape_text(&format!(
+            "Nova {} is available",
+            release.version.trim_start_matches('v')
+        )))
+        .button_label("What’s New")
+        .timeout(0)
+        .build();
+    let obj_weak = this.obj().downgrade();
+    toast.connect_button_clicked(move |_| {
+        if let Some(obj) = obj_weak.upgrade() {
+            show_release_notes(obj.imp(), &release);
+        }
+    });
+    this.toast_overlay.add_toast(toast);
+}
+
+/// The release's notes, with how the update arrives for this install
+fn show_release_notes(this: &imp::NovaWindow, release: &Release) {
+    let notes = gtk::Label::builder()
+        .label(release.notes.trim())
+        .wrap(true)
+        .selectable(true)
+        .xalign(0.0)
+        .valign(gtk::Align::Start)
+        .build();
+    let scroll = gtk::ScrolledWindow::builder()
+        .child(&notes)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .min_content_height(240)
+        .max_content_height(400)
+        .propagate_natural_height(true)
+        .build();
+
+    let heading = release.name.as_deref().unwrap_or(&release.version);
+    let dialog = adw::AlertDialog::builder()
+        .heading(heading)
+        .body(Delivery::detect().instructions())
+        .extra_child(&scroll)
+        .build();
+    dialog.add_responses(&[("close", "_Close"), ("open", "_Open Release Page")]);
+    dialog.set_default_response(Some("close"));
+    dialog.set_close_response("close");
+
+    let url = release.url.clone();
+    dialog.connect_response(Some("open"), move |_, _| {
+        if let Err(e) = gio::AppInfo::launch_default_for_uri(&url, None::<&gio::AppLaunchContext>) {
+            eprintln!("Failed to open {}: {}", url, e);
+        }
+    });
+    dialog.present(Some(&*this.obj()));
+}
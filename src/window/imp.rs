@@ -1,11 +1,82 @@
 use super::components::{
     cards::{create_album_card, create_artist_card, create_track_card, create_type_label},
-    search::{create_loading_indicator, show_loading_state, update_search_results},
+    decades::{setup_decade_banner, show_decade},
+    details::{
+        attach_go_to_menu, queue_missing_tracks, show_artist_for_track, shuffle_album,
+        shuffle_artist, update_detail_stats, update_pin_button,
+    },
+    dj_mode::{analyze_tempos, setup_dj_mode},
+    downloads::{setup_downloads_watch, DownloadsWatch},
+    export::choose_export_file,
+    history::{add_history_to_playlist, reload_history, reload_skipped},
+    home::reload_home,
+    sidebar::{
+        is_pinned, open_pinned_item, pinned_item_for_row, reload_pinned_items, toggle_pin,
+    },
+    tag_cleanup::show_tag_cleanup,
+    updates::setup_update_check,
+    gain_stages::show_gain_stages,
+    player_bar::{apply_player_bar_layout, PlayerBarLayout},
+    playing_marks::{setup_playing_marks, PlayingMark},
+    playlist_import::choose_playlist_file,
+    playlists::{
+        library_changed, prompt_new_playlist, reload_playlists, setup_playlist_page,
+        show_mix_dialog, PlaylistEntry,
+    },
+    queue_list::setup_queue_list,
+    recap::show_recap,
+    recovery::{report_problem, show_next_problem, Problem},
+    session::{
+        end_session, handle_close_request, restore_checkpoint, restore_saved_queue,
+        schedule_checkpoint,
+    },
+    scrobbling::setup_scrobbling,
+    share::{choose_snippet_file, copy_track_info, copy_track_link},
+    mpris::setup_mpris,
+    library_filter::{setup_library_filters, start_page_filter},
+    navigation::{
+        focus_search, leave_search, setup_page_memory, show_search_page, show_startup_page,
+    },
+    organize::show_organize_files,
+    search::{
+        create_loading_indicator, play_search_results, setup_search_filters, start_search,
+        SEARCH_DELAY,
+    },
 };
 use super::utils::ui;
-use crate::services::{LocalMusicProvider, ServiceManager};
-use crate::window::components::playback::Player;
-use crate::services::audio_player::AudioPlayer;
+use crate::config::APP_ID;
+use crate::library_folders::{
+    add_library_folder, choose_folder, folder_name, library_folders, replace_library_folder,
+};
+use crate::platform;
+use crate::services::models::{
+    Album, Artist, ContentFilter, HistoryEntry, LibraryChange, PinnedItem, PlayableItem,
+    PlaybackSource, Playlist, PlaylistPlayback, QueueContext, RefreshMode, SearchResults,
+    SearchWeights, Track,
+};
+use crate::preferences::enabled_plugins;
+use crate::services::plugins::{discover_plugins, PluginProvider};
+use crate::services::stream_cache::CachePrefetcher;
+use crate::services::webdav::{self, WebDavConfig, WebDavProvider};
+use crate::services::checkpoint::{self, Checkpoint};
+use crate::services::{genres, local, resources, secrets};
+use crate::services::album_gaps::MissingTrack;
+use crate::services::audio_devices::{OutputChange, OutputWatch};
+use crate::services::power::BatteryWatch;
+use crate::services::ranking::{self, RankingProfile};
+use crate::services::resources::ScanPriority;
+use crate::services::scrobbler::Scrobbler;
+use crate::services::tasks::TaskManager;
+use crate::services::writeback::{self, TagChange, WriteBackPolicy};
+use crate::services::{
+    missing_elements, LocalMusicProvider, MeteredPolicy, NetworkConditions, ProviderEvent,
+    ServiceManager, UserStore,
+};
+use crate::window::components::playback::{Player, VOLUME_STEP};
+use crate::window::utils::tooltip::{attach_tooltip, now_playing_tooltip, TooltipBuilder};
+use crate::services::audio_player::{
+    AudioPlayer, ClippingPrevention, OutputBuffer, ResamplerQuality, ShuffleMode, VolumeCurve,
+};
 use adw::prelude::*;
 use adw::subclass::prelude::*;
 use glib::Propagation;
@@ -13,11 +84,30 @@ use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use gtk::{gio, glib};
 use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+
+// Boost applied to local tracks when the user prefers them in search
+const LOCAL_PREFERENCE_WEIGHT: f32 = 1.5;
+
+// Plays looked back over when search favors recently played tracks
+const RECENT_SEARCH_PLAYS: usize = 200;
+
+// Shortest time between refills of the library page on screen while the
+// library is changing, as during a scan
+const LIBRARY_REFRESH_DELAY: Duration = Duration::from_secs(5);
+
+// Changed tracks beyond which a library page is loaded afresh rather than
+// looking each one up
+const LIBRARY_UPDATE_LIMIT: usize = 500;
+
+// Kind of the album art embedding job in the task manager
+const ARTWORK_TASK: &str = "embed-artwork";
 
 #[derive(Debug, Default, gtk::CompositeTemplate)]
 #[template(resource = "/com/lucamignatti/nova/window/window.ui")]
@@ -26,6 +116,8 @@ pub struct NovaWindow {
     #[template_child]
     pub home_button: TemplateChild<gtk::Button>,
     #[template_child]
+    pub home_shelves: TemplateChild<gtk::Box>,
+    #[template_child]
     pub header_search_entry: TemplateChild<gtk::SearchEntry>,
     #[template_child]
     pub queue_flap: TemplateChild<adw::Flap>,
@@ -58,6 +150,22 @@ pub struct NovaWindow {
     #[template_child]
     pub current_album_art: TemplateChild<gtk::Image>,
     #[template_child]
+    pub now_playing_box: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub queue_peek_label: TemplateChild<gtk::Label>,
+    // Set around the template's content in constructed()
+    pub toast_overlay: adw::ToastOverlay,
+    #[template_child]
+    pub refresh_provider_menu: TemplateChild<gio::Menu>,
+    #[template_child]
+    pub shuffle_menu: TemplateChild<gio::Menu>,
+    #[template_child]
+    pub playback_controls: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub playback_buttons_box: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub volume_controls_box: TemplateChild<gtk::Box>,
+    #[template_child]
     pub song_progress_bar: TemplateChild<gtk::Scale>,
     #[template_child]
     pub volume_scale: TemplateChild<gtk::Scale>,
@@ -71,6 +179,14 @@ pub struct NovaWindow {
     #[template_child]
     pub queue_list: TemplateChild<gtk::ListBox>,
     #[template_child]
+    pub queue_remaining_label: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub history_list: TemplateChild<gtk::ListBox>,
+    #[template_child]
+    pub history_add_all_button: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub skipped_list: TemplateChild<gtk::ListBox>,
+    #[template_child]
     pub search_stack: TemplateChild<gtk::Stack>,
     #[template_child]
     pub empty_search_page: TemplateChild<adw::StatusPage>,
@@ -79,6 +195,18 @@ pub struct NovaWindow {
     #[template_child]
     pub search_results_box: TemplateChild<gtk::Box>,
     #[template_child]
+    pub search_play_all_box: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub search_play_all: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub search_shuffle_all: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub search_filter_bar: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub search_provider_box: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub search_group_toggle: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
     pub current_song_artist: TemplateChild<gtk::Label>,
     #[template_child]
     pub content_box: TemplateChild<gtk::Box>,
@@ -101,17 +229,151 @@ pub struct NovaWindow {
     #[template_child]
     pub artists_grid: TemplateChild<gtk::FlowBox>,
     #[template_child]
+    pub artists_filter_revealer: TemplateChild<gtk::Revealer>,
+    #[template_child]
+    pub artists_filter_entry: TemplateChild<gtk::SearchEntry>,
+    #[template_child]
     pub artists_placeholder: TemplateChild<adw::StatusPage>,
     #[template_child]
     pub albums_stack: TemplateChild<gtk::Stack>,
     #[template_child]
     pub albums_grid: TemplateChild<gtk::FlowBox>,
     #[template_child]
+    pub albums_filter_revealer: TemplateChild<gtk::Revealer>,
+    #[template_child]
+    pub albums_filter_entry: TemplateChild<gtk::SearchEntry>,
+    #[template_child]
     pub albums_placeholder: TemplateChild<adw::StatusPage>,
+    #[template_child]
+    pub albums_decade_banner: TemplateChild<adw::Banner>,
+    #[template_child]
+    pub album_detail_art_box: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub album_detail_title: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub album_detail_artist: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub album_detail_info: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub album_detail_stats: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub album_detail_gaps: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub album_detail_gaps_label: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub album_detail_fill_gaps: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub album_detail_shuffle: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub album_detail_pin: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub album_detail_tracks: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub artist_detail_art_box: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub artist_detail_name: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub artist_detail_info: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub artist_detail_stats: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub artist_detail_shuffle: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub artist_detail_pin: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub artist_detail_bio: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub artist_detail_similar_box: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub artist_detail_similar: TemplateChild<gtk::FlowBox>,
+    #[template_child]
+    pub artist_detail_filters: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub artist_detail_sections: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub playlists_stack: TemplateChild<gtk::Stack>,
+    #[template_child]
+    pub playlists_list: TemplateChild<gtk::ListBox>,
+    #[template_child]
+    pub folder_playlists_box: TemplateChild<gtk::Box>,
+    #[template_child]
+    pub folder_playlists_list: TemplateChild<gtk::ListBox>,
+    #[template_child]
+    pub playlist_detail_title: TemplateChild<gtk::EditableLabel>,
+    #[template_child]
+    pub playlist_detail_info: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub playlist_detail_play: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub playlist_detail_playback: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub playlist_detail_tidy: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub playlist_detail_remove: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub playlist_detail_queue: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub playlist_detail_add: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub playlist_detail_tracks: TemplateChild<gtk::ListBox>,
+    pub album_detail_track: RefCell<Option<Track>>,
+    // Tracks the album and artist pages total up their stats over
+    pub album_detail_stat_tracks: RefCell<Vec<Track>>,
+    pub artist_detail_stat_tracks: RefCell<Vec<Track>>,
+    pub album_detail_pin_item: RefCell<Option<PinnedItem>>,
+    // Gaps in the album on the album page, by track number
+    pub album_detail_missing: RefCell<Vec<MissingTrack>>,
+    pub artist_detail_pin_item: RefCell<Option<PinnedItem>>,
+    // Artist the artist page shows, so info fetched for another is dropped
+    pub artist_detail_id: RefCell<Option<String>>,
+    pub playlist_detail: RefCell<Option<Playlist>>,
+    pub playlist_entries: RefCell<Vec<PlaylistEntry>>,
+    // Playlist folders left open on the Playlists page
+    pub expanded_playlist_folders: RefCell<HashSet<String>>,
+    pub user_store: RefCell<Option<Arc<UserStore>>>,
+    pub pinned_rows: RefCell<Vec<gtk::ListBoxRow>>,
+    pub pinned_items: RefCell<Vec<PinnedItem>>,
+    pub history_entries: RefCell<Vec<HistoryEntry>>,
     pub search_version: Cell<u32>,
+    // Page a search replaced, for Escape to go back to
+    pub page_before_search: RefCell<Option<String>>,
+    // Query behind the results shown, for Play All; None for lyrics results
+    pub search_query: RefCell<Option<String>>,
+    // Results behind the page, kept to narrow them without searching again
+    pub search_results: RefCell<Option<SearchResults>>,
+    // Provider the results are narrowed to; None shows them all
+    pub search_provider: RefCell<Option<String>>,
     pub current_search_handle: RefCell<Option<glib::JoinHandle<()>>>,
     pub spinner_container: RefCell<Option<gtk::Box>>,
     pub player: RefCell<Option<Player>>,
+    // Set while a ListenBrainz token is
+    pub scrobbler: RefCell<Option<Arc<Scrobbler>>>,
+    pub output_watch: RefCell<Option<OutputWatch>>,
+    // Set once UPower answers
+    pub battery_watch: RefCell<Option<BatteryWatch>>,
+    // Set while Downloads is watched for music
+    pub downloads_watch: RefCell<Option<DownloadsWatch>>,
+    // Set while a refill of the library page on screen is waiting
+    pub library_refresh: RefCell<Option<glib::SourceId>>,
+    // What changed in the library for that refill, or None when it isn't
+    // known and the page is loaded afresh
+    pub library_changes: RefCell<Option<LibraryChange>>,
+    // Rows listing each track, to mark the playing one in
+    pub playing_marks: RefCell<HashMap<String, Vec<PlayingMark>>>,
+    pub settings: RefCell<Option<gio::Settings>>,
+    pub quit_confirmed: Cell<bool>,
+    // Startup problems waiting to be shown, one dialog at a time
+    pub pending_problems: RefCell<VecDeque<Problem>>,
+    pub recovery_dialog_open: Cell<bool>,
+    pub replay_gain_running: Cell<bool>,
+    // Long library jobs running in the background
+    pub tasks: TaskManager,
+    pub tempo_analysis_running: Cell<bool>,
+    // Track lined up next in DJ mode, and the beats per minute it's sped
+    // up or slowed down to
+    pub dj_match: RefCell<Option<(String, f32)>>,
+    // Left by a session that didn't exit cleanly, until it's restored
+    pub pending_checkpoint: RefCell<Option<Checkpoint>>,
 }
 
 #[glib::object_subclass]
@@ -132,42 +394,1102 @@ impl ObjectSubclass for NovaWindow {
 impl ObjectImpl for NovaWindow {
     fn constructed(&self) {
         self.parent_constructed();
+        // Toasts show over everything in the window
+        let content = self.obj().content();
+        self.obj().set_content(Some(&self.toast_overlay));
+        self.toast_overlay.set_child(content.as_ref());
+        self.setup_settings();
+        self.setup_user_store();
         self.setup_service_manager();
         self.setup_search();
         self.setup_navigation();
         self.setup_playback_controls();
+        self.setup_checkpoints();
         self.setup_volume_controls();
+        self.setup_history();
+        setup_scrobbling(self);
+        setup_dj_mode(self);
+        setup_playing_marks(self);
+        setup_queue_list(self);
+        self.setup_player_bar();
+        self.setup_network_monitor();
+        self.setup_output_monitor();
+        self.setup_scan_priority();
+        self.setup_content_filter();
+        self.setup_genres();
+        self.setup_actions();
+        self.setup_recovery();
+        setup_update_check(self);
+        setup_downloads_watch(self);
     }
 }
 
 impl NovaWindow {
+    fn setup_settings(&self) {
+        self.settings.replace(Some(gio::Settings::new(APP_ID)));
+    }
+
+    fn setup_user_store(&self) {
+        let path = UserStore::default_path();
+        // A damaged store runs in safe mode, without one, until the user
+        // decides what to do with it
+        let opened = UserStore::check_integrity(&path).and_then(|_| UserStore::new(&path));
+        match opened {
+            Ok(store) => {
+                writeback::load_tag_edits(&store);
+                self.user_store.replace(Some(Arc::new(store)));
+            }
+            Err(e) => {
+                eprintln!("Error opening user store: {}", e);
+                report_problem(
+                    self,
+                    Problem::DamagedDatabase {
+                        path,
+                        error: e.to_string(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Show startup problems once the window is on screen
+    fn setup_recovery(&self) {
+        self.obj().connect_map(|obj| show_next_problem(obj.imp()));
+    }
+
+    pub fn settings(&self) -> gio::Settings {
+        self.settings
+            .borrow()
+            .clone()
+            .expect("Settings should be initialized in constructed")
+    }
+
+    /// Which track changes are written to the files' tags
+    pub fn write_back_policy(&self) -> WriteBackPolicy {
+        let settings = self.settings();
+        WriteBackPolicy {
+            edits: settings.boolean("write-edits-to-files"),
+            ratings: settings.boolean("write-ratings-to-files"),
+            play_counts: settings.boolean("write-play-counts-to-files"),
+            network_files: settings.boolean("write-to-network-files"),
+        }
+    }
+
+    /// Write queued tag changes in the background. The file playing is left
+    /// until it has finished.
+    pub fn flush_write_backs(&self) {
+        let Some(store) = self.user_store.borrow().clone() else {
+            return;
+        };
+        let policy = self.write_back_policy();
+        let playing = self
+            .player
+            .borrow()
+            .as_ref()
+            .and_then(|player| player.current_track())
+            .and_then(|track| match track.source {
+                PlaybackSource::Local { path, .. } => Some(path),
+                _ => None,
+            });
+        std::thread::spawn(move || {
+            let written = writeback::flush(&store, policy, playing.as_deref());
+            if written > 0 {
+                println!("Wrote tags to {} files", written);
+            }
+        });
+    }
+
+    /// Analyze and tag local files without ReplayGain in the background,
+    /// when the user has asked for it
+    pub fn write_replay_gain(&self) {
+        if !self.settings().boolean("write-replay-gain") || self.replay_gain_running.get() {
+            return;
+        }
+        let Some(manager) = self.service_manager.borrow().clone() else {
+            return;
+        };
+
+        self.replay_gain_running.set(true);
+        let policy = self.write_back_policy();
+        let obj_weak = self.obj().downgrade();
+        glib::MainContext::default().spawn_local(async move {
+            let items = match manager.get_library_tracks().await {
+                Ok(items) => items,
+                Err(e) => {
+                    eprintln!("Failed to list tracks for ReplayGain: {}", e);
+                    Vec::new()
+                }
+            };
+            // Files by album, told apart by folder too as album names repeat.
+            // Chapter tracks share their file, which counts once.
+            let mut albums: BTreeMap<(String, PathBuf), Vec<(PathBuf, u32)>> = BTreeMap::new();
+            for item in items {
+                let PlaybackSource::Local { path, .. } = item.track.source else {
+                    continue;
+                };
+                // A track without an album is an album of its own
+                let folder = if item.track.album.is_empty() {
+                    path.clone()
+                } else {
+                    path.parent().map(PathBuf::from).unwrap_or_default()
+                };
+                let files = albums.entry((item.track.album, folder)).or_default();
+                match files.iter_mut().find(|(file, _)| *file == path) {
+                    Some((_, length)) => *length += item.track.duration,
+                    None => files.push((path, item.track.duration)),
+                }
+            }
+            let albums: Vec<Vec<(PathBuf, u32)>> = albums.into_values().collect();
+
+            let report = tokio::task::spawn_blocking(move || {
+                writeback::write_replay_gain_batch(&albums, policy)
+            })
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("ReplayGain job stopped: {}", e);
+                Default::default()
+            });
+            println!("ReplayGain: {:?}", report);
+
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            let this = obj.imp();
+            this.replay_gain_running.set(false);
+            if report.written > 0 {
+                this.show_toast(&format!("Wrote ReplayGain tags to {} files", report.written));
+            }
+        });
+    }
+
+    /// Embed album art into local files without any in the background, when
+    /// the user has asked for it
+    pub fn embed_artwork(&self) {
+        if !self.settings().boolean("embed-artwork") {
+            return;
+        }
+        let Some(manager) = self.service_manager.borrow().clone() else {
+            return;
+        };
+        let Some(task) = self.tasks.start(ARTWORK_TASK) else {
+            return;
+        };
+
+        let policy = self.write_back_policy();
+        let max_size = self.settings().int("embedded-artwork-max-size").max(1) as u32;
+        let toast = self.show_task_toast(ARTWORK_TASK, "Embedding album art");
+        let obj_weak = self.obj().downgrade();
+        glib::MainContext::default().spawn_local(async move {
+            let items = match manager.get_library_tracks().await {
+                Ok(items) => items,
+                Err(e) => {
+                    eprintln!("Failed to list tracks for embedding art: {}", e);
+                    Vec::new()
+                }
+            };
+            // Finding the art reads and caches what's embedded, so it's done
+            // off the main thread along with the embedding
+            let report = tokio::task::spawn_blocking(move || {
+                let mut files: Vec<(PathBuf, PathBuf)> = items
+                    .into_iter()
+                    .filter_map(|item| match &item.track.source {
+                        PlaybackSource::Local { path, .. } => {
+                            Some((path.clone(), local::artwork_file(&item.track.artwork)?))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                // Chapter tracks share their file
+                files.sort();
+                files.dedup_by(|a, b| a.0 == b.0);
+                writeback::embed_artwork_batch(&files, max_size, policy, &task)
+            })
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("Artwork embedding job stopped: {}", e);
+                Default::default()
+            });
+
+            toast.dismiss();
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            if report.written > 0 {
+                obj.imp()
+                    .show_toast(&format!("Embedded album art in {} files", report.written));
+            }
+        });
+    }
+
+    /// Toast that follows the progress of the background job of `kind`
+    /// until it's dismissed, with a button to stop the job
+    fn show_task_toast(&self, kind: &'static str, title: &str) -> adw::Toast {
+        let toast = adw::Toast::builder()
+            .title(format!("{}…", title))
+            .button_label("Stop")
+            .timeout(0)
+            .build();
+        let tasks = self.tasks.clone();
+        toast.connect_button_clicked(move |_| tasks.cancel(kind));
+
+        let tasks = self.tasks.clone();
+        let (toast_weak, title) = (toast.downgrade(), title.to_string());
+        glib::timeout_add_seconds_local(1, move || {
+            let (Some(toast), Some(progress)) = (toast_weak.upgrade(), tasks.progress(kind)) else {
+                return glib::ControlFlow::Break;
+            };
+            if progress.total > 0 {
+                let (done, total) = (progress.done, progress.total);
+                toast.set_title(&format!("{}: {} of {}", title, done, total));
+            }
+            glib::ControlFlow::Continue
+        });
+        self.toast_overlay.add_toast(toast.clone());
+        toast
+    }
+
+    /// How tracks are leveled, or None when they play as they are
+    pub fn normalization(&self) -> Option<ClippingPrevention> {
+        let settings = self.settings();
+        if !settings.boolean("normalize-volume") {
+            return None;
+        }
+        match settings.string("clipping-prevention").as_str() {
+            "reduce-gain" => Some(ClippingPrevention::ReduceGain),
+            _ => Some(ClippingPrevention::Limiter),
+        }
+    }
+
+    /// Ranking weights for the next search, honouring the user's preferences
+    pub fn search_weights(&self) -> SearchWeights {
+        let settings = self.settings();
+        let profile = RankingProfile::from_setting(&settings.string("search-ranking"));
+        let mut weights = match profile {
+            RankingProfile::Custom => ranking::parse_weights(
+                &settings.string("custom-search-weights"),
+            )
+            .unwrap_or_else(|e| {
+                eprintln!("Ignoring the custom search weights: {}", e);
+                SearchWeights::default()
+            }),
+            _ => profile.weights(),
+        };
+        // Custom weights for the local provider win over the preference
+        if settings.boolean("prefer-local-results") {
+            weights
+                .provider_weights
+                .entry("local".to_string())
+                .or_insert(LOCAL_PREFERENCE_WEIGHT);
+        }
+        if weights.recent_weight > 0.0 {
+            weights.recently_played = self.recently_played();
+        }
+        weights
+    }
+
+    /// How recently each track in the play history was played, from 1.0
+    /// for the last play down towards 0.0
+    fn recently_played(&self) -> HashMap<String, f32> {
+        let history = match self.user_store.borrow().as_ref() {
+            Some(store) => store
+                .recent_history(RECENT_SEARCH_PLAYS)
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to load play history for search: {}", e);
+                    Vec::new()
+                }),
+            None => Vec::new(),
+        };
+        let mut recency = HashMap::new();
+        for (index, entry) in history.iter().enumerate() {
+            let value = 1.0 - index as f32 / RECENT_SEARCH_PLAYS as f32;
+            recency.entry(entry.track_id.clone()).or_insert(value);
+        }
+        recency
+    }
+
+    fn setup_player_bar(&self) {
+        let settings = self.settings();
+        apply_player_bar_layout(
+            self,
+            PlayerBarLayout::from_setting(&settings.string("player-bar-layout")),
+        );
+
+        let obj_weak = self.obj().downgrade();
+        settings.connect_changed(Some("player-bar-layout"), move |settings, key| {
+            if let Some(obj) = obj_weak.upgrade() {
+                apply_player_bar_layout(
+                    obj.imp(),
+                    PlayerBarLayout::from_setting(&settings.string(key)),
+                );
+            }
+        });
+    }
+
+    fn setup_network_monitor(&self) {
+        let monitor = gio::NetworkMonitor::default();
+
+        let obj_weak = self.obj().downgrade();
+        monitor.connect_network_changed(move |monitor, available| {
+            if let Some(obj) = obj_weak.upgrade() {
+                obj.imp().update_network_conditions(monitor, available);
+            }
+        });
+
+        let obj_weak = self.obj().downgrade();
+        self.settings()
+            .connect_changed(Some("metered-connection-policy"), move |_, _| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    let monitor = gio::NetworkMonitor::default();
+                    obj.imp()
+                        .update_network_conditions(&monitor, monitor.is_network_available());
+                }
+            });
+
+        self.update_network_conditions(&monitor, monitor.is_network_available());
+    }
+
+    /// Pause when headphones or another output in use go away, and resume
+    /// when they're back
+    fn setup_output_monitor(&self) {
+        let obj_weak = self.obj().downgrade();
+        let watch = OutputWatch::start(move |change| {
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            let this = obj.imp();
+            let Some(player) = this.player.borrow().clone() else {
+                return;
+            };
+            match change {
+                OutputChange::Removed(device, true) => player.handle_output_lost(&device.name),
+                OutputChange::Removed(..) => {}
+                OutputChange::Added(device) => player.handle_output_added(
+                    &device.name,
+                    device.is_default == Some(true),
+                    this.settings().boolean("resume-on-output-return"),
+                ),
+            }
+        });
+        match watch {
+            Ok(watch) => {
+                self.output_watch.replace(Some(watch));
+            }
+            Err(e) => eprintln!("Not watching audio outputs: {}", e),
+        }
+    }
+
+    /// Follow the scan priority setting, dropping to idle while on battery
+    fn setup_scan_priority(&self) {
+        self.apply_scan_priority();
+        let obj_weak = self.obj().downgrade();
+        self.settings()
+            .connect_changed(Some("scan-priority"), move |_, _| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.imp().apply_scan_priority();
+                }
+            });
+
+        let obj_weak = self.obj().downgrade();
+        glib::MainContext::default().spawn_local(async move {
+            let on_change = {
+                let obj_weak = obj_weak.clone();
+                move || {
+                    if let Some(obj) = obj_weak.upgrade() {
+                        obj.imp().apply_scan_priority();
+                    }
+                }
+            };
+            let watch = BatteryWatch::start(on_change).await;
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            match watch {
+                Ok(watch) => {
+                    obj.imp().battery_watch.replace(Some(watch));
+                    obj.imp().apply_scan_priority();
+                }
+                Err(e) => eprintln!("Not watching the power source: {}", e),
+            }
+        });
+    }
+
+    fn apply_scan_priority(&self) {
+        let on_battery = self
+            .battery_watch
+            .borrow()
+            .as_ref()
+            .is_some_and(BatteryWatch::on_battery);
+        let priority = if on_battery {
+            ScanPriority::Idle
+        } else {
+            ScanPriority::from_setting(&self.settings().string("scan-priority"))
+        };
+        resources::set_scan_priority(priority);
+    }
+
+    fn update_network_conditions(&self, monitor: &gio::NetworkMonitor, available: bool) {
+        let settings = self.settings();
+        let conditions = NetworkConditions {
+            available,
+            metered: monitor.is_network_metered(),
+            policy: MeteredPolicy::from_setting(&settings.string("metered-connection-policy")),
+        };
+
+        if let Some(player) = &*self.player.borrow() {
+            player.set_streaming_quality(conditions.streaming_quality());
+            if available {
+                player.handle_network_restored(settings.boolean("resume-on-reconnect"));
+            } else {
+                player.handle_network_lost();
+            }
+        }
+
+        if let Some(manager) = self.service_manager.borrow().clone() {
+            glib::MainContext::default().spawn_local(async move {
+                manager.set_network_conditions(conditions).await;
+            });
+        }
+    }
+
+    fn setup_content_filter(&self) {
+        let obj_weak = self.obj().downgrade();
+        self.settings()
+            .connect_changed(Some("explicit-content-filter"), move |_, _| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.imp().apply_content_filter();
+                }
+            });
+
+        self.apply_content_filter();
+    }
+
+    fn apply_content_filter(&self) {
+        let filter =
+            ContentFilter::from_setting(&self.settings().string("explicit-content-filter"));
+
+        if let Some(player) = &*self.player.borrow() {
+            player.set_skip_explicit(filter == ContentFilter::Skip);
+        }
+
+        if let Some(manager) = self.service_manager.borrow().clone() {
+            glib::MainContext::default().spawn_local(async move {
+                manager.set_content_filter(filter).await;
+            });
+        }
+    }
+
+    fn setup_genres(&self) {
+        for key in ["normalize-genres", "genre-mappings"] {
+            let obj_weak = self.obj().downgrade();
+            self.settings().connect_changed(Some(key), move |settings, _| {
+                Self::apply_genre_mappings(settings);
+                // Cached listings have their genres normalized the old way
+                let Some(obj) = obj_weak.upgrade() else {
+                    return;
+                };
+                if let Some(manager) = obj.imp().service_manager.borrow().as_ref() {
+                    manager.clear_cache();
+                }
+            });
+        }
+        Self::apply_genre_mappings(&self.settings());
+    }
+
+    /// Genres are normalized as tracks are fetched, so views pick up new
+    /// mappings the next time they load
+    fn apply_genre_mappings(settings: &gio::Settings) {
+        if settings.boolean("normalize-genres") {
+            let mappings: Vec<String> = settings
+                .strv("genre-mappings")
+                .iter()
+                .map(|mapping| mapping.to_string())
+                .collect();
+            genres::set_genre_mappings(Some(&mappings));
+        } else {
+            genres::set_genre_mappings(None);
+        }
+    }
+
+    fn shuffle_mode(&self) -> ShuffleMode {
+        if self.settings().string("shuffle-by") == "albums" {
+            ShuffleMode::Albums
+        } else if self.settings().boolean("smart-shuffle") {
+            ShuffleMode::SmartSpacing
+        } else {
+            ShuffleMode::Random
+        }
+    }
+
+    /// Often skipped tracks are shuffled towards the end, and tracks marked
+    /// to never play in shuffle are left out
+    fn update_shuffle_data(&self, player: &Player) {
+        if let Some(store) = self.user_store.borrow().as_ref() {
+            match store.skip_rates() {
+                Ok(skip_rates) => player.set_skip_rates(skip_rates),
+                Err(e) => eprintln!("Failed to load skip rates: {}", e),
+            }
+            match store.shuffle_exclusions() {
+                Ok(exclusions) => player.set_shuffle_exclusions(exclusions),
+                Err(e) => eprintln!("Failed to load shuffle exclusions: {}", e),
+            }
+        }
+    }
+
+    fn apply_shuffle(&self) {
+        let mode = self.shuffle_button.is_active().then(|| self.shuffle_mode());
+
+        if let Some(player) = &*self.player.borrow() {
+            self.update_shuffle_data(player);
+            player.set_shuffle(mode);
+        }
+    }
+
+    /// Replace the queue with `items` shuffled and start playing, turning
+    /// shuffle on so the rest of the queue stays shuffled
+    pub fn play_shuffled(&self, items: Vec<PlayableItem>, context: QueueContext) {
+        if items.is_empty() {
+            return;
+        }
+        // set_active doesn't emit clicked, so the old queue isn't reshuffled
+        self.shuffle_button.set_active(true);
+        self.shuffle_button.add_css_class("active");
+
+        if let Some(player) = &*self.player.borrow() {
+            self.update_shuffle_data(player);
+            player.play_shuffled(items, context, self.shuffle_mode());
+        }
+    }
+
+    /// Replace the queue with a playlist's `items` and play them with its
+    /// playback settings. Its shuffle setting turns shuffle on or off for
+    /// good, as the shuffle button would.
+    pub fn play_playlist(
+        &self,
+        items: Vec<PlayableItem>,
+        context: QueueContext,
+        playback: PlaylistPlayback,
+    ) {
+        if items.is_empty() {
+            return;
+        }
+        let shuffle = playback
+            .shuffle
+            .unwrap_or_else(|| self.shuffle_button.is_active());
+        // set_active doesn't emit clicked, so the old queue isn't reshuffled
+        self.shuffle_button.set_active(shuffle);
+        if shuffle {
+            self.shuffle_button.add_css_class("active");
+        } else {
+            self.shuffle_button.remove_css_class("active");
+        }
+
+        if let Some(player) = &*self.player.borrow() {
+            self.update_shuffle_data(player);
+            let mode = shuffle.then(|| self.shuffle_mode());
+            player.play_playlist(items, context, playback, mode);
+        }
+    }
+
+    /// Queue `items` as a block after the album or playlist playing now
+    pub fn play_after_current_unit(&self, items: Vec<PlayableItem>, context: QueueContext) {
+        if items.is_empty() {
+            return;
+        }
+        if let Some(player) = &*self.player.borrow() {
+            player.play_after_current_unit(items, context);
+        }
+    }
+
+    /// Add `items` to the end of the queue in one go
+    pub fn enqueue_tracks(&self, items: Vec<PlayableItem>, context: QueueContext) {
+        if items.is_empty() {
+            return;
+        }
+        if let Some(player) = &*self.player.borrow() {
+            player.enqueue_tracks(items, context);
+        }
+    }
+
+    pub fn show_toast(&self, message: &str) {
+        let toast = adw::Toast::new(message);
+        toast.set_timeout(3);
+        self.toast_overlay.add_toast(toast);
+    }
+
+    async fn update_refresh_provider_menu(&self) {
+        let manager = match self.service_manager.borrow().clone() {
+            Some(manager) => manager,
+            None => return,
+        };
+
+        self.refresh_provider_menu.remove_all();
+        for name in manager.provider_names().await {
+            let action = format!("win.refresh-provider::{}", name);
+            self.refresh_provider_menu.append(Some(&name), Some(&action));
+        }
+    }
+
+    fn refresh_library(&self, provider: Option<String>, mode: RefreshMode) {
+        let manager = match self.service_manager.borrow().clone() {
+            Some(manager) => manager,
+            None => return,
+        };
+
+        let label = match mode {
+            RefreshMode::Incremental => "Scanning for changes",
+            RefreshMode::Full => "Rescanning library",
+        };
+        match &provider {
+            Some(name) => self.show_toast(&format!("{} in {}…", label, name)),
+            None => self.show_toast(&format!("{}…", label)),
+        }
+
+        let obj_weak = self.obj().downgrade();
+        glib::MainContext::default().spawn_local(async move {
+            let result = match &provider {
+                Some(name) => manager.refresh_provider(name, mode).await,
+                None => manager.refresh_all(mode).await,
+            };
+
+            if let Some(obj) = obj_weak.upgrade() {
+                match result {
+                    Ok(()) => {
+                        obj.imp().show_toast("Library refreshed");
+                        // Pick up ReplayGain, art and tempos for files
+                        // added since the last run
+                        obj.imp().write_replay_gain();
+                        obj.imp().embed_artwork();
+                        analyze_tempos(obj.imp());
+                    }
+                    Err(e) => obj.imp().show_toast(&format!("Refresh failed: {}", e)),
+                }
+            }
+        });
+    }
+
+    fn setup_actions(&self) {
+        let volume_up_action = gio::ActionEntry::builder("volume-up")
+            .activate(|window: &super::NovaWindow, _, _| {
+                if let Some(player) = &*window.imp().player.borrow() {
+                    player.adjust_volume(VOLUME_STEP);
+                }
+            })
+            .build();
+        let volume_down_action = gio::ActionEntry::builder("volume-down")
+            .activate(|window: &super::NovaWindow, _, _| {
+                if let Some(player) = &*window.imp().player.borrow() {
+                    player.adjust_volume(-VOLUME_STEP);
+                }
+            })
+            .build();
+        let toggle_mute_action = gio::ActionEntry::builder("toggle-mute")
+            .activate(|window: &super::NovaWindow, _, _| {
+                if let Some(player) = &*window.imp().player.borrow() {
+                    player.toggle_mute();
+                }
+            })
+            .build();
+        let refresh_library_action = gio::ActionEntry::builder("refresh-library")
+            .parameter_type(Some(glib::VariantTy::STRING))
+            .activate(|window: &super::NovaWindow, _, parameter| {
+                let mode = match parameter.and_then(|p| p.str()) {
+                    Some("full") => RefreshMode::Full,
+                    _ => RefreshMode::Incremental,
+                };
+                window.imp().refresh_library(None, mode);
+            })
+            .build();
+        let refresh_provider_action = gio::ActionEntry::builder("refresh-provider")
+            .parameter_type(Some(glib::VariantTy::STRING))
+            .activate(|window: &super::NovaWindow, _, parameter| {
+                if let Some(name) = parameter.and_then(|p| p.get::<String>()) {
+                    window.imp().refresh_library(Some(name), RefreshMode::Full);
+                }
+            })
+            .build();
+        let export_library_action = gio::ActionEntry::builder("export-library")
+            .activate(|window: &super::NovaWindow, _, _| {
+                choose_export_file(window.imp());
+            })
+            .build();
+        let clean_up_tags_action = gio::ActionEntry::builder("clean-up-tags")
+            .activate(|window: &super::NovaWindow, _, _| {
+                show_tag_cleanup(window.imp());
+            })
+            .build();
+        let organize_files_action = gio::ActionEntry::builder("organize-files")
+            .activate(|window: &super::NovaWindow, _, _| {
+                show_organize_files(window.imp());
+            })
+            .build();
+        let focus_search_action = gio::ActionEntry::builder("focus-search")
+            .activate(|window: &super::NovaWindow, _, _| {
+                focus_search(window.imp());
+            })
+            .build();
+        let add_library_folder_action = gio::ActionEntry::builder("add-library-folder")
+            .activate(|window: &super::NovaWindow, _, _| {
+                let settings = window.imp().settings();
+                choose_folder(
+                    Some(window.upcast_ref()),
+                    "Add Library Folder",
+                    None,
+                    move |folder| add_library_folder(&settings, folder),
+                );
+            })
+            .build();
+        let new_playlist_action = gio::ActionEntry::builder("new-playlist")
+            .activate(|window: &super::NovaWindow, _, _| {
+                prompt_new_playlist(window.imp());
+            })
+            .build();
+        let show_decade_action = gio::ActionEntry::builder("show-decade")
+            .parameter_type(Some(glib::VariantTy::UINT32))
+            .activate(|window: &super::NovaWindow, _, parameter| {
+                if let Some(decade) = parameter.and_then(|p| p.get::<u32>()) {
+                    show_decade(window.imp(), decade);
+                }
+            })
+            .build();
+        let make_mix_action = gio::ActionEntry::builder("make-mix")
+            .activate(|window: &super::NovaWindow, _, _| {
+                show_mix_dialog(window.imp());
+            })
+            .build();
+        let copy_track_info_action = gio::ActionEntry::builder("copy-track-info")
+            .activate(|window: &super::NovaWindow, _, _| {
+                copy_track_info(window.imp());
+            })
+            .build();
+        let copy_track_link_action = gio::ActionEntry::builder("copy-track-link")
+            .activate(|window: &super::NovaWindow, _, _| {
+                copy_track_link(window.imp());
+            })
+            .build();
+        let export_snippet_action = gio::ActionEntry::builder("export-snippet")
+            .activate(|window: &super::NovaWindow, _, _| {
+                choose_snippet_file(window.imp());
+            })
+            .build();
+        let import_playlist_action = gio::ActionEntry::builder("import-playlist")
+            .activate(|window: &super::NovaWindow, _, _| {
+                choose_playlist_file(window.imp());
+            })
+            .build();
+        let export_recap_action = gio::ActionEntry::builder("export-recap")
+            .activate(|window: &super::NovaWindow, _, _| {
+                show_recap(window.imp());
+            })
+            .build();
+        let show_signal_chain_action = gio::ActionEntry::builder("show-signal-chain")
+            .activate(|window: &super::NovaWindow, _, _| {
+                show_gain_stages(window.imp());
+            })
+            .build();
+        self.obj().add_action_entries([
+            volume_up_action,
+            volume_down_action,
+            toggle_mute_action,
+            refresh_library_action,
+            refresh_provider_action,
+            add_library_folder_action,
+            new_playlist_action,
+            show_decade_action,
+            make_mix_action,
+            export_library_action,
+            clean_up_tags_action,
+            organize_files_action,
+            focus_search_action,
+            copy_track_info_action,
+            copy_track_link_action,
+            export_snippet_action,
+            export_recap_action,
+            show_signal_chain_action,
+            import_playlist_action,
+        ]);
+
+        // View toggles in the main menu, kept in step with Preferences
+        for key in ["player-bar-layout", "context-aware-home"] {
+            self.obj().add_action(&self.settings().create_action(key));
+        }
+    }
+
     fn setup_service_manager(&self) {
         if self.service_manager.borrow().is_none() {
-            let manager = ServiceManager::new();
-            let manager = Arc::new(manager);
-            let manager_clone = manager.clone();
+            let manager = Arc::new(ServiceManager::new());
+            self.service_manager.replace(Some(manager.clone()));
+            self.watch_providers(&manager);
+            self.load_local_provider();
+            self.load_webdav_provider();
+            self.load_plugins();
+
+            for key in [
+                "library-folders",
+                "local-library-enabled",
+                "low-memory-mode",
+                "split-chapters",
+            ] {
+                let obj_weak = self.obj().downgrade();
+                self.settings().connect_changed(Some(key), move |_, _| {
+                    if let Some(obj) = obj_weak.upgrade() {
+                        obj.imp().load_local_provider();
+                    }
+                });
+            }
 
-            let music_dir = dirs::audio_dir().unwrap_or_else(|| {
-                PathBuf::from(&format!("{}/Music", std::env::var("HOME").unwrap()))
+            for key in ["webdav-enabled", "webdav-url", "webdav-username"] {
+                let obj_weak = self.obj().downgrade();
+                self.settings().connect_changed(Some(key), move |_, _| {
+                    if let Some(obj) = obj_weak.upgrade() {
+                        obj.imp().load_webdav_provider();
+                    }
+                });
+            }
+            // The password is in the secret store
+            let mut secret_changes = secrets::subscribe();
+            let obj_weak = self.obj().downgrade();
+            glib::MainContext::default().spawn_local(async move {
+                while let Ok(key) = secret_changes.recv().await {
+                    let Some(obj) = obj_weak.upgrade() else {
+                        break;
+                    };
+                    if key == secrets::WEBDAV_PASSWORD {
+                        obj.imp().load_webdav_provider();
+                    }
+                }
             });
 
+            let obj_weak = self.obj().downgrade();
+            self.settings().connect_changed(Some("enabled-plugins"), move |_, _| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.imp().load_plugins();
+                }
+            });
+        }
+    }
+
+    /// Sign in to the WebDAV server from the settings and the secret store
+    /// as the "webdav" provider, or drop it when turned off or not set up
+    fn load_webdav_provider(&self) {
+        let Some(manager) = self.service_manager.borrow().clone() else {
+            return;
+        };
+        let settings = self.settings();
+        let url = settings.string("webdav-url").to_string();
+        let username = settings.string("webdav-username").to_string();
+        if !settings.boolean("webdav-enabled") || url.is_empty() {
+            webdav::forget_credentials();
             glib::MainContext::default().spawn_local(async move {
-                match LocalMusicProvider::new(music_dir).await {
+                manager.unregister_provider("webdav").await;
+            });
+            return;
+        }
+
+        let obj_weak = self.obj().downgrade();
+        glib::MainContext::default().spawn_local(async move {
+            let password = match secrets::load(secrets::WEBDAV_PASSWORD).await {
+                Ok(password) => password.unwrap_or_default(),
+                Err(e) => {
+                    eprintln!("Failed to read the WebDAV password: {}", e);
+                    String::new()
+                }
+            };
+            let config = WebDavConfig {
+                url,
+                username,
+                password,
+            };
+            match WebDavProvider::new(config).await {
+                Ok(provider) => {
+                    manager.replace_provider("webdav", Box::new(provider)).await;
+                }
+                Err(e) => {
+                    eprintln!("Error initializing WebDAV provider: {}", e);
+                    if let Some(obj) = obj_weak.upgrade() {
+                        obj.imp().show_toast("Couldn't connect to the cloud drive");
+                    }
+                    return;
+                }
+            }
+
+            // Synced once registered, so what it finds comes through as
+            // library changes rather than holding up the window
+            if let Some(provider) = manager.provider("webdav").await {
+                tokio::spawn(async move {
+                    if let Err(e) = provider.refresh(RefreshMode::Incremental).await {
+                        eprintln!("WebDAV sync failed: {}", e);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Start the plugins that were turned on and stop those turned off.
+    /// Each plugin is a provider named after its id.
+    fn load_plugins(&self) {
+        let Some(manager) = self.service_manager.borrow().clone() else {
+            return;
+        };
+        let enabled = enabled_plugins(&self.settings());
+        let plugins = discover_plugins();
+
+        let obj_weak = self.obj().downgrade();
+        glib::MainContext::default().spawn_local(async move {
+            for plugin in plugins {
+                let wanted = enabled.contains(&plugin.id);
+                let running = manager.provider(&plugin.id).await.is_some();
+                if !wanted && running {
+                    manager.unregister_provider(&plugin.id).await;
+                    continue;
+                }
+                if !wanted || running {
+                    continue;
+                }
+
+                let (id, name) = (plugin.id.clone(), plugin.name.clone());
+                match PluginProvider::start(plugin).await {
                     Ok(provider) => {
-                        println!("LocalMusicProvider initialized, registering...");
-                        manager_clone
-                            .register_provider("local", Box::new(provider))
-                            .await;
-                        println!("Provider registered successfully");
+                        println!("Started plugin {}", name);
+                        if let Err(e) = manager.register_provider(&id, Box::new(provider)).await {
+                            eprintln!("Failed to register plugin {}: {}", name, e);
+                        }
                     }
                     Err(e) => {
-                        eprintln!("Error initializing local music provider: {}", e);
+                        eprintln!("Failed to start plugin {}: {}", name, e);
+                        if let Some(obj) = obj_weak.upgrade() {
+                            obj.imp().show_toast(&format!("Couldn't start {}", name));
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Keep the window in step with providers coming and going. Playback
+    /// from a provider stops when it's unregistered.
+    fn watch_providers(&self, manager: &ServiceManager) {
+        let mut events = manager.subscribe();
+        let obj_weak = self.obj().downgrade();
+        glib::MainContext::default().spawn_local(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => Some(event),
+                    // Missed events may have been library changes, and
+                    // the menu is rebuilt below anyway
+                    Err(broadcast::error::RecvError::Lagged(_)) => None,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Some(obj) = obj_weak.upgrade() else {
+                    break;
+                };
+                let this = obj.imp();
+
+                match &event {
+                    Some(ProviderEvent::LibraryChanged(_, change)) => {
+                        this.schedule_library_refresh(Some(change));
+                        // The providers themselves are the same
+                        continue;
+                    }
+                    Some(ProviderEvent::Unregistered(name)) => {
+                        println!("Provider {} unregistered", name);
+                        if let Some(player) = &*this.player.borrow() {
+                            player.stop_provider(name);
+                        }
+                    }
+                    Some(ProviderEvent::Replaced(name)) => {
+                        println!("Provider {} replaced", name);
                     }
+                    Some(ProviderEvent::Registered(name)) => {
+                        println!("Provider {} registered", name);
+                        if name == "local" {
+                            restore_checkpoint(this);
+                            restore_saved_queue(this);
+                        }
+                        reload_home(this);
+                    }
+                    None => this.schedule_library_refresh(None),
                 }
+                this.update_refresh_provider_menu().await;
+            }
+        });
+    }
+
+    /// (Re)create the local provider from the library folders, or remove it
+    /// when the local library is turned off. Folders that can't be read are
+    /// left out and the user is asked to grant them again.
+    fn load_local_provider(&self) {
+        let Some(manager) = self.service_manager.borrow().clone() else {
+            return;
+        };
+
+        // Low-memory mode mostly applies as the provider is built, so a new
+        // one is made whenever it changes
+        resources::set_low_memory(self.settings().boolean("low-memory-mode"));
+        local::set_split_chapters(self.settings().boolean("split-chapters"));
+
+        if !self.settings().boolean("local-library-enabled") {
+            glib::MainContext::default().spawn_local(async move {
+                manager.unregister_provider("local").await;
             });
+            return;
+        }
 
-            self.service_manager.replace(Some(manager));
+        let (music_dirs, inaccessible): (Vec<PathBuf>, Vec<PathBuf>) =
+            library_folders(&self.settings())
+                .into_iter()
+                .partition(|dir| platform::can_read_dir(dir));
+        if music_dirs.is_empty() && !inaccessible.is_empty() {
+            // Nothing to scan or watch; ask rather than show an empty library
+            report_problem(self, Problem::UnreadableLibrary(inaccessible));
+            return;
+        }
+        for folder in inaccessible {
+            eprintln!("Can't read library folder {:?}", folder);
+            self.show_reauthorize_toast(folder);
         }
+
+        glib::MainContext::default().spawn_local(async move {
+            match LocalMusicProvider::new(music_dirs).await {
+                Ok(provider) => {
+                    println!("LocalMusicProvider initialized, registering...");
+                    // Changed folders swap in a fresh provider; in-flight
+                    // work finishes against the old one
+                    manager.replace_provider("local", Box::new(provider)).await;
+                }
+                Err(e) => {
+                    eprintln!("Error initializing local music provider: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Persistent toast offering to pick `folder` again through the file
+    /// chooser, which renews the portal grant when sandboxed
+    fn show_reauthorize_toast(&self, folder: PathBuf) {
+        let toast = adw::Toast::builder()
+            .title(glib::markup_escape_text(&format!(
+                "Nova can't access “{}” anymore",
+                folder_name(&folder)
+            )))
+            .button_label("Re-authorize")
+            .timeout(0)
+            .build();
+
+        let obj_weak = self.obj().downgrade();
+        toast.connect_button_clicked(move |_| {
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            let settings = obj.imp().settings();
+            let old = folder.clone();
+            choose_folder(
+                Some(obj.upcast_ref()),
+                "Choose the Folder Again",
+                Some(&folder),
+                move |new| replace_library_folder(&settings, &old, new),
+            );
+        });
+        self.toast_overlay.add_toast(toast);
     }
 
     fn setup_search(&self) {
@@ -228,15 +1550,22 @@ impl NovaWindow {
                     }
                 }
 
+                // "/" only moves to the search field
+                if key == gtk::gdk::Key::slash {
+                    focus_search(this);
+                    return Propagation::Stop;
+                }
+
                 // Handle printable characters
                 if let Some(ch) = key.to_unicode() {
                     if ch.is_alphanumeric() || ch.is_ascii_punctuation() || ch == ' ' {
-                        this.main_stack.set_visible_child_name("search");
+                        if start_page_filter(this, ch) {
+                            return Propagation::Stop;
+                        }
+                        show_search_page(this);
                         this.header_search_entry.grab_focus();
                         this.header_search_entry.set_text(&ch.to_string());
                         this.header_search_entry.set_position(-1);
-                        this.home_button.remove_css_class("selected");
-                        this.sidebar_list.unselect_all();
                         return Propagation::Stop;
                     }
                 }
@@ -244,143 +1573,98 @@ impl NovaWindow {
             Propagation::Proceed
         });
         self.obj().add_controller(key_controller);
+        setup_library_filters(self);
+        setup_decade_banner(self);
+
+        // Setup search entry handler
+        let obj_weak = self.obj().downgrade();
+        self.header_search_entry.connect_changed(move |entry| {
+            if let Some(obj) = obj_weak.upgrade() {
+                let this = obj.imp();
+                show_search_page(this);
+                start_search(this, entry.text().to_string(), SEARCH_DELAY);
+            }
+        });
 
-        // Setup search entry handler
+        // Enter searches without waiting, Escape goes back to the page the
+        // search started from
         let obj_weak = self.obj().downgrade();
-        self.header_search_entry.connect_changed(move |entry| {
+        self.header_search_entry.connect_activate(move |entry| {
             if let Some(obj) = obj_weak.upgrade() {
                 let this = obj.imp();
-                let query = entry.text().to_string();
-
-                // Switch to search view and update state
-                this.main_stack.set_visible_child_name("search");
-                this.home_button.remove_css_class("selected");
-                this.sidebar_list.unselect_all();
-
-                // Increment version to invalidate previous searches
-                let current_version = this.search_version.get() + 1;
-                this.search_version.set(current_version);
-
-                // Handle empty query
-                if query.is_empty() {
-                    this.search_stack
-                        .set_visible_child_name("empty_search_page");
-                    return;
-                }
-
-                // Check for existing results
-                let has_existing_results = this.top_result_box.center_widget().is_some()
-                    || this.tracks_box.first_child().is_some()
-                    || this.artists_box.first_child().is_some()
-                    || this.albums_box.first_child().is_some();
-
-                // Check if we're on the empty search page
-                let is_empty_page = this
-                    .search_stack
-                    .visible_child_name()
-                    .map_or(true, |name| name == "empty_search_page");
-
-                // Only show loading state if no existing results
-                if !has_existing_results || is_empty_page {
-                    this.search_stack
-                        .set_visible_child_name("search_results_scroll");
-                    show_loading_state(this);
-                } else {
-                    this.search_stack
-                        .set_visible_child_name("search_results_scroll");
-                }
-
-                // Cancel previous search if running
-                if let Some(handle) = this.current_search_handle.take() {
-                    handle.abort();
-                }
-
-                // Create new search with delay
-                let obj_weak = obj_weak.clone();
-                let query = query.clone();
-
-                let handle = glib::MainContext::default().spawn_local(async move {
-                    // Wait for debounce period
-                    glib::timeout_future(Duration::from_millis(300)).await;
-
-                    if let Some(obj) = obj_weak.upgrade() {
-                        let this = obj.imp();
-
-                        // Check if this search is still relevant
-                        if this.search_version.get() != current_version {
-                            return;
-                        }
-
-                        // Perform search
-                        if let Some(manager) = this.service_manager.borrow().as_ref() {
-                            match manager.search_all(&query, None, 20, 0).await {
-                                Ok(results) => {
-                                    // Verify search is still relevant
-                                    if this.search_version.get() != current_version {
-                                        return;
-                                    }
-
-                                    let obj_weak = obj_weak.clone();
-                                    glib::MainContext::default().spawn_local(async move {
-                                        if let Some(obj) = obj_weak.upgrade() {
-                                            let this = obj.imp();
-                                            update_search_results(this, &results, &query);
-                                        }
-                                    });
-                                }
-                                Err(e) => {
-                                    eprintln!("Search error: {}", e);
-                                    if this.search_version.get() == current_version {
-                                        this.search_stack.set_visible_child_name("no_results_page");
-                                    }
-                                }
-                            }
-                        }
-                    }
-                });
+                show_search_page(this);
+                start_search(this, entry.text().to_string(), Duration::ZERO);
+            }
+        });
+        let obj_weak = self.obj().downgrade();
+        self.header_search_entry.connect_stop_search(move |_| {
+            if let Some(obj) = obj_weak.upgrade() {
+                leave_search(obj.imp());
+            }
+        });
 
-                this.current_search_handle.replace(Some(handle));
+        // Play All and Shuffle All queue every matching track, not just the
+        // ones shown
+        let obj_weak = self.obj().downgrade();
+        self.search_play_all.connect_clicked(move |_| {
+            if let Some(obj) = obj_weak.upgrade() {
+                play_search_results(obj.imp(), false);
+            }
+        });
+        let obj_weak = self.obj().downgrade();
+        self.search_shuffle_all.connect_clicked(move |_| {
+            if let Some(obj) = obj_weak.upgrade() {
+                play_search_results(obj.imp(), true);
             }
         });
+        setup_search_filters(self);
 
         // Connect search entry focus
         let focus_controller = gtk::EventControllerFocus::new();
-        let main_stack = self.main_stack.clone();
-        let home_button = self.home_button.clone();
-        let sidebar_list = self.sidebar_list.clone();
-        let search_stack = self.search_stack.clone();
-        let search_entry = self.header_search_entry.clone();
-
+        let obj_weak = self.obj().downgrade();
         focus_controller.connect_enter(move |_| {
-            main_stack.set_visible_child_name("search");
-            home_button.remove_css_class("selected");
-            sidebar_list.unselect_all();
-
-            if search_entry.text().is_empty() {
-                search_stack.set_visible_child_name("empty_search_page");
+            if let Some(obj) = obj_weak.upgrade() {
+                let this = obj.imp();
+                show_search_page(this);
+                if this.header_search_entry.text().is_empty() {
+                    this.search_stack.set_visible_child_name("empty_search_page");
+                }
             }
         });
         self.header_search_entry.add_controller(focus_controller);
     }
 
     fn setup_navigation(&self) {
-        // Set initial selection state
-        let sidebar_list = self.sidebar_list.clone();
-        let home_button = self.home_button.clone();
-
+        // Open the startup page, once the sidebar has settled on its own
+        // initial selection
+        let this = self.obj().downgrade();
         glib::idle_add_local_once(move || {
-            sidebar_list.unselect_all();
-            home_button.add_css_class("selected");
+            if let Some(obj) = this.upgrade() {
+                show_startup_page(obj.imp());
+            }
         });
+        setup_page_memory(self);
 
-        // Setup home button navigation
+        // Setup home button navigation. Home is refilled each time, since
+        // what comes first depends on the time of day.
         let main_stack = self.main_stack.clone();
-        let home_button = self.home_button.clone();
         let sidebar_list = self.sidebar_list.clone();
+        let this = self.obj().downgrade();
         self.home_button.connect_clicked(move |button| {
             main_stack.set_visible_child_name("home");
             button.add_css_class("selected");
             sidebar_list.unselect_all();
+            if let Some(obj) = this.upgrade() {
+                reload_home(obj.imp());
+            }
+        });
+
+        reload_home(self);
+        let this = self.obj().downgrade();
+        self.settings().connect_changed(Some("context-aware-home"), move |_, _| {
+            if let Some(obj) = this.upgrade() {
+                reload_home(obj.imp());
+            }
         });
 
         // Setup ListBox navigation
@@ -390,6 +1674,10 @@ impl NovaWindow {
         self.sidebar_list.connect_row_activated(move |_, row| {
             if let Some(obj) = this.upgrade() {
                 let this = obj.imp();
+                if let Some(item) = pinned_item_for_row(this, row) {
+                    open_pinned_item(this, &item);
+                    return;
+                }
                 let page_name = match row.index() {
                     0 => {
                         // Load artists when selecting the Artists tab
@@ -401,7 +1689,10 @@ impl NovaWindow {
                         this.load_albums();
                         "albums"
                     }
-                    2 => "playlists",
+                    2 => {
+                        reload_playlists(this);
+                        "playlists"
+                    }
                     3 => "liked",
                     _ => "home",
                 };
@@ -410,6 +1701,79 @@ impl NovaWindow {
             }
         });
 
+        // Pinned items below the fixed rows
+        reload_pinned_items(self);
+
+        // Pin buttons on the album and artist pages
+        let this = self.obj().downgrade();
+        self.album_detail_pin.connect_clicked(move |button| {
+            if let Some(obj) = this.upgrade() {
+                let this = obj.imp();
+                let item = this.album_detail_pin_item.borrow().clone();
+                if let Some(item) = item {
+                    toggle_pin(this, &item);
+                    update_pin_button(button, is_pinned(this, &item));
+                }
+            }
+        });
+
+        let this = self.obj().downgrade();
+        self.artist_detail_pin.connect_clicked(move |button| {
+            if let Some(obj) = this.upgrade() {
+                let this = obj.imp();
+                let item = this.artist_detail_pin_item.borrow().clone();
+                if let Some(item) = item {
+                    toggle_pin(this, &item);
+                    update_pin_button(button, is_pinned(this, &item));
+                }
+            }
+        });
+
+        // Shuffle buttons on the album and artist pages, which play the
+        // item the page's pin refers to
+        let this = self.obj().downgrade();
+        self.album_detail_shuffle.connect_clicked(move |_| {
+            if let Some(obj) = this.upgrade() {
+                let this = obj.imp();
+                let item = this.album_detail_pin_item.borrow().clone();
+                if let Some(item) = item {
+                    shuffle_album(this, &item.provider, &item.item_id);
+                }
+            }
+        });
+
+        let this = self.obj().downgrade();
+        self.album_detail_fill_gaps.connect_clicked(move |_| {
+            if let Some(obj) = this.upgrade() {
+                queue_missing_tracks(obj.imp());
+            }
+        });
+
+        let this = self.obj().downgrade();
+        self.artist_detail_shuffle.connect_clicked(move |_| {
+            if let Some(obj) = this.upgrade() {
+                let this = obj.imp();
+                let item = this.artist_detail_pin_item.borrow().clone();
+                if let Some(item) = item {
+                    shuffle_artist(this, &item.provider, &item.item_id);
+                }
+            }
+        });
+
+        setup_playlist_page(self);
+
+        // Album page artist link
+        let this = self.obj().downgrade();
+        self.album_detail_artist.connect_clicked(move |_| {
+            if let Some(obj) = this.upgrade() {
+                let this = obj.imp();
+                let track = this.album_detail_track.borrow().clone();
+                if let Some(track) = track {
+                    show_artist_for_track(this, &track, None);
+                }
+            }
+        });
+
         // Queue toggle with flap
         let queue_flap = self.queue_flap.clone();
         self.queue_toggle.connect_toggled(move |button| {
@@ -417,8 +1781,27 @@ impl NovaWindow {
         });
     }
 
+    /// Pick up the checkpoint a crashed session left, for restoring once
+    /// the providers are up, and start saving new ones
+    fn setup_checkpoints(&self) {
+        self.pending_checkpoint.replace(checkpoint::load());
+        schedule_checkpoint(self);
+    }
+
     fn setup_playback_controls(&self) {
-        let audio_player = AudioPlayer::new().expect("Failed to create audio player");
+        let audio_player = match missing_elements() {
+            Ok(missing) if !missing.is_empty() => Err(missing.join(", ").into()),
+            Ok(_) => AudioPlayer::new(),
+            Err(e) => Err(e),
+        };
+        let audio_player = match audio_player {
+            Ok(audio_player) => audio_player,
+            Err(e) => {
+                // Browsing still works without playback
+                report_problem(self, Problem::AudioUnavailable(e.to_string()));
+                return;
+            }
+        };
         let player = Player::new(
             audio_player,
             self.play_button.clone(),
@@ -427,11 +1810,98 @@ impl NovaWindow {
             self.current_song.clone(),
             self.current_song_artist.clone(),
             self.current_album_art.clone(),
+            self.now_playing_box.clone(),
+            self.queue_peek_label.clone(),
+            self.queue_remaining_label.clone(),
+            self.toast_overlay.clone(),
             self.song_progress_bar.clone(),
             self.current_time_label.clone(),
             self.total_time_label.clone(),
         );
 
+        // Volume curve preference
+        let volume_curve = |logarithmic: bool| {
+            if logarithmic {
+                VolumeCurve::Logarithmic
+            } else {
+                VolumeCurve::Linear
+            }
+        };
+        let settings = self.settings();
+        player.set_volume_curve(volume_curve(settings.boolean("logarithmic-volume")));
+        let player_clone = player.clone();
+        settings.connect_changed(Some("logarithmic-volume"), move |settings, key| {
+            player_clone.set_volume_curve(volume_curve(settings.boolean(key)));
+        });
+
+        // Fades on play, pause, stop and seek
+        let fade_duration = |millis: i32| Duration::from_millis(millis.max(0) as u64);
+        player.set_fade_duration(fade_duration(settings.int("fade-duration-ms")));
+        let player_clone = player.clone();
+        settings.connect_changed(Some("fade-duration-ms"), move |settings, key| {
+            player_clone.set_fade_duration(fade_duration(settings.int(key)));
+        });
+
+        // Output buffering, applied from the next track
+        let output_buffer = |settings: &gio::Settings| OutputBuffer {
+            buffer: Duration::from_millis(settings.int("output-buffer-ms").max(1) as u64),
+            latency: Duration::from_millis(settings.int("output-latency-ms").max(1) as u64),
+        };
+        player.set_output_buffer(output_buffer(&settings));
+        for key in ["output-buffer-ms", "output-latency-ms"] {
+            let player_clone = player.clone();
+            settings.connect_changed(Some(key), move |settings, _| {
+                player_clone.set_output_buffer(output_buffer(settings));
+            });
+        }
+
+        // Sample rate and format conversion, applied from the next track
+        let resampler_quality =
+            |settings: &gio::Settings| match settings.string("resampler-quality").as_str() {
+                "fast" => ResamplerQuality::Fast,
+                "high" => ResamplerQuality::High,
+                _ => ResamplerQuality::Medium,
+            };
+        player.set_resampler_quality(resampler_quality(&settings));
+        let player_clone = player.clone();
+        settings.connect_changed(Some("resampler-quality"), move |settings, _| {
+            player_clone.set_resampler_quality(resampler_quality(settings));
+        });
+
+        // Headphone-safe volume cap and peak limiter
+        let volume_limit = |settings: &gio::Settings| {
+            settings
+                .boolean("volume-limiter")
+                .then(|| settings.int("max-volume-percent") as f64 / 100.0)
+        };
+        player.set_volume_limit(volume_limit(&settings));
+        for key in ["volume-limiter", "max-volume-percent"] {
+            let player_clone = player.clone();
+            settings.connect_changed(Some(key), move |settings, _| {
+                player_clone.set_volume_limit(volume_limit(settings));
+            });
+        }
+
+        // Volume leveling by ReplayGain tags
+        player.set_normalization(self.normalization());
+        for key in ["normalize-volume", "clipping-prevention"] {
+            let obj_weak = self.obj().downgrade();
+            let player_clone = player.clone();
+            settings.connect_changed(Some(key), move |_, _| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    player_clone.set_normalization(obj.imp().normalization());
+                }
+            });
+        }
+
+        // Previous restarts the track once it's this many seconds in
+        let restart_threshold = |seconds: i32| Duration::from_secs(seconds.max(0) as u64);
+        player.set_restart_threshold(restart_threshold(settings.int("previous-restart-seconds")));
+        let player_clone = player.clone();
+        settings.connect_changed(Some("previous-restart-seconds"), move |settings, key| {
+            player_clone.set_restart_threshold(restart_threshold(settings.int(key)));
+        });
+
         // Previous button
         let player_clone = player.clone();
         self.prev_button.connect_clicked(move |_| {
@@ -444,17 +1914,82 @@ impl NovaWindow {
             player_clone.next();
         });
 
+        // Holding either of them seeks through the track instead
+        player.add_hold_to_seek(&self.prev_button, false);
+        player.add_hold_to_seek(&self.next_button, true);
+        player.set_audible_seeking(settings.boolean("audible-seeking"));
+        let player_clone = player.clone();
+        settings.connect_changed(Some("audible-seeking"), move |settings, key| {
+            player_clone.set_audible_seeking(settings.boolean(key));
+        });
+
+        // Desktop media controls and the lock screen
+        setup_mpris(self, &player);
+
+        // Go to album/artist for whatever is playing
+        let player_clone = player.clone();
+        attach_go_to_menu(&*self.now_playing_box, &self.obj(), move || {
+            player_clone.current_track().map(|track| (track, None))
+        });
+
+        // What's playing, down to the format it's decoded and output in
+        let player_clone = player.clone();
+        attach_tooltip(&*self.now_playing_box, move || {
+            match player_clone.current_track() {
+                Some(track) => now_playing_tooltip(&track, player_clone.gain_stages().as_ref()),
+                None => TooltipBuilder::new("No song playing"),
+            }
+        });
+
+        // Streamed tracks coming up are fetched ahead for patchy connections
+        if let Some(manager) = self.service_manager.borrow().clone() {
+            match CachePrefetcher::new(manager) {
+                Ok(prefetcher) => prefetcher.run(player.subscribe_upcoming()),
+                Err(e) => eprintln!("Failed to set up the stream cache: {}", e),
+            }
+        }
+
         self.player.replace(Some(player));
 
         // Shuffle button
+        let obj_weak = self.obj().downgrade();
         self.shuffle_button.connect_clicked(move |button| {
             if button.is_active() {
                 button.add_css_class("active");
             } else {
                 button.remove_css_class("active");
             }
+            if let Some(obj) = obj_weak.upgrade() {
+                obj.imp().apply_shuffle();
+            }
         });
 
+        // Right-click or hold the shuffle button to pick what it shuffles
+        self.obj()
+            .add_action(&self.settings().create_action("shuffle-by"));
+        let shuffle_popover = gtk::PopoverMenu::from_model(Some(&*self.shuffle_menu));
+        shuffle_popover.set_parent(&*self.shuffle_button);
+        let popover = shuffle_popover.clone();
+        let click = gtk::GestureClick::builder()
+            .button(gtk::gdk::BUTTON_SECONDARY)
+            .build();
+        click.connect_pressed(move |_, _, _, _| popover.popup());
+        self.shuffle_button.add_controller(click);
+        let hold = gtk::GestureLongPress::new();
+        hold.connect_pressed(move |_, _, _| shuffle_popover.popup());
+        self.shuffle_button.add_controller(hold);
+
+        for key in ["smart-shuffle", "shuffle-by"] {
+            let obj_weak = self.obj().downgrade();
+            self.settings().connect_changed(Some(key), move |_, _| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    if obj.imp().shuffle_button.is_active() {
+                        obj.imp().apply_shuffle();
+                    }
+                }
+            });
+        }
+
         // Loop button
         #[derive(Debug, Clone, Copy, PartialEq, Eq)]
         enum LoopState {
@@ -466,6 +2001,7 @@ impl NovaWindow {
         let loop_state = Rc::new(RefCell::new(LoopState::Off));
         let loop_button = self.loop_button.clone();
         let loop_state_for_cb = loop_state.clone();
+        let obj_weak = self.obj().downgrade();
         loop_button.connect_clicked(move |button| {
             let mut state = loop_state_for_cb.borrow_mut();
             *state = match *state {
@@ -489,6 +2025,11 @@ impl NovaWindow {
                 }
             };
             println!("Loop state is now: {:?}", state);
+            if let Some(obj) = obj_weak.upgrade() {
+                if let Some(player) = obj.imp().player.borrow().as_ref() {
+                    player.set_repeat(*state == LoopState::Playlist);
+                }
+            }
         });
 
         // Progress bar updates
@@ -497,6 +2038,121 @@ impl NovaWindow {
         });
     }
 
+    fn setup_history(&self) {
+        reload_history(self);
+        reload_skipped(self);
+
+        // Catch up on tag writes left over from last time, and on those
+        // waiting for a policy change
+        self.flush_write_backs();
+        let obj_weak = self.obj().downgrade();
+        self.settings().connect_changed(Some("write-replay-gain"), move |_, _| {
+            if let Some(obj) = obj_weak.upgrade() {
+                obj.imp().write_replay_gain();
+            }
+        });
+        let obj_weak = self.obj().downgrade();
+        self.settings().connect_changed(Some("embed-artwork"), move |_, _| {
+            if let Some(obj) = obj_weak.upgrade() {
+                obj.imp().embed_artwork();
+            }
+        });
+        let obj_weak = self.obj().downgrade();
+        for key in [
+            "write-edits-to-files",
+            "write-ratings-to-files",
+            "write-play-counts-to-files",
+            "write-to-network-files",
+        ] {
+            let obj_weak = obj_weak.clone();
+            self.settings().connect_changed(Some(key), move |_, _| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.imp().flush_write_backs();
+                }
+            });
+        }
+
+        // Record every track that starts, whichever way it was started
+        if let Some(player) = self.player.borrow().as_ref() {
+            let obj_weak = self.obj().downgrade();
+            player.connect_track_started(move |track| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    let this = obj.imp();
+                    if let Some(store) = this.user_store.borrow().as_ref() {
+                        if let Err(e) = store.record_play(track) {
+                            eprintln!("Failed to record play of {}: {}", track.title, e);
+                        }
+                        let policy = this.write_back_policy();
+                        if policy.play_counts {
+                            let recorded = store.play_count(&track.id).and_then(|count| {
+                                writeback::record_change(
+                                    store,
+                                    policy,
+                                    track,
+                                    TagChange::PlayCount(count),
+                                )
+                            });
+                            match recorded {
+                                Ok(()) => this.flush_write_backs(),
+                                Err(e) => eprintln!("Failed to queue play count: {}", e),
+                            }
+                        }
+                    }
+                    reload_history(this);
+                    update_detail_stats(this);
+                }
+            });
+
+            let obj_weak = self.obj().downgrade();
+            player.connect_track_skipped(move |track| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    let this = obj.imp();
+                    if let Some(store) = this.user_store.borrow().as_ref() {
+                        if let Err(e) = store.record_skip(&track.id) {
+                            eprintln!("Failed to record skip of {}: {}", track.title, e);
+                        }
+                    }
+                    reload_skipped(this);
+                }
+            });
+
+            // Start and stop points, and bookmarks, are kept across sessions
+            if let Some(store) = self.user_store.borrow().as_ref() {
+                match store.track_trims() {
+                    Ok(trims) => player.set_trims(trims),
+                    Err(e) => eprintln!("Failed to load track trims: {}", e),
+                }
+                match store.track_bookmarks() {
+                    Ok(bookmarks) => player.load_bookmarks(bookmarks),
+                    Err(e) => eprintln!("Failed to load bookmarks: {}", e),
+                }
+            }
+            let obj_weak = self.obj().downgrade();
+            player.connect_trim_changed(move |track| {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return;
+                };
+                let this = obj.imp();
+                let (Some(store), Some(player)) = (
+                    this.user_store.borrow().clone(),
+                    this.player.borrow().clone(),
+                ) else {
+                    return;
+                };
+                if let Err(e) = store.set_track_trim(&track.id, player.trim(&track.id)) {
+                    eprintln!("Failed to save trim of {}: {}", track.title, e);
+                }
+            });
+        }
+
+        let obj_weak = self.obj().downgrade();
+        self.history_add_all_button.connect_clicked(move |_| {
+            if let Some(obj) = obj_weak.upgrade() {
+                add_history_to_playlist(obj.imp());
+            }
+        });
+    }
+
     fn setup_volume_controls(&self) {
         // Initialize volume
         self.volume_scale.set_value(100.0);
@@ -555,7 +2211,7 @@ impl NovaWindow {
         });
     }
 
-    fn load_artists(&self) {
+    pub(crate) fn load_artists(&self) {
         if let Some(manager) = self.service_manager.borrow().as_ref() {
             let artists_grid = self.artists_grid.clone();
             let artists_stack = self.artists_stack.clone();
@@ -588,6 +2244,8 @@ impl NovaWindow {
                                 let card =
                                     super::components::cards::create_artist_card(&artist, false);
                                 let child = gtk::FlowBoxChild::new();
+                                // Found again by ID when the library changes
+                                child.set_widget_name(&artist.id);
                                 child.set_child(Some(&card));
                                 artists_grid.append(&child);
                             }
@@ -611,10 +2269,129 @@ impl NovaWindow {
         }
     }
 
-    fn load_albums(&self) {
+    /// Refill the library page on screen with what's changed, at most once
+    /// every LIBRARY_REFRESH_DELAY so a scan doesn't refill it for every
+    /// few files
+    fn schedule_library_refresh(&self, change: Option<&LibraryChange>) {
+        // Providers that can't tell which tracks changed send them empty
+        let change = change.filter(|change| !change.is_empty());
+        if self.library_refresh.borrow().is_some() {
+            let mut pending = self.library_changes.borrow_mut();
+            match (pending.as_mut(), change) {
+                (Some(pending), Some(change)) => pending.merge(change),
+                _ => *pending = None,
+            }
+            return;
+        }
+        self.library_changes.replace(change.cloned());
+
+        let obj_weak = self.obj().downgrade();
+        let source = glib::timeout_add_local_once(LIBRARY_REFRESH_DELAY, move || {
+            let Some(obj) = obj_weak.upgrade() else {
+                return;
+            };
+            let this = obj.imp();
+            this.library_refresh.take();
+            let change = this.library_changes.take();
+            library_changed(this, change.as_ref());
+            match (this.main_stack.visible_child_name().as_deref(), change) {
+                (Some("artists"), Some(change)) => this.update_artists(change),
+                (Some("artists"), None) => this.load_artists(),
+                // A decade's albums are left as they are
+                (Some("albums"), change) if !this.albums_decade_banner.is_revealed() => {
+                    match change {
+                        Some(change) => this.update_albums(change),
+                        None => this.load_albums(),
+                    }
+                }
+                (Some("home"), _) => reload_home(this),
+                _ => {}
+            }
+        });
+        self.library_refresh.replace(Some(source));
+    }
+
+    /// Bring the artists page in line with `change` rather than loading it
+    /// afresh. Cards come and go with their artists, and those of artists
+    /// with changed tracks are made again.
+    fn update_artists(&self, change: LibraryChange) {
+        if change.updated.len() > LIBRARY_UPDATE_LIMIT {
+            self.load_artists();
+            return;
+        }
+        let Some(manager) = self.service_manager.borrow().clone() else {
+            return;
+        };
+        let (grid, stack) = (self.artists_grid.clone(), self.artists_stack.clone());
+        glib::MainContext::default().spawn_local(async move {
+            let artists = match manager.get_all_artists().await {
+                Ok(artists) => artists,
+                Err(e) => {
+                    eprintln!("Failed to update the artists page: {}", e);
+                    return;
+                }
+            };
+            let changed = changed_tracks(&manager, &change.updated).await;
+            let stale: HashSet<&str> = changed.iter().map(|track| track.artist.as_str()).collect();
+            sync_cards(
+                &grid,
+                &artists,
+                |artist| artist.id.as_str(),
+                |artist| stale.contains(artist.name.as_str()),
+                |artist| create_artist_card(artist, false).upcast(),
+            );
+            stack.set_visible_child_name(if artists.is_empty() {
+                "placeholder"
+            } else {
+                "content"
+            });
+        });
+    }
+
+    /// Bring the albums page in line with `change`, the way update_artists
+    /// does the artists page
+    fn update_albums(&self, change: LibraryChange) {
+        if change.updated.len() > LIBRARY_UPDATE_LIMIT {
+            self.load_albums();
+            return;
+        }
+        let Some(manager) = self.service_manager.borrow().clone() else {
+            return;
+        };
+        let (grid, stack) = (self.albums_grid.clone(), self.albums_stack.clone());
+        glib::MainContext::default().spawn_local(async move {
+            let albums = match manager.get_all_albums().await {
+                Ok(albums) => albums,
+                Err(e) => {
+                    eprintln!("Failed to update the albums page: {}", e);
+                    return;
+                }
+            };
+            let changed = changed_tracks(&manager, &change.updated).await;
+            let stale: HashSet<(&str, &str)> = changed
+                .iter()
+                .map(|track| (track.artist.as_str(), track.album.as_str()))
+                .collect();
+            sync_cards(
+                &grid,
+                &albums,
+                |album| album.id.as_str(),
+                |album| stale.contains(&(album.artist.as_str(), album.title.as_str())),
+                |album| create_album_card(album, false).upcast(),
+            );
+            stack.set_visible_child_name(if albums.is_empty() {
+                "placeholder"
+            } else {
+                "content"
+            });
+        });
+    }
+
+    pub(crate) fn load_albums(&self) {
         if let Some(manager) = self.service_manager.borrow().as_ref() {
             let albums_grid = self.albums_grid.clone();
             let albums_stack = self.albums_stack.clone();
+            self.albums_decade_banner.set_revealed(false);
 
             // Clear existing content
             while let Some(child) = albums_grid.first_child() {
@@ -644,6 +2421,8 @@ impl NovaWindow {
                                 let card =
                                     super::components::cards::create_album_card(&album, false);
                                 let child = gtk::FlowBoxChild::new();
+                                // Found again by ID when the library changes
+                                child.set_widget_name(&album.id);
                                 child.set_child(Some(&card));
                                 albums_grid.append(&child);
                             }
@@ -669,6 +2448,74 @@ impl NovaWindow {
 }
 // Implement other traits
 impl WidgetImpl for NovaWindow {}
-impl WindowImpl for NovaWindow {}
+impl WindowImpl for NovaWindow {
+    fn close_request(&self) -> Propagation {
+        match handle_close_request(self) {
+            Propagation::Stop => Propagation::Stop,
+            Propagation::Proceed => {
+                end_session(self);
+                self.parent_close_request()
+            }
+        }
+    }
+}
 impl ApplicationWindowImpl for NovaWindow {}
 impl AdwApplicationWindowImpl for NovaWindow {}
+
+/// Tracks of `ids` as they are now, leaving out those that are gone
+async fn changed_tracks(manager: &ServiceManager, ids: &[String]) -> Vec<Track> {
+    let mut tracks = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Ok(item) = manager.get_track(id).await {
+            tracks.push(item.track);
+        }
+    }
+    tracks
+}
+
+/// Have `grid` show a card for each of `items` in order, keeping the cards
+/// it has for them by ID unless `stale` says they changed
+fn sync_cards<T>(
+    grid: &gtk::FlowBox,
+    items: &[T],
+    id: impl Fn(&T) -> &str,
+    stale: impl Fn(&T) -> bool,
+    card: impl Fn(&T) -> gtk::Widget,
+) {
+    let mut existing = HashMap::new();
+    let mut next = grid.first_child();
+    while let Some(widget) = next {
+        next = widget.next_sibling();
+        let Ok(child) = widget.downcast::<gtk::FlowBoxChild>() else {
+            continue;
+        };
+        // Such as a loading indicator, which has no ID
+        if let Some(other) = existing.insert(child.widget_name().to_string(), child) {
+            grid.remove(&other);
+        }
+    }
+
+    for (index, item) in items.iter().enumerate() {
+        let position = index as i32;
+        match existing.remove(id(item)) {
+            Some(child) if !stale(item) => {
+                if grid.child_at_index(position).as_ref() != Some(&child) {
+                    grid.remove(&child);
+                    grid.insert(&child, position);
+                }
+            }
+            old => {
+                if let Some(old) = old {
+                    grid.remove(&old);
+                }
+                let child = gtk::FlowBoxChild::new();
+                child.set_widget_name(id(item));
+                child.set_child(Some(&card(item)));
+                grid.insert(&child, position);
+            }
+        }
+    }
+    for child in existing.into_values() {
+        grid.remove(&child);
+    }
+}
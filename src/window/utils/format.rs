@@ -0,0 +1,92 @@
+//! Durations, dates and counts as they're shown across the window. Words go
+//! through gettext, and dates through GLib, which follows the user's locale
+//! for month names and date order.
+
+use chrono::{DateTime, Local, Utc};
+use gettextrs::{gettext, ngettext, pgettext};
+use gtk::glib;
+
+/// Formats seconds as m:ss, or h:mm:ss for anything an hour or longer, for
+/// positions and the lengths of single tracks
+pub(crate) fn format_duration(seconds: u32) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds % 3600) / 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Formats seconds as "1 hr 23 min", to the nearest minute, for the lengths
+/// of albums, playlists and queues
+pub(crate) fn format_length(seconds: u32) -> String {
+    if seconds < 60 {
+        return gettext("{} sec").replace("{}", &seconds.to_string());
+    }
+    let minutes = (seconds + 30) / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => gettext("{} min").replace("{}", &minutes.to_string()),
+        (hours, 0) => gettext("{} hr").replace("{}", &format_count(hours as u64)),
+        (hours, minutes) => gettext("{hours} hr {minutes} min")
+            .replace("{hours}", &format_count(hours as u64))
+            .replace("{minutes}", &minutes.to_string()),
+    }
+}
+
+/// Formats `count` with the locale's digit grouping, such as "12,345"
+pub(crate) fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    if digits.len() <= 4 {
+        return digits;
+    }
+    // Translators: the separator between groups of three digits
+    let separator = pgettext("thousands separator", ",");
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push_str(&separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// "1 song", "1,204 songs" and the like. `singular` and `plural` hold "{}"
+/// where the count goes.
+pub(crate) fn format_count_of(count: usize, singular: &str, plural: &str) -> String {
+    ngettext(singular, plural, count as u32).replace("{}", &format_count(count as u64))
+}
+
+/// How long ago `time` was, such as "3 days ago", or its date in the
+/// locale's format once it's over a week back
+pub(crate) fn format_time_ago(time: DateTime<Utc>) -> String {
+    let now = Utc::now();
+    let elapsed = now.signed_duration_since(time);
+    let minutes = elapsed.num_minutes();
+    let hours = elapsed.num_hours();
+    let days = (now.with_timezone(&Local).date_naive() - time.with_timezone(&Local).date_naive())
+        .num_days();
+
+    if minutes < 1 {
+        gettext("just now")
+    } else if hours < 1 {
+        ngettext("{} minute ago", "{} minutes ago", minutes as u32)
+            .replace("{}", &minutes.to_string())
+    } else if days < 1 {
+        ngettext("{} hour ago", "{} hours ago", hours as u32).replace("{}", &hours.to_string())
+    } else if days == 1 {
+        gettext("yesterday")
+    } else if days < 7 {
+        ngettext("{} day ago", "{} days ago", days as u32).replace("{}", &days.to_string())
+    } else {
+        format_date(time)
+    }
+}
+
+/// `time`'s date in the locale's format
+fn format_date(time: DateTime<Utc>) -> String {
+    glib::DateTime::from_unix_local(time.timestamp())
+        .and_then(|date| date.format("%x"))
+        .map(|date| date.to_string())
+        .unwrap_or_else(|_| time.with_timezone(&Local).format("%Y-%m-%d").to_string())
+}
@@ -1 +1,3 @@
+pub mod format;
+pub mod tooltip;
 pub mod ui;
@@ -0,0 +1,150 @@
+use crate::services::audio_player::GainStages;
+use crate::services::genres::genre_path;
+use crate::services::models::{Album, Artist, ItemSource, PlaybackSource, Track};
+use crate::window::utils::format::format_duration;
+use gtk::pango;
+use gtk::prelude::*;
+
+/// Title plus a grid of label/value rows, shown as a custom tooltip
+pub(crate) struct TooltipBuilder {
+    title: String,
+    rows: Vec<(&'static str, String)>,
+}
+
+impl TooltipBuilder {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row(mut self, label: &'static str, value: impl Into<String>) -> Self {
+        self.rows.push((label, value.into()));
+        self
+    }
+
+    /// Adds the row only when there is a value, so missing metadata doesn't
+    /// leave blank lines
+    pub fn optional_row(self, label: &'static str, value: Option<impl Into<String>>) -> Self {
+        match value {
+            Some(value) => self.row(label, value),
+            None => self,
+        }
+    }
+
+    pub fn build(&self) -> gtk::Widget {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        container.add_css_class("rich-tooltip");
+
+        let title = gtk::Label::new(Some(&self.title));
+        title.set_xalign(0.0);
+        title.set_max_width_chars(40);
+        title.set_ellipsize(pango::EllipsizeMode::End);
+        title.add_css_class("heading");
+        container.append(&title);
+
+        let grid = gtk::Grid::builder()
+            .row_spacing(2)
+            .column_spacing(12)
+            .build();
+        for (index, (label, value)) in self.rows.iter().enumerate() {
+            let key = gtk::Label::new(Some(label));
+            key.set_xalign(1.0);
+            key.add_css_class("dim-label");
+
+            let value = gtk::Label::new(Some(value));
+            value.set_xalign(0.0);
+            value.set_max_width_chars(40);
+            value.set_ellipsize(pango::EllipsizeMode::End);
+
+            grid.attach(&key, 0, index as i32, 1, 1);
+            grid.attach(&value, 1, index as i32, 1, 1);
+        }
+        container.append(&grid);
+
+        container.upcast()
+    }
+}
+
+pub(crate) fn track_tooltip(track: &Track, play_count: Option<u32>) -> TooltipBuilder {
+    let format = match &track.source {
+        PlaybackSource::Local { file_format, .. } => file_format.to_uppercase(),
+        PlaybackSource::Spotify { .. } => "Spotify".to_string(),
+        PlaybackSource::YouTube { .. } => "YouTube".to_string(),
+        PlaybackSource::Stream { .. } => "Stream".to_string(),
+    };
+
+    TooltipBuilder::new(&track.title)
+        .row("Artist", &track.artist)
+        .row("Album", &track.album)
+        .optional_row("Year", track.release_year.map(|year| year.to_string()))
+        .optional_row("Genre", track.genre.as_deref().map(genre_path))
+        .row("Duration", format_duration(track.duration))
+        .row("Format", format)
+        .row(
+            "Offline",
+            if track.availability.plays_offline() {
+                "Yes"
+            } else {
+                "No"
+            },
+        )
+        .optional_row("Plays", play_count.map(|count| count.to_string()))
+}
+
+/// The playing track, with the format it's decoded in and the format the
+/// output takes once it's converted
+pub(crate) fn now_playing_tooltip(track: &Track, stages: Option<&GainStages>) -> TooltipBuilder {
+    let source = stages.and_then(|stages| stages.source_format);
+    let output = stages.and_then(|stages| stages.output_format);
+    track_tooltip(track, None)
+        .optional_row("Source", source.map(|format| format.to_string()))
+        .optional_row("Output", output.map(|format| format.to_string()))
+}
+
+pub(crate) fn album_tooltip(album: &Album) -> TooltipBuilder {
+    TooltipBuilder::new(&album.title)
+        .row("Artist", &album.artist)
+        .optional_row("Year", album.year.map(|year| year.to_string()))
+        .optional_row(
+            "Tracks",
+            (!album.tracks.is_empty()).then(|| album.tracks.len().to_string()),
+        )
+        .optional_row("Sources", sources_summary(&album.sources))
+}
+
+pub(crate) fn artist_tooltip(artist: &Artist) -> TooltipBuilder {
+    TooltipBuilder::new(&artist.name)
+        .optional_row(
+            "Albums",
+            (!artist.albums.is_empty()).then(|| artist.albums.len().to_string()),
+        )
+        .optional_row("Sources", sources_summary(&artist.sources))
+}
+
+fn sources_summary(sources: &[ItemSource]) -> Option<String> {
+    if sources.is_empty() {
+        return None;
+    }
+    Some(
+        sources
+            .iter()
+            .map(|source| source.provider.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+/// Show the tooltip from `build` when hovering `widget`. The tooltip is built
+/// on demand so values like play counts are current.
+pub(crate) fn attach_tooltip<F>(widget: &impl IsA<gtk::Widget>, build: F)
+where
+    F: Fn() -> TooltipBuilder + 'static,
+{
+    widget.set_has_tooltip(true);
+    widget.connect_query_tooltip(move |_, _, _, _, tooltip| {
+        tooltip.set_custom(Some(&build().build()));
+        true
+    });
+}
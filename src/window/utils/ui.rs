@@ -1,15 +1,17 @@
 use crate::services::models::{Artwork, ArtworkSource};
+use crate::services::remote_art;
 use gdk_pixbuf::Pixbuf;
 use gtk::prelude::*;
 use gtk::{gio, glib};
+use std::path::Path;
 
 pub(crate) fn create_artwork_image(artwork: &Artwork, size: i32) -> gtk::Image {
     match artwork {
         Artwork {
-            thumbnail: Some(data),
+            thumbnails: Some(thumbnails),
             ..
         } => {
-            let bytes = glib::Bytes::from(data);
+            let bytes = glib::Bytes::from(thumbnails.for_size(size));
             let stream = gio::MemoryInputStream::from_bytes(&bytes);
             if let Ok(pixbuf) = Pixbuf::from_stream(&stream, None::<&gio::Cancellable>) {
                 if let Some(scaled) =
@@ -27,24 +29,22 @@ pub(crate) fn create_artwork_image(artwork: &Artwork, size: i32) -> gtk::Image {
             }
         }
         Artwork {
-            thumbnail: None,
+            thumbnails: None,
             full_art: ArtworkSource::Local { path },
-        } => {
-            if let Ok(pixbuf) = Pixbuf::from_file(path) {
-                if let Some(scaled) =
-                    pixbuf.scale_simple(size, size, gdk_pixbuf::InterpType::Bilinear)
-                {
-                    let paintable = gtk::gdk::Texture::for_pixbuf(&scaled);
-                    let image = gtk::Image::from_paintable(Some(&paintable));
-                    image.add_css_class("album-art");
-                    image
-                } else {
-                    create_placeholder_image(size)
-                }
-            } else {
-                create_placeholder_image(size)
+            ..
+        } => match file_texture(path, size) {
+            Some(paintable) => {
+                let image = gtk::Image::from_paintable(Some(&paintable));
+                image.add_css_class("album-art");
+                image
             }
-        }
+            None => create_placeholder_image(size),
+        },
+        Artwork {
+            thumbnails: None,
+            full_art: ArtworkSource::Remote { url, cache_key },
+            ..
+        } => create_remote_image(url, cache_key.as_deref(), size),
         _ => create_placeholder_image(size),
     }
 }
@@ -55,3 +55,50 @@ pub(crate) fn create_placeholder_image(size: i32) -> gtk::Image {
     image.add_css_class("album-art");
     image
 }
+
+/// The image file at `path` scaled to `size`
+fn file_texture(path: &Path, size: i32) -> Option<gtk::gdk::Texture> {
+    let pixbuf = Pixbuf::from_file(path).ok()?;
+    let scaled = pixbuf.scale_simple(size, size, gdk_pixbuf::InterpType::Bilinear)?;
+    Some(gtk::gdk::Texture::for_pixbuf(&scaled))
+}
+
+/// The cover at `url`, from the cache when it's been downloaded before.
+/// Otherwise the placeholder shows until the download finishes.
+fn create_remote_image(url: &str, cache_key: Option<&str>, size: i32) -> gtk::Image {
+    if let Some(paintable) =
+        remote_art::cached_art(url, cache_key).and_then(|path| file_texture(&path, size))
+    {
+        let image = gtk::Image::from_paintable(Some(&paintable));
+        image.add_css_class("album-art");
+        return image;
+    }
+
+    let image = create_placeholder_image(size);
+    let image_weak = image.downgrade();
+    let (url, cache_key) = (url.to_string(), cache_key.map(str::to_string));
+    glib::MainContext::default().spawn_local(async move {
+        let result =
+            tokio::spawn(async move { remote_art::fetch_art(&url, cache_key.as_deref()).await })
+                .await;
+        let path = match result {
+            Ok(Ok(path)) => path,
+            Ok(Err(e)) => {
+                eprintln!("Failed to download artwork: {}", e);
+                return;
+            }
+            Err(e) => {
+                eprintln!("Artwork download stopped: {}", e);
+                return;
+            }
+        };
+        // The card may have gone while the cover downloaded
+        let Some(image) = image_weak.upgrade() else {
+            return;
+        };
+        if let Some(paintable) = file_texture(&path, size) {
+            image.set_paintable(Some(&paintable));
+        }
+    });
+    image
+}